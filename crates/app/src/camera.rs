@@ -0,0 +1,76 @@
+use std::f32::consts::FRAC_PI_2;
+
+use glam::{Mat4, Vec3};
+use renderer::CameraOverride;
+
+/// State of the free camera, which overrides the camera of the game for inspecting scenes.
+pub struct FreeCamera {
+    /// Whether the free camera overrides the camera of the game.
+    pub enabled: bool,
+    /// Position, in the view space of the game.
+    pub position: Vec3,
+    /// Rotation around the vertical axis, in radians.
+    pub yaw: f32,
+    /// Rotation around the horizontal axis, in radians.
+    pub pitch: f32,
+    /// Movement speed, in view space units per second.
+    pub speed: f32,
+    /// Rotation per pixel of mouse movement, in radians.
+    pub sensitivity: f32,
+    /// Vertical field of view, in degrees, replacing the one of the game.
+    pub fov: Option<f32>,
+    /// Whether the free camera window is capturing the keyboard, in which case it shouldn't be
+    /// used as game input.
+    pub captured: bool,
+}
+
+impl Default for FreeCamera {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            position: Vec3::ZERO,
+            yaw: 0.0,
+            pitch: 0.0,
+            speed: 100.0,
+            sensitivity: 0.005,
+            fov: None,
+            captured: false,
+        }
+    }
+}
+
+impl FreeCamera {
+    fn rotation(&self) -> Mat4 {
+        Mat4::from_rotation_y(self.yaw) * Mat4::from_rotation_x(self.pitch)
+    }
+
+    /// Moves the camera along the given direction, relative to where it's looking at, for the
+    /// given amount of seconds.
+    pub fn move_by(&mut self, direction: Vec3, seconds: f32) {
+        let direction = self
+            .rotation()
+            .transform_vector3(direction.normalize_or_zero());
+        self.position += direction * self.speed * seconds;
+    }
+
+    /// Rotates the camera by the given mouse movement, in pixels.
+    pub fn look(&mut self, dx: f32, dy: f32) {
+        self.yaw -= dx * self.sensitivity;
+        self.pitch = (self.pitch - dy * self.sensitivity).clamp(-FRAC_PI_2, FRAC_PI_2);
+    }
+
+    /// Moves the camera back to the camera of the game.
+    pub fn reset(&mut self) {
+        self.position = Vec3::ZERO;
+        self.yaw = 0.0;
+        self.pitch = 0.0;
+    }
+
+    /// Returns the camera override to use in the renderer, if enabled.
+    pub fn camera_override(&self) -> Option<CameraOverride> {
+        self.enabled.then(|| CameraOverride {
+            view: (Mat4::from_translation(self.position) * self.rotation()).inverse(),
+            fov_y: self.fov.map(f32::to_radians),
+        })
+    }
+}
@@ -1,6 +1,6 @@
 use std::path::PathBuf;
 
-use clap::{Args, Parser};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 
 #[derive(Args, Debug)]
 pub struct PpcjitConfig {
@@ -13,21 +13,183 @@ pub struct PpcjitConfig {
     /// Whether to ignore the FPU enabled bit in MSR
     #[arg(long, default_value_t = false)]
     pub force_fpu: bool,
-    /// Whether to ignore unimplemented instructions
-    #[arg(long, default_value_t = false)]
-    pub ignore_unimplemented_inst: bool,
+    /// What to do with unimplemented and illegal instructions
+    #[arg(long, value_enum, default_value_t = UnimplementedInst::Panic)]
+    pub unimplemented_inst: UnimplementedInst,
     /// Whether to clear the JIT block cache
     #[arg(long, default_value_t = false)]
     pub clear_cache: bool,
     /// Whether to perform round-to-single operations
     #[arg(long, default_value_t = false)]
     pub round_to_single: bool,
+    /// Whether to flush denormal single precision results to zero
+    #[arg(long, default_value_t = false)]
+    pub flush_denormals: bool,
+    /// Whether to count events with the performance monitor counters (PMC1-PMC4)
+    #[arg(long, default_value_t = false)]
+    pub performance_monitor: bool,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum UnimplementedInst {
+    /// Stop emulation
+    Panic,
+    /// Treat them as no-ops
+    Ignore,
+    /// Log them and raise a program exception
+    Exception,
+}
+
+impl From<UnimplementedInst> for cores::cpu::jit::ppcjit::UnimplementedPolicy {
+    fn from(value: UnimplementedInst) -> Self {
+        match value {
+            UnimplementedInst::Panic => Self::Panic,
+            UnimplementedInst::Ignore => Self::Ignore,
+            UnimplementedInst::Exception => Self::Hook,
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum Deinterlace {
+    /// Interleave the lines of both fields
+    Weave,
+    /// Line double each field
+    Bob,
+}
+
+impl From<Deinterlace> for renderer::Deinterlace {
+    fn from(value: Deinterlace) -> Self {
+        match value {
+            Deinterlace::Weave => Self::Weave,
+            Deinterlace::Bob => Self::Bob,
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum TextureFilter {
+    /// Filter textures as the game asks
+    Native,
+    /// Always filter textures linearly
+    Bilinear,
+    /// Always filter textures linearly and anisotropically
+    Anisotropic,
+}
+
+impl From<TextureFilter> for renderer::TextureFilter {
+    fn from(value: TextureFilter) -> Self {
+        match value {
+            TextureFilter::Native => Self::Native,
+            TextureFilter::Bilinear => Self::Bilinear,
+            TextureFilter::Anisotropic => Self::Anisotropic,
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum AspectRatio {
+    /// Present the output as 4:3
+    Native,
+    /// Present the output as 16:9, for games with an anamorphic widescreen mode
+    Stretch,
+    /// Present the output as 16:9 and widen 3D projections to match. Might distort 2D elements
+    Hack,
+}
+
+impl From<AspectRatio> for renderer::AspectRatio {
+    fn from(value: AspectRatio) -> Self {
+        match value {
+            AspectRatio::Native => Self::Native,
+            AspectRatio::Stretch => Self::Stretch,
+            AspectRatio::Hack => Self::Hack,
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum Dsp {
+    /// Interpret the ucode. Accurate, but slow
+    Lle,
+    /// High level emulate the AX ucode. Fast, but games using other ucodes fall back to LLE
+    Hle,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HlePatch {
+    /// Log the messages passed to OSReport
+    OsReport,
+    /// Execute memcpy natively
+    Memcpy,
+    /// Execute memset natively
+    Memset,
+}
+
+impl HlePatch {
+    /// Returns the configuration which enables the given patches.
+    pub fn config(patches: &[Self]) -> lazuli::system::hle::Config {
+        lazuli::system::hle::Config {
+            os_report: patches.contains(&Self::OsReport),
+            memcpy: patches.contains(&Self::Memcpy),
+            memset: patches.contains(&Self::Memset),
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum AudioQuality {
+    /// Repeat or drop frames. Cheapest, but aliases heavily
+    Nearest,
+    /// Linearly interpolate between frames
+    Linear,
+    /// Band-limited sinc interpolation. Best quality, but more expensive
+    Sinc4,
+}
+
+impl From<AudioQuality> for modules::audio::ResamplerQuality {
+    fn from(value: AudioQuality) -> Self {
+        match value {
+            AudioQuality::Nearest => Self::Nearest,
+            AudioQuality::Linear => Self::Linear,
+            AudioQuality::Sinc4 => Self::Sinc4,
+        }
+    }
+}
+
+fn positive_f64(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(value) if value.is_finite() && value > 0.0 => Ok(value),
+        Ok(_) => Err("must be a positive number".into()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Extract files from a disk image, without starting the emulator
+    ///
+    /// Supported formats are the same as for `rom`.
+    Extract {
+        /// Path to the disk image
+        image: PathBuf,
+        /// Path of the file or directory in the disk to extract. Defaults to the whole disk
+        #[arg(default_value = "/")]
+        path: String,
+        /// Path to write the file to, or directory to write the tree into
+        #[arg(required_unless_present = "list")]
+        out: Option<PathBuf>,
+        /// Whether to only print the files under the given path
+        #[arg(short, long, default_value_t = false)]
+        list: bool,
+    },
 }
 
 /// Lazuli: GameCube emulator
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 pub struct Config {
+    #[command(subcommand)]
+    pub command: Option<Command>,
     #[command(flatten)]
     pub ppcjit: PpcjitConfig,
     /// Path to the IPL ROM
@@ -35,12 +197,14 @@ pub struct Config {
     pub ipl: Option<PathBuf>,
     /// Path to the ROM to load and execute
     ///
-    /// Supported formats are .iso and .rvz. To sideload executables, use the `exec` argument.
+    /// Supported formats are .iso, .rvz, .wia and .ciso/.cso. To sideload executables, use the
+    /// `exec` argument.
     #[arg(short('i'), long)]
     pub rom: Option<PathBuf>,
     /// Path to the executable to sideload and execute
     ///
-    /// Supported format is .dol.
+    /// Supported formats are .dol and .elf. Symbols of an .elf are used as debug info unless
+    /// `debug` is given.
     #[arg(long)]
     pub exec: Option<PathBuf>,
     /// Path to a file to use as a debug info provider
@@ -55,6 +219,63 @@ pub struct Config {
     /// some form of shared CPU-GPU memory. Always enabled for iGPUs.
     #[arg(long, default_value_t = false)]
     pub mappable_vram: bool,
+    /// Texture memory budget, in MiB. Textures which haven't been used recently are evicted once
+    /// over it.
+    #[arg(long, default_value_t = 512)]
+    pub texture_budget: u64,
+    /// Capacity of the texture bind group cache, in entries
+    #[arg(long, default_value_t = renderer::DEFAULT_GROUP_CACHE_CAPACITY)]
+    pub bind_group_cache: u32,
+    /// How to combine the fields of interlaced video into a frame
+    #[arg(long, value_enum, default_value_t = Deinterlace::Weave)]
+    pub deinterlace: Deinterlace,
+    /// Texture filtering to use regardless of what games ask for, as an enhancement
+    #[arg(long, value_enum, default_value_t = TextureFilter::Native)]
+    pub texture_filter: TextureFilter,
+    /// Aspect ratio of the output. The widescreen hack scales perspective projections
+    /// horizontally, which might distort 2D elements such as HUDs
+    #[arg(long, value_enum, default_value_t = AspectRatio::Native)]
+    pub aspect_ratio: AspectRatio,
+    /// Factor by which to scale the CPU clock. Values above 1 overclock the CPU, values below
+    /// underclock it
+    #[arg(long, default_value_t = 1.0, value_parser = positive_f64)]
+    pub cpu_clock_multiplier: f64,
+    /// Whether disc reads complete almost instantly instead of taking as long as on a real drive.
+    /// Speeds up loading, but might break games which stream data from the disc
+    #[arg(long, default_value_t = false)]
+    pub fast_disc: bool,
+    /// HLE patches to install into sideloaded executables, found through their symbols
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub hle: Vec<HlePatch>,
+    /// How to emulate the DSP
+    #[arg(long, value_enum, default_value_t = Dsp::Lle)]
+    pub dsp: Dsp,
+    /// Path to a dump of the DSP IROM to use instead of the built-in one. Only used by the LLE
+    /// DSP, including when HLE falls back to it
+    #[arg(long)]
+    pub dsp_irom: Option<PathBuf>,
+    /// How to resample the audio output to the rate of the output device
+    #[arg(long, value_enum, default_value_t = AudioQuality::Sinc4)]
+    pub audio_quality: AudioQuality,
+    /// Size of the audio output buffer, in frames. Smaller buffers reduce latency but might cause
+    /// glitches. Uses the platform default if not given
+    #[arg(long)]
+    pub audio_buffer_size: Option<u32>,
+    /// Path to a WAV file to dump the audio output to
+    #[arg(long)]
+    pub dump_audio: Option<PathBuf>,
+    /// Path to a file to record controller input to
+    #[arg(long)]
+    pub input_record: Option<PathBuf>,
+    /// Path to a recording to replay controller input from, instead of using the gamepad
+    #[arg(long)]
+    pub input_replay: Option<PathBuf>,
+    /// Path to a plugin library to use as the input module, instead of the gamepad
+    #[arg(long)]
+    pub input_plugin: Option<PathBuf>,
+    /// Path to a plugin library to use as the audio module, instead of the audio device
+    #[arg(long)]
+    pub audio_plugin: Option<PathBuf>,
     /// Whether to LLE the IPL instead of HLEing it for loading games
     #[arg(long, default_value_t = false)]
     pub ipl_lle: bool,
@@ -0,0 +1,116 @@
+//! The `extract` subcommand: pulls files out of a disk image without starting the emulator.
+
+use std::io::{BufReader, Read, Seek};
+use std::path::{Component, Path, PathBuf};
+
+use eyre_pretty::{Context, Result, eyre};
+use lazuli::disks::cso::{self, Cso, CsoReader};
+use lazuli::disks::fst::Fst;
+use lazuli::disks::rvz::{Rvz, RvzReader};
+use lazuli::disks::wia::{Wia, WiaReader};
+
+trait DiskReader: Read + Seek {}
+impl<T> DiskReader for T where T: Read + Seek {}
+
+/// Opens a disk image as a reader of the plain disk contents.
+fn open_disk_reader(path: &Path) -> Result<Box<dyn DiskReader>> {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default();
+    let file = std::fs::File::open(path).context("opening disk image")?;
+    let mut reader = BufReader::new(file);
+
+    if cso::is_cso(&mut reader)? {
+        return Ok(Box::new(CsoReader::new(Cso::new(reader)?)));
+    }
+
+    Ok(match extension {
+        "iso" | "gcm" => Box::new(reader),
+        "rvz" => Box::new(RvzReader::new(Rvz::new(reader)?)),
+        "wia" => Box::new(WiaReader::new(Wia::new(reader)?)),
+        "cso" | "ciso" => Box::new(CsoReader::new(Cso::new(reader)?)),
+        _ => eyre::bail!("unsupported disk image format: {}", path.display()),
+    })
+}
+
+/// Joins an FST path to `base`, refusing anything that could escape it.
+fn output_path(base: &Path, relative: &str) -> Result<PathBuf> {
+    let relative = Path::new(relative);
+    if !relative
+        .components()
+        .all(|c| matches!(c, Component::Normal(_)))
+    {
+        eyre::bail!("refusing to extract suspicious path {}", relative.display());
+    }
+
+    Ok(base.join(relative))
+}
+
+fn write_file(fst: &mut Fst<Box<dyn DiskReader>>, path: &str, out: &Path) -> Result<()> {
+    let data = fst.read_file(path)?;
+    if let Some(parent) = out.parent() {
+        std::fs::create_dir_all(parent).context("creating output directory")?;
+    }
+
+    std::fs::write(out, data).context(format!("writing {}", out.display()))?;
+    println!("{path} -> {}", out.display());
+
+    Ok(())
+}
+
+/// Runs the `extract` subcommand.
+///
+/// `path` is either a file, which is written to `out`, or a directory, whose whole subtree is
+/// written into the `out` directory. An empty path or `/` refers to the root.
+pub fn run(image: &Path, path: &str, out: Option<&Path>, list: bool) -> Result<()> {
+    let mut fst = Fst::new(open_disk_reader(image)?).context("parsing the filesystem")?;
+    let dir = path.trim_matches('/');
+
+    let is_dir = dir.is_empty() || fst.directories().any(|d| d == dir);
+    let prefix = if dir.is_empty() {
+        String::new()
+    } else {
+        format!("{dir}/")
+    };
+
+    if list {
+        if let Some(id) = fst.game_id() {
+            println!("{id}: {}", fst.title());
+        }
+
+        if !is_dir {
+            let entry = fst.find(dir)?;
+            println!("{} ({})", entry.path, bytesize::ByteSize(entry.size as u64));
+            return Ok(());
+        }
+
+        for entry in fst.files().filter(|e| e.path.starts_with(&prefix)) {
+            println!("{} ({})", entry.path, bytesize::ByteSize(entry.size as u64));
+        }
+
+        return Ok(());
+    }
+
+    let Some(out) = out else {
+        eyre::bail!("an output path is required unless listing");
+    };
+
+    if !is_dir {
+        return self::write_file(&mut fst, dir, out);
+    }
+
+    let files = fst
+        .files()
+        .filter(|e| e.path.starts_with(&prefix))
+        .collect::<Vec<_>>();
+
+    for entry in &files {
+        let target = self::output_path(out, &entry.path[prefix.len()..])?;
+        self::write_file(&mut fst, &entry.path, &target)?;
+    }
+
+    println!("extracted {} files", files.len());
+
+    Ok(())
+}
@@ -1,45 +1,114 @@
-#![feature(trim_prefix_suffix)]
-
+mod camera;
 mod cli;
+mod extract;
 mod runner;
 mod windows;
 
 use std::io::BufReader;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use clap::Parser;
+use cores::dsp::interpreter::dspint::IROM_SIZE;
 use eframe::egui;
 use eframe::egui_wgpu::{WgpuConfiguration, WgpuSetup, WgpuSetupCreateNew};
-use eyre_pretty::eyre::Result;
-use lazuli::Lazuli;
-use lazuli::cores::Cores;
-use lazuli::disks::cso::Cso;
+use eyre_pretty::eyre::{self, Result};
+use lazuli::cores::{Cores, DspCore};
+use lazuli::disks::cso::{self, Cso};
 use lazuli::disks::rvz::Rvz;
+use lazuli::disks::wia::Wia;
+use lazuli::modules::audio::{AudioModule, NopAudioModule};
 use lazuli::modules::debug::{DebugModule, NopDebugModule};
 use lazuli::modules::disk::{DiskModule, NopDiskModule};
+use lazuli::modules::input::{InputModule, NopInputModule};
+use lazuli::system::action_replay::ActionReplayEngine;
 use lazuli::system::executable::Executable;
+use lazuli::system::gecko::GeckoEngine;
 use lazuli::system::{self, Modules};
-use modules::audio::CpalModule;
-use modules::debug::{Addr2LineModule, MapFileModule};
-use modules::disk::{CsoModule, IsoModule, RvzModule};
+use lazuli::{Address, Lazuli};
+use modules::audio::{CpalConfig, CpalHandle, CpalModule};
+use modules::debug::{Addr2LineModule, MapFileModule, SymbolTableModule};
+use modules::disk::{CsoModule, IsoModule, RvzModule, WiaModule};
 use modules::input::GilrsModule;
+use modules::plugin::{PluginAudioModule, PluginInputModule};
 use nanorand::Rng;
 use renderer::Renderer;
 use runner::State;
 use vtxjit::JitVertexModule;
 
+use crate::camera::FreeCamera;
 use crate::runner::Runner;
 use crate::windows::{AppWindow, AppWindowState};
 
+/// Opens a disk image as a disk module.
+fn open_disk(path: &Path) -> Result<Box<dyn DiskModule>> {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default();
+    let file = std::fs::File::open(path)?;
+    let mut reader = BufReader::new(file);
+
+    // CISO images are frequently distributed with a plain .iso extension, so sniff the
+    // magic before trusting the extension
+    if cso::is_cso(&mut reader)? {
+        let cso = Cso::new(reader).unwrap();
+        let cso = CsoModule::new(cso);
+        return Ok(Box::new(cso));
+    }
+
+    Ok(match extension {
+        "iso" | "gcm" => Box::new(IsoModule::new(reader)?),
+        "rvz" => {
+            let rvz = Rvz::new(reader).unwrap();
+            let rvz = RvzModule::new(rvz);
+            Box::new(rvz)
+        }
+        "wia" => {
+            let wia = Wia::new(reader).unwrap();
+            let wia = WiaModule::new(wia);
+            Box::new(wia)
+        }
+        "cso" | "ciso" => {
+            let cso = Cso::new(reader).unwrap();
+            let cso = CsoModule::new(cso);
+            Box::new(cso)
+        }
+        _ => eyre::bail!("unsupported disk image format: {}", path.display()),
+    })
+}
+
+/// Format of the codes loaded through the load codes dialog.
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+enum CodeFormat {
+    #[default]
+    Gecko,
+    ActionReplay,
+}
+
 struct App {
     last_update: Instant,
     renderer: Renderer,
     input: GilrsModule,
+    audio: CpalHandle,
+    camera: FreeCamera,
     windows: Vec<AppWindowState>,
     runner: Runner,
     cps: u64,
     organize: bool,
+    /// Path typed in the insert disk dialog, if it's open.
+    insert_disk_path: Option<String>,
+    /// Error of the last attempt to insert a disk.
+    insert_disk_error: Option<String>,
+    /// Path typed in the load codes dialog, if it's open.
+    load_codes_path: Option<String>,
+    /// Format selected in the load codes dialog.
+    load_codes_format: CodeFormat,
+    /// Error of the last attempt to load codes.
+    load_codes_error: Option<String>,
+    /// Navigation request for the disassembly carried over from the last frame.
+    goto: Option<Address>,
 }
 
 impl App {
@@ -54,25 +123,7 @@ impl App {
         };
 
         let disk: Box<dyn DiskModule> = if let Some(path) = &cfg.rom {
-            let extension = path.extension().and_then(|ext| ext.to_str()).unwrap();
-            let file = std::fs::File::open(path)?;
-            let reader = BufReader::new(file);
-            match extension {
-                "iso" => {
-                    Box::new(IsoModule(Some(reader)))
-                }
-                "rvz" => {
-                    let rvz = Rvz::new(reader).unwrap();
-                    let rvz = RvzModule::new(rvz);
-                    Box::new(rvz)
-                }
-                "cso" | "ciso" => {
-                    let cso = Cso::new(reader).unwrap();
-                    let cso = CsoModule::new(cso);
-                    Box::new(cso)
-                }
-                _ => unimplemented!(),
-            }
+            open_disk(path)?
         } else {
             Box::new(NopDiskModule)
         };
@@ -101,6 +152,8 @@ impl App {
                 Some("map") => Box::new(MapFileModule::new(path)) as Box<dyn DebugModule>,
                 _ => Box::new(NopDebugModule),
             }
+        } else if let Some(exec) = executable.as_ref().filter(|e| !e.symbols().is_empty()) {
+            Box::new(SymbolTableModule::new(exec.symbols()))
         } else {
             Box::new(NopDebugModule)
         };
@@ -113,6 +166,11 @@ impl App {
             wgpu_state.queue.clone(),
             wgpu_state.target_format,
         );
+        renderer.set_texture_budget(cfg.texture_budget * bytesize::MIB);
+        renderer.set_group_cache_capacity(cfg.bind_group_cache);
+        renderer.set_deinterlace(cfg.deinterlace.into());
+        renderer.set_texture_filter(cfg.texture_filter.into());
+        renderer.set_aspect_ratio(cfg.aspect_ratio.into());
 
         let dirs = directories::ProjectDirs::from("", "", "lazuli").unwrap();
         let cache_dir = dirs.cache_dir();
@@ -122,28 +180,92 @@ impl App {
             _ = std::fs::remove_dir_all(&jit_cache_path);
         }
 
+        let irom = if let Some(path) = &cfg.dsp_irom {
+            let data = std::fs::read(path)?.into_boxed_slice();
+            let irom = data.try_into().map_err(|data: Box<[u8]>| {
+                eyre::eyre!(
+                    "DSP IROM dump {} is {} bytes long, expected {}",
+                    path.display(),
+                    data.len(),
+                    IROM_SIZE,
+                )
+            })?;
+
+            Some(irom)
+        } else {
+            None
+        };
+
+        let interpreter = cores::dsp::interpreter::Config { irom };
+        let dsp: Box<dyn DspCore> = match cfg.dsp {
+            cli::Dsp::Lle => Box::new(cores::dsp::interpreter::Core::new(interpreter)),
+            cli::Dsp::Hle => Box::new(cores::dsp::ax::core(interpreter)),
+        };
+
         let cores = Cores {
-            dsp: Box::new(cores::dsp::interpreter::Core::default()),
+            dsp,
             cpu: Box::new(cores::cpu::jit::Core::new(cores::cpu::jit::Config {
                 instr_per_block: cfg.ppcjit.instr_per_block,
                 jit_settings: cores::cpu::jit::ppcjit::Settings {
                     codegen: cores::cpu::jit::ppcjit::CodegenSettings {
                         nop_syscalls: cfg.ppcjit.nop_syscalls,
                         force_fpu: cfg.ppcjit.force_fpu,
-                        ignore_unimplemented: cfg.ppcjit.ignore_unimplemented_inst,
+                        unimplemented: cfg.ppcjit.unimplemented_inst.into(),
                         round_to_single: cfg.ppcjit.round_to_single,
+                        flush_denormals: cfg.ppcjit.flush_denormals,
+                        cycles: Default::default(),
+                        performance_monitor: cfg.ppcjit.performance_monitor,
                     },
                     cache_path: Some(jit_cache_path),
                 },
             })),
         };
 
+        let mut audio = CpalModule::new(CpalConfig {
+            quality: cfg.audio_quality.into(),
+            buffer_size_frames: cfg.audio_buffer_size,
+        });
+        if let Some(path) = &cfg.dump_audio {
+            audio.start_dump(path)?;
+        }
+
+        let audio_handle = audio.handle();
         let input = GilrsModule::new();
+        if let Some(path) = &cfg.input_replay {
+            input.start_playback(path.clone())?;
+        }
+
+        if let Some(path) = &cfg.input_record {
+            input.start_recording(path.clone())?;
+        }
+
+        let audio: Box<dyn AudioModule> = match &cfg.audio_plugin {
+            Some(path) => match PluginAudioModule::load(path) {
+                Ok(plugin) => Box::new(plugin),
+                Err(e) => {
+                    tracing::error!("failed to load audio plugin {}: {e}", path.display());
+                    Box::new(NopAudioModule)
+                }
+            },
+            None => Box::new(audio),
+        };
+
+        let input_module: Box<dyn InputModule> = match &cfg.input_plugin {
+            Some(path) => match PluginInputModule::load(path) {
+                Ok(plugin) => Box::new(plugin),
+                Err(e) => {
+                    tracing::error!("failed to load input plugin {}: {e}", path.display());
+                    Box::new(NopInputModule)
+                }
+            },
+            None => Box::new(input.clone()),
+        };
+
         let modules = Modules {
-            audio: Box::new(CpalModule::new()),
+            audio,
             debug: debug_module,
             disk,
-            input: Box::new(input.clone()),
+            input: input_module,
             render: Box::new(renderer.clone()),
             vertex: Box::new(JitVertexModule::new()),
         };
@@ -156,6 +278,9 @@ impl App {
                 ipl,
                 sideload: executable,
                 perform_efb_copies: cfg.efb_ram_copies,
+                cpu_clock_multiplier: cfg.cpu_clock_multiplier,
+                fast_disc: cfg.fast_disc,
+                hle: cli::HlePatch::config(&cfg.hle),
             },
         );
 
@@ -180,10 +305,18 @@ impl App {
             last_update: Instant::now(),
             renderer,
             input,
+            audio: audio_handle,
+            camera: FreeCamera::default(),
             windows,
             runner,
             cps: 0,
             organize: false,
+            insert_disk_path: None,
+            insert_disk_error: None,
+            load_codes_path: None,
+            load_codes_format: CodeFormat::default(),
+            load_codes_error: None,
+            goto: None,
         };
 
         if create_default {
@@ -203,6 +336,129 @@ impl App {
         Ok(app)
     }
 
+    /// Shows the dialog for inserting a disk, if it's open. If the cover is closed, the current
+    /// disk is swapped for the new one.
+    fn insert_disk_dialog(&mut self, ctx: &egui::Context) {
+        let Some(path) = &mut self.insert_disk_path else {
+            return;
+        };
+
+        let mut open = true;
+        let mut insert = false;
+        egui::Window::new("Insert disc")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Path:");
+                    let response = ui.text_edit_singleline(path);
+                    insert |=
+                        response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    insert |= ui.button("Insert").clicked();
+                });
+
+                if let Some(error) = &self.insert_disk_error {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                }
+            });
+
+        if insert {
+            match open_disk(Path::new(path.trim())) {
+                Ok(disk) => {
+                    self.runner.insert_disk(disk);
+                    open = false;
+                }
+                Err(e) => self.insert_disk_error = Some(e.to_string()),
+            }
+        }
+
+        if !open {
+            self.insert_disk_path = None;
+        }
+    }
+
+    /// Shows the dialog for loading Gecko or Action Replay codes, if it's open. Loaded codes
+    /// replace the current ones of the same format.
+    fn load_codes_dialog(&mut self, ctx: &egui::Context) {
+        let Some(path) = &mut self.load_codes_path else {
+            return;
+        };
+
+        let mut open = true;
+        let mut load = false;
+        egui::Window::new("Load codes")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Format:");
+                    let format = &mut self.load_codes_format;
+                    ui.radio_value(format, CodeFormat::Gecko, "Gecko");
+                    ui.radio_value(format, CodeFormat::ActionReplay, "Action Replay");
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Path:");
+                    let response = ui.text_edit_singleline(path);
+                    load |= response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    load |= ui.button("Load").clicked();
+                });
+
+                if let Some(error) = &self.load_codes_error {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                }
+            });
+
+        if load {
+            let loaded = std::fs::read_to_string(path.trim())
+                .map_err(|e| e.to_string())
+                .and_then(|text| match self.load_codes_format {
+                    CodeFormat::Gecko => {
+                        let codes = GeckoEngine::load_codes(&text).map_err(|e| e.to_string())?;
+                        tracing::info!("loaded {} gecko codes", codes.len());
+                        self.runner.set_gecko_codes(codes);
+                        Ok(())
+                    }
+                    CodeFormat::ActionReplay => {
+                        let codes =
+                            ActionReplayEngine::load_codes(&text).map_err(|e| e.to_string())?;
+                        tracing::info!("loaded {} action replay codes", codes.len());
+                        self.runner.set_action_replay_codes(codes);
+                        Ok(())
+                    }
+                });
+
+            match loaded {
+                Ok(()) => open = false,
+                Err(e) => self.load_codes_error = Some(e),
+            }
+        }
+
+        if !open {
+            self.load_codes_path = None;
+        }
+    }
+
+    /// Starts a raw PCM audio dump in the working directory, or stops the current one.
+    fn toggle_pcm_dump(&mut self) {
+        if self.audio.is_pcm_dumping() {
+            self.audio.stop_pcm_dump();
+            return;
+        }
+
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        let path = PathBuf::from(format!("lazuli-{timestamp}.pcm"));
+        if let Err(e) = self.audio.start_pcm_dump(path) {
+            tracing::error!("failed to start PCM dump: {e}");
+        }
+    }
+
     fn create_window(&mut self, window: impl AppWindow) {
         let mut rng = nanorand::tls_rng();
         let id = rng.generate::<u64>();
@@ -246,14 +502,50 @@ impl eframe::App for App {
                         self.create_window(windows::variables());
                     }
 
+                    if ui.button("Memory Map").clicked() {
+                        self.create_window(windows::memory_map());
+                    }
+
                     if ui.button("Display").clicked() {
                         self.create_window(windows::display());
                     }
 
+                    if ui.button("DSP").clicked() {
+                        self.create_window(windows::dsp());
+                    }
+
+                    if ui.button("DSP Mailbox").clicked() {
+                        self.create_window(windows::dsp_mailbox());
+                    }
+
+                    if ui.button("DSP Audio").clicked() {
+                        self.create_window(windows::dsp_audio());
+                    }
+
                     if ui.button("Renderer").clicked() {
                         self.create_window(windows::renderer());
                     }
 
+                    if ui.button("Free Camera").clicked() {
+                        self.create_window(windows::free_camera());
+                    }
+
+                    if ui.button("Performance").clicked() {
+                        self.create_window(windows::performance());
+                    }
+
+                    if ui.button("Frame Graph").clicked() {
+                        self.create_window(windows::frame_graph());
+                    }
+
+                    if ui.button("Turbo").clicked() {
+                        self.create_window(windows::turbo());
+                    }
+
+                    if ui.button("Controller Settings").clicked() {
+                        self.create_window(windows::controller());
+                    }
+
                     ui.menu_button("Subsystems", |ui| {
                         if ui.button("Command Processor").clicked() {
                             self.create_window(windows::subsystem_cp());
@@ -262,9 +554,36 @@ impl eframe::App for App {
                         if ui.button("Processor Interface").clicked() {
                             self.create_window(windows::subsystem_pi());
                         }
+
+                        if ui.button("Video Interface").clicked() {
+                            self.create_window(windows::subsystem_vi());
+                        }
                     });
                 });
 
+                ui.menu_button("💿 Disc", |ui| {
+                    if ui.button("Open disc tray").clicked() {
+                        self.runner.open_disk_cover();
+                    }
+
+                    if ui.button("Insert disc...").clicked() {
+                        self.insert_disk_path.get_or_insert_default();
+                        self.insert_disk_error = None;
+                    }
+                });
+
+                ui.menu_button("🧩 Codes", |ui| {
+                    if ui.button("Load codes...").clicked() {
+                        self.load_codes_path.get_or_insert_default();
+                        self.load_codes_error = None;
+                    }
+
+                    if ui.button("Clear codes").clicked() {
+                        self.runner.set_gecko_codes(Vec::new());
+                        self.runner.set_action_replay_codes(Vec::new());
+                    }
+                });
+
                 ui.label(format!(
                     "Speed: {}%",
                     ((self.cps as f64 / lazuli::gekko::FREQUENCY as f64) * 100.0).round()
@@ -289,6 +608,28 @@ impl eframe::App for App {
                 * 2;
         }
 
+        let toggle_pcm_dump = ctx.input_mut(|i| {
+            i.consume_key(
+                egui::Modifiers::COMMAND | egui::Modifiers::SHIFT,
+                egui::Key::R,
+            )
+        });
+
+        if toggle_pcm_dump {
+            self.toggle_pcm_dump();
+        }
+
+        let play_macro = ctx.input_mut(|i| {
+            i.consume_key(
+                egui::Modifiers::COMMAND | egui::Modifiers::SHIFT,
+                egui::Key::M,
+            )
+        });
+
+        if play_macro {
+            self.input.play_macro();
+        }
+
         ctx.input(|i| {
             let button = |key| i.key_down(key);
             let trigger = |key| if i.key_down(key) { 255 } else { 0 };
@@ -298,6 +639,11 @@ impl eframe::App for App {
                 _ => 128,
             };
 
+            // the free camera window uses the keyboard for moving around
+            if self.camera.captured {
+                return;
+            }
+
             self.input.update_fallback(|s| {
                 s.analog_x = axis(egui::Key::A, egui::Key::D);
                 s.analog_y = axis(egui::Key::S, egui::Key::W);
@@ -324,10 +670,20 @@ impl eframe::App for App {
             self.runner.start();
         }
 
+        self.insert_disk_dialog(ctx);
+        self.load_codes_dialog(ctx);
+
+        self.camera.captured = false;
         let mut context = windows::Ctx {
             step: false,
+            reset: None,
             running: was_running,
+            goto: self.goto.take(),
             renderer: &mut self.renderer,
+            camera: &mut self.camera,
+            audio: &self.audio,
+            input: &self.input,
+            cps: self.cps,
         };
 
         egui::CentralPanel::default().show(ctx, |_| {
@@ -366,6 +722,14 @@ impl eframe::App for App {
             }
         }
 
+        self.goto = context.goto;
+        self.renderer
+            .set_camera_override(self.camera.camera_override());
+
+        if let Some(hard) = context.reset {
+            self.runner.reset(hard);
+        }
+
         if context.step {
             self.runner.step();
         }
@@ -416,36 +780,22 @@ fn setup_tracing() -> tracing_appender::non_blocking::WorkerGuard {
 fn main() -> Result<()> {
     eyre_pretty::install()?;
     let _tracing_guard = setup_tracing();
+    lazuli::panic::set_crash_report_path("crash.log");
     let cfg = cli::Config::parse();
 
-    let device_descriptor = Arc::new(move |adapter: &wgpu::Adapter| {
-        let info = adapter.get_info();
-
-        let mut required_features = wgpu::Features::empty();
-        required_features |= wgpu::Features::DUAL_SOURCE_BLENDING;
-        required_features |= wgpu::Features::FLOAT32_FILTERABLE;
-        required_features |= wgpu::Features::PUSH_CONSTANTS;
-        required_features |= wgpu::Features::CLEAR_TEXTURE;
-
-        if cfg.mappable_vram
-            || matches!(
-                info.device_type,
-                wgpu::DeviceType::IntegratedGpu | wgpu::DeviceType::Cpu
-            )
-        {
-            required_features |= wgpu::Features::MAPPABLE_PRIMARY_BUFFERS;
-        }
-
-        let mut required_limits = wgpu::Limits::defaults();
-        required_limits.max_texture_dimension_2d = 8192;
-        required_limits.max_push_constant_size = 64 + 32;
+    if let Some(cli::Command::Extract {
+        image,
+        path,
+        out,
+        list,
+    }) = &cfg.command
+    {
+        return extract::run(image, path, out.as_deref(), *list);
+    }
 
-        wgpu::DeviceDescriptor {
-            label: Some("lazuli wgpu device"),
-            required_features,
-            required_limits,
-            ..Default::default()
-        }
+    let mappable_vram = cfg.mappable_vram;
+    let device_descriptor = Arc::new(move |adapter: &wgpu::Adapter| {
+        renderer::device_descriptor(adapter, mappable_vram)
     });
 
     let icon = eframe::icon_data::from_png_bytes(include_bytes!(concat!(
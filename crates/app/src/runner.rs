@@ -5,6 +5,11 @@ use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex, MutexGuard};
 use std::time::Duration;
 
+use lazuli::breakpoint::Breakpoints;
+use lazuli::modules::disk::DiskModule;
+use lazuli::system::action_replay::ActionReplayCode;
+use lazuli::system::di;
+use lazuli::system::gecko::GeckoCode;
 use lazuli::{Address, Cycles, Lazuli};
 use spin_sleep::SpinSleeper;
 
@@ -12,19 +17,17 @@ use crate::runner::timer::Timer;
 
 pub struct State {
     pub lazuli: Lazuli,
-    pub breakpoints: Vec<Address>,
+    pub breakpoints: Breakpoints,
     pub cycles_history: VecDeque<(Cycles, Duration)>,
 }
 
 impl State {
     pub fn add_breakpoint(&mut self, breakpoint: Address) {
-        if !self.breakpoints.contains(&breakpoint) {
-            self.breakpoints.push(breakpoint);
-        }
+        self.breakpoints.add(breakpoint);
     }
 
     pub fn remove_breakpoint(&mut self, breakpoint: Address) {
-        self.breakpoints.retain(|b| *b != breakpoint);
+        self.breakpoints.remove(breakpoint);
     }
 }
 
@@ -78,7 +81,7 @@ fn worker(runner_state: Arc<Shared>) {
 
         let executed = state
             .lazuli
-            .exec(Cycles::from_duration(delta), &state.breakpoints);
+            .exec(Cycles::from_duration(delta), &mut state.breakpoints);
 
         emulated += delta;
 
@@ -105,7 +108,7 @@ impl Runner {
         let state = Shared {
             state: Mutex::new(State {
                 lazuli,
-                breakpoints: vec![],
+                breakpoints: Breakpoints::default(),
                 cycles_history: VecDeque::new(),
             }),
             advance: AtomicBool::new(false),
@@ -145,6 +148,42 @@ impl Runner {
         }
     }
 
+    pub fn reset(&mut self, hard: bool) {
+        let mut lock = self.shared.state.lock().unwrap();
+        lock.lazuli.reset(hard);
+        lock.cycles_history.clear();
+    }
+
+    /// Opens the disk cover, ejecting the current disk.
+    pub fn open_disk_cover(&mut self) {
+        let mut lock = self.shared.state.lock().unwrap();
+        di::open_cover(&mut lock.lazuli.sys);
+    }
+
+    /// Inserts a disk. If the cover is closed, it's opened first and the current disk is swapped
+    /// for the new one.
+    pub fn insert_disk(&mut self, disk: Box<dyn DiskModule>) {
+        let mut lock = self.shared.state.lock().unwrap();
+        let sys = &mut lock.lazuli.sys;
+        if sys.disk.cover.open() {
+            di::insert_disk(sys, disk);
+        } else {
+            di::swap_disk(sys, disk);
+        }
+    }
+
+    /// Replaces the Gecko codes applied every frame.
+    pub fn set_gecko_codes(&mut self, codes: Vec<GeckoCode>) {
+        let mut lock = self.shared.state.lock().unwrap();
+        lock.lazuli.sys.gecko.set_codes(codes);
+    }
+
+    /// Replaces the Action Replay codes applied every frame.
+    pub fn set_action_replay_codes(&mut self, codes: Vec<ActionReplayCode>) {
+        let mut lock = self.shared.state.lock().unwrap();
+        lock.lazuli.sys.action_replay.set_codes(codes);
+    }
+
     pub fn running(&mut self) -> bool {
         self.shared.advance.load(Ordering::SeqCst)
     }
@@ -1,24 +1,47 @@
 mod call_stack;
+mod camera;
 mod control;
+mod controller;
 mod disasm;
 mod display;
+mod dsp;
+mod dsp_audio;
+mod dsp_mailbox;
+mod frame_graph;
+mod memory_map;
+mod performance;
 mod registers;
 mod renderer_info;
 mod subsystem;
 mod threads;
+mod turbo;
 mod variables;
 mod xfb;
 
 use eframe::egui::{self, Vec2};
+use lazuli::Address;
+use modules::audio::CpalHandle;
+use modules::input::GilrsModule;
 use renderer::Renderer;
 use serde::{Deserialize, Serialize};
 
+use crate::camera::FreeCamera;
 use crate::runner::State;
 
 pub struct Ctx<'a> {
     pub step: bool,
+    /// Requested reset, if any. `true` for a hard reset, `false` for a soft reset.
+    pub reset: Option<bool>,
     pub running: bool,
+    /// Address the disassembly should navigate to, if any. Requests made after the disassembly
+    /// window has been shown are carried over to the next frame.
+    pub goto: Option<Address>,
     pub renderer: &'a mut Renderer,
+    pub camera: &'a mut FreeCamera,
+    pub audio: &'a CpalHandle,
+    pub input: &'a GilrsModule,
+    /// Emulated CPU cycles per second.
+    pub cps: u64,
 }
 
 #[typetag::serde]
@@ -62,6 +85,10 @@ pub fn variables() -> variables::Window {
     Default::default()
 }
 
+pub fn memory_map() -> memory_map::Window {
+    Default::default()
+}
+
 // pub fn xfb() -> xfb::Window {
 //     Default::default()
 // }
@@ -70,10 +97,42 @@ pub fn display() -> display::Window {
     Default::default()
 }
 
+pub fn dsp() -> dsp::Window {
+    Default::default()
+}
+
+pub fn dsp_mailbox() -> dsp_mailbox::Window {
+    Default::default()
+}
+
+pub fn dsp_audio() -> dsp_audio::Window {
+    Default::default()
+}
+
 pub fn renderer() -> renderer_info::Window {
     Default::default()
 }
 
+pub fn free_camera() -> camera::Window {
+    Default::default()
+}
+
+pub fn performance() -> performance::Window {
+    Default::default()
+}
+
+pub fn frame_graph() -> frame_graph::Window {
+    Default::default()
+}
+
+pub fn turbo() -> turbo::Window {
+    Default::default()
+}
+
+pub fn controller() -> controller::Window {
+    Default::default()
+}
+
 pub fn subsystem_cp() -> subsystem::cp::Window {
     Default::default()
 }
@@ -81,3 +140,7 @@ pub fn subsystem_cp() -> subsystem::cp::Window {
 pub fn subsystem_pi() -> subsystem::pi::Window {
     Default::default()
 }
+
+pub fn subsystem_vi() -> subsystem::vi::Window {
+    Default::default()
+}
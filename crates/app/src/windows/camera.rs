@@ -0,0 +1,80 @@
+use eframe::egui::{self, Vec2};
+use eframe::egui_wgpu;
+use glam::Vec3;
+use lazuli::system::gx::{EFB_HEIGHT, EFB_WIDTH};
+use serde::{Deserialize, Serialize};
+
+use crate::State;
+use crate::windows::display::RendererCallback;
+use crate::windows::{AppWindow, Ctx};
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Window;
+
+#[typetag::serde(name = "free_camera")]
+impl AppWindow for Window {
+    fn title(&self) -> &str {
+        "Free Camera"
+    }
+
+    fn default_size(&self) -> Option<egui::Vec2> {
+        Some(egui::Vec2::new(EFB_WIDTH as f32, EFB_HEIGHT as f32))
+    }
+
+    fn prepare(&mut self, _: &mut State) {}
+
+    fn show(&mut self, ui: &mut egui::Ui, ctx: &mut Ctx) {
+        let camera = &mut *ctx.camera;
+        if !camera.enabled {
+            ui.label("The free camera is disabled. Enable it in the renderer window.");
+            return;
+        }
+
+        ui.label("Drag to look around, hover and use WASD to move and Q/E to go down/up.");
+
+        let aspect_ratio = ctx.renderer.aspect_ratio().value();
+        let available_height = ui.available_height().max(0.0);
+        let size = if ui.available_width() < available_height * aspect_ratio {
+            Vec2::new(ui.available_width(), ui.available_width() / aspect_ratio)
+        } else {
+            Vec2::new(available_height * aspect_ratio, available_height)
+        };
+
+        let (rect, response) = ui.allocate_exact_size(size, egui::Sense::drag());
+        ui.painter().add(egui_wgpu::Callback::new_paint_callback(
+            rect,
+            RendererCallback::new(ctx.renderer.clone()),
+        ));
+
+        if response.dragged() {
+            let delta = response.drag_delta();
+            camera.look(delta.x, delta.y);
+        }
+
+        if !response.hovered() {
+            return;
+        }
+
+        camera.captured = true;
+        let (direction, seconds) = ui.input(|i| {
+            let axis = |low, high| match (i.key_down(low), i.key_down(high)) {
+                (true, false) => -1.0,
+                (false, true) => 1.0,
+                _ => 0.0,
+            };
+
+            let direction = Vec3::new(
+                axis(egui::Key::A, egui::Key::D),
+                axis(egui::Key::Q, egui::Key::E),
+                axis(egui::Key::W, egui::Key::S),
+            );
+
+            (direction, i.stable_dt.min(0.1))
+        });
+
+        if direction != Vec3::ZERO {
+            camera.move_by(direction, seconds);
+            ui.ctx().request_repaint();
+        }
+    }
+}
@@ -1,8 +1,13 @@
 use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufWriter;
 
 use eframe::egui::{self, RichText};
-use lazuli::Address;
+use lazuli::breakpoint::{Condition, ValueWatch};
+use lazuli::gekko::{Exception, ExceptionSet};
+use lazuli::{Address, diagnostics};
 use serde::{Deserialize, Serialize};
+use strum::VariantArray;
 
 use crate::State;
 use crate::windows::{AppWindow, Ctx};
@@ -21,9 +26,59 @@ pub struct Window {
     breakpoint_text: String,
     #[serde(default)]
     labels: HashMap<u32, String>,
+    #[serde(default)]
+    conditions: HashMap<u32, String>,
+    #[serde(skip)]
+    conditions_to_apply: Vec<u32>,
+    #[serde(skip)]
+    condition_errors: HashMap<u32, String>,
+    #[serde(skip)]
+    hits: HashMap<u32, u64>,
+    #[serde(skip)]
+    exceptions: ExceptionSet,
+    #[serde(skip)]
+    exceptions_changed: bool,
+    #[serde(skip)]
+    save_bundle: bool,
+    #[serde(skip)]
+    bundle_include_ram: bool,
+    #[serde(skip)]
+    bundle_status: Option<String>,
+    #[serde(skip)]
+    watch_text: String,
+    #[serde(skip)]
+    watch_mask_text: String,
+    #[serde(skip)]
+    watch_to_add: Option<(u32, u32)>,
+    #[serde(skip)]
+    watch_to_remove: Option<u32>,
+    #[serde(skip)]
+    watches: Vec<ValueWatch>,
 }
 
-impl Window {}
+impl Window {
+    /// Writes a diagnostic bundle of the current state to the working directory, returning a
+    /// message describing the outcome.
+    fn write_bundle(&self, state: &mut State) -> String {
+        let bundle = state.lazuli.diagnostic_bundle(diagnostics::Options {
+            include_ram: self.bundle_include_ram,
+        });
+
+        let path = format!("diagnostics-{}.lzdiag", bundle.created);
+        let result = File::create(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|file| {
+                bundle
+                    .write(BufWriter::new(file))
+                    .map_err(|e| e.to_string())
+            });
+
+        match result {
+            Ok(()) => format!("Saved to {path}"),
+            Err(e) => format!("Failed to save {path}: {e}"),
+        }
+    }
+}
 
 #[typetag::serde(name = "control")]
 impl AppWindow for Window {
@@ -32,28 +87,83 @@ impl AppWindow for Window {
     }
 
     fn default_size(&self) -> Option<egui::Vec2> {
-        Some(egui::Vec2::new(100.0, 200.0))
+        Some(egui::Vec2::new(420.0, 200.0))
     }
 
     fn prepare(&mut self, state: &mut State) {
         for breakpoint in self.breakpoints_to_add.drain(..) {
             state.add_breakpoint(Address(breakpoint));
+
+            // breakpoints restored from a previous session keep their condition
+            if self.conditions.contains_key(&breakpoint) {
+                self.conditions_to_apply.push(breakpoint);
+            }
         }
 
         if let Some(breakpoint) = self.breakpoint_to_remove.take() {
             state.remove_breakpoint(Address(breakpoint));
         }
 
+        for breakpoint in self.conditions_to_apply.drain(..) {
+            let text = self.conditions.get(&breakpoint).map_or("", String::as_str);
+            match text.parse::<Condition>() {
+                Ok(condition) => {
+                    // setting a condition resets the hit count, so only do it if it changed
+                    let current = state.breakpoints.get(Address(breakpoint));
+                    if current.is_some_and(|b| b.condition != condition) {
+                        state
+                            .breakpoints
+                            .set_condition(Address(breakpoint), condition);
+                    }
+
+                    self.condition_errors.remove(&breakpoint);
+                }
+                Err(e) => {
+                    self.condition_errors.insert(breakpoint, e.to_string());
+                }
+            }
+        }
+
         self.breakpoints.clear();
         self.breakpoints
-            .extend(state.breakpoints.iter().map(|b| b.value()));
+            .extend(state.breakpoints.addresses().iter().map(|b| b.value()));
         self.labels.retain(|b, _| self.breakpoints.contains(b));
+        self.conditions.retain(|b, _| self.breakpoints.contains(b));
+
+        self.hits.clear();
+        self.hits
+            .extend(state.breakpoints.iter().map(|b| (b.addr.value(), b.hits)));
+
+        if std::mem::take(&mut self.exceptions_changed) {
+            state.breakpoints.set_exceptions(self.exceptions);
+        } else {
+            self.exceptions = state.breakpoints.exceptions();
+        }
+
+        if let Some((addr, mask)) = self.watch_to_add.take() {
+            let sys = &mut state.lazuli.sys;
+            match sys.read::<u32>(Address(addr)) {
+                Some(current) => sys.watches.add(Address(addr), mask, current),
+                None => tracing::warn!("can't watch {}: address is not mapped", Address(addr)),
+            }
+        }
+
+        if let Some(addr) = self.watch_to_remove.take() {
+            state.lazuli.sys.watches.remove(Address(addr));
+        }
+
+        self.watches.clear();
+        self.watches
+            .extend(state.lazuli.sys.watches.iter().copied());
+
+        if std::mem::take(&mut self.save_bundle) {
+            self.bundle_status = Some(self.write_bundle(state));
+        }
 
         self.current_pc = state.lazuli.sys.cpu.pc.value();
     }
 
     fn show(&mut self, ui: &mut egui::Ui, ctx: &mut Ctx) {
-        ui.set_max_width(150.0);
         ui.horizontal(|ui| {
             ui.checkbox(&mut ctx.running, "Run");
 
@@ -63,6 +173,99 @@ impl AppWindow for Window {
             }
         });
 
+        ui.horizontal(|ui| {
+            if ui.button("Reset").clicked() {
+                ctx.reset = Some(false);
+            }
+
+            if ui.button("Power Cycle").clicked() {
+                ctx.reset = Some(true);
+            }
+        });
+
+        ui.horizontal(|ui| {
+            if ui.button("Save Diagnostic Bundle").clicked() {
+                self.save_bundle = true;
+            }
+
+            ui.checkbox(&mut self.bundle_include_ram, "Include RAM")
+                .on_hover_text("RAM likely contains game data, only share it if you own the game");
+        });
+
+        if let Some(status) = &self.bundle_status {
+            ui.label(status);
+        }
+
+        ui.separator();
+        ui.collapsing("Break on exception", |ui| {
+            ui.horizontal_wrapped(|ui| {
+                for exception in Exception::VARIANTS {
+                    let mut armed = self.exceptions.contains(*exception);
+                    if ui.checkbox(&mut armed, format!("{exception:?}")).changed() {
+                        self.exceptions.set(*exception, armed);
+                        self.exceptions_changed = true;
+                    }
+                }
+            });
+        });
+
+        ui.separator();
+        ui.collapsing("Value watches", |ui| {
+            ui.horizontal(|ui| {
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.watch_text)
+                        .hint_text("address")
+                        .desired_width(100.0),
+                );
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.watch_mask_text)
+                        .hint_text("FFFFFFFF")
+                        .desired_width(80.0),
+                );
+
+                if ui.button("Watch").clicked() {
+                    let parse = |text: &str| {
+                        let text = text.trim();
+                        let clean = text.strip_prefix("0x").unwrap_or(text).replace("_", "");
+                        u32::from_str_radix(&clean, 16).ok()
+                    };
+
+                    let mask = if self.watch_mask_text.trim().is_empty() {
+                        Some(!0)
+                    } else {
+                        parse(&self.watch_mask_text)
+                    };
+
+                    if let Some(addr) = parse(&self.watch_text)
+                        && let Some(mask) = mask
+                    {
+                        self.watch_to_add = Some((addr, mask));
+                    }
+                }
+            });
+
+            egui::Grid::new("watches")
+                .num_columns(4)
+                .striped(true)
+                .show(ui, |ui| {
+                    for title in ["", "Address", "Mask", "Value"] {
+                        ui.label(title);
+                    }
+                    ui.end_row();
+
+                    for watch in &self.watches {
+                        if ui.button("🗑").clicked() {
+                            self.watch_to_remove = Some(watch.addr.value());
+                        }
+
+                        ui.label(watch.addr.to_string());
+                        ui.label(format!("{:08X}", watch.mask));
+                        ui.label(format!("{:08X}", watch.old));
+                        ui.end_row();
+                    }
+                });
+        });
+
         ui.separator();
         ui.label("Breakpoints");
 
@@ -73,7 +276,8 @@ impl AppWindow for Window {
             });
 
             if ui.button("Add").clicked() {
-                let clean = self.breakpoint_text.trim_prefix("0x").replace("_", "");
+                let text = self.breakpoint_text.as_str();
+                let clean = text.strip_prefix("0x").unwrap_or(text).replace("_", "");
                 if let Ok(addr) = u32::from_str_radix(&clean, 16) {
                     self.breakpoints_to_add.push(addr);
                 }
@@ -83,26 +287,56 @@ impl AppWindow for Window {
         egui::ScrollArea::vertical()
             .auto_shrink(false)
             .show(ui, |ui| {
-                for breakpoint in &self.breakpoints {
-                    ui.horizontal(|ui| {
-                        if ui.button("🗑").clicked() {
-                            self.breakpoint_to_remove = Some(*breakpoint);
+                egui::Grid::new("breakpoints")
+                    .num_columns(5)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for title in ["", "Address", "Condition", "Hits", "Label"] {
+                            ui.label(title);
                         }
+                        ui.end_row();
 
-                        let text = RichText::new(Address(*breakpoint).to_string()).color(
-                            if *breakpoint == self.current_pc {
-                                egui::Color32::LIGHT_RED
-                            } else {
-                                egui::Color32::GRAY
-                            },
-                        );
+                        for breakpoint in &self.breakpoints {
+                            if ui.button("🗑").clicked() {
+                                self.breakpoint_to_remove = Some(*breakpoint);
+                            }
 
-                        ui.label(text);
-                    });
+                            let text = RichText::new(Address(*breakpoint).to_string()).color(
+                                if *breakpoint == self.current_pc {
+                                    egui::Color32::LIGHT_RED
+                                } else {
+                                    egui::Color32::GRAY
+                                },
+                            );
+                            ui.label(text);
 
-                    let label = self.labels.entry(*breakpoint).or_default();
-                    ui.text_edit_singleline(label);
-                }
+                            let error = self.condition_errors.get(breakpoint);
+                            let condition = self.conditions.entry(*breakpoint).or_default();
+                            let mut edit = egui::TextEdit::singleline(condition)
+                                .hint_text("always")
+                                .desired_width(160.0);
+                            if error.is_some() {
+                                edit = edit.text_color(egui::Color32::LIGHT_RED);
+                            }
+
+                            let mut response = ui.add(edit);
+                            if let Some(error) = error {
+                                response = response.on_hover_text(error);
+                            }
+
+                            // applied once done editing, which includes pressing enter
+                            if response.lost_focus() {
+                                self.conditions_to_apply.push(*breakpoint);
+                            }
+
+                            let hits = self.hits.get(breakpoint).copied().unwrap_or_default();
+                            ui.label(hits.to_string());
+
+                            let label = self.labels.entry(*breakpoint).or_default();
+                            ui.add(egui::TextEdit::singleline(label).desired_width(100.0));
+                            ui.end_row();
+                        }
+                    });
             });
     }
 }
@@ -0,0 +1,62 @@
+use eframe::egui;
+use modules::input::{AnalogConfig, ResponseCurve};
+use serde::{Deserialize, Serialize};
+
+use crate::State;
+use crate::windows::{AppWindow, Ctx};
+
+/// Shows the settings of a single axis, returning whether they changed.
+fn axis(ui: &mut egui::Ui, name: &str, config: &mut AnalogConfig) -> bool {
+    let mut changed = false;
+
+    ui.label(name);
+    changed |= ui
+        .add(egui::Slider::new(&mut config.dead_zone, 0.0..=0.5).text("Dead zone"))
+        .changed();
+
+    egui::ComboBox::from_id_salt(("response_curve", name))
+        .selected_text(config.response_curve.name())
+        .show_ui(ui, |ui| {
+            for curve in ResponseCurve::ALL {
+                changed |= ui
+                    .selectable_value(&mut config.response_curve, curve, curve.name())
+                    .changed();
+            }
+        });
+
+    ui.end_row();
+    changed
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Window;
+
+#[typetag::serde(name = "controller")]
+impl AppWindow for Window {
+    fn title(&self) -> &str {
+        "Controller Settings"
+    }
+
+    fn prepare(&mut self, _: &mut State) {}
+
+    fn show(&mut self, ui: &mut egui::Ui, ctx: &mut Ctx) {
+        ui.label("Only applies to gamepads, keyboard input is always digital.");
+
+        let mut analog = ctx.input.analog();
+        let mut changed = false;
+
+        egui::Grid::new("controller_axes")
+            .num_columns(3)
+            .striped(true)
+            .show(ui, |ui| {
+                changed |= axis(ui, "Stick X", &mut analog.stick_x);
+                changed |= axis(ui, "Stick Y", &mut analog.stick_y);
+                changed |= axis(ui, "C-Stick X", &mut analog.c_stick_x);
+                changed |= axis(ui, "C-Stick Y", &mut analog.c_stick_y);
+            });
+
+        if changed {
+            ctx.input.set_analog(analog);
+        }
+    }
+}
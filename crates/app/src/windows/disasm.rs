@@ -1,7 +1,8 @@
 use eframe::egui;
 use egui_extras::{Column, TableBuilder};
 use lazuli::Address;
-use lazuli::gekko::disasm::{Extensions, Ins, ParsedIns};
+use lazuli::gekko::InsExt;
+use lazuli::gekko::disasm::{Extensions, Ins, Opcode, ParsedIns};
 use serde::{Deserialize, Serialize};
 
 use crate::State;
@@ -25,6 +26,11 @@ pub struct Window {
     breakpoints: Vec<u32>,
     #[serde(skip)]
     breakpoint_to_toggle: Option<u32>,
+    #[serde(skip)]
+    dump_request: Option<u32>,
+    /// Address and dump of the compiled code being shown, if any.
+    #[serde(skip)]
+    compiled: Option<(u32, String)>,
 }
 
 impl Default for Window {
@@ -40,12 +46,28 @@ impl Default for Window {
             rows: 0,
             breakpoints: Vec::new(),
             breakpoint_to_toggle: None,
+            dump_request: None,
+            compiled: None,
         }
     }
 }
 
 impl Window {}
 
+/// Replaces the SPR number of `mfspr`/`mtspr` in the disassembly of `ins` with its name. Unknown
+/// SPRs are kept in their numeric form.
+fn name_spr(ins: &Ins, text: String) -> String {
+    let Some(name) = ins.spr_name() else {
+        return text;
+    };
+
+    match ins.op {
+        Opcode::Mfspr if text.starts_with("mfspr ") => format!("mfspr r{}, {name}", ins.field_rd()),
+        Opcode::Mtspr if text.starts_with("mtspr ") => format!("mtspr {name}, r{}", ins.field_rs()),
+        _ => text,
+    }
+}
+
 #[typetag::serde(name = "disasm")]
 impl AppWindow for Window {
     fn title(&self) -> &str {
@@ -55,17 +77,26 @@ impl AppWindow for Window {
     fn prepare(&mut self, state: &mut State) {
         self.breakpoints.clear();
         self.breakpoints
-            .extend(state.breakpoints.iter().map(|b| b.value()));
+            .extend(state.breakpoints.addresses().iter().map(|b| b.value()));
 
         if let Some(breakpoint) = self.breakpoint_to_toggle.take() {
             let breakpoint = Address(breakpoint);
-            if state.breakpoints.contains(&breakpoint) {
+            if state.breakpoints.contains(breakpoint) {
                 state.remove_breakpoint(breakpoint);
             } else {
                 state.add_breakpoint(breakpoint);
             }
         }
 
+        if let Some(addr) = self.dump_request.take() {
+            let dump = state
+                .lazuli
+                .dump_block(Address(addr))
+                .unwrap_or_else(|| format!("No compiled block at {}", Address(addr)));
+
+            self.compiled = Some((addr, dump));
+        }
+
         let emulator = &state.lazuli;
         self.pc = emulator.sys.cpu.pc.value();
 
@@ -88,17 +119,47 @@ impl AppWindow for Window {
         }
     }
 
-    fn show(&mut self, ui: &mut egui::Ui, _: &mut Ctx) {
+    fn show(&mut self, ui: &mut egui::Ui, ctx: &mut Ctx) {
+        if let Some(target) = ctx.goto.take() {
+            self.follow_pc = false;
+            self.target = target.value();
+            self.target_text = format!("{:08X}", self.target);
+        }
+
         ui.horizontal(|ui| {
             ui.checkbox(&mut self.follow_pc, "Follow PC");
             ui.checkbox(&mut self.simplified, "Simplified");
+            if ui.button("Show compiled code").clicked() {
+                self.dump_request = Some(self.target);
+            }
         });
 
+        if let Some((addr, dump)) = &self.compiled {
+            let mut open = true;
+            egui::Window::new(format!("Compiled code at {}", Address(*addr)))
+                .open(&mut open)
+                .default_size(egui::Vec2::new(600.0, 400.0))
+                .show(ui.ctx(), |ui| {
+                    if ui.button("Copy").clicked() {
+                        ui.ctx().copy_text(dump.clone());
+                    }
+
+                    egui::ScrollArea::both().auto_shrink(false).show(ui, |ui| {
+                        ui.label(egui::RichText::new(dump).family(egui::FontFamily::Monospace));
+                    });
+                });
+
+            if !open {
+                self.compiled = None;
+            }
+        }
+
         if !self.follow_pc {
             ui.horizontal(|ui| {
                 ui.label("Target: ");
                 if ui.text_edit_singleline(&mut self.target_text).lost_focus() {
-                    let clean = self.target_text.trim_prefix("0x").replace("_", "");
+                    let text = self.target_text.as_str();
+                    let clean = text.strip_prefix("0x").unwrap_or(text).replace("_", "");
                     if let Ok(addr) = u32::from_str_radix(&clean, 16) {
                         self.target = addr;
                         self.target_text = format!("{:08X}", self.target);
@@ -177,7 +238,8 @@ impl AppWindow for Window {
                                 ins.parse_basic(&mut parsed);
                             }
 
-                            let text = egui::RichText::new(parsed.to_string())
+                            let text = name_spr(&ins, parsed.to_string());
+                            let text = egui::RichText::new(text)
                                 .color(egui::Color32::LIGHT_GRAY)
                                 .family(egui::FontFamily::Monospace);
 
@@ -11,6 +11,12 @@ pub struct RendererCallback {
     renderer: Renderer,
 }
 
+impl RendererCallback {
+    pub fn new(renderer: Renderer) -> Self {
+        Self { renderer }
+    }
+}
+
 impl CallbackTrait for RendererCallback {
     fn paint(
         &self,
@@ -39,7 +45,7 @@ impl AppWindow for Window {
 
     fn show(&mut self, ui: &mut egui::Ui, ctx: &mut Ctx) {
         egui::Frame::canvas(ui.style()).show(ui, |ui| {
-            let aspect_ratio = 4.0 / 3.0;
+            let aspect_ratio = ctx.renderer.aspect_ratio().value();
             let available_height = (ui.available_height() - 20.0).max(0.0);
 
             let rect = if ui.available_width() < available_height {
@@ -58,9 +64,7 @@ impl AppWindow for Window {
 
             ui.painter().add(egui_wgpu::Callback::new_paint_callback(
                 rect,
-                RendererCallback {
-                    renderer: ctx.renderer.clone(),
-                },
+                RendererCallback::new(ctx.renderer.clone()),
             ));
         });
     }
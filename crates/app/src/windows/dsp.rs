@@ -0,0 +1,500 @@
+use std::collections::VecDeque;
+
+use cores::dsp::interpreter::Core;
+use cores::dsp::interpreter::dspint::{Ins, Mail, MailDirection, ProbedMmio, Reg, Registers};
+use eframe::egui::{self, Color32};
+use egui_extras::{Column, TableBuilder};
+use serde::{Deserialize, Serialize};
+
+use crate::State;
+use crate::windows::{AppWindow, Ctx};
+
+/// Number of instructions shown around the PC.
+const DISASM_ROWS: u16 = 24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+enum Group {
+    #[default]
+    Registers,
+    Stacks,
+    Accelerator,
+    Mailboxes,
+    Mmio,
+}
+
+#[derive(Default)]
+struct Accelerator {
+    format: u16,
+    predictor: u16,
+    aram_start: u32,
+    aram_end: u32,
+    aram_curr: u32,
+    gain: i16,
+    input: i16,
+    previous_samples: [i16; 2],
+}
+
+#[derive(Default)]
+struct Mailboxes {
+    cpu: (bool, u32),
+    dsp: (bool, u32),
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Window {
+    group: Group,
+    #[serde(skip)]
+    available: bool,
+    #[serde(skip)]
+    pc: u16,
+    #[serde(skip)]
+    instructions: Vec<(u16, Ins)>,
+    #[serde(skip)]
+    regs: Option<Registers>,
+    #[serde(skip)]
+    accel: Accelerator,
+    #[serde(skip)]
+    mailboxes: Mailboxes,
+    #[serde(skip)]
+    mail_history: VecDeque<Mail>,
+    #[serde(skip)]
+    probed_mmio: Vec<(u8, ProbedMmio)>,
+    #[serde(skip)]
+    breakpoints: Vec<u16>,
+    #[serde(skip)]
+    breakpoint_to_toggle: Option<u16>,
+    #[serde(skip)]
+    breakpoint_text: String,
+    #[serde(skip)]
+    step: bool,
+}
+
+fn read_ins_word(core: &Core, addr: u16) -> u16 {
+    let mem = &core.interpreter.mem;
+    match addr {
+        0x0000..0x1000 => mem.iram[addr as usize],
+        0x8000..0x9000 => mem.irom[addr as usize - 0x8000],
+        _ => 0,
+    }
+}
+
+fn key(ui: &mut egui::Ui, text: impl Into<String>) {
+    let text = egui::RichText::new(text)
+        .family(egui::FontFamily::Monospace)
+        .color(Color32::LIGHT_BLUE);
+
+    ui.label(text);
+}
+
+fn value(ui: &mut egui::Ui, text: impl Into<String>) {
+    let text = egui::RichText::new(text)
+        .family(egui::FontFamily::Monospace)
+        .color(Color32::LIGHT_GREEN);
+
+    ui.label(text);
+}
+
+fn table(ui: &mut egui::Ui, rows: &[(String, String)]) {
+    let builder = TableBuilder::new(ui)
+        .auto_shrink(egui::Vec2b::new(false, true))
+        .striped(true)
+        .resizable(false)
+        .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+        .column(Column::auto())
+        .column(Column::remainder());
+
+    let table = builder.header(20.0, |mut header| {
+        header.col(|ui| {
+            ui.label("Name");
+        });
+        header.col(|ui| {
+            ui.label("Value");
+        });
+    });
+
+    table.body(|mut body| {
+        for (name, val) in rows {
+            body.row(20.0, |mut row| {
+                row.col(|ui| key(ui, name));
+                row.col(|ui| value(ui, val));
+            });
+        }
+    });
+}
+
+impl Window {
+    fn disassembly(&mut self, ui: &mut egui::Ui) {
+        let builder = TableBuilder::new(ui)
+            .id_salt("dsp_disasm")
+            .auto_shrink(egui::Vec2b::new(false, true))
+            .striped(true)
+            .resizable(false)
+            .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+            .column(Column::auto())
+            .column(Column::remainder());
+
+        let table = builder.header(20.0, |mut header| {
+            header.col(|ui| {
+                ui.label("Address");
+            });
+            header.col(|ui| {
+                ui.label("Instruction");
+            });
+        });
+
+        table.body(|mut body| {
+            for (addr, ins) in &self.instructions {
+                body.row(20.0, |mut row| {
+                    row.col(|ui| {
+                        let color = if *addr == self.pc {
+                            Color32::LIGHT_RED
+                        } else {
+                            Color32::LIGHT_BLUE
+                        };
+
+                        let symbol =
+                            egui::RichText::new("⏺").color(if self.breakpoints.contains(addr) {
+                                Color32::LIGHT_RED
+                            } else {
+                                Color32::GRAY
+                            });
+                        let toggle = egui::Label::new(symbol)
+                            .selectable(false)
+                            .sense(egui::Sense::click());
+
+                        let text = egui::RichText::new(format!("{addr:04X}"))
+                            .family(egui::FontFamily::Monospace)
+                            .color(color);
+
+                        ui.horizontal(|ui| {
+                            if ui.add(toggle).clicked() {
+                                self.breakpoint_to_toggle = Some(*addr);
+                            }
+
+                            ui.label(text);
+                        });
+                    });
+
+                    row.col(|ui| {
+                        let text = egui::RichText::new(ins.to_string())
+                            .family(egui::FontFamily::Monospace)
+                            .color(Color32::LIGHT_GRAY);
+
+                        ui.label(text);
+                    });
+                });
+            }
+        });
+    }
+
+    fn registers(&self, ui: &mut egui::Ui) {
+        let Some(regs) = &self.regs else {
+            return;
+        };
+
+        let mut rows = Vec::new();
+        rows.push(("PC".to_string(), format!("{:04X}", self.pc)));
+        for i in 0..4 {
+            rows.push((format!("AR{i}"), format!("{:04X}", regs.addressing[i])));
+            rows.push((format!("IX{i}"), format!("{:04X}", regs.indexing[i])));
+            rows.push((format!("WR{i}"), format!("{:04X}", regs.wrapping[i])));
+        }
+
+        for (i, acc) in regs.acc40.iter().enumerate() {
+            let value = acc.get();
+            rows.push((
+                format!("AC{i}"),
+                format!("{:010X} ({value})", value as u64 & 0xFF_FFFF_FFFF),
+            ));
+        }
+
+        for (i, ax) in regs.acc32.iter().enumerate() {
+            rows.push((format!("AX{i}"), format!("{:08X} ({ax})", *ax as u32)));
+        }
+
+        let (_, _, prod) = regs.product.get();
+        rows.push((
+            "PROD".to_string(),
+            format!("{:010X} ({prod})", prod as u64 & 0xFF_FFFF_FFFF),
+        ));
+        rows.push(("CR".to_string(), format!("{:02X}", regs.config)));
+        rows.push((
+            "SR".to_string(),
+            format!("{:04X}", regs.get_pure(Reg::Status)),
+        ));
+
+        let status = regs.status;
+        let flags = [
+            ("C", status.carry()),
+            ("O", status.overflow()),
+            ("Z", status.arithmetic_zero()),
+            ("S", status.sign()),
+            ("AS", status.above_s32()),
+            ("TT", status.top_two_bits_eq()),
+            ("LZ", status.logic_zero()),
+            ("OS", status.overflow_fused()),
+            ("IE", status.interrupt_enable()),
+            ("EIE", status.external_interrupt_enable()),
+            ("AM", status.dont_double_result()),
+            ("SXM", status.sign_extend_to_40()),
+            ("SU", status.unsigned_mul()),
+        ];
+
+        let set = flags
+            .iter()
+            .filter(|(_, set)| *set)
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(" ");
+        rows.push(("Flags".to_string(), set));
+
+        table(ui, &rows);
+    }
+
+    fn stacks(&self, ui: &mut egui::Ui) {
+        let Some(regs) = &self.regs else {
+            return;
+        };
+
+        let fmt = |stack: &[u16]| {
+            stack
+                .iter()
+                .rev()
+                .map(|v| format!("{v:04X}"))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+
+        let rows = [
+            ("ST0 (call)".to_string(), fmt(&regs.call_stack)),
+            ("ST1 (data)".to_string(), fmt(&regs.data_stack)),
+            ("ST2 (loop addr)".to_string(), fmt(&regs.loop_stack)),
+            ("ST3 (loop count)".to_string(), fmt(&regs.loop_count)),
+        ];
+
+        table(ui, &rows);
+    }
+
+    fn accelerator(&self, ui: &mut egui::Ui) {
+        let accel = &self.accel;
+        let rows = [
+            ("Format".to_string(), format!("{:04X}", accel.format)),
+            ("Predictor".to_string(), format!("{:04X}", accel.predictor)),
+            ("Start".to_string(), format!("{:08X}", accel.aram_start)),
+            ("End".to_string(), format!("{:08X}", accel.aram_end)),
+            ("Current".to_string(), format!("{:08X}", accel.aram_curr)),
+            ("Gain".to_string(), format!("{}", accel.gain)),
+            ("Input".to_string(), format!("{}", accel.input)),
+            ("YN1".to_string(), format!("{}", accel.previous_samples[0])),
+            ("YN2".to_string(), format!("{}", accel.previous_samples[1])),
+        ];
+
+        table(ui, &rows);
+    }
+
+    fn mailboxes(&self, ui: &mut egui::Ui) {
+        let fmt = |(status, data): (bool, u32)| {
+            format!("{data:08X} {}", if status { "(full)" } else { "(empty)" })
+        };
+
+        let rows = [
+            ("CPU -> DSP".to_string(), fmt(self.mailboxes.cpu)),
+            ("DSP -> CPU".to_string(), fmt(self.mailboxes.dsp)),
+        ];
+
+        table(ui, &rows);
+
+        ui.separator();
+        ui.label("History");
+
+        let rows = self
+            .mail_history
+            .iter()
+            .rev()
+            .map(|mail| {
+                let direction = match mail.direction {
+                    MailDirection::CpuToDsp => "CPU -> DSP",
+                    MailDirection::DspToCpu => "DSP -> CPU",
+                };
+
+                (direction.to_string(), format!("{:08X}", mail.data))
+            })
+            .collect::<Vec<_>>();
+
+        ui.push_id("dsp_mail_history", |ui| table(ui, &rows));
+    }
+
+    fn mmio(&self, ui: &mut egui::Ui) {
+        if self.probed_mmio.is_empty() {
+            ui.label("No unknown MMIO offsets have been accessed");
+            return;
+        }
+
+        ui.label("Unknown offsets accessed so far. Reads return 0 and writes are ignored.");
+
+        let rows = self
+            .probed_mmio
+            .iter()
+            .map(|(offset, probed)| {
+                let last = probed
+                    .last_written
+                    .map_or_else(|| "-".to_string(), |v| format!("{v:04X}"));
+
+                (
+                    format!("FF{offset:02X}"),
+                    format!("R {} W {} (last {last})", probed.reads, probed.writes),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        table(ui, &rows);
+    }
+}
+
+#[typetag::serde(name = "dsp")]
+impl AppWindow for Window {
+    fn title(&self) -> &str {
+        "DSP"
+    }
+
+    fn default_size(&self) -> Option<egui::Vec2> {
+        Some(egui::Vec2::new(600.0, 500.0))
+    }
+
+    fn prepare(&mut self, state: &mut State) {
+        if std::mem::take(&mut self.step) {
+            state.lazuli.step_dsp();
+        }
+
+        let sys = &state.lazuli.sys;
+        self.mailboxes = Mailboxes {
+            cpu: (
+                sys.dsp.cpu_mailbox.status(),
+                sys.dsp.cpu_mailbox.data().value(),
+            ),
+            dsp: (
+                sys.dsp.dsp_mailbox.status(),
+                sys.dsp.dsp_mailbox.data().value(),
+            ),
+        };
+
+        let Some(core) = state
+            .lazuli
+            .cores_mut()
+            .dsp
+            .as_any_mut()
+            .downcast_mut::<Core>()
+        else {
+            self.available = false;
+            return;
+        };
+
+        self.available = true;
+
+        let interpreter = &mut core.interpreter;
+        if let Some(breakpoint) = self.breakpoint_to_toggle.take() {
+            if interpreter.breakpoints.contains(&breakpoint) {
+                interpreter.breakpoints.retain(|b| *b != breakpoint);
+            } else {
+                interpreter.breakpoints.push(breakpoint);
+            }
+        }
+
+        self.breakpoints.clone_from(&interpreter.breakpoints);
+        self.pc = interpreter.pc;
+        self.regs = Some(interpreter.regs.clone());
+        self.mail_history.clone_from(&interpreter.mail_history);
+        self.probed_mmio.clear();
+        self.probed_mmio.extend(
+            interpreter
+                .probed_mmio
+                .iter()
+                .map(|(offset, probed)| (*offset, *probed)),
+        );
+
+        let accel = &interpreter.accel;
+        self.accel = Accelerator {
+            format: accel.format.to_bits(),
+            predictor: accel.predictor.to_bits(),
+            aram_start: accel.aram_start,
+            aram_end: accel.aram_end,
+            aram_curr: accel.aram_curr,
+            gain: accel.gain,
+            input: accel.input,
+            previous_samples: accel.previous_samples,
+        };
+
+        self.instructions.clear();
+        let mut current = self.pc.saturating_sub(DISASM_ROWS / 2);
+        for _ in 0..DISASM_ROWS {
+            let base = read_ins_word(core, current);
+            let mut ins = Ins::new(base);
+
+            let len = if ins.decoded().needs_extra {
+                ins.extra = read_ins_word(core, current.wrapping_add(1));
+                2
+            } else {
+                1
+            };
+
+            self.instructions.push((current, ins));
+            current = current.wrapping_add(len);
+        }
+    }
+
+    fn show(&mut self, ui: &mut egui::Ui, ctx: &mut Ctx) {
+        if !self.available {
+            ui.label("The current DSP core does not support debugging");
+            return;
+        }
+
+        ui.horizontal(|ui| {
+            let button = egui::Button::new("Step DSP");
+            if ui.add_enabled(!ctx.running, button).clicked() {
+                self.step = true;
+            }
+
+            ui.separator();
+            ui.label("Breakpoint:");
+            ui.scope(|ui| {
+                ui.set_max_width(60.0);
+                ui.text_edit_singleline(&mut self.breakpoint_text);
+            });
+
+            if ui.button("Toggle").clicked() {
+                let text = self.breakpoint_text.as_str();
+                let clean = text.strip_prefix("0x").unwrap_or(text).replace("_", "");
+                if let Ok(addr) = u16::from_str_radix(&clean, 16) {
+                    self.breakpoint_to_toggle = Some(addr);
+                }
+            }
+        });
+
+        ui.separator();
+
+        ui.columns(2, |columns| {
+            egui::ScrollArea::vertical()
+                .id_salt("dsp_disasm_scroll")
+                .show(&mut columns[0], |ui| self.disassembly(ui));
+
+            let ui = &mut columns[1];
+            ui.horizontal(|ui| {
+                ui.selectable_value(&mut self.group, Group::Registers, "Registers");
+                ui.selectable_value(&mut self.group, Group::Stacks, "Stacks");
+                ui.selectable_value(&mut self.group, Group::Accelerator, "Accelerator");
+                ui.selectable_value(&mut self.group, Group::Mailboxes, "Mailboxes");
+                ui.selectable_value(&mut self.group, Group::Mmio, "MMIO");
+            });
+
+            egui::ScrollArea::vertical()
+                .id_salt("dsp_info_scroll")
+                .show(ui, |ui| match self.group {
+                    Group::Registers => self.registers(ui),
+                    Group::Stacks => self.stacks(ui),
+                    Group::Accelerator => self.accelerator(ui),
+                    Group::Mailboxes => self.mailboxes(ui),
+                    Group::Mmio => self.mmio(ui),
+                });
+        });
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::VecDeque;
+use std::sync::mpsc::{self, Receiver};
+
+use cores::dsp::interpreter::Core;
+use eframe::egui::{self, Color32, Vec2};
+use serde::{Deserialize, Serialize};
+
+use crate::State;
+use crate::windows::{AppWindow, Ctx};
+
+/// How many of the latest samples are plotted.
+const SAMPLES: usize = 1024;
+
+const WAVEFORM_COLOR: Color32 = Color32::from_rgb(110, 200, 110);
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Window {
+    #[serde(skip)]
+    available: bool,
+    #[serde(skip)]
+    receiver: Option<Receiver<i16>>,
+    #[serde(skip)]
+    samples: VecDeque<i16>,
+}
+
+impl Window {
+    /// Plots the samples as a waveform, with the newest sample on the right.
+    fn waveform(&self, ui: &mut egui::Ui) {
+        let (response, painter) =
+            ui.allocate_painter(Vec2::new(ui.available_width(), 200.0), egui::Sense::hover());
+        let rect = response.rect;
+        painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+        painter.hline(
+            rect.x_range(),
+            rect.center().y,
+            egui::Stroke::new(1.0, ui.visuals().weak_text_color()),
+        );
+
+        let step = rect.width() / (SAMPLES - 1) as f32;
+        let offset = SAMPLES - self.samples.len();
+        let points = self
+            .samples
+            .iter()
+            .enumerate()
+            .map(|(i, sample)| {
+                let x = rect.left() + (offset + i) as f32 * step;
+                let y = rect.center().y - *sample as f32 / 32768.0 * rect.height() / 2.0;
+                egui::pos2(x, y)
+            })
+            .collect::<Vec<_>>();
+
+        painter.add(egui::Shape::line(
+            points,
+            egui::Stroke::new(1.0, WAVEFORM_COLOR),
+        ));
+    }
+}
+
+#[typetag::serde(name = "dsp_audio")]
+impl AppWindow for Window {
+    fn title(&self) -> &str {
+        "DSP Audio"
+    }
+
+    fn default_size(&self) -> Option<Vec2> {
+        Some(Vec2::new(480.0, 260.0))
+    }
+
+    fn prepare(&mut self, state: &mut State) {
+        let Some(core) = state
+            .lazuli
+            .cores_mut()
+            .dsp
+            .as_any_mut()
+            .downcast_mut::<Core>()
+        else {
+            self.available = false;
+            return;
+        };
+
+        self.available = true;
+
+        // install a sink if the core has none, e.g. because it was replaced
+        let interpreter = &mut core.interpreter;
+        if self.receiver.is_none() || interpreter.sample_sink.is_none() {
+            let (sender, receiver) = mpsc::channel();
+            interpreter.sample_sink = Some(sender);
+            self.receiver = Some(receiver);
+        }
+
+        if let Some(receiver) = &self.receiver {
+            self.samples.extend(receiver.try_iter());
+            if self.samples.len() > SAMPLES {
+                self.samples.drain(..self.samples.len() - SAMPLES);
+            }
+        }
+    }
+
+    fn show(&mut self, ui: &mut egui::Ui, _: &mut Ctx) {
+        if !self.available {
+            ui.label("The current DSP core does not support debugging");
+            return;
+        }
+
+        self.waveform(ui);
+
+        let (min, max) = self
+            .samples
+            .iter()
+            .fold((0, 0), |(min, max), &s| (s.min(min), s.max(max)));
+        ui.label(format!(
+            "Last {} samples, min {min}, max {max}",
+            self.samples.len()
+        ));
+    }
+}
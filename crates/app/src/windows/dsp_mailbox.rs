@@ -0,0 +1,119 @@
+use cores::dsp::interpreter::Core;
+use cores::dsp::interpreter::dspint::{MailDirection, MailboxEvent};
+use eframe::egui::{self, Color32};
+use egui_extras::{Column, TableBuilder};
+use serde::{Deserialize, Serialize};
+
+use crate::State;
+use crate::windows::{AppWindow, Ctx};
+
+/// Maximum number of mails kept by the window. Older mails are dropped first.
+const MAX_EVENTS: usize = 4096;
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Window {
+    record: bool,
+    #[serde(skip)]
+    available: bool,
+    #[serde(skip)]
+    events: Vec<MailboxEvent>,
+}
+
+fn cell(ui: &mut egui::Ui, text: impl Into<String>, color: Color32) {
+    let text = egui::RichText::new(text)
+        .family(egui::FontFamily::Monospace)
+        .color(color);
+
+    ui.label(text);
+}
+
+#[typetag::serde(name = "dsp_mailbox")]
+impl AppWindow for Window {
+    fn title(&self) -> &str {
+        "DSP Mailbox"
+    }
+
+    fn default_size(&self) -> Option<egui::Vec2> {
+        Some(egui::Vec2::new(350.0, 400.0))
+    }
+
+    fn prepare(&mut self, state: &mut State) {
+        let Some(core) = state
+            .lazuli
+            .cores_mut()
+            .dsp
+            .as_any_mut()
+            .downcast_mut::<Core>()
+        else {
+            self.available = false;
+            return;
+        };
+
+        self.available = true;
+
+        let interpreter = &mut core.interpreter;
+        if self.record != interpreter.mailbox_trace.is_some() {
+            interpreter.mailbox_trace = self.record.then(Vec::new);
+        }
+
+        self.events.extend(interpreter.take_mailbox_trace());
+        if self.events.len() > MAX_EVENTS {
+            self.events.drain(..self.events.len() - MAX_EVENTS);
+        }
+    }
+
+    fn show(&mut self, ui: &mut egui::Ui, _: &mut Ctx) {
+        if !self.available {
+            ui.label("The current DSP core does not support debugging");
+            return;
+        }
+
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.record, "Record");
+            if ui.button("Clear").clicked() {
+                self.events.clear();
+            }
+
+            ui.separator();
+            ui.label(format!("{} mails", self.events.len()));
+        });
+
+        ui.separator();
+
+        let builder = TableBuilder::new(ui)
+            .auto_shrink(egui::Vec2b::new(false, false))
+            .striped(true)
+            .resizable(false)
+            .stick_to_bottom(true)
+            .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+            .column(Column::auto()) // cycle
+            .column(Column::auto()) // direction
+            .column(Column::remainder()); // data
+
+        let table = builder.header(20.0, |mut header| {
+            header.col(|ui| {
+                ui.label("Cycle");
+            });
+            header.col(|ui| {
+                ui.label("Direction");
+            });
+            header.col(|ui| {
+                ui.label("Data");
+            });
+        });
+
+        table.body(|body| {
+            body.rows(20.0, self.events.len(), |mut row| {
+                let event = &self.events[row.index()];
+                let direction = match event.direction {
+                    MailDirection::CpuToDsp => "CPU -> DSP",
+                    MailDirection::DspToCpu => "DSP -> CPU",
+                };
+
+                row.col(|ui| cell(ui, event.cycle.to_string(), Color32::GRAY));
+                row.col(|ui| cell(ui, direction, Color32::LIGHT_BLUE));
+                row.col(|ui| cell(ui, format!("{:08X}", event.data), Color32::LIGHT_GREEN));
+            });
+        });
+    }
+}
@@ -0,0 +1,176 @@
+use std::time::Duration;
+
+use eframe::egui::{self, Color32, Vec2};
+use lazuli::timing::{EmulationTimes, HISTORY_LEN, History};
+use renderer::RenderTimes;
+use serde::{Deserialize, Serialize};
+
+use crate::State;
+use crate::windows::{AppWindow, Ctx};
+
+const EMULATION_COLOR: Color32 = Color32::from_rgb(90, 140, 220);
+const GX_COLOR: Color32 = Color32::from_rgb(230, 170, 60);
+const RENDER_COLOR: Color32 = Color32::from_rgb(110, 200, 110);
+const GPU_COLOR: Color32 = Color32::from_rgb(210, 90, 90);
+
+/// Frame time the graph is scaled to by default, i.e. two frames at 60 Hz.
+const DEFAULT_SCALE: Duration = Duration::from_micros(33_333);
+
+fn millis(duration: Duration) -> f32 {
+    duration.as_secs_f32() * 1000.0
+}
+
+fn average<T>(history: &History<T>, f: impl Fn(&T) -> Duration) -> Duration {
+    if history.is_empty() {
+        return Duration::ZERO;
+    }
+
+    history.iter().map(f).sum::<Duration>() / history.len() as u32
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Window {
+    #[serde(skip)]
+    emulation: History<EmulationTimes>,
+}
+
+impl Window {
+    /// Draws one stacked bar per frame, with the newest frame on the right. Emulation and render
+    /// times are aligned by how recent the frame is, since they're measured on different threads.
+    fn graph(&self, ui: &mut egui::Ui, render: &History<RenderTimes>, gpu_timing: bool) {
+        let (response, painter) =
+            ui.allocate_painter(Vec2::new(ui.available_width(), 160.0), egui::Sense::hover());
+        let rect = response.rect;
+        painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+
+        let stacks: Vec<[(Duration, Color32); 4]> = (0..HISTORY_LEN)
+            .map(|age| {
+                let emulation = self.emulation.iter().rev().nth(age).copied();
+                let render = render.iter().rev().nth(age).copied();
+                let emulation = emulation.unwrap_or_default();
+                let render = render.unwrap_or_default();
+
+                // without timestamp queries, fall back to the time spent submitting work
+                let gpu = if gpu_timing {
+                    render.gpu.unwrap_or_default()
+                } else {
+                    render.submit
+                };
+
+                [
+                    (emulation.exec.saturating_sub(emulation.gx), EMULATION_COLOR),
+                    (emulation.gx, GX_COLOR),
+                    (render.cpu.saturating_sub(render.submit), RENDER_COLOR),
+                    (gpu, GPU_COLOR),
+                ]
+            })
+            .collect();
+
+        let tallest = stacks
+            .iter()
+            .map(|stack| stack.iter().map(|(d, _)| *d).sum::<Duration>())
+            .max()
+            .unwrap_or_default()
+            .max(DEFAULT_SCALE);
+
+        let scale = rect.height() / millis(tallest);
+        let bar_width = rect.width() / HISTORY_LEN as f32;
+        for (age, stack) in stacks.iter().enumerate() {
+            let right = rect.right() - age as f32 * bar_width;
+            let mut bottom = rect.bottom();
+            for (duration, color) in stack {
+                let height = millis(*duration) * scale;
+                if height <= 0.0 {
+                    continue;
+                }
+
+                let bar = egui::Rect::from_min_max(
+                    egui::pos2(right - bar_width, bottom - height),
+                    egui::pos2(right, bottom),
+                );
+                painter.rect_filled(bar, 0.0, *color);
+                bottom -= height;
+            }
+        }
+
+        // 60 Hz frame budget
+        let budget = rect.bottom() - millis(DEFAULT_SCALE) / 2.0 * scale;
+        painter.hline(
+            rect.x_range(),
+            budget,
+            egui::Stroke::new(1.0, ui.visuals().weak_text_color()),
+        );
+
+        ui.label(format!("Scale: {:.1} ms", millis(tallest)));
+    }
+}
+
+#[typetag::serde(name = "frame_graph")]
+impl AppWindow for Window {
+    fn title(&self) -> &str {
+        "Frame Graph"
+    }
+
+    fn default_size(&self) -> Option<Vec2> {
+        Some(Vec2::new(480.0, 320.0))
+    }
+
+    fn prepare(&mut self, state: &mut State) {
+        self.emulation = state.lazuli.sys.timing.history().clone();
+    }
+
+    fn show(&mut self, ui: &mut egui::Ui, ctx: &mut Ctx) {
+        let render = ctx.renderer.stats().frame_times;
+        let gpu_timing = render.iter().any(|t| t.gpu.is_some());
+
+        self.graph(ui, &render, gpu_timing);
+
+        ui.horizontal(|ui| {
+            let legend = [
+                (EMULATION_COLOR, "Emulation"),
+                (GX_COLOR, "GX"),
+                (RENDER_COLOR, "Render"),
+                (GPU_COLOR, if gpu_timing { "GPU" } else { "Submit" }),
+            ];
+
+            for (color, name) in legend {
+                ui.label(egui::RichText::new("■").color(color));
+                ui.label(name);
+            }
+        });
+
+        if !gpu_timing {
+            ui.weak("GPU timestamps are unavailable, showing submission times instead");
+        }
+
+        ui.separator();
+
+        let interval = average(&render, |t| t.interval);
+        ui.label(format!(
+            "Frame time: {:.2} ms average, {:.2} ms 1% low",
+            millis(interval),
+            millis(render.low(1.0, |t| t.interval))
+        ));
+        ui.label(format!(
+            "Emulation: {:.2} ms (GX {:.2} ms)",
+            millis(average(&self.emulation, |t| t.exec)),
+            millis(average(&self.emulation, |t| t.gx))
+        ));
+        ui.label(format!(
+            "Render: {:.2} ms ({:.1} passes)",
+            millis(average(&render, |t| t.cpu)),
+            render.iter().map(|t| t.passes as f32).sum::<f32>() / render.len().max(1) as f32
+        ));
+
+        if gpu_timing {
+            let measured = render.iter().filter_map(|t| t.gpu).collect::<Vec<_>>();
+            let gpu = measured.iter().sum::<Duration>() / measured.len().max(1) as u32;
+            ui.label(format!("GPU: {:.2} ms", millis(gpu)));
+        }
+
+        ui.label(format!(
+            "XFB copy to present: {:.2} ms",
+            millis(average(&render, |t| t.xfb_to_present))
+        ));
+    }
+}
@@ -0,0 +1,356 @@
+use bytesize::ByteSize;
+use eframe::egui::{self, Color32, Rect, Sense, Stroke};
+use egui_extras::{Column, TableBuilder};
+use lazuli::gekko::{Address, Bat, MemoryManagement};
+use lazuli::system::mem::{IPL_END, IPL_START, L2C_END, L2C_START, RAM_END, RAM_START};
+use serde::{Deserialize, Serialize};
+
+use crate::State;
+use crate::windows::{AppWindow, Ctx};
+
+const BAR_HEIGHT: f32 = 24.0;
+const ARROWS_HEIGHT: f32 = 110.0;
+const LABEL_HEIGHT: f32 = 12.0;
+const UNMAPPED_COLOR: Color32 = Color32::from_gray(70);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Ram,
+    Mmio,
+    Efb,
+    LockedCache,
+    Ipl,
+}
+
+impl Kind {
+    fn color(self) -> Color32 {
+        match self {
+            Self::Ram => Color32::from_rgb(60, 170, 80),
+            Self::Mmio => Color32::from_rgb(200, 60, 60),
+            Self::Efb => Color32::from_rgb(230, 140, 40),
+            Self::LockedCache => Color32::from_rgb(40, 170, 170),
+            Self::Ipl => Color32::from_rgb(150, 90, 200),
+        }
+    }
+}
+
+/// A region of the physical address space.
+struct Region {
+    name: &'static str,
+    kind: Kind,
+    start: u32,
+    end: u32,
+}
+
+const REGIONS: &[Region] = &[
+    Region {
+        name: "Main RAM",
+        kind: Kind::Ram,
+        start: RAM_START,
+        end: RAM_END,
+    },
+    Region {
+        name: "EFB",
+        kind: Kind::Efb,
+        start: 0x0800_0000,
+        end: 0x083F_FFFF,
+    },
+    Region {
+        name: "MMIO",
+        kind: Kind::Mmio,
+        start: 0x0C00_0000,
+        end: 0x0C00_FFFF,
+    },
+    Region {
+        name: "Locked Cache",
+        kind: Kind::LockedCache,
+        start: L2C_START,
+        end: L2C_END,
+    },
+    Region {
+        name: "IPL ROM",
+        kind: Kind::Ipl,
+        start: IPL_START,
+        end: IPL_END,
+    },
+];
+
+fn region_of(addr: u32) -> Option<&'static Region> {
+    REGIONS.iter().find(|r| (r.start..=r.end).contains(&addr))
+}
+
+/// Whether a BAT is valid in any mode.
+fn is_valid(bat: &Bat) -> bool {
+    bat.supervisor_mode() || bat.user_mode()
+}
+
+/// Horizontal position of an address in a bar.
+fn x_of(rect: Rect, addr: u32) -> f32 {
+    rect.left() + (addr as f64 / (1u64 << 32) as f64) as f32 * rect.width()
+}
+
+/// Rect covering the given range of addresses in a bar. Regions are always at least two pixels
+/// wide so that small ones are still visible.
+fn span(rect: Rect, start: u32, end: u32) -> Rect {
+    let left = x_of(rect, start);
+    let right = x_of(rect, end).max(left + 2.0);
+
+    Rect::from_x_y_ranges(left..=right, rect.y_range())
+}
+
+/// Address at the given horizontal position of a bar.
+fn addr_at(rect: Rect, x: f32) -> u32 {
+    let t = ((x - rect.left()) / rect.width()).clamp(0.0, 1.0) as f64;
+    (t * u32::MAX as f64) as u32
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Window {
+    #[serde(skip)]
+    memory: MemoryManagement,
+    #[serde(skip)]
+    aram_len: u32,
+}
+
+impl Window {
+    fn bats(&self) -> impl Iterator<Item = (String, &Bat, Color32)> {
+        let ibats = self
+            .memory
+            .ibat
+            .iter()
+            .enumerate()
+            .map(|(i, bat)| (format!("IBAT{i}"), bat, Color32::from_rgb(230, 210, 90)));
+        let dbats = self
+            .memory
+            .dbat
+            .iter()
+            .enumerate()
+            .map(|(i, bat)| (format!("DBAT{i}"), bat, Color32::from_rgb(120, 180, 250)));
+
+        ibats.chain(dbats)
+    }
+
+    fn map(&self, ui: &mut egui::Ui) {
+        let width = ui.available_width();
+        let height = 2.0 * BAR_HEIGHT + ARROWS_HEIGHT + LABEL_HEIGHT;
+        let (rect, response) = ui.allocate_exact_size(egui::vec2(width, height), Sense::hover());
+        let painter = ui.painter_at(rect);
+        let font = egui::FontId::monospace(10.0);
+
+        let logical = Rect::from_min_size(rect.min, egui::vec2(width, BAR_HEIGHT));
+        let physical = logical.translate(egui::vec2(0.0, BAR_HEIGHT + ARROWS_HEIGHT));
+
+        // logical address space, with the BAT ranges highlighted
+        painter.rect_filled(logical, 0.0, UNMAPPED_COLOR);
+        painter.text(
+            logical.left_center() + egui::vec2(4.0, 0.0),
+            egui::Align2::LEFT_CENTER,
+            "Logical",
+            font.clone(),
+            Color32::WHITE,
+        );
+
+        // physical address space, colored by region
+        painter.rect_filled(physical, 0.0, UNMAPPED_COLOR);
+        for region in REGIONS {
+            let area = span(physical, region.start, region.end);
+            painter.rect_filled(area, 0.0, region.kind.color());
+        }
+        painter.text(
+            physical.center(),
+            egui::Align2::CENTER_CENTER,
+            "Physical",
+            font.clone(),
+            Color32::WHITE,
+        );
+
+        // BAT mappings
+        for (index, (name, bat, color)) in
+            self.bats().filter(|(_, bat, _)| is_valid(bat)).enumerate()
+        {
+            let from = span(
+                logical,
+                bat.logical_start().value(),
+                bat.logical_end().value(),
+            );
+            let to = span(
+                physical,
+                bat.physical_start().value(),
+                bat.physical_end().value(),
+            );
+
+            painter.rect_filled(from, 0.0, color.gamma_multiply(0.6));
+            painter.rect_stroke(to, 0.0, Stroke::new(1.0, color), egui::StrokeKind::Inside);
+
+            let origin = from.center_bottom();
+            painter.arrow(origin, to.center_top() - origin, Stroke::new(1.5, color));
+
+            // stagger labels so that overlapping mappings stay readable
+            let label_pos = origin + egui::vec2(4.0, 4.0 + index as f32 * LABEL_HEIGHT);
+            painter.text(label_pos, egui::Align2::LEFT_TOP, name, font.clone(), color);
+        }
+
+        // address ticks
+        for tick in [0x0000_0000u32, 0x4000_0000, 0x8000_0000, 0xC000_0000] {
+            let x = x_of(physical, tick);
+            painter.text(
+                egui::pos2(x + 2.0, physical.bottom() + 1.0),
+                egui::Align2::LEFT_TOP,
+                format!("{tick:08X}"),
+                font.clone(),
+                Color32::GRAY,
+            );
+        }
+
+        if let Some(pos) = response.hover_pos() {
+            let addr = addr_at(rect, pos.x);
+            if logical.contains(pos) {
+                let bats = self
+                    .bats()
+                    .filter(|(_, bat, _)| is_valid(bat) && bat.contains(Address(addr)))
+                    .map(|(name, bat, _)| format!("{name} -> {}", bat.translate(Address(addr))))
+                    .collect::<Vec<_>>();
+
+                let text = if bats.is_empty() {
+                    format!("{}\nNo BAT mapping", Address(addr))
+                } else {
+                    format!("{}\n{}", Address(addr), bats.join("\n"))
+                };
+
+                response.on_hover_text_at_pointer(text);
+            } else if physical.contains(pos) {
+                let region = region_of(addr).map(|r| r.name).unwrap_or("Unmapped");
+                response.on_hover_text_at_pointer(format!("{}\n{region}", Address(addr)));
+            }
+        }
+    }
+
+    fn legend(&self, ui: &mut egui::Ui) {
+        let entry = |ui: &mut egui::Ui, color: Color32, text: String| {
+            ui.horizontal(|ui| {
+                let (rect, _) = ui.allocate_exact_size(egui::vec2(12.0, 12.0), Sense::hover());
+                ui.painter().rect_filled(rect, 2.0, color);
+                ui.label(text);
+            });
+        };
+
+        for region in REGIONS {
+            entry(
+                ui,
+                region.kind.color(),
+                format!(
+                    "{}: {} - {} ({})",
+                    region.name,
+                    Address(region.start),
+                    Address(region.end),
+                    ByteSize(region.end as u64 - region.start as u64 + 1),
+                ),
+            );
+        }
+
+        entry(
+            ui,
+            Color32::from_rgb(60, 100, 210),
+            format!(
+                "DSP ARAM: {} (not mapped in the CPU address space, accessed through DMA)",
+                ByteSize(self.aram_len as u64)
+            ),
+        );
+        entry(ui, UNMAPPED_COLOR, "Unmapped".to_owned());
+    }
+
+    fn table(&self, ui: &mut egui::Ui) {
+        let mono = |text: String, color: Color32| {
+            egui::RichText::new(text)
+                .family(egui::FontFamily::Monospace)
+                .color(color)
+        };
+
+        let builder = TableBuilder::new(ui)
+            .auto_shrink(egui::Vec2b::new(false, true))
+            .striped(true)
+            .resizable(false)
+            .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+            .column(Column::auto()) // name
+            .column(Column::auto()) // logical
+            .column(Column::auto()) // physical
+            .column(Column::auto()) // length
+            .column(Column::remainder()); // flags
+
+        let table = builder.header(20.0, |mut header| {
+            for title in ["BAT", "Logical", "Physical", "Length", "Flags"] {
+                header.col(|ui| {
+                    ui.label(title);
+                });
+            }
+        });
+
+        table.body(|mut body| {
+            for (name, bat, color) in self.bats() {
+                let (logical, physical, length, flags) = if is_valid(bat) {
+                    (
+                        format!("{} - {}", bat.logical_start(), bat.logical_end()),
+                        format!("{} - {}", bat.physical_start(), bat.physical_end()),
+                        ByteSize(bat.block_length() as u64).to_string(),
+                        format!(
+                            "Vs={} Vp={} WIMG={:04b} PP={:02b}",
+                            bat.supervisor_mode() as u8,
+                            bat.user_mode() as u8,
+                            bat.wimg().value(),
+                            bat.protection().value(),
+                        ),
+                    )
+                } else {
+                    Default::default()
+                };
+
+                body.row(20.0, |mut row| {
+                    row.col(|ui| {
+                        ui.label(mono(name, color));
+                    });
+                    row.col(|ui| {
+                        ui.label(mono(logical, Color32::LIGHT_GREEN));
+                    });
+                    row.col(|ui| {
+                        ui.label(mono(physical, Color32::LIGHT_GREEN));
+                    });
+                    row.col(|ui| {
+                        ui.label(length);
+                    });
+                    row.col(|ui| {
+                        ui.label(flags);
+                    });
+                });
+            }
+        });
+    }
+}
+
+#[typetag::serde(name = "memory_map")]
+impl AppWindow for Window {
+    fn title(&self) -> &str {
+        "Memory Map"
+    }
+
+    fn default_size(&self) -> Option<egui::Vec2> {
+        Some(egui::vec2(720.0, 520.0))
+    }
+
+    fn prepare(&mut self, state: &mut State) {
+        let sys = &state.lazuli.sys;
+        self.memory = sys.cpu.supervisor.memory.clone();
+        self.aram_len = sys.dsp.aram_len;
+    }
+
+    fn show(&mut self, ui: &mut egui::Ui, _: &mut Ctx) {
+        egui::ScrollArea::vertical()
+            .auto_shrink(false)
+            .show(ui, |ui| {
+                self.map(ui);
+                ui.separator();
+                self.legend(ui);
+                ui.separator();
+                self.table(ui);
+            });
+    }
+}
@@ -0,0 +1,34 @@
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+
+use crate::State;
+use crate::windows::{AppWindow, Ctx};
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Window;
+
+#[typetag::serde(name = "performance")]
+impl AppWindow for Window {
+    fn title(&self) -> &str {
+        "Performance"
+    }
+
+    fn prepare(&mut self, _: &mut State) {}
+
+    fn show(&mut self, ui: &mut egui::Ui, ctx: &mut Ctx) {
+        ui.heading("Emulation");
+        let speed = ctx.cps as f64 / lazuli::gekko::FREQUENCY as f64;
+        ui.label(format!("Speed: {:.0}%", speed * 100.0));
+        ui.label(format!("CPU: {:.2} MHz", ctx.cps as f64 / 1_000_000.0));
+
+        ui.heading("Audio");
+        let latency = ctx.audio.latency();
+        ui.label(format!(
+            "Sample rate: {} Hz → {} Hz",
+            latency.input_rate, latency.output_rate
+        ));
+        ui.label(format!("Device buffer: {} frames", latency.buffer_frames));
+        ui.label(format!("Queued: {} frames", latency.queued_frames));
+        ui.label(format!("Estimated latency: {:.1} ms", latency.millis()));
+    }
+}
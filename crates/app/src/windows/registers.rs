@@ -175,7 +175,7 @@ impl Window {
             });
 
             for index in 0..8 {
-                let cr = self.cpu.user.cr.fields_at(7 - index).unwrap();
+                let cr = self.cpu.user.cr.field(index);
                 body.row(20.0, |mut row| {
                     row.col(|ui| {
                         let text = egui::RichText::new(format!("CR{index:02}"))
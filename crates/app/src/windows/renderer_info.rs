@@ -1,10 +1,15 @@
 use bytesize::ByteSize;
 use eframe::egui;
+use lazuli::system::gx;
+use renderer::DebugFlags;
 use serde::{Deserialize, Serialize};
 
 use crate::State;
 use crate::windows::{AppWindow, Ctx};
 
+/// File the GX register trace is written to when started from this window.
+const GX_TRACE_PATH: &str = "gx_trace.log";
+
 #[cfg(not(target_os = "macos"))]
 type RenderDoc = renderdoc::RenderDoc<renderdoc::V140>;
 
@@ -18,6 +23,12 @@ pub struct Window {
     capture: bool,
     #[serde(skip)]
     is_capturing: bool,
+    #[serde(skip)]
+    debug_flags: DebugFlags,
+    #[serde(skip)]
+    gx_trace: bool,
+    #[serde(skip)]
+    toggle_gx_trace: bool,
 }
 
 impl Default for Window {
@@ -27,6 +38,9 @@ impl Default for Window {
             renderdoc: RenderDoc::new().ok(),
             capture: false,
             is_capturing: false,
+            debug_flags: DebugFlags::default(),
+            gx_trace: false,
+            toggle_gx_trace: false,
         }
     }
 }
@@ -37,12 +51,36 @@ impl AppWindow for Window {
         "Renderer"
     }
 
-    fn prepare(&mut self, _: &mut State) {}
+    fn prepare(&mut self, state: &mut State) {
+        let sys = &mut state.lazuli.sys;
+        if std::mem::take(&mut self.toggle_gx_trace) {
+            if gx::trace::is_active(sys) {
+                gx::trace::stop(sys);
+            } else if let Err(e) = gx::trace::start(sys, GX_TRACE_PATH) {
+                tracing::error!("failed to start GX register trace: {e}");
+            }
+        }
+
+        self.gx_trace = gx::trace::is_active(sys);
+    }
 
     fn show(&mut self, ui: &mut egui::Ui, ctx: &mut Ctx) {
         let stats = ctx.renderer.stats();
 
         ui.vertical(|ui| {
+            ui.heading("Frame Timing");
+            let speed = ctx.cps as f64 / lazuli::gekko::FREQUENCY as f64;
+            ui.label(format!("Emulation speed: {:.0}%", speed * 100.0));
+            if let Some(gpu) = stats.gpu_frame_time {
+                ui.label(format!(
+                    "GPU time: {:.2} ms (average of {} frames)",
+                    gpu.as_secs_f64() * 1000.0,
+                    renderer::GPU_TIME_WINDOW,
+                ));
+            } else {
+                ui.label("GPU time: unavailable");
+            }
+
             ui.heading("Allocator Report");
             if let Some(alloc) = &stats.alloc {
                 ui.label(format!(
@@ -58,6 +96,23 @@ impl AppWindow for Window {
                 ui.label("Report unavailable");
             }
 
+            ui.heading("Texture Cache");
+            ui.label(format!(
+                "Memory: {} / {}",
+                ByteSize(stats.texture_memory),
+                ByteSize(stats.texture_budget),
+            ));
+            ui.label(format!("Peak: {}", ByteSize(stats.texture_memory_peak)));
+
+            let cache = &stats.cache;
+            ui.label(format!("Hits: {} / Misses: {}", cache.hits, cache.misses));
+            ui.label(format!("Evictions: {}", cache.evictions));
+            ui.label(format!("Replacements: {}", cache.replacements));
+            ui.label(format!(
+                "Bind Group Hits: {} / Misses: {} (capacity {})",
+                cache.group_hits, cache.group_misses, stats.group_cache_capacity,
+            ));
+
             let counters = &stats.counters.hal;
             ui.heading("Counters");
             ui.label(format!(
@@ -83,6 +138,49 @@ impl AppWindow for Window {
                 counters.memory_allocations.read(),
             ));
 
+            ui.heading("Free Camera");
+            let camera = &mut *ctx.camera;
+            ui.checkbox(&mut camera.enabled, "Override game camera");
+            ui.add(
+                egui::Slider::new(&mut camera.speed, 1.0..=10_000.0)
+                    .logarithmic(true)
+                    .text("Speed"),
+            );
+            ui.add(
+                egui::Slider::new(&mut camera.sensitivity, 0.0005..=0.05)
+                    .logarithmic(true)
+                    .text("Sensitivity"),
+            );
+            ui.horizontal(|ui| {
+                let mut override_fov = camera.fov.is_some();
+                ui.checkbox(&mut override_fov, "Override FOV");
+                if override_fov {
+                    let fov = camera.fov.get_or_insert(60.0);
+                    ui.add(egui::Slider::new(fov, 10.0..=150.0).suffix("°"));
+                } else {
+                    camera.fov = None;
+                }
+            });
+            if ui.button("Reset position").clicked() {
+                camera.reset();
+            }
+
+            ui.heading("Debug");
+            let mut flags = self.debug_flags;
+            ui.add_enabled_ui(ctx.renderer.supports_wireframe(), |ui| {
+                ui.checkbox(&mut flags.wireframe, "Wireframe")
+                    .on_disabled_hover_text("Not supported by the GPU");
+            });
+            ui.checkbox(&mut flags.no_culling, "Disable culling");
+            let mut gx_trace = self.gx_trace;
+            ui.checkbox(&mut gx_trace, "Trace GX register writes")
+                .on_hover_text(format!("Writes every GX register write to {GX_TRACE_PATH}"));
+            self.toggle_gx_trace = gx_trace != self.gx_trace;
+            if flags != self.debug_flags {
+                self.debug_flags = flags;
+                ctx.renderer.set_debug_flags(flags);
+            }
+
             ui.heading("Renderdoc");
 
             #[cfg(not(target_os = "macos"))]
@@ -1,5 +1,6 @@
 pub mod cp;
 pub mod pi;
+pub mod vi;
 
 use eframe::egui;
 
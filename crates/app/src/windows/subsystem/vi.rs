@@ -0,0 +1,141 @@
+use eframe::egui;
+use lazuli::Address;
+use lazuli::system::vi::{
+    self, BeamPosition, DisplayConfig, DisplayInterrupt, HorizontalTiming, VerticalTiming,
+    VideoMode,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::windows::Ctx;
+use crate::windows::subsystem::mmio_dbg;
+use crate::{AppWindow, State};
+
+/// Decoded state of the video interface.
+#[derive(Default)]
+struct Snapshot {
+    display_config: DisplayConfig,
+    vertical_timing: VerticalTiming,
+    horizontal_timing: HorizontalTiming,
+    enabled: bool,
+    video_mode: Option<VideoMode>,
+    active_lines: u16,
+    lines_per_frame: u32,
+    halfline_width: u16,
+    frame_rate: f64,
+    beam: BeamPosition,
+    interrupts: [DisplayInterrupt; 4],
+    interrupt_counts: [u64; 4],
+    top_xfb: Address,
+    bottom_xfb: Address,
+    xfb_width: u16,
+    xfb_stride: u16,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Window {
+    #[serde(skip)]
+    snapshot: Snapshot,
+}
+
+fn xfb_row(ui: &mut egui::Ui, ctx: &mut Ctx, name: &str, addr: Address) {
+    ui.horizontal(|ui| {
+        ui.label(format!("{name}: {addr}"));
+        if ui.button("Go to").clicked() {
+            // XFB addresses are physical, show them through the cached mirror
+            ctx.goto = Some(Address(addr.value() | 0x8000_0000));
+        }
+    });
+}
+
+#[typetag::serde(name = "subsystem-vi")]
+impl AppWindow for Window {
+    fn title(&self) -> &str {
+        "Video Interface"
+    }
+
+    fn prepare(&mut self, state: &mut State) {
+        let sys = &state.lazuli.sys;
+        let video = &sys.video;
+
+        self.snapshot = Snapshot {
+            display_config: video.display_config,
+            vertical_timing: video.vertical_timing,
+            horizontal_timing: video.horizontal_timing,
+            enabled: video.display_config.enable(),
+            video_mode: Some(video.video_mode()),
+            active_lines: video.vertical_timing.active_video_lines().value(),
+            lines_per_frame: video.lines_per_frame(),
+            halfline_width: video.horizontal_timing.halfline_width().value(),
+            frame_rate: video.frame_rate(),
+            beam: vi::beam_position(sys),
+            interrupts: video.interrupts,
+            interrupt_counts: video.interrupt_counts,
+            top_xfb: video.top_xfb_address(),
+            bottom_xfb: video.bottom_xfb_address(),
+            xfb_width: video.xfb_width.width(),
+            xfb_stride: video.xfb_stride(),
+        };
+    }
+
+    fn show(&mut self, ui: &mut egui::Ui, ctx: &mut Ctx) {
+        let snapshot = &self.snapshot;
+        egui::ScrollArea::both().auto_shrink(false).show(ui, |ui| {
+            ui.heading("Timing");
+            ui.label(format!("Enabled: {}", snapshot.enabled));
+            if let Some(mode) = snapshot.video_mode {
+                ui.label(format!("Mode: {mode:?}"));
+            }
+            ui.label(format!("Active lines per field: {}", snapshot.active_lines));
+            ui.label(format!("Lines per frame: {}", snapshot.lines_per_frame));
+            ui.label(format!(
+                "Halfline width: {} samples",
+                snapshot.halfline_width
+            ));
+            if snapshot.frame_rate.is_finite() {
+                ui.label(format!("Refresh rate: {:.2} Hz", snapshot.frame_rate));
+            } else {
+                ui.label("Refresh rate: -");
+            }
+            ui.label(format!(
+                "Beam: line {}, sample {}",
+                snapshot.beam.vertical, snapshot.beam.horizontal
+            ));
+            ui.separator();
+
+            ui.heading("Display Interrupts");
+            egui::Grid::new("vi_interrupts")
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.label("#");
+                    ui.label("Line");
+                    ui.label("Sample");
+                    ui.label("Enabled");
+                    ui.label("Asserted");
+                    ui.label("Triggered");
+                    ui.end_row();
+
+                    for (i, interrupt) in snapshot.interrupts.iter().enumerate() {
+                        ui.label(i.to_string());
+                        ui.label(interrupt.vertical_count().value().to_string());
+                        ui.label(interrupt.horizontal_count().value().to_string());
+                        ui.label(interrupt.enable().to_string());
+                        ui.label(interrupt.status().to_string());
+                        ui.label(snapshot.interrupt_counts[i].to_string());
+                        ui.end_row();
+                    }
+                });
+            ui.separator();
+
+            ui.heading("External Framebuffer");
+            xfb_row(ui, ctx, "Top field", snapshot.top_xfb);
+            xfb_row(ui, ctx, "Bottom field", snapshot.bottom_xfb);
+            ui.label(format!("Width: {}", snapshot.xfb_width));
+            ui.label(format!("Stride: {}", snapshot.xfb_stride));
+            ui.separator();
+
+            mmio_dbg(ui, "Display config", &snapshot.display_config);
+            mmio_dbg(ui, "Vertical timing", &snapshot.vertical_timing);
+            mmio_dbg(ui, "Horizontal timing", &snapshot.horizontal_timing);
+        });
+    }
+}
@@ -0,0 +1,111 @@
+use eframe::egui;
+use lazuli::modules::input::ControllerButton;
+use modules::input::TurboConfig;
+use serde::{Deserialize, Serialize};
+
+use crate::State;
+use crate::windows::{AppWindow, Ctx};
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Window;
+
+#[typetag::serde(name = "turbo")]
+impl AppWindow for Window {
+    fn title(&self) -> &str {
+        "Turbo"
+    }
+
+    fn prepare(&mut self, _: &mut State) {}
+
+    fn show(&mut self, ui: &mut egui::Ui, ctx: &mut Ctx) {
+        ui.heading("Turbo");
+        ui.label("While held, these buttons are pressed and released repeatedly.");
+
+        let mut turbo = ctx.input.turbo();
+        let mut changed = false;
+        let mut remove = None;
+
+        egui::Grid::new("turbo_buttons")
+            .num_columns(3)
+            .striped(true)
+            .show(ui, |ui| {
+                for (index, config) in turbo.iter_mut().enumerate() {
+                    egui::ComboBox::from_id_salt(("turbo_button", index))
+                        .selected_text(config.button.name())
+                        .show_ui(ui, |ui| {
+                            for button in ControllerButton::ALL {
+                                changed |= ui
+                                    .selectable_value(&mut config.button, button, button.name())
+                                    .changed();
+                            }
+                        });
+
+                    changed |= ui
+                        .add(
+                            egui::DragValue::new(&mut config.frequency_hz)
+                                .range(1.0..=30.0)
+                                .speed(0.1)
+                                .suffix(" Hz"),
+                        )
+                        .changed();
+
+                    if ui.button("Remove").clicked() {
+                        remove = Some(index);
+                    }
+
+                    ui.end_row();
+                }
+            });
+
+        if let Some(index) = remove {
+            turbo.remove(index);
+            changed = true;
+        }
+
+        if ui.button("Add").clicked() {
+            turbo.push(TurboConfig {
+                button: ControllerButton::A,
+                frequency_hz: 10.0,
+            });
+            changed = true;
+        }
+
+        if changed {
+            ctx.input.set_turbo(turbo);
+        }
+
+        ui.separator();
+        ui.heading("Macro");
+
+        let config = ctx.input.macro_config();
+        ui.label(format!(
+            "{} steps, {:.2} s",
+            config.steps.len(),
+            config.duration().as_secs_f32()
+        ));
+
+        ui.horizontal(|ui| {
+            if ctx.input.is_recording_macro() {
+                if ui.button("Stop Recording").clicked() {
+                    ctx.input.stop_macro_recording();
+                }
+            } else if ui.button("Record").clicked() {
+                ctx.input.start_macro_recording();
+            }
+
+            let can_play = !config.steps.is_empty() && !ctx.input.is_recording_macro();
+            if ui
+                .add_enabled(can_play, egui::Button::new("Play"))
+                .clicked()
+            {
+                ctx.input.play_macro();
+            }
+
+            if ctx.input.is_playing_macro() {
+                ui.label("Playing...");
+            }
+        });
+
+        ui.label("Press Ctrl+Shift+M to play the macro.");
+    }
+}
@@ -76,7 +76,8 @@ impl AppWindow for Window {
                         });
 
                     if ui.button("Add").clicked() {
-                        let address = self.variable_address.trim_prefix("0x").replace("_", "");
+                        let text = self.variable_address.as_str();
+                        let address = text.strip_prefix("0x").unwrap_or(text).replace("_", "");
                         let label = self.variable_label.clone();
                         let kind = self.variable_kind;
 
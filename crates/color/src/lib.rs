@@ -50,9 +50,11 @@ pub struct Rgba8 {
 }
 
 impl Rgba8 {
+    /// Converts an IA8 value, which holds the alpha in the high byte and the intensity in the low
+    /// byte (i.e. alpha comes first in memory).
     #[inline(always)]
     pub fn from_ia8(value: u16) -> Self {
-        let [alpha, intensity] = value.to_le_bytes();
+        let [alpha, intensity] = value.to_be_bytes();
         Self {
             r: intensity,
             g: intensity,
@@ -186,6 +188,19 @@ pub struct Rgba16 {
     pub a: i16,
 }
 
+impl Rgba16 {
+    /// Converts to RGBA8 by keeping only the low 8 bits of each channel.
+    #[inline(always)]
+    pub fn wrap(self) -> Rgba8 {
+        Rgba8 {
+            r: self.r as u8,
+            g: self.g as u8,
+            b: self.b as u8,
+            a: self.a as u8,
+        }
+    }
+}
+
 impl std::fmt::Debug for Rgba16 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         Rgba::from(*self).fmt(f)
@@ -0,0 +1,64 @@
+use cores::cpu::jit::{Config, Core};
+use criterion::{Criterion, criterion_group, criterion_main};
+use lazuli::cores::CpuCore;
+use lazuli::system::{self, Modules, System};
+use lazuli::{Address, Cycles};
+
+/// How many cycles the CPU is executed for at a time.
+const STEP: u64 = 4096;
+
+/// Where the loop is placed in memory.
+const CODE: u32 = 0x1000;
+
+/// `lwzu r0, 4(r4); stwu r0, 4(r3); bdnz -8`, a loop copying words.
+const COPY: [u32; 3] = [0x8404_0004, 0x9403_0004, 0x4200_FFF8];
+
+/// Same loop, but with a `nop` in the middle so it isn't detected as a copy loop.
+const COPY_NOP: [u32; 4] = [0x8404_0004, 0x6000_0000, 0x9403_0004, 0x4200_FFF4];
+
+fn system(code: &[u32]) -> System {
+    let mut sys = System::new(Modules::nop(), system::Config::default());
+
+    for (i, ins) in code.iter().enumerate() {
+        sys.write(Address(CODE + 4 * i as u32), *ins);
+    }
+
+    sys
+}
+
+/// Restarts the loop, copying 1 MiB from `0x10_0000` to `0x20_0000`.
+fn restart(sys: &mut System) {
+    sys.cpu.pc = Address(CODE);
+    sys.cpu.user.ctr = 0x4_0000;
+    sys.cpu.user.gpr[3] = 0x20_0000 - 4;
+    sys.cpu.user.gpr[4] = 0x10_0000 - 4;
+}
+
+fn memcpy_loop(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Word copy loop");
+    group.throughput(criterion::Throughput::Elements(STEP));
+
+    for (name, code) in [("Detected", &COPY[..]), ("Executed", &COPY_NOP[..])] {
+        let mut sys = system(code);
+        let mut core = Core::new(Config {
+            instr_per_block: 128,
+            jit_settings: Default::default(),
+        });
+
+        restart(&mut sys);
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                if sys.cpu.user.ctr == 0 {
+                    restart(&mut sys);
+                }
+
+                core.exec(&mut sys, Cycles(STEP), &[])
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, memcpy_loop);
+criterion_main!(benches);
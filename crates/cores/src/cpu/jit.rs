@@ -2,13 +2,17 @@ mod icache;
 mod mapping;
 mod table;
 
+use std::collections::VecDeque;
+use std::fmt::Write;
+
 use indexmap::IndexSet;
 use lazuli::cores::{CpuCore, Executed};
-use lazuli::gekko::{self, Cpu, DEQUANTIZATION_LUT, QUANTIZATION_LUT, QuantReg, QuantizedType};
+use lazuli::gekko::{self, Cpu, QuantReg};
+use lazuli::system::scheduler::{INTERRUPT_PRIORITY, SchedulerEventKind};
 use lazuli::system::{self, System};
 use lazuli::{Address, Cycles, Primitive};
 use mapping::Mapping;
-use ppcjit::block::{BlockFn, Info, LinkData, Pattern};
+use ppcjit::block::{BlockFn, Info, LinkData, Meta, Pattern};
 use ppcjit::hooks::*;
 use ppcjit::{Block, FastmemLut};
 
@@ -198,13 +202,50 @@ struct Context<'a> {
     exit_reason: ExitReason,
 }
 
+/// Invalidates the blocks and the icache entry of the cache line containing `addr`.
+fn invalidate_cacheline(
+    sys: &System,
+    blocks: &mut Blocks,
+    icache: &mut icache::Cache,
+    is_logical: bool,
+    addr: Address,
+) {
+    let cacheline_base = addr.align_down_pow2(32);
+
+    if is_logical {
+        for offset in 0..32 {
+            let logical = cacheline_base + offset;
+            let physical = sys.translate_inst_addr(logical);
+
+            blocks.invalidate(true, logical);
+            if let Some(physical) = physical {
+                blocks.invalidate(false, physical);
+            }
+        }
+
+        if let Some(physical) = sys.translate_inst_addr(cacheline_base) {
+            icache.invalidate(physical);
+        }
+    } else {
+        for offset in 0..32 {
+            let physical = cacheline_base + offset;
+            blocks.invalidate(false, physical);
+        }
+
+        icache.invalidate(cacheline_base);
+    }
+}
+
 const CTX_HOOKS: Hooks = {
     extern "C-unwind" fn get_registers<'a>(ctx: &'a mut Context) -> &'a mut Cpu {
         &mut ctx.sys.cpu
     }
 
     extern "C-unwind" fn get_fastmem<'a>(ctx: &'a mut Context) -> &'a FastmemLut {
-        if ctx.sys.cpu.supervisor.config.msr.data_addr_translation() {
+        // writes must go through the slow path so that they're checked against value watches
+        if !ctx.sys.watches.is_empty() {
+            ctx.sys.mem.data_fastmem_lut_empty()
+        } else if ctx.sys.cpu.supervisor.config.msr.data_addr_translation() {
             ctx.sys.mem.data_fastmem_lut_logical()
         } else {
             ctx.sys.mem.data_fastmem_lut_physical()
@@ -239,6 +280,8 @@ const CTX_HOOKS: Hooks = {
                     true
                 }
             }
+            // exit to the dispatcher, which runs the loop on the host
+            Pattern::MemcpyLoop => false,
             _ => true,
         };
 
@@ -296,31 +339,21 @@ const CTX_HOOKS: Hooks = {
         gqr: QuantReg,
         value: &mut f64,
     ) -> u8 {
-        let ty = gqr.load_type();
-        let scale = if ty != QuantizedType::Float {
-            gqr.load_scale().value()
-        } else {
-            0
+        let size = gqr.load_type().size();
+        let raw = match size {
+            1 => ctx.sys.read::<u8>(addr).map(|x| x as u32),
+            2 => ctx.sys.read::<u16>(addr).map(|x| x as u32),
+            _ => ctx.sys.read::<u32>(addr),
         };
 
-        let read = match ty {
-            QuantizedType::U8 => ctx.sys.read::<u8>(addr).map(|x| x as f64),
-            QuantizedType::U16 => ctx.sys.read::<u16>(addr).map(|x| x as f64),
-            QuantizedType::I8 => ctx.sys.read::<i8>(addr).map(|x| x as f64),
-            QuantizedType::I16 => ctx.sys.read::<i16>(addr).map(|x| x as f64),
-            _ => ctx.sys.read::<u32>(addr).map(|x| f32::from_bits(x) as f64),
-        };
-
-        let Some(read) = read else {
+        let Some(raw) = raw else {
             std::hint::cold_path();
             tracing::error!("failed to translate address {addr}");
             return 0;
         };
 
-        let scaled = read * DEQUANTIZATION_LUT[(scale as usize) & 0b0011_1111];
-        *value = scaled;
-
-        ty.size()
+        *value = gqr.dequantize(raw);
+        size
     }
 
     extern "C-unwind" fn write_quantized(
@@ -329,20 +362,12 @@ const CTX_HOOKS: Hooks = {
         gqr: QuantReg,
         value: f64,
     ) -> u8 {
-        let ty = gqr.store_type();
-        let scale = if ty != QuantizedType::Float {
-            gqr.store_scale().value()
-        } else {
-            0
-        };
-
-        let scaled = value * QUANTIZATION_LUT[(scale as usize) & 0b0011_1111];
-        let success = match ty {
-            QuantizedType::U8 => ctx.sys.write(addr, scaled as u8),
-            QuantizedType::U16 => ctx.sys.write(addr, scaled as u16),
-            QuantizedType::I8 => ctx.sys.write(addr, scaled as i8),
-            QuantizedType::I16 => ctx.sys.write(addr, scaled as i16),
-            _ => ctx.sys.write(addr, (scaled as f32).to_bits()),
+        let size = gqr.store_type().size();
+        let raw = gqr.quantize(value);
+        let success = match size {
+            1 => ctx.sys.write(addr, raw as u8),
+            2 => ctx.sys.write(addr, raw as u16),
+            _ => ctx.sys.write(addr, raw),
         };
 
         if !success {
@@ -351,69 +376,37 @@ const CTX_HOOKS: Hooks = {
             return 0;
         }
 
-        ty.size()
+        size
     }
 
     extern "C-unwind" fn invalidate_icache(ctx: &mut Context, addr: Address) {
-        let cacheline_base = addr.align_down(32);
         let is_logical = ctx.sys.cpu.supervisor.config.msr.instr_addr_translation();
-
-        if is_logical {
-            for offset in 0..32 {
-                let logical = cacheline_base + offset;
-                let physical = ctx.sys.translate_inst_addr(logical);
-
-                ctx.blocks.invalidate(true, logical);
-                if let Some(physical) = physical {
-                    ctx.blocks.invalidate(false, physical);
-                }
-            }
-
-            if let Some(physical) = ctx.sys.translate_inst_addr(cacheline_base) {
-                ctx.icache.invalidate(physical);
-            }
-        } else {
-            for offset in 0..32 {
-                let physical = cacheline_base + offset;
-                ctx.blocks.invalidate(false, physical);
-            }
-
-            ctx.icache.invalidate(cacheline_base);
-        }
+        invalidate_cacheline(ctx.sys, ctx.blocks, ctx.icache, is_logical, addr);
     }
 
     extern "C-unwind" fn clear_icache(ctx: &mut Context) {
         ctx.icache.clear();
     }
 
+    /// Runs a locked cache DMA, kicked by a write to DMAL or DMAU.
+    ///
+    /// The transfer happens synchronously, and observes every guest store made before the kick:
+    /// fastmem stores write guest memory directly, slow stores go through the bus immediately
+    /// and the JIT emits memory accesses and hook calls in program order. The only write
+    /// combining is the gather pipe, which only ever bursts complete lines (just like the
+    /// hardware) and therefore isn't flushed here.
     extern "C-unwind" fn dcache_dma(ctx: &mut Context) {
-        let dma = ctx.sys.cpu.supervisor.config.dma.clone();
-
-        if dma.lower.trigger() {
-            let regions = ctx.sys.mem.regions();
-            let ram =
-                &mut regions.ram[dma.mem_address().value() as usize..][..dma.length() as usize];
-            let l2c = &mut regions.l2c[dma.cache_address().value() as usize - 0xE000_0000..]
-                [..dma.length() as usize];
-
-            debug_assert!(dma.length() <= 4096);
-
-            match dma.lower.direction() {
-                gekko::DmaDirection::FromCacheToRam => {
-                    ram.copy_from_slice(l2c);
-                }
-                gekko::DmaDirection::FromRamToCache => {
-                    l2c.copy_from_slice(ram);
-                }
-            }
-        }
+        ctx.sys.execute_dma();
+    }
 
-        ctx.sys.cpu.supervisor.config.dma.lower.set_trigger(false);
-        ctx.sys.cpu.supervisor.config.dma.lower.set_flush(false);
+    extern "C-unwind" fn hid2_changed(ctx: &mut Context) {
+        ctx.sys.hid2_changed();
     }
 
     extern "C-unwind" fn msr_changed(ctx: &mut Context) {
-        ctx.sys.scheduler.schedule_now(system::pi::check_interrupts);
+        ctx.sys
+            .scheduler
+            .schedule_now(INTERRUPT_PRIORITY, SchedulerEventKind::CheckInterrupts);
     }
 
     extern "C-unwind" fn ibat_changed(ctx: &mut Context) {
@@ -437,14 +430,41 @@ const CTX_HOOKS: Hooks = {
 
     extern "C-unwind" fn dec_changed(ctx: &mut Context) {
         ctx.sys.lazy.last_updated_dec = ctx.sys.scheduler.elapsed();
-        ctx.sys.scheduler.cancel(System::decrementer_overflow);
 
         let dec = ctx.sys.cpu.supervisor.misc.dec;
         tracing::trace!("decrementer changed to {dec}");
 
-        ctx.sys
-            .scheduler
-            .schedule(dec as u64, System::decrementer_overflow);
+        ctx.sys.schedule_decrementer_overflow(dec as u64);
+    }
+
+    extern "C-unwind" fn wpar_read(ctx: &mut Context) {
+        system::wgp::pad_and_flush(ctx.sys);
+    }
+
+    extern "C-unwind" fn wpar_changed(ctx: &mut Context) {
+        system::wgp::wpar_changed(ctx.sys);
+    }
+
+    extern "C-unwind" fn unimplemented_instruction(
+        _: &mut Context,
+        addr: Address,
+        code: u32,
+    ) -> bool {
+        let ins = gekko::disasm::Ins::new(code, gekko::disasm::Extensions::gekko_broadway());
+        tracing::error!(
+            "unimplemented instruction {:?} (0x{code:08X}) at {addr}, raising a program exception",
+            ins.op
+        );
+
+        true
+    }
+
+    extern "C-unwind" fn hle(ctx: &mut Context, addr: Address, code: u32) {
+        system::hle::call(ctx.sys, addr, code);
+    }
+
+    extern "C-unwind" fn pmc_increment(ctx: &mut Context, event: gekko::PmcEvent, count: u32) {
+        system::pmc::increment(ctx.sys, event, count);
     }
 
     extern "C-unwind" fn tb_read(ctx: &mut Context) {
@@ -499,6 +519,7 @@ const CTX_HOOKS: Hooks = {
             transmute::<_, InvalidateICache>(invalidate_icache as extern "C-unwind" fn(_, _));
         let clear_icache = transmute::<_, GenericHook>(clear_icache as extern "C-unwind" fn(_));
         let dcache_dma = transmute::<_, GenericHook>(dcache_dma as extern "C-unwind" fn(_));
+        let hid2_changed = transmute::<_, GenericHook>(hid2_changed as extern "C-unwind" fn(_));
 
         let msr_changed = transmute::<_, GenericHook>(msr_changed as extern "C-unwind" fn(_));
 
@@ -511,6 +532,18 @@ const CTX_HOOKS: Hooks = {
         let dec_read = transmute::<_, GenericHook>(dec_read as extern "C-unwind" fn(_));
         let dec_changed = transmute::<_, GenericHook>(dec_changed as extern "C-unwind" fn(_));
 
+        let wpar_read = transmute::<_, GenericHook>(wpar_read as extern "C-unwind" fn(_));
+        let wpar_changed = transmute::<_, GenericHook>(wpar_changed as extern "C-unwind" fn(_));
+
+        let unimplemented_instruction = transmute::<_, UnimplementedInstructionHook>(
+            unimplemented_instruction as extern "C-unwind" fn(_, _, _) -> _,
+        );
+
+        let hle = transmute::<_, HleHook>(hle as extern "C-unwind" fn(_, _, _));
+
+        let pmc_increment =
+            transmute::<_, PmcIncrementHook>(pmc_increment as extern "C-unwind" fn(_, _, _));
+
         Hooks {
             get_registers,
             get_fastmem,
@@ -532,6 +565,7 @@ const CTX_HOOKS: Hooks = {
             invalidate_icache,
             clear_icache,
             dcache_dma,
+            hid2_changed,
 
             msr_changed,
 
@@ -543,6 +577,15 @@ const CTX_HOOKS: Hooks = {
 
             dec_read,
             dec_changed,
+
+            wpar_read,
+            wpar_changed,
+
+            unimplemented_instruction,
+
+            hle,
+
+            pmc_increment,
         }
     }
 };
@@ -555,11 +598,17 @@ pub struct Config {
     pub jit_settings: ppcjit::Settings,
 }
 
+/// How many recently entered blocks are kept for crash reports and diagnostic bundles.
+const RECENT_BLOCKS: usize = 64;
+
 pub struct Core {
     pub config: Config,
     pub compiler: ppcjit::Jit,
     pub blocks: Blocks,
     pub icache: icache::Cache,
+    /// Addresses of the last blocks entered from outside the JIT, oldest first, and whether
+    /// they're logical. Linked blocks are not included.
+    recent: VecDeque<(bool, Address)>,
 }
 
 fn closest_breakpoint(pc: Address, breakpoints: &[Address]) -> Address {
@@ -579,6 +628,57 @@ fn closest_breakpoint(pc: Address, breakpoints: &[Address]) -> Address {
     closest_breakpoint
 }
 
+/// Runs a block matching [`Pattern::MemcpyLoop`] on the host, for as many iterations as fit in
+/// `target_cycles` (at least one). Returns `None` if no iteration could be run, in which case the
+/// block should be executed normally.
+fn run_memcpy_loop(sys: &mut System, meta: &Meta, target_cycles: u32) -> Option<Executed> {
+    let (load, store) = (meta.seq[0], meta.seq[1]);
+    let (src, dst, value) = (load.field_ra(), store.field_ra(), load.field_rd());
+    let (src_offset, dst_offset) = (load.field_offset() as i32, store.field_offset() as i32);
+
+    let regs = &sys.cpu.user;
+    if regs.ctr == 0 || meta.cycles == 0 {
+        return None;
+    }
+
+    let iterations = regs.ctr.min((target_cycles / meta.cycles).max(1));
+    let mut done = 0;
+    while done < iterations {
+        let from = sys.cpu.user.gpr[src as usize].wrapping_add_signed(src_offset);
+        let to = sys.cpu.user.gpr[dst as usize].wrapping_add_signed(dst_offset);
+
+        // stop at the first access that fails, so that the block raises the exception
+        let Some(word) = sys.read::<u32>(Address(from)) else {
+            break;
+        };
+
+        if !sys.write(Address(to), word) {
+            break;
+        }
+
+        let regs = &mut sys.cpu.user;
+        regs.gpr[src as usize] = from;
+        regs.gpr[value as usize] = word;
+        regs.gpr[dst as usize] = to;
+        regs.ctr -= 1;
+        done += 1;
+    }
+
+    if done == 0 {
+        return None;
+    }
+
+    if sys.cpu.user.ctr == 0 {
+        sys.cpu.pc += 12u32;
+    }
+
+    Some(Executed {
+        instructions: 3 * done,
+        cycles: Cycles(meta.cycles as u64 * done as u64),
+        hit_breakpoint: false,
+    })
+}
+
 impl Core {
     pub fn new(config: Config) -> Self {
         let compiler = ppcjit::Jit::new(config.jit_settings.clone(), CTX_HOOKS);
@@ -588,6 +688,7 @@ impl Core {
             compiler,
             blocks: Blocks::default(),
             icache: Default::default(),
+            recent: VecDeque::with_capacity(RECENT_BLOCKS),
         }
     }
 
@@ -666,6 +767,11 @@ impl Core {
             }
         };
 
+        if self.recent.len() == RECENT_BLOCKS {
+            self.recent.pop_front();
+        }
+        self.recent.push_back((logical, sys.cpu.pc));
+
         let mut ctx = Context {
             sys,
             blocks: &mut self.blocks,
@@ -752,6 +858,20 @@ impl Core {
                 }
             }
 
+            // run word copy loops on the host
+            if !BREAKPOINTS
+                && let Some(stored) = self.blocks.get(logical, sys.cpu.pc)
+                && stored.inner.meta().pattern == Pattern::MemcpyLoop
+            {
+                let target_cycles = cycles - executed.cycles;
+                let meta = stored.inner.meta();
+                if let Some(e) = run_memcpy_loop(sys, meta, target_cycles.0 as u32) {
+                    executed.instructions += e.instructions;
+                    executed.cycles += e.cycles;
+                    continue;
+                }
+            }
+
             let max_instructions = if BREAKPOINTS {
                 let closest_breakpoint = closest_breakpoint(sys.cpu.pc, breakpoints);
                 (closest_breakpoint.value() - sys.cpu.pc.value()) / 4
@@ -765,7 +885,14 @@ impl Core {
             executed.instructions += e.instructions;
             executed.cycles += e.cycles;
 
-            if BREAKPOINTS && breakpoints.contains(&sys.cpu.pc) {
+            if BREAKPOINTS && (breakpoints.contains(&sys.cpu.pc) || sys.watches.has_hit()) {
+                executed.hit_breakpoint = true;
+                break;
+            }
+
+            // stop right away at armed exceptions, so that the handler hasn't run yet
+            if sys.cpu.caught_exception.is_some() {
+                std::hint::cold_path();
                 executed.hit_breakpoint = true;
                 break;
             }
@@ -777,7 +904,8 @@ impl Core {
 
 impl CpuCore for Core {
     fn exec(&mut self, sys: &mut System, cycles: Cycles, breakpoints: &[Address]) -> Executed {
-        if breakpoints.is_empty() {
+        // value watches need to stop right after the block which hit them
+        if breakpoints.is_empty() && sys.watches.is_empty() {
             self.exec_inner::<false>(sys, cycles, &[])
         } else {
             self.exec_inner::<true>(sys, cycles, breakpoints)
@@ -787,4 +915,137 @@ impl CpuCore for Core {
     fn step(&mut self, sys: &mut System) -> Executed {
         self.uncached_exec(sys, u32::MAX, 1, true)
     }
+
+    fn reset(&mut self, _: &mut System) {
+        self.blocks.clear();
+        self.icache.clear();
+        self.recent.clear();
+    }
+
+    fn invalidate_code(&mut self, sys: &mut System, addr: Address) {
+        invalidate_cacheline(sys, &mut self.blocks, &mut self.icache, true, addr);
+    }
+
+    fn crash_report(&self, _: &System, out: &mut String) {
+        _ = writeln!(out, "Recently entered blocks (oldest first):");
+        for (logical, addr) in &self.recent {
+            let kind = if *logical { "logical" } else { "physical" };
+            _ = writeln!(out, "  {addr} ({kind})");
+        }
+
+        // only look up blocks that have already been compiled: the compiler might be in a broken
+        // state if the panic happened inside of it
+        let Some(&(logical, addr)) = self.recent.back() else {
+            _ = writeln!(out, "No block has been entered");
+            return;
+        };
+
+        let Some(stored) = self
+            .blocks
+            .get_mapping(logical, addr)
+            .and_then(|mapping| self.blocks.storage.get(mapping.id.0))
+        else {
+            _ = writeln!(out, "\nLast entered block at {addr} is not cached");
+            return;
+        };
+
+        let meta = stored.inner.meta();
+        _ = writeln!(out, "\nLast entered block at {addr}:");
+        _ = writeln!(out, "Pattern: {:?}", meta.pattern);
+        _ = writeln!(out, "Cycles: {}", meta.cycles);
+        _ = writeln!(out, "\n--- Sequence ---\n{}", meta.seq);
+
+        // only available in debug builds
+        if let Some(disasm) = &meta.disasm {
+            _ = writeln!(out, "--- Disassembly ---\n{disasm}");
+        }
+
+        if let Some(clir) = &meta.clir {
+            _ = writeln!(out, "--- CLIR ---\n{clir}");
+        }
+    }
+
+    fn dump_block(&mut self, sys: &System, addr: Address) -> Option<String> {
+        let logical = sys.cpu.supervisor.config.msr.instr_addr_translation();
+        let meta = self.blocks.get(logical, addr)?.inner.meta();
+
+        // release builds don't keep the code around, so compile the block again to get it
+        let inspected;
+        let meta = if meta.disasm.is_some() && meta.clir.is_some() {
+            meta
+        } else {
+            let sequence = meta.seq.0.clone();
+            inspected = match self.compiler.inspect(sequence.into_iter()) {
+                Ok(meta) => meta,
+                Err(e) => return Some(format!("failed to compile block at {addr}: {e}")),
+            };
+
+            &inspected
+        };
+
+        let mut out = String::new();
+        _ = writeln!(out, "Block at {addr}");
+        _ = writeln!(out, "Pattern: {:?}", meta.pattern);
+        _ = writeln!(out, "Cycles: {}", meta.cycles);
+        _ = writeln!(out, "\n--- Sequence ---\n{}", meta.seq);
+
+        if let Some(disasm) = &meta.disasm {
+            _ = writeln!(out, "--- Disassembly ---\n{disasm}");
+        }
+
+        if let Some(clir) = &meta.clir {
+            _ = writeln!(out, "--- CLIR ---\n{clir}");
+        }
+
+        Some(out)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lazuli::cores::CpuCore;
+    use lazuli::system::{self, Modules, System};
+    use lazuli::{Address, Cycles};
+
+    use super::{Config, Core};
+
+    /// Where the code is placed in memory.
+    const CODE: u32 = 0x1000;
+
+    /// `lis r3, 0xE000; lwz r4, 0(r3); b 0`, a load from the start of the locked cache.
+    const LOAD: [u32; 3] = [0x3C60_E000, 0x8083_0000, 0x4800_0000];
+
+    /// `lis r5, 0x1000; mtspr HID2, r5`, which sets HID2.LCE.
+    const ENABLE: [u32; 2] = [0x3CA0_1000, 0x7CB8_E3A6];
+
+    /// Runs the given code with a word in the locked cache and returns `r4`.
+    fn run(code: &[u32]) -> u32 {
+        let mut sys = System::new(Modules::nop(), system::Config::default());
+        sys.mem.l2c_mut()[..4].copy_from_slice(&0xDEAD_BEEFu32.to_be_bytes());
+        for (i, ins) in code.iter().enumerate() {
+            sys.write(Address(CODE + 4 * i as u32), *ins);
+        }
+
+        let mut core = Core::new(Config {
+            instr_per_block: 128,
+            jit_settings: Default::default(),
+        });
+
+        sys.cpu.pc = Address(CODE);
+        sys.cpu.user.gpr[4] = 0x1234_5678;
+        core.exec(&mut sys, Cycles(64), &[]);
+
+        sys.cpu.user.gpr[4]
+    }
+
+    #[test]
+    fn locked_cache_load_disabled() {
+        assert_eq!(run(&LOAD), 0);
+    }
+
+    #[test]
+    fn locked_cache_load_enabled() {
+        let code: Vec<u32> = ENABLE.iter().chain(&LOAD).copied().collect();
+        assert_eq!(run(&code), 0xDEAD_BEEF);
+    }
 }
@@ -1,6 +1,7 @@
 use lazuli::Address;
+use lazuli::gekko::PmcEvent;
 use lazuli::gekko::disasm::{Extensions, Ins};
-use lazuli::system::System;
+use lazuli::system::{self, System};
 
 use crate::cpu::jit::table::Table;
 
@@ -37,7 +38,8 @@ impl Cache {
         let cacheline = match level2.get(idx2) {
             Some(cacheline) => cacheline,
             None => {
-                let base = physical.align_down(32);
+                system::pmc::increment(sys, PmcEvent::ICacheMiss, 1);
+                let base = physical.align_down_pow2(32);
 
                 let mut cacheline = [0; 8];
                 for (index, word) in cacheline.iter_mut().enumerate() {
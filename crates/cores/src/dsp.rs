@@ -1,3 +1,4 @@
+pub mod ax;
 pub mod interpreter;
 
 const fn convert_to_dsp_words<const N: usize>(bytes: &[u8]) -> [u16; N] {
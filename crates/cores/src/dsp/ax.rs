@@ -0,0 +1,13 @@
+use dspax::AxHle;
+
+use super::{DSP_COEF, interpreter};
+
+#[rustfmt::skip]
+pub use dspax;
+
+/// Creates an AX HLE core, resampling with the coefficients of the DSP ROM. Ucodes which aren't
+/// AX are run by an interpreter created with `config`.
+pub fn core(config: interpreter::Config) -> AxHle {
+    let fallback = interpreter::Core::new(config);
+    AxHle::new(&DSP_COEF, Some(Box::new(fallback)))
+}
@@ -1,38 +1,122 @@
-use dspint::Interpreter;
-use lazuli::cores::DspCore;
+use std::any::Any;
+use std::fmt::Write;
+
+use dspint::{Exit, IROM_SIZE, Interpreter};
+use lazuli::cores::{DspCore, DspExecuted};
 use lazuli::system::System;
 
 use super::{DSP_COEF, DSP_ROM};
 
+#[rustfmt::skip]
+pub use dspint;
+
+/// DSP interpreter configuration.
+#[derive(Default)]
+pub struct Config {
+    /// Dump of the IROM to use instead of the built-in one.
+    pub irom: Option<Box<[u8; IROM_SIZE]>>,
+}
+
 pub struct Core {
-    interpreter: Interpreter,
+    pub interpreter: Interpreter,
 }
 
-impl Default for Core {
-    fn default() -> Self {
+impl Core {
+    pub fn new(config: Config) -> Self {
         let mut interpreter = Interpreter::default();
-        interpreter.mem.irom.copy_from_slice(&DSP_ROM[..]);
+        match &config.irom {
+            Some(irom) => interpreter.load_irom(irom),
+            None => interpreter.mem.irom.copy_from_slice(&DSP_ROM[..]),
+        }
         interpreter.mem.coef.copy_from_slice(&DSP_COEF[..]);
 
         Self { interpreter }
     }
 }
 
+impl Default for Core {
+    fn default() -> Self {
+        Self::new(Config::default())
+    }
+}
+
 impl DspCore for Core {
-    fn exec(&mut self, sys: &mut System, instructions: u32) -> u32 {
+    fn exec(&mut self, sys: &mut System, cycles: u32) -> DspExecuted {
+        self.interpreter.do_dma(sys);
+        self.interpreter.check_reset(sys);
+
+        let executed = self.interpreter.exec(sys, cycles);
+        let hit_breakpoint = executed.exit == Exit::Breakpoint;
+
+        DspExecuted {
+            instructions: executed.instructions,
+            // a halted or waiting DSP idles through the rest of the budget
+            cycles: if hit_breakpoint {
+                executed.instructions
+            } else {
+                cycles
+            },
+            hit_breakpoint,
+        }
+    }
+
+    fn step(&mut self, sys: &mut System) {
         self.interpreter.do_dma(sys);
         self.interpreter.check_reset(sys);
+        self.interpreter.step(sys);
+    }
+
+    fn reset(&mut self, sys: &mut System) {
+        self.interpreter.reset(sys);
+        self.interpreter.old_reset_high = sys.dsp.control.reset_high();
+    }
 
-        if sys.dsp.control.halt()
-            || !sys.dsp.cpu_mailbox.status() && self.interpreter.is_waiting_for_cpu_mail()
-            || sys.dsp.dsp_mailbox.status() && self.interpreter.is_waiting_for_dsp_mail()
-        {
-            std::hint::cold_path();
-            self.interpreter.check_interrupts(sys);
-        } else {
-            self.interpreter.exec(sys, instructions);
+    fn crash_report(&self, _: &System, out: &mut String) {
+        let interpreter = &self.interpreter;
+        _ = writeln!(out, "PC: {:04X}", interpreter.pc);
+        _ = writeln!(out, "{:#?}", interpreter.regs);
+
+        _ = writeln!(out, "Recent mails (oldest first):");
+        for mail in &interpreter.mail_history {
+            _ = writeln!(out, "  {:?}: {:08X}", mail.direction, mail.data);
         }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lazuli::cores::DspCore;
+    use lazuli::system::{self, Modules, System};
+
+    use super::Core;
+
+    #[test]
+    fn breakpoint_counts_executed() {
+        let mut sys = System::new(Modules::nop(), system::Config::default());
+        let mut core = Core::default();
+
+        // IRAM is filled with NOPs
+        let interpreter = &mut core.interpreter;
+        interpreter.mem.iram.fill(0);
+        interpreter.pc = 0;
+        interpreter.breakpoints.push(5);
+
+        let executed = core.exec(&mut sys, 64);
+        assert!(executed.hit_breakpoint);
+        assert_eq!((executed.instructions, executed.cycles), (5, 5));
+        assert_eq!(core.interpreter.pc, 5);
 
-        instructions
+        // resumes past the breakpoint
+        let executed = core.exec(&mut sys, 64);
+        assert!(!executed.hit_breakpoint);
+        assert_eq!((executed.instructions, executed.cycles), (64, 64));
     }
 }
@@ -5,9 +5,30 @@ use std::io::{Read, Seek, SeekFrom};
 use binrw::{BinRead, BinResult};
 use crate::{apploader, dol, iso};
 
+/// The magic at the start of every CSO file.
+pub const CSO_MAGIC: [u8; 4] = *b"CISO";
+
 const CSO_HEADER_SIZE: usize = 0x8000; // 32KB
 const CSO_MAP_SIZE: usize = CSO_HEADER_SIZE - size_of::<u32>() - 4; // 0x8000 (32768) - 4 (magic) - 4 (block_size)
 
+/// Checks whether the given reader contains a CSO file by looking for [`CSO_MAGIC`] at offset 0.
+/// The reader is rewound to the start afterwards.
+pub fn is_cso<R>(reader: &mut R) -> std::io::Result<bool>
+where
+    R: Read + Seek,
+{
+    let mut magic = [0; 4];
+    reader.seek(SeekFrom::Start(0))?;
+    let result = match reader.read_exact(&mut magic) {
+        Ok(()) => magic == CSO_MAGIC,
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => false,
+        Err(e) => return Err(e),
+    };
+    reader.seek(SeekFrom::Start(0))?;
+
+    Ok(result)
+}
+
 #[binrw::parser(reader, endian)]
 fn parse_bool_array() -> BinResult<[bool; CSO_MAP_SIZE]> {
     let block_used: [u8; CSO_MAP_SIZE] = BinRead::read_options(reader, endian, ())?;
@@ -75,12 +96,22 @@ where
         &mut self.reader
     }
 
+    /// Length of the uncompressed disk, in bytes.
+    ///
+    /// The header doesn't store the size of the image and the map covers much more than a whole
+    /// disc, so this is the size of a GameCube disc (unless the map is somehow smaller).
+    pub fn len(&self) -> u64 {
+        let capacity = self.map.len() as u64 * self.header.block_size as u64;
+        capacity.min(iso::DISC_SIZE)
+    }
+
     /// Read from disk at the given offset and writes it into the output buffer.
     /// Returns how many bytes were actually read.
     pub fn read(&mut self, disk_offset: u64, out: &mut [u8]) -> std::io::Result<u64> {
         let block_size = self.header().block_size as u64;
         let mut current_disk_offset = disk_offset;
-        let mut remaining = out.len() as u64;
+        let mut remaining = (out.len() as u64).min(self.len().saturating_sub(disk_offset));
+        let total = remaining;
 
         while remaining > 0 {
 
@@ -105,7 +136,7 @@ where
             remaining -= to_read;
         }
 
-        Ok(out.len() as u64 - remaining)
+        Ok(total)
     }
 }
 
@@ -160,11 +191,7 @@ where
         match pos {
             SeekFrom::Start(x) => self.position = x,
             SeekFrom::End(x) => {
-                self.position = self
-                    .cso
-                    .header()
-                    .block_size
-                    .saturating_sub_signed(x as i32) as u64;
+                self.position = self.cso.len().saturating_add_signed(x);
             },
             SeekFrom::Current(x) => self.position = self.position.saturating_add_signed(x),
         }
@@ -210,3 +237,62 @@ where
         iso::filesystem::FileSystem::read(self)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::io::{Cursor, Read, Seek, SeekFrom};
+
+    use super::{CSO_HEADER_SIZE, CSO_MAGIC, Cso, CsoReader};
+    use crate::iso::DISC_SIZE;
+
+    const BLOCK_SIZE: u32 = 0x20_0000;
+
+    /// Builds an image where only the first and the last block of the disc are present, filled
+    /// with `0xAA` and `0xBB` respectively.
+    fn image() -> CsoReader<Cursor<Vec<u8>>> {
+        let last = (DISC_SIZE - 1) / BLOCK_SIZE as u64;
+
+        let mut data = vec![0; CSO_HEADER_SIZE];
+        data[..4].copy_from_slice(&CSO_MAGIC);
+        data[4..8].copy_from_slice(&BLOCK_SIZE.to_le_bytes());
+        data[8] = 1;
+        data[8 + last as usize] = 1;
+        data.extend(std::iter::repeat_n(0xAA, BLOCK_SIZE as usize));
+        data.extend(std::iter::repeat_n(0xBB, BLOCK_SIZE as usize));
+
+        CsoReader::new(Cso::new(Cursor::new(data)).unwrap())
+    }
+
+    #[test]
+    fn seek_end() {
+        let mut reader = image();
+        assert_eq!(reader.inner().len(), DISC_SIZE);
+        assert_eq!(reader.seek(SeekFrom::End(0)).unwrap(), DISC_SIZE);
+        assert_eq!(reader.seek(SeekFrom::End(-4)).unwrap(), DISC_SIZE - 4);
+    }
+
+    #[test]
+    fn read_end() {
+        let mut reader = image();
+        reader.seek(SeekFrom::End(-0x10)).unwrap();
+
+        let mut buf = [0; 0x20];
+        assert_eq!(reader.read(&mut buf).unwrap(), 0x10);
+        assert_eq!(buf[..0x10], [0xBB; 0x10]);
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn read_blocks() {
+        let mut reader = image();
+
+        // crosses from the first block into an unused one
+        reader
+            .seek(SeekFrom::Start(BLOCK_SIZE as u64 - 0x10))
+            .unwrap();
+        let mut buf = [0xFF; 0x20];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf[..0x10], [0xAA; 0x10]);
+        assert_eq!(buf[0x10..], [0; 0x10]);
+    }
+}
@@ -0,0 +1,350 @@
+//! A browsable view of the file system table (FST) of a disk.
+//!
+//! The FST is read through any reader of the disk data, so it works with every disk format as
+//! long as it presents the plain disk contents (e.g. the readers of the compressed formats).
+
+use std::io::{Read, Seek, SeekFrom};
+
+use binrw::{BinRead, NullString};
+use easyerr::{Error, ResultExt};
+
+use crate::iso::Header;
+use crate::iso::filesystem::{Entry, FileSystem};
+
+#[derive(Debug, Error)]
+pub enum FstError {
+    #[error(transparent)]
+    ParsingHeader { source: binrw::Error },
+    #[error(transparent)]
+    ParsingFileSystem { source: binrw::Error },
+    #[error(transparent)]
+    ParsingName { source: binrw::Error },
+    #[error(transparent)]
+    ReadingFile { source: std::io::Error },
+    #[error("no entry at path {path:?}")]
+    NotFound { path: String },
+    #[error("entry at path {path:?} is a directory")]
+    IsDirectory { path: String },
+}
+
+/// A file in the FST.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FstEntry {
+    /// Path of the file, with components separated by `/` and no leading separator.
+    pub path: String,
+    /// Offset of the file data in the disk.
+    pub offset: u32,
+    /// Length of the file data.
+    pub size: u32,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    File { offset: u32, size: u32 },
+    Directory,
+}
+
+/// Normalizes a path so that it can be compared against FST paths.
+fn normalize(path: &str) -> &str {
+    path.trim_matches('/')
+}
+
+/// The FST of a disk, with every path resolved.
+#[derive(Debug)]
+pub struct Fst<R> {
+    /// Header of the disk.
+    header: Header,
+    /// Entries of the FST in order, except the root.
+    entries: Vec<(String, Node)>,
+    /// Reader of the disk data.
+    reader: R,
+}
+
+impl<R> Fst<R>
+where
+    R: Read + Seek,
+{
+    /// Reads the disk header and the FST from the given reader of disk data.
+    pub fn new(mut reader: R) -> Result<Self, FstError> {
+        reader
+            .seek(SeekFrom::Start(0))
+            .map_err(binrw::Error::from)
+            .context(FstCtx::ParsingHeader)?;
+        let header = Header::read(&mut reader).context(FstCtx::ParsingHeader)?;
+
+        reader
+            .seek(SeekFrom::Start(header.filesystem_offset as u64))
+            .map_err(binrw::Error::from)
+            .context(FstCtx::ParsingFileSystem)?;
+        let filesystem = FileSystem::read(&mut reader).context(FstCtx::ParsingFileSystem)?;
+
+        let mut read_name = |name_offset: u32| -> Result<String, FstError> {
+            let offset = filesystem.strings_offset as u64 + name_offset as u64;
+            reader
+                .seek(SeekFrom::Start(offset))
+                .map_err(binrw::Error::from)
+                .context(FstCtx::ParsingName)?;
+
+            let name = NullString::read(&mut reader).context(FstCtx::ParsingName)?;
+            Ok(name.to_string())
+        };
+
+        // entry indices count the root as zero, so the first entry in the list has index one
+        let mut entries = Vec::with_capacity(filesystem.entries.len());
+        let mut dir_stack: Vec<(String, u32)> = vec![(String::new(), filesystem.root.entry_count)];
+        for (index, entry) in filesystem.entries.iter().enumerate() {
+            let index = index as u32 + 1;
+            while dir_stack.len() > 1 && dir_stack.last().unwrap().1 <= index {
+                dir_stack.pop();
+            }
+
+            let parent = &dir_stack.last().unwrap().0;
+            match entry {
+                Entry::File(file) => {
+                    let path = format!("{parent}{}", read_name(file.name_offset)?);
+                    let node = Node::File {
+                        offset: file.data_offset,
+                        size: file.data_length,
+                    };
+
+                    entries.push((path, node));
+                }
+                Entry::Directory(dir) => {
+                    let path = format!("{parent}{}", read_name(dir.name_offset)?);
+                    dir_stack.push((format!("{path}/"), dir.end_index));
+                    entries.push((path, Node::Directory));
+                }
+            }
+        }
+
+        Ok(Self {
+            header,
+            entries,
+            reader,
+        })
+    }
+
+    /// Header of the disk.
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// The game ID of the disk, i.e. its game code followed by its maker code (e.g. `GALE01`).
+    pub fn game_id(&self) -> Option<String> {
+        let meta = &self.header.meta;
+        let maker = String::from_utf8(meta.maker_code.to_be_bytes().into()).ok()?;
+
+        Some(meta.game_code_str()? + &maker)
+    }
+
+    /// The title of the game in the disk.
+    pub fn title(&self) -> String {
+        self.header.meta.game_name.to_string()
+    }
+
+    /// Iterates over every file in the FST, in the order they're listed in.
+    pub fn files(&self) -> impl Iterator<Item = FstEntry> + '_ {
+        self.entries.iter().filter_map(|(path, node)| match node {
+            Node::File { offset, size } => Some(FstEntry {
+                path: path.clone(),
+                offset: *offset,
+                size: *size,
+            }),
+            Node::Directory => None,
+        })
+    }
+
+    /// Iterates over the path of every directory in the FST, except the root.
+    pub fn directories(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().filter_map(|(path, node)| match node {
+            Node::File { .. } => None,
+            Node::Directory => Some(path.as_str()),
+        })
+    }
+
+    /// Finds the file at the given path.
+    pub fn find(&self, path: &str) -> Result<FstEntry, FstError> {
+        let normalized = normalize(path);
+        let Some((found, node)) = self.entries.iter().find(|(p, _)| p == normalized) else {
+            return Err(FstError::NotFound {
+                path: path.to_owned(),
+            });
+        };
+
+        match node {
+            Node::File { offset, size } => Ok(FstEntry {
+                path: found.clone(),
+                offset: *offset,
+                size: *size,
+            }),
+            Node::Directory => Err(FstError::IsDirectory {
+                path: found.clone(),
+            }),
+        }
+    }
+
+    /// Reads the contents of the file at the given path.
+    pub fn read_file(&mut self, path: &str) -> Result<Vec<u8>, FstError> {
+        let entry = self.find(path)?;
+
+        let mut data = vec![0; entry.size as usize];
+        self.reader
+            .seek(SeekFrom::Start(entry.offset as u64))
+            .context(FstCtx::ReadingFile)?;
+        self.reader
+            .read_exact(&mut data)
+            .context(FstCtx::ReadingFile)?;
+
+        Ok(data)
+    }
+
+    /// Reader of the disk data.
+    pub fn reader(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    /// Consumes the FST, returning the reader of the disk data.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use binrw::{BinWrite, NullString};
+
+    use super::{Fst, FstEntry, FstError};
+    use crate::iso::{Header, MagicWord, Meta};
+
+    const FST_OFFSET: u32 = 0x1000;
+
+    fn file(name_offset: u32, offset: u32, size: u32) -> [u8; 12] {
+        let mut entry = [0; 12];
+        entry[0..4].copy_from_slice(&name_offset.to_be_bytes());
+        entry[4..8].copy_from_slice(&offset.to_be_bytes());
+        entry[8..12].copy_from_slice(&size.to_be_bytes());
+        entry
+    }
+
+    fn dir(name_offset: u32, parent: u32, end: u32) -> [u8; 12] {
+        let mut entry = file(name_offset, parent, end);
+        entry[0] = 1;
+        entry
+    }
+
+    /// Builds a disk with the following tree:
+    ///
+    /// ```text
+    /// opening.bnr
+    /// audio/
+    ///     bgm/
+    ///         track.adp
+    /// readme.txt
+    /// ```
+    fn disk() -> Vec<u8> {
+        let header = Header {
+            meta: Meta {
+                console_id: b'G',
+                game_id: u16::from_be_bytes(*b"TS"),
+                country_code: b'T',
+                maker_code: u16::from_be_bytes(*b"01"),
+                disk_id: 0,
+                version: 0,
+                audio_streaming: 0,
+                stream_buffer_size: 0,
+                magic: MagicWord,
+                game_name: NullString::from("Test Game"),
+            },
+            debug_monitor_offset: 0,
+            debug_monitor_target: 0,
+            bootfile_offset: 0,
+            filesystem_offset: FST_OFFSET,
+            filesystem_size: 0,
+            max_filesystem_size: 0,
+            user_position: 0,
+            user_length: 0,
+        };
+
+        let mut disk = Cursor::new(vec![0; 0x3000]);
+        header.write(&mut disk).unwrap();
+
+        let names = b"opening.bnr\0audio\0bgm\0track.adp\0readme.txt\0";
+        let entries = [
+            dir(0, 0, 6),
+            file(0, 0x2000, 5),
+            dir(12, 0, 5),
+            dir(18, 2, 5),
+            file(22, 0x2100, 8),
+            file(32, 0x2200, 3),
+        ];
+
+        let mut disk = disk.into_inner();
+        let fst = &mut disk[FST_OFFSET as usize..];
+        for (i, entry) in entries.iter().enumerate() {
+            fst[i * 12..][..12].copy_from_slice(entry);
+        }
+        fst[entries.len() * 12..][..names.len()].copy_from_slice(names);
+
+        disk[0x2000..][..5].copy_from_slice(b"hello");
+        disk[0x2100..][..8].copy_from_slice(b"trackdat");
+        disk[0x2200..][..3].copy_from_slice(b"hi!");
+
+        disk
+    }
+
+    #[test]
+    fn header() {
+        let fst = Fst::new(Cursor::new(disk())).unwrap();
+        assert_eq!(fst.game_id().as_deref(), Some("GTST01"));
+        assert_eq!(fst.title(), "Test Game");
+    }
+
+    #[test]
+    fn tree() {
+        let fst = Fst::new(Cursor::new(disk())).unwrap();
+
+        let files = fst.files().collect::<Vec<_>>();
+        assert_eq!(
+            files,
+            [
+                FstEntry {
+                    path: "opening.bnr".into(),
+                    offset: 0x2000,
+                    size: 5,
+                },
+                FstEntry {
+                    path: "audio/bgm/track.adp".into(),
+                    offset: 0x2100,
+                    size: 8,
+                },
+                FstEntry {
+                    path: "readme.txt".into(),
+                    offset: 0x2200,
+                    size: 3,
+                },
+            ]
+        );
+
+        let dirs = fst.directories().collect::<Vec<_>>();
+        assert_eq!(dirs, ["audio", "audio/bgm"]);
+    }
+
+    #[test]
+    fn read_file() {
+        let mut fst = Fst::new(Cursor::new(disk())).unwrap();
+        assert_eq!(fst.read_file("opening.bnr").unwrap(), b"hello");
+        assert_eq!(fst.read_file("/audio/bgm/track.adp").unwrap(), b"trackdat");
+        assert_eq!(fst.read_file("readme.txt").unwrap(), b"hi!");
+
+        assert!(matches!(
+            fst.read_file("audio/bgm"),
+            Err(FstError::IsDirectory { .. })
+        ));
+        assert!(matches!(
+            fst.read_file("audio/readme.txt"),
+            Err(FstError::NotFound { .. })
+        ));
+    }
+}
@@ -9,6 +9,9 @@ use filesystem::FileSystem;
 
 use crate::{Console, apploader, dol};
 
+/// Size of a GameCube disc, in bytes.
+pub const DISC_SIZE: u64 = 0x5705_8000;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, BinRead, BinWrite)]
 #[brw(big, magic = 0xC233_9F3D_u32)]
 pub struct MagicWord;
@@ -2,11 +2,13 @@
 
 pub mod apploader;
 pub mod dol;
+pub mod fst;
 pub mod iso;
 pub mod cso;
 pub mod rvz;
+pub mod wia;
 
-pub use binrw;
+pub use {binrw, elf};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Console {
@@ -0,0 +1,730 @@
+//! A `.wia` file is a disc format designed to store the same data as `.iso` files in a
+//! space-efficient manner. It is the predecessor of the [`rvz`](crate::rvz) format, which shares
+//! most of it's structure.
+//!
+//! Only GameCube disks are supported, i.e. Wii partition data is not handled. Every compression
+//! method WIA allows (none, purge, bzip2, LZMA and LZMA2) can be read.
+
+use std::io::{Cursor, Read, Seek, SeekFrom};
+
+use binrw::{BinRead, binread};
+use bzip2::read::BzDecoder;
+use easyerr::{Error, ResultExt};
+use liblzma::read::XzDecoder;
+use liblzma::stream::{Filters, Stream};
+
+use crate::rvz::{Compression, DiskHeader, DiskSection, Sha1Hash, Version};
+use crate::{apploader, dol, iso};
+
+/// The magic of a WIA file.
+pub const WIA_MAGIC: [u8; 4] = *b"WIA\x01";
+
+/// The actual header of a WIA file.
+#[derive(Debug, Clone, BinRead)]
+#[br(big, magic = b"WIA\x01")]
+pub struct WiaHeaderInner {
+    /// Version of this WIA.
+    pub version: Version,
+    /// Version that supports reading this WIA.
+    pub compatible_version: Version,
+    /// The length of the disk header.
+    pub disk_header_len: u32,
+    /// The SHA1 hash of the disk header.
+    pub disk_header_sha1: Sha1Hash,
+    /// The length of the disk this WIA contains.
+    pub disk_len: u64,
+    /// The length of this WIA.
+    pub wia_len: u64,
+}
+
+/// The header of a .wia file. This is a wrapper around [`WiaHeaderInner`] which also contains it's
+/// hash.
+#[derive(Debug, Clone, BinRead)]
+#[br(big)]
+pub struct WiaHeader {
+    /// The actual contents of the header.
+    pub inner: WiaHeaderInner,
+    /// The SHA1 hash of the inner field.
+    pub hash: Sha1Hash,
+}
+
+/// A file section (also known as a group) describes a specific range of data in the WIA file.
+///
+/// Unlike RVZ, WIA has no per-section compression flag: every non-empty section is compressed
+/// with the method in the [`DiskHeader`].
+#[binread(big)]
+#[derive(Debug, Clone, Copy)]
+pub struct FileSection {
+    #[br(temp)]
+    file_offset_div_4: u32,
+
+    /// The file offset this section refers to.
+    #[br(calc = file_offset_div_4 as u64 * 4)]
+    pub file_offset: u64,
+    /// The length of the compressed data of this file section. If zero, the section is zeroed.
+    pub len: u32,
+}
+
+impl FileSection {
+    /// Whether this file section is zeroed (i.e. all of it's bytes are zero).
+    pub fn is_zeroed(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// A segment of non-zero data in purge compressed data.
+#[binread]
+#[br(big)]
+#[derive(Debug, Clone)]
+struct PurgeSegment {
+    offset: u32,
+    #[br(temp)]
+    len: u32,
+    #[br(count = len)]
+    data: Vec<u8>,
+}
+
+/// Decompresses raw LZMA or LZMA2 data, i.e. without any container around it.
+fn decompress_lzma(data: &[u8], length: usize, filters: &Filters) -> std::io::Result<Vec<u8>> {
+    let stream = Stream::new_raw_decoder(filters)?;
+    let mut output = Vec::with_capacity(length);
+    XzDecoder::new_stream(data, stream)
+        .take(length as u64)
+        .read_to_end(&mut output)?;
+
+    Ok(output)
+}
+
+enum Decompressor {
+    None,
+    Purge,
+    Bzip2,
+    /// LZMA, with the encoded filter properties from the [`DiskHeader`].
+    Lzma(Vec<u8>),
+    /// LZMA2, with the encoded filter properties from the [`DiskHeader`].
+    Lzma2(Vec<u8>),
+}
+
+impl Decompressor {
+    fn decompress(&mut self, data: &[u8], length: usize) -> Result<Vec<u8>, binrw::Error> {
+        let output = match self {
+            Self::None => return Ok(data.to_vec()),
+            Self::Bzip2 => {
+                let mut output = Vec::with_capacity(length);
+                BzDecoder::new(data)
+                    .take(length as u64)
+                    .read_to_end(&mut output)?;
+
+                output
+            }
+            Self::Lzma(properties) => {
+                let mut filters = Filters::new();
+                filters
+                    .lzma1_properties(properties)
+                    .map_err(std::io::Error::from)?;
+
+                decompress_lzma(data, length, &filters)?
+            }
+            Self::Lzma2(properties) => {
+                let mut filters = Filters::new();
+                filters
+                    .lzma2_properties(properties)
+                    .map_err(std::io::Error::from)?;
+
+                decompress_lzma(data, length, &filters)?
+            }
+            Self::Purge => {
+                // purge data is a sequence of segments followed by a SHA1 hash of the data
+                let segments = &data[..data.len().saturating_sub(size_of::<Sha1Hash>())];
+                let mut cursor = Cursor::new(segments);
+                let mut output = vec![0; length];
+
+                while cursor.position() != segments.len() as u64 {
+                    let segment = PurgeSegment::read(&mut cursor)?;
+                    let start = segment.offset as usize;
+                    let Some(out) = output.get_mut(start..start + segment.data.len()) else {
+                        return Err(binrw::Error::AssertFail {
+                            pos: cursor.position(),
+                            message: "purge segment out of bounds".into(),
+                        });
+                    };
+
+                    out.copy_from_slice(&segment.data);
+                }
+
+                return Ok(output);
+            }
+        };
+
+        if output.len() != length {
+            return Err(binrw::Error::Io(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "decompressed data is shorter than expected",
+            )));
+        }
+
+        Ok(output)
+    }
+}
+
+/// Reads the disk sections in a WIA.
+fn read_disk_sections<R: Read + Seek>(
+    disk: &DiskHeader,
+    decompressor: &mut Decompressor,
+    mut reader: R,
+) -> Result<Vec<DiskSection>, binrw::Error> {
+    let mut compressed = vec![0; disk.disk_sections_len as usize];
+    reader.seek(SeekFrom::Start(disk.disk_sections_offset))?;
+    reader.read_exact(&mut compressed)?;
+
+    let decompressed_size = disk.disk_sections_count as usize * size_of::<DiskSection>();
+    let decompressed = decompressor.decompress(&compressed, decompressed_size)?;
+
+    let mut cursor = Cursor::new(decompressed);
+    let decoded = <Vec<DiskSection>>::read_options(
+        &mut cursor,
+        binrw::endian::BE,
+        binrw::VecArgs::builder()
+            .count(disk.disk_sections_count as usize)
+            .finalize(),
+    )?;
+
+    Ok(decoded)
+}
+
+/// Reads the file sections in a WIA.
+fn read_file_sections<R: Read + Seek>(
+    disk: &DiskHeader,
+    decompressor: &mut Decompressor,
+    mut reader: R,
+) -> Result<Vec<FileSection>, binrw::Error> {
+    let mut compressed = vec![0; disk.file_sections_len as usize];
+    reader.seek(SeekFrom::Start(disk.file_sections_offset))?;
+    reader.read_exact(&mut compressed)?;
+
+    // each entry is 8 bytes long: the file offset divided by 4 and the length
+    let decompressed_size = disk.file_sections_count as usize * 8;
+    let decompressed = decompressor.decompress(&compressed, decompressed_size)?;
+
+    let mut cursor = Cursor::new(decompressed);
+    let decoded = <Vec<FileSection>>::read_options(
+        &mut cursor,
+        binrw::endian::BE,
+        binrw::VecArgs::builder()
+            .count(disk.file_sections_count as usize)
+            .finalize(),
+    )?;
+
+    Ok(decoded)
+}
+
+struct FoundFileSection {
+    inner: FileSection,
+    disk_start: u64,
+    disk_len: u64,
+}
+
+#[derive(Debug, Error)]
+pub enum WiaError {
+    #[error("unsupported compression format {f0:?}")]
+    UnsupportedCompression(Compression),
+    #[error(transparent)]
+    ParsingWiaHeader { source: binrw::Error },
+    #[error(transparent)]
+    ParsingDiskHeader { source: binrw::Error },
+    #[error(transparent)]
+    ParsingDiskSections { source: binrw::Error },
+    #[error(transparent)]
+    ParsingFileSections { source: binrw::Error },
+    #[error(transparent)]
+    ReadingFileSection { source: std::io::Error },
+    #[error(transparent)]
+    DecompressingFileSection { source: binrw::Error },
+    #[error(
+        "file section containing offset {disk_section_offset} of {disk_section:?} could not be found"
+    )]
+    FileSectionNotFound {
+        disk_section: DiskSection,
+        disk_section_offset: u64,
+    },
+}
+
+/// A .wia file.
+pub struct Wia<R> {
+    wia_header: WiaHeader,
+    disk_header: DiskHeader,
+    disk_sections: Vec<DiskSection>,
+    file_sections: Vec<FileSection>,
+    decompressor: Decompressor,
+    reader: R,
+}
+
+impl<R> Wia<R>
+where
+    R: Read + Seek,
+{
+    /// Creates a new [`Wia`] from the given reader. This function _does not_ validate the WIA,
+    /// i.e. hashes are not computed and checked.
+    pub fn new(mut reader: R) -> Result<Self, WiaError> {
+        let header = WiaHeader::read(&mut reader).context(WiaCtx::ParsingWiaHeader)?;
+        let disk = DiskHeader::read(&mut reader).context(WiaCtx::ParsingDiskHeader)?;
+
+        let properties_len = (disk.compressor_data_count as usize).min(disk.compressor_data.len());
+        let properties = disk.compressor_data[..properties_len].to_vec();
+        let mut decompressor = match disk.compression {
+            Compression::None => Decompressor::None,
+            Compression::Purge => Decompressor::Purge,
+            Compression::Bzip2 => Decompressor::Bzip2,
+            Compression::Lzma => Decompressor::Lzma(properties),
+            Compression::Lzma2 => Decompressor::Lzma2(properties),
+            Compression::Zstd => return Err(WiaError::UnsupportedCompression(disk.compression)),
+        };
+
+        let disk_sections = read_disk_sections(&disk, &mut decompressor, &mut reader)
+            .context(WiaCtx::ParsingDiskSections)?;
+        let file_sections = read_file_sections(&disk, &mut decompressor, &mut reader)
+            .context(WiaCtx::ParsingFileSections)?;
+
+        Ok(Self {
+            wia_header: header,
+            disk_header: disk,
+            disk_sections,
+            file_sections,
+            decompressor,
+            reader,
+        })
+    }
+
+    pub fn wia_header(&self) -> &WiaHeader {
+        &self.wia_header
+    }
+
+    pub fn disk_header(&self) -> &DiskHeader {
+        &self.disk_header
+    }
+
+    pub fn disk_sections(&self) -> &[DiskSection] {
+        &self.disk_sections
+    }
+
+    pub fn file_sections(&self) -> &[FileSection] {
+        &self.file_sections
+    }
+
+    pub fn reader(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    /// Finds the disk section that contains the given disk offset.
+    pub fn find_disk_section(&self, disk_offset: u64) -> Option<DiskSection> {
+        self.disk_sections
+            .iter()
+            .find(|x| x.contains(disk_offset))
+            .copied()
+    }
+
+    /// Finds the file section that contains the given offset into it's disk section.
+    fn find_file_section(
+        &self,
+        disk_section: DiskSection,
+        disk_section_offset: u64,
+    ) -> Option<FoundFileSection> {
+        let chunk_len = self.disk_header.chunk_len as u64;
+        let file_section_idx = disk_section_offset / chunk_len;
+        let file_section_disk_start = file_section_idx * chunk_len;
+        let file_section_disk_len =
+            (disk_section.disk_len - file_section_disk_start).min(chunk_len);
+
+        (file_section_idx < disk_section.file_sections_count as u64).then(|| {
+            let file_section_idx = disk_section.file_sections_index as u64 + file_section_idx;
+            FoundFileSection {
+                inner: self.file_sections[file_section_idx as usize],
+                disk_start: file_section_disk_start,
+                disk_len: file_section_disk_len,
+            }
+        })
+    }
+
+    /// Reads a disk section at the given offset and writes it into the output buffer.
+    pub fn read_disk_section(
+        &mut self,
+        disk_section: DiskSection,
+        disk_section_offset: u64,
+        out: &mut [u8],
+    ) -> Result<(), WiaError> {
+        let mut current_disk_section_offset = disk_section_offset;
+        let mut remaining = out.len() as u64;
+
+        while remaining > 0 {
+            // find file section containing current offset
+            let Some(section) = self.find_file_section(disk_section, current_disk_section_offset)
+            else {
+                return Err(WiaError::FileSectionNotFound {
+                    disk_section,
+                    disk_section_offset: current_disk_section_offset,
+                });
+            };
+
+            // 01. read and decompress data
+            let decompressed = if section.inner.is_zeroed() {
+                vec![0; section.disk_len as usize]
+            } else {
+                let mut compressed = vec![0; section.inner.len as usize];
+
+                self.reader
+                    .seek(SeekFrom::Start(section.inner.file_offset))
+                    .context(WiaCtx::ReadingFileSection)?;
+
+                self.reader
+                    .read_exact(&mut compressed)
+                    .context(WiaCtx::ReadingFileSection)?;
+
+                self.decompressor
+                    .decompress(&compressed, section.disk_len as usize)
+                    .context(WiaCtx::DecompressingFileSection)?
+            };
+
+            // 02. copy to output
+            let file_section_offset = current_disk_section_offset - section.disk_start;
+            let to_read = remaining.min(section.disk_len - file_section_offset);
+
+            let out_start = current_disk_section_offset - disk_section_offset;
+            let out = &mut out[out_start as usize..][..to_read as usize];
+            out.copy_from_slice(&decompressed[file_section_offset as usize..][..to_read as usize]);
+
+            current_disk_section_offset += to_read;
+            remaining -= to_read;
+        }
+
+        Ok(())
+    }
+
+    /// Reads from disk at the given offset and writes it into the output buffer. Returns how many
+    /// bytes were actually read.
+    pub fn read(&mut self, disk_offset: u64, out: &mut [u8]) -> Result<u64, WiaError> {
+        let mut current_disk_offset = disk_offset;
+        let mut remaining = out.len() as u64;
+
+        while remaining > 0 {
+            let Some(section) = self.find_disk_section(current_disk_offset) else {
+                break;
+            };
+
+            // read as many bytes as possible from the section
+            let section_offset = current_disk_offset - section.disk_offset;
+            let remaining_section_len = section.disk_len - section_offset;
+            let to_read = remaining.min(remaining_section_len);
+
+            let out_start = current_disk_offset - disk_offset;
+            let out = &mut out[out_start as usize..][..to_read as usize];
+            self.read_disk_section(section, section_offset, out)?;
+
+            // advance
+            current_disk_offset += to_read;
+            remaining -= to_read;
+        }
+
+        Ok(out.len() as u64 - remaining)
+    }
+}
+
+/// A wrapper around [`Wia`] providing an implementation of [`Read`] and [`Seek`].
+pub struct WiaReader<R> {
+    wia: Wia<R>,
+    position: u64,
+}
+
+impl<R> WiaReader<R> {
+    pub fn new(wia: Wia<R>) -> Self {
+        Self { wia, position: 0 }
+    }
+
+    pub fn inner(&self) -> &Wia<R> {
+        &self.wia
+    }
+
+    pub fn inner_mut(&mut self) -> &mut Wia<R> {
+        &mut self.wia
+    }
+
+    pub fn into_inner(self) -> Wia<R> {
+        self.wia
+    }
+}
+
+impl<R> Read for WiaReader<R>
+where
+    R: Read + Seek,
+{
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = match self.wia.read(self.position, buf) {
+            Ok(read) => read,
+            Err(e) => {
+                return Err(std::io::Error::other(format!(
+                    "wia disk module failed: {e}"
+                )));
+            }
+        };
+
+        self.position += read;
+        Ok(read as usize)
+    }
+}
+
+impl<R> Seek for WiaReader<R>
+where
+    R: Read + Seek,
+{
+    fn seek(&mut self, from: SeekFrom) -> std::io::Result<u64> {
+        match from {
+            SeekFrom::Start(x) => self.position = x,
+            SeekFrom::End(x) => {
+                self.position = self
+                    .wia
+                    .wia_header()
+                    .inner
+                    .disk_len
+                    .saturating_add_signed(x)
+            }
+            SeekFrom::Current(x) => self.position = self.position.saturating_add_signed(x),
+        }
+
+        Ok(self.position)
+    }
+}
+
+impl<R> WiaReader<R>
+where
+    R: Read + Seek,
+{
+    pub fn iso_header(&mut self) -> Result<iso::Header, binrw::Error> {
+        self.seek(SeekFrom::Start(0))?;
+        iso::Header::read_be(self)
+    }
+
+    pub fn bootfile(&mut self) -> Result<dol::Dol, binrw::Error> {
+        let header = self.iso_header()?;
+        self.seek(SeekFrom::Start(header.bootfile_offset as u64))?;
+        dol::Dol::read(self)
+    }
+
+    pub fn bootfile_header(&mut self) -> Result<dol::Header, binrw::Error> {
+        let header = self.iso_header()?;
+        self.seek(SeekFrom::Start(header.bootfile_offset as u64))?;
+        dol::Header::read(self)
+    }
+
+    pub fn apploader(&mut self) -> Result<apploader::Apploader, binrw::Error> {
+        self.seek(SeekFrom::Start(0x2440))?;
+        apploader::Apploader::read(self)
+    }
+
+    pub fn apploader_header(&mut self) -> Result<apploader::Header, binrw::Error> {
+        self.seek(SeekFrom::Start(0x2440))?;
+        apploader::Header::read(self)
+    }
+
+    pub fn filesystem(&mut self) -> Result<iso::filesystem::FileSystem, binrw::Error> {
+        let header = self.iso_header()?;
+        self.seek(SeekFrom::Start(header.filesystem_offset as u64))?;
+        iso::filesystem::FileSystem::read(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+
+    use bzip2::write::BzEncoder;
+    use liblzma::stream::{Filters, LzmaOptions, Stream};
+    use liblzma::write::XzEncoder;
+
+    use super::{Wia, WiaReader};
+    use crate::rvz::Compression;
+
+    const CHUNK_LEN: usize = 0x8000;
+    /// Three chunks, the second of which is zeroed.
+    const DISK_LEN: usize = 3 * CHUNK_LEN;
+    /// Dictionary size of the LZMA encoders.
+    const DICT_SIZE: u32 = 0x1_0000;
+
+    const DISK_SECTIONS_OFFSET: usize = 0x200;
+    const FILE_SECTIONS_OFFSET: usize = 0x400;
+    const DATA_OFFSET: usize = 0x1000;
+
+    fn disk() -> Vec<u8> {
+        let mut disk: Vec<u8> = (0..DISK_LEN)
+            .map(|i| (i % 251) as u8 ^ (i >> 8) as u8)
+            .collect();
+        disk[CHUNK_LEN..2 * CHUNK_LEN].fill(0);
+
+        // the start of the disk is also stored in the disk header, so it must be a valid header
+        disk[..0x80].fill(0);
+        disk[..6].copy_from_slice(b"GTSE01");
+        disk[0x1C..0x20].copy_from_slice(&0xC233_9F3Du32.to_be_bytes());
+        disk[0x20..0x24].copy_from_slice(b"Test");
+
+        disk
+    }
+
+    /// Compresses data with the given method. Returns the compressed data and the compressor data
+    /// (i.e. the encoded filter properties) of the disk header.
+    fn compress(compression: Compression, data: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        match compression {
+            Compression::None => (data.to_vec(), vec![]),
+            Compression::Purge => {
+                // a single segment with all of the data, followed by an unchecked hash
+                let mut purged = vec![];
+                purged.extend(0u32.to_be_bytes());
+                purged.extend((data.len() as u32).to_be_bytes());
+                purged.extend(data);
+                purged.extend([0; 20]);
+                (purged, vec![])
+            }
+            Compression::Bzip2 => {
+                let mut encoder = BzEncoder::new(vec![], bzip2::Compression::best());
+                encoder.write_all(data).unwrap();
+                (encoder.finish().unwrap(), vec![])
+            }
+            Compression::Lzma | Compression::Lzma2 => {
+                let mut options = LzmaOptions::new_preset(6).unwrap();
+                options.dict_size(DICT_SIZE);
+
+                let mut filters = Filters::new();
+                let properties = if compression == Compression::Lzma {
+                    // (pb * 5 + lp) * 9 + lc for the preset, followed by the dictionary size
+                    filters.lzma1(&options);
+                    let mut properties = vec![2 * 5 * 9 + 3];
+                    properties.extend(DICT_SIZE.to_le_bytes());
+                    properties
+                } else {
+                    // a dictionary size of 2 << (8 / 2 + 11)
+                    filters.lzma2(&options);
+                    vec![8]
+                };
+
+                let stream = Stream::new_raw_encoder(&filters).unwrap();
+                let mut encoder = XzEncoder::new_stream(vec![], stream);
+                encoder.write_all(data).unwrap();
+                (encoder.finish().unwrap(), properties)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Builds a WIA of [`disk`] compressed with the given method.
+    fn image(compression: Compression) -> WiaReader<Cursor<Vec<u8>>> {
+        let disk = disk();
+
+        let mut data = vec![];
+        let mut file_sections = vec![];
+        let mut properties = vec![];
+        for chunk in disk.chunks(CHUNK_LEN) {
+            if chunk.iter().all(|&b| b == 0) {
+                file_sections.extend([0; 8]);
+                continue;
+            }
+
+            let compressed;
+            (compressed, properties) = compress(compression, chunk);
+
+            let offset = (DATA_OFFSET + data.len()) as u32;
+            file_sections.extend((offset / 4).to_be_bytes());
+            file_sections.extend((compressed.len() as u32).to_be_bytes());
+
+            data.extend(compressed);
+            data.resize(data.len().next_multiple_of(4), 0);
+        }
+
+        // raw data starts after the part of the disk stored in the disk header
+        let mut disk_sections = vec![];
+        disk_sections.extend(0x80u64.to_be_bytes());
+        disk_sections.extend((DISK_LEN as u64 - 0x80).to_be_bytes());
+        disk_sections.extend(0u32.to_be_bytes());
+        disk_sections.extend(3u32.to_be_bytes());
+
+        let (disk_sections, _) = compress(compression, &disk_sections);
+        let (file_sections, _) = compress(compression, &file_sections);
+
+        let mut disk_header = vec![];
+        disk_header.extend(1u32.to_be_bytes());
+        disk_header.extend((compression as u32).to_be_bytes());
+        disk_header.extend(0u32.to_be_bytes());
+        disk_header.extend((CHUNK_LEN as u32).to_be_bytes());
+        disk_header.extend(&disk[..0x80]);
+        disk_header.extend([0; 4 + 4 + 8 + 20]);
+        disk_header.extend(1u32.to_be_bytes());
+        disk_header.extend((DISK_SECTIONS_OFFSET as u64).to_be_bytes());
+        disk_header.extend((disk_sections.len() as u32).to_be_bytes());
+        disk_header.extend(3u32.to_be_bytes());
+        disk_header.extend((FILE_SECTIONS_OFFSET as u64).to_be_bytes());
+        disk_header.extend((file_sections.len() as u32).to_be_bytes());
+        disk_header.push(properties.len() as u8);
+        properties.resize(7, 0);
+        disk_header.extend(properties);
+
+        let wia_len = DATA_OFFSET + data.len();
+        let mut wia = vec![];
+        wia.extend(b"WIA\x01");
+        wia.extend([1, 0, 0, 0, 1, 0, 0, 0]);
+        wia.extend((disk_header.len() as u32).to_be_bytes());
+        wia.extend([0; 20]);
+        wia.extend((DISK_LEN as u64).to_be_bytes());
+        wia.extend((wia_len as u64).to_be_bytes());
+        wia.extend([0; 20]);
+        wia.extend(disk_header);
+
+        wia.resize(DISK_SECTIONS_OFFSET, 0);
+        wia.extend(disk_sections);
+        wia.resize(FILE_SECTIONS_OFFSET, 0);
+        wia.extend(file_sections);
+        wia.resize(DATA_OFFSET, 0);
+        wia.extend(data);
+
+        WiaReader::new(Wia::new(Cursor::new(wia)).unwrap())
+    }
+
+    fn check(compression: Compression) {
+        let disk = disk();
+        let mut reader = image(compression);
+
+        let mut read = vec![0; DISK_LEN];
+        reader.read_exact(&mut read).unwrap();
+        assert!(read == disk);
+
+        // from the end of the zeroed chunk into the last one
+        let offset = 2 * CHUNK_LEN - 0x10;
+        reader.seek(SeekFrom::Start(offset as u64)).unwrap();
+        let mut buf = [0; 0x20];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, disk[offset..offset + 0x20]);
+
+        let header = reader.iso_header().unwrap();
+        assert_eq!(header.meta.game_code_str().as_deref(), Some("GTSE"));
+    }
+
+    #[test]
+    fn none() {
+        check(Compression::None);
+    }
+
+    #[test]
+    fn purge() {
+        check(Compression::Purge);
+    }
+
+    #[test]
+    fn bzip2() {
+        check(Compression::Bzip2);
+    }
+
+    #[test]
+    fn lzma() {
+        check(Compression::Lzma);
+    }
+
+    #[test]
+    fn lzma2() {
+        check(Compression::Lzma2);
+    }
+}
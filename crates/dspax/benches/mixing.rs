@@ -0,0 +1,130 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+use dspax::pb::{self, Pb};
+use dspax::{AxHle, COEFS_LEN, cpu_mail, dsp_mail};
+use lazuli::Address;
+use lazuli::cores::DspCore;
+use lazuli::system::dspi::Mailbox;
+use lazuli::system::{self, Modules, System};
+
+/// How many voices are mixed by the command list.
+const VOICES: u32 = 64;
+
+const UCODE: u32 = 0x0001_0000;
+const COMMAND_LIST: u32 = 0x0002_0000;
+const SETUP: u32 = 0x0003_0000;
+const OUTPUT: u32 = 0x0004_0000;
+const SURROUND: u32 = 0x0005_0000;
+const PBS: u32 = 0x0006_0000;
+
+/// Stand-in for AX, which compares mails against the prefixes of requests and command lists, and
+/// sends mails with its own prefix.
+const AX: [u16; 3] = [0xBABE, 0xCDD1, 0xDCD1];
+
+/// A frame in the style of what games send every 5 milliseconds: clear the buses, mix every voice
+/// and output the result.
+const LIST: [u16; 14] = [
+    0x0000, 0x0003, 0x0000, // setup from SETUP
+    0x0002, 0x0006, 0x0000, // voices at PBS
+    0x0003, // process
+    0x000E, 0x8000, 0x0005, 0x0000, 0x0004, 0x0000, // output to SURROUND and OUTPUT
+    0x000F, // end
+];
+
+fn system() -> System {
+    System::new(Modules::nop(), system::Config::default())
+}
+
+fn send(sys: &mut System, ax: &mut AxHle, mail: u32) {
+    sys.dsp.cpu_mailbox = Mailbox::from_bits(mail).with_status(true);
+    ax.exec(sys, 1);
+}
+
+fn receive(sys: &mut System, ax: &mut AxHle) -> u32 {
+    ax.exec(sys, 1);
+    let mail = sys.dsp.dsp_mailbox.to_bits();
+    sys.dsp.dsp_mailbox.set_status(false);
+    mail
+}
+
+fn write_words(sys: &mut System, addr: u32, words: &[u16]) {
+    let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_be_bytes()).collect();
+    sys.write_phys_block(Address(addr), &bytes).unwrap();
+}
+
+/// Creates a list of looping 16-bit PCM voices, resampled from 22 kHz and mixed into the main
+/// left and right channels.
+fn voices(sys: &mut System) {
+    for (i, sample) in sys.dsp.aram[..0x10000].chunks_exact_mut(2).enumerate() {
+        sample.copy_from_slice(&((i as i16).wrapping_mul(331)).to_be_bytes());
+    }
+
+    let pb_len = pb::PB_LEN as u32 * 2;
+    for i in 0..VOICES {
+        let next = if i + 1 == VOICES {
+            0
+        } else {
+            PBS + (i + 1) * pb_len
+        };
+
+        let mut pb = Pb::default();
+        pb.set_hilo(pb::NEXT, next);
+        pb[pb::RUNNING] = 1;
+        pb[pb::FORMAT] = 0x0A; // 16-bit PCM
+        pb[pb::LOOPING] = 1;
+        pb.set_hilo(pb::END_ADDR, 0x7FFF);
+        pb.set_hilo(pb::CURRENT_ADDR, i * 0x100);
+        pb.set_hilo(pb::SRC_RATIO, 0xB000);
+        pb[pb::VOLUME] = 0x4000;
+        pb[pb::MIXER_CONTROL] = 0x0003;
+        pb[pb::MIXER] = 0x2000;
+        pb[pb::MIXER + 2] = 0x2000;
+        pb.write(sys, PBS + i * pb_len);
+    }
+}
+
+fn ax() -> (System, AxHle) {
+    let mut sys = system();
+    let mut ax = AxHle::new(&[0; COEFS_LEN], None);
+
+    write_words(&mut sys, UCODE, &AX);
+    ax.reset(&mut sys);
+    assert_eq!(receive(&mut sys, &mut ax), dsp_mail::ROM_READY);
+
+    let parameters = [
+        (cpu_mail::ROM_IRAM_MMEM_ADDR, 0x8000_0000 | UCODE),
+        (cpu_mail::ROM_IRAM_LEN, 2 * AX.len() as u32),
+        (cpu_mail::ROM_START, 0x10),
+    ];
+
+    for (parameter, value) in parameters {
+        send(&mut sys, &mut ax, parameter);
+        send(&mut sys, &mut ax, value);
+    }
+
+    assert_eq!(receive(&mut sys, &mut ax), dsp_mail::INIT);
+
+    write_words(&mut sys, COMMAND_LIST, &LIST);
+    voices(&mut sys);
+
+    (sys, ax)
+}
+
+fn mixing(c: &mut Criterion) {
+    let (mut sys, mut ax) = ax();
+    let mut group = c.benchmark_group("AX HLE");
+    group.throughput(criterion::Throughput::Elements(VOICES as u64));
+
+    let len = LIST.len() as u32;
+    group.bench_function("Command list", |b| {
+        b.iter(|| {
+            send(&mut sys, &mut ax, cpu_mail::COMMAND_LIST | len);
+            send(&mut sys, &mut ax, 0x8000_0000 | COMMAND_LIST);
+            assert_eq!(receive(&mut sys, &mut ax), dsp_mail::YIELD);
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, mixing);
+criterion_main!(benches);
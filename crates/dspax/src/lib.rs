@@ -0,0 +1,770 @@
+//! High level emulation of AX, the audio mixing ucode used by most GameCube games.
+//!
+//! Instead of executing DSP instructions, [`AxHle`] speaks the mail protocols of the DSP ROM and of
+//! AX itself, and performs the mixing described by AX command lists directly. Ucodes booted through
+//! it which don't look like AX are handed over to a fallback core, usually the interpreter.
+mod mem;
+mod mixer;
+pub mod pb;
+mod voice;
+
+use std::any::Any;
+use std::collections::VecDeque;
+use std::fmt::Write;
+
+use lazuli::cores::{DspCore, DspExecuted};
+use lazuli::system::System;
+use lazuli::system::dspi::{self, Mailbox};
+use lazuli::system::scheduler::{INTERRUPT_PRIORITY, SchedulerEventKind};
+
+use crate::mixer::Mixer;
+
+#[rustfmt::skip]
+pub use crate::voice::{COEFS_LEN, Coefs};
+
+/// Mails sent by the DSP.
+pub mod dsp_mail {
+    /// Sent by the ROM once it's ready to boot a ucode.
+    pub const ROM_READY: u32 = 0x8071_FEED;
+    /// Sent by the audio system initialization code of the OS once it's done.
+    pub const INIT_DONE: u32 = 0x8054_4348;
+    /// Sent by AX once it has booted.
+    pub const INIT: u32 = 0xDCD1_0000;
+    /// Acknowledges a resume request.
+    pub const RESUME: u32 = 0xDCD1_0001;
+    /// Sent by AX once it's done with a command list.
+    pub const YIELD: u32 = 0xDCD1_0002;
+}
+
+/// Mails sent by the CPU.
+pub mod cpu_mail {
+    /// Prefix of the parameters of the ucode to boot, sent to the ROM.
+    pub const ROM_PARAMETER: u32 = 0x80F3_0000;
+    /// Address of the ucode in main memory.
+    pub const ROM_IRAM_MMEM_ADDR: u32 = 0x80F3_A001;
+    /// Address of the ucode in IRAM.
+    pub const ROM_IRAM_ADDR: u32 = 0x80F3_C002;
+    /// Length of the ucode.
+    pub const ROM_IRAM_LEN: u32 = 0x80F3_A002;
+    /// Length of the DRAM data of the ucode.
+    pub const ROM_DRAM_LEN: u32 = 0x80F3_B002;
+    /// Entry point of the ucode. Boots it.
+    pub const ROM_START: u32 = 0x80F3_D001;
+
+    /// Requests AX to resume.
+    pub const RESUME: u32 = 0xCDD1_0000;
+    /// Requests AX to boot a new ucode, whose parameters follow.
+    pub const NEW_UCODE: u32 = 0xCDD1_0001;
+    /// Requests AX to reset back into the ROM.
+    pub const RESET: u32 = 0xCDD1_0002;
+    /// Requests AX to continue, sent before a command list.
+    pub const CONTINUE: u32 = 0xCDD1_0003;
+    /// Prefix of a command list, whose lower half is the length in words. The address follows.
+    pub const COMMAND_LIST: u32 = 0xBABE_0000;
+}
+
+/// Number of parameter mails which follow a request for a new ucode.
+const NEW_UCODE_PARAMETERS: u8 = 10;
+
+/// Indices of the parameters of a new ucode. Before booting it, AX saves its DRAM to main memory
+/// as described by the first three.
+mod new_ucode {
+    /// Address of the IRAM image in main memory.
+    pub const IRAM_RAM_ADDR: usize = 3;
+    /// Length of the IRAM image, in bytes.
+    pub const IRAM_LEN: usize = 4;
+    /// Address the IRAM image is loaded at.
+    pub const IRAM_ADDR: usize = 5;
+    /// Entry point.
+    pub const START: usize = 6;
+    /// Address of the DRAM image in main memory.
+    pub const DRAM_RAM_ADDR: usize = 7;
+    /// Length of the DRAM image, in bytes.
+    pub const DRAM_LEN: usize = 8;
+}
+
+/// Length of IRAM, in bytes.
+const IRAM_LEN: u32 = 0x2000;
+
+/// Whether a ucode looks like AX.
+///
+/// This is a heuristic: AX compares mails from the CPU against the prefixes of requests and of
+/// command lists, and sends mails with its own prefix, so its code contains all of them as
+/// immediates. Other ucodes of the SDK speak the same request protocol, but don't receive command
+/// lists.
+fn is_ax(ucode: &[u16]) -> bool {
+    [cpu_mail::RESUME, cpu_mail::COMMAND_LIST, dsp_mail::INIT]
+        .into_iter()
+        .all(|mail| ucode.contains(&((mail >> 16) as u16)))
+}
+
+/// What the DSP is currently running.
+#[derive(Debug, Clone, Copy)]
+enum State {
+    /// The audio system initialization code of the OS.
+    Init,
+    /// The ROM, waiting for the parameters of a ucode to boot.
+    Rom {
+        /// The parameter whose value is expected next, if any.
+        parameter: Option<u32>,
+    },
+    /// AX.
+    Ax {
+        /// Length of the command list whose address is expected next, if any.
+        command_list: Option<u16>,
+    },
+    /// AX, receiving the parameters of a new ucode.
+    Upload {
+        /// How many parameters are still to be received.
+        remaining: u8,
+    },
+    /// A ucode which isn't AX, run by the fallback core until the DSP is reset.
+    Fallback,
+}
+
+/// A DSP core which HLEs the AX ucode.
+pub struct AxHle {
+    state: State,
+    /// Mails waiting for the DSP mailbox to be free, and whether they raise an interrupt.
+    outbox: VecDeque<(u32, bool)>,
+    mixer: Mixer,
+    coefs: Box<Coefs>,
+    old_reset_high: bool,
+    /// Address and length in bytes of the ucode being booted by the ROM.
+    ucode: (u32, u32),
+    /// Mails received by the ROM since it booted.
+    rom_mails: Vec<u32>,
+    /// Core which runs ucodes that aren't AX.
+    fallback: Option<Box<dyn DspCore>>,
+    /// Mails received by the ROM which are still to be sent to the fallback core.
+    replay: VecDeque<u32>,
+    /// Parameters of the new ucode received by AX so far.
+    upload: Vec<u32>,
+}
+
+impl AxHle {
+    /// Creates a new AX HLE core with the resampling coefficients of the DSP ROM.
+    ///
+    /// Ucodes which aren't AX are run by `fallback`, which must boot into the DSP ROM on reset. If
+    /// there's no fallback, every ucode is assumed to be AX.
+    pub fn new(coefs: &[u16; COEFS_LEN], fallback: Option<Box<dyn DspCore>>) -> Self {
+        Self {
+            state: State::Rom { parameter: None },
+            outbox: VecDeque::new(),
+            mixer: Mixer::default(),
+            coefs: Box::new(coefs.map(|c| c as i16)),
+            old_reset_high: true,
+            ucode: (0, 0),
+            rom_mails: Vec::new(),
+            fallback,
+            replay: VecDeque::new(),
+            upload: Vec::new(),
+        }
+    }
+
+    fn send_mail(&mut self, mail: u32, interrupt: bool) {
+        self.outbox.push_back((mail, interrupt));
+    }
+
+    /// Moves the next mail into the DSP mailbox, if it's free.
+    fn deliver_mail(&mut self, sys: &mut System) {
+        if sys.dsp.dsp_mailbox.status() {
+            return;
+        }
+
+        let Some((mail, interrupt)) = self.outbox.pop_front() else {
+            return;
+        };
+
+        tracing::debug!("AX HLE sending mail 0x{mail:08X}");
+        sys.dsp.dsp_mailbox = Mailbox::from_bits(mail).with_status(true);
+
+        if interrupt {
+            sys.dsp.control.set_dsp_interrupt(true);
+            sys.scheduler
+                .schedule_now(INTERRUPT_PRIORITY, SchedulerEventKind::CheckInterrupts);
+        }
+    }
+
+    /// Boots into the ROM or into the initialization code, depending on the reset vector.
+    fn boot(&mut self, sys: &mut System) {
+        self.outbox.clear();
+        self.rom_mails.clear();
+        self.replay.clear();
+        sys.dsp.dsp_mailbox = Mailbox::from_bits(0);
+        sys.dsp.cpu_mailbox = Mailbox::from_bits(0);
+
+        if sys.dsp.control.reset_high() {
+            tracing::debug!("AX HLE booting into the ROM");
+            self.state = State::Rom { parameter: None };
+            self.send_mail(dsp_mail::ROM_READY, true);
+        } else {
+            tracing::debug!("AX HLE booting into the initialization code");
+            self.state = State::Init;
+            self.send_mail(dsp_mail::INIT_DONE, false);
+        }
+    }
+
+    /// Whether the CPU reset the DSP.
+    fn reset_requested(&self, sys: &System) -> bool {
+        sys.dsp.control.reset() || (sys.dsp.control.reset_high() != self.old_reset_high)
+    }
+
+    fn check_reset(&mut self, sys: &mut System) {
+        if self.reset_requested(sys) {
+            std::hint::cold_path();
+            self.boot(sys);
+        }
+
+        sys.dsp.control.set_reset(false);
+        self.old_reset_high = sys.dsp.control.reset_high();
+    }
+
+    /// Completes DSP DMAs started by the CPU. There's no DSP memory to transfer to or from, so
+    /// only the timing is emulated.
+    fn do_dma(&mut self, sys: &mut System) {
+        if sys.dsp.dsp_dma.control.transfer_ongoing() && sys.dsp.dsp_dma_pending.is_none() {
+            std::hint::cold_path();
+
+            let length = sys.dsp.dsp_dma.length;
+            tracing::debug!(
+                "ignoring DSP DMA of {length} bytes from RAM {:08X}",
+                sys.dsp.dsp_dma.ram_base
+            );
+
+            dspi::schedule_dsp_dma_completion(sys, length);
+        }
+    }
+
+    /// Hands the ucode being booted over to the fallback core, by booting it into the ROM and
+    /// sending it the mails the ROM has received so far.
+    fn start_fallback(&mut self, sys: &mut System) {
+        let Some(fallback) = &mut self.fallback else {
+            return;
+        };
+
+        fallback.reset(sys);
+        self.replay = self.rom_mails.drain(..).collect();
+        self.state = State::Fallback;
+    }
+
+    /// Runs the fallback core, feeding it the mails it still has to receive.
+    fn exec_fallback(&mut self, sys: &mut System, cycles: u32) -> DspExecuted {
+        let replaying = !self.replay.is_empty();
+        if !sys.dsp.cpu_mailbox.status()
+            && let Some(mail) = self.replay.pop_front()
+        {
+            sys.dsp.cpu_mailbox = Mailbox::from_bits(mail).with_status(true);
+        }
+
+        let executed = self.fallback.as_mut().unwrap().exec(sys, cycles);
+
+        // the CPU already received the ready mail of the ROM from HLE
+        let mailbox = sys.dsp.dsp_mailbox;
+        if replaying && mailbox.status() && mailbox.to_bits() == dsp_mail::ROM_READY {
+            sys.dsp.dsp_mailbox.set_status(false);
+            sys.dsp.control.set_dsp_interrupt(false);
+        }
+
+        executed
+    }
+
+    fn rom_mail(&mut self, sys: &mut System, mail: u32, parameter: Option<u32>) {
+        self.rom_mails.push(mail);
+        let Some(parameter) = parameter else {
+            if mail & 0xFFFF_0000 == cpu_mail::ROM_PARAMETER {
+                self.state = State::Rom {
+                    parameter: Some(mail),
+                };
+            } else {
+                tracing::warn!("unexpected mail 0x{mail:08X} sent to the DSP ROM");
+            }
+
+            return;
+        };
+
+        // the top bit is the mailbox status
+        let value = mail & 0x7FFF_FFFF;
+        self.state = State::Rom { parameter: None };
+        match parameter {
+            cpu_mail::ROM_IRAM_MMEM_ADDR => {
+                tracing::debug!("ucode at RAM {value:08X}");
+                self.ucode.0 = value;
+            }
+            cpu_mail::ROM_IRAM_ADDR => tracing::debug!("ucode loaded at IRAM {value:04X}"),
+            cpu_mail::ROM_IRAM_LEN => {
+                tracing::debug!("ucode is {value} bytes long");
+                self.ucode.1 = value;
+            }
+            cpu_mail::ROM_DRAM_LEN => tracing::debug!("ucode has {value} bytes of DRAM"),
+            cpu_mail::ROM_START => {
+                let (addr, len) = self.ucode;
+                self.boot_ucode(sys, addr, len, value);
+            }
+            _ => tracing::warn!("unknown DSP ROM parameter 0x{parameter:08X} = 0x{value:08X}"),
+        }
+    }
+
+    /// Boots the ucode of `len` bytes at `addr` in main memory, starting at `start`. If it's not
+    /// AX, it's handed over to the fallback core along with the mails received by the ROM.
+    fn boot_ucode(&mut self, sys: &mut System, addr: u32, len: u32, start: u32) {
+        let mut ucode = vec![0; len.min(IRAM_LEN) as usize / 2];
+        mem::read_words(sys, addr, &mut ucode);
+
+        if !self::is_ax(&ucode) {
+            if self.fallback.is_some() {
+                tracing::warn!("ucode at RAM {addr:08X} is not AX, falling back to LLE");
+                self.start_fallback(sys);
+                return;
+            }
+
+            tracing::warn!("ucode at RAM {addr:08X} is not AX, but there's no fallback");
+        }
+
+        tracing::info!("booting AX ucode at {start:04X}");
+        self.rom_mails.clear();
+        self.state = State::Ax { command_list: None };
+        self.mixer = Mixer::default();
+        self.send_mail(dsp_mail::INIT, true);
+    }
+
+    /// Boots the ucode whose parameters AX received. In case it's not AX, the fallback core boots
+    /// it through the ROM, as if the CPU had sent the parameters to it.
+    fn new_ucode(&mut self, sys: &mut System) {
+        // the top bit is the mailbox status
+        let parameter = |index: usize| self.upload[index] & 0x7FFF_FFFF;
+        let addr = parameter(new_ucode::IRAM_RAM_ADDR);
+        let len = parameter(new_ucode::IRAM_LEN) & 0xFFFF;
+        let iram_addr = parameter(new_ucode::IRAM_ADDR) & 0xFFFF;
+        let start = parameter(new_ucode::START) & 0xFFFF;
+        let dram_addr = parameter(new_ucode::DRAM_RAM_ADDR);
+        let dram_len = parameter(new_ucode::DRAM_LEN) & 0xFFFF;
+
+        tracing::debug!(
+            "AX switching to the ucode at RAM {addr:08X} ({len} bytes), with {dram_len} bytes of \
+             DRAM at RAM {dram_addr:08X}"
+        );
+
+        self.rom_mails = [
+            (cpu_mail::ROM_IRAM_MMEM_ADDR, addr),
+            (cpu_mail::ROM_IRAM_ADDR, iram_addr),
+            (cpu_mail::ROM_IRAM_LEN, len),
+            (cpu_mail::ROM_DRAM_LEN, dram_len),
+            (cpu_mail::ROM_START, start),
+        ]
+        .into_iter()
+        .flat_map(|(parameter, value)| [parameter, 1 << 31 | value])
+        .collect();
+
+        self.boot_ucode(sys, addr, len, start);
+        if dram_len != 0 && matches!(self.state, State::Fallback) {
+            tracing::warn!("the ROM can't load DRAM, the new ucode starts without it");
+        }
+    }
+
+    fn ax_mail(&mut self, sys: &mut System, mail: u32, command_list: Option<u16>) {
+        self.state = State::Ax { command_list: None };
+        if let Some(len) = command_list {
+            self.mixer.run(sys, &self.coefs, mail, len);
+            self.send_mail(dsp_mail::YIELD, true);
+            return;
+        }
+
+        match mail {
+            cpu_mail::RESUME => self.send_mail(dsp_mail::RESUME, true),
+            cpu_mail::NEW_UCODE => {
+                self.upload.clear();
+                self.state = State::Upload {
+                    remaining: NEW_UCODE_PARAMETERS,
+                }
+            }
+            cpu_mail::RESET => {
+                tracing::debug!("AX resetting into the ROM");
+                self.state = State::Rom { parameter: None };
+                self.send_mail(dsp_mail::ROM_READY, true);
+            }
+            cpu_mail::CONTINUE => (),
+            _ if mail & 0xFFFF_0000 == cpu_mail::COMMAND_LIST => {
+                self.state = State::Ax {
+                    command_list: Some(mail as u16),
+                };
+            }
+            _ => tracing::warn!("unknown mail 0x{mail:08X} sent to AX"),
+        }
+    }
+
+    /// Receives the next mail from the CPU, if any.
+    fn receive_mail(&mut self, sys: &mut System) {
+        if !sys.dsp.cpu_mailbox.status() {
+            return;
+        }
+
+        let mail = sys.dsp.cpu_mailbox.to_bits();
+        sys.dsp.cpu_mailbox.set_status(false);
+        tracing::debug!("AX HLE received mail 0x{mail:08X}");
+
+        match self.state {
+            State::Init => tracing::warn!("unexpected mail 0x{mail:08X} sent during init"),
+            State::Rom { parameter } => self.rom_mail(sys, mail, parameter),
+            State::Ax { command_list } => self.ax_mail(sys, mail, command_list),
+            State::Upload { remaining } => {
+                self.upload.push(mail);
+                if remaining > 1 {
+                    self.state = State::Upload {
+                        remaining: remaining - 1,
+                    };
+                } else {
+                    self.new_ucode(sys);
+                }
+            }
+            State::Fallback => unreachable!("the fallback core receives its own mail"),
+        }
+    }
+}
+
+impl DspCore for AxHle {
+    fn exec(&mut self, sys: &mut System, cycles: u32) -> DspExecuted {
+        // resetting the DSP boots back into the ROM, which is HLEd again
+        if matches!(self.state, State::Fallback) {
+            if !self.reset_requested(sys) {
+                return self.exec_fallback(sys, cycles);
+            }
+
+            tracing::debug!("DSP reset, leaving the fallback core");
+        }
+
+        self.check_reset(sys);
+        self.do_dma(sys);
+
+        if !sys.dsp.control.halt() {
+            self.receive_mail(sys);
+            self.deliver_mail(sys);
+        }
+
+        DspExecuted {
+            instructions: 0,
+            cycles,
+            hit_breakpoint: false,
+        }
+    }
+
+    fn step(&mut self, sys: &mut System) {
+        if matches!(self.state, State::Fallback)
+            && !self.reset_requested(sys)
+            && let Some(fallback) = &mut self.fallback
+        {
+            fallback.step(sys);
+        } else {
+            self.exec(sys, 1);
+        }
+    }
+
+    fn reset(&mut self, sys: &mut System) {
+        self.boot(sys);
+        self.old_reset_high = sys.dsp.control.reset_high();
+    }
+
+    fn crash_report(&self, sys: &System, out: &mut String) {
+        _ = writeln!(out, "AX HLE state: {:?}", self.state);
+        _ = writeln!(out, "Outbox (mail, interrupt): {:08X?}", self.outbox);
+
+        if matches!(self.state, State::Fallback)
+            && let Some(fallback) = &self.fallback
+        {
+            fallback.crash_report(sys, out);
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::any::Any;
+    use std::sync::{Arc, Mutex};
+
+    use lazuli::Address;
+    use lazuli::cores::{DspCore, DspExecuted};
+    use lazuli::system::dspi::Mailbox;
+    use lazuli::system::{self, Modules, System};
+
+    use super::{AxHle, COEFS_LEN, cpu_mail, dsp_mail};
+
+    /// Address of the ucode booted by the tests.
+    const UCODE: u32 = 0x0001_0000;
+
+    /// Address of the second ucode booted by the tests.
+    const NEW_UCODE: u32 = 0x0002_0000;
+
+    /// Stand-in for AX, which compares mails against the prefixes of requests and command lists,
+    /// and sends mails with its own prefix.
+    const AX: [u16; 4] = [0x0000, 0xBABE, 0xCDD1, 0xDCD1];
+
+    /// Stand-in for a ucode which speaks the request protocol, but isn't AX.
+    const NOT_AX: [u16; 4] = [0x0000, 0x0000, 0xCDD1, 0xDCD1];
+
+    /// Fallback core which records the mails it receives. Like the ROM, it sends a ready mail once
+    /// it boots.
+    struct Recorder {
+        booted: bool,
+        received: Arc<Mutex<Vec<u32>>>,
+    }
+
+    impl DspCore for Recorder {
+        fn exec(&mut self, sys: &mut System, cycles: u32) -> DspExecuted {
+            if !std::mem::replace(&mut self.booted, true) {
+                sys.dsp.dsp_mailbox = Mailbox::from_bits(dsp_mail::ROM_READY).with_status(true);
+                sys.dsp.control.set_dsp_interrupt(true);
+            }
+
+            if sys.dsp.cpu_mailbox.status() {
+                self.received
+                    .lock()
+                    .unwrap()
+                    .push(sys.dsp.cpu_mailbox.to_bits());
+                sys.dsp.cpu_mailbox.set_status(false);
+            }
+
+            DspExecuted {
+                instructions: cycles,
+                cycles,
+                hit_breakpoint: false,
+            }
+        }
+
+        fn step(&mut self, sys: &mut System) {
+            self.exec(sys, 1);
+        }
+
+        fn reset(&mut self, _: &mut System) {
+            self.booted = false;
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+
+        fn as_any_mut(&mut self) -> &mut dyn Any {
+            self
+        }
+    }
+
+    fn system() -> System {
+        System::new(Modules::nop(), system::Config::default())
+    }
+
+    fn send(sys: &mut System, core: &mut AxHle, mail: u32) {
+        sys.dsp.cpu_mailbox = Mailbox::from_bits(mail).with_status(true);
+        core.exec(sys, 1);
+        assert!(!sys.dsp.cpu_mailbox.status());
+    }
+
+    fn receive(sys: &mut System, core: &mut AxHle) -> u32 {
+        core.exec(sys, 1);
+        assert!(sys.dsp.dsp_mailbox.status());
+
+        let mail = sys.dsp.dsp_mailbox.to_bits();
+        sys.dsp.dsp_mailbox.set_status(false);
+        mail
+    }
+
+    fn write_words(sys: &mut System, addr: u32, words: &[u16]) {
+        let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_be_bytes()).collect();
+        sys.write_phys_block(Address(addr), &bytes).unwrap();
+    }
+
+    /// Boots the given ucode through the ROM, returning the mails sent to it.
+    fn boot_rom(sys: &mut System, core: &mut AxHle, ucode: &[u16]) -> Vec<u32> {
+        core.reset(sys);
+        assert_eq!(receive(sys, core), dsp_mail::ROM_READY);
+        start_ucode(sys, core, ucode)
+    }
+
+    /// Sends the parameters of the given ucode to the ROM, returning the mails sent to it.
+    fn start_ucode(sys: &mut System, core: &mut AxHle, ucode: &[u16]) -> Vec<u32> {
+        write_words(sys, UCODE, ucode);
+        let parameters = [
+            (cpu_mail::ROM_IRAM_MMEM_ADDR, 0x8000_0000 | UCODE),
+            (cpu_mail::ROM_IRAM_ADDR, 0),
+            (cpu_mail::ROM_IRAM_LEN, 2 * ucode.len() as u32),
+            (cpu_mail::ROM_DRAM_LEN, 0),
+            (cpu_mail::ROM_START, 0x10),
+        ];
+
+        let mut mails = Vec::new();
+        for (parameter, value) in parameters {
+            send(sys, core, parameter);
+            send(sys, core, value);
+            mails.extend([parameter, value]);
+        }
+
+        mails
+    }
+
+    fn boot(sys: &mut System, core: &mut AxHle) {
+        boot_rom(sys, core, &AX);
+        assert_eq!(receive(sys, core), dsp_mail::INIT);
+    }
+
+    #[test]
+    fn command_list() {
+        let mut sys = system();
+        let mut core = AxHle::new(&[0; COEFS_LEN], None);
+        boot(&mut sys, &mut core);
+
+        // main left and right start at 0x1000 and 0x2000, left ramping up by 1
+        let mut init = [0; 27];
+        init[..6].copy_from_slice(&[0, 0x1000, 1, 0, 0x2000, 0]);
+        write_words(&mut sys, 0x2000, &init);
+
+        let list = [
+            0x0000, 0x8000, 0x2000, // setup
+            0x000E, 0x4000, 0x8000, 0x3000, 0x8000, 0x4000, // output at half volume
+            0x000F, // end
+        ];
+        write_words(&mut sys, 0x1000, &list);
+
+        let len = list.len() as u32;
+        send(&mut sys, &mut core, cpu_mail::COMMAND_LIST | len);
+        send(&mut sys, &mut core, 0x8000_1000);
+        assert_eq!(receive(&mut sys, &mut core), dsp_mail::YIELD);
+
+        let output = &sys.mem.ram()[0x4000..];
+        assert_eq!(&output[..4], &[0x10, 0x00, 0x08, 0x00]);
+        assert_eq!(&output[4..8], &[0x10, 0x00, 0x08, 0x00]);
+        assert_eq!(&output[8..12], &[0x10, 0x00, 0x08, 0x01]);
+    }
+
+    #[test]
+    fn falls_back_when_not_ax() {
+        let mut sys = system();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let fallback = Recorder {
+            booted: false,
+            received: received.clone(),
+        };
+
+        let mut core = AxHle::new(&[0; COEFS_LEN], Some(Box::new(fallback)));
+        let mails = boot_rom(&mut sys, &mut core, &[0x1234; 16]);
+
+        // the fallback reads a mail at a time
+        for _ in 0..mails.len() {
+            core.exec(&mut sys, 1);
+        }
+
+        let sent: Vec<_> = mails.iter().map(|mail| mail | 1 << 31).collect();
+        assert_eq!(*received.lock().unwrap(), sent);
+
+        // the CPU only receives the ready mail sent by HLE
+        assert!(!sys.dsp.dsp_mailbox.status());
+        assert!(!sys.dsp.control.dsp_interrupt());
+    }
+
+    #[test]
+    fn assumes_ax_without_fallback() {
+        let mut sys = system();
+        let mut core = AxHle::new(&[0; COEFS_LEN], None);
+        boot_rom(&mut sys, &mut core, &[0x1234; 16]);
+        assert_eq!(receive(&mut sys, &mut core), dsp_mail::INIT);
+    }
+
+    /// Makes AX switch to the given ucode, returning the ROM mails which would boot it.
+    fn switch_ucode(sys: &mut System, core: &mut AxHle, ucode: &[u16]) -> Vec<u32> {
+        write_words(sys, NEW_UCODE, ucode);
+        send(sys, core, cpu_mail::NEW_UCODE);
+
+        let len = 2 * ucode.len() as u32;
+        let parameters = [0x0000_1000, 0x2000, 0, NEW_UCODE, len, 0, 0x10, 0, 0, 0];
+        for parameter in parameters {
+            send(sys, core, 1 << 31 | parameter);
+        }
+
+        [
+            (cpu_mail::ROM_IRAM_MMEM_ADDR, NEW_UCODE),
+            (cpu_mail::ROM_IRAM_ADDR, 0),
+            (cpu_mail::ROM_IRAM_LEN, len),
+            (cpu_mail::ROM_DRAM_LEN, 0),
+            (cpu_mail::ROM_START, 0x10),
+        ]
+        .into_iter()
+        .flat_map(|(parameter, value)| [parameter, 1 << 31 | value])
+        .collect()
+    }
+
+    #[test]
+    fn detects_ax() {
+        assert!(super::is_ax(&AX));
+        assert!(!super::is_ax(&NOT_AX));
+        assert!(!super::is_ax(&[0xBABE; 4]));
+    }
+
+    #[test]
+    fn switches_to_ax() {
+        let mut sys = system();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let fallback = Recorder {
+            booted: false,
+            received: received.clone(),
+        };
+
+        let mut core = AxHle::new(&[0; COEFS_LEN], Some(Box::new(fallback)));
+        boot(&mut sys, &mut core);
+
+        switch_ucode(&mut sys, &mut core, &AX);
+        assert_eq!(receive(&mut sys, &mut core), dsp_mail::INIT);
+        assert!(received.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn switches_to_fallback_when_not_ax() {
+        let mut sys = system();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let fallback = Recorder {
+            booted: false,
+            received: received.clone(),
+        };
+
+        let mut core = AxHle::new(&[0; COEFS_LEN], Some(Box::new(fallback)));
+        boot(&mut sys, &mut core);
+
+        // the fallback boots the new ucode through the ROM
+        let mails = switch_ucode(&mut sys, &mut core, &NOT_AX);
+        for _ in 0..mails.len() {
+            core.exec(&mut sys, 1);
+        }
+
+        assert_eq!(*received.lock().unwrap(), mails);
+        assert!(!sys.dsp.dsp_mailbox.status());
+    }
+
+    #[test]
+    fn reset_leaves_fallback() {
+        let mut sys = system();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let fallback = Recorder {
+            booted: false,
+            received: received.clone(),
+        };
+
+        let mut core = AxHle::new(&[0; COEFS_LEN], Some(Box::new(fallback)));
+        let mails = boot_rom(&mut sys, &mut core, &NOT_AX);
+        for _ in 0..mails.len() {
+            core.exec(&mut sys, 1);
+        }
+
+        // once reset, the DSP boots into the HLE ROM again, which can boot AX
+        sys.dsp.control.set_reset(true);
+        received.lock().unwrap().clear();
+        assert_eq!(receive(&mut sys, &mut core), dsp_mail::ROM_READY);
+
+        start_ucode(&mut sys, &mut core, &AX);
+        assert_eq!(receive(&mut sys, &mut core), dsp_mail::INIT);
+        assert!(received.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,50 @@
+//! Accesses to main memory.
+//!
+//! Addresses sent by the CPU might be virtual, so they're masked into physical ones, just like the
+//! DSP DMA does.
+use lazuli::system::System;
+use lazuli::{Address, Primitive};
+
+const ADDRESS_MASK: u32 = 0x03FF_FFFF;
+
+fn read<P: Primitive>(sys: &mut System, addr: u32, out: &mut [P]) {
+    let mut data = vec![0; out.len() * size_of::<P>()];
+    if let Err(fault) = sys.read_phys_block(Address(addr & ADDRESS_MASK), &mut data) {
+        tracing::error!("AX read from RAM failed: {fault}");
+    }
+
+    for (value, bytes) in out.iter_mut().zip(data.chunks_exact(size_of::<P>())) {
+        *value = P::read_be_bytes(bytes);
+    }
+}
+
+fn write<P: Primitive>(sys: &mut System, addr: u32, values: &[P]) {
+    let mut data = vec![0; values.len() * size_of::<P>()];
+    for (value, bytes) in values.iter().zip(data.chunks_exact_mut(size_of::<P>())) {
+        value.write_be_bytes(bytes);
+    }
+
+    if let Err(fault) = sys.write_phys_block(Address(addr & ADDRESS_MASK), &data) {
+        tracing::error!("AX write to RAM failed: {fault}");
+    }
+}
+
+/// Reads big endian words from main memory.
+pub fn read_words(sys: &mut System, addr: u32, out: &mut [u16]) {
+    self::read(sys, addr, out);
+}
+
+/// Writes big endian words to main memory.
+pub fn write_words(sys: &mut System, addr: u32, values: &[u16]) {
+    self::write(sys, addr, values);
+}
+
+/// Reads big endian 32-bit samples from main memory.
+pub fn read_samples(sys: &mut System, addr: u32, out: &mut [i32]) {
+    self::read(sys, addr, out);
+}
+
+/// Writes big endian 32-bit samples to main memory.
+pub fn write_samples(sys: &mut System, addr: u32, values: &[i32]) {
+    self::write(sys, addr, values);
+}
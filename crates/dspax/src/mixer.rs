@@ -0,0 +1,357 @@
+//! The AX mixer and its command lists.
+//!
+//! Every command list mixes a frame of 5 milliseconds at 32 kHz into three buses (main, AUX A and
+//! AUX B), each with a left, right and surround channel. The AUX buses are sent to the CPU for
+//! effects processing and the results are mixed back into the main bus, which is then output.
+use lazuli::system::System;
+
+use crate::mem;
+use crate::pb::{self, Pb};
+use crate::voice::{self, Coefs};
+
+/// Samples mixed per millisecond. AX always mixes at 32 kHz.
+pub const SAMPLES_PER_MS: usize = 32;
+
+/// Milliseconds mixed by each command list.
+pub const FRAME_MS: usize = 5;
+
+/// Samples mixed by each command list.
+pub const FRAME_LEN: usize = SAMPLES_PER_MS * FRAME_MS;
+
+/// The left, right and surround channels of a bus.
+pub type Bus = [[i32; FRAME_LEN]; 3];
+
+const MAIN: usize = 0;
+const AUX_A: usize = 1;
+const AUX_B: usize = 2;
+
+const LEFT: usize = 0;
+const RIGHT: usize = 1;
+const SURROUND: usize = 2;
+
+/// Maximum length of a command list, in words.
+const MAX_COMMAND_LIST_LEN: u16 = 0x200;
+
+/// Maximum number of PBs processed in a single frame, to avoid hanging on cyclic lists.
+const MAX_VOICES: usize = 256;
+
+mod cmd {
+    pub const SETUP: u16 = 0x00;
+    pub const DOWNLOAD_AND_MIX: u16 = 0x01;
+    pub const PB_ADDR: u16 = 0x02;
+    pub const PROCESS: u16 = 0x03;
+    pub const MIX_AUX_A: u16 = 0x04;
+    pub const MIX_AUX_B: u16 = 0x05;
+    pub const UPLOAD_LRS: u16 = 0x06;
+    pub const SET_LR: u16 = 0x07;
+    pub const UNKNOWN_08: u16 = 0x08;
+    pub const MIX_AUX_B_NO_WRITE: u16 = 0x09;
+    pub const COMPRESSOR_TABLE: u16 = 0x0A;
+    pub const UNKNOWN_0B: u16 = 0x0B;
+    pub const UNKNOWN_0C: u16 = 0x0C;
+    pub const MORE: u16 = 0x0D;
+    pub const OUTPUT: u16 = 0x0E;
+    pub const END: u16 = 0x0F;
+}
+
+/// A command list read from main memory.
+struct CommandList {
+    words: Vec<u16>,
+    position: usize,
+}
+
+impl CommandList {
+    fn read(sys: &mut System, addr: u32, len: u16) -> Self {
+        let len = if len > MAX_COMMAND_LIST_LEN {
+            tracing::warn!("AX command list of {len} words truncated to {MAX_COMMAND_LIST_LEN}");
+            MAX_COMMAND_LIST_LEN
+        } else {
+            len
+        };
+
+        let mut words = vec![0; len as usize];
+        mem::read_words(sys, addr, &mut words);
+
+        Self { words, position: 0 }
+    }
+
+    fn next_word(&mut self) -> Option<u16> {
+        let word = self.words.get(self.position).copied();
+        self.position += 1;
+        word
+    }
+
+    /// Reads a word argument. Missing arguments are zero.
+    fn word(&mut self) -> u16 {
+        self.next_word().unwrap_or_default()
+    }
+
+    /// Reads an address argument, stored as a high word followed by a low word.
+    fn addr(&mut self) -> u32 {
+        ((self.word() as u32) << 16) | self.word() as u32
+    }
+
+    fn skip(&mut self, len: usize) {
+        self.position += len;
+    }
+}
+
+/// State of the AX mixer.
+pub struct Mixer {
+    buses: [Bus; 3],
+}
+
+impl Default for Mixer {
+    fn default() -> Self {
+        Self {
+            buses: [[[0; FRAME_LEN]; 3]; 3],
+        }
+    }
+}
+
+impl Mixer {
+    /// Initializes every channel with a ramp described at the given address.
+    fn setup(&mut self, sys: &mut System, addr: u32) {
+        let mut init = [0; 27];
+        mem::read_words(sys, addr, &mut init);
+
+        let channels = self.buses.iter_mut().flatten();
+        for (channel, init) in channels.zip(init.chunks_exact(3)) {
+            let mut value = ((init[0] as i32) << 16) | init[1] as i32;
+            let delta = init[2] as i16 as i32;
+
+            for sample in channel {
+                *sample = value;
+                value = value.wrapping_add(delta);
+            }
+        }
+    }
+
+    /// Mixes samples from main memory into every bus, with a volume for each bus.
+    fn download_and_mix(&mut self, sys: &mut System, addr: u32, volumes: [u16; 3]) {
+        let mut samples = [0; 3 * FRAME_LEN];
+        mem::read_samples(sys, addr, &mut samples);
+
+        for (bus, volume) in self.buses.iter_mut().zip(volumes) {
+            for (channel, samples) in bus.iter_mut().zip(samples.chunks_exact(FRAME_LEN)) {
+                for (out, &sample) in channel.iter_mut().zip(samples) {
+                    *out = out.wrapping_add(((sample as i64 * volume as i64) >> 15) as i32);
+                }
+            }
+        }
+    }
+
+    /// Processes every voice in the list of PBs starting at the given address.
+    fn process(&mut self, sys: &mut System, coefs: &Coefs, mut addr: u32) {
+        let mut voices = 0;
+        while addr != 0 {
+            if voices == MAX_VOICES {
+                tracing::warn!("AX PB list is longer than {MAX_VOICES} voices, stopping");
+                break;
+            }
+
+            let mut pb = Pb::read(sys, addr);
+
+            // updates are sent as (offset, value) pairs, grouped by millisecond
+            let counts: [u16; FRAME_MS] = std::array::from_fn(|i| pb[pb::UPDATES_COUNT + i]);
+            let total = counts.iter().map(|&c| c as usize).sum::<usize>();
+            let mut updates = vec![0; 2 * total];
+            if total > 0 {
+                mem::read_words(sys, pb.hilo(pb::UPDATES_DATA), &mut updates);
+            }
+
+            let mut updates = updates.chunks_exact(2);
+            for (ms, count) in counts.into_iter().enumerate() {
+                for update in updates.by_ref().take(count as usize) {
+                    pb.update(update[0], update[1]);
+                }
+
+                voice::process(
+                    &mut pb,
+                    &sys.dsp.aram[..],
+                    coefs,
+                    &mut self.buses,
+                    ms * SAMPLES_PER_MS,
+                );
+            }
+
+            pb.write(sys, addr);
+            addr = pb.hilo(pb::NEXT);
+            voices += 1;
+        }
+    }
+
+    /// Sends an AUX bus to the CPU, if an address is given, and mixes the processed samples it
+    /// sends back into the main bus.
+    fn mix_aux(&mut self, sys: &mut System, aux: usize, write_addr: Option<u32>, read_addr: u32) {
+        if let Some(addr) = write_addr {
+            mem::write_samples(sys, addr, self.buses[aux].as_flattened());
+        }
+
+        let mut samples = [0; 3 * FRAME_LEN];
+        mem::read_samples(sys, read_addr, &mut samples);
+
+        let main = self.buses[MAIN].as_flattened_mut();
+        for (out, sample) in main.iter_mut().zip(samples) {
+            *out = out.wrapping_add(sample);
+        }
+    }
+
+    /// Sets the left and right channels of the main bus to samples from main memory, clearing the
+    /// surround channel.
+    fn set_lr(&mut self, sys: &mut System, addr: u32) {
+        let main = &mut self.buses[MAIN];
+        mem::read_samples(sys, addr, &mut main[LEFT]);
+        main[RIGHT] = main[LEFT];
+        main[SURROUND] = [0; FRAME_LEN];
+    }
+
+    /// Writes the final output: the surround channel as 32-bit samples and the left and right
+    /// channels as interleaved 16-bit samples (right first), scaled by the given volume.
+    fn output(&self, sys: &mut System, surround_addr: u32, lr_addr: u32, volume: u16) {
+        let main = &self.buses[MAIN];
+        mem::write_samples(sys, surround_addr, &main[SURROUND]);
+
+        let scale = |sample: i32| {
+            ((sample as i64 * volume as i64) >> 15).clamp(i16::MIN as i64, i16::MAX as i64) as u16
+        };
+
+        let mut output = [0; 2 * FRAME_LEN];
+        for (i, frame) in output.chunks_exact_mut(2).enumerate() {
+            frame[0] = scale(main[RIGHT][i]);
+            frame[1] = scale(main[LEFT][i]);
+        }
+
+        mem::write_words(sys, lr_addr, &output);
+    }
+
+    /// Runs the command list at the given address.
+    pub fn run(&mut self, sys: &mut System, coefs: &Coefs, addr: u32, len: u16) {
+        let mut list = CommandList::read(sys, addr, len);
+        let mut pb_addr = 0;
+
+        loop {
+            let Some(command) = list.next_word() else {
+                tracing::warn!("AX command list ended without an end command");
+                break;
+            };
+
+            match command {
+                cmd::SETUP => {
+                    let addr = list.addr();
+                    self.setup(sys, addr);
+                }
+                cmd::DOWNLOAD_AND_MIX => {
+                    let addr = list.addr();
+                    let volumes = [list.word(), list.word(), list.word()];
+                    self.download_and_mix(sys, addr, volumes);
+                }
+                cmd::PB_ADDR => pb_addr = list.addr(),
+                cmd::PROCESS => self.process(sys, coefs, pb_addr),
+                cmd::MIX_AUX_A | cmd::MIX_AUX_B => {
+                    let aux = if command == cmd::MIX_AUX_A {
+                        AUX_A
+                    } else {
+                        AUX_B
+                    };
+
+                    let write_addr = list.addr();
+                    let read_addr = list.addr();
+                    self.mix_aux(sys, aux, Some(write_addr), read_addr);
+                }
+                cmd::UPLOAD_LRS => {
+                    let addr = list.addr();
+                    mem::write_samples(sys, addr, self.buses[MAIN].as_flattened());
+                }
+                cmd::SET_LR => {
+                    let addr = list.addr();
+                    self.set_lr(sys, addr);
+                }
+                cmd::UNKNOWN_08 => list.skip(10),
+                cmd::MIX_AUX_B_NO_WRITE => {
+                    let read_addr = list.addr();
+                    self.mix_aux(sys, AUX_B, None, read_addr);
+                }
+                cmd::COMPRESSOR_TABLE => list.skip(2),
+                cmd::UNKNOWN_0B | cmd::UNKNOWN_0C => (),
+                cmd::MORE => {
+                    let addr = list.addr();
+                    let len = list.word();
+                    list = CommandList::read(sys, addr, len);
+                }
+                cmd::OUTPUT => {
+                    let volume = list.word();
+                    let surround_addr = list.addr();
+                    let lr_addr = list.addr();
+                    self.output(sys, surround_addr, lr_addr, volume);
+                }
+                cmd::END => break,
+                _ => {
+                    tracing::warn!("unknown AX command 0x{command:04X}, stopping command list");
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lazuli::system::{self, Modules, System};
+
+    use super::{AUX_A, AUX_B, FRAME_LEN, LEFT, MAIN, Mixer, RIGHT, SURROUND};
+    use crate::pb::{self, Pb};
+    use crate::voice::{self, COEFS_LEN};
+
+    const PB: u32 = 0x1000;
+
+    #[test]
+    fn voice_routing() {
+        let mut sys = System::new(Modules::nop(), system::Config::default());
+        for sample in sys.dsp.aram[..2 * FRAME_LEN].chunks_exact_mut(2) {
+            sample.copy_from_slice(&0x4000i16.to_be_bytes());
+        }
+
+        // a voice playing to main left at full volume and to AUX A right at half volume, without
+        // sample rate conversion
+        let mut pb = Pb::default();
+        pb[pb::FORMAT] = voice::FORMAT_PCM16;
+        pb[pb::RUNNING] = 1;
+        pb[pb::SRC_TYPE] = 2;
+        pb.set_hilo(pb::SRC_RATIO, 0x10000);
+        pb.set_hilo(pb::END_ADDR, 0xFFFF);
+        pb[pb::VOLUME] = 0x8000;
+        pb[pb::MIXER_CONTROL] = 0x0001 | 0x0020;
+        pb[pb::MIXER] = 0x8000;
+        pb[pb::MIXER + 6] = 0x4000;
+        pb.write(&mut sys, PB);
+
+        let mut mixer = Mixer::default();
+        mixer.process(&mut sys, &[0; COEFS_LEN], PB);
+
+        // the first sample is the initial history of the resampler
+        let main = &mixer.buses[MAIN];
+        assert_eq!(main[LEFT][0], 0);
+        assert!(main[LEFT][1..].iter().all(|&s| s == 0x4000));
+        assert!(mixer.buses[AUX_A][RIGHT][1..].iter().all(|&s| s == 0x2000));
+
+        let silent = [
+            (MAIN, RIGHT),
+            (MAIN, SURROUND),
+            (AUX_A, LEFT),
+            (AUX_A, SURROUND),
+            (AUX_B, LEFT),
+            (AUX_B, RIGHT),
+            (AUX_B, SURROUND),
+        ];
+
+        for (bus, channel) in silent {
+            assert!(mixer.buses[bus][channel].iter().all(|&s| s == 0));
+        }
+
+        // the state of the voice is written back
+        let pb = Pb::read(&mut sys, PB);
+        assert_eq!(pb.hilo(pb::CURRENT_ADDR), FRAME_LEN as u32);
+        assert_eq!(pb[pb::DPOP], 0x4000);
+    }
+}
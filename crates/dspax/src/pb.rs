@@ -0,0 +1,96 @@
+//! Parameter blocks (PBs), which describe the state of a voice.
+//!
+//! PBs live in main memory as arrays of big endian words, and are also patched by the CPU as
+//! (offset, value) word pairs through updates, so they're kept as words here as well.
+use std::ops::{Index, IndexMut};
+
+use lazuli::system::System;
+
+use crate::mem;
+
+pub const NEXT: usize = 0;
+pub const SRC_TYPE: usize = 4;
+pub const COEF_SELECT: usize = 5;
+pub const MIXER_CONTROL: usize = 6;
+pub const RUNNING: usize = 7;
+pub const IS_STREAM: usize = 8;
+pub const MIXER: usize = 9;
+pub const UPDATES_COUNT: usize = 34;
+pub const UPDATES_DATA: usize = 39;
+pub const DPOP: usize = 41;
+pub const VOLUME: usize = 50;
+pub const VOLUME_DELTA: usize = 51;
+pub const LOOPING: usize = 55;
+pub const FORMAT: usize = 56;
+pub const LOOP_ADDR: usize = 57;
+pub const END_ADDR: usize = 59;
+pub const CURRENT_ADDR: usize = 61;
+pub const ADPCM_COEFS: usize = 63;
+pub const PRED_SCALE: usize = 80;
+pub const YN1: usize = 81;
+pub const YN2: usize = 82;
+pub const SRC_RATIO: usize = 83;
+pub const SRC_FRAC: usize = 85;
+pub const SRC_HISTORY: usize = 86;
+pub const LOOP_PRED_SCALE: usize = 90;
+pub const LOOP_YN1: usize = 91;
+pub const LOOP_YN2: usize = 92;
+
+/// Length of a PB, in words.
+pub const PB_LEN: usize = 93;
+
+/// A parameter block.
+pub struct Pb([u16; PB_LEN]);
+
+impl Default for Pb {
+    fn default() -> Self {
+        Self([0; PB_LEN])
+    }
+}
+
+impl Pb {
+    /// Reads the PB at the given address of main memory.
+    pub fn read(sys: &mut System, addr: u32) -> Self {
+        let mut words = [0; PB_LEN];
+        mem::read_words(sys, addr, &mut words);
+        Self(words)
+    }
+
+    /// Writes the PB back to the given address of main memory.
+    pub fn write(&self, sys: &mut System, addr: u32) {
+        mem::write_words(sys, addr, &self.0);
+    }
+
+    /// Reads a 32-bit value stored as a high word followed by a low word.
+    pub fn hilo(&self, offset: usize) -> u32 {
+        ((self.0[offset] as u32) << 16) | self.0[offset + 1] as u32
+    }
+
+    /// Writes a 32-bit value as a high word followed by a low word.
+    pub fn set_hilo(&mut self, offset: usize, value: u32) {
+        self.0[offset] = (value >> 16) as u16;
+        self.0[offset + 1] = value as u16;
+    }
+
+    /// Applies an update sent by the CPU, ignoring those outside of the PB.
+    pub fn update(&mut self, offset: u16, value: u16) {
+        match self.0.get_mut(offset as usize) {
+            Some(word) => *word = value,
+            None => tracing::warn!("ignoring update of PB word {offset} to 0x{value:04X}"),
+        }
+    }
+}
+
+impl Index<usize> for Pb {
+    type Output = u16;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.0[index]
+    }
+}
+
+impl IndexMut<usize> for Pb {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        &mut self.0[index]
+    }
+}
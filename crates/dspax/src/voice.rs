@@ -0,0 +1,402 @@
+//! Voice processing: sample streaming out of ARAM, sample rate conversion and mixing.
+use crate::mixer::{Bus, SAMPLES_PER_MS};
+use crate::pb::{self, Pb};
+
+/// Number of resampling coefficients in the DSP ROM.
+pub const COEFS_LEN: usize = 0x800;
+
+/// Resampling coefficients of the polyphase filter: 4 tables of 128 phases with 4 taps each.
+pub type Coefs = [i16; COEFS_LEN];
+
+const FORMAT_ADPCM: u16 = 0x00;
+pub const FORMAT_PCM16: u16 = 0x0A;
+const FORMAT_PCM8: u16 = 0x19;
+
+const SRC_POLYPHASE: u16 = 0;
+const SRC_LINEAR: u16 = 1;
+
+/// Masks of the enable and ramp bits in the mixer control of each bus and channel.
+const MIXER_CONTROL: [[(u16, u16); 3]; 3] = [
+    [(0x0001, 0x0008), (0x0002, 0x0008), (0x0004, 0x0008)],
+    [(0x0010, 0x0040), (0x0020, 0x0040), (0x0080, 0x0100)],
+    [(0x0200, 0x0800), (0x0400, 0x0800), (0x1000, 0x2000)],
+];
+
+/// Offsets of the volume of each bus and channel in the PB. The volume delta follows it.
+const MIXER_VOLUME: [[usize; 3]; 3] = [
+    [pb::MIXER, pb::MIXER + 2, pb::MIXER + 14],
+    [pb::MIXER + 4, pb::MIXER + 6, pb::MIXER + 16],
+    [pb::MIXER + 8, pb::MIXER + 10, pb::MIXER + 12],
+];
+
+/// Offsets of the last sample mixed into each bus and channel in the PB.
+const DPOP: [[usize; 3]; 3] = [
+    [pb::DPOP, pb::DPOP + 3, pb::DPOP + 6],
+    [pb::DPOP + 1, pb::DPOP + 4, pb::DPOP + 7],
+    [pb::DPOP + 2, pb::DPOP + 5, pb::DPOP + 8],
+];
+
+/// The accelerator, which streams and decodes the samples of a voice out of ARAM.
+///
+/// Addresses are in units of the sample format: nibbles for ADPCM, bytes for 8-bit PCM and words
+/// for 16-bit PCM.
+struct Accelerator {
+    format: u16,
+    looping: bool,
+    stream: bool,
+    loop_addr: u32,
+    end_addr: u32,
+    current_addr: u32,
+    pred_scale: u16,
+    yn1: i16,
+    yn2: i16,
+    stopped: bool,
+}
+
+impl Accelerator {
+    fn load(pb: &Pb) -> Self {
+        Self {
+            format: pb[pb::FORMAT],
+            looping: pb[pb::LOOPING] != 0,
+            stream: pb[pb::IS_STREAM] != 0,
+            loop_addr: pb.hilo(pb::LOOP_ADDR),
+            end_addr: pb.hilo(pb::END_ADDR),
+            current_addr: pb.hilo(pb::CURRENT_ADDR),
+            pred_scale: pb[pb::PRED_SCALE],
+            yn1: pb[pb::YN1] as i16,
+            yn2: pb[pb::YN2] as i16,
+            stopped: false,
+        }
+    }
+
+    fn store(&self, pb: &mut Pb) {
+        pb.set_hilo(pb::CURRENT_ADDR, self.current_addr);
+        pb[pb::PRED_SCALE] = self.pred_scale;
+        pb[pb::YN1] = self.yn1 as u16;
+        pb[pb::YN2] = self.yn2 as u16;
+    }
+
+    fn aram_byte(aram: &[u8], addr: u32) -> u8 {
+        aram.get(addr as usize).copied().unwrap_or(0)
+    }
+
+    fn decode(&mut self, pb: &Pb, aram: &[u8]) -> i16 {
+        match self.format {
+            FORMAT_ADPCM => {
+                // every frame of 16 nibbles starts with a header byte
+                if self.current_addr.is_multiple_of(16) {
+                    self.pred_scale = Self::aram_byte(aram, self.current_addr / 2) as u16;
+                    self.current_addr += 2;
+                }
+
+                let byte = Self::aram_byte(aram, self.current_addr / 2);
+                let nibble = if self.current_addr.is_multiple_of(2) {
+                    byte >> 4
+                } else {
+                    byte & 0xF
+                };
+
+                let scale = 1 << (self.pred_scale & 0xF);
+                let coef_index = pb::ADPCM_COEFS + 2 * ((self.pred_scale >> 4) & 0x7) as usize;
+                let coef1 = pb[coef_index] as i16 as i32;
+                let coef2 = pb[coef_index + 1] as i16 as i32;
+
+                let delta = scale * (((nibble as i8) << 4) >> 4) as i32;
+                let prediction = (0x400 + coef1 * self.yn1 as i32 + coef2 * self.yn2 as i32) >> 11;
+                let sample = (delta + prediction).clamp(-0x7FFF, 0x7FFF) as i16;
+
+                self.yn2 = self.yn1;
+                self.yn1 = sample;
+                sample
+            }
+            FORMAT_PCM16 => {
+                let high = Self::aram_byte(aram, 2 * self.current_addr);
+                let low = Self::aram_byte(aram, 2 * self.current_addr + 1);
+                let sample = i16::from_be_bytes([high, low]);
+
+                self.yn2 = self.yn1;
+                self.yn1 = sample;
+                sample
+            }
+            FORMAT_PCM8 => {
+                let sample = (Self::aram_byte(aram, self.current_addr) as i16) << 8;
+
+                self.yn2 = self.yn1;
+                self.yn1 = sample;
+                sample
+            }
+            format => {
+                tracing::warn!("unknown AX sample format 0x{format:02X}");
+                self.stopped = true;
+                0
+            }
+        }
+    }
+
+    /// Reads the next sample, looping or stopping the voice once the end is reached.
+    fn read(&mut self, pb: &mut Pb, aram: &[u8]) -> i16 {
+        if self.stopped {
+            return 0;
+        }
+
+        let sample = self.decode(pb, aram);
+        if self.current_addr != self.end_addr {
+            self.current_addr += 1;
+            return sample;
+        }
+
+        if self.looping {
+            self.current_addr = self.loop_addr;
+            self.pred_scale = pb[pb::LOOP_PRED_SCALE];
+
+            // streams are refilled while playing, so the history at the loop point is whatever was
+            // last decoded
+            if self.stream {
+                pb[pb::LOOP_YN1] = self.yn1 as u16;
+                pb[pb::LOOP_YN2] = self.yn2 as u16;
+            } else {
+                self.yn1 = pb[pb::LOOP_YN1] as i16;
+                self.yn2 = pb[pb::LOOP_YN2] as i16;
+            }
+        } else {
+            self.stopped = true;
+            pb[pb::RUNNING] = 0;
+        }
+
+        sample
+    }
+}
+
+/// Reads a millisecond worth of samples of the voice, converting them to the output rate.
+fn resample(pb: &mut Pb, aram: &[u8], coefs: &Coefs, out: &mut [i16; SAMPLES_PER_MS]) {
+    let mut accel = Accelerator::load(pb);
+    let ratio = pb.hilo(pb::SRC_RATIO) as u64;
+    let mut position = pb[pb::SRC_FRAC] as u64;
+    let mut history: [i16; 4] = std::array::from_fn(|i| pb[pb::SRC_HISTORY + i] as i16);
+
+    for sample in out {
+        *sample = match pb[pb::SRC_TYPE] {
+            SRC_POLYPHASE => {
+                let table = (pb[pb::COEF_SELECT] & 0x3) as usize * 0x200;
+                let phase = (position >> 9) as usize & 0x7F;
+                let taps = &coefs[table + 4 * phase..][..4];
+
+                let sum = taps
+                    .iter()
+                    .zip(history)
+                    .map(|(&tap, sample)| tap as i32 * sample as i32)
+                    .sum::<i32>();
+
+                (sum >> 15).clamp(-0x8000, 0x7FFF) as i16
+            }
+            SRC_LINEAR => {
+                let (previous, current) = (history[2] as i64, history[3] as i64);
+                (previous + (((current - previous) * position as i64) >> 16)) as i16
+            }
+            _ => history[3],
+        };
+
+        position += ratio;
+        for _ in 0..position >> 16 {
+            history.rotate_left(1);
+            history[3] = accel.read(pb, aram);
+        }
+
+        position &= 0xFFFF;
+    }
+
+    accel.store(pb);
+    pb[pb::SRC_FRAC] = position as u16;
+    for (i, sample) in history.into_iter().enumerate() {
+        pb[pb::SRC_HISTORY + i] = sample as u16;
+    }
+}
+
+/// Mixes samples into a buffer with the given volume of the PB, optionally ramping it.
+fn mix(pb: &mut Pb, out: &mut [i32], samples: &[i16], volume: usize, dpop: usize, ramp: bool) {
+    let delta = if ramp { pb[volume + 1] } else { 0 };
+    for (out, &sample) in out.iter_mut().zip(samples) {
+        let sample = ((sample as i32 * pb[volume] as i32) >> 15).clamp(-0x7FFF, 0x7FFF);
+        *out = out.wrapping_add(sample);
+
+        pb[volume] = pb[volume].wrapping_add(delta);
+        pb[dpop] = sample as u16;
+    }
+}
+
+/// Processes a millisecond of the voice, mixing it into the buses at the given sample offset.
+pub fn process(pb: &mut Pb, aram: &[u8], coefs: &Coefs, buses: &mut [Bus; 3], offset: usize) {
+    if pb[pb::RUNNING] != 1 {
+        return;
+    }
+
+    let mut samples = [0; SAMPLES_PER_MS];
+    self::resample(pb, aram, coefs, &mut samples);
+
+    // volume envelope
+    for sample in &mut samples {
+        *sample = ((*sample as i32 * pb[pb::VOLUME] as i32) >> 15).clamp(-0x7FFF, 0x7FFF) as i16;
+        pb[pb::VOLUME] = pb[pb::VOLUME].wrapping_add(pb[pb::VOLUME_DELTA]);
+    }
+
+    let control = pb[pb::MIXER_CONTROL];
+    for (bus_index, bus) in buses.iter_mut().enumerate() {
+        for (channel, buffer) in bus.iter_mut().enumerate() {
+            let (enable, ramp) = MIXER_CONTROL[bus_index][channel];
+            if control & enable == 0 {
+                continue;
+            }
+
+            self::mix(
+                pb,
+                &mut buffer[offset..][..SAMPLES_PER_MS],
+                &samples,
+                MIXER_VOLUME[bus_index][channel],
+                DPOP[bus_index][channel],
+                control & ramp != 0,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        Accelerator, COEFS_LEN, Coefs, FORMAT_ADPCM, FORMAT_PCM16, SRC_LINEAR, SRC_POLYPHASE,
+    };
+    use crate::mixer::SAMPLES_PER_MS;
+    use crate::pb::{self, Pb};
+
+    /// A voice playing a 16-bit PCM ramp of 64 samples, going up by 100.
+    fn ramp(src_type: u16, ratio: u32) -> (Pb, Vec<u8>) {
+        let aram = (0..64i16).flat_map(|i| (100 * i).to_be_bytes()).collect();
+
+        let mut pb = Pb::default();
+        pb[pb::FORMAT] = FORMAT_PCM16;
+        pb[pb::RUNNING] = 1;
+        pb[pb::SRC_TYPE] = src_type;
+        pb.set_hilo(pb::SRC_RATIO, ratio);
+        pb.set_hilo(pb::END_ADDR, 63);
+
+        (pb, aram)
+    }
+
+    fn resample(pb: &mut Pb, aram: &[u8], coefs: &Coefs) -> [i16; SAMPLES_PER_MS] {
+        let mut out = [0; SAMPLES_PER_MS];
+        super::resample(pb, aram, coefs, &mut out);
+        out
+    }
+
+    #[test]
+    fn pcm16_loop() {
+        let aram = [0x12, 0x34, 0x80, 0x00, 0x7F, 0xFF];
+        let mut pb = Pb::default();
+        pb[pb::FORMAT] = FORMAT_PCM16;
+        pb[pb::RUNNING] = 1;
+        pb[pb::LOOPING] = 1;
+        pb.set_hilo(pb::LOOP_ADDR, 1);
+        pb.set_hilo(pb::END_ADDR, 2);
+
+        let mut accel = Accelerator::load(&pb);
+        let samples: Vec<_> = (0..5).map(|_| accel.read(&mut pb, &aram)).collect();
+        assert_eq!(samples, [0x1234, -0x8000, 0x7FFF, -0x8000, 0x7FFF]);
+        assert_eq!(pb[pb::RUNNING], 1);
+    }
+
+    #[test]
+    fn adpcm_one_shot() {
+        // header with scale 2^1 and the first coefficient pair, followed by 14 nibbles
+        let aram = [0x01, 0x12, 0x7F, 0x80, 0x00, 0x00, 0x00, 0x00];
+        let mut pb = Pb::default();
+        pb[pb::FORMAT] = FORMAT_ADPCM;
+        pb[pb::RUNNING] = 1;
+        pb[pb::ADPCM_COEFS] = 0x0800;
+        pb.set_hilo(pb::END_ADDR, 5);
+
+        let mut accel = Accelerator::load(&pb);
+        let samples: Vec<_> = (0..6).map(|_| accel.read(&mut pb, &aram)).collect();
+
+        // each sample is the previous one (coefficient of 1.0) plus the scaled nibble
+        assert_eq!(samples, [2, 6, 20, 18, 0, 0]);
+        assert_eq!(pb[pb::RUNNING], 0);
+        assert_eq!(accel.pred_scale, 0x01);
+    }
+
+    #[test]
+    fn src_linear() {
+        let (mut pb, aram) = ramp(SRC_LINEAR, 0xC000);
+        let out = resample(&mut pb, &aram, &[0; COEFS_LEN]);
+
+        // 3/4 of the speed, interpolating between the last two samples read
+        assert_eq!(out[..8], [0, 0, 0, 25, 100, 175, 250, 325]);
+        assert_eq!(out[31], 2125);
+        assert_eq!(pb.hilo(pb::CURRENT_ADDR), 24);
+        assert_eq!(pb[pb::SRC_FRAC], 0);
+
+        let history: Vec<_> = (0..4).map(|i| pb[pb::SRC_HISTORY + i]).collect();
+        assert_eq!(history, [2000, 2100, 2200, 2300]);
+    }
+
+    #[test]
+    fn src_linear_resumes() {
+        let (mut pb, aram) = ramp(SRC_LINEAR, 0xC000);
+        let first = resample(&mut pb, &aram, &[0; COEFS_LEN]);
+        let second = resample(&mut pb, &aram, &[0; COEFS_LEN]);
+
+        // the second millisecond continues where the first one stopped
+        assert_eq!(first[31] + 75, second[0]);
+        assert_eq!(second[31], 4525);
+    }
+
+    #[test]
+    fn src_polyphase() {
+        let mut coefs = [0; COEFS_LEN];
+        // the first table would double the newest sample, the second one halves it
+        coefs[3] = 0x7FFF;
+        coefs[0x200 + 3] = 0x4000;
+
+        let (mut pb, aram) = ramp(SRC_POLYPHASE, 0x10000);
+        pb[pb::COEF_SELECT] = 1;
+        let out = resample(&mut pb, &aram, &coefs);
+
+        assert_eq!(out[..6], [0, 0, 50, 100, 150, 200]);
+        assert_eq!(out[31], 1500);
+    }
+
+    #[test]
+    fn src_none() {
+        // without conversion, the newest sample is output as is
+        let (mut pb, aram) = ramp(2, 0x20000);
+        let out = resample(&mut pb, &aram, &[0; COEFS_LEN]);
+
+        assert_eq!(out[..4], [0, 100, 300, 500]);
+        assert_eq!(out[31], 6100);
+
+        // the last sample was read, so the voice stopped
+        assert_eq!(pb[pb::RUNNING], 0);
+    }
+
+    #[test]
+    fn volume_ramp() {
+        let mut pb = Pb::default();
+        pb[pb::MIXER] = 0x4000;
+        pb[pb::MIXER + 1] = 0x0100;
+
+        let samples = [0x4000; SAMPLES_PER_MS];
+        let mut out = [1; SAMPLES_PER_MS];
+        super::mix(&mut pb, &mut out, &samples, pb::MIXER, pb::DPOP, true);
+
+        // the volume starts at 0.5 and goes up by 1/128 every sample
+        for (i, &sample) in out.iter().enumerate() {
+            assert_eq!(sample, 1 + 0x2000 + 0x80 * i as i32);
+        }
+
+        assert_eq!(pb[pb::MIXER], 0x6000);
+        assert_eq!(pb[pb::DPOP], 0x2000 + 0x80 * 31);
+
+        // without ramping, the volume stays put
+        super::mix(&mut pb, &mut out, &samples, pb::MIXER, pb::DPOP, false);
+        assert_eq!(out[0], 1 + 0x2000 + 0x3000);
+        assert_eq!(pb[pb::MIXER], 0x6000);
+    }
+}
@@ -0,0 +1,48 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+use dspint::Interpreter;
+use lazuli::system::{self, Modules, System};
+
+/// How many cycles the DSP is executed for at a time.
+const STEP: u32 = 64;
+
+/// A mixing loop in the style of the AX microcode, where every instruction has an extension:
+///
+/// ```text
+/// bloopi #0xFF, 0x0005
+///     mulx'ld
+///     addp'ls
+///     mulac'ln
+///     nx'mv
+/// jmp 0x0000
+/// ```
+const MIXING: [u16; 8] = [
+    0x11FF, 0x0005, 0xA0C0, 0x4E80, 0x9444, 0x8010, 0x029F, 0x0000,
+];
+
+fn system() -> System {
+    System::new(Modules::nop(), system::Config::default())
+}
+
+fn interpreter(code: &[u16]) -> Interpreter {
+    let mut interpreter = Interpreter::default();
+    interpreter.mem.iram[..code.len()].copy_from_slice(code);
+    interpreter.pc = 0;
+
+    // keep the addressing registers inside a small buffer in DRAM
+    interpreter.regs.wrapping = [0xFF; 4];
+    interpreter
+}
+
+fn extensions(c: &mut Criterion) {
+    let mut sys = system();
+    let mut group = c.benchmark_group("DSP extensions");
+    group.throughput(criterion::Throughput::Elements(STEP as u64));
+
+    let mut dsp = interpreter(&MIXING);
+    group.bench_function("Mixing loop", |b| b.iter(|| dsp.exec(&mut sys, STEP)));
+
+    group.finish();
+}
+
+criterion_group!(benches, extensions);
+criterion_main!(benches);
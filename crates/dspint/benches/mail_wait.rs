@@ -0,0 +1,40 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+use dspint::Interpreter;
+use lazuli::system::{self, Modules, System};
+
+/// How many cycles the DSP is executed for at a time.
+const STEP: u32 = 64;
+
+/// `lrs $ACM0, @cmbh; andcf $ACM0, #0x8000; jlnz 0x0000`, the usual loop waiting for CPU mail.
+const MAIL_WAIT: [u16; 5] = [0x26FE, 0x02C0, 0x8000, 0x029C, 0x0000];
+
+/// Same loop, but testing a different bit so it isn't detected as waiting for mail.
+const BUSY_WAIT: [u16; 5] = [0x26FE, 0x02C0, 0x4000, 0x029C, 0x0000];
+
+fn system() -> System {
+    System::new(Modules::nop(), system::Config::default())
+}
+
+fn interpreter(code: &[u16]) -> Interpreter {
+    let mut interpreter = Interpreter::default();
+    interpreter.mem.iram[..code.len()].copy_from_slice(code);
+    interpreter.pc = 0;
+    interpreter
+}
+
+fn mail_wait(c: &mut Criterion) {
+    let mut sys = system();
+    let mut group = c.benchmark_group("DSP mailbox polling");
+    group.throughput(criterion::Throughput::Elements(STEP as u64));
+
+    let mut dsp = interpreter(&MAIL_WAIT);
+    group.bench_function("Detected", |b| b.iter(|| dsp.exec(&mut sys, STEP)));
+
+    let mut dsp = interpreter(&BUSY_WAIT);
+    group.bench_function("Executed", |b| b.iter(|| dsp.exec(&mut sys, STEP)));
+
+    group.finish();
+}
+
+criterion_group!(benches, mail_wait);
+criterion_main!(benches);
@@ -2,7 +2,7 @@ use bitos::BitUtils;
 use lazuli::system::System;
 
 use crate::ins::CondCode;
-use crate::{Acc40, Ins, Interpreter, Reg, Registers, Status};
+use crate::{Acc40, ExtensionRegisters, Ins, Interpreter, Reg, Status};
 
 #[derive(Clone, Copy, PartialEq, Eq)]
 enum MultiplyMode {
@@ -1880,7 +1880,7 @@ impl Interpreter {
 }
 
 impl Interpreter {
-    pub fn ext_dr(&mut self, _: &mut System, ins: Ins, regs: &Registers) {
+    pub fn ext_dr(&mut self, _: &mut System, ins: Ins, regs: &ExtensionRegisters) {
         let r = ins.base.bits(0, 2) as usize;
 
         let ar = regs.addressing[r];
@@ -1889,7 +1889,7 @@ impl Interpreter {
         self.regs.addressing[r] = sub_from_addr_reg(ar, wr, 1i16);
     }
 
-    pub fn ext_ir(&mut self, _: &mut System, ins: Ins, regs: &Registers) {
+    pub fn ext_ir(&mut self, _: &mut System, ins: Ins, regs: &ExtensionRegisters) {
         let r = ins.base.bits(0, 2) as usize;
 
         let ar = regs.addressing[r];
@@ -1898,7 +1898,7 @@ impl Interpreter {
         self.regs.addressing[r] = add_to_addr_reg(ar, wr, 1i16);
     }
 
-    pub fn ext_nr(&mut self, _: &mut System, ins: Ins, regs: &Registers) {
+    pub fn ext_nr(&mut self, _: &mut System, ins: Ins, regs: &ExtensionRegisters) {
         let r = ins.base.bits(0, 2) as usize;
 
         let ar = regs.addressing[r];
@@ -1908,7 +1908,7 @@ impl Interpreter {
         self.regs.addressing[r] = add_to_addr_reg(ar, wr, ir as i16);
     }
 
-    pub fn ext_mv(&mut self, _: &mut System, ins: Ins, regs: &Registers) {
+    pub fn ext_mv(&mut self, _: &mut System, ins: Ins, regs: &ExtensionRegisters) {
         let s = ins.base.bits(0, 2) as u8;
         let d = ins.base.bits(2, 4) as u8;
 
@@ -1916,7 +1916,7 @@ impl Interpreter {
             .set(Reg::new(0x18 + d), regs.get_pure(Reg::new(0x1C + s)));
     }
 
-    pub fn ext_l(&mut self, sys: &mut System, ins: Ins, regs: &Registers) {
+    pub fn ext_l(&mut self, sys: &mut System, ins: Ins, regs: &ExtensionRegisters) {
         let s = ins.base.bits(0, 2) as usize;
         let d = ins.base.bits(3, 6) as u8;
 
@@ -1929,7 +1929,7 @@ impl Interpreter {
         self.regs.addressing[s] = add_to_addr_reg(ar, wr, 1);
     }
 
-    pub fn ext_ln(&mut self, sys: &mut System, ins: Ins, regs: &Registers) {
+    pub fn ext_ln(&mut self, sys: &mut System, ins: Ins, regs: &ExtensionRegisters) {
         let s = ins.base.bits(0, 2) as usize;
         let d = ins.base.bits(3, 6) as u8;
 
@@ -1943,7 +1943,7 @@ impl Interpreter {
         self.regs.addressing[s] = add_to_addr_reg(ar, wr, ix as i16);
     }
 
-    pub fn ext_ld(&mut self, sys: &mut System, ins: Ins, regs: &Registers) {
+    pub fn ext_ld(&mut self, sys: &mut System, ins: Ins, regs: &ExtensionRegisters) {
         let s = ins.base.bits(0, 2) as usize;
         if s == 3 {
             self.ext_ldax(sys, ins, regs);
@@ -1976,7 +1976,7 @@ impl Interpreter {
         self.regs.addressing[3] = add_to_addr_reg(ar, wr, 1);
     }
 
-    pub fn ext_ldax(&mut self, sys: &mut System, ins: Ins, regs: &Registers) {
+    pub fn ext_ldax(&mut self, sys: &mut System, ins: Ins, regs: &ExtensionRegisters) {
         let s = ins.base.bit(5) as usize;
         let r = ins.base.bit(4) as usize;
 
@@ -2000,7 +2000,7 @@ impl Interpreter {
         self.regs.addressing[3] = add_to_addr_reg(ar, wr, 1);
     }
 
-    pub fn ext_ldm(&mut self, sys: &mut System, ins: Ins, regs: &Registers) {
+    pub fn ext_ldm(&mut self, sys: &mut System, ins: Ins, regs: &ExtensionRegisters) {
         let s = ins.base.bits(0, 2) as usize;
         if s == 3 {
             self.ext_ldaxm(sys, ins, regs);
@@ -2034,7 +2034,7 @@ impl Interpreter {
         self.regs.addressing[3] = add_to_addr_reg(ar, wr, ix as i16);
     }
 
-    pub fn ext_ldaxm(&mut self, sys: &mut System, ins: Ins, regs: &Registers) {
+    pub fn ext_ldaxm(&mut self, sys: &mut System, ins: Ins, regs: &ExtensionRegisters) {
         let s = ins.base.bit(5) as usize;
         let r = ins.base.bit(4) as usize;
 
@@ -2059,7 +2059,7 @@ impl Interpreter {
         self.regs.addressing[3] = add_to_addr_reg(ar, wr, ix as i16);
     }
 
-    pub fn ext_ldnm(&mut self, sys: &mut System, ins: Ins, regs: &Registers) {
+    pub fn ext_ldnm(&mut self, sys: &mut System, ins: Ins, regs: &ExtensionRegisters) {
         let s = ins.base.bits(0, 2) as usize;
         if s == 3 {
             self.ext_ldaxnm(sys, ins, regs);
@@ -2094,7 +2094,7 @@ impl Interpreter {
         self.regs.addressing[3] = add_to_addr_reg(ar, wr, ix as i16);
     }
 
-    pub fn ext_ldaxnm(&mut self, sys: &mut System, ins: Ins, regs: &Registers) {
+    pub fn ext_ldaxnm(&mut self, sys: &mut System, ins: Ins, regs: &ExtensionRegisters) {
         let s = ins.base.bit(5) as usize;
         let r = ins.base.bit(4) as usize;
 
@@ -2120,7 +2120,7 @@ impl Interpreter {
         self.regs.addressing[3] = add_to_addr_reg(ar, wr, ix as i16);
     }
 
-    pub fn ext_ldn(&mut self, sys: &mut System, ins: Ins, regs: &Registers) {
+    pub fn ext_ldn(&mut self, sys: &mut System, ins: Ins, regs: &ExtensionRegisters) {
         let s = ins.base.bits(0, 2) as usize;
         if s == 3 {
             self.ext_ldaxn(sys, ins, regs);
@@ -2154,7 +2154,7 @@ impl Interpreter {
         self.regs.addressing[3] = add_to_addr_reg(ar, wr, 1);
     }
 
-    pub fn ext_ldaxn(&mut self, sys: &mut System, ins: Ins, regs: &Registers) {
+    pub fn ext_ldaxn(&mut self, sys: &mut System, ins: Ins, regs: &ExtensionRegisters) {
         let s = ins.base.bit(5) as usize;
         let r = ins.base.bit(4) as usize;
 
@@ -2179,7 +2179,7 @@ impl Interpreter {
         self.regs.addressing[3] = add_to_addr_reg(ar, wr, 1);
     }
 
-    pub fn ext_s(&mut self, sys: &mut System, ins: Ins, regs: &Registers) {
+    pub fn ext_s(&mut self, sys: &mut System, ins: Ins, regs: &ExtensionRegisters) {
         let d = ins.base.bits(0, 2) as usize;
         let s = ins.base.bits(3, 5) as u8;
 
@@ -2192,7 +2192,7 @@ impl Interpreter {
         self.regs.addressing[d] = add_to_addr_reg(ar, wr, 1);
     }
 
-    pub fn ext_sn(&mut self, sys: &mut System, ins: Ins, regs: &Registers) {
+    pub fn ext_sn(&mut self, sys: &mut System, ins: Ins, regs: &ExtensionRegisters) {
         let d = ins.base.bits(0, 2) as usize;
         let s = ins.base.bits(3, 5) as u8;
 
@@ -2206,7 +2206,7 @@ impl Interpreter {
         self.regs.addressing[d] = add_to_addr_reg(ar, wr, ix as i16);
     }
 
-    pub fn ext_ls(&mut self, sys: &mut System, ins: Ins, regs: &Registers) {
+    pub fn ext_ls(&mut self, sys: &mut System, ins: Ins, regs: &ExtensionRegisters) {
         let s = ins.base.bit(0) as usize;
         let d = ins.base.bits(4, 6) as u8;
 
@@ -2215,7 +2215,7 @@ impl Interpreter {
         self.regs.set(Reg::new(0x18 + d), data);
 
         let ar = regs.addressing[3];
-        let data = regs.acc40[s].mid;
+        let data = regs.get_pure(Reg::new(0x1E + s as u8));
         self.write_dmem(sys, ar, data);
 
         let ar = regs.addressing[0];
@@ -2227,7 +2227,7 @@ impl Interpreter {
         self.regs.addressing[3] = add_to_addr_reg(ar, wr, 1);
     }
 
-    pub fn ext_lsm(&mut self, sys: &mut System, ins: Ins, regs: &Registers) {
+    pub fn ext_lsm(&mut self, sys: &mut System, ins: Ins, regs: &ExtensionRegisters) {
         let s = ins.base.bit(0) as usize;
         let d = ins.base.bits(4, 6) as u8;
 
@@ -2236,7 +2236,7 @@ impl Interpreter {
         self.regs.set(Reg::new(0x18 + d), data);
 
         let ar = regs.addressing[3];
-        let data = regs.acc40[s].mid;
+        let data = regs.get_pure(Reg::new(0x1E + s as u8));
         self.write_dmem(sys, ar, data);
 
         let ar = regs.addressing[0];
@@ -2249,7 +2249,7 @@ impl Interpreter {
         self.regs.addressing[3] = add_to_addr_reg(ar, wr, ix as i16);
     }
 
-    pub fn ext_lsnm(&mut self, sys: &mut System, ins: Ins, regs: &Registers) {
+    pub fn ext_lsnm(&mut self, sys: &mut System, ins: Ins, regs: &ExtensionRegisters) {
         let s = ins.base.bit(0) as usize;
         let d = ins.base.bits(4, 6) as u8;
 
@@ -2258,7 +2258,7 @@ impl Interpreter {
         self.regs.set(Reg::new(0x18 + d), data);
 
         let ar = regs.addressing[3];
-        let data = regs.acc40[s].mid;
+        let data = regs.get_pure(Reg::new(0x1E + s as u8));
         self.write_dmem(sys, ar, data);
 
         let ar = regs.addressing[0];
@@ -2272,7 +2272,7 @@ impl Interpreter {
         self.regs.addressing[3] = add_to_addr_reg(ar, wr, ix as i16);
     }
 
-    pub fn ext_lsn(&mut self, sys: &mut System, ins: Ins, regs: &Registers) {
+    pub fn ext_lsn(&mut self, sys: &mut System, ins: Ins, regs: &ExtensionRegisters) {
         let s = ins.base.bit(0) as usize;
         let d = ins.base.bits(4, 6) as u8;
 
@@ -2281,7 +2281,7 @@ impl Interpreter {
         self.regs.set(Reg::new(0x18 + d), data);
 
         let ar = regs.addressing[3];
-        let data = regs.acc40[s].mid;
+        let data = regs.get_pure(Reg::new(0x1E + s as u8));
         self.write_dmem(sys, ar, data);
 
         let ar = regs.addressing[0];
@@ -2294,12 +2294,12 @@ impl Interpreter {
         self.regs.addressing[3] = add_to_addr_reg(ar, wr, 1);
     }
 
-    pub fn ext_sl(&mut self, sys: &mut System, ins: Ins, regs: &Registers) {
+    pub fn ext_sl(&mut self, sys: &mut System, ins: Ins, regs: &ExtensionRegisters) {
         let s = ins.base.bit(0) as usize;
         let d = ins.base.bits(4, 6) as u8;
 
         let ar = regs.addressing[0];
-        let data = regs.acc40[s].mid;
+        let data = regs.get_pure(Reg::new(0x1E + s as u8));
         self.write_dmem(sys, ar, data);
 
         let ar = regs.addressing[3];
@@ -2315,12 +2315,12 @@ impl Interpreter {
         self.regs.addressing[3] = add_to_addr_reg(ar, wr, 1);
     }
 
-    pub fn ext_slm(&mut self, sys: &mut System, ins: Ins, regs: &Registers) {
+    pub fn ext_slm(&mut self, sys: &mut System, ins: Ins, regs: &ExtensionRegisters) {
         let s = ins.base.bit(0) as usize;
         let d = ins.base.bits(4, 6) as u8;
 
         let ar = regs.addressing[0];
-        let data = regs.acc40[s].mid;
+        let data = regs.get_pure(Reg::new(0x1E + s as u8));
         self.write_dmem(sys, ar, data);
 
         let ar = regs.addressing[3];
@@ -2337,12 +2337,12 @@ impl Interpreter {
         self.regs.addressing[3] = add_to_addr_reg(ar, wr, ix as i16);
     }
 
-    pub fn ext_slnm(&mut self, sys: &mut System, ins: Ins, regs: &Registers) {
+    pub fn ext_slnm(&mut self, sys: &mut System, ins: Ins, regs: &ExtensionRegisters) {
         let s = ins.base.bit(0) as usize;
         let d = ins.base.bits(4, 6) as u8;
 
         let ar = regs.addressing[0];
-        let data = regs.acc40[s].mid;
+        let data = regs.get_pure(Reg::new(0x1E + s as u8));
         self.write_dmem(sys, ar, data);
 
         let ar = regs.addressing[3];
@@ -2360,12 +2360,12 @@ impl Interpreter {
         self.regs.addressing[3] = add_to_addr_reg(ar, wr, ix as i16);
     }
 
-    pub fn ext_sln(&mut self, sys: &mut System, ins: Ins, regs: &Registers) {
+    pub fn ext_sln(&mut self, sys: &mut System, ins: Ins, regs: &ExtensionRegisters) {
         let s = ins.base.bit(0) as usize;
         let d = ins.base.bits(4, 6) as u8;
 
         let ar = regs.addressing[0];
-        let data = regs.acc40[s].mid;
+        let data = regs.get_pure(Reg::new(0x1E + s as u8));
         self.write_dmem(sys, ar, data);
 
         let ar = regs.addressing[3];
@@ -1,3 +1,4 @@
+pub mod disasm;
 mod opcodes;
 
 pub use opcodes::{CondCode, ExtensionOpcode, Opcode};
@@ -0,0 +1,436 @@
+//! Disassembly of DSP instructions, following duddie's syntax.
+use std::fmt::{self, Display, Write};
+
+use bitos::BitUtils;
+
+use super::{CondCode, ExtensionOpcode, Ins, Opcode};
+
+const REGISTER_NAMES: [&str; 32] = [
+    "$AR0", "$AR1", "$AR2", "$AR3", "$IX0", "$IX1", "$IX2", "$IX3", "$WR0", "$WR1", "$WR2", "$WR3",
+    "$ST0", "$ST1", "$ST2", "$ST3", "$AC0.H", "$AC1.H", "$CR", "$SR", "$PROD.L", "$PROD.M1",
+    "$PROD.H", "$PROD.M2", "$AX0.L", "$AX1.L", "$AX0.H", "$AX1.H", "$AC0.L", "$AC1.L", "$AC0.M",
+    "$AC1.M",
+];
+
+/// Returns the name of the register with the given index.
+pub fn register_name(index: u8) -> &'static str {
+    REGISTER_NAMES[index as usize & 0x1F]
+}
+
+/// Returns the name of the MMIO register at `0xFF00 | offset`, if it is known.
+pub fn mmio_name(offset: u8) -> Option<&'static str> {
+    Some(match offset {
+        0xA0..=0xAF => return None,
+        0xC9 => "dscr",
+        0xCB => "dsbl",
+        0xCD => "dspa",
+        0xCE => "dsmah",
+        0xCF => "dsmal",
+        0xD1 => "sampleformat",
+        0xD3 => "accelraw",
+        0xD4 => "acsah",
+        0xD5 => "acsal",
+        0xD6 => "aceah",
+        0xD7 => "aceal",
+        0xD8 => "accah",
+        0xD9 => "accal",
+        0xDA => "pred_scale",
+        0xDB => "yn1",
+        0xDC => "yn2",
+        0xDD => "accelsample",
+        0xDE => "gain",
+        0xDF => "input",
+        0xEF => "amdm",
+        0xFB => "diri",
+        0xFC => "dmbh",
+        0xFD => "dmbl",
+        0xFE => "cmbh",
+        0xFF => "cmbl",
+        _ => return None,
+    })
+}
+
+fn cond_suffix(code: CondCode) -> &'static str {
+    match code {
+        CondCode::GreaterOrEqual => "ge",
+        CondCode::Less => "l",
+        CondCode::Greater => "g",
+        CondCode::LessOrEqual => "le",
+        CondCode::NotZero => "nz",
+        CondCode::Zero => "z",
+        CondCode::NotCarry => "nc",
+        CondCode::Carry => "c",
+        CondCode::BelowS32 => "x8",
+        CondCode::AboveS32 => "x9",
+        CondCode::WeirdA => "xa",
+        CondCode::WeirdB => "xb",
+        CondCode::NotLogicZero => "lnz",
+        CondCode::LogicZero => "lz",
+        CondCode::Overflow => "o",
+        CondCode::Always => "",
+    }
+}
+
+/// A data memory address operand.
+struct DataAddr(u16);
+
+impl Display for DataAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0 >= 0xFF00
+            && let Some(name) = mmio_name(self.0 as u8)
+        {
+            write!(f, "@{name}")
+        } else {
+            write!(f, "@0x{:04X}", self.0)
+        }
+    }
+}
+
+/// A short data memory address operand, relative to the page in `$CR` (usually the MMIO page).
+struct ShortAddr(u8);
+
+impl Display for ShortAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(name) = mmio_name(self.0) {
+            write!(f, "@{name}")
+        } else {
+            write!(f, "@0x{:02X}", self.0)
+        }
+    }
+}
+
+/// A signed immediate operand.
+struct SignedImm(i16);
+
+impl Display for SignedImm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0 < 0 {
+            write!(f, "#-0x{:02X}", self.0.unsigned_abs())
+        } else {
+            write!(f, "#0x{:02X}", self.0)
+        }
+    }
+}
+
+fn write_main(f: &mut impl Write, ins: Ins, opcode: Opcode) -> fmt::Result {
+    use Opcode::*;
+
+    let base = ins.base;
+    let extra = ins.extra;
+    let reg = |index: u16| register_name(index as u8);
+    let cond = || cond_suffix(CondCode::new(base.bits(0, 4) as u8));
+
+    let b8 = base.bit(8) as u8;
+    let b9 = base.bit(9) as u8;
+    let b11 = base.bit(11) as u8;
+    let b12 = base.bit(12) as u8;
+    let hl = |high: bool| if high { 'H' } else { 'L' };
+
+    match opcode {
+        Nop | Halt | Lsrn | Asrn | Nx | Cmp | Mulaxh | Clrp | Tstprod | M2 | M0 | Clr15 | Set15
+        | Set16 | Set40 => write!(f, "{}", mnemonic(opcode)),
+        Illegal => write!(f, "illegal 0x{base:04X}"),
+
+        // addressing registers
+        Dar | Iar | Subarn => write!(f, "{} $AR{}", mnemonic(opcode), base.bits(0, 2)),
+        Addarn => write!(f, "addarn $AR{}, $IX{}", base.bits(0, 2), base.bits(2, 4)),
+
+        // loops
+        Loop => write!(f, "loop {}", reg(base.bits(0, 5))),
+        Bloop => write!(f, "bloop {}, 0x{extra:04X}", reg(base.bits(0, 5))),
+        Loopi => write!(f, "loopi #0x{:02X}", base.bits(0, 8)),
+        Bloopi => write!(f, "bloopi #0x{:02X}, 0x{extra:04X}", base.bits(0, 8)),
+
+        // control flow
+        If => write!(f, "if{}", cond()),
+        Jmp if CondCode::new(base.bits(0, 4) as u8) == CondCode::Always => {
+            write!(f, "jmp 0x{extra:04X}")
+        }
+        Jmp => write!(f, "j{} 0x{extra:04X}", cond()),
+        Call => write!(f, "call{} 0x{extra:04X}", cond()),
+        Ret => write!(f, "ret{}", cond()),
+        Rti => write!(f, "rti{}", cond()),
+        Jr if CondCode::new(base.bits(0, 4) as u8) == CondCode::Always => {
+            write!(f, "jmpr {}", reg(base.bits(5, 8)))
+        }
+        Jr => write!(f, "jr{} {}", cond(), reg(base.bits(5, 8))),
+        Callr => write!(f, "callr{} {}", cond(), reg(base.bits(5, 8))),
+
+        // status bits
+        Sbclr | Sbset => write!(f, "{} #{}", mnemonic(opcode), 6 + base.bits(0, 3)),
+
+        // immediates
+        Addi | Xori | Andi | Ori | Cmpi | Andf | Andcf => {
+            write!(f, "{} $ACM{b8}, #0x{extra:04X}", mnemonic(opcode))
+        }
+        Addis | Cmpis => write!(
+            f,
+            "{} $ACM{b8}, {}",
+            mnemonic(opcode),
+            SignedImm(base as u8 as i8 as i16)
+        ),
+        Lris => write!(
+            f,
+            "lris {}, {}",
+            reg(0x18 + base.bits(8, 11)),
+            SignedImm(base as u8 as i8 as i16)
+        ),
+        Lsl | Asl => write!(f, "{} $ACC{b8}, #{}", mnemonic(opcode), base.bits(0, 6)),
+        Lsr | Asr => write!(
+            f,
+            "{} $ACC{b8}, #{}",
+            mnemonic(opcode),
+            (64 - base.bits(0, 6)) % 64
+        ),
+
+        // loads and stores
+        Lri => write!(f, "lri {}, #0x{extra:04X}", reg(base.bits(0, 5))),
+        Lr => write!(f, "lr {}, {}", reg(base.bits(0, 5)), DataAddr(extra)),
+        Sr => write!(f, "sr {}, {}", DataAddr(extra), reg(base.bits(0, 5))),
+        Si => write!(
+            f,
+            "si {}, #0x{extra:04X}",
+            DataAddr(0xFF00 | base.bits(0, 8))
+        ),
+        Lrr | Lrrd | Lrri | Lrrn => write!(
+            f,
+            "{} {}, @$AR{}",
+            mnemonic(opcode),
+            reg(base.bits(0, 5)),
+            base.bits(5, 7)
+        ),
+        Srr | Srrd | Srri | Srrn => write!(
+            f,
+            "{} @$AR{}, {}",
+            mnemonic(opcode),
+            base.bits(5, 7),
+            reg(base.bits(0, 5))
+        ),
+        Ilrr | Ilrrd | Ilrri | Ilrrn => {
+            write!(f, "{} $ACM{b8}, @$AR{}", mnemonic(opcode), base.bits(0, 2))
+        }
+        Lrs => write!(
+            f,
+            "lrs {}, {}",
+            reg(0x18 + base.bits(8, 11)),
+            ShortAddr(base as u8)
+        ),
+        Srsh => write!(f, "srsh {}, $AC{b8}.H", ShortAddr(base as u8)),
+        Srs => write!(
+            f,
+            "srs {}, {}",
+            ShortAddr(base as u8),
+            reg(0x1C + base.bits(8, 10))
+        ),
+        Mrr => write!(f, "mrr {}, {}", reg(base.bits(5, 10)), reg(base.bits(0, 5))),
+
+        // logic
+        Xorr | Andr | Orr => write!(f, "{} $ACM{b8}, $AX{b9}.H", mnemonic(opcode)),
+        Andc | Orc | Xorc => write!(f, "{} $ACM{b8}, $ACM{}", mnemonic(opcode), 1 - b8),
+        Not | Incm | Decm => write!(f, "{} $ACM{b8}", mnemonic(opcode)),
+        Lsrnrx | Asrnrx => write!(f, "{} $ACC{b8}, $AX{b9}.H", mnemonic(opcode)),
+        Lsrnr | Asrnr => write!(f, "{} $ACC{b8}, $ACM{}", mnemonic(opcode), 1 - b8),
+
+        // arithmetic
+        Addr | Subr | Movr => write!(
+            f,
+            "{} $ACC{b8}, {}",
+            mnemonic(opcode),
+            reg(0x18 + base.bits(9, 11))
+        ),
+        Addax | Subax | Movax => write!(f, "{} $ACC{b8}, $AX{b9}", mnemonic(opcode)),
+        Add | Sub | Mov => write!(f, "{} $ACC{b8}, $ACC{}", mnemonic(opcode), 1 - b8),
+        Addp | Subp | Movp | Inc | Dec | Neg | Movnp | Lsl16 | Lsr16 | Movpz => {
+            write!(f, "{} $ACC{b8}", mnemonic(opcode))
+        }
+        Addaxl => write!(f, "addaxl $ACC{b8}, $AX{b9}.L"),
+        Addpaxz => write!(f, "addpaxz $ACC{b8}, $AX{b9}.H"),
+        Clrl => write!(f, "clrl $AC{b8}.L"),
+        Clr | Abs | Tst | Asr16 => write!(f, "{} $ACC{b11}", mnemonic(opcode)),
+        Tstaxh => write!(f, "tstaxh $AX{b8}.H"),
+        Cmpaxh => write!(f, "cmpaxh $ACC{b12}, $AX{b11}.H"),
+
+        // multiplication
+        Mul => write!(f, "mul $AX{b11}.L, $AX{b11}.H"),
+        Mulmvz | Mulac | Mulmv => {
+            write!(f, "{} $AX{b11}.L, $AX{b11}.H, $ACC{b8}", mnemonic(opcode))
+        }
+        Mulx => write!(f, "mulx $AX0.{}, $AX1.{}", hl(b12 != 0), hl(b11 != 0)),
+        Mulxmvz | Mulxac | Mulxmv => write!(
+            f,
+            "{} $AX0.{}, $AX1.{}, $ACC{b8}",
+            mnemonic(opcode),
+            hl(b12 != 0),
+            hl(b11 != 0)
+        ),
+        Mulc => write!(f, "mulc $AC{b12}.M, $AX{b11}.H"),
+        Mulcmvz | Mulcac | Mulcmv => {
+            write!(f, "{} $AC{b12}.M, $AX{b11}.H, $ACC{b8}", mnemonic(opcode))
+        }
+        Maddx | Msubx => write!(
+            f,
+            "{} $AX0.{}, $AX1.{}",
+            mnemonic(opcode),
+            hl(b9 != 0),
+            hl(b8 != 0)
+        ),
+        Maddc | Msubc => write!(f, "{} $AC{b9}.M, $AX{b8}.H", mnemonic(opcode)),
+        Madd | Msub => write!(f, "{} $AX{b8}.L, $AX{b8}.H", mnemonic(opcode)),
+    }
+}
+
+fn write_extension(f: &mut impl Write, ins: Ins, extension: ExtensionOpcode) -> fmt::Result {
+    use ExtensionOpcode::*;
+
+    let base = ins.base;
+    let reg = |index: u16| register_name(index as u8);
+    let name = extension_mnemonic(extension);
+
+    match extension {
+        Nop => Ok(()),
+        Illegal => write!(f, " : 'illegal"),
+        Dr | Ir | Nr => write!(f, " : '{name} $AR{}", base.bits(0, 2)),
+        Mv => write!(
+            f,
+            " : 'mv {}, {}",
+            reg(0x18 + base.bits(2, 4)),
+            reg(0x1C + base.bits(0, 2))
+        ),
+        S | Sn => write!(
+            f,
+            " : '{name} @$AR{}, {}",
+            base.bits(0, 2),
+            reg(0x1C + base.bits(3, 5))
+        ),
+        L | Ln => write!(
+            f,
+            " : '{name} {}, @$AR{}",
+            reg(0x18 + base.bits(3, 6)),
+            base.bits(0, 2)
+        ),
+        Ls | Lsn | Lsm | Lsnm => write!(
+            f,
+            " : '{name} {}, $ACM{}",
+            reg(0x18 + base.bits(4, 6)),
+            base.bit(0) as u8
+        ),
+        Sl | Sln | Slm | Slnm => write!(
+            f,
+            " : '{name} $ACM{}, {}",
+            base.bit(0) as u8,
+            reg(0x18 + base.bits(4, 6))
+        ),
+        Ld | Ldn | Ldm | Ldnm if base.bits(0, 2) == 3 => write!(
+            f,
+            " : '{}ax{} $AX{}, @$AR{}",
+            &name[..2],
+            &name[2..],
+            base.bit(4) as u8,
+            base.bit(5) as u8
+        ),
+        Ld | Ldn | Ldm | Ldnm => write!(
+            f,
+            " : '{name} $AX0.{}, $AX1.{}, @$AR{}",
+            if base.bit(5) { 'H' } else { 'L' },
+            if base.bit(4) { 'H' } else { 'L' },
+            base.bits(0, 2)
+        ),
+    }
+}
+
+fn mnemonic(opcode: Opcode) -> String {
+    let mut name = format!("{opcode:?}");
+    name.make_ascii_lowercase();
+    name
+}
+
+fn extension_mnemonic(extension: ExtensionOpcode) -> String {
+    let mut name = format!("{extension:?}");
+    name.make_ascii_lowercase();
+    name
+}
+
+impl Display for Ins {
+    /// Formats this instruction as disassembly. Instructions which take an extra word must have
+    /// it set in [`Ins::extra`].
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let decoded = self.decoded();
+        write_main(f, *self, decoded.opcode)?;
+
+        if let Some(extension) = decoded.extension {
+            write_extension(f, *self, extension)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Ins;
+
+    fn disasm(base: u16, extra: u16) -> String {
+        Ins::with_extra(base, extra).to_string()
+    }
+
+    #[test]
+    fn mailbox_wait_loop() {
+        // from the mailbox polling loop present in most ucodes
+        assert_eq!(disasm(0x26FE, 0), "lrs $AC0.M, @cmbh");
+        assert_eq!(disasm(0x27FC, 0), "lrs $AC1.M, @dmbh");
+        assert_eq!(disasm(0x02C0, 0x8000), "andcf $ACM0, #0x8000");
+        assert_eq!(disasm(0x03C0, 0x8000), "andcf $ACM1, #0x8000");
+        assert_eq!(disasm(0x029C, 0x0010), "jlnz 0x0010");
+        assert_eq!(disasm(0x029D, 0x0010), "jlz 0x0010");
+    }
+
+    #[test]
+    fn control_flow() {
+        assert_eq!(disasm(0x029F, 0x0C00), "jmp 0x0C00");
+        assert_eq!(disasm(0x02BF, 0x80B5), "call 0x80B5");
+        assert_eq!(disasm(0x02DF, 0), "ret");
+        assert_eq!(disasm(0x02FF, 0), "rti");
+        assert_eq!(disasm(0x0275, 0), "ifz");
+        assert_eq!(disasm(0x170F, 0), "jmpr $AR0");
+        assert_eq!(disasm(0x171F, 0), "callr $AR0");
+        assert_eq!(disasm(0x0021, 0), "halt");
+        assert_eq!(disasm(0x1103, 0x0050), "bloopi #0x03, 0x0050");
+    }
+
+    #[test]
+    fn loads_and_stores() {
+        assert_eq!(disasm(0x0092, 0x00FF), "lri $CR, #0x00FF");
+        assert_eq!(disasm(0x16FC, 0xDCD1), "si @dmbh, #0xDCD1");
+        assert_eq!(disasm(0x00DE, 0xFFFF), "lr $AC0.M, @cmbl");
+        assert_eq!(disasm(0x00FE, 0x0E44), "sr @0x0E44, $AC0.M");
+        assert_eq!(disasm(0x1C1E, 0), "mrr $AR0, $AC0.M");
+        assert_eq!(disasm(0x1FFC, 0), "mrr $AC1.M, $AC0.L");
+        assert_eq!(disasm(0x191E, 0), "lrri $AC0.M, @$AR0");
+        assert_eq!(disasm(0x1B1E, 0), "srri @$AR0, $AC0.M");
+        assert_eq!(disasm(0x0A00, 0), "lris $AX0.H, #0x00");
+        assert_eq!(disasm(0x0EFF, 0), "lris $AC0.M, #-0x01");
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(disasm(0x8100, 0), "clr $ACC0");
+        assert_eq!(disasm(0x8900, 0), "clr $ACC1");
+        assert_eq!(disasm(0x8E00, 0), "set16");
+        assert_eq!(disasm(0x4C00, 0), "add $ACC0, $ACC1");
+        assert_eq!(disasm(0x1404, 0), "lsl $ACC0, #4");
+        assert_eq!(disasm(0x147C, 0), "lsr $ACC0, #4");
+        assert_eq!(disasm(0x0400, 0), "addis $ACM0, #0x00");
+        assert_eq!(disasm(0xA000, 0), "mulx $AX0.L, $AX1.L");
+    }
+
+    #[test]
+    fn extensions() {
+        assert_eq!(disasm(0x6800, 0), "movax $ACC0, $AX0");
+        assert_eq!(disasm(0x6841, 0), "movax $ACC0, $AX0 : 'l $AX0.L, @$AR1");
+        assert_eq!(disasm(0x8080, 0), "nx : 'ls $AX0.L, $ACM0");
+        assert_eq!(disasm(0x80C0, 0), "nx : 'ld $AX0.L, $AX1.L, @$AR0");
+        assert_eq!(disasm(0x80C3, 0), "nx : 'ldax $AX0, @$AR0");
+        assert_eq!(disasm(0x8004, 0), "nx : 'dr $AR0");
+        assert_eq!(disasm(0x8012, 0), "nx : 'mv $AX0.L, $AC0.M");
+    }
+}
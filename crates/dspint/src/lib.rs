@@ -4,11 +4,15 @@ mod exec;
 
 pub mod ins;
 
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+
 use bitos::integer::{u3, u4, u15};
 use bitos::{BitUtils, bitos};
-use lazuli::Primitive;
 use lazuli::system::System;
-use lazuli::system::dspi::{DspDmaControl, DspDmaDirection, DspDmaTarget, Mailbox};
+use lazuli::system::dspi::{self, DspDmaControl, DspDmaDirection, DspDmaTarget, Mailbox};
+use lazuli::{Address, Primitive, adpcm};
 use strum::FromRepr;
 use tinyvec::ArrayVec;
 use util::boxed_array;
@@ -20,10 +24,18 @@ use crate::ins::{ExtensionOpcode, Opcode};
 pub use crate::ins::Ins;
 
 const IRAM_LEN: usize = 0x1000;
-const IROM_LEN: usize = 0x1000;
+/// Length of the IROM, in words.
+pub const IROM_LEN: usize = 0x1000;
+/// Size of a dump of the IROM, in bytes.
+pub const IROM_SIZE: usize = 2 * IROM_LEN;
 const DRAM_LEN: usize = 0x1000;
 const COEF_LEN: usize = 0x0800;
 
+/// How many mails are kept in the mailbox history.
+pub const MAIL_HISTORY_LEN: usize = 32;
+/// How far back a jump can go for it to be considered the end of a mail wait loop.
+const MAIL_WAIT_MAX_JUMP: u16 = 4;
+
 pub struct Memory {
     pub iram: Box<[u16; IRAM_LEN]>,
     pub irom: Box<[u16; IROM_LEN]>,
@@ -239,18 +251,23 @@ impl Default for Registers {
     }
 }
 
+/// Reads the middle of an accumulator, saturating it if 40-bit mode is enabled and the value
+/// doesn't fit in 32 bits.
+#[inline(always)]
+fn acc_saturate(acc: Acc40, status: Status) -> u16 {
+    let ml = acc.get() as i32 as i64;
+    let hml = acc.get();
+
+    if status.sign_extend_to_40() && ml != hml {
+        if hml >= 0 { 0x7FFF } else { 0x8000 }
+    } else {
+        acc.mid
+    }
+}
+
 impl Registers {
     pub fn get_pure(&self, reg: Reg) -> u16 {
-        let acc_saturate = |i: usize| {
-            let ml = self.acc40[i].get() as i32 as i64;
-            let hml = self.acc40[i].get();
-
-            if self.status.sign_extend_to_40() && ml != hml {
-                if hml >= 0 { 0x7FFF } else { 0x8000 }
-            } else {
-                self.acc40[i].mid
-            }
-        };
+        let mid = |i: usize| acc_saturate(self.acc40[i], self.status);
 
         match reg {
             Reg::Addr0 => self.addressing[0],
@@ -283,8 +300,8 @@ impl Registers {
             Reg::Acc32High1 => self.acc32[1].bits(16, 32) as u16,
             Reg::Acc40Low0 => self.acc40[0].low,
             Reg::Acc40Low1 => self.acc40[1].low,
-            Reg::Acc40Mid0 => acc_saturate(0),
-            Reg::Acc40Mid1 => acc_saturate(1),
+            Reg::Acc40Mid0 => mid(0),
+            Reg::Acc40Mid1 => mid(1),
         }
     }
 
@@ -361,6 +378,51 @@ impl Registers {
     }
 }
 
+/// The registers extension instructions read, as they were before the main instruction executed.
+///
+/// Extensions observe the state from before the instruction they're attached to, even if the main
+/// instruction modifies it. Only these registers are captured, which is much cheaper than cloning
+/// the whole [`Registers`] for every instruction with an extension.
+#[derive(Debug, Clone, Copy)]
+pub struct ExtensionRegisters {
+    pub addressing: [u16; 4],
+    pub indexing: [u16; 4],
+    pub wrapping: [u16; 4],
+    pub acc40: [Acc40; 2],
+    pub acc32: [i32; 2],
+    pub status: Status,
+}
+
+impl ExtensionRegisters {
+    #[inline(always)]
+    pub fn capture(regs: &Registers) -> Self {
+        Self {
+            addressing: regs.addressing,
+            indexing: regs.indexing,
+            wrapping: regs.wrapping,
+            acc40: regs.acc40,
+            acc32: regs.acc32,
+            status: regs.status,
+        }
+    }
+
+    /// Same as [`Registers::get_pure`], but only for the accumulators, which are the only
+    /// registers extensions read by number.
+    pub fn get_pure(&self, reg: Reg) -> u16 {
+        match reg {
+            Reg::Acc32Low0 => self.acc32[0].bits(0, 16) as u16,
+            Reg::Acc32Low1 => self.acc32[1].bits(0, 16) as u16,
+            Reg::Acc32High0 => self.acc32[0].bits(16, 32) as u16,
+            Reg::Acc32High1 => self.acc32[1].bits(16, 32) as u16,
+            Reg::Acc40Low0 => self.acc40[0].low,
+            Reg::Acc40Low1 => self.acc40[1].low,
+            Reg::Acc40Mid0 => acc_saturate(self.acc40[0], self.status),
+            Reg::Acc40Mid1 => acc_saturate(self.acc40[1], self.status),
+            _ => unreachable!("{reg:?} is not captured for extensions"),
+        }
+    }
+}
+
 #[bitos(2)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum SampleSize {
@@ -372,12 +434,25 @@ pub enum SampleSize {
 }
 
 impl SampleSize {
+    /// The size actually used: the reserved size behaves as [`Word`](Self::Word).
+    pub fn effective(self) -> Self {
+        if self != Self::Reserved {
+            return self;
+        }
+
+        static WARNED: AtomicBool = AtomicBool::new(false);
+        if !WARNED.swap(true, Ordering::Relaxed) {
+            tracing::warn!("reserved accelerator sample size used, treating it as word");
+        }
+
+        Self::Word
+    }
+
     pub fn size(self) -> u32 {
-        match self {
+        match self.effective() {
             Self::Nibble => 1,
             Self::Byte => 2,
-            Self::Word => 4,
-            _ => panic!("reserved size"),
+            Self::Word | Self::Reserved => 4,
         }
     }
 }
@@ -393,7 +468,7 @@ pub enum SampleDecoding {
 }
 
 #[bitos(2)]
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum PcmDivisor {
     #[default]
     D2048    = 0b00,
@@ -403,22 +478,34 @@ pub enum PcmDivisor {
 }
 
 impl PcmDivisor {
+    /// The divisor actually used: the reserved divisor behaves as [`D65536`](Self::D65536).
+    pub fn effective(self) -> Self {
+        if self != Self::Reserved {
+            return self;
+        }
+
+        static WARNED: AtomicBool = AtomicBool::new(false);
+        if !WARNED.swap(true, Ordering::Relaxed) {
+            tracing::warn!("reserved accelerator PCM divisor used, treating it as 65536");
+        }
+
+        Self::D65536
+    }
+
     pub fn value(self) -> u32 {
-        match self {
+        match self.effective() {
             Self::D2048 => 2048,
             Self::D1 => 1,
-            Self::D65536 => 65536,
-            _ => panic!("reserved divisor"),
+            Self::D65536 | Self::Reserved => 65536,
         }
     }
 
     /// Applies rounding division.
     pub fn apply(self, value: i32) -> i32 {
-        match self {
+        match self.effective() {
             Self::D2048 => (value + (1 << 10)) >> 11,
             Self::D1 => value,
-            Self::D65536 => (value + (1 << 15)) >> 16,
-            _ => panic!("reserved divisor"),
+            Self::D65536 | Self::Reserved => (value + (1 << 15)) >> 16,
         }
     }
 }
@@ -466,12 +553,15 @@ pub struct Accelerator {
     pub aram_start: u32,
     pub aram_end: u32,
     pub aram_curr: u32,
+    /// Gain applied to PCM input samples. ADPCM samples ignore it.
     pub gain: i16,
     pub input: i16,
     pub previous_samples: [i16; 2],
     pub has_data: bool,
     pub dma_masked: bool,
     pub overflow: AccelOverflow,
+    /// Value of the 0xD2 register. Its purpose is unknown, but ucodes set it during init.
+    pub unknown: u16,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, FromRepr)]
@@ -504,6 +594,7 @@ pub enum Mmio {
 
     // Accelerator
     AccelFormat        = 0xD1,
+    AccelUnknown       = 0xD2,
     AccelRaw           = 0xD3,
     AccelStartAddrHigh = 0xD4,
     AccelStartAddrLow  = 0xD5,
@@ -531,6 +622,71 @@ pub enum Mmio {
     CpuMailboxLow      = 0xFF,
 }
 
+/// Direction of a mail exchanged through the mailboxes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MailDirection {
+    CpuToDsp,
+    DspToCpu,
+}
+
+impl MailDirection {
+    /// Whether a loop waiting on mail in this direction would still be blocked, i.e. whether the
+    /// CPU mailbox is still empty or the DSP mailbox is still full.
+    fn is_blocked(self, sys: &System) -> bool {
+        match self {
+            Self::CpuToDsp => !sys.dsp.cpu_mailbox.status(),
+            Self::DspToCpu => sys.dsp.dsp_mailbox.status(),
+        }
+    }
+}
+
+/// A mail exchanged between the CPU and the DSP.
+#[derive(Debug, Clone, Copy)]
+pub struct Mail {
+    pub direction: MailDirection,
+    pub data: u32,
+}
+
+/// A mail recorded in the mailbox trace, along with when it was exchanged.
+#[derive(Debug, Clone, Copy)]
+pub struct MailboxEvent {
+    pub direction: MailDirection,
+    pub data: u32,
+    /// System cycle at which the DSP received or sent the mail.
+    pub cycle: u64,
+}
+
+/// Accesses to an MMIO offset with no known behaviour. Reads from it return 0 and writes to it are
+/// ignored.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProbedMmio {
+    pub reads: u64,
+    pub writes: u64,
+    pub last_written: Option<u16>,
+}
+
+/// Why [`Interpreter::exec`] returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    /// The whole budget was executed.
+    Budget,
+    /// The DSP is halted.
+    Halted,
+    /// The DSP is busy waiting in a mailbox polling loop.
+    WaitingForMail,
+    /// A breakpoint was reached.
+    Breakpoint,
+}
+
+/// What [`Interpreter::exec`] executed.
+#[derive(Debug, Clone, Copy)]
+pub struct Executed {
+    /// How many instructions have been executed.
+    pub instructions: u32,
+    /// Why execution stopped.
+    pub exit: Exit,
+}
+
 #[derive(Clone, Copy)]
 struct CachedIns {
     ins: Ins,
@@ -545,8 +701,23 @@ pub struct Interpreter {
     pub mem: Memory,
     pub accel: Accelerator,
     pub old_reset_high: bool,
+    /// PC values at which execution should stop.
+    pub breakpoints: Vec<u16>,
+    /// The last [`MAIL_HISTORY_LEN`] mails exchanged with the CPU, oldest first.
+    pub mail_history: VecDeque<Mail>,
+    /// Every mail exchanged with the CPU since the last call to
+    /// [`Interpreter::take_mailbox_trace`], oldest first. Only recorded if `Some`.
+    pub mailbox_trace: Option<Vec<MailboxEvent>>,
+    /// Unknown MMIO offsets accessed so far.
+    pub probed_mmio: BTreeMap<u8, ProbedMmio>,
+    /// Receives every sample decoded by the accelerator, if set. Cleared once the receiving end
+    /// is dropped.
+    pub sample_sink: Option<Sender<i16>>,
 
     cached: Box<[Option<CachedIns>; 1 << 16]>,
+    skip_breakpoint: bool,
+    /// Start of the mail wait loop the DSP is stuck in and which mail it's waiting on, if any.
+    mail_wait: Option<(u16, MailDirection)>,
 }
 
 impl Default for Interpreter {
@@ -557,7 +728,14 @@ impl Default for Interpreter {
             mem: Default::default(),
             accel: Default::default(),
             old_reset_high: Default::default(),
+            breakpoints: Vec::new(),
+            mail_history: VecDeque::with_capacity(MAIL_HISTORY_LEN),
+            mailbox_trace: None,
+            probed_mmio: BTreeMap::new(),
+            sample_sink: None,
             cached: util::boxed_array(None),
+            skip_breakpoint: false,
+            mail_wait: None,
         }
     }
 }
@@ -695,10 +873,11 @@ static OPCODE_EXEC_LUT: [OpcodeFn; 1 << 8] = {
     lut
 };
 
-type ExtensionFn = for<'a, 'b, 'c> fn(&'a mut Interpreter, &'b mut System, Ins, &'c Registers);
+type ExtensionFn =
+    for<'a, 'b, 'c> fn(&'a mut Interpreter, &'b mut System, Ins, &'c ExtensionRegisters);
 
 static EXTENSION_EXEC_LUT: [ExtensionFn; 1 << 8] = {
-    fn nop(_: &mut Interpreter, _: &mut System, _: Ins, _: &Registers) {}
+    fn nop(_: &mut Interpreter, _: &mut System, _: Ins, _: &ExtensionRegisters) {}
     let mut lut = [nop as ExtensionFn; 1 << 8];
 
     lut[ExtensionOpcode::Dr as usize] = Interpreter::ext_dr as ExtensionFn;
@@ -725,6 +904,53 @@ static EXTENSION_EXEC_LUT: [ExtensionFn; 1 << 8] = {
     lut
 };
 
+/// Reads the RAM side of a DSP DMA. If the transfer faults, the remaining data is left zeroed.
+fn dma_read_ram(sys: &mut System, ram_base: u32, length: u16) -> Vec<u8> {
+    let mut data = vec![0; (length & !1) as usize];
+    if let Err(fault) = sys.read_phys_block(Address(ram_base), &mut data) {
+        tracing::error!("DSP DMA from RAM failed: {fault}");
+    }
+
+    data
+}
+
+/// Decodes a PCM sample of the accelerator.
+///
+/// The gain scales the input sample only, not the predictions, and the divisor applies to the
+/// whole sum: `(gain * input + a * history[0] + b * history[1]) / divisor`.
+fn decode_pcm_sample(
+    divisor: PcmDivisor,
+    coeffs: AccelCoefficients,
+    history: [i16; 2],
+    gain: i16,
+    input: i16,
+) -> i16 {
+    let acc = gain as i32 * input as i32
+        + coeffs.a as i32 * history[0] as i32
+        + coeffs.b as i32 * history[1] as i32;
+
+    divisor.apply(acc) as i16
+}
+
+/// Decodes an ADPCM nibble of the accelerator. Unlike PCM, ADPCM samples ignore the gain and the
+/// divisor.
+fn decode_adpcm_sample(
+    coeffs: AccelCoefficients,
+    scale_log2: u8,
+    history: [i16; 2],
+    data: u8,
+) -> i16 {
+    let value = adpcm::nibble(data) << scale_log2;
+    let prediction = adpcm::predict(
+        [coeffs.a as i32, coeffs.b as i32],
+        history.map(|sample| sample as i32),
+        11,
+    );
+
+    let result = prediction + value;
+    result.clamp(i16::MIN as i32, i16::MAX as i32) as i16
+}
+
 impl Interpreter {
     fn raise_interrupt(&mut self, interrupt: Interrupt) {
         self.regs.call_stack.push(self.pc);
@@ -785,6 +1011,16 @@ impl Interpreter {
         }
     }
 
+    /// Loads the IROM from a dump of it, made of big-endian words.
+    pub fn load_irom(&mut self, data: &[u8; IROM_SIZE]) {
+        for (word, bytes) in self.mem.irom.iter_mut().zip(data.chunks_exact(2)) {
+            *word = u16::from_be_bytes([bytes[0], bytes[1]]);
+        }
+
+        // instructions decoded from the previous contents are stale
+        self.cached[0x8000..0x8000 + IROM_LEN].fill(None);
+    }
+
     /// Soft resets the DSP.
     pub fn reset(&mut self, sys: &mut System) {
         self.regs = Default::default();
@@ -792,6 +1028,7 @@ impl Interpreter {
         sys.dsp.cpu_mailbox = Mailbox::from_bits(0);
 
         self.cached.fill(None);
+        self.mail_wait = None;
         self.pc = if sys.dsp.control.reset_high() {
             tracing::debug!("resetting at IROM (0x8000)");
             0x8000
@@ -826,9 +1063,12 @@ impl Interpreter {
         self.old_reset_high = sys.dsp.control.reset_high();
     }
 
-    /// Performs the DSP DMA if the transfer is ongoing.
+    /// Performs the DSP DMA if the transfer is ongoing and hasn't been performed yet.
+    ///
+    /// The data is moved right away, but the transfer is only reported as finished once the time
+    /// it takes on hardware has elapsed.
     pub fn do_dma(&mut self, sys: &mut System) {
-        if sys.dsp.dsp_dma.control.transfer_ongoing() {
+        if sys.dsp.dsp_dma.control.transfer_ongoing() && sys.dsp.dsp_dma_pending.is_none() {
             std::hint::cold_path();
 
             let ram_base = sys.dsp.dsp_dma.ram_base.with_bits(26, 32, 0);
@@ -846,12 +1086,9 @@ impl Interpreter {
                         "DSP DMA {length:04X} bytes from RAM {ram_base:08X} to DMEM {dsp_base:04X}",
                     );
 
-                    for word in 0..(length / 2) {
-                        let data = u16::read_be_bytes(
-                            &sys.mem.ram()[(ram_base + 2 * word as u32) as usize..],
-                        );
-
-                        self.write_dmem(sys, dsp_base + word, data);
+                    let data = self::dma_read_ram(sys, ram_base, length);
+                    for (word, data) in (0..).zip(data.chunks_exact(2)) {
+                        self.write_dmem(sys, dsp_base + word, u16::read_be_bytes(data));
                     }
                 }
                 (DspDmaTarget::Dmem, DspDmaDirection::FromDspToRam) => {
@@ -859,11 +1096,13 @@ impl Interpreter {
                         "DSP DMA {length:04X} bytes from DMEM {dsp_base:04X} to RAM {ram_base:08X}"
                     );
 
-                    for word in 0..(length / 2) {
-                        let data = self.read_dmem(sys, dsp_base + word);
-                        data.write_be_bytes(
-                            &mut sys.mem.ram_mut()[(ram_base + 2 * word as u32) as usize..],
-                        );
+                    let mut data = vec![0; (length & !1) as usize];
+                    for (word, data) in (0..).zip(data.chunks_exact_mut(2)) {
+                        self.read_dmem(sys, dsp_base + word).write_be_bytes(data);
+                    }
+
+                    if let Err(fault) = sys.write_phys_block(Address(ram_base), &data) {
+                        tracing::error!("DSP DMA to RAM failed: {fault}");
                     }
                 }
                 (DspDmaTarget::Imem, DspDmaDirection::FromRamToDsp) => {
@@ -873,12 +1112,9 @@ impl Interpreter {
                         "DSP DMA {length:04X} bytes from RAM {ram_base:08X} to IMEM {dsp_base:04X} (ucode)"
                     );
 
-                    for word in 0..(length / 2) {
-                        let data = u16::read_be_bytes(
-                            &sys.mem.ram()[(ram_base + 2 * word as u32) as usize..],
-                        );
-
-                        self.write_imem(dsp_base + word, data);
+                    let data = self::dma_read_ram(sys, ram_base, length);
+                    for (word, data) in (0..).zip(data.chunks_exact(2)) {
+                        self.write_imem(dsp_base + word, u16::read_be_bytes(data));
                     }
 
                     // clear cache
@@ -887,8 +1123,7 @@ impl Interpreter {
                 (DspDmaTarget::Imem, DspDmaDirection::FromDspToRam) => unimplemented!(),
             };
 
-            sys.dsp.dsp_dma.length = 0;
-            sys.dsp.dsp_dma.control.set_transfer_ongoing(false);
+            dspi::schedule_dsp_dma_completion(sys, length);
         }
     }
 
@@ -904,7 +1139,7 @@ impl Interpreter {
     fn read_accel_raw(&mut self, sys: &mut System) -> u16 {
         let format = self.accel.format;
         let index = self.accel.aram_curr.with_bit(31, false);
-        let value = match format.sample() {
+        let value = match format.sample().effective() {
             SampleSize::Nibble => {
                 let address = index / 2;
                 let byte = sys.dsp.aram[address as usize] as u16;
@@ -915,11 +1150,10 @@ impl Interpreter {
                 }
             }
             SampleSize::Byte => sys.dsp.aram[index as usize] as u16,
-            SampleSize::Word => {
+            SampleSize::Word | SampleSize::Reserved => {
                 let address = index * 2;
                 u16::read_be_bytes(&sys.dsp.aram[address as usize..])
             }
-            _ => panic!("reserved format"),
         };
 
         tracing::debug!(
@@ -932,20 +1166,17 @@ impl Interpreter {
         value
     }
 
-    fn pcm_gain(&self, value: i32) -> i32 {
-        value * self.accel.gain as i32
-    }
-
-    fn pcm_decode(&self, value: i32) -> i16 {
+    fn pcm_decode(&self, value: i16) -> i16 {
         let predictor = self.accel.predictor;
         let coeff_idx = predictor.coefficients().value();
-        let coeffs = self.accel.coefficients[coeff_idx as usize];
 
-        let acc = self.pcm_gain(value)
-            + coeffs.a as i32 * self.accel.previous_samples[0] as i32
-            + coeffs.b as i32 * self.accel.previous_samples[1] as i32;
-
-        self.accel.format.divisor().apply(acc) as i16
+        decode_pcm_sample(
+            self.accel.format.divisor(),
+            self.accel.coefficients[coeff_idx as usize],
+            self.accel.previous_samples,
+            self.accel.gain,
+            value,
+        )
     }
 
     fn adpcm_decode(&mut self, sys: &mut System) -> i16 {
@@ -965,18 +1196,15 @@ impl Interpreter {
         let predictor = self.accel.predictor;
         let coeff_idx = predictor.coefficients().value();
 
-        let coeffs = self.accel.coefficients[coeff_idx as usize];
-        let scale = 1 << predictor.scale_log2().value();
-
-        let data = ((self.read_accel_raw(sys) as i8) << 4) >> 4;
+        let data = self.read_accel_raw(sys) as u8;
         self.increment_accel_curr(AccelOverflow::Sample);
 
-        let value = scale * data as i32;
-        let prediction = coeffs.a as i32 * self.accel.previous_samples[0] as i32
-            + coeffs.b as i32 * self.accel.previous_samples[1] as i32;
-
-        let result = PcmDivisor::D2048.apply(prediction) + value;
-        result.clamp(i16::MIN as i32, i16::MAX as i32) as i16
+        decode_adpcm_sample(
+            self.accel.coefficients[coeff_idx as usize],
+            predictor.scale_log2().value(),
+            self.accel.previous_samples,
+            data,
+        )
     }
 
     fn read_accel_sample(&mut self, sys: &mut System) -> i16 {
@@ -988,27 +1216,52 @@ impl Interpreter {
 
         let value = match self.accel.format.decoding() {
             SampleDecoding::AramAdpcm => self.adpcm_decode(sys),
-            SampleDecoding::AcinPcm => self.pcm_decode(self.accel.input as i32),
+            SampleDecoding::AcinPcm => self.pcm_decode(self.accel.input),
             SampleDecoding::AramPcm => {
                 let value = self.read_accel_raw(sys) as i16;
                 self.increment_accel_curr(AccelOverflow::Sample);
-                self.pcm_decode(value as i32)
+                self.pcm_decode(value)
             }
             SampleDecoding::AcinPcmInc => {
                 self.increment_accel_curr(AccelOverflow::Sample);
-                self.pcm_decode(self.accel.input as i32)
+                self.pcm_decode(self.accel.input)
             }
         };
 
         self.accel.previous_samples[1] = self.accel.previous_samples[0];
         self.accel.previous_samples[0] = value;
 
+        if let Some(sink) = &self.sample_sink
+            && sink.send(value).is_err()
+        {
+            self.sample_sink = None;
+        }
+
         value
     }
 
+    /// Records an access to an unknown MMIO offset, warning about the first one.
+    fn probe_mmio(&mut self, offset: u8, written: Option<u16>) {
+        let probed = self.probed_mmio.entry(offset).or_insert_with(|| {
+            tracing::warn!(
+                "accessing unknown DSP MMIO 0x{offset:02X} [0x{:04X}]",
+                self.pc
+            );
+            ProbedMmio::default()
+        });
+
+        match written {
+            Some(value) => {
+                probed.writes += 1;
+                probed.last_written = Some(value);
+            }
+            None => probed.reads += 1,
+        }
+    }
+
     pub fn read_mmio(&mut self, sys: &mut System, offset: u8) -> u16 {
         let Some(mmio) = Mmio::from_repr(offset) else {
-            println!("!!!!! reading from unknown MMIO 0x{offset:02X}");
+            self.probe_mmio(offset, None);
             return 0;
         };
 
@@ -1029,8 +1282,14 @@ impl Interpreter {
             Mmio::DmaDspAddr => sys.dsp.dsp_dma.dsp_base,
             Mmio::DmaRamAddrHigh => (sys.dsp.dsp_dma.ram_base >> 16) as u16,
             Mmio::DmaRamAddrLow => sys.dsp.dsp_dma.ram_base as u16,
+            Mmio::DmaMasked => self.accel.dma_masked as u16,
+
+            // Interrupt (write only)
+            Mmio::InterruptRequest => 0,
 
             // Accelerator
+            Mmio::AccelFormat => self.accel.format.to_bits(),
+            Mmio::AccelUnknown => self.accel.unknown,
             Mmio::AccelRaw => {
                 let value = self.read_accel_raw(sys);
                 self.increment_accel_curr(AccelOverflow::RawRead);
@@ -1055,22 +1314,22 @@ impl Interpreter {
             Mmio::CpuMailboxHigh => sys.dsp.cpu_mailbox.high_and_status(),
             Mmio::CpuMailboxLow => {
                 if sys.dsp.cpu_mailbox.status() {
-                    tracing::trace!(
-                        "received from CPU mailbox: 0x{:08X}",
-                        sys.dsp.cpu_mailbox.data().value()
-                    );
+                    let data = sys.dsp.cpu_mailbox.data().value();
+                    tracing::trace!("received from CPU mailbox: 0x{data:08X}");
+                    self.record_mail(sys, MailDirection::CpuToDsp, data);
                     sys.dsp.cpu_mailbox.set_status(false);
                 }
 
                 sys.dsp.cpu_mailbox.low()
             }
-            _ => unimplemented!("read from {mmio:?}"),
+            _ => unreachable!("coefficients are handled above"),
         }
     }
 
     pub fn write_mmio(&mut self, sys: &mut System, offset: u8, value: u16) {
         let Some(mmio) = Mmio::from_repr(offset) else {
-            panic!("writing to unknown MMIO 0x{offset:02X}");
+            self.probe_mmio(offset, Some(value));
+            return;
         };
 
         match mmio {
@@ -1099,16 +1358,19 @@ impl Interpreter {
             Mmio::DmaRamAddrLow => {
                 sys.dsp.dsp_dma.ram_base = sys.dsp.dsp_dma.ram_base.with_bits(0, 16, value as u32)
             }
+            Mmio::DmaMasked => self.accel.dma_masked = value.bit(0),
 
             // Interrupt
             Mmio::InterruptRequest => {
-                if value > 0 {
+                // only bit 0 requests an interrupt, the rest are ignored
+                if value.bit(0) {
                     sys.dsp.control.set_dsp_interrupt(true);
                 }
             }
 
             // Accelerator
             Mmio::AccelFormat => self.accel.format = AccelFormat::from_bits(value),
+            Mmio::AccelUnknown => self.accel.unknown = value,
             Mmio::AccelRaw => {
                 tracing::debug!(
                     "accelerator writing 0x{value:04X} to ARAM 0x{:08X} (wraps at 0x{:08X})",
@@ -1122,10 +1384,7 @@ impl Interpreter {
                         .as_mut_bytes(),
                 );
 
-                self.accel.aram_curr += 1;
-                if self.accel.aram_curr > self.accel.aram_end {
-                    todo!("should wrap");
-                }
+                self.increment_accel_curr(AccelOverflow::RawWrite);
             }
             Mmio::AccelStartAddrHigh => {
                 self.accel.aram_start = self.accel.aram_start.with_bits(16, 32, value as u32)
@@ -1153,6 +1412,9 @@ impl Interpreter {
                 self.accel.previous_samples[1] = value as i16;
                 self.accel.has_data = true;
             }
+            Mmio::AccelSample => {
+                tracing::debug!("ignoring write of 0x{value:04X} to the accelerator sample");
+            }
             Mmio::AccelGain => self.accel.gain = value as i16,
             Mmio::AccelInput => self.accel.input = value as i16,
 
@@ -1163,11 +1425,40 @@ impl Interpreter {
             Mmio::DspMailboxLow => {
                 sys.dsp.dsp_mailbox.set_low(value);
                 sys.dsp.dsp_mailbox.set_status(true);
+                let data = sys.dsp.dsp_mailbox.data().value();
+                self.record_mail(sys, MailDirection::DspToCpu, data);
+            }
+            Mmio::CpuMailboxHigh | Mmio::CpuMailboxLow => {
+                tracing::debug!("ignoring write of 0x{value:04X} to the CPU mailbox");
             }
-            _ => unimplemented!("write to {mmio:?}"),
+            _ => unreachable!("coefficients are handled above"),
+        }
+    }
+
+    fn record_mail(&mut self, sys: &System, direction: MailDirection, data: u32) {
+        if self.mail_history.len() == MAIL_HISTORY_LEN {
+            self.mail_history.pop_front();
+        }
+
+        self.mail_history.push_back(Mail { direction, data });
+
+        if let Some(trace) = &mut self.mailbox_trace {
+            trace.push(MailboxEvent {
+                direction,
+                data,
+                cycle: sys.scheduler.elapsed(),
+            });
         }
     }
 
+    /// Takes the mails recorded in the mailbox trace so far, if it is enabled.
+    pub fn take_mailbox_trace(&mut self) -> Vec<MailboxEvent> {
+        self.mailbox_trace
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
     /// Reads from data memory.
     pub fn read_dmem(&mut self, sys: &mut System, addr: u16) -> u16 {
         match addr {
@@ -1290,13 +1581,17 @@ impl Interpreter {
         ];
 
         let current = [
-            self.read_imem(start),
-            self.read_imem(start.wrapping_add(1)),
-            self.read_imem(start.wrapping_add(2)),
-            self.read_imem(start.wrapping_add(3)),
-            self.read_imem(start.wrapping_add(4)),
+            self.try_read_imem(start),
+            self.try_read_imem(start.wrapping_add(1)),
+            self.try_read_imem(start.wrapping_add(2)),
+            self.try_read_imem(start.wrapping_add(3)),
+            self.try_read_imem(start.wrapping_add(4)),
         ];
 
+        let Some(current) = current.try_map(|x| x) else {
+            return false;
+        };
+
         current == pattern_a || current == pattern_b
     }
 
@@ -1307,6 +1602,18 @@ impl Interpreter {
             || self.is_waiting_for_dsp_mail_inner(-3)
     }
 
+    /// Returns which mail the loop starting at the current PC is waiting on, if it's a mail wait
+    /// loop.
+    fn mail_wait_loop(&mut self) -> Option<MailDirection> {
+        if self.is_waiting_for_cpu_mail_inner(0) {
+            Some(MailDirection::CpuToDsp)
+        } else if self.is_waiting_for_dsp_mail_inner(0) {
+            Some(MailDirection::DspToCpu)
+        } else {
+            None
+        }
+    }
+
     fn fetch_decode_and_cache(&mut self) -> CachedIns {
         // fetch
         let mut ins = Ins::new(self.read_imem(self.pc));
@@ -1341,17 +1648,37 @@ impl Interpreter {
         cached
     }
 
-    pub fn exec(&mut self, sys: &mut System, instructions: u32) {
+    fn exec_inner<const BREAKPOINTS: bool>(
+        &mut self,
+        sys: &mut System,
+        instructions: u32,
+    ) -> Executed {
         let mut i = 0;
         while i < instructions {
             if sys.dsp.control.halt() {
                 std::hint::cold_path();
-                break;
+                return Executed {
+                    instructions: i,
+                    exit: Exit::Halted,
+                };
             }
 
             self.check_loop();
             self.check_interrupts(sys);
 
+            if BREAKPOINTS {
+                if !self.skip_breakpoint && self.breakpoints.contains(&self.pc) {
+                    std::hint::cold_path();
+                    self.skip_breakpoint = true;
+                    return Executed {
+                        instructions: i,
+                        exit: Exit::Breakpoint,
+                    };
+                }
+
+                self.skip_breakpoint = false;
+            }
+
             // have we cached this instruction already?
             let ins = if let Some(cached) = self.cached[self.pc as usize] {
                 cached
@@ -1361,8 +1688,9 @@ impl Interpreter {
             };
 
             // execute
+            let pc = self.pc;
             if let Some(extension) = ins.extension {
-                let regs_previous = self.regs.clone();
+                let regs_previous = ExtensionRegisters::capture(&self.regs);
                 (ins.main)(self, sys, ins.ins);
                 (extension)(self, sys, ins.ins, &regs_previous);
             } else {
@@ -1371,10 +1699,524 @@ impl Interpreter {
 
             self.pc = self.pc.wrapping_add(ins.len);
             i += 1;
+
+            // mail wait loops end with a short backward jump, so only look for them after one
+            let jumped_back = pc.wrapping_sub(self.pc);
+            if (1..=MAIL_WAIT_MAX_JUMP).contains(&jumped_back)
+                && let Some(direction) = self.mail_wait_loop()
+                && direction.is_blocked(sys)
+            {
+                self.mail_wait = Some((self.pc, direction));
+                return Executed {
+                    instructions: i,
+                    exit: Exit::WaitingForMail,
+                };
+            }
+        }
+
+        Executed {
+            instructions: i,
+            exit: Exit::Budget,
         }
     }
 
+    /// Executes the DSP for at most `cycles` cycles, with every instruction taking a single cycle.
+    ///
+    /// Execution stops early if the DSP halts, if it starts busy waiting for mail or if a
+    /// breakpoint is reached. While the DSP keeps waiting for the same mail, following calls
+    /// return immediately without executing anything.
+    ///
+    /// When execution stops at a breakpoint, the next call resumes past it.
+    pub fn exec(&mut self, sys: &mut System, cycles: u32) -> Executed {
+        if sys.dsp.control.halt() {
+            std::hint::cold_path();
+            self.check_interrupts(sys);
+            return Executed {
+                instructions: 0,
+                exit: Exit::Halted,
+            };
+        }
+
+        if let Some((start, direction)) = self.mail_wait {
+            if self.pc == start && direction.is_blocked(sys) {
+                // an interrupt can still get the DSP out of the loop
+                self.check_interrupts(sys);
+                if self.pc == start {
+                    return Executed {
+                        instructions: 0,
+                        exit: Exit::WaitingForMail,
+                    };
+                }
+            }
+
+            self.mail_wait = None;
+        }
+
+        if self.breakpoints.is_empty() {
+            self.exec_inner::<false>(sys, cycles)
+        } else {
+            self.exec_inner::<true>(sys, cycles)
+        }
+    }
+
+    /// Executes a single instruction, ignoring breakpoints.
     pub fn step(&mut self, sys: &mut System) {
-        self.exec(sys, 1);
+        self.exec_inner::<false>(sys, 1);
+        self.skip_breakpoint = false;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lazuli::Address;
+    use lazuli::system::dspi::{self, DspDmaControl, DspDmaDirection, DspDmaTarget};
+    use lazuli::system::{self, Modules, System};
+
+    use super::{
+        Acc40, AccelCoefficients, AccelFormat, Exit, ExtensionRegisters, IROM_SIZE, Interpreter,
+        MailDirection, Mmio, PcmDivisor, Product, Reg, Registers, SampleDecoding, SampleSize,
+        decode_adpcm_sample, decode_pcm_sample,
+    };
+
+    const MAX: i64 = (1 << 39) - 1;
+
+    fn system() -> System {
+        System::new(Modules::nop(), system::Config::default())
+    }
+
+    fn acc(value: i64) -> Acc40 {
+        Acc40::from(value)
+    }
+
+    fn registers(sign_extend_to_40: bool) -> Registers {
+        let mut regs = Registers::default();
+        regs.status.set_sign_extend_to_40(sign_extend_to_40);
+        regs
+    }
+
+    #[test]
+    fn acc40_get_set() {
+        let values = [
+            0,
+            1,
+            -1,
+            0x7FFF,
+            0x8000,
+            i32::MAX as i64,
+            i32::MIN as i64,
+            MAX,
+            Acc40::MIN,
+        ];
+
+        for value in values {
+            assert_eq!(acc(value).get(), value, "{value:#X}");
+        }
+
+        let parts = acc(-1);
+        assert_eq!((parts.high, parts.mid, parts.low), (0xFF, 0xFFFF, 0xFFFF));
+
+        let parts = acc(0x12_3456_789A);
+        assert_eq!((parts.high, parts.mid, parts.low), (0x12, 0x3456, 0x789A));
+
+        // values wrap around to 40 bits
+        let mut value = Acc40::default();
+        assert_eq!(value.set(MAX + 1), Acc40::MIN);
+        assert_eq!(value.set(Acc40::MIN - 1), MAX);
+        assert_eq!(value.set(1 << 40), 0);
+        assert_eq!(value.set(0x80_0000_0000), Acc40::MIN);
+    }
+
+    #[test]
+    fn acc40_high_sign_extends() {
+        let mut regs = registers(false);
+        regs.acc40[0] = acc(0x7F_0000_0000);
+        regs.acc40[1] = acc(Acc40::MIN);
+
+        assert_eq!(regs.get_pure(Reg::Acc40High0), 0x007F);
+        assert_eq!(regs.get_pure(Reg::Acc40High1), 0xFF80);
+
+        // writes keep only the low 8 bits
+        regs.set(Reg::Acc40High0, 0x1280);
+        assert_eq!(regs.acc40[0].get(), Acc40::MIN);
+    }
+
+    #[test]
+    fn acc40_mid_saturated_read() {
+        let cases = [
+            // fits in 32 bits: not saturated
+            (0x0000_7FFF_FFFF, 0x7FFF),
+            (-0x0000_8000_0000, 0x8000),
+            (0x0000_1234_5678, 0x1234),
+            (-1, 0xFFFF),
+            // doesn't fit in 32 bits: saturated towards the sign
+            (0x0000_8000_0000, 0x7FFF),
+            (0x0001_0000_0000, 0x7FFF),
+            (MAX, 0x7FFF),
+            (-0x0000_8000_0001, 0x8000),
+            (-0x0001_0000_0000, 0x8000),
+            (Acc40::MIN, 0x8000),
+        ];
+
+        for (value, expected) in cases {
+            let mut regs = registers(true);
+            regs.acc40[0] = acc(value);
+            regs.acc40[1] = acc(value);
+            assert_eq!(regs.get_pure(Reg::Acc40Mid0), expected, "{value:#X}");
+            assert_eq!(regs.get_pure(Reg::Acc40Mid1), expected, "{value:#X}");
+
+            // without 40-bit mode, the raw middle is read
+            regs.status.set_sign_extend_to_40(false);
+            assert_eq!(regs.get_pure(Reg::Acc40Mid0), acc(value).mid, "{value:#X}");
+        }
+    }
+
+    #[test]
+    fn acc40_mid_saturated_write() {
+        let mut regs = registers(true);
+        regs.acc40[0] = acc(0x12_3456_789A);
+        regs.set_saturate(Reg::Acc40Mid0, 0x7FFF);
+        assert_eq!(regs.acc40[0].get(), 0x00_7FFF_0000);
+
+        regs.set_saturate(Reg::Acc40Mid0, 0x8000);
+        assert_eq!(regs.acc40[0].get(), -0x8000_0000);
+        assert_eq!(regs.get_pure(Reg::Acc40High0), 0xFFFF);
+
+        regs.set_saturate(Reg::Acc40Mid1, 0xFFFF);
+        assert_eq!(regs.acc40[1].get(), -0x1_0000);
+
+        // without 40-bit mode, only the middle is written
+        let mut regs = registers(false);
+        regs.acc40[0] = acc(0x12_3456_789A);
+        regs.set_saturate(Reg::Acc40Mid0, 0x8000);
+        assert_eq!(regs.acc40[0].get(), 0x12_8000_789A);
+
+        // plain writes never sign extend
+        let mut regs = registers(true);
+        regs.acc40[0] = acc(0x12_3456_789A);
+        regs.set(Reg::Acc40Mid0, 0x8000);
+        assert_eq!(regs.acc40[0].get(), 0x12_8000_789A);
+    }
+
+    #[test]
+    fn product_get() {
+        fn product(low: u16, mid1: u16, mid2: u16, high: u8) -> Product {
+            Product {
+                low,
+                mid1,
+                mid2,
+                high,
+            }
+        }
+
+        // (product, carry, overflow, value)
+        let cases = [
+            (product(0x5678, 0x1234, 0, 0x00), false, false, 0x1234_5678),
+            (product(1, 0x1000, 0x2000, 0), false, false, 0x3000_0001),
+            // the middle parts carry into the high part
+            (product(0, 0xFFFF, 1, 0), false, false, 0x1_0000_0000),
+            (product(0, 0x8000, 0x8000, 0x12), false, false, 0x13 << 32),
+            // negative values are sign extended from 40 bits
+            (product(0xFFFF, 0xFFFF, 0, 0xFF), false, false, -1),
+            (product(0, 0, 0, 0x80), false, false, Acc40::MIN),
+            // the cleared state (see `clrp`) carries out of the high part and is zero
+            (product(0, 0xFFF0, 0x10, 0xFF), true, false, 0),
+            // carrying into the sign of the high part overflows
+            (product(0, 0xFFFF, 1, 0x7F), false, true, Acc40::MIN),
+        ];
+
+        for (product, carry, overflow, value) in cases {
+            assert_eq!(product.get(), (carry, overflow, value), "{product:?}");
+        }
+    }
+
+    #[test]
+    fn product_set() {
+        for value in [0, 1, -1, 0x1234_5678, -0x1234_5678, MAX, Acc40::MIN] {
+            let mut product = Product::default();
+            product.set(value);
+            assert_eq!(product.get(), (false, false, value), "{value:#X}");
+            assert_eq!(product.mid1, 0);
+        }
+    }
+
+    #[test]
+    fn reserved_accel_format() {
+        assert_eq!(SampleSize::Reserved.effective(), SampleSize::Word);
+        assert_eq!(SampleSize::Reserved.size(), SampleSize::Word.size());
+
+        assert_eq!(PcmDivisor::Reserved.effective(), PcmDivisor::D65536);
+        assert_eq!(PcmDivisor::Reserved.value(), 65536);
+        for value in [0, 0x8000, 0x1_8000, -0x1_8000, i32::MAX >> 1] {
+            assert_eq!(
+                PcmDivisor::Reserved.apply(value),
+                PcmDivisor::D65536.apply(value)
+            );
+        }
+    }
+
+    #[test]
+    fn pcm_decoding() {
+        let coeffs = |a, b| AccelCoefficients { a, b };
+
+        // (divisor, coefficients, history, gain, input, expected)
+        #[rustfmt::skip]
+        let vectors = [
+            (PcmDivisor::D2048, coeffs(0, 0), [0, 0], 0x0800, 1000, 1000),
+            // the gain doesn't scale the prediction
+            (PcmDivisor::D2048, coeffs(0x0800, 0), [500, 0], 0x0400, 1000, 1000),
+            (PcmDivisor::D2048, coeffs(0x0800, 0), [500, 0], 0, 1000, 500),
+            (PcmDivisor::D2048, coeffs(0x0FA0, -0x0700), [300, -200], 0x1000, -3, 755),
+            (PcmDivisor::D1, coeffs(1, -1), [10, 4], 3, 7, 27),
+            (PcmDivisor::D65536, coeffs(0, 0), [0, 0], 0x7FFF, 0x4000, 8192),
+        ];
+
+        for (divisor, coeffs, history, gain, input, expected) in vectors {
+            assert_eq!(
+                decode_pcm_sample(divisor, coeffs, history, gain, input),
+                expected,
+                "{divisor:?} {coeffs:?} {history:?} gain {gain} input {input}"
+            );
+        }
+    }
+
+    #[test]
+    fn adpcm_decoding() {
+        let coeffs = |a, b| AccelCoefficients { a, b };
+
+        // (coefficients, scale, history, nibble, expected)
+        let vectors = [
+            (coeffs(0x0800, 0), 4, [100, 0], 0xF, 84),
+            (coeffs(0x0FA0, -0x0700), 11, [300, -200], 0x7, 15097),
+            (coeffs(0, 0), 0, [0, 0], 0x8, -8),
+            // saturates
+            (coeffs(0x0800, 0), 12, [32000, 0], 0x7, i16::MAX),
+            (coeffs(0x0800, 0), 12, [-32000, 0], 0x8, i16::MIN),
+        ];
+
+        for (coeffs, scale, history, data, expected) in vectors {
+            assert_eq!(
+                decode_adpcm_sample(coeffs, scale, history, data),
+                expected,
+                "{coeffs:?} scale {scale} {history:?} nibble {data:X}"
+            );
+        }
+    }
+
+    #[test]
+    fn mmio_is_total() {
+        let mut sys = system();
+        let mut interpreter = Interpreter::default();
+
+        for offset in 0..=0xFF {
+            interpreter.read_mmio(&mut sys, offset);
+        }
+
+        for offset in 0..=0xFF {
+            interpreter.write_mmio(&mut sys, offset, 0xFFFF);
+        }
+
+        // unknown offsets are recorded, known ones aren't
+        let unknown = (0..=0xFF).filter(|&offset| Mmio::from_repr(offset).is_none());
+        assert!(unknown.eq(interpreter.probed_mmio.keys().copied()));
+
+        let probed = interpreter.probed_mmio[&0xE0];
+        assert_eq!((probed.reads, probed.writes), (1, 1));
+        assert_eq!(probed.last_written, Some(0xFFFF));
+        assert_eq!(interpreter.read_mmio(&mut sys, 0xE0), 0);
+
+        // known registers read back what was written
+        assert_eq!(
+            interpreter.read_mmio(&mut sys, Mmio::AccelUnknown as u8),
+            0xFFFF
+        );
+        assert_eq!(interpreter.read_mmio(&mut sys, Mmio::DmaMasked as u8), 1);
+    }
+
+    #[test]
+    fn interrupt_request() {
+        let mut sys = system();
+        let mut interpreter = Interpreter::default();
+
+        interpreter.write_mmio(&mut sys, Mmio::InterruptRequest as u8, 0x0002);
+        assert!(!sys.dsp.control.dsp_interrupt());
+
+        interpreter.write_mmio(&mut sys, Mmio::InterruptRequest as u8, 0x0001);
+        assert!(sys.dsp.control.dsp_interrupt());
+    }
+
+    #[test]
+    fn dma_completes_after_bus_time() {
+        let mut sys = system();
+        let mut interpreter = Interpreter::default();
+        sys.write_phys_slow::<u16>(Address(0x1000), 0x1234);
+
+        sys.dsp.dsp_dma.ram_base = 0x1000;
+        sys.dsp.dsp_dma.dsp_base = 0x0010;
+        sys.dsp.dsp_dma.length = 0x20;
+        sys.dsp.dsp_dma.control = DspDmaControl::default()
+            .with_dsp_target(DspDmaTarget::Dmem)
+            .with_direction(DspDmaDirection::FromRamToDsp)
+            .with_transfer_ongoing(true);
+
+        // the data is there right away, but the transfer is still ongoing
+        interpreter.do_dma(&mut sys);
+        assert_eq!(interpreter.read_dmem(&mut sys, 0x0010), 0x1234);
+        assert!(sys.dsp.dsp_dma.control.transfer_ongoing());
+
+        // and isn't performed again while in flight
+        sys.write_phys_slow::<u16>(Address(0x1000), 0x5678);
+        interpreter.do_dma(&mut sys);
+        assert_eq!(interpreter.read_dmem(&mut sys, 0x0010), 0x1234);
+
+        let cycles = dspi::dma_stall_cycles(0x20).0;
+        sys.scheduler.advance(cycles - 1);
+        sys.process_events();
+        assert!(sys.dsp.dsp_dma.control.transfer_ongoing());
+
+        sys.scheduler.advance(1);
+        sys.process_events();
+        assert!(!sys.dsp.dsp_dma.control.transfer_ongoing());
+        assert_eq!(sys.dsp.dsp_dma.length, 0);
+        assert_eq!(sys.dsp.dsp_dma_pending, None);
+    }
+
+    #[test]
+    fn mail_wait_exits_early() {
+        // lrs $ACM0, @cmbh; andcf $ACM0, #0x8000; jlnz 0x0000
+        const LOOP: [u16; 5] = [0x26FE, 0x02C0, 0x8000, 0x029C, 0x0000];
+
+        let mut sys = system();
+        let mut interpreter = Interpreter::default();
+        interpreter.mem.iram[..LOOP.len()].copy_from_slice(&LOOP);
+        interpreter.pc = 0;
+
+        // stops after the first iteration
+        let executed = interpreter.exec(&mut sys, 64);
+        assert_eq!(
+            (executed.instructions, executed.exit),
+            (3, Exit::WaitingForMail)
+        );
+        assert_eq!(interpreter.pc, 0);
+
+        // and keeps waiting without executing anything
+        let executed = interpreter.exec(&mut sys, 64);
+        assert_eq!(
+            (executed.instructions, executed.exit),
+            (0, Exit::WaitingForMail)
+        );
+
+        // until mail arrives
+        sys.dsp.cpu_mailbox.set_status(true);
+        let executed = interpreter.exec(&mut sys, 64);
+        assert_eq!((executed.instructions, executed.exit), (64, Exit::Budget));
+
+        sys.dsp.control.set_halt(true);
+        let executed = interpreter.exec(&mut sys, 64);
+        assert_eq!((executed.instructions, executed.exit), (0, Exit::Halted));
+    }
+
+    #[test]
+    fn extension_registers_match() {
+        let mut regs = registers(true);
+        regs.acc40[0] = acc(0x12_3456_789A);
+        regs.acc40[1] = acc(-0x1234);
+        regs.acc32 = [0x1234_5678, -2];
+
+        let captured = ExtensionRegisters::capture(&regs);
+        for reg in (0x18..0x20).map(Reg::new) {
+            assert_eq!(captured.get_pure(reg), regs.get_pure(reg), "{reg:?}");
+        }
+    }
+
+    #[test]
+    fn extensions_see_previous_registers() {
+        // inc $ac0 : s @$ar0, $ac0.l
+        // inc $ac0 : l $ac0.l, @$ar1
+        const CODE: [u16; 2] = [0x7620, 0x7661];
+
+        let mut sys = system();
+        let mut interpreter = Interpreter::default();
+        interpreter.mem.iram[..CODE.len()].copy_from_slice(&CODE);
+        interpreter.mem.dram[0x20] = 0xABCD;
+        interpreter.regs.addressing[0] = 0x10;
+        interpreter.regs.addressing[1] = 0x20;
+        interpreter.regs.acc40[0] = acc(0x1_FFFF);
+        interpreter.pc = 0;
+
+        // the store sees the accumulator from before the increment
+        interpreter.step(&mut sys);
+        assert_eq!(interpreter.mem.dram[0x10], 0xFFFF);
+        assert_eq!(interpreter.regs.acc40[0].get(), 0x2_0000);
+        assert_eq!(interpreter.regs.addressing[0], 0x11);
+
+        // the load is written after the increment, so it wins
+        interpreter.step(&mut sys);
+        assert_eq!(interpreter.regs.acc40[0].get(), 0x2_ABCD);
+        assert_eq!(interpreter.regs.addressing[1], 0x21);
+    }
+
+    #[test]
+    fn sample_sink() {
+        let mut sys = system();
+        let mut interpreter = Interpreter::default();
+        interpreter.accel.format = AccelFormat::default().with_decoding(SampleDecoding::AcinPcm);
+        interpreter.accel.gain = 0x0800;
+        interpreter.write_mmio(&mut sys, Mmio::AccelPrevSample1 as u8, 0);
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        interpreter.sample_sink = Some(sender);
+
+        for input in [1000, -3] {
+            interpreter.accel.input = input;
+            interpreter.read_mmio(&mut sys, Mmio::AccelSample as u8);
+        }
+
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), [1000, -3]);
+
+        // the sink is dropped along with the receiver
+        drop(receiver);
+        interpreter.read_mmio(&mut sys, Mmio::AccelSample as u8);
+        assert!(interpreter.sample_sink.is_none());
+    }
+
+    #[test]
+    fn mailbox_trace() {
+        let mut sys = system();
+        let mut interpreter = Interpreter::default();
+
+        // disabled by default
+        interpreter.write_mmio(&mut sys, Mmio::DspMailboxHigh as u8, 0x1234);
+        interpreter.write_mmio(&mut sys, Mmio::DspMailboxLow as u8, 0x5678);
+        assert!(interpreter.take_mailbox_trace().is_empty());
+
+        interpreter.mailbox_trace = Some(Vec::new());
+        interpreter.write_mmio(&mut sys, Mmio::DspMailboxHigh as u8, 0xABCD);
+        interpreter.write_mmio(&mut sys, Mmio::DspMailboxLow as u8, 0xEF01);
+
+        sys.dsp.cpu_mailbox.set_status(true);
+        interpreter.read_mmio(&mut sys, Mmio::CpuMailboxLow as u8);
+
+        let trace = interpreter.take_mailbox_trace();
+        assert_eq!(trace.len(), 2);
+        assert_eq!(trace[0].direction, MailDirection::DspToCpu);
+        assert_eq!(trace[0].data, 0xABCD_EF01);
+        assert_eq!(trace[1].direction, MailDirection::CpuToDsp);
+
+        // taking the trace keeps it enabled
+        assert!(interpreter.take_mailbox_trace().is_empty());
+        assert!(interpreter.mailbox_trace.is_some());
+    }
+
+    #[test]
+    fn irom_is_big_endian() {
+        let mut interpreter = Interpreter::default();
+        let mut irom = [0; IROM_SIZE];
+        irom[..4].copy_from_slice(&[0x12, 0x34, 0x00, 0x21]);
+        irom[IROM_SIZE - 2..].copy_from_slice(&[0xAB, 0xCD]);
+
+        interpreter.load_irom(&irom);
+        assert_eq!(interpreter.mem.irom[..2], [0x1234, 0x0021]);
+        assert_eq!(interpreter.mem.irom.last(), Some(&0xABCD));
+        assert_eq!(interpreter.read_imem(0x8001), 0x0021);
     }
 }
@@ -1,16 +1,8 @@
-#![feature(trim_prefix_suffix)]
-
 mod file;
 
 use std::fmt::Write;
 
 use dspint::{Interpreter, Registers};
-use lazuli::modules::audio::NopAudioModule;
-use lazuli::modules::debug::NopDebugModule;
-use lazuli::modules::disk::NopDiskModule;
-use lazuli::modules::input::NopInputModule;
-use lazuli::modules::render::NopRenderModule;
-use lazuli::modules::vertex::NopVertexModule;
 use lazuli::system::{self, Modules, System};
 use libtest_mimic::{Arguments, Failed, Trial};
 
@@ -92,24 +84,7 @@ fn run_test(file: file::TestFile, quiet: bool) -> Result<(), Failed> {
     let total = file.cases.len();
     let mut failures = vec![];
 
-    let modules = Modules {
-        audio: Box::new(NopAudioModule),
-        debug: Box::new(NopDebugModule),
-        disk: Box::new(NopDiskModule),
-        input: Box::new(NopInputModule),
-        render: Box::new(NopRenderModule),
-        vertex: Box::new(NopVertexModule),
-    };
-
-    let mut system = System::new(
-        modules,
-        system::Config {
-            ipl: None,
-            sideload: None,
-            ipl_lle: false,
-            perform_efb_copies: false,
-        },
-    );
+    let mut system = System::new(Modules::nop(), system::Config::default());
 
     for (i, case) in file.cases.into_iter().enumerate() {
         let Err(failure) = run_case(&mut system, case) else {
@@ -128,21 +103,18 @@ fn run_test(file: file::TestFile, quiet: bool) -> Result<(), Failed> {
             .iter()
             .map(|(r, v, e)| format!("{r:?}(v={v:04X}, e={e:04X}), "))
             .collect::<String>();
+        let divergences = divergences.strip_suffix(", ").unwrap_or(&divergences);
 
         if early_exit {
             failures.push(format!(
                 "Case {i} failed:\r\nINITIAL: {:04X?}\r\nEXPECTED: {:04X?}\r\nDIVERGENCES: {}\r\nCODE:\r\n{disasm}",
                 failure.initial,
                 failure.expected,
-                divergences.trim_suffix(", "),
+                divergences,
             ));
             break;
         } else {
-            failures.push(format!(
-                "Case {i} failed: {}\r\n{}",
-                divergences.trim_suffix(", "),
-                disasm
-            ));
+            failures.push(format!("Case {i} failed: {divergences}\r\n{disasm}"));
         }
     }
 
@@ -1,11 +1,12 @@
 //! The `powerpc` crate, which is a disassembler of PowerPC instructions, is re-exported under
 //! [`disasm`].
 
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
 
-use bitos::integer::{i6, u2, u4, u5, u7, u11, u15, u27};
+use bitos::integer::{i6, u2, u4, u5, u6, u7, u11, u15, u27};
 use bitos::{BitUtils, bitos};
-use strum::{FromRepr, VariantArray};
+use strum::{FromRepr, IntoStaticStr, VariantArray};
 use util::offset_of;
 use zerocopy::{FromBytes, Immutable, IntoBytes};
 
@@ -50,18 +51,127 @@ impl Address {
         self.0 == 0
     }
 
-    /// Aligns this address down to the given alignment.
+    /// Aligns this address down to the given alignment, which must not be zero.
+    ///
+    /// Prefer [`Self::align_down_pow2`] if the alignment is known to be a power of two.
     pub const fn align_down(self, alignment: u32) -> Self {
+        assert!(alignment != 0, "alignment must not be zero");
         let rem = self.0 % alignment;
-        Self(self.0 - rem)
+        Self(self.0.saturating_sub(rem))
+    }
+
+    /// Aligns this address down to the given alignment, which must be a power of two.
+    #[inline(always)]
+    pub const fn align_down_pow2(self, alignment: u32) -> Self {
+        debug_assert!(
+            alignment.is_power_of_two(),
+            "alignment must be a power of two"
+        );
+        Self(self.0 & !(alignment.wrapping_sub(1)))
     }
 
-    /// Aligns this address up to the given alignment.
+    /// Aligns this address up to the given alignment, which must not be zero.
+    ///
+    /// If the aligned address does not fit in the address space, it saturates to the largest
+    /// aligned address (e.g. aligning `0xFFFF_FFF0` up to `0x100` results in `0xFFFF_FF00`). Use
+    /// [`Self::checked_align_up`] to detect that case.
     pub const fn align_up(self, alignment: u32) -> Self {
-        Self(self.0.next_multiple_of(alignment))
+        assert!(alignment != 0, "alignment must not be zero");
+        match self.0.checked_next_multiple_of(alignment) {
+            Some(value) => Self(value),
+            None => Self(u32::MAX).align_down(alignment),
+        }
+    }
+
+    /// Aligns this address up to the given alignment, returning [`None`] if the alignment is zero
+    /// or if the aligned address does not fit in the address space.
+    pub const fn checked_align_up(self, alignment: u32) -> Option<Self> {
+        match self.0.checked_next_multiple_of(alignment) {
+            Some(value) => Some(Self(value)),
+            None => None,
+        }
+    }
+
+    /// Adds an offset to this address, returning [`None`] if it would go past the end of the
+    /// address space.
+    #[inline(always)]
+    pub const fn checked_add(self, offset: u32) -> Option<Self> {
+        match self.0.checked_add(offset) {
+            Some(value) => Some(Self(value)),
+            None => None,
+        }
+    }
+
+    /// Subtracts an offset from this address, returning [`None`] if it would go past the start
+    /// of the address space.
+    #[inline(always)]
+    pub const fn checked_sub(self, offset: u32) -> Option<Self> {
+        match self.0.checked_sub(offset) {
+            Some(value) => Some(Self(value)),
+            None => None,
+        }
+    }
+
+    /// Returns the offset of this address from the given base, or [`None`] if this address comes
+    /// before it.
+    #[inline(always)]
+    pub const fn offset_from(self, base: Address) -> Option<u32> {
+        self.0.checked_sub(base.0)
     }
+
+    /// Returns an iterator over the addresses from this one up to `end` (inclusive) which are
+    /// aligned to `step`, which must not be zero.
+    ///
+    /// The iterator stops at the end of the address space instead of wrapping around.
+    pub fn range_step(self, end: Address, step: u32) -> AddressStep {
+        assert!(step != 0, "step must not be zero");
+        AddressStep {
+            next: self.checked_align_up(step),
+            end,
+            step,
+        }
+    }
+}
+
+/// Iterator over aligned addresses, created by [`Address::range_step`].
+#[derive(Debug, Clone)]
+pub struct AddressStep {
+    next: Option<Address>,
+    end: Address,
+    step: u32,
 }
 
+impl AddressStep {
+    #[inline(always)]
+    fn remaining(&self) -> usize {
+        match self.next {
+            Some(next) if next <= self.end => ((self.end.0 - next.0) / self.step) as usize + 1,
+            _ => 0,
+        }
+    }
+}
+
+impl Iterator for AddressStep {
+    type Item = Address;
+
+    #[inline(always)]
+    fn next(&mut self) -> Option<Self::Item> {
+        let current = self.next.filter(|&next| next <= self.end)?;
+        self.next = current.checked_add(self.step);
+        Some(current)
+    }
+
+    #[inline(always)]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.remaining();
+        (remaining, Some(remaining))
+    }
+}
+
+impl ExactSizeIterator for AddressStep {}
+
+impl std::iter::FusedIterator for AddressStep {}
+
 impl std::ops::Add<u32> for Address {
     type Output = Self;
 
@@ -159,6 +269,10 @@ impl From<Address> for u32 {
 /// The CPU frequency.
 pub const FREQUENCY: u64 = 486_000_000;
 
+/// Primary opcode of HLE traps. It's unused by the Gekko, so no real code contains it and the
+/// lower 26 bits of a trap are free to identify what it does.
+pub const HLE_OPCODE: u32 = 1;
+
 /// An amount of cycles of the Gekko CPU. This is a thin wrapper around an [`u64`].
 #[derive(
     Debug,
@@ -212,6 +326,22 @@ impl Cycles {
     pub fn to_dsp_cycles(&self) -> f64 {
         self.0 as f64 / 6.0
     }
+
+    /// Subtracts `rhs` from this amount, returning `None` if `rhs` is larger.
+    #[inline(always)]
+    pub const fn checked_sub(self, rhs: Self) -> Option<Self> {
+        match self.0.checked_sub(rhs.0) {
+            Some(value) => Some(Self(value)),
+            None => None,
+        }
+    }
+
+    /// Subtracts `rhs` from this amount, clamping at zero. Use this where `rhs` can legitimately
+    /// be larger, e.g. when a core overshoots its budget.
+    #[inline(always)]
+    pub const fn saturating_sub(self, rhs: Self) -> Self {
+        Self(self.0.saturating_sub(rhs.0))
+    }
 }
 
 impl std::ops::Add<Cycles> for Cycles {
@@ -284,6 +414,8 @@ impl std::ops::Sub<i64> for Cycles {
     }
 }
 
+/// Panics if `rhs` is larger than `self`, which is a logic error. See [`Cycles::saturating_sub`]
+/// and [`Cycles::checked_sub`] for the non-panicking alternatives.
 impl std::ops::Sub<Cycles> for Cycles {
     type Output = Cycles;
 
@@ -375,7 +507,12 @@ pub trait InsExt {
     /// FPR indicated by field frD.
     fn fpr_d(&self) -> FPR;
     /// SPR indicated by field SPR.
+    ///
+    /// # Panics
+    /// Panics if the SPR is unknown. Use [`SPR::from_repr`] on field SPR if it might be.
     fn spr(&self) -> SPR;
+    /// Name of the SPR indicated by field SPR, or `None` if it is unknown.
+    fn spr_name(&self) -> Option<&'static str>;
 }
 
 impl InsExt for disasm::Ins {
@@ -428,11 +565,16 @@ impl InsExt for disasm::Ins {
     fn spr(&self) -> SPR {
         SPR::new(self.field_spr())
     }
+
+    #[inline(always)]
+    fn spr_name(&self) -> Option<&'static str> {
+        SPR::from_repr(self.field_spr()).map(SPR::name)
+    }
 }
 
 /// An exception which can be generated by the Gekko CPU. The variants have the lower 16 bits of the
 /// exception vector as their values.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, VariantArray)]
 #[repr(u16)]
 pub enum Exception {
     Reset              = 0x0100,
@@ -460,6 +602,38 @@ impl Exception {
     }
 }
 
+/// A set of [`Exception`]s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ExceptionSet(u32);
+
+impl ExceptionSet {
+    #[inline(always)]
+    fn bit(exception: Exception) -> u32 {
+        1 << (exception as u16 >> 8)
+    }
+
+    /// Whether the set contains no exceptions.
+    #[inline(always)]
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Whether the given exception is in the set.
+    #[inline(always)]
+    pub fn contains(self, exception: Exception) -> bool {
+        self.0 & Self::bit(exception) != 0
+    }
+
+    /// Adds or removes the given exception from the set.
+    pub fn set(&mut self, exception: Exception, value: bool) {
+        if value {
+            self.0 |= Self::bit(exception);
+        } else {
+            self.0 &= !Self::bit(exception);
+        }
+    }
+}
+
 /// A condition group field in the [`CondReg`].
 #[bitos(4)]
 #[derive(Debug, Clone, Copy, Default)]
@@ -492,11 +666,63 @@ pub struct Cond {
 #[bitos(32)]
 #[derive(Debug, Clone, PartialEq, Default)]
 pub struct CondReg {
-    // NOTE: CR0 is actually index 7! PPC bit order is big endian
+    // NOTE: CR0 is actually index 7! PPC bit order is big endian. Prefer the accessors below, which
+    // take architectural field and bit numbers.
     #[bits(..)]
     pub fields: [Cond; 8],
 }
 
+impl CondReg {
+    /// Index of the stored bit corresponding to the architectural CR bit `bit` (where bit 0 is the
+    /// MSB, i.e. `CR0[LT]`).
+    #[inline(always)]
+    pub const fn crb_index(bit: u8) -> u8 {
+        assert!(bit < 32);
+        31 - bit
+    }
+
+    /// Shift of the architectural CR field `n` in the stored value.
+    #[inline(always)]
+    pub const fn field_shift(n: u8) -> u8 {
+        assert!(n < 8);
+        4 * (7 - n)
+    }
+
+    /// Mask of the architectural CR field `n` in the stored value.
+    #[inline(always)]
+    pub const fn field_mask(n: u8) -> u32 {
+        0xF << Self::field_shift(n)
+    }
+
+    /// Returns the architectural CR field `n`.
+    #[inline(always)]
+    pub fn field(&self, n: u8) -> Cond {
+        let shift = Self::field_shift(n);
+        Cond::from_bits(u4::new(((self.to_bits() >> shift) & 0xF) as u8))
+    }
+
+    /// Sets the architectural CR field `n`.
+    #[inline(always)]
+    pub fn set_field(&mut self, n: u8, value: Cond) {
+        let shift = Self::field_shift(n);
+        let bits =
+            (self.to_bits() & !Self::field_mask(n)) | ((value.to_bits().value() as u32) << shift);
+        *self = Self::from_bits(bits);
+    }
+
+    /// Returns the architectural CR bit `bit`.
+    #[inline(always)]
+    pub fn crb(&self, bit: u8) -> bool {
+        self.to_bits().bit(Self::crb_index(bit))
+    }
+
+    /// Sets the architectural CR bit `bit`.
+    #[inline(always)]
+    pub fn set_crb(&mut self, bit: u8, value: bool) {
+        *self = Self::from_bits(self.to_bits().with_bit(Self::crb_index(bit), value));
+    }
+}
+
 /// The Machine State register.
 #[bitos(32)]
 #[derive(Debug, Clone, PartialEq)]
@@ -942,6 +1168,27 @@ impl DmaConfig {
     }
 }
 
+/// The Hardware Implementation Dependent register 2.
+#[bitos(32)]
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Hid2 {
+    /// Whether paired single loads and stores are enabled.
+    #[bits(31)]
+    pub paired_single_load_store: bool,
+    /// Whether the write gather pipe is enabled.
+    #[bits(30)]
+    pub write_gather_pipe: bool,
+    /// Whether paired single instructions are enabled.
+    #[bits(29)]
+    pub paired_single: bool,
+    /// Whether half of the L1 data cache is locked and usable as scratch memory.
+    #[bits(28)]
+    pub locked_cache: bool,
+    /// Length of the locked cache DMA queue.
+    #[bits(24..28)]
+    pub dma_queue_length: u4,
+}
+
 /// Configuration registers.
 #[derive(Debug, Clone, PartialEq, Default)]
 pub struct Configuration {
@@ -955,6 +1202,14 @@ pub struct Configuration {
     pub dma: DmaConfig,
 }
 
+impl Configuration {
+    /// The HID2 register.
+    #[inline(always)]
+    pub fn hid2(&self) -> Hid2 {
+        Hid2::from_bits(self.hid[2])
+    }
+}
+
 /// A quantized type.
 #[bitos(3)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
@@ -971,12 +1226,53 @@ pub enum QuantizedType {
 }
 
 impl QuantizedType {
-    pub fn size(&self) -> u8 {
+    /// The type the hardware actually uses: reserved types behave as [`Float`](Self::Float).
+    pub fn effective(self) -> Self {
         match self {
-            Self::Float => 4,
+            Self::Reserved0 | Self::Reserved1 | Self::Reserved2 => {
+                static WARNED: AtomicBool = AtomicBool::new(false);
+                if !WARNED.swap(true, Ordering::Relaxed) {
+                    tracing::warn!("reserved quantized type {self:?} used, treating it as float");
+                }
+
+                Self::Float
+            }
+            _ => self,
+        }
+    }
+
+    /// Size of a value of this type, in bytes.
+    pub fn size(&self) -> u8 {
+        match self.effective() {
             Self::U8 | Self::I8 => 1,
             Self::U16 | Self::I16 => 2,
-            _ => panic!("reserved quantized type"),
+            _ => 4,
+        }
+    }
+
+    /// Converts the raw bits of a value of this type, as read from memory, into a float. Signed
+    /// types are sign extended.
+    #[inline(always)]
+    pub fn to_float(self, raw: u32) -> f64 {
+        match self {
+            Self::U8 => raw as u8 as f64,
+            Self::U16 => raw as u16 as f64,
+            Self::I8 => raw as u8 as i8 as f64,
+            Self::I16 => raw as u16 as i16 as f64,
+            _ => f32::from_bits(raw) as f64,
+        }
+    }
+
+    /// Converts a float into the raw bits of a value of this type, saturating it to the range of
+    /// the type.
+    #[inline(always)]
+    pub fn from_float(self, value: f64) -> u32 {
+        match self {
+            Self::U8 => value as u8 as u32,
+            Self::U16 => value as u16 as u32,
+            Self::I8 => value as i8 as u8 as u32,
+            Self::I16 => value as i16 as u16 as u32,
+            _ => (value as f32).to_bits(),
         }
     }
 }
@@ -999,15 +1295,61 @@ pub struct QuantReg {
     pub load_scale: i6,
 }
 
+impl QuantReg {
+    /// Index of a scale into [`DEQUANTIZATION_LUT`] and [`QUANTIZATION_LUT`], i.e. its 6-bit two's
+    /// complement encoding.
+    #[inline(always)]
+    fn lut_index(scale: i6) -> usize {
+        (scale.value() as usize) & 0b0011_1111
+    }
+
+    /// Factor by which values loaded by `psq_l` are multiplied, i.e. `2^-load_scale`. Floats are
+    /// never scaled.
+    #[inline(always)]
+    pub fn load_factor(&self) -> f64 {
+        if self.load_type().effective() == QuantizedType::Float {
+            return 1.0;
+        }
+
+        DEQUANTIZATION_LUT[Self::lut_index(self.load_scale())]
+    }
+
+    /// Factor by which values stored by `psq_st` are multiplied, i.e. `2^store_scale`. Floats are
+    /// never scaled.
+    #[inline(always)]
+    pub fn store_factor(&self) -> f64 {
+        if self.store_type().effective() == QuantizedType::Float {
+            return 1.0;
+        }
+
+        QUANTIZATION_LUT[Self::lut_index(self.store_scale())]
+    }
+
+    /// Dequantizes a value loaded by `psq_l`, given its raw bits in memory.
+    #[inline(always)]
+    pub fn dequantize(&self, raw: u32) -> f64 {
+        self.load_type().to_float(raw) * self.load_factor()
+    }
+
+    /// Quantizes a value stored by `psq_st`, returning its raw bits in memory.
+    #[inline(always)]
+    pub fn quantize(&self, value: f64) -> u32 {
+        self.store_type().from_float(value * self.store_factor())
+    }
+}
+
+/// Dequantization factors, indexed by the 6-bit encoding of a scale. See
+/// [`QuantReg::load_factor`].
 pub static DEQUANTIZATION_LUT: [f64; 1 << 6] = {
     let mut result = [0.0; 1 << 6];
 
     let mut i = 0;
     loop {
+        // sign extend the 6-bit scale
         let scale = ((i as i8) << 2) >> 2;
         let exp = scale.unsigned_abs();
         let factor = if scale >= 0 {
-            1.0 / ((1 << exp) as f64)
+            1.0 / ((1u64 << exp) as f64)
         } else {
             (1u64 << exp) as f64
         };
@@ -1023,6 +1365,8 @@ pub static DEQUANTIZATION_LUT: [f64; 1 << 6] = {
     result
 };
 
+/// Quantization factors, indexed by the 6-bit encoding of a scale. See
+/// [`QuantReg::store_factor`].
 pub static QUANTIZATION_LUT: [f64; 1 << 6] = {
     let mut result = DEQUANTIZATION_LUT;
 
@@ -1050,6 +1394,81 @@ pub struct Miscellaneous {
     pub l2cr: u32,
 }
 
+/// An event which can be counted by the performance monitor counters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, FromRepr, VariantArray)]
+#[repr(u8)]
+pub enum PmcEvent {
+    /// An instruction completed.
+    InstructionComplete,
+    /// A branch was taken.
+    BranchTaken,
+    /// An instruction fetch missed the L1 instruction cache.
+    ICacheMiss,
+    /// A data access missed the L1 data cache.
+    DCacheMiss,
+}
+
+impl PmcEvent {
+    /// The event selection value of counter `n` (PMC1 is 0) which counts this event, if any.
+    pub fn selection(self, n: usize) -> Option<u8> {
+        match (self, n) {
+            (Self::InstructionComplete, 0..4) => Some(0b00_0010),
+            (Self::ICacheMiss, 1) => Some(0b00_0101),
+            (Self::DCacheMiss, 2) => Some(0b00_0101),
+            (Self::BranchTaken, 3) => Some(0b00_1000),
+            _ => None,
+        }
+    }
+}
+
+/// Monitor Mode Control Register 0 (MMCR0).
+#[bitos(32)]
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MonitorControl0 {
+    /// Event selection of PMC2.
+    #[bits(0..6)]
+    pub pmc2_select: u6,
+    /// Event selection of PMC1.
+    #[bits(6..13)]
+    pub pmc1_select: u7,
+    /// Whether PMC2-PMC4 becoming negative signals a performance monitor interrupt.
+    #[bits(14)]
+    pub pmcn_interrupt: bool,
+    /// Whether PMC1 becoming negative signals a performance monitor interrupt.
+    #[bits(15)]
+    pub pmc1_interrupt: bool,
+    /// Whether performance monitor interrupts are enabled.
+    #[bits(26)]
+    pub interrupts: bool,
+    /// Disables counting while MSR[PM] is clear.
+    #[bits(27)]
+    pub disable_unmarked: bool,
+    /// Disables counting while MSR[PM] is set.
+    #[bits(28)]
+    pub disable_marked: bool,
+    /// Disables counting in user mode.
+    #[bits(29)]
+    pub disable_user: bool,
+    /// Disables counting in supervisor mode.
+    #[bits(30)]
+    pub disable_supervisor: bool,
+    /// Disables counting unconditionally.
+    #[bits(31)]
+    pub disable: bool,
+}
+
+/// Monitor Mode Control Register 1 (MMCR1).
+#[bitos(32)]
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MonitorControl1 {
+    /// Event selection of PMC4.
+    #[bits(22..27)]
+    pub pmc4_select: u5,
+    /// Event selection of PMC3.
+    #[bits(27..32)]
+    pub pmc3_select: u5,
+}
+
 /// Performance monitor registers.
 #[derive(Debug, Clone, PartialEq, Default)]
 pub struct PerformanceMonitor {
@@ -1059,6 +1478,73 @@ pub struct PerformanceMonitor {
     pub control: [u32; 2],
 }
 
+impl PerformanceMonitor {
+    /// Mask of the event selection fields in MMCR0 and MMCR1. Nothing is counted if all of them
+    /// are zero.
+    pub const SELECT_MASK: [u32; 2] = [0x0000_1FFF, 0xFFC0_0000];
+
+    #[inline(always)]
+    pub fn mmcr0(&self) -> MonitorControl0 {
+        MonitorControl0::from_bits(self.control[0])
+    }
+
+    #[inline(always)]
+    pub fn mmcr1(&self) -> MonitorControl1 {
+        MonitorControl1::from_bits(self.control[1])
+    }
+
+    /// The event selection value of counter `n` (PMC1 is 0).
+    pub fn selection(&self, n: usize) -> u8 {
+        match n {
+            0 => self.mmcr0().pmc1_select().value(),
+            1 => self.mmcr0().pmc2_select().value(),
+            2 => self.mmcr1().pmc3_select().value(),
+            3 => self.mmcr1().pmc4_select().value(),
+            _ => panic!("no performance counter {n}"),
+        }
+    }
+
+    /// Whether the counters count events in the given machine state.
+    pub fn counting(&self, msr: &MachineState) -> bool {
+        let mmcr0 = self.mmcr0();
+        !(mmcr0.disable()
+            || (mmcr0.disable_user() && msr.user_mode())
+            || (mmcr0.disable_supervisor() && !msr.user_mode())
+            || (mmcr0.disable_marked() && msr.performance_monitor())
+            || (mmcr0.disable_unmarked() && !msr.performance_monitor()))
+    }
+
+    /// Adds `count` occurrences of `event` to every counter which selects it. Returns whether a
+    /// counter became negative while it is allowed to signal a performance monitor interrupt.
+    pub fn increment(&mut self, event: PmcEvent, count: u32, msr: &MachineState) -> bool {
+        if count == 0 || !self.counting(msr) {
+            return false;
+        }
+
+        let mmcr0 = self.mmcr0();
+        let mut interrupt = false;
+        for n in 0..4 {
+            if event.selection(n) != Some(self.selection(n)) {
+                continue;
+            }
+
+            let old = self.counters[n];
+            let new = old.wrapping_add(count);
+            self.counters[n] = new;
+
+            let enabled = if n == 0 {
+                mmcr0.pmc1_interrupt()
+            } else {
+                mmcr0.pmcn_interrupt()
+            };
+
+            interrupt |= enabled && (old as i32) >= 0 && (new as i32) < 0;
+        }
+
+        interrupt && mmcr0.interrupts()
+    }
+}
+
 /// Supervisor level registers.
 #[repr(C)]
 #[derive(Debug, Clone, PartialEq, Default)]
@@ -1087,6 +1573,10 @@ pub struct Cpu {
     pub user: User,
     /// Supervisor level registers
     pub supervisor: Supervisor,
+    /// Exceptions which should stop execution once taken. Not part of the architectural state.
+    pub armed_exceptions: ExceptionSet,
+    /// The last armed exception taken, if any. Cleared by whoever stops execution because of it.
+    pub caught_exception: Option<Exception>,
 }
 
 impl Cpu {
@@ -1124,6 +1614,11 @@ impl Cpu {
         };
 
         self.pc = Address(base | exception as u32);
+
+        if self.armed_exceptions.contains(exception) {
+            std::hint::cold_path();
+            self.caught_exception = Some(exception);
+        }
     }
 }
 
@@ -1238,7 +1733,7 @@ impl FPR {
 }
 
 /// A Special Purpose Register.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, FromRepr, VariantArray)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, FromRepr, IntoStaticStr, VariantArray)]
 #[repr(u16)]
 pub enum SPR {
     XER    = 1,
@@ -1319,6 +1814,12 @@ impl SPR {
         }
     }
 
+    /// Name of this SPR, e.g. `LR` or `GQR3`.
+    #[inline(always)]
+    pub fn name(self) -> &'static str {
+        self.into()
+    }
+
     /// Offset of this SPR in the [`Cpu`] struct.
     pub fn offset(self) -> usize {
         match self {
@@ -1527,3 +2028,357 @@ impl From<SPR> for Reg {
         Self::SPR(value)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use bitos::integer::u4;
+    use strum::VariantArray;
+
+    use super::{
+        Address, Cond, CondReg, Cpu, DEQUANTIZATION_LUT, Exception, ExceptionSet, InsExt,
+        QUANTIZATION_LUT, QuantReg, QuantizedType, disasm,
+    };
+
+    /// Values covering the whole `u32` range: a sparse sweep plus the edges of the address space.
+    fn values() -> impl Iterator<Item = u32> {
+        let sweep = (0..=u32::MAX).step_by(65_521);
+        let edges = (0..=0x1000).chain(u32::MAX - 0x1000..=u32::MAX);
+
+        sweep.chain(edges)
+    }
+
+    fn alignments() -> impl Iterator<Item = u32> {
+        (0..32)
+            .map(|shift| 1 << shift)
+            .chain([3, 12, 100, 0x8000_0001, u32::MAX])
+    }
+
+    #[test]
+    fn align_down() {
+        for alignment in alignments() {
+            for value in values() {
+                let aligned = Address(value).align_down(alignment);
+                assert_eq!(aligned.value() % alignment, 0);
+                assert!(aligned.value() <= value);
+                assert!(value - aligned.value() < alignment);
+
+                if alignment.is_power_of_two() {
+                    assert_eq!(Address(value).align_down_pow2(alignment), aligned);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn align_up() {
+        for alignment in alignments() {
+            for value in values() {
+                let expected = (value as u64).next_multiple_of(alignment as u64);
+                let checked = Address(value).checked_align_up(alignment);
+                let saturated = Address(value).align_up(alignment);
+
+                assert_eq!(saturated.value() % alignment, 0);
+                match u32::try_from(expected) {
+                    Ok(expected) => {
+                        assert_eq!(checked, Some(Address(expected)));
+                        assert_eq!(saturated, Address(expected));
+                    }
+                    Err(_) => {
+                        assert_eq!(checked, None);
+                        assert_eq!(saturated, Address(u32::MAX).align_down(alignment));
+                    }
+                }
+            }
+        }
+
+        assert_eq!(Address(0x1234).checked_align_up(0), None);
+    }
+
+    #[test]
+    fn align_saturates() {
+        assert_eq!(Address(0xFFFF_FFF0).align_up(0x100), Address(0xFFFF_FF00));
+        assert_eq!(Address(0xFFFF_FFFF).align_up(2), Address(0xFFFF_FFFE));
+        assert_eq!(Address(0xFFFF_FFFF).align_up(u32::MAX), Address(u32::MAX));
+        assert_eq!(Address(0xFFFF_FFFF).align_up(1), Address(u32::MAX));
+        assert_eq!(Address(0xFFFF_FFFF).align_down(4), Address(0xFFFF_FFFC));
+        assert_eq!(Address(0xFFFF_FFFF).align_down(u32::MAX), Address(u32::MAX));
+        assert_eq!(Address(0).align_down(0x8000_0001), Address(0));
+    }
+
+    #[test]
+    fn range_step() {
+        let addrs: Vec<_> = Address(0x8000_0001)
+            .range_step(Address(0x8000_0010), 4)
+            .collect();
+        assert_eq!(
+            addrs,
+            [0x8000_0004, 0x8000_0008, 0x8000_000C, 0x8000_0010].map(Address)
+        );
+
+        // stops at the end of the address space
+        let iter = Address(0xFFFF_FFF0).range_step(Address(u32::MAX), 4);
+        assert_eq!(iter.len(), 4);
+        assert_eq!(iter.last(), Some(Address(0xFFFF_FFFC)));
+
+        let iter = Address(0).range_step(Address(u32::MAX), 1 << 28);
+        assert_eq!(iter.len(), 16);
+        assert_eq!(iter.last(), Some(Address(0xF000_0000)));
+
+        // no aligned address in range
+        assert_eq!(
+            Address(0xFFFF_FFFD)
+                .range_step(Address(u32::MAX), 4)
+                .count(),
+            0
+        );
+        assert_eq!(Address(0x11).range_step(Address(0x13), 4).count(), 0);
+        assert_eq!(Address(0x20).range_step(Address(0x10), 4).count(), 0);
+
+        for value in values() {
+            let mut iter = Address(value).range_step(Address(value), 1);
+            assert_eq!(iter.len(), 1);
+            assert_eq!(iter.next(), Some(Address(value)));
+            assert_eq!(iter.next(), None);
+        }
+    }
+
+    #[test]
+    fn checked_arithmetic() {
+        for value in values() {
+            let addr = Address(value);
+            assert_eq!(addr.checked_add(1).is_none(), value == u32::MAX);
+            assert_eq!(addr.checked_sub(1).is_none(), value == 0);
+            assert_eq!(addr.offset_from(Address(0)), Some(value));
+            assert_eq!(Address(0).offset_from(addr), (value == 0).then_some(0));
+
+            if let Some(next) = addr.checked_add(0x20) {
+                assert_eq!(next.offset_from(addr), Some(0x20));
+                assert_eq!(next.checked_sub(0x20), Some(addr));
+            }
+        }
+    }
+
+    #[test]
+    fn cr_bits() {
+        for bit in 0..32u8 {
+            let mut cr = CondReg::default();
+            cr.set_crb(bit, true);
+            assert_eq!(cr.to_bits(), 1 << (31 - bit), "CR bit {bit}");
+
+            for other in 0..32u8 {
+                assert_eq!(cr.crb(other), other == bit);
+            }
+
+            cr.set_crb(bit, false);
+            assert_eq!(cr.to_bits(), 0);
+        }
+
+        let mut cr = CondReg::from_bits(u32::MAX);
+        cr.set_crb(0, false);
+        assert_eq!(cr.to_bits(), 0x7FFF_FFFF);
+    }
+
+    #[test]
+    fn cr_fields() {
+        for n in 0..8u8 {
+            assert_eq!(CondReg::field_mask(n), 0xF000_0000 >> (4 * n));
+
+            for nibble in 0..16u8 {
+                let mut cr = CondReg::from_bits(0x1234_5678);
+                let cond = Cond::from_bits(u4::new(nibble));
+                cr.set_field(n, cond);
+
+                assert_eq!(cr.field(n).to_bits(), cond.to_bits());
+                assert_eq!(
+                    cr.to_bits(),
+                    (0x1234_5678 & !CondReg::field_mask(n))
+                        | ((nibble as u32) << (28 - 4 * n as u32))
+                );
+
+                // LT, GT, EQ and SO are the bits 4n, 4n + 1, 4n + 2 and 4n + 3
+                let base = 4 * n;
+                assert_eq!(cr.crb(base), cond.lt());
+                assert_eq!(cr.crb(base + 1), cond.gt());
+                assert_eq!(cr.crb(base + 2), cond.eq());
+                assert_eq!(cr.crb(base + 3), cond.ov());
+            }
+        }
+    }
+
+    #[test]
+    fn reserved_quantized_type() {
+        for ty in [
+            QuantizedType::Reserved0,
+            QuantizedType::Reserved1,
+            QuantizedType::Reserved2,
+        ] {
+            assert_eq!(ty.effective(), QuantizedType::Float);
+            assert_eq!(ty.size(), 4);
+        }
+
+        assert_eq!(QuantizedType::I16.effective(), QuantizedType::I16);
+        assert_eq!(QuantizedType::U8.size(), 1);
+    }
+
+    #[test]
+    fn signed_quantization_round_trip() {
+        const I8: u32 = 6;
+        for scale in [0i32, 3, 6, -2] {
+            let encoding = (scale as u32) & 0b0011_1111;
+            let gqr = QuantReg::from_bits((encoding << 24) | (I8 << 16) | (encoding << 8) | I8);
+            assert_eq!(gqr.load_type(), QuantizedType::I8);
+            assert_eq!(gqr.store_type(), QuantizedType::I8);
+
+            for value in -128i8..=127 {
+                let raw = value as u8 as u32;
+                let loaded = gqr.dequantize(raw);
+                assert_eq!(loaded, value as f64 * 2f64.powi(-scale));
+                assert_eq!(gqr.quantize(loaded), raw, "I8 {value} with scale {scale}");
+            }
+        }
+
+        // the upper bits of the raw value are ignored
+        assert_eq!(QuantizedType::I16.to_float(0xFFFF_8000), -32768.0);
+        assert_eq!(QuantizedType::I16.to_float(0x0000_FFFF), -1.0);
+        assert_eq!(QuantizedType::U16.to_float(0x0000_FFFF), 65535.0);
+
+        // out of range values saturate
+        assert_eq!(QuantizedType::I8.from_float(200.0), 0x7F);
+        assert_eq!(QuantizedType::I8.from_float(-200.0), 0x80);
+        assert_eq!(QuantizedType::I16.from_float(-1.0), 0xFFFF);
+        assert_eq!(QuantizedType::U8.from_float(-1.0), 0);
+    }
+
+    #[test]
+    fn armed_exceptions() {
+        let mut set = ExceptionSet::default();
+        for exception in Exception::VARIANTS {
+            set.set(*exception, true);
+        }
+
+        for exception in Exception::VARIANTS {
+            assert!(set.contains(*exception));
+            set.set(*exception, false);
+            assert!(!set.contains(*exception));
+        }
+        assert!(set.is_empty());
+
+        let mut cpu = Cpu {
+            pc: Address(0x8000_3100),
+            ..Default::default()
+        };
+        cpu.armed_exceptions.set(Exception::DSI, true);
+
+        cpu.raise_exception(Exception::Decrementer);
+        assert_eq!(cpu.caught_exception, None);
+
+        cpu.pc = Address(0x8000_3100);
+        cpu.raise_exception(Exception::DSI);
+        assert_eq!(cpu.caught_exception, Some(Exception::DSI));
+        assert_eq!(cpu.pc.value() & 0xFFFF, Exception::DSI as u32);
+        assert_eq!(cpu.supervisor.exception.srr[0], 0x8000_3100);
+    }
+
+    #[test]
+    fn quantization_factors() {
+        for encoding in 0..64u32 {
+            let scale = if encoding >= 32 {
+                encoding as i32 - 64
+            } else {
+                encoding as i32
+            };
+
+            let dequantize = 2f64.powi(-scale);
+            let quantize = 2f64.powi(scale);
+            assert_eq!(DEQUANTIZATION_LUT[encoding as usize], dequantize, "{scale}");
+            assert_eq!(QUANTIZATION_LUT[encoding as usize], quantize, "{scale}");
+            assert_eq!(dequantize * quantize, 1.0);
+
+            for ty in [QuantizedType::U8, QuantizedType::I16] {
+                let gqr = QuantReg::from_bits(
+                    (encoding << 24) | ((ty as u32) << 16) | (encoding << 8) | ty as u32,
+                );
+                assert_eq!(gqr.load_scale().value() as i32, scale);
+                assert_eq!(gqr.load_factor(), dequantize);
+                assert_eq!(gqr.store_factor(), quantize);
+            }
+
+            // floats are never scaled
+            let gqr = QuantReg::from_bits((encoding << 24) | (encoding << 8));
+            assert_eq!(gqr.load_factor(), 1.0);
+            assert_eq!(gqr.store_factor(), 1.0);
+        }
+
+        // the extremes are exactly representable
+        assert_eq!(DEQUANTIZATION_LUT[32], 4_294_967_296.0);
+        assert_eq!(QUANTIZATION_LUT[32], 1.0 / 4_294_967_296.0);
+        assert_eq!(DEQUANTIZATION_LUT[31], 1.0 / 2_147_483_648.0);
+    }
+
+    #[test]
+    fn performance_counters() {
+        let mut pm = PerformanceMonitor::default();
+        let msr = MachineState::default();
+
+        // nothing selected
+        assert!(!pm.increment(PmcEvent::InstructionComplete, 10, &msr));
+        assert_eq!(pm.counters, [0; 4]);
+
+        // PMC1 counts instructions, PMC2 icache misses and PMC4 taken branches
+        pm.control[0] = MonitorControl0::default()
+            .with_pmc1_select(u7::new(2))
+            .with_pmc2_select(u6::new(5))
+            .to_bits();
+        pm.control[1] = MonitorControl1::default()
+            .with_pmc4_select(u5::new(8))
+            .to_bits();
+
+        pm.increment(PmcEvent::InstructionComplete, 10, &msr);
+        pm.increment(PmcEvent::ICacheMiss, 2, &msr);
+        pm.increment(PmcEvent::BranchTaken, 3, &msr);
+        pm.increment(PmcEvent::DCacheMiss, 4, &msr);
+        assert_eq!(pm.counters, [10, 2, 0, 3]);
+
+        // supervisor counting disabled
+        pm.control[0] = pm.mmcr0().with_disable_supervisor(true).to_bits();
+        pm.increment(PmcEvent::InstructionComplete, 10, &msr);
+        assert_eq!(pm.counters[0], 10);
+        pm.increment(
+            PmcEvent::InstructionComplete,
+            10,
+            &msr.clone().with_user_mode(true),
+        );
+        assert_eq!(pm.counters[0], 20);
+
+        // becoming negative only interrupts if enabled
+        pm.control[0] = pm.mmcr0().with_disable_supervisor(false).to_bits();
+        pm.counters[0] = 0x7FFF_FFFF;
+        assert!(!pm.increment(PmcEvent::InstructionComplete, 1, &msr));
+
+        pm.control[0] = pm
+            .mmcr0()
+            .with_interrupts(true)
+            .with_pmc1_interrupt(true)
+            .to_bits();
+        pm.counters[0] = 0x7FFF_FFFF;
+        assert!(pm.increment(PmcEvent::InstructionComplete, 1, &msr));
+        assert!(!pm.increment(PmcEvent::InstructionComplete, 1, &msr));
+    }
+
+    #[test]
+    fn spr_names() {
+        let extensions = disasm::Extensions::gekko_broadway;
+
+        // mtspr LR, r0
+        let ins = disasm::Ins::new(0x7C08_03A6, extensions());
+        assert_eq!(ins.spr_name(), Some("LR"));
+
+        // mfspr r3, GQR0
+        let ins = disasm::Ins::new(0x7C70_E2A6, extensions());
+        assert_eq!(ins.spr_name(), Some("GQR0"));
+
+        // mfspr r3, 0
+        let ins = disasm::Ins::new(0x7C60_02A6, extensions());
+        assert_eq!(ins.spr_name(), None);
+    }
+}
@@ -1,8 +1,9 @@
 #![expect(clippy::identity_op, reason = "seq expanded code")]
 #![expect(clippy::erasing_op, reason = "seq expanded code")]
-#![feature(portable_simd)]
+#![cfg_attr(feature = "nightly", feature(portable_simd))]
 
 use std::marker::PhantomData;
+#[cfg(feature = "nightly")]
 use std::simd::{ToBytes, simd_swizzle, u8x32, u16x16, u16x32};
 
 use bitut::BitUtils;
@@ -435,6 +436,24 @@ impl Format for FastRgb565 {
         }
     }
 
+    #[cfg(not(feature = "nightly"))]
+    #[inline(always)]
+    fn decode_tile(data: &[u8], mut set: impl FnMut(usize, usize, Pixel)) {
+        let pixels: [u16; 16] =
+            std::array::from_fn(|i| u16::from_be_bytes([data[2 * i], data[2 * i + 1]]));
+        let conv = pixels.map(Pixel::from_rgb565_fast);
+        seq! {
+            Y in 0..4 {
+                seq! {
+                    X in 0..4 {
+                        set(X, Y, conv[X + 4 * Y]);
+                    }
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "nightly")]
     #[inline(always)]
     fn decode_tile(data: &[u8], mut set: impl FnMut(usize, usize, Pixel)) {
         // 01. convert endianness
@@ -0,0 +1,44 @@
+use std::hint::black_box;
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use lazuli::Address;
+use lazuli::system::{self, Modules, System};
+
+/// Size of a typical DSP ucode upload.
+const UCODE_LEN: usize = 4096;
+
+fn system() -> System {
+    System::new(Modules::nop(), system::Config::default())
+}
+
+fn ucode_dma(c: &mut Criterion) {
+    let mut sys = system();
+    let base = Address(0x0080_0000);
+    let mut ucode = vec![0u16; UCODE_LEN / 2];
+    let mut bytes = vec![0u8; UCODE_LEN];
+
+    let mut group = c.benchmark_group("DSP ucode DMA");
+    group.throughput(criterion::Throughput::Bytes(UCODE_LEN as u64));
+
+    group.bench_function("Per word", |b| {
+        b.iter(|| {
+            for (i, word) in ucode.iter_mut().enumerate() {
+                *word = sys.read_phys_slow::<u16>(black_box(base) + 2 * i as u32);
+            }
+        })
+    });
+
+    group.bench_function("Block", |b| {
+        b.iter(|| {
+            sys.read_phys_block(black_box(base), &mut bytes).unwrap();
+            for (word, data) in ucode.iter_mut().zip(bytes.chunks_exact(2)) {
+                *word = u16::from_be_bytes([data[0], data[1]]);
+            }
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, ucode_dma);
+criterion_main!(benches);
@@ -0,0 +1,112 @@
+use std::any::Any;
+use std::hint::black_box;
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use lazuli::breakpoint::Breakpoints;
+use lazuli::cores::{Cores, CpuCore, DspCore, DspExecuted, Executed};
+use lazuli::gekko::FREQUENCY;
+use lazuli::system::scheduler::SchedulerEventKind;
+use lazuli::system::{self, Modules, System};
+use lazuli::{Address, Cycles, Lazuli};
+
+/// Emulated time executed per iteration: a millisecond.
+const SLICE: Cycles = Cycles(FREQUENCY / 1000);
+
+/// A CPU core which executes exactly as many cycles as requested without doing anything, so that
+/// only the overhead of the execution loop is measured.
+struct NopCpu;
+
+impl CpuCore for NopCpu {
+    fn exec(&mut self, _: &mut System, cycles: Cycles, _: &[Address]) -> Executed {
+        Executed {
+            instructions: (cycles.0 / 2).max(1) as u32,
+            cycles,
+            hit_breakpoint: false,
+        }
+    }
+
+    fn step(&mut self, _: &mut System) -> Executed {
+        Executed {
+            instructions: 1,
+            cycles: Cycles(2),
+            hit_breakpoint: false,
+        }
+    }
+
+    fn reset(&mut self, _: &mut System) {}
+}
+
+/// A DSP core which is always idle.
+struct IdleDsp;
+
+impl DspCore for IdleDsp {
+    fn exec(&mut self, _: &mut System, cycles: u32) -> DspExecuted {
+        DspExecuted {
+            instructions: 0,
+            cycles,
+            hit_breakpoint: false,
+        }
+    }
+
+    fn step(&mut self, _: &mut System) {}
+
+    fn reset(&mut self, _: &mut System) {}
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+fn emulator(event_interval: u64) -> Lazuli {
+    let cores = Cores {
+        cpu: Box::new(NopCpu),
+        dsp: Box::new(IdleDsp),
+    };
+
+    let mut lazuli = Lazuli::new(cores, Modules::nop(), system::Config::default());
+
+    fn nop(_: &mut System) {}
+    lazuli
+        .sys
+        .scheduler
+        .schedule_repeating(event_interval, SchedulerEventKind::Callback(nop));
+
+    lazuli
+}
+
+fn exec_loop(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Exec loop");
+    group.throughput(criterion::Throughput::Elements(SLICE.0));
+
+    // a synthetic 1 kHz event and one as frequent as audio DMA
+    for (name, interval) in [
+        ("1 kHz events", FREQUENCY / 1000),
+        ("2000 cycle events", 2000),
+    ] {
+        let mut emu = emulator(interval);
+        let mut breakpoints = Breakpoints::default();
+        group.bench_function(BenchmarkId::new(name, "no breakpoints"), |b| {
+            b.iter(|| emu.exec(black_box(SLICE), &mut breakpoints))
+        });
+
+        // breakpoints which are never reached
+        let mut emu = emulator(interval);
+        let mut breakpoints = Breakpoints::default();
+        for i in 0..256 {
+            breakpoints.add(Address(0x8100_0000 + 4 * i));
+        }
+
+        group.bench_function(BenchmarkId::new(name, "256 breakpoints"), |b| {
+            b.iter(|| emu.exec(black_box(SLICE), &mut breakpoints))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, exec_loop);
+criterion_main!(benches);
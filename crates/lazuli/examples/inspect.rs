@@ -0,0 +1,89 @@
+//! Pretty-prints the contents of a diagnostic bundle.
+//!
+//! Usage: `lazuli-inspect <bundle>`
+
+use std::fs::File;
+use std::io::BufReader;
+
+use lazuli::diagnostics::{Bundle, Section};
+
+/// How many bytes of binary sections are dumped.
+const DUMP_LEN: usize = 256;
+
+fn hexdump(data: &[u8]) {
+    for (i, row) in data.chunks(16).enumerate() {
+        print!("{:08X}  ", 16 * i);
+        for byte in row {
+            print!("{byte:02X} ");
+        }
+
+        let ascii: String = row
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+
+        println!("{:width$} {ascii}", "", width = 3 * (16 - row.len()));
+    }
+}
+
+fn disc_header(section: &Section) {
+    let data = &section.data;
+    if data.len() >= 8 {
+        println!("Game ID: {}", String::from_utf8_lossy(&data[..6]));
+        println!("Disc number: {}", data[6]);
+        println!("Revision: {}", data[7]);
+    }
+
+    hexdump(data);
+}
+
+fn main() {
+    let Some(path) = std::env::args().nth(1) else {
+        eprintln!("usage: lazuli-inspect <bundle>");
+        std::process::exit(1);
+    };
+
+    let file = match File::open(&path) {
+        Ok(file) => file,
+        Err(e) => {
+            eprintln!("failed to open {path}: {e}");
+            std::process::exit(1);
+        }
+    };
+
+    let bundle = match Bundle::read(BufReader::new(file)) {
+        Ok(bundle) => bundle,
+        Err(e) => {
+            eprintln!("failed to read {path}: {e}");
+            std::process::exit(1);
+        }
+    };
+
+    println!("=== Manifest ===");
+    println!("Emulator version: {}", bundle.version);
+    println!("Created: {} (Unix time)", bundle.created);
+    for section in &bundle.sections {
+        println!("  {}: {} bytes", section.name, section.data.len());
+    }
+
+    for section in &bundle.sections {
+        println!("\n=== {} ===", section.name);
+        match section.name.as_str() {
+            "disc_header" => disc_header(section),
+            "ram" => {
+                println!("{} bytes, first {DUMP_LEN}:", section.data.len());
+                hexdump(&section.data[..section.data.len().min(DUMP_LEN)]);
+            }
+            _ => match section.as_text() {
+                Some(text) => print!("{text}"),
+                None => hexdump(&section.data[..section.data.len().min(DUMP_LEN)]),
+            },
+        }
+    }
+}
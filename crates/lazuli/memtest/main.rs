@@ -1,22 +1,13 @@
 use std::env;
-use std::ops::RangeInclusive;
 
 use gekko::{Address, MemoryManagement};
 use indicatif::ProgressBar;
-use lazuli::modules::audio::NopAudioModule;
-use lazuli::modules::debug::NopDebugModule;
-use lazuli::modules::disk::NopDiskModule;
-use lazuli::modules::input::NopInputModule;
-use lazuli::modules::render::NopRenderModule;
-use lazuli::modules::vertex::NopVertexModule;
 use lazuli::system::mem::{RAM_END, RAM_LEN, RAM_START};
 use lazuli::system::{self, Modules, System};
 
-fn test_inner(sys: &mut System, range: RangeInclusive<u32>) {
+fn test_inner(sys: &mut System, start: u32, end: u32) {
     let bar = ProgressBar::new(RAM_LEN as u64);
-    for addr in (range).step_by(4) {
-        let addr = Address(addr);
-
+    for addr in Address(start).range_step(Address(end), 4) {
         sys.write_fast(addr, 0xDEAD_BEEFu32);
         assert_eq!(sys.read_slow(addr), Some(0xDEAD_BEEFu32));
         assert_eq!(sys.read_fast(addr), Some(0xDEAD_BEEFu32));
@@ -39,7 +30,7 @@ fn test_physical(sys: &mut System) {
         .msr
         .set_data_addr_translation(false);
 
-    test_inner(sys, RAM_START..=RAM_END);
+    test_inner(sys, RAM_START, RAM_END);
 }
 
 /// Tests default logical memory.
@@ -55,7 +46,7 @@ fn test_logical(sys: &mut System) {
         .set_data_addr_translation(false);
 
     println!("physical");
-    test_inner(sys, RAM_START..=RAM_END);
+    test_inner(sys, RAM_START, RAM_END);
 
     sys.cpu
         .supervisor
@@ -64,10 +55,10 @@ fn test_logical(sys: &mut System) {
         .set_data_addr_translation(true);
 
     println!("cached ram");
-    test_inner(sys, 0x8000_0000 + RAM_START..=0x8000_0000 + RAM_END);
+    test_inner(sys, 0x8000_0000 + RAM_START, 0x8000_0000 + RAM_END);
 
     println!("uncached ram");
-    test_inner(sys, 0xC000_0000 + RAM_START..=0xC000_0000 + RAM_END);
+    test_inner(sys, 0xC000_0000 + RAM_START, 0xC000_0000 + RAM_END);
 }
 
 fn main() {
@@ -76,24 +67,7 @@ fn main() {
         return;
     }
 
-    let modules = Modules {
-        audio: Box::new(NopAudioModule),
-        debug: Box::new(NopDebugModule),
-        disk: Box::new(NopDiskModule),
-        input: Box::new(NopInputModule),
-        render: Box::new(NopRenderModule),
-        vertex: Box::new(NopVertexModule),
-    };
-
-    let mut system = System::new(
-        modules,
-        system::Config {
-            ipl: None,
-            sideload: None,
-            ipl_lle: false,
-            perform_efb_copies: false,
-        },
-    );
+    let mut system = System::new(Modules::nop(), system::Config::default());
 
     test_physical(&mut system);
     test_logical(&mut system);
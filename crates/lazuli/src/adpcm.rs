@@ -0,0 +1,119 @@
+//! ADPCM decoding, shared by the DSP accelerator and streamed disc audio.
+
+/// Size of a block of streamed disc audio, in bytes.
+pub const STREAM_BLOCK_SIZE: usize = 32;
+/// Number of stereo samples in a block of streamed disc audio.
+pub const STREAM_BLOCK_SAMPLES: usize = 28;
+
+/// Filter coefficients of streamed disc audio, with 6 fractional bits.
+const STREAM_COEFFICIENTS: [[i32; 2]; 4] = [[0, 0], [60, 0], [115, -52], [98, -55]];
+
+/// Sign extends the low nibble of `value`.
+#[inline(always)]
+pub fn nibble(value: u8) -> i32 {
+    ((value << 4) as i8 >> 4) as i32
+}
+
+/// Predicts the next sample from the last two, with `history[0]` being the most recent one.
+///
+/// The coefficients have `shift` fractional bits, and the prediction is rounded to the nearest
+/// integer.
+#[inline(always)]
+pub fn predict(coefficients: [i32; 2], history: [i32; 2], shift: u32) -> i32 {
+    let sum = coefficients[0] * history[0] + coefficients[1] * history[1];
+    (sum + (1 << (shift - 1))) >> shift
+}
+
+/// Decoder of streamed disc audio (DTK).
+///
+/// Streams are made of [`STREAM_BLOCK_SIZE`] byte blocks. The first two bytes of a block are the
+/// headers of the left and right channels (filter in the high nibble, shift in the low nibble),
+/// the next two repeat them and the remaining bytes contain one sample per channel each, with the
+/// left one in the low nibble.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StreamDecoder {
+    left: [i32; 2],
+    right: [i32; 2],
+}
+
+impl StreamDecoder {
+    /// Decodes a single sample. History is kept with 6 extra fractional bits.
+    fn decode_sample(header: u8, data: u8, history: &mut [i32; 2]) -> i16 {
+        let coefficients = STREAM_COEFFICIENTS[(header >> 4) as usize & 0b11];
+        let prediction = predict(coefficients, *history, 6).clamp(-0x20_0000, 0x1F_FFFF);
+
+        // the nibble holds the top bits of the sample, scaled down by the shift
+        let value = ((nibble(data) << 12) >> (header & 0xF)) << 6;
+        let sample = value + prediction;
+
+        *history = [sample, history[0]];
+        (sample >> 6).clamp(i16::MIN as i32, i16::MAX as i32) as i16
+    }
+
+    /// Decodes a block into `[left, right]` samples.
+    pub fn decode(&mut self, block: &[u8; STREAM_BLOCK_SIZE]) -> [[i16; 2]; STREAM_BLOCK_SAMPLES] {
+        let [left_header, right_header] = [block[0], block[1]];
+        let data = &block[STREAM_BLOCK_SIZE - STREAM_BLOCK_SAMPLES..];
+
+        std::array::from_fn(|i| {
+            [
+                Self::decode_sample(left_header, data[i] & 0xF, &mut self.left),
+                Self::decode_sample(right_header, data[i] >> 4, &mut self.right),
+            ]
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{STREAM_BLOCK_SIZE, StreamDecoder, nibble, predict};
+
+    #[test]
+    fn nibbles() {
+        assert_eq!(nibble(0x07), 7);
+        assert_eq!(nibble(0x08), -8);
+        assert_eq!(nibble(0xAF), -1);
+    }
+
+    #[test]
+    fn prediction_rounds() {
+        assert_eq!(predict([2048, 0], [100, 0], 11), 100);
+        assert_eq!(predict([1024, 0], [3, 0], 11), 2);
+        assert_eq!(predict([115, -52], [640, 320], 6), 890);
+    }
+
+    #[test]
+    fn stream_block() {
+        let mut block = [0u8; STREAM_BLOCK_SIZE];
+        block[0] = 0x24; // left: filter 2, shift 4
+        block[1] = 0x1C; // right: filter 1, shift 12
+        block[2] = block[0];
+        block[3] = block[1];
+        for (i, byte) in block[4..].iter_mut().enumerate() {
+            *byte = (i as u8).wrapping_mul(0x37);
+        }
+
+        let mut decoder = StreamDecoder::default();
+        let samples = decoder.decode(&block);
+
+        let left: Vec<i16> = samples.iter().map(|[l, _]| *l).collect();
+        let right: Vec<i16> = samples.iter().map(|[_, r]| *r).collect();
+        assert_eq!(left, LEFT);
+        assert_eq!(right, RIGHT);
+
+        // history carries over to the next block
+        let samples = decoder.decode(&block);
+        assert_eq!(samples[0], NEXT_FIRST);
+    }
+
+    // reference output of the decoder used by existing emulators
+    const LEFT: [i16; 28] = [
+        0, 1792, 2708, 4689, 5202, 6306, 5568, 5138, 2659, 348, 1, -1049, -862, -1976, -2339,
+        -4389, -5986, -5398, -5348, -3944, -3766, -2794, -3496, -3757, -5958, -7909, -7834, -8420,
+    ];
+    const RIGHT: [i16; 28] = [
+        0, 3, 8, 2, -1, 0, 4, -5, -9, -11, -8, -3, -10, -13, -12, -8, -1, -7, -10, -8, -3, -11,
+        -16, -16, -13, -7, -14, -17,
+    ];
+    const NEXT_FIRST: [i16; 2] = [-8764, -16];
+}
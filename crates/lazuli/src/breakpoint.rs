@@ -0,0 +1,719 @@
+//! CPU breakpoints with optional conditions.
+//!
+//! A condition is a list of clauses joined by `&&`, each comparing an operand against a literal,
+//! e.g. `r3 == 0x80001234 && hits >= 500`. Operands are:
+//! - `r0`-`r31`: general purpose registers
+//! - `f0`-`f31`: first element of the floating point registers
+//! - `pc`, `lr` and `ctr`
+//! - `hits`: how many times the breakpoint has been reached, including the current time
+//!
+//! Integer literals can be written in decimal or in hexadecimal with a `0x` prefix. Comparing a
+//! GPR against a negative literal compares it as a signed integer.
+//!
+//! For programmatic use, a breakpoint can also have a [`Predicate`], which is checked along with
+//! its condition and has access to the whole system.
+//!
+//! Besides breakpoints on code, [`Watches`] stop execution when the value of a word in memory
+//! changes, which helps finding out what writes to it.
+use std::fmt::Display;
+use std::str::FromStr;
+
+use easyerr::Error;
+use gekko::{Address, Cpu, ExceptionSet};
+
+use crate::system::System;
+
+#[derive(Debug, Error)]
+pub enum ParseError {
+    #[error("clause {clause:?} is not in the form `<operand> <comparison> <value>`")]
+    Malformed { clause: String },
+    #[error("unknown operand {operand:?}")]
+    UnknownOperand { operand: String },
+    #[error("invalid value {value:?}")]
+    InvalidValue { value: String },
+}
+
+/// Something whose value can be inspected when a breakpoint is reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    Gpr(u8),
+    Fpr(u8),
+    Pc,
+    Lr,
+    Ctr,
+    Hits,
+}
+
+impl FromStr for Operand {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let register = |prefix: &str| {
+            s.strip_prefix(prefix)
+                .and_then(|index| index.parse::<u8>().ok())
+                .filter(|index| *index < 32)
+        };
+
+        Ok(match s {
+            "pc" => Self::Pc,
+            "lr" => Self::Lr,
+            "ctr" => Self::Ctr,
+            "hits" => Self::Hits,
+            _ => {
+                if let Some(index) = register("r") {
+                    Self::Gpr(index)
+                } else if let Some(index) = register("f") {
+                    Self::Fpr(index)
+                } else {
+                    return Err(ParseError::UnknownOperand {
+                        operand: s.to_owned(),
+                    });
+                }
+            }
+        })
+    }
+}
+
+impl Display for Operand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Gpr(index) => write!(f, "r{index}"),
+            Self::Fpr(index) => write!(f, "f{index}"),
+            Self::Pc => write!(f, "pc"),
+            Self::Lr => write!(f, "lr"),
+            Self::Ctr => write!(f, "ctr"),
+            Self::Hits => write!(f, "hits"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    /// Every comparison along with its symbol.
+    const ALL: [(&'static str, Self); 6] = [
+        ("==", Self::Eq),
+        ("!=", Self::Ne),
+        ("<=", Self::Le),
+        (">=", Self::Ge),
+        ("<", Self::Lt),
+        (">", Self::Gt),
+    ];
+
+    fn test<T: PartialOrd>(self, lhs: T, rhs: T) -> bool {
+        match self {
+            Self::Eq => lhs == rhs,
+            Self::Ne => lhs != rhs,
+            Self::Lt => lhs < rhs,
+            Self::Le => lhs <= rhs,
+            Self::Gt => lhs > rhs,
+            Self::Ge => lhs >= rhs,
+        }
+    }
+}
+
+impl Display for Comparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (symbol, _) = Self::ALL.iter().find(|(_, c)| c == self).unwrap();
+        write!(f, "{symbol}")
+    }
+}
+
+/// A literal in a condition.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Float(f64),
+}
+
+impl FromStr for Value {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, s),
+        };
+
+        let int = if let Some(hex) = digits.strip_prefix("0x") {
+            i64::from_str_radix(&hex.replace('_', ""), 16).ok()
+        } else {
+            digits.replace('_', "").parse::<i64>().ok()
+        };
+
+        if let Some(int) = int {
+            return Ok(Self::Int(if negative { -int } else { int }));
+        }
+
+        s.parse::<f64>()
+            .map(Self::Float)
+            .map_err(|_| ParseError::InvalidValue {
+                value: s.to_owned(),
+            })
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Int(value) if *value >= 0x1_0000 => write!(f, "0x{value:X}"),
+            Self::Int(value) => write!(f, "{value}"),
+            Self::Float(value) => write!(f, "{value:?}"),
+        }
+    }
+}
+
+/// A single comparison of a condition.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Clause {
+    pub operand: Operand,
+    pub comparison: Comparison,
+    pub value: Value,
+}
+
+impl Clause {
+    fn eval(&self, cpu: &Cpu, hits: u64) -> bool {
+        let int = |value: i64| match self.value {
+            Value::Int(rhs) => self.comparison.test(value, rhs),
+            Value::Float(rhs) => self.comparison.test(value as f64, rhs),
+        };
+
+        match self.operand {
+            Operand::Gpr(index) => {
+                let value = cpu.user.gpr[index as usize];
+                match self.value {
+                    Value::Int(rhs) if rhs < 0 => int(value as i32 as i64),
+                    _ => int(value as i64),
+                }
+            }
+            Operand::Fpr(index) => {
+                let value = cpu.user.fpr[index as usize][0];
+                match self.value {
+                    Value::Int(rhs) => self.comparison.test(value, rhs as f64),
+                    Value::Float(rhs) => self.comparison.test(value, rhs),
+                }
+            }
+            Operand::Pc => int(cpu.pc.value() as i64),
+            Operand::Lr => int(cpu.user.lr as i64),
+            Operand::Ctr => int(cpu.user.ctr as i64),
+            Operand::Hits => int(hits as i64),
+        }
+    }
+}
+
+impl FromStr for Clause {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let malformed = || ParseError::Malformed {
+            clause: s.to_owned(),
+        };
+
+        let (index, symbol, comparison) = Comparison::ALL
+            .iter()
+            .filter_map(|(symbol, comparison)| {
+                s.find(symbol).map(|index| (index, *symbol, *comparison))
+            })
+            .min_by_key(|(index, symbol, _)| (*index, std::cmp::Reverse(symbol.len())))
+            .ok_or_else(malformed)?;
+
+        let operand = s[..index].trim();
+        let value = s[index + symbol.len()..].trim();
+        if operand.is_empty() || value.is_empty() {
+            return Err(malformed());
+        }
+
+        // catch things like `=<`, which would otherwise be split in the wrong place
+        let symbols = ['=', '<', '>', '!'];
+        if operand.ends_with(symbols) || value.starts_with(symbols) {
+            return Err(malformed());
+        }
+
+        Ok(Self {
+            operand: operand.to_ascii_lowercase().parse()?,
+            comparison,
+            value: value.parse()?,
+        })
+    }
+}
+
+impl Display for Clause {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {}", self.operand, self.comparison, self.value)
+    }
+}
+
+/// A condition which must hold for a breakpoint to stop execution. An empty condition always
+/// holds.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Condition {
+    pub clauses: Vec<Clause>,
+}
+
+impl Condition {
+    /// Evaluates this condition for the given CPU state and hit count.
+    pub fn eval(&self, cpu: &Cpu, hits: u64) -> bool {
+        self.clauses.iter().all(|clause| clause.eval(cpu, hits))
+    }
+}
+
+impl FromStr for Condition {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim().is_empty() {
+            return Ok(Self::default());
+        }
+
+        let clauses = s
+            .split("&&")
+            .map(str::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self { clauses })
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (index, clause) in self.clauses.iter().enumerate() {
+            if index != 0 {
+                write!(f, " && ")?;
+            }
+
+            write!(f, "{clause}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A condition for a breakpoint to stop execution, written in code instead of parsed from text.
+pub type Predicate = fn(&System) -> bool;
+
+/// A CPU breakpoint.
+#[derive(Debug, Clone)]
+pub struct Breakpoint {
+    /// Address of the breakpoint.
+    pub addr: Address,
+    /// Condition for the breakpoint to stop execution.
+    pub condition: Condition,
+    /// Additional condition for the breakpoint to stop execution, if any.
+    pub predicate: Option<Predicate>,
+    /// How many times execution has reached this breakpoint.
+    pub hits: u64,
+}
+
+/// A set of CPU breakpoints.
+#[derive(Debug, Clone, Default)]
+pub struct Breakpoints {
+    /// Addresses of the breakpoints in ascending order, kept separately so that cores can use them
+    /// directly.
+    addresses: Vec<Address>,
+    /// The breakpoints, in the same order as `addresses`.
+    breakpoints: Vec<Breakpoint>,
+    /// Exceptions which stop execution once taken.
+    exceptions: ExceptionSet,
+}
+
+impl Breakpoints {
+    /// Adds an unconditional breakpoint at the given address, if there isn't one already.
+    pub fn add(&mut self, addr: Address) {
+        if let Err(index) = self.addresses.binary_search(&addr) {
+            self.addresses.insert(index, addr);
+            self.breakpoints.insert(
+                index,
+                Breakpoint {
+                    addr,
+                    condition: Condition::default(),
+                    predicate: None,
+                    hits: 0,
+                },
+            );
+        }
+    }
+
+    /// Removes the breakpoint at the given address.
+    pub fn remove(&mut self, addr: Address) {
+        if let Ok(index) = self.addresses.binary_search(&addr) {
+            self.addresses.remove(index);
+            self.breakpoints.remove(index);
+        }
+    }
+
+    /// Whether there's a breakpoint at the given address.
+    #[inline(always)]
+    pub fn contains(&self, addr: Address) -> bool {
+        self.addresses.binary_search(&addr).is_ok()
+    }
+
+    /// Addresses of the breakpoints, in ascending order.
+    #[inline(always)]
+    pub fn addresses(&self) -> &[Address] {
+        &self.addresses
+    }
+
+    /// Iterates over the breakpoints.
+    pub fn iter(&self) -> impl Iterator<Item = &Breakpoint> {
+        self.breakpoints.iter()
+    }
+
+    /// The breakpoint at the given address.
+    pub fn get(&self, addr: Address) -> Option<&Breakpoint> {
+        let index = self.addresses.binary_search(&addr).ok()?;
+        Some(&self.breakpoints[index])
+    }
+
+    /// The breakpoint at the given address.
+    pub fn get_mut(&mut self, addr: Address) -> Option<&mut Breakpoint> {
+        let index = self.addresses.binary_search(&addr).ok()?;
+        Some(&mut self.breakpoints[index])
+    }
+
+    /// Sets the condition of the breakpoint at the given address, resetting its hit count.
+    pub fn set_condition(&mut self, addr: Address, condition: Condition) {
+        if let Some(breakpoint) = self.get_mut(addr) {
+            breakpoint.condition = condition;
+            breakpoint.hits = 0;
+        }
+    }
+
+    /// Sets the predicate of the breakpoint at the given address, resetting its hit count.
+    pub fn set_predicate(&mut self, addr: Address, predicate: Option<Predicate>) {
+        if let Some(breakpoint) = self.get_mut(addr) {
+            breakpoint.predicate = predicate;
+            breakpoint.hits = 0;
+        }
+    }
+
+    /// Exceptions which stop execution once taken. Execution stops at the exception vector, with
+    /// SRR0 pointing at the instruction which caused it.
+    #[inline(always)]
+    pub fn exceptions(&self) -> ExceptionSet {
+        self.exceptions
+    }
+
+    /// Sets the exceptions which stop execution once taken.
+    pub fn set_exceptions(&mut self, exceptions: ExceptionSet) {
+        self.exceptions = exceptions;
+    }
+
+    /// Registers that execution reached the given address, returning whether it should stop, i.e.
+    /// whether there's a breakpoint at the address and both its condition and predicate hold.
+    pub fn hit(&mut self, addr: Address, sys: &System) -> bool {
+        let Some(breakpoint) = self.get_mut(addr) else {
+            return false;
+        };
+
+        breakpoint.hits += 1;
+        breakpoint.condition.eval(&sys.cpu, breakpoint.hits)
+            && breakpoint.predicate.is_none_or(|predicate| predicate(sys))
+    }
+}
+
+/// A breakpoint which stops execution when the value of the word at an address changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValueWatch {
+    /// Logical address of the watched word.
+    pub addr: Address,
+    /// Bits of the word which are compared.
+    pub mask: u32,
+    /// Last known value of the word.
+    pub old: u32,
+}
+
+/// A change of value detected by a [`ValueWatch`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchHit {
+    /// Logical address of the watched word.
+    pub addr: Address,
+    /// Value of the PC when the word was written. Cores which execute blocks of instructions
+    /// might only update it at the start of each block.
+    pub pc: Address,
+    pub old: u32,
+    pub new: u32,
+}
+
+/// A set of [`ValueWatch`]es, checked on every write to memory made by the CPU and by block
+/// transfers.
+#[derive(Debug, Clone, Default)]
+pub struct Watches {
+    watches: Vec<ValueWatch>,
+    /// The first change detected since the last call to [`Watches::take_hit`].
+    hit: Option<WatchHit>,
+}
+
+impl Watches {
+    /// Watches the word at the given address, whose current value is `current`. Replaces any
+    /// watch at the same address.
+    pub fn add(&mut self, addr: Address, mask: u32, current: u32) {
+        self.remove(addr);
+        self.watches.push(ValueWatch {
+            addr,
+            mask,
+            old: current,
+        });
+    }
+
+    /// Removes the watch at the given address.
+    pub fn remove(&mut self, addr: Address) {
+        self.watches.retain(|w| w.addr != addr);
+    }
+
+    /// Whether there are no watches, in which case writes don't need to be checked.
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.watches.is_empty()
+    }
+
+    /// Iterates over the watches.
+    pub fn iter(&self) -> impl Iterator<Item = &ValueWatch> {
+        self.watches.iter()
+    }
+
+    /// Whether a change has been detected since the last call to [`Watches::take_hit`].
+    #[inline(always)]
+    pub fn has_hit(&self) -> bool {
+        self.hit.is_some()
+    }
+
+    /// Takes the change detected since the last call to this method, if any.
+    pub fn take_hit(&mut self) -> Option<WatchHit> {
+        self.hit.take()
+    }
+
+    /// Checks the watches overlapping a write of `len` bytes at `addr`, made at `pc`. `read` is
+    /// used to read the value of watched words after the write.
+    pub fn check(
+        &mut self,
+        addr: Address,
+        len: u32,
+        pc: Address,
+        read: impl FnMut(Address) -> Option<u32>,
+    ) {
+        self.check_with(addr, len, pc, Some, read);
+    }
+
+    /// Checks the watches overlapping a write of `len` bytes at the physical address `addr`, made
+    /// while the CPU was at `pc`. `translate` maps the logical address of watched words to their
+    /// physical address, and `read` is used just like in [`Watches::check`].
+    pub fn check_physical(
+        &mut self,
+        addr: Address,
+        len: u32,
+        pc: Address,
+        translate: impl FnMut(Address) -> Option<Address>,
+        read: impl FnMut(Address) -> Option<u32>,
+    ) {
+        self.check_with(addr, len, pc, translate, read);
+    }
+
+    /// Checks the watches whose word, once located by `locate`, overlaps a write of `len` bytes
+    /// at `addr`.
+    fn check_with(
+        &mut self,
+        addr: Address,
+        len: u32,
+        pc: Address,
+        mut locate: impl FnMut(Address) -> Option<Address>,
+        mut read: impl FnMut(Address) -> Option<u32>,
+    ) {
+        let start = addr.value();
+        let end = start.saturating_add(len);
+        for watch in &mut self.watches {
+            let Some(watched) = locate(watch.addr) else {
+                continue;
+            };
+
+            let watched = watched.value();
+            if watched.saturating_add(4) <= start || end <= watched {
+                continue;
+            }
+
+            let Some(new) = read(watch.addr) else {
+                continue;
+            };
+
+            let old = std::mem::replace(&mut watch.old, new);
+            if (new & watch.mask) != (old & watch.mask) && self.hit.is_none() {
+                self.hit = Some(WatchHit {
+                    addr: watch.addr,
+                    pc,
+                    old,
+                    new,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use gekko::{Address, Cpu};
+
+    use super::{Breakpoints, Comparison, Condition, Operand, Value, WatchHit, Watches};
+    use crate::test::system;
+
+    #[test]
+    fn parse() {
+        let condition: Condition = "r3 == 0x80001234 && f1 < 0.0 && hits>=500".parse().unwrap();
+        let clauses = &condition.clauses;
+        assert_eq!(clauses.len(), 3);
+
+        assert_eq!(clauses[0].operand, Operand::Gpr(3));
+        assert_eq!(clauses[0].comparison, Comparison::Eq);
+        assert_eq!(clauses[0].value, Value::Int(0x8000_1234));
+
+        assert_eq!(clauses[1].operand, Operand::Fpr(1));
+        assert_eq!(clauses[1].comparison, Comparison::Lt);
+        assert_eq!(clauses[1].value, Value::Float(0.0));
+
+        assert_eq!(clauses[2].operand, Operand::Hits);
+        assert_eq!(clauses[2].comparison, Comparison::Ge);
+        assert_eq!(clauses[2].value, Value::Int(500));
+
+        let displayed = condition.to_string();
+        assert_eq!(displayed, "r3 == 0x80001234 && f1 < 0.0 && hits >= 500");
+        assert_eq!(displayed.parse::<Condition>().unwrap(), condition);
+
+        assert!("".parse::<Condition>().unwrap().clauses.is_empty());
+        assert!("r32 == 0".parse::<Condition>().is_err());
+        assert!("r3 =< 0".parse::<Condition>().is_err());
+        assert!("r3 == zero".parse::<Condition>().is_err());
+        assert!("r3".parse::<Condition>().is_err());
+    }
+
+    #[test]
+    fn conditions() {
+        let mut cpu = Cpu::default();
+        cpu.user.gpr[3] = 0xFFFF_FFFF;
+        cpu.user.fpr[1][0] = -2.5;
+
+        let eval = |s: &str| s.parse::<Condition>().unwrap().eval(&cpu, 1);
+        assert!(eval("r3 == 0xFFFFFFFF"));
+        assert!(eval("r3 == -1"));
+        assert!(eval("r3 > 0"));
+        assert!(!eval("r3 < 0x10"));
+        assert!(eval("f1 < 0.0"));
+        assert!(eval("f1 == -2.5 && r3 != 0"));
+        assert!(!eval("f1 > -1"));
+    }
+
+    #[test]
+    fn hit_count() {
+        let sys = system();
+        let addr = Address(0x8000_3100);
+
+        let mut breakpoints = Breakpoints::default();
+        breakpoints.add(addr);
+        breakpoints.set_condition(addr, "hits >= 3".parse().unwrap());
+
+        assert!(!breakpoints.hit(Address(0x8000_3104), &sys));
+        assert!(!breakpoints.hit(addr, &sys));
+        assert!(!breakpoints.hit(addr, &sys));
+        assert!(breakpoints.hit(addr, &sys));
+        assert!(breakpoints.hit(addr, &sys));
+        assert_eq!(breakpoints.get(addr).unwrap().hits, 4);
+
+        breakpoints.remove(addr);
+        assert!(breakpoints.addresses().is_empty());
+        assert!(!breakpoints.hit(addr, &sys));
+    }
+
+    #[test]
+    fn sorted_addresses() {
+        let sys = system();
+        let addrs = [0x8000_3200, 0x8000_3100, 0x8000_3300, 0x8000_3000].map(Address);
+
+        let mut breakpoints = Breakpoints::default();
+        for addr in addrs {
+            breakpoints.add(addr);
+        }
+        breakpoints.add(addrs[0]);
+        breakpoints.set_condition(addrs[1], "hits >= 2".parse().unwrap());
+
+        let mut sorted = addrs;
+        sorted.sort();
+        assert_eq!(breakpoints.addresses(), sorted);
+        assert!(breakpoints.iter().map(|b| b.addr).eq(sorted));
+
+        assert!(!breakpoints.hit(addrs[1], &sys));
+        assert!(breakpoints.hit(addrs[1], &sys));
+        assert!(breakpoints.hit(addrs[2], &sys));
+
+        breakpoints.remove(addrs[0]);
+        assert!(!breakpoints.contains(addrs[0]));
+        assert_eq!(breakpoints.get(addrs[1]).unwrap().hits, 2);
+        assert_eq!(breakpoints.addresses().len(), 3);
+    }
+
+    #[test]
+    fn predicates() {
+        let mut sys = system();
+        let addr = Address(0x8000_3100);
+
+        let mut breakpoints = Breakpoints::default();
+        breakpoints.add(addr);
+        breakpoints.set_condition(addr, "r3 == 1".parse().unwrap());
+        breakpoints.set_predicate(addr, Some(|sys| sys.mem.ram()[0x10] == 0xAB));
+
+        sys.cpu.user.gpr[3] = 1;
+        assert!(!breakpoints.hit(addr, &sys));
+
+        sys.mem.ram_mut()[0x10] = 0xAB;
+        assert!(breakpoints.hit(addr, &sys));
+
+        // both must hold
+        sys.cpu.user.gpr[3] = 2;
+        assert!(!breakpoints.hit(addr, &sys));
+
+        breakpoints.set_predicate(addr, None);
+        sys.cpu.user.gpr[3] = 1;
+        sys.mem.ram_mut()[0x10] = 0;
+        assert!(breakpoints.hit(addr, &sys));
+    }
+
+    #[test]
+    fn value_watches() {
+        let addr = Address(0x8000_1000);
+        let pc = Address(0x8000_3100);
+        let mut value = 0x1234_5678u32;
+
+        let mut watches = Watches::default();
+        watches.add(addr, 0xFFFF_0000, value);
+
+        // writes elsewhere and masked out changes don't hit
+        watches.check(Address(0x8000_1004), 4, pc, |_| Some(value));
+        value = 0x1234_0000;
+        watches.check(Address(0x8000_1002), 2, pc, |_| Some(value));
+        assert!(!watches.has_hit());
+
+        // a byte write inside the word does
+        value = 0x1299_0000;
+        watches.check(Address(0x8000_1001), 1, pc, |_| Some(value));
+        assert_eq!(
+            watches.take_hit(),
+            Some(WatchHit {
+                addr,
+                pc,
+                old: 0x1234_0000,
+                new: 0x1299_0000,
+            })
+        );
+
+        // the new value is the reference from now on
+        watches.check(addr, 4, pc, |_| Some(value));
+        assert!(!watches.has_hit());
+
+        watches.remove(addr);
+        assert!(watches.is_empty());
+    }
+}
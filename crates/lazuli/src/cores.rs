@@ -1,3 +1,5 @@
+use std::any::Any;
+
 use gekko::{Address, Cycles};
 
 use crate::system::System;
@@ -19,13 +21,52 @@ pub trait CpuCore: Send {
     fn exec(&mut self, sys: &mut System, cycles: Cycles, breakpoints: &[Address]) -> Executed;
     /// Steps the CPU, i.e. runs exactly 1 instruction.
     fn step(&mut self, sys: &mut System) -> Executed;
+    /// Resets the core, discarding any state derived from the previous boot (e.g. compiled code).
+    fn reset(&mut self, sys: &mut System);
+    /// Discards any state derived from the 32 byte cache line at the logical address `addr`
+    /// (e.g. compiled code), which was modified from outside of the CPU.
+    fn invalidate_code(&mut self, _: &mut System, _: Address) {}
+    /// Writes implementation specific state useful to diagnose a crash into `out` (e.g. the code
+    /// being executed). Called after unwinding from a panic, so it must not execute or compile any
+    /// code, as the core might have been left in an inconsistent state.
+    fn crash_report(&self, _: &System, _: &mut String) {}
+    /// Returns a human readable dump of the compiled code for the block at the logical address
+    /// `addr`, if the core compiles code and has a block there.
+    fn dump_block(&mut self, _: &System, _: Address) -> Option<String> {
+        None
+    }
+}
+
+#[derive(Default, Clone, Copy)]
+pub struct DspExecuted {
+    /// How many instructions have been executed.
+    pub instructions: u32,
+    /// How many DSP cycles have been consumed, including any the DSP spent idle.
+    pub cycles: u32,
+    /// Whether a breakpoint was hit.
+    pub hit_breakpoint: bool,
 }
 
 /// Trait for DSP cores.
 pub trait DspCore: Send {
-    /// Drives the DSP core forward by _at most_ the specified amount of instructions. The actual
-    /// number of instructions executed is returned.
-    fn exec(&mut self, sys: &mut System, instructions: u32) -> u32;
+    /// Drives the DSP core forward by _at most_ the specified amount of DSP cycles, stopping at
+    /// any breakpoints the core has been configured with.
+    ///
+    /// Unless a breakpoint is hit, the whole budget must be consumed: if the DSP is idle (e.g.
+    /// halted or waiting for mail), the core can skip the rest of it without executing anything.
+    fn exec(&mut self, sys: &mut System, cycles: u32) -> DspExecuted;
+    /// Steps the DSP, i.e. runs exactly 1 instruction, ignoring breakpoints.
+    fn step(&mut self, sys: &mut System);
+    /// Resets the core to its power-on state.
+    fn reset(&mut self, sys: &mut System);
+    /// Writes implementation specific state useful to diagnose a crash into `out` (e.g. registers
+    /// and recent mails). Called after unwinding from a panic, so it must not execute any code.
+    fn crash_report(&self, _: &System, _: &mut String) {}
+    /// Returns the core as [`Any`], allowing debuggers to inspect implementation specific state.
+    fn as_any(&self) -> &dyn Any;
+    /// Returns the core as mutable [`Any`], allowing debuggers to modify implementation specific
+    /// state.
+    fn as_any_mut(&mut self) -> &mut dyn Any;
 }
 
 /// Cores that emulate system components.
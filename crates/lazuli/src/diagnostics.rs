@@ -0,0 +1,355 @@
+//! Diagnostic bundles, which collect the state of the emulator into a single file that can be
+//! attached to bug reports.
+//!
+//! A bundle is a manifest followed by a list of named sections, all compressed with zstd. Besides
+//! the 32-byte disc header, disc contents are never included unless the user opts into a copy of
+//! main memory.
+
+use std::fmt::Write as _;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use easyerr::{Error, ResultExt};
+use gekko::Cpu;
+
+use crate::Lazuli;
+
+/// Magic bytes at the start of a decompressed bundle.
+const MAGIC: [u8; 8] = *b"LZDIAG\0\0";
+/// Version of the bundle format.
+pub const FORMAT_VERSION: u32 = 1;
+/// Number of bytes of the disc header included in bundles.
+pub const DISC_HEADER_LEN: usize = 32;
+/// zstd compression level of bundles.
+const COMPRESSION_LEVEL: i32 = 9;
+
+/// A named section of a [`Bundle`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+impl Section {
+    pub fn text(name: impl Into<String>, text: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            data: text.into().into_bytes(),
+        }
+    }
+
+    pub fn binary(name: impl Into<String>, data: Vec<u8>) -> Self {
+        Self {
+            name: name.into(),
+            data,
+        }
+    }
+
+    /// Returns the contents of this section as text, or `None` if they're not valid UTF-8.
+    pub fn as_text(&self) -> Option<&str> {
+        std::str::from_utf8(&self.data).ok()
+    }
+}
+
+/// What to include in a [`Bundle`] besides the state of the emulator.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Options {
+    /// Whether to include a copy of main memory. It likely contains data loaded from the disc, so
+    /// it must only be included if the user explicitly asks for it.
+    pub include_ram: bool,
+}
+
+#[derive(Debug, Error)]
+pub enum BundleError {
+    #[error(transparent)]
+    Io { source: std::io::Error },
+    #[error("not a diagnostic bundle")]
+    InvalidMagic,
+    #[error("unsupported bundle format version {version}")]
+    UnsupportedVersion { version: u32 },
+    #[error("section name is not valid UTF-8")]
+    InvalidName,
+}
+
+/// A diagnostic bundle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bundle {
+    /// Version of the emulator which created the bundle.
+    pub version: String,
+    /// When the bundle was created, in seconds since the Unix epoch.
+    pub created: u64,
+    pub sections: Vec<Section>,
+}
+
+impl Default for Bundle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn read_u16(reader: &mut impl Read) -> std::io::Result<u16> {
+    let mut bytes = [0; 2];
+    reader.read_exact(&mut bytes)?;
+    Ok(u16::from_le_bytes(bytes))
+}
+
+fn read_u32(reader: &mut impl Read) -> std::io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> std::io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_string(reader: &mut impl Read) -> Result<String, BundleError> {
+    let len = read_u16(reader).context(BundleCtx::Io)?;
+    let mut bytes = vec![0; len as usize];
+    reader.read_exact(&mut bytes).context(BundleCtx::Io)?;
+    String::from_utf8(bytes).map_err(|_| BundleError::InvalidName)
+}
+
+fn write_string(out: &mut Vec<u8>, value: &str) {
+    let len = u16::try_from(value.len()).expect("string is too long");
+    out.extend_from_slice(&len.to_le_bytes());
+    out.extend_from_slice(value.as_bytes());
+}
+
+impl Bundle {
+    /// Creates an empty bundle, created now by this version of the emulator.
+    pub fn new() -> Self {
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            created,
+            sections: Vec::new(),
+        }
+    }
+
+    /// Returns the section with the given name, if any.
+    pub fn section(&self, name: &str) -> Option<&Section> {
+        self.sections.iter().find(|s| s.name == name)
+    }
+
+    /// Writes this bundle, compressed.
+    pub fn write(&self, writer: impl Write) -> Result<(), BundleError> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&MAGIC);
+        out.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+
+        // manifest
+        write_string(&mut out, &self.version);
+        out.extend_from_slice(&self.created.to_le_bytes());
+        out.extend_from_slice(&(self.sections.len() as u32).to_le_bytes());
+        for section in &self.sections {
+            write_string(&mut out, &section.name);
+            out.extend_from_slice(&(section.data.len() as u64).to_le_bytes());
+        }
+
+        for section in &self.sections {
+            out.extend_from_slice(&section.data);
+        }
+
+        zstd::stream::copy_encode(out.as_slice(), writer, COMPRESSION_LEVEL).context(BundleCtx::Io)
+    }
+
+    /// Reads a compressed bundle.
+    pub fn read(reader: impl Read) -> Result<Self, BundleError> {
+        let mut reader = zstd::Decoder::new(reader).context(BundleCtx::Io)?;
+
+        let mut magic = [0; MAGIC.len()];
+        reader.read_exact(&mut magic).context(BundleCtx::Io)?;
+        if magic != MAGIC {
+            return Err(BundleError::InvalidMagic);
+        }
+
+        let version = read_u32(&mut reader).context(BundleCtx::Io)?;
+        if version != FORMAT_VERSION {
+            return Err(BundleError::UnsupportedVersion { version });
+        }
+
+        // manifest
+        let emulator_version = read_string(&mut reader)?;
+        let created = read_u64(&mut reader).context(BundleCtx::Io)?;
+        let count = read_u32(&mut reader).context(BundleCtx::Io)?;
+        let mut manifest = Vec::new();
+        for _ in 0..count {
+            let name = read_string(&mut reader)?;
+            let len = read_u64(&mut reader).context(BundleCtx::Io)?;
+            manifest.push((name, len));
+        }
+
+        let mut sections = Vec::with_capacity(manifest.len());
+        for (name, len) in manifest {
+            let mut data = Vec::new();
+            (&mut reader)
+                .take(len)
+                .read_to_end(&mut data)
+                .context(BundleCtx::Io)?;
+
+            if data.len() as u64 != len {
+                return Err(BundleError::Io {
+                    source: std::io::ErrorKind::UnexpectedEof.into(),
+                });
+            }
+
+            sections.push(Section { name, data });
+        }
+
+        Ok(Self {
+            version: emulator_version,
+            created,
+            sections,
+        })
+    }
+}
+
+/// Writes the registers of the CPU.
+pub(crate) fn write_cpu_state(out: &mut String, cpu: &Cpu) {
+    _ = writeln!(out, "PC: {}", cpu.pc);
+    for (i, row) in cpu.user.gpr.chunks(4).enumerate() {
+        for (j, value) in row.iter().enumerate() {
+            _ = write!(out, "r{:<2}: {value:08X}  ", 4 * i + j);
+        }
+
+        _ = writeln!(out);
+    }
+
+    _ = writeln!(out, "LR: {:08X}  CTR: {:08X}", cpu.user.lr, cpu.user.ctr);
+    _ = writeln!(
+        out,
+        "SRR0: {:08X}  SRR1: {:08X}",
+        cpu.supervisor.exception.srr[0], cpu.supervisor.exception.srr[1]
+    );
+    _ = writeln!(out, "{:#?}", cpu.user);
+    _ = writeln!(out, "{:#?}", cpu.supervisor);
+}
+
+/// Collects a bundle of the current state of the emulator.
+pub(crate) fn collect(lazuli: &mut Lazuli, options: Options) -> Bundle {
+    let mut bundle = Bundle::new();
+    let sys = &mut lazuli.sys;
+
+    let config = &sys.config;
+    let mut settings = String::new();
+    _ = writeln!(settings, "IPL LLE: {}", config.ipl_lle);
+    _ = writeln!(settings, "IPL provided: {}", config.ipl.is_some());
+    _ = writeln!(
+        settings,
+        "Sideloaded executable: {}",
+        config.sideload.is_some()
+    );
+    _ = writeln!(settings, "EFB copies: {}", config.perform_efb_copies);
+    _ = writeln!(
+        settings,
+        "CPU clock multiplier: {}",
+        config.cpu_clock_multiplier
+    );
+    _ = writeln!(settings, "Fast disc: {}", config.fast_disc);
+    _ = writeln!(settings, "HLE patches: {:?}", config.hle);
+    bundle.sections.push(Section::text("settings", settings));
+
+    let mut cpu = String::new();
+    write_cpu_state(&mut cpu, &sys.cpu);
+    bundle.sections.push(Section::text("cpu", cpu));
+
+    let mut cpu_core = String::new();
+    lazuli.cores.cpu.crash_report(sys, &mut cpu_core);
+    bundle.sections.push(Section::text("cpu_core", cpu_core));
+
+    let mut dsp = String::new();
+    _ = writeln!(dsp, "{:#?}", sys.dsp.control);
+    _ = writeln!(dsp, "DSP mailbox: {:#?}", sys.dsp.dsp_mailbox);
+    _ = writeln!(dsp, "CPU mailbox: {:#?}", sys.dsp.cpu_mailbox);
+    lazuli.cores.dsp.crash_report(sys, &mut dsp);
+    bundle.sections.push(Section::text("dsp", dsp));
+
+    let gpu = &sys.gpu;
+    let mut gx = String::new();
+    _ = writeln!(gx, "{:#?}", gpu.mode);
+    _ = writeln!(gx, "{:#?}", gpu.line_point_size);
+    _ = writeln!(gx, "Write mask: {:06X}", gpu.write_mask);
+    _ = writeln!(gx, "{:#?}", gpu.cmd.status);
+    _ = writeln!(gx, "{:#?}", gpu.cmd.control);
+    _ = writeln!(gx, "{:#?}", gpu.cmd.fifo);
+    _ = writeln!(gx, "{:#?}", gpu.cmd.internal);
+    _ = writeln!(gx, "{:#?}", gpu.env);
+    _ = writeln!(gx, "{:#?}", gpu.pix);
+    bundle.sections.push(Section::text("gx", gx));
+
+    if sys.modules.disk.has_disk() {
+        let mut header = vec![0; DISC_HEADER_LEN];
+        let disk = &mut sys.modules.disk;
+        match disk
+            .seek(SeekFrom::Start(0))
+            .and_then(|_| disk.read_exact(&mut header))
+        {
+            Ok(()) => bundle.sections.push(Section::binary("disc_header", header)),
+            Err(e) => tracing::warn!("failed to read disc header for diagnostic bundle: {e}"),
+        }
+    }
+
+    if options.include_ram {
+        bundle
+            .sections
+            .push(Section::binary("ram", sys.mem.ram().to_vec()));
+    }
+
+    bundle
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Bundle, BundleError, Section};
+
+    #[test]
+    fn roundtrip() {
+        let mut bundle = Bundle::new();
+        bundle
+            .sections
+            .push(Section::text("cpu", "PC: 0x8000_3100\n"));
+        bundle.sections.push(Section::binary("empty", Vec::new()));
+        bundle.sections.push(Section::binary(
+            "ram",
+            (0..=255).cycle().take(0x10000).collect(),
+        ));
+
+        let mut encoded = Vec::new();
+        bundle.write(&mut encoded).unwrap();
+        assert!(encoded.len() < 0x10000);
+
+        let decoded = Bundle::read(encoded.as_slice()).unwrap();
+        assert_eq!(decoded, bundle);
+        assert_eq!(
+            decoded.section("cpu").and_then(Section::as_text),
+            Some("PC: 0x8000_3100\n")
+        );
+        assert!(decoded.section("disc_header").is_none());
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let encoded = zstd::encode_all(&b"definitely not a bundle"[..], 0).unwrap();
+        assert!(matches!(
+            Bundle::read(encoded.as_slice()),
+            Err(BundleError::InvalidMagic)
+        ));
+
+        // truncated
+        let mut encoded = Vec::new();
+        Bundle::new().write(&mut encoded).unwrap();
+        let decoded = zstd::decode_all(encoded.as_slice()).unwrap();
+        let truncated = zstd::encode_all(&decoded[..decoded.len() - 1], 0).unwrap();
+        assert!(matches!(
+            Bundle::read(truncated.as_slice()),
+            Err(BundleError::Io { .. })
+        ));
+    }
+}
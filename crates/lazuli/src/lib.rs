@@ -1,27 +1,26 @@
-#![feature(deque_extend_front)]
-
 pub mod primitive;
 pub mod stream;
 
+pub mod adpcm;
+pub mod breakpoint;
 pub mod cores;
+pub mod diagnostics;
 pub mod modules;
 
 pub mod panic;
 pub mod system;
+pub mod timing;
 
 pub use disks;
 pub use gekko::{self, Address, Cycles};
 pub use primitive::Primitive;
 
+use crate::breakpoint::Breakpoints;
 use crate::cores::Cores;
 use crate::system::{Modules, System};
 
-/// How many DSP instructions to execute per cycle.
-const DSP_INST_PER_CYCLE: f64 = 1.0;
 /// How many DSP cycles to execute per step.
 const DSP_STEP: u32 = 64;
-/// How many DSP instructions to execute per step.
-const DSP_INST_PER_STEP: u32 = (DSP_STEP as f64 * DSP_INST_PER_CYCLE) as u32;
 
 /// The Lazuli emulator.
 pub struct Lazuli {
@@ -31,68 +30,582 @@ pub struct Lazuli {
     cores: Cores,
     /// How many DSP cycles are pending.
     dsp_pending: f64,
+    /// System cycles until enough DSP cycles are pending for a whole step. Recomputed from
+    /// `dsp_pending` whenever the DSP executes and counted down in between.
+    dsp_countdown: Cycles,
+    /// Fraction of a system cycle the CPU has executed but which hasn't been accounted for yet.
+    /// Only ever non-zero when the CPU clock is scaled.
+    cpu_pending: f64,
+    /// System cycles executed past the budget of a previous call to [`Lazuli::exec`], which are
+    /// taken from the budget of the next ones. Cores execute whole blocks, so they can slightly
+    /// overshoot the cycles they're asked to execute.
+    overshoot: Cycles,
 }
 
 impl Lazuli {
     pub fn new(cores: Cores, modules: Modules, config: system::Config) -> Self {
+        assert!(
+            config.cpu_clock_multiplier > 0.0,
+            "CPU clock multiplier must be positive"
+        );
+
         Self {
             sys: System::new(modules, config),
             cores,
             dsp_pending: 0.0,
+            dsp_countdown: Self::dsp_step_after(0.0),
+            cpu_pending: 0.0,
+            overshoot: Cycles(0),
+        }
+    }
+
+    /// System cycles until a whole DSP step is pending, given how many DSP cycles are pending.
+    fn dsp_step_after(dsp_pending: f64) -> Cycles {
+        Cycles((6.0 * (DSP_STEP as f64 - dsp_pending)).ceil() as u64)
+    }
+
+    /// Converts a number of system cycles into how many CPU cycles fit in them.
+    fn to_cpu_cycles(&self, cycles: Cycles) -> Cycles {
+        let multiplier = self.sys.config.cpu_clock_multiplier;
+        if multiplier == 1.0 {
+            return cycles;
+        }
+
+        Cycles((cycles.0 as f64 * multiplier).ceil().min(u64::MAX as f64) as u64)
+    }
+
+    /// Converts a number of executed CPU cycles into system cycles, carrying over the fractional
+    /// part.
+    fn to_system_cycles(&mut self, cycles: Cycles) -> Cycles {
+        let multiplier = self.sys.config.cpu_clock_multiplier;
+        if multiplier == 1.0 {
+            return cycles;
         }
+
+        let exact = cycles.0 as f64 / multiplier + self.cpu_pending;
+        let whole = exact.floor();
+        self.cpu_pending = exact - whole;
+
+        Cycles(whole as u64)
+    }
+
+    /// Advances emulation by the specified number of system cycles, stopping at any breakpoint
+    /// whose condition holds.
+    ///
+    /// Cycles are always system cycles (i.e. emulated time at stock CPU speed), both here and in
+    /// the returned value. With a scaled CPU clock, the CPU executes more or fewer of its own
+    /// cycles in the same time.
+    ///
+    /// Cycles executed past the requested amount are carried over, so that they're taken from the
+    /// next calls instead of being lost.
+    pub fn exec(&mut self, cycles: Cycles, breakpoints: &mut Breakpoints) -> cores::Executed {
+        panic::catch_crash(self, |lazuli| lazuli.exec_inner(cycles, breakpoints))
     }
 
-    /// Advances emulation by the specified number of CPU cycles.
-    pub fn exec(&mut self, cycles: Cycles, breakpoints: &[Address]) -> cores::Executed {
+    fn exec_inner(&mut self, cycles: Cycles, breakpoints: &mut Breakpoints) -> cores::Executed {
+        self.sys.timing.begin_exec();
+        self.sys.cpu.armed_exceptions = breakpoints.exceptions();
+
+        // cycles already executed by a previous call count towards this one
+        let carried = self.overshoot.min(cycles);
+        self.overshoot -= carried;
+        let budget = cycles - carried;
+
         let mut total_executed = cores::Executed::default();
-        while total_executed.cycles < cycles {
+        while total_executed.cycles < budget {
             // how many CPU cycles can we execute?
-            let remaining = cycles - total_executed.cycles;
-            let until_next_dsp_step =
-                Cycles((6.0 * ((DSP_STEP as f64) - self.dsp_pending)).ceil() as u64);
+            let remaining = budget.saturating_sub(total_executed.cycles);
             let until_next_event = Cycles(self.sys.scheduler.until_next().unwrap_or(u64::MAX));
-            let can_execute = until_next_dsp_step.min(until_next_event).min(remaining);
+            let can_execute = self.dsp_countdown.min(until_next_event).min(remaining);
+
+            // the CPU can't run while a DSP DMA holds the bus
+            let stalled = self.sys.dsp.dma_stall.min(can_execute);
+            self.sys.dsp.dma_stall -= stalled;
 
             // execute CPU
-            let executed = self.cores.cpu.exec(&mut self.sys, can_execute, breakpoints);
+            let executed = if stalled < can_execute {
+                self.cores.cpu.exec(
+                    &mut self.sys,
+                    self.to_cpu_cycles(can_execute.saturating_sub(stalled)),
+                    breakpoints.addresses(),
+                )
+            } else {
+                cores::Executed::default()
+            };
+            let elapsed = stalled + self.to_system_cycles(executed.cycles);
             total_executed.instructions += executed.instructions;
-            total_executed.cycles += executed.cycles;
+            total_executed.cycles += elapsed;
 
             // execute DSP
-            self.dsp_pending += executed.cycles.to_dsp_cycles();
-            while self.dsp_pending >= DSP_STEP as f64 {
-                self.cores.dsp.exec(&mut self.sys, DSP_INST_PER_STEP);
-                self.dsp_pending -= DSP_STEP as f64;
+            self.dsp_pending += elapsed.to_dsp_cycles();
+            let dsp_hit_breakpoint = if elapsed >= self.dsp_countdown {
+                self.exec_dsp()
+            } else {
+                self.dsp_countdown -= elapsed;
+                false
+            };
+
+            self.sys.scheduler.advance(elapsed.0);
+            if self.sys.scheduler.is_due() {
+                self.sys.process_events();
+                self.invalidate_patched_code();
             }
 
-            self.sys.scheduler.advance(executed.cycles.0);
-            self.sys.process_events();
+            // conditions are only evaluated when execution arrives at a breakpoint, which can't
+            // happen if nothing was executed
+            let cpu_hit_breakpoint =
+                executed.instructions > 0 && breakpoints.hit(self.sys.cpu.pc, &self.sys);
 
-            if executed.hit_breakpoint || breakpoints.contains(&self.sys.cpu.pc) {
+            // exceptions can also be raised by events, so check after processing them
+            let caught_exception = self.take_caught_exception();
+            let watch_hit = self.take_watch_hit();
+
+            if cpu_hit_breakpoint || dsp_hit_breakpoint || caught_exception || watch_hit {
                 std::hint::cold_path();
                 total_executed.hit_breakpoint = true;
                 break;
             }
         }
 
+        self.overshoot += total_executed.cycles.saturating_sub(budget);
+        self.sys.timing.end_exec();
         total_executed
     }
 
-    pub fn step(&mut self) -> cores::Executed {
+    /// Executes the DSP in steps of [`DSP_STEP`] cycles while enough cycles are pending. Returns
+    /// whether it hit a breakpoint.
+    ///
+    /// Only the cycles the DSP actually consumed are taken from the pending ones, so when it stops
+    /// at a breakpoint the rest of the step is executed later.
+    fn exec_dsp(&mut self) -> bool {
+        let mut hit_breakpoint = false;
+        while self.dsp_pending >= DSP_STEP as f64 {
+            let executed = self.cores.dsp.exec(&mut self.sys, DSP_STEP);
+            self.dsp_pending -= executed.cycles as f64;
+
+            if executed.hit_breakpoint {
+                std::hint::cold_path();
+                hit_breakpoint = true;
+                break;
+            }
+
+            debug_assert!(executed.cycles > 0, "DSP core did not consume its budget");
+        }
+
+        self.dsp_countdown = Self::dsp_step_after(self.dsp_pending);
+        hit_breakpoint
+    }
+
+    /// Lets the CPU core know about code modified by Gecko and Action Replay codes, and by HLE
+    /// patches.
+    fn invalidate_patched_code(&mut self) {
+        if self.sys.gecko.has_patched() {
+            for addr in self.sys.gecko.take_patched() {
+                self.cores.cpu.invalidate_code(&mut self.sys, addr);
+            }
+        }
+
+        if self.sys.action_replay.has_patched() {
+            for addr in self.sys.action_replay.take_patched() {
+                self.cores.cpu.invalidate_code(&mut self.sys, addr);
+            }
+        }
+
+        if self.sys.hle.has_patched() {
+            for addr in self.sys.hle.take_patched() {
+                self.cores.cpu.invalidate_code(&mut self.sys, addr);
+            }
+        }
+    }
+
+    /// Resets the emulator. See [`System::reset`] for the difference between hard and soft resets.
+    pub fn reset(&mut self, hard: bool) {
+        self.sys.reset(hard);
+        self.cores.cpu.reset(&mut self.sys);
+        self.cores.dsp.reset(&mut self.sys);
+        self.dsp_pending = 0.0;
+        self.dsp_countdown = Self::dsp_step_after(0.0);
+        self.cpu_pending = 0.0;
+        self.overshoot = Cycles(0);
+    }
+
+    /// Collects a diagnostic bundle of the current state of the emulator, for bug reports.
+    pub fn diagnostic_bundle(&mut self, options: diagnostics::Options) -> diagnostics::Bundle {
+        diagnostics::collect(self, options)
+    }
+
+    /// The cores of the emulator.
+    pub fn cores(&self) -> &Cores {
+        &self.cores
+    }
+
+    /// The cores of the emulator.
+    pub fn cores_mut(&mut self) -> &mut Cores {
+        &mut self.cores
+    }
+
+    /// Returns a dump of the compiled code for the block at `addr`, if the CPU core has one. See
+    /// [`CpuCore::dump_block`](cores::CpuCore::dump_block).
+    pub fn dump_block(&mut self, addr: Address) -> Option<String> {
+        self.cores.cpu.dump_block(&self.sys, addr)
+    }
+
+    /// Steps the DSP by a single instruction, without advancing the CPU.
+    pub fn step_dsp(&mut self) {
+        self.cores.dsp.step(&mut self.sys);
+    }
+
+    /// Steps the CPU by a single instruction, then lets the DSP catch up and processes any
+    /// pending events. Returns what was executed and whether the DSP hit a breakpoint.
+    fn step_once(&mut self) -> (cores::Executed, bool) {
         // execute CPU
-        let executed = self.cores.cpu.step(&mut self.sys);
+        let mut executed = self.cores.cpu.step(&mut self.sys);
+        executed.cycles = self.to_system_cycles(executed.cycles);
         self.dsp_pending += executed.cycles.to_dsp_cycles();
 
         // execute DSP
-        while self.dsp_pending >= DSP_STEP as f64 {
-            self.cores.dsp.exec(&mut self.sys, DSP_INST_PER_STEP);
-            self.dsp_pending -= DSP_STEP as f64;
-        }
+        let dsp_hit_breakpoint = self.exec_dsp();
 
         // process events
         self.sys.scheduler.advance(executed.cycles.0);
         self.sys.process_events();
+        self.invalidate_patched_code();
+
+        if self.take_caught_exception() | self.take_watch_hit() {
+            executed.hit_breakpoint = true;
+        }
+
+        (executed, dsp_hit_breakpoint)
+    }
+
+    /// Clears the armed exception caught by the CPU, if any, returning whether there was one.
+    fn take_caught_exception(&mut self) -> bool {
+        let Some(exception) = self.sys.cpu.caught_exception.take() else {
+            return false;
+        };
+
+        std::hint::cold_path();
+        tracing::info!(
+            "stopped at armed exception {exception:?} (SRR0 = {:08X})",
+            self.sys.cpu.supervisor.exception.srr[0]
+        );
+
+        true
+    }
+
+    /// Clears the change detected by a value watch, if any, returning whether there was one.
+    fn take_watch_hit(&mut self) -> bool {
+        let Some(hit) = self.sys.watches.take_hit() else {
+            return false;
+        };
+
+        std::hint::cold_path();
+        tracing::info!(
+            "value at {} changed from {:08X} to {:08X} (PC = {})",
+            hit.addr,
+            hit.old,
+            hit.new,
+            hit.pc
+        );
+
+        true
+    }
+
+    pub fn step(&mut self) -> cores::Executed {
+        panic::catch_crash(self, |lazuli| lazuli.step_once().0)
+    }
+
+    /// Executes exactly `n` CPU instructions, one at a time, unless a breakpoint whose condition
+    /// holds is hit first. Like in [`Lazuli::exec`], a CPU breakpoint is hit when an instruction
+    /// arrives at it, so one at the current instruction doesn't stop execution.
+    ///
+    /// The DSP is interleaved exactly as in [`Lazuli::exec`]: the cycles of each CPU instruction
+    /// accumulate as pending DSP cycles, and the DSP runs in fixed steps of 64 cycles whenever
+    /// enough are pending. This means the DSP lags behind the CPU by up to one step between
+    /// instructions, and that stepping a single instruction might not advance the DSP at all.
+    /// Scheduled events are processed after every instruction.
+    pub fn step_instructions(&mut self, n: u32, breakpoints: &mut Breakpoints) -> cores::Executed {
+        panic::catch_crash(self, |lazuli| {
+            lazuli.step_instructions_inner(n, breakpoints)
+        })
+    }
+
+    fn step_instructions_inner(
+        &mut self,
+        n: u32,
+        breakpoints: &mut Breakpoints,
+    ) -> cores::Executed {
+        self.sys.cpu.armed_exceptions = breakpoints.exceptions();
+
+        let mut total_executed = cores::Executed::default();
+        while total_executed.instructions < n {
+            let (executed, dsp_hit_breakpoint) = self.step_once();
+            total_executed.instructions += executed.instructions;
+            total_executed.cycles += executed.cycles;
+
+            let cpu_hit_breakpoint =
+                executed.instructions > 0 && breakpoints.hit(self.sys.cpu.pc, &self.sys);
+
+            if executed.hit_breakpoint || cpu_hit_breakpoint || dsp_hit_breakpoint {
+                std::hint::cold_path();
+                total_executed.hit_breakpoint = true;
+                break;
+            }
+
+            // a step which makes no progress at all would loop forever
+            if executed.instructions == 0 && executed.cycles == Cycles(0) {
+                break;
+            }
+        }
+
+        total_executed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::any::Any;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use crate::breakpoint::Breakpoints;
+    use crate::cores::{Cores, CpuCore, DspCore, DspExecuted, Executed};
+    use crate::system::{Config, Modules, System};
+    use crate::{Address, Cycles, Lazuli};
+
+    /// A CPU core which executes exactly as many cycles as requested.
+    struct ExactCpu;
+
+    impl CpuCore for ExactCpu {
+        fn exec(&mut self, _: &mut System, cycles: Cycles, _: &[Address]) -> Executed {
+            Executed {
+                instructions: 1,
+                cycles,
+                hit_breakpoint: false,
+            }
+        }
+
+        fn step(&mut self, _: &mut System) -> Executed {
+            Executed {
+                instructions: 1,
+                cycles: Cycles(1),
+                hit_breakpoint: false,
+            }
+        }
+
+        fn reset(&mut self, _: &mut System) {}
+    }
+
+    /// A CPU core which always executes a few more cycles than requested, like a core executing
+    /// whole blocks.
+    struct OvershootingCpu;
+
+    /// How many cycles [`OvershootingCpu`] executes past its budget.
+    const OVERSHOOT: u64 = 7;
+
+    impl CpuCore for OvershootingCpu {
+        fn exec(&mut self, _: &mut System, cycles: Cycles, _: &[Address]) -> Executed {
+            Executed {
+                instructions: 1,
+                cycles: cycles + OVERSHOOT,
+                hit_breakpoint: false,
+            }
+        }
+
+        fn step(&mut self, _: &mut System) -> Executed {
+            Executed {
+                instructions: 1,
+                cycles: Cycles(1),
+                hit_breakpoint: false,
+            }
+        }
+
+        fn reset(&mut self, _: &mut System) {}
+    }
+
+    /// A CPU core which steps over one instruction at a time.
+    struct SteppingCpu;
+
+    impl CpuCore for SteppingCpu {
+        fn exec(&mut self, sys: &mut System, cycles: Cycles, _: &[Address]) -> Executed {
+            self.step(sys);
+            Executed {
+                instructions: 1,
+                cycles,
+                hit_breakpoint: false,
+            }
+        }
+
+        fn step(&mut self, sys: &mut System) -> Executed {
+            sys.cpu.pc += 4u32;
+            Executed {
+                instructions: 1,
+                cycles: Cycles(1),
+                hit_breakpoint: false,
+            }
+        }
+
+        fn reset(&mut self, _: &mut System) {}
+    }
+
+    /// A CPU core which panics as soon as it executes.
+    struct PanickingCpu;
+
+    impl CpuCore for PanickingCpu {
+        fn exec(&mut self, _: &mut System, _: Cycles, _: &[Address]) -> Executed {
+            panic!("miscompiled block");
+        }
+
+        fn step(&mut self, _: &mut System) -> Executed {
+            panic!("miscompiled block");
+        }
+
+        fn reset(&mut self, _: &mut System) {}
+
+        fn crash_report(&self, _: &System, out: &mut String) {
+            out.push_str("panicking core state");
+        }
+    }
+
+    struct IdleDsp;
+
+    impl DspCore for IdleDsp {
+        fn exec(&mut self, _: &mut System, cycles: u32) -> DspExecuted {
+            DspExecuted {
+                instructions: 0,
+                cycles,
+                hit_breakpoint: false,
+            }
+        }
+
+        fn step(&mut self, _: &mut System) {}
+
+        fn reset(&mut self, _: &mut System) {}
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+
+        fn as_any_mut(&mut self) -> &mut dyn Any {
+            self
+        }
+    }
+
+    /// A system with no-op modules and the default configuration.
+    pub(crate) fn system() -> System {
+        System::new(Modules::nop(), Config::default())
+    }
+
+    #[test]
+    fn overshoot_is_carried_over() {
+        let cores = Cores {
+            cpu: Box::new(OvershootingCpu),
+            dsp: Box::new(IdleDsp),
+        };
+
+        let mut lazuli = Lazuli::new(cores, Modules::nop(), Config::default());
+
+        // budgets smaller than the overshoot are consumed entirely by it
+        let mut breakpoints = Breakpoints::default();
+        let mut requested = Cycles(0);
+        let mut executed = Cycles(0);
+        for budget in [1, 3, 2, 100, 1, 5000, 4, 10] {
+            requested += Cycles(budget);
+            executed += lazuli.exec(Cycles(budget), &mut breakpoints).cycles;
+
+            assert_eq!(executed, requested + lazuli.overshoot);
+            assert!(lazuli.overshoot.0 <= OVERSHOOT);
+        }
+    }
+
+    #[test]
+    fn scheduled_callbacks() {
+        static FIRED_AT: AtomicU64 = AtomicU64::new(0);
+        fn record(sys: &mut System) {
+            FIRED_AT.store(sys.scheduler.elapsed(), Ordering::Relaxed);
+        }
+
+        fn unreachable(_: &mut System) {
+            panic!("cancelled callback was called");
+        }
+
+        let cores = Cores {
+            cpu: Box::new(ExactCpu),
+            dsp: Box::new(IdleDsp),
+        };
+
+        let mut lazuli = Lazuli::new(cores, Modules::nop(), Config::default());
+        let mut breakpoints = Breakpoints::default();
+
+        const AFTER: u64 = 1000;
+        let start = lazuli.sys.scheduler.elapsed();
+        lazuli.sys.scheduler.schedule_in(Cycles(AFTER), record);
+        let token = lazuli
+            .sys
+            .scheduler
+            .schedule_at(Cycles(start + AFTER / 2), unreachable);
+        assert!(lazuli.sys.scheduler.cancel(token));
+
+        // one cycle short of the deadline
+        lazuli.exec(Cycles(AFTER - 1), &mut breakpoints);
+        assert_eq!(FIRED_AT.load(Ordering::Relaxed), 0);
+
+        lazuli.exec(Cycles(1), &mut breakpoints);
+        assert_eq!(FIRED_AT.load(Ordering::Relaxed), start + AFTER);
+    }
+
+    #[test]
+    fn step_instructions_stops_at_breakpoints() {
+        let cores = Cores {
+            cpu: Box::new(SteppingCpu),
+            dsp: Box::new(IdleDsp),
+        };
+
+        let mut lazuli = Lazuli::new(cores, Modules::nop(), Config::default());
+        lazuli.sys.cpu.pc = Address(0x8000_3100);
+
+        // one at the current instruction is not hit
+        let mut breakpoints = Breakpoints::default();
+        breakpoints.add(Address(0x8000_3100));
+        breakpoints.add(Address(0x8000_310C));
+
+        let executed = lazuli.step_instructions(10, &mut breakpoints);
+        assert!(executed.hit_breakpoint);
+        assert_eq!(executed.instructions, 3);
+        assert_eq!(lazuli.sys.cpu.pc, Address(0x8000_310C));
+
+        // without breakpoints in the way, exactly `n` instructions are executed
+        let executed = lazuli.step_instructions(10, &mut breakpoints);
+        assert!(!executed.hit_breakpoint);
+        assert_eq!(executed.instructions, 10);
+        assert_eq!(lazuli.sys.cpu.pc, Address(0x8000_3134));
+    }
+
+    #[test]
+    fn crash_report_after_unwinding() {
+        let path = std::env::temp_dir().join(format!("lazuli-crash-{}.log", std::process::id()));
+        crate::panic::set_crash_report_path(&path);
+        crate::panic::set_hook(Box::new(|_| ()), false);
+
+        let cores = Cores {
+            cpu: Box::new(PanickingCpu),
+            dsp: Box::new(IdleDsp),
+        };
+
+        let mut lazuli = Lazuli::new(cores, Modules::nop(), Config::default());
+        lazuli.sys.cpu.pc = Address(0x8000_3100);
+
+        // the panic is resumed once the report is written
+        let mut breakpoints = Breakpoints::default();
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            lazuli.exec(Cycles(100), &mut breakpoints)
+        }));
+        assert!(result.is_err());
+
+        let report = std::fs::read_to_string(&path).unwrap();
+        _ = std::fs::remove_file(&path);
 
-        executed
+        assert!(report.contains("miscompiled block"));
+        assert!(report.contains("PC: 0x8000_3100"));
+        assert!(report.contains("panicking core state"));
     }
 }
@@ -36,6 +36,11 @@ impl<'a> Location<'a> {
 pub trait DebugModule: Send {
     fn find_symbol(&self, addr: Address) -> Option<String>;
     fn find_location(&self, addr: Address) -> Option<Location<'_>>;
+    /// Finds the address of the symbol with the given (unmangled) name, if the module supports
+    /// looking symbols up by name.
+    fn find_address(&self, _: &str) -> Option<Address> {
+        None
+    }
 }
 
 /// An implementation of [`DebugModule`] which does nothing.
@@ -1,6 +1,8 @@
 //! Input module interface.
 
-#[derive(Debug, Clone, Copy)]
+use gekko::Cycles;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ControllerState {
     // Analog
     pub analog_x: u8,
@@ -56,9 +58,102 @@ impl Default for ControllerState {
     }
 }
 
+/// A digital button of a controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ControllerButton {
+    TriggerZ,
+    TriggerLeft,
+    TriggerRight,
+    PadLeft,
+    PadRight,
+    PadDown,
+    PadUp,
+    A,
+    B,
+    X,
+    Y,
+    Start,
+}
+
+impl ControllerButton {
+    pub const ALL: [Self; 12] = [
+        Self::TriggerZ,
+        Self::TriggerLeft,
+        Self::TriggerRight,
+        Self::PadLeft,
+        Self::PadRight,
+        Self::PadDown,
+        Self::PadUp,
+        Self::A,
+        Self::B,
+        Self::X,
+        Self::Y,
+        Self::Start,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::TriggerZ => "Z",
+            Self::TriggerLeft => "L",
+            Self::TriggerRight => "R",
+            Self::PadLeft => "D-Pad Left",
+            Self::PadRight => "D-Pad Right",
+            Self::PadDown => "D-Pad Down",
+            Self::PadUp => "D-Pad Up",
+            Self::A => "A",
+            Self::B => "B",
+            Self::X => "X",
+            Self::Y => "Y",
+            Self::Start => "Start",
+        }
+    }
+}
+
+impl ControllerState {
+    /// Sets whether the given button is pressed.
+    pub fn set(&mut self, button: ControllerButton, pressed: bool) {
+        let value = match button {
+            ControllerButton::TriggerZ => &mut self.trigger_z,
+            ControllerButton::TriggerLeft => &mut self.trigger_left,
+            ControllerButton::TriggerRight => &mut self.trigger_right,
+            ControllerButton::PadLeft => &mut self.pad_left,
+            ControllerButton::PadRight => &mut self.pad_right,
+            ControllerButton::PadDown => &mut self.pad_down,
+            ControllerButton::PadUp => &mut self.pad_up,
+            ControllerButton::A => &mut self.button_a,
+            ControllerButton::B => &mut self.button_b,
+            ControllerButton::X => &mut self.button_x,
+            ControllerButton::Y => &mut self.button_y,
+            ControllerButton::Start => &mut self.button_start,
+        };
+
+        *value = pressed;
+    }
+
+    /// Whether the given button is pressed.
+    pub fn pressed(&self, button: ControllerButton) -> bool {
+        match button {
+            ControllerButton::TriggerZ => self.trigger_z,
+            ControllerButton::TriggerLeft => self.trigger_left,
+            ControllerButton::TriggerRight => self.trigger_right,
+            ControllerButton::PadLeft => self.pad_left,
+            ControllerButton::PadRight => self.pad_right,
+            ControllerButton::PadDown => self.pad_down,
+            ControllerButton::PadUp => self.pad_up,
+            ControllerButton::A => self.button_a,
+            ControllerButton::B => self.button_b,
+            ControllerButton::X => self.button_x,
+            ControllerButton::Y => self.button_y,
+            ControllerButton::Start => self.button_start,
+        }
+    }
+}
+
 /// Trait for controller modules.
 pub trait InputModule: Send {
-    fn controller(&mut self, index: usize) -> Option<ControllerState>;
+    /// Polls the controller at the given port. `time` is the number of cycles elapsed since the
+    /// system started.
+    fn controller(&mut self, index: usize, time: Cycles) -> Option<ControllerState>;
 }
 
 /// An implementation of [`InputModule`] which does nothing: every controller is always
@@ -67,7 +162,7 @@ pub trait InputModule: Send {
 pub struct NopInputModule;
 
 impl InputModule for NopInputModule {
-    fn controller(&mut self, _: usize) -> Option<ControllerState> {
+    fn controller(&mut self, _: usize, _: Cycles) -> Option<ControllerState> {
         None
     }
 }
@@ -5,6 +5,7 @@ use glam::Mat4;
 use oneshot::Sender;
 use ordered_float::OrderedFloat;
 use static_assertions::const_assert;
+use strum::IntoStaticStr;
 
 use crate::system::gx::pix::{
     BlendMode, BufferFormat, ColorCopyFormat, ConstantAlpha, CopyDims, CopySrc, DepthCopyFormat,
@@ -12,7 +13,9 @@ use crate::system::gx::pix::{
 };
 use crate::system::gx::tev::Fog;
 use crate::system::gx::xform::{BaseTexGen, Channel, Light, ProjectionMtx};
-use crate::system::gx::{CullingMode, EFB_HEIGHT, EFB_WIDTH, Topology, VertexStream, tev, tex};
+use crate::system::gx::{
+    CullingMode, EFB_HEIGHT, EFB_WIDTH, LinePointSize, Topology, VertexStream, tev, tex,
+};
 use crate::system::vi::Dimensions;
 
 #[rustfmt::skip]
@@ -162,18 +165,30 @@ pub struct XfbPart {
     pub id: u32,
     pub offset_x: u32,
     pub offset_y: u32,
+    /// Distance between the frame rows of consecutive lines of the copy. This is 2 when the copy
+    /// is a single field of an interlaced frame, and 1 otherwise.
+    pub line_step: u32,
 }
 
 /// A vector of texture data (i.e. it's texels). For color textures, the data is encoded as
 /// RGBA8. For depth textures, it's encoded as a F32 (little-endian).
 pub type Texels = Vec<u32>;
 
+/// A buffer of the EFB.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EfbBuffer {
+    Color,
+    Depth,
+}
+
+#[derive(IntoStaticStr)]
 pub enum Action {
     SetXfbDimensions(Dimensions),
     SetEfbFormat(BufferFormat),
     SetViewport(Viewport),
     SetScissor(Scissor),
     SetCullingMode(CullingMode),
+    SetLinePointSize(LinePointSize),
     SetClearColor(Rgba),
     SetClearDepth(f32),
     SetDepthMode(DepthMode),
@@ -205,6 +220,9 @@ pub enum Action {
         sampler: Sampler,
         scaling: Scaling,
     },
+    /// Releases the data of textures previously evicted by the module. These are loaded again
+    /// before being used.
+    ReleaseTextures(Vec<TextureId>),
     Draw(Topology, VertexStream),
     CopyColor {
         args: CopyArgs,
@@ -223,12 +241,34 @@ pub enum Action {
         id: u32,
     },
     PresentXfb(Vec<XfbPart>),
+    /// Reads back the whole contents of an EFB buffer, row by row.
+    ReadEfb {
+        buffer: EfbBuffer,
+        response: Sender<Texels>,
+    },
+    PokeColor {
+        x: u16,
+        y: u16,
+        color: Rgba,
+    },
+    PokeDepth {
+        x: u16,
+        y: u16,
+        depth: f32,
+    },
 }
 
 const_assert!(size_of::<Action>() <= 64);
 
 pub trait RenderModule: Send {
     fn exec(&mut self, action: Action);
+
+    /// Takes the IDs of textures evicted by the module since the last call. The emulator must
+    /// load these again before using them, and should release them with
+    /// [`Action::ReleaseTextures`].
+    fn take_evicted_textures(&mut self) -> Vec<TextureId> {
+        Vec::new()
+    }
 }
 
 /// An implementation of [`RenderModule`] that does nothing.
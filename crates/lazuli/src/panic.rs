@@ -1,11 +1,18 @@
-//! Thread-local panic hooks.
+//! Thread-local panic hooks and crash reports.
 
-use std::cell::RefCell;
-use std::panic::PanicHookInfo;
+use std::backtrace::Backtrace;
+use std::cell::{Cell, RefCell};
+use std::fmt::Write;
+use std::panic::{AssertUnwindSafe, PanicHookInfo};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 use std::sync::atomic::{AtomicBool, Ordering};
 
 use color_backtrace::{BacktracePrinter, default_output_stream};
 
+use crate::Lazuli;
+use crate::diagnostics::write_cpu_state;
+
 pub type PanicHook = Box<dyn Fn(&PanicHookInfo)>;
 
 struct Config {
@@ -15,8 +22,24 @@ struct Config {
 
 thread_local! {
     static CONFIG: RefCell<Config> = const { RefCell::new(Config { hook: None, print_backtrace: true }) };
+    /// Whether the emulator is executing on this thread.
+    static EXECUTING: Cell<bool> = const { Cell::new(false) };
+    /// A panic which happened while the emulator was executing on this thread, to be reported
+    /// once it has unwound.
+    static CAPTURED: RefCell<Option<Captured>> = const { RefCell::new(None) };
+}
+
+/// What the panic hook knows about a panic, which isn't available anymore after unwinding.
+struct Captured {
+    thread: String,
+    location: String,
+    message: String,
+    backtrace: Backtrace,
 }
 
+/// Where crash reports are written to. `None` if disabled.
+static CRASH_REPORT_PATH: Mutex<Option<PathBuf>> = Mutex::new(None);
+
 fn setup() {
     static SETUP: AtomicBool = AtomicBool::new(false);
     if SETUP.load(Ordering::Acquire) {
@@ -24,6 +47,7 @@ fn setup() {
     }
 
     std::panic::set_hook(Box::new(move |info| {
+        capture(info);
         CONFIG.with_borrow(|config| {
             if let Some(hook) = &config.hook {
                 hook(info)
@@ -46,3 +70,98 @@ pub fn set_hook(hook: PanicHook, print_backtrace: bool) {
         print_backtrace,
     });
 }
+
+/// Enables crash reports, written to `path` whenever a panic happens while the emulator is
+/// executing.
+pub fn set_crash_report_path(path: impl Into<PathBuf>) {
+    setup();
+    *CRASH_REPORT_PATH.lock().unwrap() = Some(path.into());
+}
+
+/// Executes `f`, writing a crash report if it panics.
+///
+/// The report is written after unwinding back to here, where the emulator can be borrowed again,
+/// and the panic is then resumed. Builds which abort on panic (e.g. `release-dist`) never unwind,
+/// so they don't write reports.
+pub(crate) fn catch_crash<T>(lazuli: &mut Lazuli, f: impl FnOnce(&mut Lazuli) -> T) -> T {
+    let previous = EXECUTING.replace(true);
+    let result = std::panic::catch_unwind(AssertUnwindSafe(|| f(lazuli)));
+    EXECUTING.set(previous);
+
+    // panics caught further down (e.g. by plugins) are captured as well, so always clear it
+    let captured = CAPTURED.take();
+    match result {
+        Ok(value) => value,
+        Err(payload) => {
+            if let Some(captured) = captured {
+                self::crash_report(lazuli, &captured);
+            }
+
+            std::panic::resume_unwind(payload)
+        }
+    }
+}
+
+/// Captures the panic for a crash report if the panicking thread is executing the emulator.
+fn capture(info: &PanicHookInfo) {
+    if !EXECUTING.get() || CRASH_REPORT_PATH.lock().is_ok_and(|path| path.is_none()) {
+        return;
+    }
+
+    let thread = std::thread::current();
+    CAPTURED.set(Some(Captured {
+        thread: thread.name().unwrap_or("<unnamed>").to_owned(),
+        location: info
+            .location()
+            .map(ToString::to_string)
+            .unwrap_or_else(|| "<unknown>".into()),
+        message: info
+            .payload_as_str()
+            .unwrap_or("<non-string payload>")
+            .to_owned(),
+        backtrace: Backtrace::force_capture(),
+    }));
+}
+
+/// Writes a crash report for a captured panic.
+fn crash_report(lazuli: &Lazuli, captured: &Captured) {
+    let path = CRASH_REPORT_PATH
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone();
+
+    let Some(path) = path else {
+        return;
+    };
+
+    match write_crash_report(&path, lazuli, captured) {
+        Ok(()) => eprintln!("crash report written to {}", path.display()),
+        Err(e) => eprintln!("failed to write crash report to {}: {e}", path.display()),
+    }
+}
+
+fn write_crash_report(path: &Path, lazuli: &Lazuli, captured: &Captured) -> std::io::Result<()> {
+    let mut out = String::new();
+
+    _ = writeln!(out, "=== Panic ===");
+    _ = writeln!(
+        out,
+        "thread '{}' panicked at {}:",
+        captured.thread, captured.location
+    );
+    _ = writeln!(out, "{}", captured.message);
+
+    _ = writeln!(out, "\n=== CPU ===");
+    write_cpu_state(&mut out, &lazuli.sys.cpu);
+
+    _ = writeln!(out, "\n=== CPU Core ===");
+    lazuli.cores.cpu.crash_report(&lazuli.sys, &mut out);
+
+    _ = writeln!(out, "\n=== DSP Core ===");
+    lazuli.cores.dsp.crash_report(&lazuli.sys, &mut out);
+
+    _ = writeln!(out, "\n=== Backtrace ===");
+    _ = writeln!(out, "{}", captured.backtrace);
+
+    std::fs::write(path, out)
+}
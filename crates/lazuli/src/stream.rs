@@ -105,7 +105,10 @@ impl BinRingBuffer {
     }
 
     pub fn push_front_bytes(&mut self, bytes: &[u8]) {
-        self.data.prepend(bytes.iter().copied());
+        self.data.reserve(bytes.len());
+        for byte in bytes.iter().rev() {
+            self.data.push_front(*byte);
+        }
     }
 
     /// Current length of the buffer.
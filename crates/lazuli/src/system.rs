@@ -1,8 +1,11 @@
 //! State of the system (i.e. GameCube and emulator).
 
+pub mod action_replay;
 pub mod bus;
 pub mod eabi;
 pub mod executable;
+pub mod gecko;
+pub mod hle;
 pub mod ipl;
 pub mod lazy;
 pub mod os;
@@ -15,29 +18,35 @@ pub mod exi;
 pub mod gx;
 pub mod mem;
 pub mod pi;
+pub mod pmc;
 pub mod si;
 pub mod vi;
+pub mod wgp;
 
 use std::io::{Cursor, SeekFrom};
 
 use disks::binrw::BinRead;
 use disks::{apploader, dol, iso};
 use easyerr::{Error, ResultExt};
-use gekko::{Address, Cpu, Cycles};
-
-use crate::modules::audio::AudioModule;
-use crate::modules::debug::DebugModule;
-use crate::modules::disk::DiskModule;
-use crate::modules::input::InputModule;
-use crate::modules::render::RenderModule;
-use crate::modules::vertex::VertexModule;
+use gekko::{Address, Cpu, Cycles, DmaDirection};
+
+use crate::breakpoint::Watches;
+use crate::modules::audio::{AudioModule, NopAudioModule};
+use crate::modules::debug::{DebugModule, NopDebugModule};
+use crate::modules::disk::{DiskModule, NopDiskModule};
+use crate::modules::input::{InputModule, NopInputModule};
+use crate::modules::render::{NopRenderModule, RenderModule};
+use crate::modules::vertex::{NopVertexModule, VertexModule};
+use crate::system::action_replay::ActionReplayEngine;
 use crate::system::dspi::Dsp;
 use crate::system::executable::Executable;
+use crate::system::gecko::GeckoEngine;
 use crate::system::gx::Gpu;
 use crate::system::ipl::Ipl;
 use crate::system::lazy::Lazy;
 use crate::system::mem::Memory;
-use crate::system::scheduler::{HandlerCtx, Scheduler};
+use crate::system::scheduler::{HandlerCtx, Scheduler, SchedulerEventKind};
+use crate::timing;
 
 /// System configuration.
 pub struct Config {
@@ -45,6 +54,33 @@ pub struct Config {
     pub ipl: Option<Vec<u8>>,
     pub sideload: Option<Executable>,
     pub perform_efb_copies: bool,
+    /// Factor by which the CPU clock is scaled relative to [`FREQUENCY`](gekko::FREQUENCY).
+    /// Values above 1.0 overclock the CPU, values below underclock it. Must be positive.
+    ///
+    /// Only the CPU is affected: every other component (including the DSP, VI and audio) keeps
+    /// running at stock speed, so A/V stays in sync. The decrementer and the time base are driven
+    /// by the bus clock and therefore also keep their stock rates, which means the guest observes
+    /// more (or fewer) instructions per timer tick.
+    pub cpu_clock_multiplier: f64,
+    /// Whether disc commands complete almost instantly, instead of taking as long as they would
+    /// on a real drive.
+    pub fast_disc: bool,
+    /// Which built-in HLE patches are installed.
+    pub hle: hle::Config,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            ipl_lle: false,
+            ipl: None,
+            sideload: None,
+            perform_efb_copies: false,
+            cpu_clock_multiplier: 1.0,
+            fast_disc: false,
+            hle: Default::default(),
+        }
+    }
 }
 
 /// System modules.
@@ -57,6 +93,20 @@ pub struct Modules {
     pub vertex: Box<dyn VertexModule>,
 }
 
+impl Modules {
+    /// Modules which do nothing.
+    pub fn nop() -> Self {
+        Self {
+            audio: Box::new(NopAudioModule),
+            debug: Box::new(NopDebugModule),
+            disk: Box::new(NopDiskModule),
+            input: Box::new(NopInputModule),
+            render: Box::new(NopRenderModule),
+            vertex: Box::new(NopVertexModule),
+        }
+    }
+}
+
 /// System state.
 pub struct System {
     /// System configuration.
@@ -87,6 +137,20 @@ pub struct System {
     pub disk: di::Interface,
     /// The serial interface.
     pub serial: si::Interface,
+    /// The write gather pipe.
+    pub gather_pipe: wgp::GatherPipe,
+    /// Host time spent in each frame.
+    pub timing: timing::Tracker,
+    /// Devices mapped into the MMIO region.
+    pub devices: bus::Devices,
+    /// Gecko codes applied to memory every frame.
+    pub gecko: GeckoEngine,
+    /// Action Replay codes applied to memory every frame.
+    pub action_replay: ActionReplayEngine,
+    /// HLE patches for SDK functions.
+    pub hle: hle::Patches,
+    /// Words in memory whose changes stop execution.
+    pub watches: Watches,
 }
 
 #[derive(Debug, Error)]
@@ -113,43 +177,56 @@ impl System {
         Ok(Address(apploader.header.entrypoint))
     }
 
+    /// Writes the contents of an executable section to memory. Sections running into unmapped
+    /// memory (or past the end of the address space) are truncated.
+    fn load_section(&mut self, target: Address, content: &[u8]) {
+        if let Err(fault) = self.write_block(target, content) {
+            tracing::warn!("section at {target} was truncated: {fault}");
+        }
+    }
+
     fn load_executable(&mut self) {
         let Some(exec) = self.config.sideload.take() else {
             return;
         };
 
+        self.cpu.pc = exec.entrypoint();
+        self.cpu.supervisor.memory.setup_default_bats();
+        self.mem.build_bat_lut(&self.cpu.supervisor.memory);
+
+        self.cpu
+            .supervisor
+            .config
+            .msr
+            .set_instr_addr_translation(true);
+        self.cpu
+            .supervisor
+            .config
+            .msr
+            .set_data_addr_translation(true);
+
         match &exec {
             Executable::Dol(dol) => {
-                self.cpu.pc = Address(dol.entrypoint());
-                self.cpu.supervisor.memory.setup_default_bats();
-                self.mem.build_bat_lut(&self.cpu.supervisor.memory);
-
-                self.cpu
-                    .supervisor
-                    .config
-                    .msr
-                    .set_instr_addr_translation(true);
-                self.cpu
-                    .supervisor
-                    .config
-                    .msr
-                    .set_data_addr_translation(true);
-
                 // zero bss first, let other sections overwrite it if it occurs
-                for offset in 0..dol.header.bss_size {
-                    self.write(Address(dol.header.bss_target + offset), 0u8);
-                }
+                let bss = vec![0; dol.header.bss_size as usize];
+                self.load_section(Address(dol.header.bss_target), &bss);
 
                 for section in dol.text_sections() {
-                    for (offset, byte) in section.content.iter().copied().enumerate() {
-                        self.write(Address(section.target) + offset as u32, byte);
-                    }
+                    self.load_section(Address(section.target), section.content);
                 }
 
                 for section in dol.data_sections() {
-                    for (offset, byte) in section.content.iter().copied().enumerate() {
-                        self.write(Address(section.target) + offset as u32, byte);
-                    }
+                    self.load_section(Address(section.target), section.content);
+                }
+            }
+            Executable::Elf(elf) => {
+                for segment in &elf.segments {
+                    self.load_section(segment.target, &segment.content);
+
+                    // zero-fill whatever is not backed by the file (i.e. bss)
+                    let filled = segment.content.len() as u32;
+                    let zeros = vec![0; (segment.size - filled) as usize];
+                    self.load_section(segment.target + filled, &zeros);
                 }
             }
         }
@@ -197,8 +274,9 @@ impl System {
             "/../../local/ipl-hle.dol"
         )));
         let ipl = dol::Dol::read(&mut cursor).unwrap();
-        self.config.sideload = Some(Executable::Dol(ipl));
+        let sideload = self.config.sideload.replace(Executable::Dol(ipl));
         self.load_executable();
+        self.config.sideload = sideload;
 
         // setup apploader entrypoint for ipl-hle
         self.cpu.user.gpr[3] = entry.value();
@@ -243,10 +321,22 @@ impl System {
 
     pub fn new(modules: Modules, mut config: Config) -> Self {
         let mut scheduler = Scheduler::default();
-        scheduler.schedule(1 << 16, gx::cmd::process);
+        scheduler.schedule_repeating(
+            gx::cmd::PROCESS_INTERVAL,
+            SchedulerEventKind::ProcessCommands,
+        );
+        scheduler.schedule_repeating(gecko::APPLY_INTERVAL, SchedulerEventKind::GeckoCodes);
+        scheduler.schedule_repeating(
+            action_replay::APPLY_INTERVAL,
+            SchedulerEventKind::ActionReplayCodes,
+        );
 
         let ipl = Ipl::new(config.ipl.take().unwrap_or_else(|| vec![0; mem::IPL_LEN]));
 
+        let mut devices = bus::Devices::default();
+        devices.register::<di::Interface>();
+        devices.register::<si::Interface>();
+
         let mut system = System {
             scheduler,
             cpu: Cpu::default(),
@@ -260,24 +350,132 @@ impl System {
             audio: ai::Interface::default(),
             disk: di::Interface::default(),
             serial: si::Interface::default(),
+            gather_pipe: wgp::GatherPipe::default(),
+            timing: timing::Tracker::default(),
+            devices,
+            gecko: GeckoEngine::default(),
+            action_replay: ActionReplayEngine::default(),
+            hle: hle::Patches::new(&config.hle),
+            watches: Watches::default(),
 
             config,
             modules,
         };
 
-        if system.config.ipl_lle {
-            system.load_ipl();
-        } else if system.config.sideload.is_some() {
-            system.load_executable();
-        } else if system.modules.disk.has_disk() {
-            system.load_ipl_hle();
-        } else {
-            system.load_ipl();
+        if let Some(path) = std::env::var_os(gx::trace::PATH_VAR) {
+            if let Err(e) = gx::trace::start(&mut system, &path) {
+                tracing::error!("failed to start GX register trace: {e}");
+            }
         }
 
+        system.boot();
         system
     }
 
+    /// Sets up the system for execution, either through the IPL, a sideloaded executable or IPL
+    /// HLE.
+    fn boot(&mut self) {
+        self.hle.forget_game();
+        if self.config.ipl_lle {
+            self.load_ipl();
+            self.install_patches_on_game_start();
+        } else if self.config.sideload.is_some() {
+            self.load_executable();
+            hle::install(self);
+        } else if self.modules.disk.has_disk() {
+            self.load_ipl_hle();
+            self.install_patches_on_game_start();
+        } else {
+            self.load_ipl();
+        }
+    }
+
+    /// Reads the header of the executable of the inserted disc.
+    fn read_game_header(&mut self) -> Result<dol::Header, disks::binrw::Error> {
+        self.modules.disk.seek(SeekFrom::Start(0))?;
+        let header = iso::Header::read(&mut self.modules.disk)?;
+
+        self.modules
+            .disk
+            .seek(SeekFrom::Start(header.bootfile_offset as u64))?;
+        dol::Header::read(&mut self.modules.disk)
+    }
+
+    /// Installs the HLE patches once the executable of the inserted disc starts running.
+    fn install_patches_on_game_start(&mut self) {
+        if self.hle.is_empty() || !self.modules.disk.has_disk() {
+            return;
+        }
+
+        match self.read_game_header() {
+            Ok(header) => hle::install_on_start(self, &header),
+            Err(e) => tracing::warn!(
+                "failed to read the executable of the disc, HLE patches won't be installed: {e}"
+            ),
+        }
+    }
+
+    /// Resets the system and boots it again.
+    ///
+    /// A hard reset reinitializes every component, as if the console had been power cycled. A soft
+    /// reset behaves like the reset button: main memory, ARAM, SRAM and the processor interface
+    /// reset code are preserved and the reset switch interrupt is raised, so the OS can detect it
+    /// was restarted.
+    pub fn reset(&mut self, hard: bool) {
+        tracing::info!(hard, "resetting system");
+
+        let mut scheduler = Scheduler::default();
+        scheduler.schedule_repeating(
+            gx::cmd::PROCESS_INTERVAL,
+            SchedulerEventKind::ProcessCommands,
+        );
+        scheduler.schedule_repeating(gecko::APPLY_INTERVAL, SchedulerEventKind::GeckoCodes);
+        scheduler.schedule_repeating(
+            action_replay::APPLY_INTERVAL,
+            SchedulerEventKind::ActionReplayCodes,
+        );
+
+        self.scheduler = scheduler;
+        self.cpu = Cpu::default();
+        let trace = self.gpu.trace.take();
+        self.gpu = Gpu::default();
+        self.gpu.trace = trace;
+        self.lazy = Lazy::default();
+        self.video = vi::Interface::default();
+        self.audio = ai::Interface::default();
+        self.disk = di::Interface::default();
+        self.serial = si::Interface::default();
+        self.gather_pipe = wgp::GatherPipe::default();
+        self.timing.clear();
+
+        if hard {
+            self.dsp = Dsp::new();
+            self.external = exi::Interface::new();
+            self.processor = pi::Interface::default();
+            self.mem.ram_mut().fill(0);
+            self.mem.l2c_mut().fill(0);
+        } else {
+            let old = std::mem::replace(&mut self.dsp, Dsp::new());
+            self.dsp.aram = old.aram;
+            self.dsp.aram_len = old.aram_len;
+
+            self.external.channel0 = Default::default();
+            self.external.channel1 = Default::default();
+            self.external.channel2 = Default::default();
+
+            // pressing the reset switch raises its interrupt, which the OS polls for
+            self.processor = pi::Interface {
+                reset_code: self.processor.reset_code,
+                reset_switch: true,
+                ..Default::default()
+            };
+        }
+
+        self.mem.build_bat_lut(&self.cpu.supervisor.memory);
+        self.hid2_changed();
+        self.boot();
+    }
+
     /// Processes scheduled events.
     #[inline(always)]
     pub fn process_events(&mut self) {
@@ -287,7 +485,137 @@ impl System {
                 cycles_late: Cycles(cycles_late),
             };
 
-            event.handler.call(self, ctx);
+            event.kind.handler().call(self, ctx);
         }
     }
+
+    /// Restores the pending scheduled events, as returned by [`Scheduler::serialize`]. Tokens kept
+    /// by the system are updated to point to the restored events.
+    pub fn restore_events(&mut self, events: Vec<(u64, SchedulerEventKind)>) {
+        self.scheduler.restore(events);
+        self.lazy.decrementer_event = self.scheduler.find(SchedulerEventKind::DecrementerOverflow);
+    }
+
+    /// Maps or unmaps the locked cache according to HID2.LCE. Should be called whenever HID2 is
+    /// written.
+    pub fn hid2_changed(&mut self) {
+        let enabled = self.cpu.supervisor.config.hid2().locked_cache();
+        self.mem.set_locked_cache(enabled);
+    }
+
+    /// Executes the locked cache DMA configured in `DMAU`/`DMAL`, if it has been triggered. Should
+    /// be called whenever one of these registers is written.
+    ///
+    /// The transfer happens instantly, so the trigger and flush bits are always cleared afterwards.
+    pub fn execute_dma(&mut self) {
+        let dma = &mut self.cpu.supervisor.config.dma;
+        let trigger = dma.lower.trigger();
+        dma.lower.set_trigger(false);
+        dma.lower.set_flush(false);
+
+        if !trigger {
+            return;
+        }
+
+        let dma = dma.clone();
+        let length = dma.length() as usize;
+        let ram_offset = dma.mem_address().value() as usize;
+        let l2c_offset = dma.cache_address().value().wrapping_sub(mem::L2C_START) as usize;
+
+        let regions = self.mem.regions();
+        let (Some(ram), Some(l2c)) = (
+            regions.ram.get_mut(ram_offset..ram_offset + length),
+            regions.l2c.get_mut(l2c_offset..l2c_offset + length),
+        ) else {
+            tracing::warn!(
+                "locked cache DMA out of bounds: {} <-> {}, length {length}",
+                dma.mem_address(),
+                dma.cache_address()
+            );
+            return;
+        };
+
+        match dma.lower.direction() {
+            DmaDirection::FromCacheToRam => {
+                // written through the block transfer path, so value watches see it
+                let data = l2c.to_vec();
+                if let Err(fault) = self.write_phys_block(dma.mem_address(), &data) {
+                    tracing::warn!("locked cache DMA to RAM failed: {fault}");
+                }
+            }
+            DmaDirection::FromRamToCache => l2c.copy_from_slice(ram),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bitos::integer::{u2, u5, u27};
+    use gekko::Address;
+    use gekko::DmaDirection::{self, FromCacheToRam, FromRamToCache};
+
+    use crate::system::{System, pi};
+    use crate::test::system;
+
+    /// Writes `DMAU` and `DMAL` for a transfer of `lines` 32 byte lines.
+    fn kick(sys: &mut System, ram: u32, l2c: u32, lines: u32, direction: DmaDirection) {
+        let dma = &mut sys.cpu.supervisor.config.dma;
+        dma.upper.set_mem_base(u27::new(ram >> 5));
+        dma.upper.set_length_upper(u5::new((lines >> 2) as u8));
+        dma.lower.set_cache_base(u27::new(l2c >> 5));
+        dma.lower.set_length_lower(u2::new((lines & 0b11) as u8));
+        dma.lower.set_direction(direction);
+        dma.lower.set_trigger(true);
+        sys.execute_dma();
+    }
+
+    #[test]
+    fn locked_cache_dma() {
+        let mut sys = system();
+        for (i, byte) in sys.mem.l2c_mut()[..0x60].iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+
+        // flush 3 lines from the cache to RAM
+        sys.watches.add(Address(0x1020), !0, 0);
+        kick(&mut sys, 0x1000, 0xE000_0000, 3, FromCacheToRam);
+        assert_eq!(sys.mem.ram()[0x1000..0x1060], sys.mem.l2c()[..0x60]);
+        assert_eq!(sys.mem.ram()[0x1060], 0);
+        assert!(!sys.cpu.supervisor.config.dma.lower.trigger());
+
+        // flushing is a write to RAM, so watches see it
+        let hit = sys.watches.take_hit().unwrap();
+        assert_eq!(hit.new, 0x2021_2223);
+
+        // and load them back elsewhere in the cache
+        kick(&mut sys, 0x1000, 0xE000_0100, 3, FromRamToCache);
+        assert_eq!(sys.mem.l2c()[0x100..0x160], sys.mem.ram()[0x1000..0x1060]);
+
+        // transfers outside of the cache are ignored
+        kick(&mut sys, 0x1000, 0x8000_0000, 3, FromRamToCache);
+        assert!(!sys.cpu.supervisor.config.dma.lower.trigger());
+    }
+
+    #[test]
+    fn reset_switch() {
+        let mut sys = system();
+        let cause = Address(0x0C00_3000);
+        let pressed = |sys: &System| pi::get_active_interrupts(sys).reset();
+
+        sys.reset(true);
+        assert!(!pressed(&sys));
+
+        sys.mem.ram_mut()[0x0100_0000] = 0xAB;
+        sys.reset(false);
+        assert!(pressed(&sys));
+        assert_eq!(sys.mem.ram()[0x0100_0000], 0xAB);
+
+        // the switch reads as released, and acknowledging the interrupt clears it
+        assert_ne!(
+            sys.read_phys_slow::<u32>(cause) & pi::RESET_SWITCH_RELEASED,
+            0
+        );
+        sys.write_phys_slow::<u32>(cause, 1 << 1);
+        assert!(!pressed(&sys));
+    }
 }
@@ -0,0 +1,403 @@
+//! Action Replay codes, another popular format for patching games.
+//!
+//! Only decrypted codes are supported. Each line is made of two 32-bit hex words: the first one
+//! holds the code kind in its top 7 bits and an address offset from `0x8000_0000` in the rest, and
+//! the second one holds a value. The top 7 bits are split into:
+//!
+//! - bits 30..32: the subtype
+//! - bits 27..30: the type
+//! - bits 25..27: the size of the access (8, 16 or 32 bits)
+//!
+//! Supported codes are RAM writes (type and subtype 0, e.g. `00`, `02` and `04` for 8, 16 and
+//! 32-bit writes) and equality conditions (type 1, e.g. `08`, `0A` and `0C`), which skip the next
+//! line, the next two lines or the rest of the code if the value in memory differs. Zero codes
+//! (lines starting with `00000000`) are not supported.
+
+use easyerr::Error;
+use gekko::{Address, FREQUENCY};
+
+use crate::Primitive;
+use crate::system::System;
+use crate::system::gecko::{patch, ram_offset};
+
+/// Interval at which codes are applied, once per frame.
+pub const APPLY_INTERVAL: u64 = FREQUENCY / 60;
+
+#[derive(Debug, Error)]
+pub enum ActionReplayError {
+    #[error("line {line}: invalid or unsupported code")]
+    InvalidLine { line: usize },
+}
+
+/// Size of the memory access of a code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArSize {
+    Byte,
+    Half,
+    Word,
+}
+
+/// What is skipped when a condition doesn't hold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArSkip {
+    /// The next line.
+    Line,
+    /// The next two lines.
+    TwoLines,
+    /// The rest of the code.
+    All,
+}
+
+/// A single line of an Action Replay code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArCode {
+    /// Writes `value` to `count + 1` consecutive bytes.
+    Write8 {
+        addr: u32,
+        value: u8,
+        count: u32,
+    },
+    /// Writes `value` to `count + 1` consecutive halfwords.
+    Write16 {
+        addr: u32,
+        value: u16,
+        count: u16,
+    },
+    Write32 {
+        addr: u32,
+        value: u32,
+    },
+    /// Compares the value of the given size at `addr` with `value`, skipping lines if they
+    /// differ.
+    IfEqual {
+        size: ArSize,
+        addr: u32,
+        value: u32,
+        skip: ArSkip,
+    },
+}
+
+/// A named list of [`ArCode`]s.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActionReplayCode {
+    pub name: String,
+    lines: Vec<ArCode>,
+}
+
+/// Applies Action Replay codes to the system memory.
+#[derive(Debug, Default)]
+pub struct ActionReplayEngine {
+    codes: Vec<ActionReplayCode>,
+    /// Cache lines modified by codes since the last call to
+    /// [`ActionReplayEngine::take_patched`].
+    patched: Vec<Address>,
+}
+
+impl ActionReplayEngine {
+    /// Parses a single line in the `XXXXXXXX YYYYYYYY` format. Returns `None` if the line is
+    /// malformed or the code is not supported.
+    pub fn parse_code(line: &str) -> Option<ArCode> {
+        let mut words = line.split_whitespace().map(|word| {
+            (word.len() == 8)
+                .then(|| u32::from_str_radix(word, 16).ok())
+                .flatten()
+        });
+
+        let (Some(Some(a)), Some(Some(b)), None) = (words.next(), words.next(), words.next())
+        else {
+            return None;
+        };
+
+        if a == 0 {
+            return None;
+        }
+
+        let addr = 0x8000_0000 | (a & 0x01FF_FFFF);
+        let size = match (a >> 25) & 0b11 {
+            0 => ArSize::Byte,
+            1 => ArSize::Half,
+            2 => ArSize::Word,
+            _ => return None,
+        };
+        let kind = (a >> 27) & 0b111;
+        let subtype = a >> 30;
+
+        let code = match (kind, subtype, size) {
+            (0, 0, ArSize::Byte) => ArCode::Write8 {
+                addr,
+                value: b as u8,
+                count: b >> 8,
+            },
+            (0, 0, ArSize::Half) => ArCode::Write16 {
+                addr,
+                value: b as u16,
+                count: (b >> 16) as u16,
+            },
+            (0, 0, ArSize::Word) => ArCode::Write32 { addr, value: b },
+            (1, _, _) => ArCode::IfEqual {
+                size,
+                addr,
+                value: b,
+                skip: match subtype {
+                    0 => ArSkip::Line,
+                    1 => ArSkip::TwoLines,
+                    2 => ArSkip::All,
+                    _ => return None,
+                },
+            },
+            _ => return None,
+        };
+
+        Some(code)
+    }
+
+    /// Parses codes in the usual text format: a line starting with `$` begins a new code with
+    /// the rest of the line as its name, followed by its lines. Empty lines, notes (starting with
+    /// `*`) and section headers (starting with `[`) are ignored.
+    pub fn load_codes(text: &str) -> Result<Vec<ActionReplayCode>, ActionReplayError> {
+        let mut codes = Vec::new();
+        let mut name = String::from("Unnamed");
+        let mut lines = Vec::new();
+
+        let mut finish = |name: &mut String, lines: &mut Vec<ArCode>| {
+            if !lines.is_empty() {
+                codes.push(ActionReplayCode {
+                    name: std::mem::take(name),
+                    lines: std::mem::take(lines),
+                });
+            }
+        };
+
+        for (i, text) in text.lines().enumerate() {
+            let text = text.trim();
+            if text.is_empty() || text.starts_with(['*', '[']) {
+                continue;
+            }
+
+            if let Some(rest) = text.strip_prefix('$') {
+                finish(&mut name, &mut lines);
+                name = rest.trim().to_owned();
+                continue;
+            }
+
+            let code =
+                Self::parse_code(text).ok_or(ActionReplayError::InvalidLine { line: i + 1 })?;
+            lines.push(code);
+        }
+
+        finish(&mut name, &mut lines);
+        Ok(codes)
+    }
+
+    /// The codes being applied.
+    pub fn codes(&self) -> &[ActionReplayCode] {
+        &self.codes
+    }
+
+    /// Replaces the codes being applied.
+    pub fn set_codes(&mut self, codes: Vec<ActionReplayCode>) {
+        self.codes = codes;
+    }
+
+    /// Takes the addresses of the cache lines modified by codes, so that any code compiled from
+    /// them can be invalidated.
+    pub fn take_patched(&mut self) -> Vec<Address> {
+        std::mem::take(&mut self.patched)
+    }
+
+    /// Whether codes modified memory since the last call to
+    /// [`ActionReplayEngine::take_patched`].
+    pub fn has_patched(&self) -> bool {
+        !self.patched.is_empty()
+    }
+
+    fn read<P: Primitive>(ram: &[u8], addr: u32) -> Option<P> {
+        let offset = ram_offset(addr, size_of::<P>())?;
+        Some(P::read_be_bytes(&ram[offset..]))
+    }
+
+    fn write<P: Primitive>(ram: &mut [u8], patched: &mut Vec<Address>, addr: u32, value: P) {
+        if !patch(ram, patched, addr, value) {
+            tracing::warn!(
+                "action replay code writing to {} outside of RAM",
+                Address(addr)
+            );
+        }
+    }
+
+    /// Runs every code once over `ram`.
+    fn run(&mut self, ram: &mut [u8]) {
+        let patched = &mut self.patched;
+        for code in &self.codes {
+            let mut lines = code.lines.iter();
+            while let Some(line) = lines.next() {
+                match *line {
+                    ArCode::Write8 { addr, value, count } => {
+                        for i in 0..=count {
+                            Self::write(ram, patched, addr.wrapping_add(i), value);
+                        }
+                    }
+                    ArCode::Write16 { addr, value, count } => {
+                        for i in 0..=count as u32 {
+                            Self::write(ram, patched, addr.wrapping_add(2 * i), value);
+                        }
+                    }
+                    ArCode::Write32 { addr, value } => Self::write(ram, patched, addr, value),
+                    ArCode::IfEqual {
+                        size,
+                        addr,
+                        value,
+                        skip,
+                    } => {
+                        let current = match size {
+                            ArSize::Byte => Self::read::<u8>(ram, addr).map(|x| x as u32),
+                            ArSize::Half => Self::read::<u16>(ram, addr).map(|x| x as u32),
+                            ArSize::Word => Self::read::<u32>(ram, addr),
+                        };
+
+                        let mask = match size {
+                            ArSize::Byte => 0xFF,
+                            ArSize::Half => 0xFFFF,
+                            ArSize::Word => !0,
+                        };
+
+                        if current == Some(value & mask) {
+                            continue;
+                        }
+
+                        match skip {
+                            ArSkip::Line => {
+                                lines.next();
+                            }
+                            ArSkip::TwoLines => {
+                                lines.nth(1);
+                            }
+                            ArSkip::All => break,
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Applies the codes. Runs every [`APPLY_INTERVAL`] cycles.
+    pub fn apply(sys: &mut System) {
+        sys.action_replay.run(sys.mem.ram_mut());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ActionReplayEngine, ActionReplayError, ArCode, ArSize, ArSkip};
+    use crate::system::mem::RAM_LEN;
+
+    fn run(text: &str, ram: &mut [u8]) -> ActionReplayEngine {
+        let mut engine = ActionReplayEngine::default();
+        engine.set_codes(ActionReplayEngine::load_codes(text).unwrap());
+        engine.run(ram);
+        engine
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            ActionReplayEngine::parse_code("00001234 00000263"),
+            Some(ArCode::Write8 {
+                addr: 0x8000_1234,
+                value: 0x63,
+                count: 2
+            })
+        );
+        assert_eq!(
+            ActionReplayEngine::parse_code("0C001234 DEADBEEF"),
+            Some(ArCode::IfEqual {
+                size: ArSize::Word,
+                addr: 0x8000_1234,
+                value: 0xDEAD_BEEF,
+                skip: ArSkip::Line
+            })
+        );
+        assert_eq!(
+            ActionReplayEngine::parse_code("4A001234 0000BEEF"),
+            Some(ArCode::IfEqual {
+                size: ArSize::Half,
+                addr: 0x8000_1234,
+                value: 0xBEEF,
+                skip: ArSkip::TwoLines
+            })
+        );
+
+        // zero codes, other types, encrypted codes and malformed lines
+        assert_eq!(ActionReplayEngine::parse_code("00000000 40000000"), None);
+        assert_eq!(ActionReplayEngine::parse_code("10001234 00000001"), None);
+        assert_eq!(ActionReplayEngine::parse_code("ABCD-EFGH-IJKLM"), None);
+        assert_eq!(ActionReplayEngine::parse_code("0400123 00000063"), None);
+
+        let codes = ActionReplayEngine::load_codes(
+            "[ActionReplay]\n$Lives\n04001234 00000063\n$Other\n*note\n00000010 00000001\n",
+        )
+        .unwrap();
+        assert_eq!(codes.len(), 2);
+        assert_eq!(codes[0].name, "Lives");
+        assert_eq!(codes[1].name, "Other");
+
+        assert!(matches!(
+            ActionReplayEngine::load_codes("$Bad\n04001234 00000063\nZZ"),
+            Err(ActionReplayError::InvalidLine { line: 3 })
+        ));
+    }
+
+    #[test]
+    fn writes() {
+        let mut ram = vec![0; RAM_LEN];
+        let mut engine = run(
+            "00000010 00000211\n02000020 00012233\n04000030 DEADBEEF\n",
+            &mut ram,
+        );
+
+        assert_eq!(ram[0x10..0x14], [0x11, 0x11, 0x11, 0]);
+        assert_eq!(ram[0x20..0x24], [0x22, 0x33, 0x22, 0x33]);
+        assert_eq!(ram[0x30..0x34], [0xDE, 0xAD, 0xBE, 0xEF]);
+        assert_eq!(engine.take_patched().len(), 2);
+
+        // nothing changes the second time
+        engine.run(&mut ram);
+        assert!(!engine.has_patched());
+    }
+
+    #[test]
+    fn conditions() {
+        let mut ram = vec![0; RAM_LEN];
+        ram[0x100..0x104].copy_from_slice(&5u32.to_be_bytes());
+
+        run(
+            "\
+            0C000100 00000005\n\
+            04000000 00000001\n\
+            0C000100 00000006\n\
+            04000004 00000001\n\
+            04000008 00000001\n\
+            4C000100 00000006\n\
+            0400000C 00000001\n\
+            04000010 00000001\n\
+            04000014 00000001\n\
+            08000103 00000005\n\
+            04000018 00000001\n\
+            8C000100 00000000\n\
+            0400001C 00000001\n",
+            &mut ram,
+        );
+
+        assert_eq!(ram[0x00..0x04], 1u32.to_be_bytes(), "true");
+        assert_eq!(ram[0x04..0x08], 0u32.to_be_bytes(), "false, skipped");
+        assert_eq!(ram[0x08..0x0C], 1u32.to_be_bytes(), "after one line");
+        assert_eq!(ram[0x0C..0x14], [0; 8], "false, two lines skipped");
+        assert_eq!(ram[0x14..0x18], 1u32.to_be_bytes(), "after two lines");
+        assert_eq!(ram[0x18..0x1C], 1u32.to_be_bytes(), "byte comparison");
+        assert_eq!(
+            ram[0x1C..0x20],
+            0u32.to_be_bytes(),
+            "rest of the code skipped"
+        );
+    }
+}
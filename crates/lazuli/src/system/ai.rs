@@ -1,11 +1,21 @@
 //! Audio interface (AI).
+//!
+//! The sample counter advances once per streamed sample while streaming plays. Like the beam
+//! position of the VI, it's computed from the scheduler time when read, and its interrupt is
+//! scheduled for the exact cycle the counter reaches the interrupt sample.
+use std::collections::VecDeque;
+
 use bitos::integer::u15;
 use bitos::{BitUtils, bitos};
 use gekko::Address;
 use zerocopy::{FromBytes, Immutable, IntoBytes};
 
-use crate::system::scheduler::HandlerCtx;
-use crate::system::{System, pi};
+use crate::system::scheduler::{HandlerCtx, SchedulerEventKind};
+use crate::system::{System, di, pi};
+
+/// Maximum number of streamed frames waiting to be mixed into DMA audio. Older frames are dropped
+/// past this, e.g. while DMA audio isn't playing.
+const MAX_PENDING_STREAM: usize = 4096;
 
 #[bitos(1)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -59,60 +69,179 @@ pub struct DmaControl {
     pub playing: bool,
 }
 
+/// Volume of streamed audio.
+#[bitos(32)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Volume {
+    #[bits(0..8)]
+    pub left: u8,
+    #[bits(8..16)]
+    pub right: u8,
+}
+
+impl Volume {
+    pub fn apply(&self, frame: Frame) -> Frame {
+        let scale = |sample: i16, volume: u8| (sample as i32 * volume as i32 / 0xFF) as i16;
+        Frame {
+            left: scale(frame.left, self.left()),
+            right: scale(frame.right, self.right()),
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct Interface {
     pub control: Control,
+    pub volume: Volume,
     pub dma_base: Address,
     pub dma_control: DmaControl,
     pub current_dma_block: u16,
-    pub sample_counter: u32,
     pub interrupt_sample: u32,
+    /// Value of the sample counter at `counter_start`.
+    counter_base: u32,
+    /// Cycle since which the sample counter advances, while streaming plays.
+    counter_start: u64,
+    /// Streamed frames waiting to be mixed into DMA audio.
+    pending_stream: VecDeque<Frame>,
+    /// Streamed frame currently being mixed.
+    current_stream: Frame,
+    /// Fraction of a streamed frame consumed by the DMA frames mixed so far.
+    stream_phase: f64,
 }
 
 impl Interface {
-    pub fn write_control(&mut self, value: Control) {
-        self.control.set_playing(value.playing());
-        self.control.set_aux_sample_rate(value.aux_sample_rate());
-        self.control
-            .set_interrupt_enabled(value.interrupt_enabled());
-        self.control
-            .set_interrupt(self.control.interrupt() & !value.interrupt());
-        self.control.set_interrupt_valid(value.interrupt_valid());
-
-        if value.sample_counter_reset() {
-            self.sample_counter = 0;
+    /// Value of the sample counter on the given cycle.
+    pub fn sample_counter_at(&self, cycle: u64) -> u32 {
+        if !self.control.playing() {
+            return self.counter_base;
+        }
+
+        let samples = cycle.saturating_sub(self.counter_start)
+            / self.control.aux_sample_rate().cycles_per_frame();
+        self.counter_base.wrapping_add(samples as u32)
+    }
+
+    /// Cycle on which the sample counter reaches the interrupt sample, if the interrupt is valid
+    /// and the counter reaches it after the given cycle without wrapping around.
+    pub fn interrupt_cycle(&self, cycle: u64) -> Option<u64> {
+        if !self.control.playing() || !self.control.interrupt_valid() {
+            return None;
         }
 
-        self.control.set_dsp_sample_rate(value.dsp_sample_rate());
+        let current = self.sample_counter_at(cycle);
+        (self.interrupt_sample > current).then(|| {
+            let samples = self.interrupt_sample.wrapping_sub(self.counter_base) as u64;
+            self.counter_start + samples * self.control.aux_sample_rate().cycles_per_frame()
+        })
+    }
+
+    /// Mixes streamed audio into a frame of DMA audio, resampling it to the DSP sample rate.
+    fn mix_stream(&mut self, frame: Frame) -> Frame {
+        let ratio = self.control.aux_sample_rate().value() as f64
+            / self.control.dsp_sample_rate().value() as f64;
+
+        self.stream_phase += ratio;
+        while self.stream_phase >= 1.0 {
+            self.stream_phase -= 1.0;
+            self.current_stream = self.pending_stream.pop_front().unwrap_or_default();
+        }
+
+        Frame {
+            left: frame.left.saturating_add(self.current_stream.left),
+            right: frame.right.saturating_add(self.current_stream.right),
+        }
     }
 }
 
-fn push_streaming_frame(sys: &mut System, ctx: HandlerCtx) {
-    sys.audio.sample_counter += 1;
-    if sys.audio.control.interrupt_valid() && sys.audio.sample_counter == sys.audio.interrupt_sample
-    {
-        println!("raising sample counter int");
-        sys.audio.control.set_interrupt(true);
-        pi::check_interrupts(sys);
+/// Writes to the control register, starting or stopping streaming and rescheduling the sample
+/// counter interrupt as needed.
+pub fn write_control(sys: &mut System, value: Control) {
+    let now = sys.scheduler.elapsed();
+    let audio = &mut sys.audio;
+    let already_playing = audio.control.playing();
+
+    // the counter continues from its current value, at the new rate
+    audio.counter_base = audio.sample_counter_at(now);
+    audio.counter_start = now;
+
+    audio.control.set_playing(value.playing());
+    audio.control.set_aux_sample_rate(value.aux_sample_rate());
+    audio
+        .control
+        .set_interrupt_enabled(value.interrupt_enabled());
+    audio
+        .control
+        .set_interrupt(audio.control.interrupt() & !value.interrupt());
+    audio.control.set_interrupt_valid(value.interrupt_valid());
+
+    if value.sample_counter_reset() {
+        audio.counter_base = 0;
     }
 
-    sys.scheduler.schedule_full(
+    audio.control.set_dsp_sample_rate(value.dsp_sample_rate());
+
+    if !already_playing && sys.audio.control.playing() {
+        self::start_streaming(sys);
+    } else if !sys.audio.control.playing() {
+        self::stop_streaming(sys);
+    }
+
+    self::schedule_interrupt(sys);
+}
+
+/// Current value of the sample counter.
+pub fn sample_counter(sys: &System) -> u32 {
+    sys.audio.sample_counter_at(sys.scheduler.elapsed())
+}
+
+/// The sample counter reached the interrupt sample.
+pub fn sample_interrupt(sys: &mut System) {
+    tracing::debug!("raising sample counter interrupt");
+    sys.audio.control.set_interrupt(true);
+    pi::check_interrupts(sys);
+}
+
+/// Schedules the sample counter interrupt. Must be called whenever the control or the interrupt
+/// sample registers change.
+pub fn schedule_interrupt(sys: &mut System) {
+    sys.scheduler
+        .cancel_kind(SchedulerEventKind::SampleInterrupt);
+
+    let now = sys.scheduler.elapsed();
+    if let Some(cycle) = sys.audio.interrupt_cycle(now) {
+        sys.scheduler
+            .schedule(cycle - now, SchedulerEventKind::SampleInterrupt);
+    }
+}
+
+/// Pushes a frame of streamed audio and schedules the next one.
+pub fn push_streaming_frame(sys: &mut System, ctx: HandlerCtx) {
+    if let Some(frame) = di::next_stream_frame(sys) {
+        let frame = sys.audio.volume.apply(frame);
+        sys.audio.pending_stream.push_back(frame);
+        if sys.audio.pending_stream.len() > MAX_PENDING_STREAM {
+            sys.audio.pending_stream.pop_front();
+        }
+    }
+
+    sys.scheduler.schedule(
         sys.audio.control.aux_sample_rate().cycles_per_frame() - ctx.cycles_late.value(),
-        self::push_streaming_frame,
+        SchedulerEventKind::StreamingFrame,
     );
 }
 
 pub fn start_streaming(sys: &mut System) {
-    if !sys.scheduler.contains_full(self::push_streaming_frame) {
-        sys.scheduler.schedule_full(
+    if !sys.scheduler.contains(SchedulerEventKind::StreamingFrame) {
+        sys.scheduler.schedule(
             sys.audio.control.aux_sample_rate().cycles_per_frame(),
-            self::push_streaming_frame,
+            SchedulerEventKind::StreamingFrame,
         );
     }
 }
 
 pub fn stop_streaming(sys: &mut System) {
-    sys.scheduler.cancel_full(self::push_streaming_frame);
+    sys.scheduler
+        .cancel_kind(SchedulerEventKind::StreamingFrame);
 }
 
 #[derive(Debug, Clone, Copy, Default, IntoBytes, FromBytes, Immutable)]
@@ -122,7 +251,8 @@ pub struct Frame {
     pub right: i16,
 }
 
-fn push_data_dma_block(sys: &mut System, ctx: HandlerCtx) {
+/// Pushes a block of DMA audio and schedules the next one, unless the DMA is over.
+pub fn push_data_dma_block(sys: &mut System, ctx: HandlerCtx) {
     let addr =
         Address(sys.audio.dma_base.0.with_bit(31, false)) + 32 * sys.audio.current_dma_block as u32;
     let frames: [Frame; 8] = std::array::from_fn(|i| Frame {
@@ -131,6 +261,7 @@ fn push_data_dma_block(sys: &mut System, ctx: HandlerCtx) {
     });
 
     for frame in frames {
+        let frame = sys.audio.mix_stream(frame);
         sys.modules.audio.play(frame);
     }
 
@@ -149,9 +280,9 @@ fn push_data_dma_block(sys: &mut System, ctx: HandlerCtx) {
         }
     }
 
-    sys.scheduler.schedule_full(
+    sys.scheduler.schedule(
         sys.audio.control.dsp_sample_rate().cycles_per_block() - ctx.cycles_late.value(),
-        self::push_data_dma_block,
+        SchedulerEventKind::DmaBlock,
     );
 }
 
@@ -160,14 +291,100 @@ pub fn start_data_dma(sys: &mut System) {
         .audio
         .set_sample_rate(sys.audio.control.dsp_sample_rate());
 
-    if !sys.scheduler.contains_full(self::push_data_dma_block) {
-        sys.scheduler.schedule_full(
+    if !sys.scheduler.contains(SchedulerEventKind::DmaBlock) {
+        sys.scheduler.schedule(
             sys.audio.control.dsp_sample_rate().cycles_per_block(),
-            self::push_data_dma_block,
+            SchedulerEventKind::DmaBlock,
         );
     }
 }
 
 pub fn stop_data_dma(sys: &mut System) {
-    sys.scheduler.cancel_full(self::push_data_dma_block);
+    sys.scheduler.cancel_kind(SchedulerEventKind::DmaBlock);
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Control, SampleRate};
+    use crate::system::{System, ai};
+
+    /// Runs until the sample counter interrupt is raised, in steps of `step` cycles, returning the
+    /// cycle it was raised on.
+    fn run_until_interrupt(sys: &mut System, step: u64) -> u64 {
+        let mut fired = None;
+        for _ in 0..100_000 {
+            sys.scheduler.advance(step);
+            sys.process_events();
+
+            if sys.audio.control.interrupt() {
+                fired = Some(sys.scheduler.elapsed());
+                break;
+            }
+        }
+
+        fired.expect("sample counter interrupt was never raised")
+    }
+
+    #[test]
+    fn interrupt_at_sample() {
+        const SAMPLE: u32 = 1000;
+        const STEP: u64 = 97;
+
+        let mut sys = crate::test::system();
+        let start = sys.scheduler.elapsed();
+        sys.audio.interrupt_sample = SAMPLE;
+        ai::write_control(
+            &mut sys,
+            Control::default()
+                .with_playing(true)
+                .with_interrupt_valid(true)
+                .with_aux_sample_rate(SampleRate::KHz48),
+        );
+
+        let cycles_per_sample = SampleRate::KHz48.cycles_per_frame();
+        let expected = start + SAMPLE as u64 * cycles_per_sample;
+        let fired = run_until_interrupt(&mut sys, STEP);
+        assert!(fired >= expected && fired - expected < cycles_per_sample.max(STEP));
+        assert_eq!(ai::sample_counter(&sys), SAMPLE);
+    }
+
+    #[test]
+    fn interrupt_rescheduled_on_rewrite() {
+        let mut sys = crate::test::system();
+        let start = sys.scheduler.elapsed();
+        sys.audio.interrupt_sample = 5000;
+        ai::write_control(
+            &mut sys,
+            Control::default()
+                .with_playing(true)
+                .with_interrupt_valid(true)
+                .with_aux_sample_rate(SampleRate::KHz32),
+        );
+
+        // moving the interrupt earlier mid-stream makes it fire at the new sample
+        let cycles_per_sample = SampleRate::KHz32.cycles_per_frame();
+        sys.scheduler.advance(100 * cycles_per_sample);
+        sys.process_events();
+        assert_eq!(ai::sample_counter(&sys), 100);
+
+        sys.audio.interrupt_sample = 200;
+        ai::schedule_interrupt(&mut sys);
+
+        let fired = run_until_interrupt(&mut sys, cycles_per_sample);
+        assert_eq!(fired, start + 200 * cycles_per_sample);
+
+        // stopping freezes the counter, and resetting it starts over
+        ai::write_control(&mut sys, sys.audio.control.with_playing(false));
+        sys.scheduler.advance(1000 * cycles_per_sample);
+        assert_eq!(ai::sample_counter(&sys), 200);
+
+        ai::write_control(
+            &mut sys,
+            sys.audio
+                .control
+                .with_playing(true)
+                .with_sample_counter_reset(true),
+        );
+        assert_eq!(ai::sample_counter(&sys), 0);
+    }
 }
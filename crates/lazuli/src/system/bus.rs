@@ -1,3 +1,5 @@
+mod block;
+pub mod device;
 mod mmio;
 
 use std::ops::Range;
@@ -7,11 +9,32 @@ use gekko::Address;
 use zerocopy::IntoBytes;
 
 use crate::Primitive;
+use crate::system::gx::efb::{EFB_BASE, EFB_LEN};
 use crate::system::mem::{IPL_LEN, L2C_LEN, RAM_LEN};
-use crate::system::{System, ai, di, dspi, exi, gx, pi, si, vi};
+use crate::system::scheduler::{INTERRUPT_PRIORITY, SchedulerEventKind};
+use crate::system::{System, ai, di, dspi, exi, gx, pi, vi, wgp};
 
 #[rustfmt::skip]
 pub use mmio::Mmio;
+pub use block::MemFault;
+pub use device::{Device, Devices};
+
+/// Resolves an access of type `P` to the given offset into the MMIO region. Returns the register
+/// being accessed, the offset into it and the range of its native endian bytes covered by the
+/// access.
+pub fn resolve<P: Primitive>(offset: u16) -> Option<(Mmio, usize, Range<usize>)> {
+    let (reg, offset) = Mmio::find(offset)?;
+    let range = if cfg!(target_endian = "big") {
+        offset..offset + size_of::<P>()
+    } else {
+        let size = reg.size() as usize;
+        let end = size.saturating_sub(offset);
+        let start = end.saturating_sub(size_of::<P>());
+        start..end
+    };
+
+    Some((reg, offset, range))
+}
 
 fn range_overlap(a: Range<usize>, b: Range<usize>) -> bool {
     (a.start < b.end) && (b.start < a.end)
@@ -84,19 +107,15 @@ impl System {
     }
 
     fn read_mmio<P: Primitive>(&mut self, offset: u16) -> P {
-        let Some((reg, offset)) = Mmio::find(offset) else {
+        if let Some(device) = self.devices.find(offset) {
+            return device.read(self, offset);
+        }
+
+        let Some((reg, offset, mmio_range)) = self::resolve::<P>(offset) else {
             tracing::error!(pc = ?self.cpu.pc, "reading from unknown mmio register ({offset:04X})");
             return P::default();
         };
 
-        // convert the range to native endian
-        let mmio_range = if cfg!(target_endian = "big") {
-            offset..offset + size_of::<P>()
-        } else {
-            let size = reg.size();
-            (size as usize - offset - size_of::<P>())..(size as usize - offset)
-        };
-
         // read from native endian bytes
         macro_rules! ne {
             ($bytes:expr) => {
@@ -125,6 +144,10 @@ impl System {
             Mmio::CpFifoWritePtrHigh => ne!(self.gpu.cmd.fifo.write_ptr.as_bytes()[2..4]),
             Mmio::CpFifoReadPtrLow => ne!(self.gpu.cmd.fifo.read_ptr.as_bytes()[0..2]),
             Mmio::CpFifoReadPtrHigh => ne!(self.gpu.cmd.fifo.read_ptr.as_bytes()[2..4]),
+            Mmio::CpGp0MetricLow => ne!(self.gpu.perf.gp0.value.as_bytes()[0..2]),
+            Mmio::CpGp0MetricHigh => ne!(self.gpu.perf.gp0.value.as_bytes()[2..4]),
+            Mmio::CpGp1MetricLow => ne!(self.gpu.perf.gp1.value.as_bytes()[0..2]),
+            Mmio::CpGp1MetricHigh => ne!(self.gpu.perf.gp1.value.as_bytes()[2..4]),
 
             // === Pixel Engine ===
             Mmio::PixelInterruptStatus => {
@@ -133,6 +156,10 @@ impl System {
                 ne!(to_read.as_bytes())
             }
             Mmio::PixelToken => ne!((self.gpu.pix.token as u16).as_bytes()),
+            Mmio::PixelBBoxLeft => ne!(self.gpu.pix.bbox.left.as_bytes()),
+            Mmio::PixelBBoxRight => ne!(self.gpu.pix.bbox.right.as_bytes()),
+            Mmio::PixelBBoxTop => ne!(self.gpu.pix.bbox.top.as_bytes()),
+            Mmio::PixelBBoxBottom => ne!(self.gpu.pix.bbox.bottom.as_bytes()),
 
             // === Video Interface ===
             Mmio::VideoVerticalTiming => ne!(self.video.vertical_timing.as_bytes()),
@@ -146,8 +173,14 @@ impl System {
             Mmio::VideoTopBaseRight => ne!(self.video.top_base_right.as_bytes()),
             Mmio::VideoBottomBaseLeft => ne!(self.video.bottom_base_left.as_bytes()),
             Mmio::VideoBottomBaseRight => ne!(self.video.bottom_base_right.as_bytes()),
-            Mmio::VideoVerticalCount => ne!(self.video.vertical_count.as_bytes()),
-            Mmio::VideoHorizontalCount => ne!(self.video.horizontal_count.as_bytes()),
+            Mmio::VideoVerticalCount => {
+                let count = vi::beam_position(self).vertical;
+                ne!(count.as_bytes())
+            }
+            Mmio::VideoHorizontalCount => {
+                let count = vi::beam_position(self).horizontal;
+                ne!(count.as_bytes())
+            }
 
             // Interrupts
             Mmio::VideoDisplayInterrupt0 => ne!(self.video.interrupts[0].as_bytes()),
@@ -172,7 +205,8 @@ impl System {
             // === Processor Interface ===
             // Interrupts
             Mmio::ProcessorInterruptCause => {
-                ne!((pi::get_raised_interrupts(self).to_bits().value() as u32).as_bytes())
+                let raised = pi::get_raised_interrupts(self).to_bits().value() as u32;
+                ne!((raised | pi::RESET_SWITCH_RELEASED).as_bytes())
             }
             Mmio::ProcessorInterruptMask => ne!(self.processor.mask.as_bytes()),
 
@@ -181,6 +215,9 @@ impl System {
             Mmio::ProcessorFifoEnd => ne!(self.processor.fifo_end.as_bytes()),
             Mmio::ProcessorFifoCurrent => ne!(self.processor.fifo_current.as_bytes()),
 
+            // Reset
+            Mmio::ProcessorDvdReset => ne!(self.processor.reset_code.as_bytes()),
+
             // === DSP Interface ===
             Mmio::DspSendMailbox => ne!(self.dsp.cpu_mailbox.as_bytes()),
             Mmio::DspRecvMailbox => {
@@ -213,35 +250,6 @@ impl System {
                 ne!(remaining.as_bytes())
             }
 
-            // === Disk Interface ===
-            Mmio::DiskStatus => ne!(self.disk.status.as_bytes()),
-            Mmio::DiskCover => ne!(self.disk.cover.as_bytes()),
-            Mmio::DiskDmaBase => ne!(self.disk.dma_base.as_bytes()),
-            Mmio::DiskDmaLength => ne!(self.disk.dma_length.as_bytes()),
-            Mmio::DiskControl => ne!(self.disk.control.as_bytes()),
-            Mmio::DiskImmediateData => ne!(self.disk.immediate.as_bytes()),
-            Mmio::DiskConfiguration => ne!(self.disk.config.as_bytes()),
-
-            // === Serial Interface ===
-            Mmio::SerialOutputBuf0 => ne!(self.serial.channel_output[0].data.as_bytes()),
-            Mmio::SerialInput0High => ne!(self.serial.channel_input[0].high.as_bytes()),
-            Mmio::SerialInput0Low => ne!(self.serial.channel_input[0].low.as_bytes()),
-            Mmio::SerialOutputBuf1 => ne!(self.serial.channel_output[1].data.as_bytes()),
-            Mmio::SerialInput1High => ne!(self.serial.channel_input[1].high.as_bytes()),
-            Mmio::SerialInput1Low => ne!(self.serial.channel_input[1].low.as_bytes()),
-            Mmio::SerialOutputBuf2 => ne!(self.serial.channel_output[2].data.as_bytes()),
-            Mmio::SerialInput2High => ne!(self.serial.channel_input[2].high.as_bytes()),
-            Mmio::SerialInput2Low => ne!(self.serial.channel_input[2].low.as_bytes()),
-            Mmio::SerialOutputBuf3 => ne!(self.serial.channel_output[3].data.as_bytes()),
-            Mmio::SerialInput3High => ne!(self.serial.channel_input[3].high.as_bytes()),
-            Mmio::SerialInput3Low => ne!(self.serial.channel_input[3].low.as_bytes()),
-            Mmio::SerialPoll => ne!(self.serial.poll.as_bytes()),
-            Mmio::SerialCommControl => ne!(self.serial.comm_control.as_bytes()),
-            Mmio::SerialStatus => ne!(self.serial.status.as_bytes()),
-            Mmio::SerialBuffer => {
-                P::read_be_bytes(&self.serial.buffer[offset..offset + size_of::<P>()])
-            }
-
             // === External Interface ===
             Mmio::ExiChannel0Param => ne!(self.external.channel0.parameter.as_bytes()),
             Mmio::ExiChannel0DmaBase => ne!(self.external.channel0.dma_base.as_bytes()),
@@ -263,7 +271,11 @@ impl System {
 
             // === Audio Interface ===
             Mmio::AudioControl => ne!(self.audio.control.as_bytes()),
-            Mmio::AudioSampleCounter => ne!(self.audio.sample_counter.as_bytes()),
+            Mmio::AudioVolume => ne!(self.audio.volume.as_bytes()),
+            Mmio::AudioSampleCounter => {
+                let count = ai::sample_counter(self);
+                ne!(count.as_bytes())
+            }
             Mmio::AudioInterruptSample => ne!(self.audio.interrupt_sample.as_bytes()),
 
             _ => {
@@ -292,7 +304,15 @@ impl System {
             offset, addr;
             0x0C00_0000, 0xFFFF => self.read_mmio(addr.value() as u16),
             0x0000_0000, RAM_LEN => P::read_be_bytes(&self.mem.ram()[offset..]),
-            0xE000_0000, L2C_LEN => P::read_be_bytes(&self.mem.l2c()[offset..]),
+            EFB_BASE, EFB_LEN => P::read_be_bytes(&gx::efb::peek(self, offset as u32).to_be_bytes()),
+            0xE000_0000, L2C_LEN => {
+                if !self.mem.locked_cache() {
+                    tracing::warn!(pc = ?self.cpu.pc, "reading from {addr} (locked cache disabled)");
+                    return P::default();
+                }
+
+                P::read_be_bytes(&self.mem.l2c()[offset..])
+            },
             0xFFF0_0000, IPL_LEN / 2 => P::read_be_bytes(&self.mem.ipl()[offset..]),
             @default => {
                 std::hint::cold_path();
@@ -335,19 +355,14 @@ impl System {
     }
 
     fn write_mmio<P: Primitive>(&mut self, offset: u16, value: P) {
-        let Some((reg, offset)) = Mmio::find(offset) else {
-            tracing::error!("writing 0x{value:08X} to unknown mmio register ({offset:04X})");
+        if let Some(device) = self.devices.find(offset) {
+            device.write(self, offset, value);
             return;
-        };
+        }
 
-        // convert the range to native endian
-        let mmio_range = if cfg!(target_endian = "big") {
-            offset..offset + size_of::<P>()
-        } else {
-            let size = reg.size() as usize;
-            let end = size.saturating_sub(offset);
-            let start = end.saturating_sub(size_of::<P>());
-            start..end
+        let Some((reg, offset, mmio_range)) = self::resolve::<P>(offset) else {
+            tracing::error!("writing 0x{value:08X} to unknown mmio register ({offset:04X})");
+            return;
         };
 
         if !matches!(reg, Mmio::FakeStdout | Mmio::ProcessorFifo) {
@@ -380,6 +395,9 @@ impl System {
                 let mut written = 0;
                 ne!(written.as_mut_bytes());
                 self.gpu.cmd.write_clear(written);
+                if written.bit(2) {
+                    self.gpu.perf.clear();
+                }
             }
             Mmio::CpFifoStartLow => {
                 ne!(self.gpu.cmd.fifo.start.as_mut_bytes()[0..2]);
@@ -440,19 +458,25 @@ impl System {
             }
 
             // === Video Interface ===
-            Mmio::VideoVerticalTiming => ne!(self.video.vertical_timing.as_mut_bytes()),
+            Mmio::VideoVerticalTiming => {
+                ne!(self.video.vertical_timing.as_mut_bytes());
+                vi::update(self);
+            }
             Mmio::VideoDisplayConfig => {
                 ne!(self.video.display_config.as_mut_bytes());
                 vi::update(self);
             }
             Mmio::VideoHorizontalTiming => {
-                ne!(self.video.horizontal_timing.as_mut_bytes())
+                ne!(self.video.horizontal_timing.as_mut_bytes());
+                vi::update(self);
             }
             Mmio::VideoOddVerticalTiming => {
-                ne!(self.video.top_vertical_timing.as_mut_bytes())
+                ne!(self.video.top_vertical_timing.as_mut_bytes());
+                vi::update(self);
             }
             Mmio::VideoEvenVerticalTiming => {
-                ne!(self.video.bottom_vertical_timing.as_mut_bytes())
+                ne!(self.video.bottom_vertical_timing.as_mut_bytes());
+                vi::update(self);
             }
             Mmio::VideoTopBaseLeft => ne!(self.video.top_base_left.as_mut_bytes()),
             Mmio::VideoTopBaseRight => ne!(self.video.top_base_right.as_mut_bytes()),
@@ -464,21 +488,25 @@ impl System {
                 let mut written = self.video.interrupts[0];
                 ne!(written.as_mut_bytes());
                 self.video.write_interrupt::<0>(written);
+                vi::schedule_interrupt::<0>(self);
             }
             Mmio::VideoDisplayInterrupt1 => {
                 let mut written = self.video.interrupts[1];
                 ne!(written.as_mut_bytes());
                 self.video.write_interrupt::<1>(written);
+                vi::schedule_interrupt::<1>(self);
             }
             Mmio::VideoDisplayInterrupt2 => {
                 let mut written = self.video.interrupts[2];
                 ne!(written.as_mut_bytes());
                 self.video.write_interrupt::<2>(written);
+                vi::schedule_interrupt::<2>(self);
             }
             Mmio::VideoDisplayInterrupt3 => {
                 let mut written = self.video.interrupts[3];
                 ne!(written.as_mut_bytes());
                 self.video.write_interrupt::<3>(written);
+                vi::schedule_interrupt::<3>(self);
             }
 
             Mmio::VideoExternalFramebufferWidth => {
@@ -497,13 +525,28 @@ impl System {
             | Mmio::VideoFilterCoeff5
             | Mmio::VideoFilterCoeff6 => (), // NOTE: stubbed
 
-            Mmio::VideoClock => ne!(self.video.clock.as_mut_bytes()),
+            Mmio::VideoClock => {
+                ne!(self.video.clock.as_mut_bytes());
+                vi::update(self);
+            }
 
             // === Processor Interface ===
             // Interrupts
+            Mmio::ProcessorInterruptCause => {
+                let mut written = 0u32;
+                ne!(written.as_mut_bytes());
+
+                // only the reset switch interrupt is acknowledged here, others are cleared at
+                // their source
+                let acknowledged = pi::InterruptMask::from_bits(written).sources();
+                if acknowledged.reset() {
+                    self.processor.reset_switch = false;
+                }
+            }
             Mmio::ProcessorInterruptMask => {
                 ne!(self.processor.mask.as_mut_bytes());
-                self.scheduler.schedule_now(pi::check_interrupts);
+                self.scheduler
+                    .schedule_now(INTERRUPT_PRIORITY, SchedulerEventKind::CheckInterrupts);
             }
 
             // FIFO
@@ -514,9 +557,8 @@ impl System {
             }
             Mmio::ProcessorFifoCurrent => ne!(self.processor.fifo_current.as_mut_bytes()),
             Mmio::ProcessorDvdReset => {
-                let mut value = 0u32;
-                ne!(value.as_mut_bytes());
-                di::reset(self, value);
+                ne!(self.processor.reset_code.as_mut_bytes());
+                di::reset(self, self.processor.reset_code);
             }
 
             // === DSP Interface ===
@@ -544,7 +586,7 @@ impl System {
 
                 if range_overlap(mmio_range, 0..2) {
                     self.dsp.control.set_aram_dma_ongoing(true);
-                    self.scheduler.schedule(10000, dspi::aram_dma);
+                    self.scheduler.schedule(10000, SchedulerEventKind::AramDma);
                 }
             }
             Mmio::AudioDmaBase => ne!(self.audio.dma_base.as_mut_bytes()),
@@ -558,71 +600,6 @@ impl System {
                 }
             }
 
-            // === Disk Interface ===
-            Mmio::DiskStatus => {
-                let mut written = di::Status::from_bits(0);
-                ne!(written.as_mut_bytes());
-                self.disk.write_status(written);
-                tracing::debug!(diskstatus = ?self.disk.status);
-                self.scheduler.schedule_now(pi::check_interrupts);
-            }
-            Mmio::DiskCover => {
-                let mut written = di::Cover::from_bits(0);
-                ne!(written.as_mut_bytes());
-                self.disk.write_cover(written);
-                self.disk.cover.set_open(false);
-                tracing::debug!(diskcover = ?self.disk.cover);
-                self.scheduler.schedule_now(pi::check_interrupts);
-            }
-            Mmio::DiskCommand0 => ne!(self.disk.command_buffer[0].as_mut_bytes()),
-            Mmio::DiskCommand1 => ne!(self.disk.command_buffer[1].as_mut_bytes()),
-            Mmio::DiskCommand2 => ne!(self.disk.command_buffer[2].as_mut_bytes()),
-            Mmio::DiskDmaBase => ne!(self.disk.dma_base.as_mut_bytes()),
-            Mmio::DiskDmaLength => ne!(self.disk.dma_length.as_mut_bytes()),
-            Mmio::DiskControl => {
-                let mut written = di::Control::from_bits(0);
-                ne!(written.as_mut_bytes());
-                di::write_control(self, written);
-            }
-            Mmio::DiskConfiguration => {
-                ne!(self.disk.config.as_mut_bytes());
-            }
-
-            // === Serial Interface ===
-            Mmio::SerialOutputBuf0 => {
-                ne!(self.serial.channel_output[0].data.as_mut_bytes());
-                self.serial.channel_output[0].dirty = true;
-            }
-            Mmio::SerialOutputBuf1 => {
-                ne!(self.serial.channel_output[1].data.as_mut_bytes());
-                self.serial.channel_output[1].dirty = true;
-            }
-            Mmio::SerialOutputBuf2 => {
-                ne!(self.serial.channel_output[2].data.as_mut_bytes());
-                self.serial.channel_output[2].dirty = true;
-            }
-            Mmio::SerialOutputBuf3 => {
-                ne!(self.serial.channel_output[3].data.as_mut_bytes());
-                self.serial.channel_output[3].dirty = true;
-            }
-            Mmio::SerialPoll => {
-                ne!(self.serial.poll.as_mut_bytes());
-                tracing::debug!("SI poll: {:?}", self.serial.poll);
-            }
-            Mmio::SerialCommControl => {
-                let mut written = self.serial.comm_control;
-                ne!(written.as_mut_bytes());
-                si::write_comm_control(self, written);
-            }
-            Mmio::SerialStatus => {
-                let mut written = self.serial.status;
-                ne!(written.as_mut_bytes());
-                si::write_status(self, written);
-            }
-            Mmio::SerialBuffer => {
-                value.write_be_bytes(&mut self.serial.buffer[offset..offset + size_of::<P>()])
-            }
-
             // === External Interface ===
             Mmio::ExiChannel0Param => {
                 let mut written = exi::Parameter::from_bits(0);
@@ -668,18 +645,15 @@ impl System {
 
             // === Audio Interface ===
             Mmio::AudioControl => {
-                let already_playing = self.audio.control.playing();
                 let mut written = self.audio.control;
                 ne!(written.as_mut_bytes());
-                self.audio.write_control(written);
-
-                if !already_playing && self.audio.control.playing() {
-                    ai::start_streaming(self);
-                } else if !self.audio.control.playing() {
-                    ai::stop_streaming(self);
-                }
+                ai::write_control(self, written);
+            }
+            Mmio::AudioVolume => ne!(self.audio.volume.as_mut_bytes()),
+            Mmio::AudioInterruptSample => {
+                ne!(self.audio.interrupt_sample.as_mut_bytes());
+                ai::schedule_interrupt(self);
             }
-            Mmio::AudioInterruptSample => ne!(self.audio.interrupt_sample.as_mut_bytes()),
 
             // === Fake STDOUT ===
             Mmio::FakeStdout => {
@@ -689,7 +663,7 @@ impl System {
             }
 
             // === PI FIFO ===
-            Mmio::ProcessorFifo => pi::fifo_push(self, value),
+            Mmio::ProcessorFifo => wgp::push(self, value),
             _ => tracing::warn!("unimplemented write to known mmio register ({reg:?})"),
         }
     }
@@ -701,7 +675,19 @@ impl System {
             offset, addr;
             0x0C00_0000, 0xFFFF => self.write_mmio(addr.value() as u16, value),
             0x0000_0000, RAM_LEN => value.write_be_bytes(&mut self.mem.ram_mut()[offset..]),
-            0xE000_0000, L2C_LEN => value.write_be_bytes(&mut self.mem.l2c_mut()[offset..]),
+            EFB_BASE, EFB_LEN => {
+                let mut bytes = [0; 4];
+                value.write_be_bytes(&mut bytes);
+                gx::efb::poke(self, offset as u32, u32::from_be_bytes(bytes));
+            },
+            0xE000_0000, L2C_LEN => {
+                if !self.mem.locked_cache() {
+                    tracing::warn!(pc = ?self.cpu.pc, "writing 0x{value:08X} to {addr} (locked cache disabled)");
+                    return;
+                }
+
+                value.write_be_bytes(&mut self.mem.l2c_mut()[offset..])
+            },
             0xFFF0_0000, IPL_LEN / 2 => tracing::warn!("bus write to IPL"),
             @default => {
                 std::hint::cold_path();
@@ -710,11 +696,59 @@ impl System {
         }
     }
 
+    /// Checks the value watches after a write of `len` bytes to the given logical address.
+    #[cold]
+    fn check_watches(&mut self, addr: Address, len: usize) {
+        let Self {
+            cpu, mem, watches, ..
+        } = self;
+
+        let translate = |watched| {
+            if cpu.supervisor.config.msr.data_addr_translation() {
+                mem.translate_data_addr(watched)
+            } else {
+                Some(watched)
+            }
+        };
+
+        // watches only look at RAM, since reading MMIO registers could have side effects
+        watches.check(addr, len as u32, cpu.pc, |watched| {
+            let offset = translate(watched)?.value() as usize;
+            (offset + 4 <= RAM_LEN).then(|| u32::read_be_bytes(&mem.ram()[offset..]))
+        });
+    }
+
+    /// Checks the value watches after a write of `len` bytes to the given physical address, e.g.
+    /// by a block transfer.
+    #[cold]
+    fn check_watches_phys(&mut self, addr: Address, len: usize) {
+        let Self {
+            cpu, mem, watches, ..
+        } = self;
+
+        let translate = |watched| {
+            if cpu.supervisor.config.msr.data_addr_translation() {
+                mem.translate_data_addr(watched)
+            } else {
+                Some(watched)
+            }
+        };
+
+        watches.check_physical(addr, len as u32, cpu.pc, translate, |watched| {
+            let offset = translate(watched)?.value() as usize;
+            (offset + 4 <= RAM_LEN).then(|| u32::read_be_bytes(&mem.ram()[offset..]))
+        });
+    }
+
     /// Writes a primitive to the given logical address.
     #[inline(always)]
     pub fn write_slow<P: Primitive>(&mut self, addr: Address, value: P) -> bool {
-        if let Some(addr) = self.translate_data_addr(addr) {
-            self.write_phys_slow(addr, value);
+        if let Some(physical) = self.translate_data_addr(addr) {
+            self.write_phys_slow(physical, value);
+            if !self.watches.is_empty() {
+                self.check_watches(addr, size_of::<P>());
+            }
+
             true
         } else {
             false
@@ -736,6 +770,11 @@ impl System {
             let offset = addr.value().bits(0, 17) as usize;
             let ptr = unsafe { base.add(offset) };
             unsafe { ptr.cast::<P>().write(value.to_be()) }
+
+            if !self.watches.is_empty() {
+                self.check_watches(addr, size_of::<P>());
+            }
+
             true
         } else {
             false
@@ -0,0 +1,270 @@
+//! Block transfers, for DMA-style users which move a lot of data at once.
+//!
+//! Contiguous memory backed ranges are copied directly, while MMIO is accessed word by word
+//! through the slow path.
+use easyerr::Error;
+use gekko::Address;
+
+use crate::system::System;
+use crate::system::mem::{
+    IPL_END, IPL_START, L2C_END, L2C_LEN, L2C_START, RAM_END, RAM_LEN, RAM_START,
+};
+
+/// Granularity of address translation.
+const TRANSLATION_PAGE_LEN: u32 = 1 << 17;
+
+const MMIO_START: u32 = 0x0C00_0000;
+const MMIO_END: u32 = 0x0C00_FFFE;
+
+#[derive(Debug, Error)]
+pub enum MemFault {
+    #[error("logical address {addr} is not mapped")]
+    Translation { addr: Address },
+    #[error("physical address {addr} is not mapped")]
+    Unmapped { addr: Address },
+    #[error("physical address {addr} is read-only")]
+    ReadOnly { addr: Address },
+}
+
+impl MemFault {
+    /// The address which caused the fault.
+    pub fn addr(&self) -> Address {
+        match self {
+            Self::Translation { addr } | Self::Unmapped { addr } | Self::ReadOnly { addr } => *addr,
+        }
+    }
+}
+
+/// Returns the address `offset` bytes after `base`, failing if it is past the end of the address
+/// space.
+fn offset_addr(base: Address, offset: usize) -> Result<Address, MemFault> {
+    base.checked_add(offset as u32).ok_or(MemFault::Unmapped {
+        addr: Address(u32::MAX),
+    })
+}
+
+/// How many bytes from `addr` until the end of its translation page.
+fn page_remaining(addr: Address) -> usize {
+    (TRANSLATION_PAGE_LEN - addr.value() % TRANSLATION_PAGE_LEN) as usize
+}
+
+impl System {
+    /// Reads a block of data starting at the given physical address.
+    ///
+    /// On a fault, the data before the faulting address has already been read.
+    pub fn read_phys_block(&mut self, addr: Address, dst: &mut [u8]) -> Result<(), MemFault> {
+        let mut done = 0;
+        while done < dst.len() {
+            let current = self::offset_addr(addr, done)?;
+            let remaining = &mut dst[done..];
+
+            let value = current.value();
+            done += match value {
+                RAM_START..=RAM_END => {
+                    let offset = (value - RAM_START) as usize;
+                    let len = remaining.len().min(RAM_LEN - offset);
+                    remaining[..len].copy_from_slice(&self.mem.ram()[offset..][..len]);
+                    len
+                }
+                L2C_START..=L2C_END if self.mem.locked_cache() => {
+                    let offset = (value - L2C_START) as usize;
+                    let len = remaining.len().min(L2C_LEN - offset);
+                    remaining[..len].copy_from_slice(&self.mem.l2c()[offset..][..len]);
+                    len
+                }
+                IPL_START..=IPL_END => {
+                    let offset = (value - IPL_START) as usize;
+                    let len = remaining.len().min((IPL_END - value) as usize + 1);
+                    remaining[..len].copy_from_slice(&self.mem.ipl()[offset..][..len]);
+                    len
+                }
+                MMIO_START..=MMIO_END => {
+                    if value.is_multiple_of(4) && remaining.len() >= 4 {
+                        let word = self.read_phys_slow::<u32>(current);
+                        remaining[..4].copy_from_slice(&word.to_be_bytes());
+                        4
+                    } else {
+                        remaining[0] = self.read_phys_slow::<u8>(current);
+                        1
+                    }
+                }
+                _ => return Err(MemFault::Unmapped { addr: current }),
+            };
+        }
+
+        Ok(())
+    }
+
+    /// Writes a block of data starting at the given physical address. Value watches over the
+    /// written RAM are checked.
+    ///
+    /// On a fault, the data before the faulting address has already been written.
+    pub fn write_phys_block(&mut self, addr: Address, src: &[u8]) -> Result<(), MemFault> {
+        let mut done = 0;
+        while done < src.len() {
+            let current = self::offset_addr(addr, done)?;
+            let remaining = &src[done..];
+
+            let value = current.value();
+            done += match value {
+                RAM_START..=RAM_END => {
+                    let offset = (value - RAM_START) as usize;
+                    let len = remaining.len().min(RAM_LEN - offset);
+                    self.mem.ram_mut()[offset..][..len].copy_from_slice(&remaining[..len]);
+                    if !self.watches.is_empty() {
+                        self.check_watches_phys(current, len);
+                    }
+
+                    len
+                }
+                L2C_START..=L2C_END if self.mem.locked_cache() => {
+                    let offset = (value - L2C_START) as usize;
+                    let len = remaining.len().min(L2C_LEN - offset);
+                    self.mem.l2c_mut()[offset..][..len].copy_from_slice(&remaining[..len]);
+                    len
+                }
+                IPL_START..=IPL_END => return Err(MemFault::ReadOnly { addr: current }),
+                MMIO_START..=MMIO_END => {
+                    if value.is_multiple_of(4) && remaining.len() >= 4 {
+                        let word = u32::from_be_bytes(remaining[..4].try_into().unwrap());
+                        self.write_phys_slow(current, word);
+                        4
+                    } else {
+                        self.write_phys_slow(current, remaining[0]);
+                        1
+                    }
+                }
+                _ => return Err(MemFault::Unmapped { addr: current }),
+            };
+        }
+
+        Ok(())
+    }
+
+    /// Reads a block of data starting at the given logical address. Translation happens once per
+    /// translation page.
+    ///
+    /// On a fault, the data before the faulting address has already been read.
+    pub fn read_block(&mut self, addr: Address, dst: &mut [u8]) -> Result<(), MemFault> {
+        let mut done = 0;
+        while done < dst.len() {
+            let current = self::offset_addr(addr, done)?;
+            let len = self::page_remaining(current).min(dst.len() - done);
+            let phys = self
+                .translate_data_addr(current)
+                .ok_or(MemFault::Translation { addr: current })?;
+
+            self.read_phys_block(phys, &mut dst[done..][..len])?;
+            done += len;
+        }
+
+        Ok(())
+    }
+
+    /// Writes a block of data starting at the given logical address. Translation happens once per
+    /// translation page.
+    ///
+    /// On a fault, the data before the faulting address has already been written.
+    pub fn write_block(&mut self, addr: Address, src: &[u8]) -> Result<(), MemFault> {
+        let mut done = 0;
+        while done < src.len() {
+            let current = self::offset_addr(addr, done)?;
+            let len = self::page_remaining(current).min(src.len() - done);
+            let phys = self
+                .translate_data_addr(current)
+                .ok_or(MemFault::Translation { addr: current })?;
+
+            self.write_phys_block(phys, &src[done..][..len])?;
+            done += len;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use gekko::Address;
+
+    use super::MemFault;
+    use crate::system::mem::RAM_LEN;
+    use crate::test::system;
+
+    #[test]
+    fn roundtrip() {
+        let mut sys = system();
+        let data: Vec<u8> = (0..4096).map(|i| i as u8).collect();
+
+        sys.write_phys_block(Address(0x1234), &data).unwrap();
+        assert_eq!(&sys.mem.ram()[0x1234..][..data.len()], &data);
+
+        let mut read = vec![0; data.len()];
+        sys.read_phys_block(Address(0x1234), &mut read).unwrap();
+        assert_eq!(read, data);
+    }
+
+    #[test]
+    fn straddle_unmapped() {
+        let mut sys = system();
+        let end = Address(RAM_LEN as u32);
+        sys.mem.ram_mut()[RAM_LEN - 8..].fill(0xAA);
+
+        let mut read = [0; 16];
+        let fault = sys.read_phys_block(end - 8u32, &mut read).unwrap_err();
+        assert!(matches!(fault, MemFault::Unmapped { .. }));
+        assert_eq!(fault.addr(), end);
+        assert_eq!(&read[..8], &[0xAA; 8]);
+        assert_eq!(&read[8..], &[0; 8]);
+
+        let fault = sys.write_phys_block(end - 4u32, &[0x55; 8]).unwrap_err();
+        assert_eq!(fault.addr(), end);
+        assert_eq!(
+            &sys.mem.ram()[RAM_LEN - 8..],
+            &[0xAA, 0xAA, 0xAA, 0xAA, 0x55, 0x55, 0x55, 0x55]
+        );
+    }
+
+    #[test]
+    fn untranslated() {
+        let mut sys = system();
+        sys.cpu
+            .supervisor
+            .config
+            .msr
+            .set_data_addr_translation(true);
+        sys.cpu.supervisor.memory.setup_default_bats();
+        sys.mem.build_bat_lut(&sys.cpu.supervisor.memory);
+
+        sys.write_block(Address(0x8000_0100), &[1, 2, 3, 4])
+            .unwrap();
+        assert_eq!(&sys.mem.ram()[0x100..][..4], &[1, 2, 3, 4]);
+
+        let fault = sys.write_block(Address(0x4000_0000), &[0; 4]).unwrap_err();
+        assert!(matches!(fault, MemFault::Translation { .. }));
+        assert_eq!(fault.addr(), Address(0x4000_0000));
+    }
+
+    #[test]
+    fn watched() {
+        let mut sys = system();
+        sys.cpu
+            .supervisor
+            .config
+            .msr
+            .set_data_addr_translation(true);
+        sys.cpu.supervisor.memory.setup_default_bats();
+        sys.mem.build_bat_lut(&sys.cpu.supervisor.memory);
+
+        // watches are on logical addresses, while block writes are physical
+        let addr = Address(0x8000_0104);
+        sys.watches.add(addr, !0, 0);
+
+        sys.write_phys_block(Address(0x100), &[0xAA; 4]).unwrap();
+        assert!(!sys.watches.has_hit());
+
+        sys.write_phys_block(Address(0x100), &[0x55; 8]).unwrap();
+        let hit = sys.watches.take_hit().unwrap();
+        assert_eq!(hit.addr, addr);
+        assert_eq!(hit.new, 0x5555_5555);
+    }
+}
@@ -0,0 +1,243 @@
+//! Registry of memory mapped devices.
+//!
+//! A device claims a range of the MMIO region (i.e. offsets into `0x0C00_0000`) and receives typed
+//! reads and writes to it from the slow memory path. Offsets not claimed by any device are handled
+//! by the bus itself.
+use std::ops::Range;
+
+use zerocopy::IntoBytes;
+
+use crate::Primitive;
+use crate::system::System;
+
+/// Alignment of the ranges claimed by devices.
+pub const DEVICE_ALIGN: u16 = 0x40;
+
+const PAGES: usize = 0x1_0000 / DEVICE_ALIGN as usize;
+
+/// A device mapped into the MMIO region.
+pub trait Device {
+    /// Name of the device, used in logs.
+    const NAME: &'static str;
+    /// Offsets into the MMIO region claimed by this device. Must be aligned to [`DEVICE_ALIGN`].
+    const RANGE: Range<u16>;
+
+    /// Reads a primitive from the given offset into the MMIO region.
+    fn read<P: Primitive>(sys: &mut System, offset: u16) -> P;
+
+    /// Writes a primitive to the given offset into the MMIO region.
+    fn write<P: Primitive>(sys: &mut System, offset: u16, value: P);
+}
+
+/// The handlers of a registered device, one for each access size.
+#[derive(Clone, Copy)]
+pub struct Handlers {
+    name: &'static str,
+    start: u16,
+    end: u16,
+    read_u8: fn(&mut System, u16) -> u8,
+    read_u16: fn(&mut System, u16) -> u16,
+    read_u32: fn(&mut System, u16) -> u32,
+    read_u64: fn(&mut System, u16) -> u64,
+    write_u8: fn(&mut System, u16, u8),
+    write_u16: fn(&mut System, u16, u16),
+    write_u32: fn(&mut System, u16, u32),
+    write_u64: fn(&mut System, u16, u64),
+}
+
+impl Handlers {
+    fn of<D: Device>() -> Self {
+        Self {
+            name: D::NAME,
+            start: D::RANGE.start,
+            end: D::RANGE.end,
+            read_u8: D::read::<u8>,
+            read_u16: D::read::<u16>,
+            read_u32: D::read::<u32>,
+            read_u64: D::read::<u64>,
+            write_u8: D::write::<u8>,
+            write_u16: D::write::<u16>,
+            write_u32: D::write::<u32>,
+            write_u64: D::write::<u64>,
+        }
+    }
+
+    /// Name of the device.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Offsets into the MMIO region claimed by the device.
+    pub fn range(&self) -> Range<u16> {
+        self.start..self.end
+    }
+
+    /// Reads a primitive from the device.
+    #[inline]
+    pub fn read<P: Primitive>(&self, sys: &mut System, offset: u16) -> P {
+        match size_of::<P>() {
+            1 => P::read_ne_bytes(&(self.read_u8)(sys, offset).to_ne_bytes()),
+            2 => P::read_ne_bytes(&(self.read_u16)(sys, offset).to_ne_bytes()),
+            4 => P::read_ne_bytes(&(self.read_u32)(sys, offset).to_ne_bytes()),
+            8 => P::read_ne_bytes(&(self.read_u64)(sys, offset).to_ne_bytes()),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Writes a primitive to the device.
+    #[inline]
+    pub fn write<P: Primitive>(&self, sys: &mut System, offset: u16, value: P) {
+        let bytes = value.as_bytes();
+        match size_of::<P>() {
+            1 => (self.write_u8)(sys, offset, u8::read_ne_bytes(bytes)),
+            2 => (self.write_u16)(sys, offset, u16::read_ne_bytes(bytes)),
+            4 => (self.write_u32)(sys, offset, u32::read_ne_bytes(bytes)),
+            8 => (self.write_u64)(sys, offset, u64::read_ne_bytes(bytes)),
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Devices registered in the MMIO region.
+pub struct Devices {
+    handlers: Vec<Handlers>,
+    /// Index of the device claiming each [`DEVICE_ALIGN`] sized page of the region.
+    lut: Box<[Option<u8>; PAGES]>,
+}
+
+impl Default for Devices {
+    fn default() -> Self {
+        Self {
+            handlers: Vec::new(),
+            lut: Box::new([None; PAGES]),
+        }
+    }
+}
+
+impl Devices {
+    /// Registers a device.
+    ///
+    /// # Panics
+    /// Panics if the range of the device is empty, not aligned to [`DEVICE_ALIGN`] or overlaps
+    /// the range of an already registered device.
+    pub fn register<D: Device>(&mut self) {
+        let range = D::RANGE;
+        assert!(
+            !range.is_empty()
+                && range.start.is_multiple_of(DEVICE_ALIGN)
+                && range.end.is_multiple_of(DEVICE_ALIGN),
+            "device {} has an invalid range ({range:04X?})",
+            D::NAME
+        );
+
+        let index = u8::try_from(self.handlers.len()).expect("too many devices");
+        let pages = range.start as usize / DEVICE_ALIGN as usize
+            ..range.end as usize / DEVICE_ALIGN as usize;
+
+        for page in pages.clone() {
+            if let Some(other) = self.lut[page] {
+                panic!(
+                    "device {} overlaps device {} ({range:04X?})",
+                    D::NAME,
+                    self.handlers[other as usize].name
+                );
+            }
+        }
+
+        for page in pages {
+            self.lut[page] = Some(index);
+        }
+
+        tracing::debug!("registered device {} at {range:04X?}", D::NAME);
+        self.handlers.push(Handlers::of::<D>());
+    }
+
+    /// Returns the handlers of the device claiming the given offset into the MMIO region, if any.
+    #[inline]
+    pub fn find(&self, offset: u16) -> Option<Handlers> {
+        self.lut[offset as usize / DEVICE_ALIGN as usize].map(|i| self.handlers[i as usize])
+    }
+
+    /// Iterates over the registered devices.
+    pub fn iter(&self) -> impl Iterator<Item = &Handlers> {
+        self.handlers.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::ops::Range;
+
+    use gekko::Address;
+
+    use super::Device;
+    use crate::Primitive;
+    use crate::system::System;
+    use crate::test::system;
+
+    /// Device which mirrors a window of RAM.
+    struct Mirror;
+
+    impl Mirror {
+        const BASE: usize = 0x100;
+    }
+
+    impl Device for Mirror {
+        const NAME: &'static str = "Mirror";
+        const RANGE: Range<u16> = 0x7040..0x7080;
+
+        fn read<P: Primitive>(sys: &mut System, offset: u16) -> P {
+            let offset = (offset - Self::RANGE.start) as usize;
+            P::read_be_bytes(&sys.mem.ram()[Self::BASE + offset..])
+        }
+
+        fn write<P: Primitive>(sys: &mut System, offset: u16, value: P) {
+            let offset = (offset - Self::RANGE.start) as usize;
+            value.write_be_bytes(&mut sys.mem.ram_mut()[Self::BASE + offset..]);
+        }
+    }
+
+    /// Device which overlaps [`Mirror`].
+    struct Overlapping;
+
+    impl Device for Overlapping {
+        const NAME: &'static str = "Overlapping";
+        const RANGE: Range<u16> = 0x7000..0x7080;
+
+        fn read<P: Primitive>(_: &mut System, _: u16) -> P {
+            P::default()
+        }
+
+        fn write<P: Primitive>(_: &mut System, _: u16, _: P) {}
+    }
+
+    #[test]
+    fn routing() {
+        let mut sys = system();
+        sys.devices.register::<Mirror>();
+
+        sys.write_phys_slow::<u32>(Address(0x0C00_7044), 0x1234_5678);
+        assert_eq!(&sys.mem.ram()[0x104..0x108], &[0x12, 0x34, 0x56, 0x78]);
+
+        sys.write_phys_slow::<u8>(Address(0x0C00_707F), 0xAB);
+        assert_eq!(sys.mem.ram()[0x13F], 0xAB);
+
+        assert_eq!(sys.read_phys_slow::<u16>(Address(0x0C00_7046)), 0x5678);
+        assert_eq!(
+            sys.read_phys_slow::<u64>(Address(0x0C00_7040)),
+            0x0000_0000_1234_5678
+        );
+
+        // unclaimed offsets are left to the bus
+        sys.write_phys_slow::<u32>(Address(0x0C00_7080), 0xFFFF_FFFF);
+        assert_eq!(&sys.mem.ram()[0x140..0x144], &[0; 4]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn overlap() {
+        let mut sys = system();
+        sys.devices.register::<Mirror>();
+        sys.devices.register::<Overlapping>();
+    }
+}
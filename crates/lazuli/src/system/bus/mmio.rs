@@ -64,10 +64,18 @@ mmio! {
     0x003A, 2, CpFifoReadPtrHigh;
     0x003C, 2, CpFifoBreakpointLow;
     0x003E, 2, CpFifoBreakpointHigh;
+    0x0040, 2, CpGp0MetricLow;
+    0x0042, 2, CpGp0MetricHigh;
+    0x0044, 2, CpGp1MetricLow;
+    0x0046, 2, CpGp1MetricHigh;
 
     // === Pixel Engine ===
     0x100A, 2, PixelInterruptStatus;
     0x100E, 2, PixelToken;
+    0x1010, 2, PixelBBoxLeft;
+    0x1012, 2, PixelBBoxRight;
+    0x1014, 2, PixelBBoxTop;
+    0x1016, 2, PixelBBoxBottom;
 
     // === Video Interface ===
     0x2000, 2, VideoVerticalTiming;
@@ -1,12 +1,71 @@
 //! Disk interface (DI).
+use std::collections::VecDeque;
 use std::io::SeekFrom;
+use std::ops::Range;
+use std::time::Duration;
 
 use bitos::{BitUtils, bitos};
-use gekko::Address;
+use gekko::{Address, Cycles, FREQUENCY};
 use strum::FromRepr;
-
+use zerocopy::IntoBytes;
+
+use crate::Primitive;
+use crate::adpcm::{STREAM_BLOCK_SIZE, StreamDecoder};
+use crate::modules::disk::{DiskModule, NopDiskModule};
+use crate::system::ai::Frame;
+use crate::system::bus::{self, Device, Mmio};
+use crate::system::scheduler::{INTERRUPT_PRIORITY, SchedulerEventKind};
 use crate::system::{System, pi};
 
+/// Drive error reported while the cover is open: cover opened, medium not present.
+pub const ERROR_COVER_OPEN: u32 = 0x0102_3A00;
+/// Drive error reported by the first command after a disk is inserted: medium may have changed.
+pub const ERROR_MEDIUM_CHANGED: u32 = 0x0006_2800;
+
+/// How long the cover stays open when swapping disks.
+pub const SWAP_DELAY: u64 = FREQUENCY / 2;
+
+/// Size of a disc, in bytes.
+pub const DISC_SIZE: u64 = disks::iso::DISC_SIZE;
+/// Read speed at the inner edge of the disc, in bytes per second.
+const INNER_READ_SPEED: f64 = 2_000_000.0;
+/// Read speed at the outer edge of the disc, in bytes per second. The disc spins at a constant
+/// angular velocity, so data passes under the head faster further out.
+const OUTER_READ_SPEED: f64 = 3_100_000.0;
+/// Time taken by the shortest seek.
+const MIN_SEEK: Duration = Duration::from_millis(1);
+/// Time taken by a seek across the whole disc.
+const FULL_SEEK: Duration = Duration::from_millis(100);
+/// Reads starting at most this far after the head don't need a seek, since the drive just waits
+/// for the data to come around.
+const SEQUENTIAL_WINDOW: u64 = 0x8000;
+/// Time taken by commands when the latency model is disabled.
+const FAST_COMMAND: u64 = 10000;
+
+/// Time taken by the drive to move the head from the offset `from` to the offset `to`.
+pub fn seek_time(from: u64, to: u64) -> Duration {
+    if to >= from && to - from <= SEQUENTIAL_WINDOW {
+        return Duration::ZERO;
+    }
+
+    // seek time grows with the square root of the distance, since the sled accelerates
+    let distance = (from.abs_diff(to) as f64 / DISC_SIZE as f64).min(1.0);
+    MIN_SEEK + (FULL_SEEK - MIN_SEEK).mul_f64(distance.sqrt())
+}
+
+/// Time taken by the drive to transfer `length` bytes starting at `offset`, once the head is
+/// there.
+pub fn transfer_time(offset: u64, length: u64) -> Duration {
+    let position = (offset as f64 / DISC_SIZE as f64).min(1.0);
+    let speed = INNER_READ_SPEED + (OUTER_READ_SPEED - INNER_READ_SPEED) * position;
+    Duration::from_secs_f64(length as f64 / speed)
+}
+
+/// Time taken by the drive to read `length` bytes at `offset` with the head at `head`.
+pub fn read_time(head: u64, offset: u64, length: u64) -> Duration {
+    seek_time(head, offset) + transfer_time(offset, length)
+}
+
 #[bitos(32)]
 #[derive(Debug, Clone, Copy, Default)]
 pub struct Status {
@@ -28,7 +87,7 @@ pub struct Status {
 
 impl Status {
     pub fn any_interrupt(&self) -> bool {
-        let device_err = self.device_err_interrupt() && self.device_err_interrupt();
+        let device_err = self.device_err_interrupt() && self.device_err_interrupt_mask();
         let transfer = self.transfer_interrupt() && self.transfer_interrupt_mask();
         let break_ = self.break_interrupt() && self.break_interrupt_mask();
         device_err || transfer || break_
@@ -93,7 +152,7 @@ pub enum Command {
     Status,
     StartAudioStream { offset: u32, length: u32 },
     StopAudioStream,
-    AudioStreamStatus,
+    AudioStreamStatus(StreamQuery),
     StopMotor,
     DisableAudioStream,
     EnableAudioStream,
@@ -101,6 +160,77 @@ pub enum Command {
     DebugEnable,
 }
 
+/// What the audio stream status command asks for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamQuery {
+    Playing,
+    Position,
+    Start,
+    Length,
+}
+
+/// State of streamed disc audio (DTK).
+#[derive(Debug, Default)]
+pub struct AudioStream {
+    /// Whether streaming has been enabled by the audio config command.
+    pub enabled: bool,
+    pub playing: bool,
+    /// Whether the stream stops once the current range ends, instead of moving to the next one.
+    pub stop_at_end: bool,
+    /// Disc offset of the current range.
+    pub start: u32,
+    /// Length of the current range, in bytes.
+    pub length: u32,
+    /// Disc offset of the range played once the current one ends.
+    pub next_start: u32,
+    /// Length of the range played once the current one ends, in bytes.
+    pub next_length: u32,
+    /// Disc offset of the next block to decode.
+    pub position: u32,
+    decoder: StreamDecoder,
+    decoded: VecDeque<Frame>,
+}
+
+impl AudioStream {
+    fn start(&mut self, offset: u32, length: u32) {
+        if length == 0 {
+            self.stop_at_end = true;
+            return;
+        }
+
+        self.next_start = offset;
+        self.next_length = length;
+        if !self.playing {
+            self.playing = true;
+            self.stop_at_end = false;
+            self.jump_to_next();
+        }
+    }
+
+    fn stop(&mut self) {
+        self.playing = false;
+        self.stop_at_end = false;
+        self.decoded.clear();
+    }
+
+    fn jump_to_next(&mut self) {
+        self.start = self.next_start;
+        self.length = self.next_length;
+        self.position = self.start;
+        self.decoder = StreamDecoder::default();
+    }
+
+    fn query(&self, query: StreamQuery) -> u32 {
+        match query {
+            StreamQuery::Playing => self.playing as u32,
+            // reported at the granularity of ECC blocks
+            StreamQuery::Position => (self.position & !0x7FFF) >> 2,
+            StreamQuery::Start => self.start >> 2,
+            StreamQuery::Length => self.length,
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct Interface {
     pub status: Status,
@@ -111,9 +241,22 @@ pub struct Interface {
     pub cover: Cover,
     pub config: u32,
     pub immediate: u32,
+    /// Pending drive error, reported by the status command. Any other command fails with a device
+    /// error while this is not zero.
+    pub error: u32,
+    /// Disc offset the head of the drive is at.
+    pub head: u64,
+    pub stream: AudioStream,
+    /// Disk to insert once the cover closes during a swap.
+    swap: Option<Box<dyn DiskModule>>,
 }
 
 impl Interface {
+    pub fn any_interrupt(&self) -> bool {
+        let cover = self.cover.interrupt() && self.cover.interrupt_mask();
+        self.status.any_interrupt() || cover
+    }
+
     pub fn write_status(&mut self, value: Status) {
         self.status
             .set_device_err_interrupt_mask(value.device_err_interrupt_mask());
@@ -171,7 +314,10 @@ impl Interface {
                 _ => panic!("unknown audio stream command: {:02X}", buf[1]),
             },
             Opcode::AudioStatus => match buf[1] {
-                0x00 => Command::AudioStreamStatus,
+                0x00 => Command::AudioStreamStatus(StreamQuery::Playing),
+                0x01 => Command::AudioStreamStatus(StreamQuery::Position),
+                0x02 => Command::AudioStreamStatus(StreamQuery::Start),
+                0x03 => Command::AudioStreamStatus(StreamQuery::Length),
                 _ => panic!("unknown audio stream status command: {:02X}", buf[1]),
             },
             Opcode::StopMotor => Command::StopMotor,
@@ -204,6 +350,55 @@ pub fn complete_seek(sys: &mut System) {
     pi::check_interrupts(sys);
 }
 
+/// Opens the cover, ejecting the disk.
+pub fn open_cover(sys: &mut System) {
+    tracing::info!("disk cover opened");
+    sys.modules.disk = Box::new(NopDiskModule);
+    sys.disk.cover.set_open(true);
+    sys.disk.cover.set_interrupt(true);
+    sys.disk.error = ERROR_COVER_OPEN;
+    pi::check_interrupts(sys);
+}
+
+/// Inserts a disk and closes the cover.
+pub fn insert_disk(sys: &mut System, disk: Box<dyn DiskModule>) {
+    tracing::info!("disk inserted, cover closed");
+    sys.modules.disk = disk;
+    sys.disk.cover.set_open(false);
+    sys.disk.cover.set_interrupt(true);
+    sys.disk.error = ERROR_MEDIUM_CHANGED;
+    pi::check_interrupts(sys);
+}
+
+/// Swaps the disk: the cover is opened right away and closes with the new disk inserted after
+/// [`SWAP_DELAY`] cycles.
+pub fn swap_disk(sys: &mut System, disk: Box<dyn DiskModule>) {
+    self::open_cover(sys);
+    sys.disk.swap = Some(disk);
+    sys.scheduler.cancel_kind(SchedulerEventKind::DiscSwap);
+    sys.scheduler
+        .schedule(SWAP_DELAY, SchedulerEventKind::DiscSwap);
+}
+
+/// Closes the cover with the swapped disk inserted.
+pub fn finish_swap(sys: &mut System) {
+    if let Some(disk) = sys.disk.swap.take() {
+        self::insert_disk(sys, disk);
+    }
+}
+
+/// Fails the current command with a device error.
+fn fail_command(sys: &mut System, command: Command) {
+    tracing::debug!(
+        "failing DI command {command:?} with error 0x{:08X}",
+        sys.disk.error
+    );
+
+    sys.disk.status.set_device_err_interrupt(true);
+    sys.disk.control.set_transfer_ongoing(false);
+    pi::check_interrupts(sys);
+}
+
 pub fn write_control(sys: &mut System, value: Control) {
     sys.disk.control.set_dma(value.dma());
     sys.disk.control.set_mode(value.mode());
@@ -213,7 +408,24 @@ pub fn write_control(sys: &mut System, value: Control) {
         sys.disk.control.set_transfer_ongoing(true);
 
         let command = sys.disk.command();
+        if sys.disk.error != 0 && command != Command::Status {
+            self::fail_command(sys, command);
+            return;
+        }
+
         match command {
+            Command::Status => {
+                sys.disk.immediate = sys.disk.error;
+
+                // the medium changed condition is cleared once reported, but an open cover
+                // persists
+                if !sys.disk.cover.open() {
+                    sys.disk.error = 0;
+                }
+
+                sys.disk.status.set_transfer_interrupt(true);
+                sys.disk.control.set_transfer_ongoing(false);
+            }
             Command::Identify => {
                 // TODO: is this right?
                 let target = sys.mem.translate_data_addr(sys.disk.dma_base).unwrap();
@@ -227,7 +439,8 @@ pub fn write_control(sys: &mut System, value: Control) {
                 ]);
 
                 sys.mem.ram_mut()[target.value() as usize + 12..][..32 - 12].fill(0);
-                sys.scheduler.schedule(10000, complete_transfer);
+                sys.scheduler
+                    .schedule(10000, SchedulerEventKind::DiTransferComplete);
             }
             Command::Read { offset, length } => {
                 assert!(sys.disk.control.dma());
@@ -266,11 +479,28 @@ pub fn write_control(sys: &mut System, value: Control) {
                     sys.modules.disk.read_exact(slice).unwrap();
                 }
 
-                sys.scheduler.schedule(10000, complete_transfer);
+                let delay = if sys.config.fast_disc {
+                    FAST_COMMAND
+                } else {
+                    let time = self::read_time(sys.disk.head, offset as u64, length as u64);
+                    Cycles::from_duration(time).value().max(1)
+                };
+
+                sys.disk.head = offset as u64 + length as u64;
+                sys.scheduler
+                    .schedule(delay, SchedulerEventKind::DiTransferComplete);
             }
-            Command::Seek { .. } => {
-                tracing::warn!("stubbed DVD command - disk seek");
-                sys.scheduler.schedule(5000, complete_seek);
+            Command::Seek { offset } => {
+                let delay = if sys.config.fast_disc {
+                    FAST_COMMAND / 2
+                } else {
+                    let time = self::seek_time(sys.disk.head, offset as u64);
+                    Cycles::from_duration(time).value().max(1)
+                };
+
+                sys.disk.head = offset as u64;
+                sys.scheduler
+                    .schedule(delay, SchedulerEventKind::DiSeekComplete);
             }
             Command::StopMotor => {
                 tracing::warn!("stubbed DVD command - stop motor");
@@ -278,32 +508,33 @@ pub fn write_control(sys: &mut System, value: Control) {
                 sys.disk.control.set_transfer_ongoing(false);
                 sys.disk.immediate = 0;
             }
-            Command::StartAudioStream { .. } => {
-                tracing::warn!("stubbed DVD command - start audio stream");
+            Command::StartAudioStream { offset, length } => {
+                tracing::debug!("audio stream of 0x{length:08X} bytes at 0x{offset:08X}");
+                sys.disk.stream.start(offset, length);
                 sys.disk.status.set_transfer_interrupt(true);
                 sys.disk.control.set_transfer_ongoing(false);
                 sys.disk.immediate = 0;
             }
             Command::StopAudioStream => {
-                tracing::warn!("stubbed DVD command - stop audio stream");
+                sys.disk.stream.stop();
                 sys.disk.status.set_transfer_interrupt(true);
                 sys.disk.control.set_transfer_ongoing(false);
                 sys.disk.immediate = 0;
             }
-            Command::AudioStreamStatus => {
-                tracing::warn!("stubbed DVD command - audio stream status");
+            Command::AudioStreamStatus(query) => {
+                sys.disk.immediate = sys.disk.stream.query(query);
                 sys.disk.status.set_transfer_interrupt(true);
                 sys.disk.control.set_transfer_ongoing(false);
-                sys.disk.immediate = 0;
             }
             Command::EnableAudioStream => {
-                tracing::warn!("stubbed DVD command - enable audio stream");
+                sys.disk.stream.enabled = true;
                 sys.disk.status.set_transfer_interrupt(true);
                 sys.disk.control.set_transfer_ongoing(false);
                 sys.disk.immediate = 0;
             }
             Command::DisableAudioStream => {
-                tracing::warn!("stubbed DVD command - disable audio stream");
+                sys.disk.stream.enabled = false;
+                sys.disk.stream.stop();
                 sys.disk.status.set_transfer_interrupt(true);
                 sys.disk.control.set_transfer_ongoing(false);
                 sys.disk.immediate = 0;
@@ -313,6 +544,57 @@ pub fn write_control(sys: &mut System, value: Control) {
     }
 }
 
+/// Returns the next frame of streamed disc audio, reading and decoding a block from the disc if
+/// needed. Returns `None` if no stream is playing.
+pub fn next_stream_frame(sys: &mut System) -> Option<Frame> {
+    let stream = &mut sys.disk.stream;
+    if !stream.enabled || !stream.playing {
+        return None;
+    }
+
+    if let Some(frame) = stream.decoded.pop_front() {
+        return Some(frame);
+    }
+
+    if stream.position as u64 >= stream.start as u64 + stream.length as u64 {
+        if stream.stop_at_end {
+            stream.stop();
+            return None;
+        }
+
+        stream.jump_to_next();
+    }
+
+    let position = stream.position;
+    let mut block = [0; STREAM_BLOCK_SIZE];
+    if sys.modules.disk.has_disk() {
+        let read = sys
+            .modules
+            .disk
+            .seek(SeekFrom::Start(position as u64))
+            .and_then(|_| sys.modules.disk.read_exact(&mut block));
+
+        if let Err(e) = read {
+            tracing::warn!("failed to read audio stream block at 0x{position:08X}: {e}");
+            block.fill(0);
+        }
+    }
+
+    // streaming moves the head just like reads do
+    sys.disk.head = position as u64 + STREAM_BLOCK_SIZE as u64;
+
+    let stream = &mut sys.disk.stream;
+    stream.position += STREAM_BLOCK_SIZE as u32;
+    let samples = stream.decoder.decode(&block);
+    stream.decoded.extend(
+        samples
+            .into_iter()
+            .map(|[left, right]| Frame { left, right }),
+    );
+
+    stream.decoded.pop_front()
+}
+
 // TODO: figure this out
 pub fn reset(sys: &mut System, value: u32) {
     if !value.bit(2) {
@@ -320,5 +602,342 @@ pub fn reset(sys: &mut System, value: u32) {
     }
 
     tracing::warn!("dvd drive reset through processor interface");
+
+    // the cover and whatever is happening to it are not affected by the reset
+    let cover = sys.disk.cover.with_interrupt(false);
+    let swap = sys.disk.swap.take();
     sys.disk = Default::default();
+    sys.disk.cover = cover;
+    sys.disk.swap = swap;
+    if cover.open() {
+        sys.disk.error = ERROR_COVER_OPEN;
+    }
+}
+
+impl Device for Interface {
+    const NAME: &'static str = "DI";
+    const RANGE: Range<u16> = 0x6000..0x6040;
+
+    fn read<P: Primitive>(sys: &mut System, offset: u16) -> P {
+        let Some((reg, _, range)) = bus::resolve::<P>(offset) else {
+            tracing::error!(pc = ?sys.cpu.pc, "reading from unknown DI register ({offset:04X})");
+            return P::default();
+        };
+
+        let disk = &sys.disk;
+        let bytes = match reg {
+            Mmio::DiskStatus => disk.status.as_bytes(),
+            Mmio::DiskCover => disk.cover.as_bytes(),
+            Mmio::DiskDmaBase => disk.dma_base.as_bytes(),
+            Mmio::DiskDmaLength => disk.dma_length.as_bytes(),
+            Mmio::DiskControl => disk.control.as_bytes(),
+            Mmio::DiskImmediateData => disk.immediate.as_bytes(),
+            Mmio::DiskConfiguration => disk.config.as_bytes(),
+            _ => {
+                tracing::warn!(pc = ?sys.cpu.pc, "unimplemented read from known mmio register ({reg:?})");
+                return P::default();
+            }
+        };
+
+        let value = P::read_ne_bytes(&bytes[range.clone()]);
+        if reg != Mmio::DiskControl {
+            tracing::debug!(pc = ?sys.cpu.pc, "reading from {reg:?}[{range:?}]: {value:08X}");
+        }
+
+        value
+    }
+
+    fn write<P: Primitive>(sys: &mut System, offset: u16, value: P) {
+        let Some((reg, _, range)) = bus::resolve::<P>(offset) else {
+            tracing::error!("writing 0x{value:08X} to unknown DI register ({offset:04X})");
+            return;
+        };
+
+        tracing::debug!(pc = ?sys.cpu.pc, "writing 0x{value:08X} to {reg:?}[{range:?}]");
+
+        // write to native endian bytes
+        macro_rules! ne {
+            ($bytes:expr) => {
+                value.write_ne_bytes(&mut $bytes[range.clone()])
+            };
+        }
+
+        match reg {
+            Mmio::DiskStatus => {
+                let mut written = Status::from_bits(0);
+                ne!(written.as_mut_bytes());
+                sys.disk.write_status(written);
+                tracing::debug!(diskstatus = ?sys.disk.status);
+                sys.scheduler
+                    .schedule_now(INTERRUPT_PRIORITY, SchedulerEventKind::CheckInterrupts);
+            }
+            Mmio::DiskCover => {
+                let mut written = Cover::from_bits(0);
+                ne!(written.as_mut_bytes());
+                sys.disk.write_cover(written);
+                tracing::debug!(diskcover = ?sys.disk.cover);
+                sys.scheduler
+                    .schedule_now(INTERRUPT_PRIORITY, SchedulerEventKind::CheckInterrupts);
+            }
+            Mmio::DiskCommand0 => ne!(sys.disk.command_buffer[0].as_mut_bytes()),
+            Mmio::DiskCommand1 => ne!(sys.disk.command_buffer[1].as_mut_bytes()),
+            Mmio::DiskCommand2 => ne!(sys.disk.command_buffer[2].as_mut_bytes()),
+            Mmio::DiskDmaBase => ne!(sys.disk.dma_base.as_mut_bytes()),
+            Mmio::DiskDmaLength => ne!(sys.disk.dma_length.as_mut_bytes()),
+            Mmio::DiskControl => {
+                let mut written = Control::from_bits(0);
+                ne!(written.as_mut_bytes());
+                self::write_control(sys, written);
+            }
+            Mmio::DiskConfiguration => ne!(sys.disk.config.as_mut_bytes()),
+            _ => tracing::warn!("unimplemented write to known mmio register ({reg:?})"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Cursor, Read, Seek, SeekFrom};
+    use std::time::Duration;
+
+    use gekko::{Address, Cycles};
+
+    use super::{
+        Control, Cover, DISC_SIZE, ERROR_COVER_OPEN, ERROR_MEDIUM_CHANGED, SWAP_DELAY, Status,
+    };
+    use crate::modules::disk::DiskModule;
+    use crate::system::{self, Modules, System, di, pi};
+
+    /// A disk whose header starts with the given game ID.
+    struct FakeDisk(Cursor<Vec<u8>>);
+
+    impl FakeDisk {
+        fn new(id: &[u8; 6]) -> Box<Self> {
+            let mut data = vec![0; 0x1000];
+            data[..6].copy_from_slice(id);
+            Box::new(Self(Cursor::new(data)))
+        }
+    }
+
+    impl Read for FakeDisk {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+
+    impl Seek for FakeDisk {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.0.seek(pos)
+        }
+    }
+
+    impl DiskModule for FakeDisk {
+        fn has_disk(&self) -> bool {
+            true
+        }
+    }
+
+    fn system() -> System {
+        let mut sys = System::new(
+            Modules::nop(),
+            system::Config {
+                fast_disc: true,
+                ..Default::default()
+            },
+        );
+
+        sys.disk
+            .write_cover(Cover::default().with_interrupt_mask(true));
+        sys.disk.write_status(
+            Status::default()
+                .with_device_err_interrupt_mask(true)
+                .with_transfer_interrupt_mask(true),
+        );
+
+        sys
+    }
+
+    fn run(sys: &mut System, cycles: u64) {
+        sys.scheduler.advance(cycles);
+        sys.process_events();
+    }
+
+    fn start(sys: &mut System, command: [u32; 3], dma_length: u32) {
+        sys.disk.command_buffer = command;
+        sys.disk.dma_base = Address(0x1000);
+        sys.disk.dma_length = dma_length;
+        di::write_control(
+            sys,
+            Control::default()
+                .with_transfer_ongoing(true)
+                .with_dma(dma_length != 0),
+        );
+    }
+
+    /// Reads the disk header into RAM, returning the game ID.
+    fn read_header(sys: &mut System) -> [u8; 6] {
+        start(sys, [0xA800_0000, 0, 0x20], 0x20);
+        run(sys, 10000);
+        sys.mem.ram()[0x1000..][..6].try_into().unwrap()
+    }
+
+    /// Requests the drive error.
+    fn request_error(sys: &mut System) -> u32 {
+        start(sys, [0xE000_0000, 0, 0], 0);
+        sys.disk.immediate
+    }
+
+    /// Acknowledges every DI interrupt.
+    fn acknowledge(sys: &mut System) {
+        sys.disk.write_cover(
+            Cover::default()
+                .with_interrupt_mask(true)
+                .with_interrupt(true),
+        );
+        sys.disk.write_status(
+            Status::default()
+                .with_device_err_interrupt_mask(true)
+                .with_device_err_interrupt(true)
+                .with_transfer_interrupt_mask(true)
+                .with_transfer_interrupt(true),
+        );
+    }
+
+    fn di_interrupt(sys: &System) -> bool {
+        pi::get_active_interrupts(sys).dvd_interface()
+    }
+
+    #[test]
+    fn swap() {
+        let mut sys = system();
+        di::insert_disk(&mut sys, FakeDisk::new(b"GAME01"));
+        assert!(sys.disk.cover.interrupt());
+        assert_eq!(request_error(&mut sys), ERROR_MEDIUM_CHANGED);
+        acknowledge(&mut sys);
+
+        assert_eq!(&read_header(&mut sys), b"GAME01");
+        acknowledge(&mut sys);
+        assert!(!di_interrupt(&sys));
+
+        // 1. the cover opens
+        di::swap_disk(&mut sys, FakeDisk::new(b"GAME02"));
+        assert!(sys.disk.cover.open());
+        assert!(sys.disk.cover.interrupt());
+        assert!(di_interrupt(&sys));
+        acknowledge(&mut sys);
+        assert!(!di_interrupt(&sys));
+
+        // 2. commands fail while it is open
+        start(&mut sys, [0xA800_0000, 0, 0x20], 0x20);
+        assert!(sys.disk.status.device_err_interrupt());
+        assert!(!sys.disk.control.transfer_ongoing());
+        assert_eq!(request_error(&mut sys), ERROR_COVER_OPEN);
+        assert_eq!(request_error(&mut sys), ERROR_COVER_OPEN);
+        acknowledge(&mut sys);
+
+        // 3. the cover closes with the new disk inserted
+        run(&mut sys, SWAP_DELAY);
+        assert!(!sys.disk.cover.open());
+        assert!(sys.disk.cover.interrupt());
+        assert!(di_interrupt(&sys));
+        acknowledge(&mut sys);
+
+        // 4. the first command reports the medium change
+        start(&mut sys, [0xA800_0000, 0, 0x20], 0x20);
+        assert!(sys.disk.status.device_err_interrupt());
+        assert_eq!(request_error(&mut sys), ERROR_MEDIUM_CHANGED);
+        assert_eq!(request_error(&mut sys), 0);
+        acknowledge(&mut sys);
+
+        // 5. after a reset, the new disk is read
+        di::reset(&mut sys, 1 << 2);
+        assert!(!sys.disk.cover.open());
+        assert_eq!(&read_header(&mut sys), b"GAME02");
+        assert!(sys.disk.status.transfer_interrupt());
+    }
+
+    #[test]
+    fn latency_model() {
+        // reads right after the head don't seek
+        assert_eq!(di::seek_time(0x1000, 0x1000), Duration::ZERO);
+        assert_eq!(di::seek_time(0x1000, 0x2000), Duration::ZERO);
+
+        // reads behind it or far away do, and longer seeks take longer
+        assert!(di::seek_time(0x2000, 0x1000) > Duration::ZERO);
+        assert!(di::seek_time(0, 0x100_0000) < di::seek_time(0, 0x4000_0000));
+        assert_eq!(di::seek_time(0, DISC_SIZE), Duration::from_millis(100));
+        assert_eq!(
+            di::seek_time(0, DISC_SIZE / 2),
+            di::seek_time(DISC_SIZE, DISC_SIZE / 2)
+        );
+
+        // data at the outer edge is read faster
+        let inner = di::transfer_time(0, 2_000_000);
+        let outer = di::transfer_time(DISC_SIZE - 2_000_000, 2_000_000);
+        assert!((inner.as_secs_f64() - 1.0).abs() < 1e-6);
+        assert!(outer < inner);
+
+        assert_eq!(
+            di::read_time(0, DISC_SIZE / 2, 0x8000),
+            di::seek_time(0, DISC_SIZE / 2) + di::transfer_time(DISC_SIZE / 2, 0x8000)
+        );
+    }
+
+    #[test]
+    fn read_latency() {
+        let mut sys = system();
+        sys.config.fast_disc = false;
+        di::insert_disk(&mut sys, FakeDisk::new(b"GAME01"));
+        request_error(&mut sys);
+        acknowledge(&mut sys);
+
+        // the head is halfway through the disc, so reading the header needs a long seek
+        sys.disk.head = DISC_SIZE / 2;
+        let latency = Cycles::from_duration(di::read_time(DISC_SIZE / 2, 0, 0x20)).value();
+        assert!(latency > 10000);
+
+        start(&mut sys, [0xA800_0000, 0, 0x20], 0x20);
+        run(&mut sys, latency - 1);
+        assert!(!sys.disk.status.transfer_interrupt());
+
+        run(&mut sys, 1);
+        assert!(sys.disk.status.transfer_interrupt());
+        assert_eq!(sys.disk.head, 0x20);
+    }
+
+    #[test]
+    fn audio_stream() {
+        let mut sys = system();
+        di::insert_disk(&mut sys, FakeDisk::new(b"GAME01"));
+        request_error(&mut sys);
+        acknowledge(&mut sys);
+
+        // nothing plays until streaming is enabled and a stream is started
+        assert!(di::next_stream_frame(&mut sys).is_none());
+        start(&mut sys, [0xE401_000A, 0, 0], 0);
+        start(&mut sys, [0xE100_0000, 0x800 >> 2, 0x40], 0);
+        start(&mut sys, [0xE200_0000, 0, 0], 0);
+        assert_eq!(sys.disk.immediate, 1);
+        start(&mut sys, [0xE202_0000, 0, 0], 0);
+        assert_eq!(sys.disk.immediate, 0x800 >> 2);
+
+        // two blocks of 28 frames each, after which the range loops
+        for _ in 0..2 * 28 {
+            assert!(di::next_stream_frame(&mut sys).is_some());
+        }
+
+        assert_eq!(sys.disk.stream.position, 0x840);
+        assert!(di::next_stream_frame(&mut sys).is_some());
+        assert_eq!(sys.disk.stream.position, 0x820);
+
+        // a zero length stream stops playback once the current range ends
+        start(&mut sys, [0xE100_0000, 0, 0], 0);
+        for _ in 0..2 * 28 - 1 {
+            assert!(di::next_stream_frame(&mut sys).is_some());
+        }
+
+        assert!(di::next_stream_frame(&mut sys).is_none());
+        start(&mut sys, [0xE200_0000, 0, 0], 0);
+        assert_eq!(sys.disk.immediate, 0);
+    }
 }
@@ -1,13 +1,18 @@
 //! DSP interface (DSPI).
 use bitos::integer::{u15, u31};
 use bitos::{BitUtils, bitos};
-use gekko::Address;
+use gekko::{Address, Cycles, FREQUENCY};
 use util::boxed_array;
 
 use crate::system::System;
+use crate::system::mem::RAM_LEN;
+use crate::system::scheduler::SchedulerEventKind;
 
 pub const ARAM_LEN: usize = 16 * bytesize::MIB as usize;
 
+/// Frequency of the bus used by DSP DMA transfers, which move a 16-bit word per cycle.
+pub const DSP_DMA_BUS_FREQUENCY: u64 = 32_000_000;
+
 #[bitos(32)]
 #[derive(Debug, Default)]
 pub struct Mailbox {
@@ -140,6 +145,10 @@ pub struct Dsp {
     /// Data from CPU to DSP
     pub cpu_mailbox: Mailbox,
     pub dsp_dma: DspDma,
+    /// Length in bytes and completion cycle of the DSP DMA currently in flight, if any.
+    pub dsp_dma_pending: Option<(u32, Cycles)>,
+    /// Cycles the CPU still has to be stalled for because of DSP DMAs holding the bus.
+    pub dma_stall: Cycles,
     pub aram_dma: AramDma,
     pub aram_len: u32,
     pub aram: Box<[u8; ARAM_LEN]>,
@@ -152,6 +161,8 @@ impl Dsp {
             dsp_mailbox: Default::default(),
             cpu_mailbox: Default::default(),
             dsp_dma: Default::default(),
+            dsp_dma_pending: None,
+            dma_stall: Cycles(0),
             aram_dma: Default::default(),
             aram_len: 0,
             aram: boxed_array(0),
@@ -192,9 +203,35 @@ pub fn write_control(sys: &mut System, value: Control) {
     sys.dsp.control.set_reset_high(value.reset_high());
 }
 
+/// How long a DSP DMA of `length` bytes holds the bus, in system cycles.
+pub fn dma_stall_cycles(length: u16) -> Cycles {
+    let words = (length as u64).div_ceil(2);
+    Cycles((words * FREQUENCY).div_ceil(DSP_DMA_BUS_FREQUENCY).max(1))
+}
+
+/// Schedules the completion of a DSP DMA of `length` bytes whose data has already been moved.
+/// The transfer keeps being reported as ongoing until then, and the CPU is stalled for as long as
+/// it holds the bus.
+pub fn schedule_dsp_dma_completion(sys: &mut System, length: u16) {
+    let cycles = self::dma_stall_cycles(length);
+    let completion = Cycles(sys.scheduler.elapsed()) + cycles;
+
+    sys.dsp.dsp_dma_pending = Some((length as u32, completion));
+    sys.dsp.dma_stall += cycles;
+    sys.scheduler
+        .schedule(cycles.0, SchedulerEventKind::DspDmaComplete);
+}
+
+/// Finishes the DSP DMA in flight.
+pub fn complete_dsp_dma(sys: &mut System) {
+    sys.dsp.dsp_dma_pending = None;
+    sys.dsp.dsp_dma.length = 0;
+    sys.dsp.dsp_dma.control.set_transfer_ongoing(false);
+}
+
 /// Performs the ARAM DMA if length is not zero.
 pub fn aram_dma(sys: &mut System) {
-    let ram_base = sys.dsp.aram_dma.ram_base.value().with_bits(26, 32, 0);
+    let ram_base = Address(sys.dsp.aram_dma.ram_base.value().with_bits(26, 32, 0));
     let aram_base = sys.dsp.aram_dma.aram_base as usize;
 
     if aram_base >= ARAM_LEN {
@@ -206,27 +243,36 @@ pub fn aram_dma(sys: &mut System) {
         return;
     }
 
-    let max_length = ARAM_LEN - aram_base;
+    // transfers running past the end of either memory are truncated
+    let max_aram_length = ARAM_LEN - aram_base;
+    let max_ram_length = Address(RAM_LEN as u32).offset_from(ram_base).unwrap_or(0) as usize;
     let length = sys.dsp.aram_dma.control.length().value() as usize;
-    let effective_length = length.min(max_length);
+    let effective_length = length.min(max_aram_length).min(max_ram_length);
+    if effective_length < length {
+        tracing::warn!(
+            "ARAM DMA of {length} bytes between RAM {ram_base} and ARAM {aram_base:08X} truncated \
+             to {effective_length} bytes"
+        );
+    }
+
+    // an out of bounds RAM base has a length of zero at this point, clamp it to keep slicing valid
+    let ram_base = (ram_base.value() as usize).min(RAM_LEN);
 
     match sys.dsp.aram_dma.control.direction() {
         AramDmaDirection::FromRamToAram => {
             tracing::debug!(
-                "ARAM DMA {effective_length} bytes from RAM {} to ARAM {aram_base:08X}",
-                Address(ram_base)
+                "ARAM DMA {effective_length} bytes from RAM {ram_base:08X} to ARAM {aram_base:08X}"
             );
 
             let aram = &mut sys.dsp.aram[aram_base..][..effective_length];
-            aram.copy_from_slice(&sys.mem.ram()[ram_base as usize..][..effective_length]);
+            aram.copy_from_slice(&sys.mem.ram()[ram_base..][..effective_length]);
         }
         AramDmaDirection::FromAramToRam => {
             tracing::debug!(
-                "ARAM DMA {effective_length} bytes from ARAM {aram_base:08X} to RAM {}",
-                Address(ram_base)
+                "ARAM DMA {effective_length} bytes from ARAM {aram_base:08X} to RAM {ram_base:08X}"
             );
 
-            sys.mem.ram_mut()[ram_base as usize..][..effective_length]
+            sys.mem.ram_mut()[ram_base..][..effective_length]
                 .copy_from_slice(&sys.dsp.aram[aram_base..][..effective_length]);
         }
     }
@@ -235,3 +281,34 @@ pub fn aram_dma(sys: &mut System) {
     sys.dsp.control.set_aram_dma_interrupt(true);
     sys.dsp.control.set_aram_dma_ongoing(false);
 }
+
+#[cfg(test)]
+mod test {
+    use gekko::Cycles;
+
+    use crate::test::system;
+
+    #[test]
+    fn dma_stall_cycles() {
+        // a word takes 486 / 32 = 15.1875 system cycles
+        assert_eq!(super::dma_stall_cycles(2), Cycles(16));
+        assert_eq!(super::dma_stall_cycles(0x20), Cycles(243));
+        assert_eq!(super::dma_stall_cycles(0x1000), Cycles(31104));
+
+        // odd lengths take a whole word, and even empty transfers take a cycle
+        assert_eq!(super::dma_stall_cycles(3), Cycles(31));
+        assert_eq!(super::dma_stall_cycles(4), Cycles(31));
+        assert_eq!(super::dma_stall_cycles(0), Cycles(1));
+    }
+
+    #[test]
+    fn dma_stall_accumulates() {
+        let mut sys = system();
+        super::schedule_dsp_dma_completion(&mut sys, 0x20);
+        super::schedule_dsp_dma_completion(&mut sys, 0x400);
+
+        // 243 + 7776 cycles
+        assert_eq!(sys.dsp.dma_stall, Cycles(8019));
+        assert_eq!(sys.dsp.dsp_dma_pending, Some((0x400, Cycles(7776))));
+    }
+}
@@ -2,6 +2,9 @@ use gekko::Address;
 
 use crate::system::System;
 
+/// Maximum number of frames unwound, to avoid hanging on cyclic back chains.
+const MAX_FRAMES: usize = 256;
+
 #[derive(Debug)]
 pub struct CallFrame {
     /// Address of this call.
@@ -16,7 +19,7 @@ pub struct CallFrame {
     pub returns: Address,
 }
 
-#[derive(Default)]
+#[derive(Debug, Default)]
 pub struct CallStack(pub Vec<CallFrame>);
 
 impl std::fmt::Display for CallStack {
@@ -46,7 +49,7 @@ pub fn call_stack(sys: &System, top_frame: Address, top_routine: Address) -> Cal
     let mut current_routine = top_routine.value();
 
     loop {
-        if current_frame == 0 || current_routine == 0 {
+        if current_frame == 0 || current_routine == 0 || call_stack.len() == MAX_FRAMES {
             break;
         }
 
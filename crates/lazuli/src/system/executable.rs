@@ -1,28 +1,523 @@
+use std::io::Cursor;
 use std::path::Path;
 
 use disks::binrw::BinRead;
-use disks::binrw::io::BufReader;
 use disks::dol::Dol;
+use disks::elf;
+use disks::elf::ElfBytes;
+use disks::elf::endian::AnyEndian;
 use easyerr::{Error, ResultExt};
+use gekko::Address;
+
+use crate::system::mem::RAM_LEN;
+
+const ELF_MAGIC: &[u8] = b"\x7FELF";
+
+/// Bases of the cached and uncached mirrors of RAM.
+const RAM_MIRRORS: [u32; 2] = [0x8000_0000, 0xC000_0000];
+/// Bases of the cached and uncached mirrors of the Wii's MEM2.
+const MEM2_MIRRORS: [u32; 2] = [0x9000_0000, 0xD000_0000];
+const MEM2_LEN: u32 = 0x0400_0000;
 
 #[derive(Debug, Error)]
-pub enum OpenError {
+pub enum ExecutableError {
     #[error("executable has an unknown format")]
     UnknownFormat,
     #[error(transparent)]
     Io { source: std::io::Error },
+    #[error(transparent)]
+    Dol { source: disks::binrw::Error },
+    #[error(transparent)]
+    Elf { source: elf::ParseError },
+    #[error("elf is not a PowerPC executable (machine {machine})")]
+    NotPowerPc { machine: u16 },
+    #[error("elf is relocatable and has no program headers, link it into an executable first")]
+    Relocatable,
+    #[error("segment at {target} has more file contents than memory size")]
+    MalformedSegment { target: Address },
+    #[error("segment at {target} targets MEM2, which only exists on the Wii")]
+    Mem2Segment { target: Address },
+    #[error("segment at {target} with size {size:#X} does not fit in RAM")]
+    SegmentOutsideRam { target: Address, size: u32 },
+    #[error("entrypoint {entry} is not in RAM")]
+    EntryOutsideRam { entry: Address },
+}
+
+/// Translates an address found in an executable to a virtual address. Physical addresses (i.e.
+/// below `0x8000_0000`) are mapped into the cached RAM mirror of the default BATs.
+fn translate(addr: u32) -> Address {
+    if addr < 0x8000_0000 {
+        Address(addr | 0x8000_0000)
+    } else {
+        Address(addr)
+    }
+}
+
+/// Whether the region of `size` bytes starting at `target` lies entirely within a RAM mirror.
+fn in_ram(target: Address, size: u32) -> bool {
+    let start = target.value() as u64;
+    let end = start + size as u64;
+    RAM_MIRRORS
+        .iter()
+        .any(|&base| start >= base as u64 && end <= base as u64 + RAM_LEN as u64)
+}
+
+fn in_mem2(target: Address) -> bool {
+    MEM2_MIRRORS
+        .iter()
+        .any(|&base| (base..base + MEM2_LEN).contains(&target.value()))
+}
+
+/// A loadable segment of an ELF executable.
+#[derive(Debug, Clone)]
+pub struct Segment {
+    /// Address where the segment should be loaded at.
+    pub target: Address,
+    /// Contents of the segment. If shorter than `size`, the rest of the segment is zero-filled.
+    pub content: Vec<u8>,
+    /// Size of the segment in memory.
+    pub size: u32,
+}
+
+/// A function or object symbol of an ELF executable.
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub addr: Address,
+    pub size: u32,
+}
+
+/// An ELF executable, loaded strictly by its program headers.
+#[derive(Debug, Clone)]
+pub struct Elf {
+    /// Entrypoint of the executable.
+    pub entry: Address,
+    /// Loadable segments of the executable.
+    pub segments: Vec<Segment>,
+    /// Symbols from the `.symtab` section, sorted by address. Empty if the executable is stripped.
+    pub symbols: Vec<Symbol>,
+}
+
+impl Elf {
+    pub fn parse(data: &[u8]) -> Result<Self, ExecutableError> {
+        let file = ElfBytes::<AnyEndian>::minimal_parse(data).context(ExecutableCtx::Elf)?;
+        if file.ehdr.e_machine != elf::abi::EM_PPC {
+            return Err(ExecutableError::NotPowerPc {
+                machine: file.ehdr.e_machine,
+            });
+        }
+
+        let mut segments = vec![];
+        for phdr in file.segments().into_iter().flatten() {
+            if phdr.p_type != elf::abi::PT_LOAD || phdr.p_memsz == 0 {
+                continue;
+            }
+
+            let target = translate(phdr.p_vaddr as u32);
+            if phdr.p_filesz > phdr.p_memsz {
+                return Err(ExecutableError::MalformedSegment { target });
+            }
+
+            let size = phdr.p_memsz as u32;
+            if in_mem2(target) {
+                return Err(ExecutableError::Mem2Segment { target });
+            }
+
+            if !in_ram(target, size) {
+                return Err(ExecutableError::SegmentOutsideRam { target, size });
+            }
+
+            let content = file.segment_data(&phdr).context(ExecutableCtx::Elf)?;
+            segments.push(Segment {
+                target,
+                content: content.to_vec(),
+                size,
+            });
+        }
+
+        // relocatable objects only have sections, which would need to be placed and relocated
+        if file.ehdr.e_type == elf::abi::ET_REL && segments.is_empty() {
+            return Err(ExecutableError::Relocatable);
+        }
+
+        let entry = translate(file.ehdr.e_entry as u32);
+        if !in_ram(entry, 4) {
+            return Err(ExecutableError::EntryOutsideRam { entry });
+        }
+
+        // symbols of relocatable objects are section relative, so they're of no use here
+        let symbols = if file.ehdr.e_type == elf::abi::ET_REL {
+            vec![]
+        } else {
+            match Self::symbols(&file) {
+                Ok(symbols) => symbols,
+                Err(e) => {
+                    tracing::warn!("failed to parse elf symbol table: {e}");
+                    vec![]
+                }
+            }
+        };
+
+        Ok(Self {
+            entry,
+            segments,
+            symbols,
+        })
+    }
+
+    fn symbols(file: &ElfBytes<AnyEndian>) -> Result<Vec<Symbol>, elf::ParseError> {
+        let Some((symtab, strtab)) = file.symbol_table()? else {
+            return Ok(vec![]);
+        };
+
+        let mut symbols = vec![];
+        for sym in symtab.iter() {
+            let kind = sym.st_symtype();
+            if sym.st_shndx == elf::abi::SHN_UNDEF
+                || (kind != elf::abi::STT_FUNC && kind != elf::abi::STT_OBJECT)
+            {
+                continue;
+            }
+
+            let name = strtab.get(sym.st_name as usize)?;
+            if name.is_empty() {
+                continue;
+            }
+
+            symbols.push(Symbol {
+                name: name.to_owned(),
+                addr: translate(sym.st_value as u32),
+                size: sym.st_size as u32,
+            });
+        }
+
+        symbols.sort_by_key(|s| s.addr);
+        Ok(symbols)
+    }
 }
 
 pub enum Executable {
     Dol(Dol),
+    Elf(Elf),
 }
 
 impl Executable {
-    pub fn open(exec: &Path) -> Result<Self, OpenError> {
-        let exec_file = std::fs::File::open(exec).context(OpenCtx::Io)?;
+    pub fn open(exec: &Path) -> Result<Self, ExecutableError> {
+        let data = std::fs::read(exec).context(ExecutableCtx::Io)?;
+        if data.starts_with(ELF_MAGIC) {
+            return Elf::parse(&data).map(Executable::Elf);
+        }
+
         Ok(match exec.extension().and_then(|s| s.to_str()) {
-            Some("dol") => Executable::Dol(Dol::read(&mut BufReader::new(exec_file)).unwrap()),
-            _ => return Err(OpenError::UnknownFormat),
+            Some("dol") => {
+                Executable::Dol(Dol::read(&mut Cursor::new(data)).context(ExecutableCtx::Dol)?)
+            }
+            _ => return Err(ExecutableError::UnknownFormat),
         })
     }
+
+    /// Entrypoint of the executable.
+    pub fn entrypoint(&self) -> Address {
+        match self {
+            Executable::Dol(dol) => Address(dol.entrypoint()),
+            Executable::Elf(elf) => elf.entry,
+        }
+    }
+
+    /// Symbols embedded in the executable, sorted by address.
+    pub fn symbols(&self) -> &[Symbol] {
+        match self {
+            Executable::Dol(_) => &[],
+            Executable::Elf(elf) => &elf.symbols,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use gekko::Address;
+
+    use super::{Elf, ExecutableError};
+
+    const ET_EXEC: u16 = 2;
+    const ET_REL: u16 = 1;
+
+    struct Fixture {
+        kind: u16,
+        entry: u32,
+        /// `(vaddr, contents, memsz)` of each loadable segment.
+        segments: Vec<(u32, Vec<u8>, u32)>,
+        /// `(name, value)` of each function symbol.
+        symbols: Vec<(&'static str, u32)>,
+    }
+
+    impl Fixture {
+        fn exec(entry: u32) -> Self {
+            Self {
+                kind: ET_EXEC,
+                entry,
+                segments: vec![],
+                symbols: vec![],
+            }
+        }
+
+        fn segment(mut self, vaddr: u32, contents: &[u8], memsz: u32) -> Self {
+            self.segments.push((vaddr, contents.to_vec(), memsz));
+            self
+        }
+
+        fn symbol(mut self, name: &'static str, value: u32) -> Self {
+            self.symbols.push((name, value));
+            self
+        }
+
+        /// Builds a big-endian ELF32 file.
+        fn build(&self) -> Vec<u8> {
+            const EHDR_SIZE: usize = 52;
+            const PHDR_SIZE: usize = 32;
+            const SHDR_SIZE: usize = 40;
+            const SYM_SIZE: usize = 16;
+
+            fn u16(out: &mut Vec<u8>, value: u16) {
+                out.extend(value.to_be_bytes());
+            }
+
+            fn u32(out: &mut Vec<u8>, value: u32) {
+                out.extend(value.to_be_bytes());
+            }
+
+            let phoff = EHDR_SIZE;
+            let mut offset = phoff + PHDR_SIZE * self.segments.len();
+
+            let mut phdrs = vec![];
+            let mut contents: Vec<u8> = vec![];
+            for (vaddr, data, memsz) in &self.segments {
+                u32(&mut phdrs, 1); // PT_LOAD
+                u32(&mut phdrs, offset as u32);
+                u32(&mut phdrs, *vaddr);
+                u32(&mut phdrs, *vaddr);
+                u32(&mut phdrs, data.len() as u32);
+                u32(&mut phdrs, *memsz);
+                u32(&mut phdrs, 0b111); // RWX
+                u32(&mut phdrs, 4);
+
+                contents.extend(data);
+                offset += data.len();
+            }
+
+            let mut sections = vec![];
+            let mut shdrs = vec![];
+            if !self.symbols.is_empty() {
+                let mut strtab = vec![0];
+                let mut symtab = vec![0; SYM_SIZE];
+                for (name, value) in &self.symbols {
+                    u32(&mut symtab, strtab.len() as u32);
+                    u32(&mut symtab, *value);
+                    u32(&mut symtab, 4);
+                    symtab.push(0x12); // STB_GLOBAL, STT_FUNC
+                    symtab.push(0);
+                    u16(&mut symtab, 1);
+
+                    strtab.extend(name.as_bytes());
+                    strtab.push(0);
+                }
+
+                let symtab_offset = offset;
+                let strtab_offset = symtab_offset + symtab.len();
+                offset = (strtab_offset + strtab.len()).next_multiple_of(4);
+
+                // null section
+                shdrs.extend([0; SHDR_SIZE]);
+
+                // .symtab
+                u32(&mut shdrs, 0);
+                u32(&mut shdrs, 2); // SHT_SYMTAB
+                u32(&mut shdrs, 0);
+                u32(&mut shdrs, 0);
+                u32(&mut shdrs, symtab_offset as u32);
+                u32(&mut shdrs, symtab.len() as u32);
+                u32(&mut shdrs, 2); // link to .strtab
+                u32(&mut shdrs, 1);
+                u32(&mut shdrs, 4);
+                u32(&mut shdrs, SYM_SIZE as u32);
+
+                // .strtab
+                u32(&mut shdrs, 0);
+                u32(&mut shdrs, 3); // SHT_STRTAB
+                u32(&mut shdrs, 0);
+                u32(&mut shdrs, 0);
+                u32(&mut shdrs, strtab_offset as u32);
+                u32(&mut shdrs, strtab.len() as u32);
+                u32(&mut shdrs, 0);
+                u32(&mut shdrs, 0);
+                u32(&mut shdrs, 1);
+                u32(&mut shdrs, 0);
+
+                sections.extend(symtab);
+                sections.extend(strtab);
+                sections.resize(offset - symtab_offset, 0);
+            }
+
+            let shnum = shdrs.len() / SHDR_SIZE;
+            let shoff = if shnum == 0 { 0 } else { offset };
+
+            let mut out = vec![0x7F, b'E', b'L', b'F', 1, 2, 1];
+            out.resize(16, 0);
+            u16(&mut out, self.kind);
+            u16(&mut out, 20); // EM_PPC
+            u32(&mut out, 1);
+            u32(&mut out, self.entry);
+            u32(
+                &mut out,
+                if self.segments.is_empty() {
+                    0
+                } else {
+                    phoff as u32
+                },
+            );
+            u32(&mut out, shoff as u32);
+            u32(&mut out, 0);
+            u16(&mut out, EHDR_SIZE as u16);
+            u16(&mut out, PHDR_SIZE as u16);
+            u16(&mut out, self.segments.len() as u16);
+            u16(&mut out, SHDR_SIZE as u16);
+            u16(&mut out, shnum as u16);
+            u16(&mut out, 0);
+
+            out.extend(phdrs);
+            out.extend(contents);
+            out.extend(sections);
+            out.extend(shdrs);
+            out
+        }
+    }
+
+    #[test]
+    fn segments_with_bss() {
+        let data = Fixture::exec(0x8000_3100)
+            .segment(0x8000_3100, &[0x60, 0, 0, 0], 4)
+            .segment(0x8001_0000, &[1, 2, 3, 4, 5, 6, 7, 8], 0x100)
+            .build();
+
+        let elf = Elf::parse(&data).unwrap();
+        assert_eq!(elf.entry, Address(0x8000_3100));
+        assert_eq!(elf.segments.len(), 2);
+
+        let data = &elf.segments[1];
+        assert_eq!(data.target, Address(0x8001_0000));
+        assert_eq!(data.content, [1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(data.size, 0x100);
+    }
+
+    #[test]
+    fn physical_addresses_are_translated() {
+        let data = Fixture::exec(0x0000_3100)
+            .segment(0x0000_3100, &[0x60, 0, 0, 0], 4)
+            .build();
+
+        let elf = Elf::parse(&data).unwrap();
+        assert_eq!(elf.entry, Address(0x8000_3100));
+        assert_eq!(elf.segments[0].target, Address(0x8000_3100));
+    }
+
+    #[test]
+    fn uncached_mirror_is_accepted() {
+        let data = Fixture::exec(0x8000_3100)
+            .segment(0xC000_4000, &[], 0x40)
+            .segment(0x8000_3100, &[0x60, 0, 0, 0], 4)
+            .build();
+
+        let elf = Elf::parse(&data).unwrap();
+        assert_eq!(elf.segments[0].target, Address(0xC000_4000));
+    }
+
+    #[test]
+    fn segment_past_end_of_ram() {
+        let data = Fixture::exec(0x8000_3100)
+            .segment(0x817F_FFF0, &[], 0x20)
+            .build();
+
+        assert!(matches!(
+            Elf::parse(&data),
+            Err(ExecutableError::SegmentOutsideRam {
+                target: Address(0x817F_FFF0),
+                size: 0x20
+            })
+        ));
+    }
+
+    #[test]
+    fn mem2_segment() {
+        let data = Fixture::exec(0x8000_3100)
+            .segment(0x9000_0000, &[0; 4], 4)
+            .build();
+
+        assert!(matches!(
+            Elf::parse(&data),
+            Err(ExecutableError::Mem2Segment {
+                target: Address(0x9000_0000)
+            })
+        ));
+    }
+
+    #[test]
+    fn entry_outside_ram() {
+        let data = Fixture::exec(0xCC00_0000)
+            .segment(0x8000_3100, &[0; 4], 4)
+            .build();
+
+        assert!(matches!(
+            Elf::parse(&data),
+            Err(ExecutableError::EntryOutsideRam {
+                entry: Address(0xCC00_0000)
+            })
+        ));
+    }
+
+    #[test]
+    fn relocatable_without_segments() {
+        let mut fixture = Fixture::exec(0);
+        fixture.kind = ET_REL;
+
+        assert!(matches!(
+            Elf::parse(&fixture.build()),
+            Err(ExecutableError::Relocatable)
+        ));
+    }
+
+    #[test]
+    fn relocatable_with_segments() {
+        let mut fixture = Fixture::exec(0x3100)
+            .segment(0x3100, &[0x60, 0, 0, 0], 4)
+            .symbol("main", 0x10);
+        fixture.kind = ET_REL;
+
+        let elf = Elf::parse(&fixture.build()).unwrap();
+        assert_eq!(elf.entry, Address(0x8000_3100));
+        assert!(elf.symbols.is_empty());
+    }
+
+    #[test]
+    fn symbols() {
+        let data = Fixture::exec(0x8000_3100)
+            .segment(0x8000_3100, &[0; 0x20], 0x20)
+            .symbol("main", 0x8000_3110)
+            .symbol("_start", 0x0000_3100)
+            .build();
+
+        let elf = Elf::parse(&data).unwrap();
+        let symbols: Vec<_> = elf
+            .symbols
+            .iter()
+            .map(|s| (s.name.as_str(), s.addr))
+            .collect();
+
+        assert_eq!(
+            symbols,
+            [
+                ("_start", Address(0x8000_3100)),
+                ("main", Address(0x8000_3110))
+            ]
+        );
+    }
 }
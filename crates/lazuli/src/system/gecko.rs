@@ -0,0 +1,745 @@
+//! Gecko codes, a widely used format for patching games.
+//!
+//! A code is a list of lines made of two 32-bit hex words: the first one holds the codetype in
+//! its top byte and an address offset in the rest, and the second one holds a value. Supported
+//! codetypes are:
+//!
+//! - `00`/`02`/`04`: 8, 16 and 32-bit RAM writes (8 and 16-bit ones can fill a range)
+//! - `06`: string writes, with the data in the following lines
+//! - `20`..`2E`: 32-bit and masked 16-bit comparisons, skipping code until the matching endif
+//! - `40`/`42`/`44` and `48`/`4A`/`4C`: load, set and store the base address and the pointer
+//! - `E0`/`E2`: full terminator and endif (or else)
+//! - `F0`: end of the code
+//!
+//! Addresses of writes and comparisons are relative to the base address, or to the pointer if bit
+//! 4 of the codetype is set (e.g. `14` instead of `04`). Gecko registers and assembly codetypes
+//! are not supported.
+
+use easyerr::Error;
+use gekko::{Address, FREQUENCY};
+use zerocopy::IntoBytes;
+
+use crate::Primitive;
+use crate::system::System;
+use crate::system::mem::RAM_LEN;
+
+/// Interval at which codes are applied, once per frame.
+pub const APPLY_INTERVAL: u64 = FREQUENCY / 60;
+
+/// Initial value of the base address and the pointer.
+const DEFAULT_BASE: u32 = 0x8000_0000;
+
+#[derive(Debug, Error)]
+pub enum GeckoError {
+    #[error("line {line}: expected two 32-bit hex words")]
+    InvalidLine { line: usize },
+    #[error("line {line}: unsupported codetype {codetype:02X}")]
+    Unsupported { line: usize, codetype: u8 },
+    #[error("line {line}: string write is missing data")]
+    MissingData { line: usize },
+}
+
+/// Which register an address is relative to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Base {
+    /// The base address.
+    Ba,
+    /// The pointer.
+    Po,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Greater,
+    Lower,
+}
+
+impl Comparison {
+    fn eval<T: Ord>(self, lhs: T, rhs: T) -> bool {
+        match self {
+            Self::Equal => lhs == rhs,
+            Self::NotEqual => lhs != rhs,
+            Self::Greater => lhs > rhs,
+            Self::Lower => lhs < rhs,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Op {
+    /// Writes `value` to `count + 1` consecutive bytes.
+    Fill8 {
+        base: Base,
+        offset: u32,
+        value: u8,
+        count: u16,
+    },
+    /// Writes `value` to `count + 1` consecutive halfwords.
+    Fill16 {
+        base: Base,
+        offset: u32,
+        value: u16,
+        count: u16,
+    },
+    Write32 {
+        base: Base,
+        offset: u32,
+        value: u32,
+    },
+    WriteString {
+        base: Base,
+        offset: u32,
+        data: Vec<u8>,
+    },
+    /// Compares the word at the address with `value`. If `endif` is set, an endif is applied
+    /// before the comparison.
+    If32 {
+        endif: bool,
+        base: Base,
+        offset: u32,
+        comparison: Comparison,
+        value: u32,
+    },
+    /// Compares the halfword at the address, with the bits in `mask` cleared, with `value`.
+    If16 {
+        endif: bool,
+        base: Base,
+        offset: u32,
+        comparison: Comparison,
+        mask: u16,
+        value: u16,
+    },
+    /// Loads `target` from the word at `address`, optionally relative to `relative`. If `add` is
+    /// set, the word is added to `target` instead.
+    Load {
+        target: Base,
+        add: bool,
+        relative: Option<Base>,
+        address: u32,
+    },
+    /// Sets `target` to `value`, optionally relative to `relative`. If `add` is set, the result
+    /// is added to `target` instead.
+    Set {
+        target: Base,
+        add: bool,
+        relative: Option<Base>,
+        value: u32,
+    },
+    /// Stores `target` to `address`, optionally relative to `relative`.
+    Store {
+        target: Base,
+        relative: Option<Base>,
+        address: u32,
+    },
+    /// Applies `count` endifs, then inverts the current condition if `invert` is set. The high
+    /// halves of the base address and the pointer are set to `ba` and `po`, if not zero.
+    Endif {
+        count: u8,
+        invert: bool,
+        ba: u16,
+        po: u16,
+    },
+    /// Like [`Op::Endif`], but applies every endif.
+    Terminator {
+        ba: u16,
+        po: u16,
+    },
+    /// Stops the execution of the code.
+    End,
+}
+
+/// A Gecko code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GeckoCode {
+    pub name: String,
+    ops: Vec<Op>,
+}
+
+/// Parses a line of a code into its two words.
+fn parse_line(text: &str, line: usize) -> Result<(u32, u32), GeckoError> {
+    let mut words = text.split_whitespace().map(|word| {
+        (word.len() == 8)
+            .then(|| u32::from_str_radix(word, 16).ok())
+            .flatten()
+    });
+
+    match (words.next(), words.next(), words.next()) {
+        (Some(Some(a)), Some(Some(b)), None) => Ok((a, b)),
+        _ => Err(GeckoError::InvalidLine { line }),
+    }
+}
+
+/// Decodes the lines of a code, each with its line number, into operations.
+fn decode(lines: &[(usize, u32, u32)]) -> Result<Vec<Op>, GeckoError> {
+    let mut ops = Vec::new();
+    let mut lines = lines.iter().copied();
+    while let Some((line, a, b)) = lines.next() {
+        let codetype = (a >> 24) as u8;
+        let base = if codetype & 0x10 != 0 {
+            Base::Po
+        } else {
+            Base::Ba
+        };
+        let offset = a & 0x01FF_FFFF;
+
+        // base address and pointer operations use two nibbles to select what they operate on
+        let add = (a >> 20) & 0xF == 1;
+        let relative = match (a >> 16) & 0xF {
+            0 => Some(None),
+            1 => Some(Some(Base::Ba)),
+            2 => Some(Some(Base::Po)),
+            _ => None,
+        };
+        let unsupported = GeckoError::Unsupported { line, codetype };
+
+        // bit 0 of the codetype is part of the offset and bit 4 selects the base, except for
+        // terminators
+        let group = if codetype >= 0xE0 {
+            codetype
+        } else {
+            codetype & 0xEE
+        };
+
+        let op = match group {
+            0x00 => Op::Fill8 {
+                base,
+                offset,
+                value: b as u8,
+                count: (b >> 16) as u16,
+            },
+            0x02 => Op::Fill16 {
+                base,
+                offset,
+                value: b as u16,
+                count: (b >> 16) as u16,
+            },
+            0x04 => Op::Write32 {
+                base,
+                offset,
+                value: b,
+            },
+            0x06 => {
+                let len = b as usize;
+                let mut data = Vec::with_capacity(len.next_multiple_of(8));
+                while data.len() < len {
+                    let Some((_, x, y)) = lines.next() else {
+                        return Err(GeckoError::MissingData { line });
+                    };
+
+                    data.extend_from_slice(&x.to_be_bytes());
+                    data.extend_from_slice(&y.to_be_bytes());
+                }
+
+                data.truncate(len);
+                Op::WriteString { base, offset, data }
+            }
+            0x20 | 0x22 | 0x24 | 0x26 | 0x28 | 0x2A | 0x2C | 0x2E => {
+                let comparison = match (codetype >> 1) & 0b11 {
+                    0 => Comparison::Equal,
+                    1 => Comparison::NotEqual,
+                    2 => Comparison::Greater,
+                    _ => Comparison::Lower,
+                };
+                let endif = a & 1 != 0;
+                let offset = offset & !1;
+
+                if codetype & 0x08 == 0 {
+                    Op::If32 {
+                        endif,
+                        base,
+                        offset,
+                        comparison,
+                        value: b,
+                    }
+                } else {
+                    Op::If16 {
+                        endif,
+                        base,
+                        offset,
+                        comparison,
+                        mask: (b >> 16) as u16,
+                        value: b as u16,
+                    }
+                }
+            }
+            0x40 | 0x42 | 0x44 | 0x48 | 0x4A | 0x4C => {
+                // gecko registers are not supported
+                let Some(relative) = relative else {
+                    return Err(unsupported);
+                };
+                if codetype & 0x11 != 0 || a & 0xF00F != 0 {
+                    return Err(unsupported);
+                }
+
+                let target = if codetype & 0x08 == 0 {
+                    Base::Ba
+                } else {
+                    Base::Po
+                };
+
+                match codetype & 0x06 {
+                    0x00 => Op::Load {
+                        target,
+                        add,
+                        relative,
+                        address: b,
+                    },
+                    0x02 => Op::Set {
+                        target,
+                        add,
+                        relative,
+                        value: b,
+                    },
+                    _ => Op::Store {
+                        target,
+                        relative,
+                        address: b,
+                    },
+                }
+            }
+            0xE0 => Op::Terminator {
+                ba: (b >> 16) as u16,
+                po: b as u16,
+            },
+            0xE2 => Op::Endif {
+                count: a as u8,
+                invert: (a >> 20) & 0xF == 1,
+                ba: (b >> 16) as u16,
+                po: b as u16,
+            },
+            0xF0 => Op::End,
+            _ => return Err(unsupported),
+        };
+
+        ops.push(op);
+    }
+
+    Ok(ops)
+}
+
+/// Returns the offset into RAM of a `len` bytes long access to `addr`, which must be in one of
+/// the direct mapped regions (`0x8000_0000` or `0xC000_0000`).
+pub(crate) fn ram_offset(addr: u32, len: usize) -> Option<usize> {
+    if !matches!(addr >> 28, 0x8 | 0xC) {
+        return None;
+    }
+
+    let offset = (addr & 0x0FFF_FFFF) as usize;
+    (offset + len <= RAM_LEN).then_some(offset)
+}
+
+/// Writes `value` to `addr`, recording its cache line in `patched` if memory changed, since code
+/// might have been patched. Returns `false` if `addr` is outside of RAM.
+pub(crate) fn patch<P: Primitive>(
+    ram: &mut [u8],
+    patched: &mut Vec<Address>,
+    addr: u32,
+    value: P,
+) -> bool {
+    let Some(offset) = ram_offset(addr, size_of::<P>()) else {
+        return false;
+    };
+
+    let target = &mut ram[offset..][..size_of::<P>()];
+    let value = value.to_be();
+    if target == value.as_bytes() {
+        return true;
+    }
+
+    target.copy_from_slice(value.as_bytes());
+
+    let line = Address(addr).align_down_pow2(32);
+    if patched.last() != Some(&line) {
+        patched.push(line);
+    }
+
+    true
+}
+
+/// State of the execution of a code.
+struct Exec<'a> {
+    ram: &'a mut [u8],
+    patched: &'a mut Vec<Address>,
+    ba: u32,
+    po: u32,
+    /// Stack of conditions, one bit for each if. Operations only execute if all of them are
+    /// zero.
+    skip: u32,
+}
+
+impl Exec<'_> {
+    fn addr(&self, base: Base, offset: u32) -> u32 {
+        let base = match base {
+            Base::Ba => self.ba,
+            Base::Po => self.po,
+        };
+
+        base.wrapping_add(offset)
+    }
+
+    fn relative(&self, relative: Option<Base>, value: u32) -> u32 {
+        match relative {
+            Some(base) => self.addr(base, value),
+            None => value,
+        }
+    }
+
+    fn read<P: Primitive>(&self, addr: u32) -> Option<P> {
+        let offset = ram_offset(addr, size_of::<P>())?;
+        Some(P::read_be_bytes(&self.ram[offset..]))
+    }
+
+    fn write<P: Primitive>(&mut self, addr: u32, value: P) {
+        if !patch(self.ram, self.patched, addr, value) {
+            tracing::warn!("gecko code writing to {} outside of RAM", Address(addr));
+        }
+    }
+
+    fn set_bases(&mut self, ba: u16, po: u16) {
+        if ba != 0 {
+            self.ba = (ba as u32) << 16;
+        }
+
+        if po != 0 {
+            self.po = (po as u32) << 16;
+        }
+    }
+
+    fn run(&mut self, ops: &[Op]) {
+        for op in ops {
+            match *op {
+                Op::If32 {
+                    endif,
+                    base,
+                    offset,
+                    comparison,
+                    value,
+                } => {
+                    if endif {
+                        self.skip >>= 1;
+                    }
+
+                    let addr = self.addr(base, offset);
+                    let holds = self.skip == 0
+                        && self
+                            .read::<u32>(addr)
+                            .is_some_and(|current| comparison.eval(current, value));
+                    self.skip = (self.skip << 1) | !holds as u32;
+                }
+                Op::If16 {
+                    endif,
+                    base,
+                    offset,
+                    comparison,
+                    mask,
+                    value,
+                } => {
+                    if endif {
+                        self.skip >>= 1;
+                    }
+
+                    let addr = self.addr(base, offset);
+                    let holds = self.skip == 0
+                        && self
+                            .read::<u16>(addr)
+                            .is_some_and(|current| comparison.eval(current & !mask, value));
+                    self.skip = (self.skip << 1) | !holds as u32;
+                }
+                Op::Endif {
+                    count,
+                    invert,
+                    ba,
+                    po,
+                } => {
+                    self.skip = self.skip.checked_shr(count as u32).unwrap_or(0);
+                    if invert {
+                        self.skip ^= 1;
+                    }
+
+                    self.set_bases(ba, po);
+                }
+                Op::Terminator { ba, po } => {
+                    self.skip = 0;
+                    self.set_bases(ba, po);
+                }
+                Op::End => break,
+                _ if self.skip != 0 => (),
+                Op::Fill8 {
+                    base,
+                    offset,
+                    value,
+                    count,
+                } => {
+                    let addr = self.addr(base, offset);
+                    for i in 0..=count as u32 {
+                        self.write(addr.wrapping_add(i), value);
+                    }
+                }
+                Op::Fill16 {
+                    base,
+                    offset,
+                    value,
+                    count,
+                } => {
+                    let addr = self.addr(base, offset);
+                    for i in 0..=count as u32 {
+                        self.write(addr.wrapping_add(2 * i), value);
+                    }
+                }
+                Op::Write32 {
+                    base,
+                    offset,
+                    value,
+                } => {
+                    let addr = self.addr(base, offset);
+                    self.write(addr, value);
+                }
+                Op::WriteString {
+                    base,
+                    offset,
+                    ref data,
+                } => {
+                    let addr = self.addr(base, offset);
+                    for (i, byte) in data.iter().copied().enumerate() {
+                        self.write(addr.wrapping_add(i as u32), byte);
+                    }
+                }
+                Op::Load {
+                    target,
+                    add,
+                    relative,
+                    address,
+                } => {
+                    let addr = self.relative(relative, address);
+                    let value = self.read::<u32>(addr).unwrap_or_default();
+                    self.set(target, add, value);
+                }
+                Op::Set {
+                    target,
+                    add,
+                    relative,
+                    value,
+                } => {
+                    let value = self.relative(relative, value);
+                    self.set(target, add, value);
+                }
+                Op::Store {
+                    target,
+                    relative,
+                    address,
+                } => {
+                    let addr = self.relative(relative, address);
+                    let value = self.addr(target, 0);
+                    self.write(addr, value);
+                }
+            }
+        }
+    }
+
+    fn set(&mut self, target: Base, add: bool, value: u32) {
+        let target = match target {
+            Base::Ba => &mut self.ba,
+            Base::Po => &mut self.po,
+        };
+
+        *target = if add {
+            target.wrapping_add(value)
+        } else {
+            value
+        };
+    }
+}
+
+/// Applies Gecko codes to the system memory.
+#[derive(Debug, Default)]
+pub struct GeckoEngine {
+    codes: Vec<GeckoCode>,
+    /// Cache lines modified by codes since the last call to [`GeckoEngine::take_patched`].
+    patched: Vec<Address>,
+}
+
+impl GeckoEngine {
+    /// Parses codes in the usual text format: a line starting with `$` begins a new code with
+    /// the rest of the line as its name, followed by its lines. Empty lines, notes (starting with
+    /// `*`) and section headers (starting with `[`) are ignored.
+    pub fn load_codes(text: &str) -> Result<Vec<GeckoCode>, GeckoError> {
+        let mut codes = Vec::new();
+        let mut name = String::from("Unnamed");
+        let mut lines = Vec::new();
+
+        let mut finish =
+            |name: &mut String, lines: &mut Vec<(usize, u32, u32)>| -> Result<(), GeckoError> {
+                if !lines.is_empty() {
+                    codes.push(GeckoCode {
+                        name: std::mem::take(name),
+                        ops: decode(lines)?,
+                    });
+                    lines.clear();
+                }
+
+                Ok(())
+            };
+
+        for (i, text) in text.lines().enumerate() {
+            let text = text.trim();
+            if text.is_empty() || text.starts_with(['*', '[']) {
+                continue;
+            }
+
+            if let Some(rest) = text.strip_prefix('$') {
+                finish(&mut name, &mut lines)?;
+                name = rest.trim().to_owned();
+                continue;
+            }
+
+            let line = i + 1;
+            let (a, b) = parse_line(text, line)?;
+            lines.push((line, a, b));
+        }
+
+        finish(&mut name, &mut lines)?;
+        Ok(codes)
+    }
+
+    /// The codes being applied.
+    pub fn codes(&self) -> &[GeckoCode] {
+        &self.codes
+    }
+
+    /// Replaces the codes being applied.
+    pub fn set_codes(&mut self, codes: Vec<GeckoCode>) {
+        self.codes = codes;
+    }
+
+    /// Takes the addresses of the cache lines modified by codes, so that any code compiled from
+    /// them can be invalidated.
+    pub fn take_patched(&mut self) -> Vec<Address> {
+        std::mem::take(&mut self.patched)
+    }
+
+    /// Whether codes modified memory since the last call to [`GeckoEngine::take_patched`].
+    pub fn has_patched(&self) -> bool {
+        !self.patched.is_empty()
+    }
+
+    /// Runs every code once over `ram`. Each code starts with the default base address and
+    /// pointer.
+    fn run(&mut self, ram: &mut [u8]) {
+        for code in &self.codes {
+            Exec {
+                ram: &mut *ram,
+                patched: &mut self.patched,
+                ba: DEFAULT_BASE,
+                po: DEFAULT_BASE,
+                skip: 0,
+            }
+            .run(&code.ops);
+        }
+    }
+
+    /// Applies the codes. Runs every [`APPLY_INTERVAL`] cycles.
+    pub fn apply(sys: &mut System) {
+        sys.gecko.run(sys.mem.ram_mut());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{GeckoEngine, GeckoError};
+    use crate::system::mem::RAM_LEN;
+
+    fn run(text: &str, ram: &mut [u8]) -> GeckoEngine {
+        let mut engine = GeckoEngine::default();
+        engine.set_codes(GeckoEngine::load_codes(text).unwrap());
+        engine.run(ram);
+        engine
+    }
+
+    #[test]
+    fn parse() {
+        let codes = GeckoEngine::load_codes(
+            "[Gecko]\n$Infinite Lives [someone]\n04001234 00000063\n*a note\n\n$Other\n00000010 00000001\n",
+        )
+        .unwrap();
+        assert_eq!(codes.len(), 2);
+        assert_eq!(codes[0].name, "Infinite Lives [someone]");
+        assert_eq!(codes[1].name, "Other");
+
+        assert!(matches!(
+            GeckoEngine::load_codes("$Bad\n0400123 00000063"),
+            Err(GeckoError::InvalidLine { line: 2 })
+        ));
+        assert!(matches!(
+            GeckoEngine::load_codes("C2001234 00000001"),
+            Err(GeckoError::Unsupported {
+                line: 1,
+                codetype: 0xC2
+            })
+        ));
+        assert!(matches!(
+            GeckoEngine::load_codes("06000000 00000009\n01020304 05060708"),
+            Err(GeckoError::MissingData { line: 1 })
+        ));
+    }
+
+    #[test]
+    fn writes() {
+        let mut ram = vec![0; RAM_LEN];
+        let mut engine = run(
+            "00000010 00020011\n02000020 00012233\n04000030 DEADBEEF\n06000040 00000003\n41424344 00000000\n",
+            &mut ram,
+        );
+
+        assert_eq!(ram[0x10..0x14], [0x11, 0x11, 0x11, 0]);
+        assert_eq!(ram[0x20..0x24], [0x22, 0x33, 0x22, 0x33]);
+        assert_eq!(ram[0x30..0x34], [0xDE, 0xAD, 0xBE, 0xEF]);
+        assert_eq!(ram[0x40..0x44], *b"ABC\0");
+        assert!(engine.has_patched());
+        assert_eq!(engine.take_patched().len(), 3);
+
+        // nothing changes the second time
+        engine.run(&mut ram);
+        assert!(!engine.has_patched());
+    }
+
+    #[test]
+    fn conditions() {
+        let mut ram = vec![0; RAM_LEN];
+        ram[0x100..0x104].copy_from_slice(&5u32.to_be_bytes());
+
+        run(
+            "\
+            20000100 00000005\n\
+            04000000 00000001\n\
+            E2100000 00000000\n\
+            04000004 00000001\n\
+            E0000000 00000000\n\
+            22000100 00000005\n\
+            04000008 00000001\n\
+            E2000001 00000000\n\
+            0400000C 00000001\n",
+            &mut ram,
+        );
+
+        assert_eq!(ram[0x00..0x04], 1u32.to_be_bytes(), "if");
+        assert_eq!(ram[0x04..0x08], 0u32.to_be_bytes(), "else");
+        assert_eq!(ram[0x08..0x0C], 0u32.to_be_bytes(), "false if");
+        assert_eq!(ram[0x0C..0x10], 1u32.to_be_bytes(), "after endif");
+    }
+
+    #[test]
+    fn pointers() {
+        let mut ram = vec![0; RAM_LEN];
+        ram[0x100..0x104].copy_from_slice(&0x8000_0200u32.to_be_bytes());
+
+        run(
+            "48000000 80000100\n14000010 CAFEBABE\n4C000000 80000300\n",
+            &mut ram,
+        );
+
+        assert_eq!(ram[0x210..0x214], 0xCAFE_BABEu32.to_be_bytes());
+        assert_eq!(ram[0x300..0x304], 0x8000_0200u32.to_be_bytes());
+    }
+}
@@ -1,27 +1,28 @@
 //! Graphics subsystem (GX).
 pub mod cmd;
+pub mod efb;
+pub mod perf;
 pub mod pix;
 pub mod tev;
 pub mod tex;
+pub mod trace;
 pub mod xform;
 
-use std::num::NonZero;
-use std::sync::{LazyLock, Mutex};
+use std::sync::Mutex;
 
 use bitos::integer::{UnsignedInt, u3, u4};
 use bitos::{BitUtils, TryBits, bitos};
 use bitvec::array::BitArray;
-use color::{Rgba, Rgba8};
+use color::{Rgba, Rgba16};
 use gekko::Address;
 use glam::{Mat4, Vec2, Vec3};
-use ring_arena::{Handle, RingArena};
 use seq_macro::seq;
 use strum::FromRepr;
 use zerocopy::IntoBytes;
 
 use crate::modules::{render, vertex};
 use crate::system::gx::cmd::VertexAttributeStream;
-use crate::system::pi;
+use crate::system::scheduler::{INTERRUPT_PRIORITY, SchedulerEventKind};
 use crate::{Primitive, System};
 
 #[rustfmt::skip]
@@ -433,6 +434,39 @@ impl GenMode {
     }
 }
 
+/// Size of line and point primitives.
+#[bitos(32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LinePointSize {
+    /// Width of lines, in sixths of a pixel.
+    #[bits(0..8)]
+    pub line_width: u8,
+    /// Width and height of points, in sixths of a pixel.
+    #[bits(8..16)]
+    pub point_size: u8,
+    /// Texture coordinate offset applied across the width of lines.
+    #[bits(16..19)]
+    pub line_tex_offset: u3,
+    /// Texture coordinate offset applied across the size of points.
+    #[bits(19..22)]
+    pub point_tex_offset: u3,
+    /// Whether line widths are halved vertically, for rendering a single field.
+    #[bits(22)]
+    pub field_aspect: bool,
+}
+
+impl LinePointSize {
+    /// Width of lines, in pixels.
+    pub fn line_width_px(&self) -> f32 {
+        self.line_width() as f32 / 6.0
+    }
+
+    /// Size of points, in pixels.
+    pub fn point_size_px(&self) -> f32 {
+        self.point_size() as f32 / 6.0
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 pub struct MatrixId(u8);
 
@@ -502,23 +536,86 @@ pub struct Vertex {
     pub tex_coords_matrix: [MatrixId; 8],
 }
 
+/// A free list of buffers which are handed back once the value using them is dropped.
+struct BufferPool<T> {
+    free: Mutex<Vec<Vec<T>>>,
+}
+
+impl<T> BufferPool<T> {
+    /// Maximum number of free buffers kept around. Any extra buffer returned to the pool is
+    /// simply deallocated.
+    const MAX_FREE: usize = 256;
+
+    const fn new() -> Self {
+        Self {
+            free: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Takes an empty buffer from the pool with at least `capacity` elements of capacity.
+    fn take(&'static self, capacity: usize) -> PooledBuffer<T> {
+        let mut buffer = self.free.lock().unwrap().pop().unwrap_or_default();
+        buffer.reserve(capacity);
+
+        PooledBuffer { buffer, pool: self }
+    }
+
+    fn give_back(&self, mut buffer: Vec<T>) {
+        buffer.clear();
+
+        let mut free = self.free.lock().unwrap();
+        if free.len() < Self::MAX_FREE {
+            free.push(buffer);
+        }
+    }
+}
+
+/// A buffer owned by a single value which returns to its [`BufferPool`] when dropped.
+struct PooledBuffer<T: 'static> {
+    buffer: Vec<T>,
+    pool: &'static BufferPool<T>,
+}
+
+impl<T> Drop for PooledBuffer<T> {
+    fn drop(&mut self) {
+        self.pool.give_back(std::mem::take(&mut self.buffer));
+    }
+}
+
+static VERTEX_POOL: BufferPool<Vertex> = BufferPool::new();
+static MATRIX_POOL: BufferPool<(MatrixId, Mat4)> = BufferPool::new();
+
 /// A stream of [`Vertex`] elements and their associated matrices.
+///
+/// The stream owns its buffers, so it can safely outlive the draw that created it (e.g. while
+/// queued for the render thread). Buffers are recycled once the stream is dropped.
 pub struct VertexStream {
-    vertices: Handle<Vertex>,
-    matrices: Handle<(MatrixId, Mat4)>,
+    vertices: PooledBuffer<Vertex>,
+    matrices: PooledBuffer<(MatrixId, Mat4)>,
 }
 
 impl VertexStream {
+    /// Builds a stream out of the given vertices and matrices, e.g. for feeding a renderer
+    /// directly.
+    pub fn new(vertices: impl IntoIterator<Item = Vertex>, matrices: &[(MatrixId, Mat4)]) -> Self {
+        let mut pooled_vertices = VERTEX_POOL.take(0);
+        pooled_vertices.buffer.extend(vertices);
+
+        let mut pooled_matrices = MATRIX_POOL.take(matrices.len());
+        pooled_matrices.buffer.extend_from_slice(matrices);
+
+        Self {
+            vertices: pooled_vertices,
+            matrices: pooled_matrices,
+        }
+    }
+
     pub fn vertices(&self) -> &[Vertex] {
-        // SAFETY: this struct is only created inside `extract_vertices`, which mantains
-        // a static arena
-        unsafe { self.vertices.as_slice().assume_init_ref() }
+        &self.vertices.buffer
     }
 
     pub fn matrices(&self) -> &[(MatrixId, Mat4)] {
-        // SAFETY: this struct is only created inside `extract_vertices`, which mantains
-        // a static arena
-        unsafe { self.matrices.as_slice().assume_init_ref() }
+        &self.matrices.buffer
     }
 }
 
@@ -562,28 +659,36 @@ pub struct XfbCopy {
 
 pub struct Gpu {
     pub mode: GenMode,
+    pub line_point_size: LinePointSize,
     pub cmd: cmd::Interface,
     pub xform: xform::Interface,
     pub env: tev::Interface,
     pub tex: tex::Interface,
     pub pix: pix::Interface,
+    pub efb: efb::Shadow,
+    pub perf: perf::Interface,
     pub write_mask: u32,
     pub xfb_copies: Vec<XfbCopy>,
     matrix_set: Box<MatrixSet>,
+    pub(crate) trace: Option<trace::RegisterTrace>,
 }
 
 impl Default for Gpu {
     fn default() -> Self {
         Self {
             mode: Default::default(),
+            line_point_size: Default::default(),
             cmd: Default::default(),
             xform: Default::default(),
             env: Default::default(),
             tex: Default::default(),
             pix: Default::default(),
+            efb: Default::default(),
+            perf: Default::default(),
             write_mask: 0x00FF_FFFF,
             matrix_set: Box::default(),
             xfb_copies: Vec::with_capacity(4),
+            trace: None,
         }
     }
 }
@@ -623,12 +728,7 @@ pub fn update_texenv(sys: &mut System) {
 
     let registers = render::TexEnvRegisters {
         regs: sys.gpu.env.regs,
-        constants: sys.gpu.env.consts.map(|x| Rgba8 {
-            r: x.r as u8,
-            g: x.g as u8,
-            b: x.b as u8,
-            a: x.a as u8,
-        }),
+        constants: sys.gpu.env.consts.map(Rgba16::wrap),
     };
 
     sys.modules
@@ -641,6 +741,18 @@ pub fn update_texenv(sys: &mut System) {
 }
 
 pub fn set_register(sys: &mut System, reg: Reg, value: u32) {
+    if sys.gpu.trace.is_none() {
+        write_register(sys, reg, value);
+        return;
+    }
+
+    let mask = sys.gpu.write_mask;
+    trace::begin(sys);
+    write_register(sys, reg, value);
+    trace::record(sys, reg, value, mask);
+}
+
+fn write_register(sys: &mut System, reg: Reg, value: u32) {
     let mask = std::mem::replace(&mut sys.gpu.write_mask, 0x00FF_FFFF);
     let masked = value & mask;
 
@@ -705,6 +817,13 @@ pub fn set_register(sys: &mut System, reg: Reg, value: u32) {
                 .exec(render::Action::SetCullingMode(sys.gpu.mode.culling_mode()));
         }
 
+        Reg::SetupLpSize => {
+            write_masked!(sys.gpu.line_point_size);
+            sys.modules
+                .render
+                .exec(render::Action::SetLinePointSize(sys.gpu.line_point_size));
+        }
+
         Reg::ScissorTopLeft => write_masked!(sys.gpu.pix.scissor.top_left),
         Reg::ScissorBottomRight => write_masked!(sys.gpu.pix.scissor.bottom_right),
         Reg::ScissorOffset => write_masked!(sys.gpu.pix.scissor.offset),
@@ -760,13 +879,15 @@ pub fn set_register(sys: &mut System, reg: Reg, value: u32) {
         }
         Reg::PixelDone => {
             sys.gpu.pix.interrupt.set_finish(true);
-            sys.scheduler.schedule_now(pi::check_interrupts);
+            sys.scheduler
+                .schedule_now(INTERRUPT_PRIORITY, SchedulerEventKind::CheckInterrupts);
         }
         Reg::PixelToken => write_masked!(0xFFFF; sys.gpu.pix.token),
         Reg::PixelTokenInt => {
             write_masked!(0xFFFF; sys.gpu.pix.token);
             sys.gpu.pix.interrupt.set_token(true);
-            sys.scheduler.schedule_now(pi::check_interrupts);
+            sys.scheduler
+                .schedule_now(INTERRUPT_PRIORITY, SchedulerEventKind::CheckInterrupts);
         }
         Reg::PixelCopySrc => write_masked!(sys.gpu.pix.copy.src),
         Reg::PixelCopyDimensions => write_masked!(sys.gpu.pix.copy.dims),
@@ -798,6 +919,8 @@ pub fn set_register(sys: &mut System, reg: Reg, value: u32) {
                 sys.gpu.pix.copy.clear_depth as f32 / DEPTH_24_BIT_MAX as f32,
             ));
         }
+        Reg::PixelXBound => sys.gpu.pix.bbox.write_x(masked),
+        Reg::PixelYBound => sys.gpu.pix.bbox.write_y(masked),
         Reg::PixelCopyCmd => {
             // TODO: proper masked
             let cmd = pix::CopyCmd::from_bits(value);
@@ -975,6 +1098,11 @@ pub fn set_register(sys: &mut System, reg: Reg, value: u32) {
         Reg::TevConstSelect6 => write_masked!(sys.gpu.env.stage_consts[6]),
         Reg::TevConstSelect7 => write_masked!(sys.gpu.env.stage_consts[7]),
 
+        Reg::SetupPerf => sys.gpu.perf.gp0.select(perf::Metric::Triangles, masked),
+        Reg::RasterPerf => sys.gpu.perf.gp0.select(perf::Metric::Pixels, masked),
+        Reg::PixelPerfMode => sys.gpu.perf.gp1.select(perf::Metric::Pixels, masked),
+        Reg::TexPerfMode => sys.gpu.perf.gp1.select(perf::Metric::TextureMisses, masked),
+
         Reg::WriteMask => {
             sys.gpu.write_mask = value;
         }
@@ -1010,31 +1138,9 @@ pub fn set_register(sys: &mut System, reg: Reg, value: u32) {
     }
 }
 
-#[inline]
-fn alloc_vertices_handle(length: usize) -> Handle<Vertex> {
-    const CHUNK_SIZE: usize = bytesize::MIB as usize;
-    const CHUNK_CAPACITY: NonZero<usize> = NonZero::new(CHUNK_SIZE / size_of::<Vertex>()).unwrap();
-
-    static ARENA: LazyLock<Mutex<RingArena<Vertex>>> =
-        LazyLock::new(|| Mutex::new(RingArena::new(CHUNK_CAPACITY)));
-
-    ARENA.lock().unwrap().allocate(length)
-}
-
-#[inline]
-fn alloc_matrices_handle(length: usize) -> Handle<(MatrixId, Mat4)> {
-    const CHUNK_SIZE: usize = 2 * bytesize::MIB as usize;
-    const CHUNK_CAPACITY: NonZero<usize> = NonZero::new(CHUNK_SIZE / size_of::<Mat4>()).unwrap();
-
-    static ARENA: LazyLock<Mutex<RingArena<(MatrixId, Mat4)>>> =
-        LazyLock::new(|| Mutex::new(RingArena::new(CHUNK_CAPACITY)));
-
-    ARENA.lock().unwrap().allocate(length)
-}
-
 fn extract_vertices(sys: &mut System, stream: &VertexAttributeStream) -> VertexStream {
-    let mut vertices = alloc_vertices_handle(stream.count() as usize);
-    let vertices_slice = unsafe { vertices.as_mut_slice() };
+    let count = stream.count() as usize;
+    let mut vertices = VERTEX_POOL.take(count);
 
     sys.gpu.matrix_set.clear();
 
@@ -1053,26 +1159,49 @@ fn extract_vertices(sys: &mut System, stream: &VertexAttributeStream) -> VertexS
         vcd,
         vat,
         stream,
-        vertices_slice,
+        &mut vertices.buffer.spare_capacity_mut()[..count],
         &mut sys.gpu.matrix_set,
     );
 
-    let mut matrices = alloc_matrices_handle(sys.gpu.matrix_set.len());
-    let matrices_slice = unsafe { matrices.as_mut_slice() };
+    // SAFETY: the vertex module initializes every vertex in the slice it's given
+    unsafe { vertices.buffer.set_len(count) };
 
-    for (i, mat_id) in sys.gpu.matrix_set.iter().enumerate() {
+    let mut matrices = MATRIX_POOL.take(sys.gpu.matrix_set.len());
+    for mat_id in sys.gpu.matrix_set.iter() {
         let mat = if mat_id.is_normal() {
             Mat4::from_mat3(sys.gpu.xform.normal_matrix(mat_id.index()))
         } else {
             sys.gpu.xform.matrix(mat_id.index())
         };
 
-        matrices_slice[i].write((mat_id, mat));
+        matrices.buffer.push((mat_id, mat));
     }
 
     VertexStream { vertices, matrices }
 }
 
+/// Extends the PE bounding box with the screen positions of the given vertices.
+///
+/// This is conservative: the box covers the vertices themselves rather than the pixels actually
+/// rasterized, so clipped or culled primitives still count.
+fn update_bbox(sys: &mut System, stream: &VertexStream) {
+    let projection = sys.gpu.xform.projection_matrix();
+    let viewport = &sys.gpu.xform.internal.viewport;
+
+    for vertex in stream.vertices() {
+        let view = sys.gpu.xform.matrix(vertex.pos_norm_matrix.index());
+        let clip = projection * view * vertex.position.extend(1.0);
+        if clip.w <= 0.0 {
+            continue;
+        }
+
+        let ndc = clip.truncate() / clip.w;
+        let x = viewport.center_x + ndc.x * viewport.width / 2.0;
+        let y = viewport.center_y - ndc.y * viewport.height / 2.0;
+        sys.gpu.pix.bbox.include(x, y);
+    }
+}
+
 fn draw(sys: &mut System, topology: Topology, stream: &VertexAttributeStream) {
     if std::mem::take(&mut sys.gpu.xform.internal.viewport_dirty) {
         let viewport = &sys.gpu.xform.internal.viewport;
@@ -1105,6 +1234,15 @@ fn draw(sys: &mut System, topology: Topology, stream: &VertexAttributeStream) {
     }
 
     let vertices = self::extract_vertices(sys, stream);
+    if sys.gpu.pix.bbox.active {
+        self::update_bbox(sys, &vertices);
+    }
+
+    if sys.gpu.perf.is_active() {
+        perf::count_draw(sys, topology, &vertices);
+    }
+
+    sys.gpu.efb.invalidate();
     sys.modules
         .render
         .exec(render::Action::Draw(topology, vertices));
@@ -1127,6 +1265,10 @@ fn efb_copy(sys: &mut System, cmd: pix::CopyCmd) {
         clear: cmd.clear(),
     };
 
+    if args.clear {
+        sys.gpu.efb.invalidate();
+    }
+
     let divisor = if args.half { 2 } else { 1 };
     let width = args.dims.width() as u32 / divisor;
     let height = args.dims.height() as u32 / divisor;
@@ -1141,67 +1283,165 @@ fn efb_copy(sys: &mut System, cmd: pix::CopyCmd) {
             .render
             .exec(render::Action::CopyXfb { args, id });
 
+        // once per frame is often enough to catch up with evictions
+        tex::release_evicted(sys);
+
         return;
     }
 
     let id = render::TextureId(dst.value());
-    let format = if sys.gpu.pix.control.format().is_depth() {
-        let (sender, receiver) = if sys.config.perform_efb_copies {
-            let (sender, receiver) = oneshot::channel();
-            (Some(sender), Some(receiver))
-        } else {
-            (None, None)
-        };
+    let is_depth = sys.gpu.pix.control.format().is_depth();
+    let format = if is_depth {
+        cmd.depth_format().texture_format()
+    } else {
+        cmd.color_format().texture_format()
+    };
+
+    let (sender, receiver) = if sys.config.perform_efb_copies {
+        let (sender, receiver) = oneshot::channel();
+        (Some(sender), Some(receiver))
+    } else {
+        (None, None)
+    };
 
+    if is_depth {
         sys.modules.render.exec(render::Action::CopyDepth {
             args,
             format: cmd.depth_format(),
             response: sender,
             id,
         });
-
-        if let Some(receiver) = receiver {
-            let Ok(texels) = receiver.recv() else {
-                tracing::error!("render module did not answer depth copy request");
-                return;
-            };
-
-            let output = &mut sys.mem.ram_mut()[dst.value() as usize..];
-            tex::encode_depth_texture(texels, cmd.depth_format(), stride, width, height, output);
-        }
-
-        cmd.depth_format().texture_format()
     } else {
-        let (sender, receiver) = if sys.config.perform_efb_copies {
-            let (sender, receiver) = oneshot::channel();
-            (Some(sender), Some(receiver))
-        } else {
-            (None, None)
-        };
-
         sys.modules.render.exec(render::Action::CopyColor {
             args,
             format: cmd.color_format(),
             response: sender,
             id,
         });
+    }
 
-        if let Some(receiver) = receiver {
-            let Ok(texels) = receiver.recv() else {
-                tracing::error!("render module did not answer color copy request");
-                return;
-            };
+    let start = dst.value() as usize;
+    if let Some(receiver) = receiver {
+        let Ok(texels) = receiver.recv() else {
+            tracing::error!("render module did not answer copy request");
+            return;
+        };
 
-            let output = &mut sys.mem.ram_mut()[dst.value() as usize..];
-            tex::encode_color_texture(texels, cmd.color_format(), stride, width, height, output);
+        // rows of tiles are `stride` apart, so the memory in between them has to be preserved
+        let copy_len = tex::Encoding::copy_length_for(stride, width, height, format) as usize;
+        let mut output = vec![0; copy_len];
+        if let Err(fault) = sys.read_phys_block(dst, &mut output) {
+            tracing::warn!("copy destination {dst} is not writable: {fault}");
+            return;
         }
 
-        cmd.color_format().texture_format()
+        if is_depth {
+            tex::encode_depth_texture(
+                texels,
+                cmd.depth_format(),
+                stride,
+                width,
+                height,
+                &mut output,
+            );
+        } else {
+            tex::encode_color_texture(
+                texels,
+                cmd.color_format(),
+                stride,
+                width,
+                height,
+                &mut output,
+            );
+        }
+
+        // the destination is a physical address, written like any other DMA so watches see it
+        if let Err(fault) = sys.write_phys_block(dst, &output) {
+            tracing::warn!("copy to {dst} failed: {fault}");
+            return;
+        }
+    }
+
+    // the render module keeps the copy as a texture, which stays valid as long as the guest does
+    // not modify the memory it was copied to
+    let len = tex::Encoding::length_for(width, height, format) as usize;
+    let Some(data) = sys.mem.ram().get(start..start + len) else {
+        tracing::warn!("copy destination {dst} is outside of RAM");
+        return;
     };
 
-    if !sys.config.perform_efb_copies {
-        let len = tex::Encoding::length_for(width, height, format) as usize;
-        let data = &sys.mem.ram()[dst.value() as usize..][..len];
-        sys.gpu.tex.update_tex_hash(dst, data);
+    sys.gpu.tex.update_tex_hash(dst, data);
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use glam::{Mat4, Vec3};
+
+    use super::{MATRIX_POOL, MatrixId, VERTEX_POOL, Vertex, VertexStream};
+
+    fn vertex(seed: usize, index: usize) -> Vertex {
+        Vertex {
+            position: Vec3::new(seed as f32, index as f32, 1.0),
+            pos_norm_matrix: MatrixId((seed % 64) as u8),
+            ..Default::default()
+        }
+    }
+
+    fn matrix(seed: usize) -> (MatrixId, Mat4) {
+        (
+            MatrixId((seed % 64) as u8),
+            Mat4::from_translation(Vec3::splat(seed as f32)),
+        )
+    }
+
+    fn stream(seed: usize) -> VertexStream {
+        let len = 1 + seed % 97;
+
+        let mut vertices = VERTEX_POOL.take(len);
+        vertices.buffer.extend((0..len).map(|i| vertex(seed, i)));
+
+        let mut matrices = MATRIX_POOL.take(1);
+        matrices.buffer.push(matrix(seed));
+
+        VertexStream { vertices, matrices }
+    }
+
+    #[test]
+    fn streams_outlive_producer() {
+        const DRAWS: usize = 4096;
+
+        let (sender, receiver) = mpsc::sync_channel::<(usize, VertexStream)>(64);
+        let consumer = std::thread::spawn(move || {
+            // stall the consumer so the producer fills the queue and keeps recycling buffers
+            std::thread::sleep(Duration::from_millis(50));
+
+            let mut received = 0;
+            for (seed, stream) in receiver {
+                if seed % 512 == 0 {
+                    std::thread::sleep(Duration::from_millis(5));
+                }
+
+                let len = 1 + seed % 97;
+                assert_eq!(stream.vertices().len(), len);
+                for (i, v) in stream.vertices().iter().enumerate() {
+                    assert_eq!(*v, vertex(seed, i));
+                }
+                assert_eq!(stream.matrices(), &[matrix(seed)]);
+
+                received += 1;
+            }
+
+            received
+        });
+
+        for seed in 0..DRAWS {
+            sender.send((seed, stream(seed))).unwrap();
+        }
+        drop(sender);
+
+        assert_eq!(consumer.join().unwrap(), DRAWS);
     }
 }
@@ -1,6 +1,8 @@
 //! Command processor (CP).
 pub mod attributes;
 
+use std::time::Instant;
+
 use attributes::VertexAttributeTable;
 use bitos::integer::u3;
 use bitos::{BitUtils, bitos};
@@ -362,6 +364,10 @@ pub struct VertexAttributeStream {
 }
 
 impl VertexAttributeStream {
+    pub fn new(table: u8, count: u16, data: Vec<u8>) -> Self {
+        Self { table, count, data }
+    }
+
     pub fn table_index(&self) -> usize {
         self.table as usize
     }
@@ -668,8 +674,13 @@ pub fn consume(sys: &mut System) {
     }
 }
 
-/// Process consumed CP commands until the queue is either empty or incomplete.
+/// Interval, in CPU cycles, at which [`process`] runs.
+pub const PROCESS_INTERVAL: u64 = 1 << 16;
+
+/// Process consumed CP commands until the queue is either empty or incomplete. Runs periodically
+/// as a repeating event.
 pub fn process(sys: &mut System) {
+    let start = Instant::now();
     let current_token = sys.gpu.pix.token;
     loop {
         let draw_done = sys.gpu.pix.interrupt.finish();
@@ -745,7 +756,7 @@ pub fn process(sys: &mut System) {
         }
     }
 
-    sys.scheduler.schedule(1 << 16, self::process);
+    sys.timing.add_gx(start.elapsed());
 }
 
 /// Synchronizes the CP fifo to the PI fifo.
@@ -0,0 +1,263 @@
+//! CPU access to the embedded framebuffer (EFB).
+//!
+//! The EFB is mapped at physical address [`EFB_BASE`]. Bits 2..12 of an offset into it select the
+//! x coordinate of the accessed pixel, bits 12..22 its y coordinate and bits 22..24 whether the
+//! color or the depth buffer is accessed.
+//!
+//! Reading pixels back from the renderer for every access would stall it constantly, so peeks are
+//! served from a [`Shadow`] of the EFB which is only read back when something was rendered since
+//! the last peek.
+use color::Rgba;
+
+use crate::modules::render::{self, EfbBuffer, Texels};
+use crate::system::System;
+use crate::system::gx::EFB_WIDTH;
+use crate::system::gx::pix::BufferFormat;
+
+/// Physical address the EFB is mapped at.
+pub const EFB_BASE: u32 = 0x0800_0000;
+/// Length of the EFB mapping.
+pub const EFB_LEN: u32 = 0x0100_0000;
+
+const MAX_Z24: u32 = 0x00FF_FFFF;
+
+/// An EFB access, decoded from its offset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Access {
+    pub buffer: EfbBuffer,
+    pub x: u16,
+    pub y: u16,
+}
+
+impl Access {
+    /// Decodes the access to the given offset into the EFB mapping. Returns `None` if it targets
+    /// neither the color nor the depth buffer.
+    pub fn decode(offset: u32) -> Option<Self> {
+        let buffer = match (offset >> 22) & 0b11 {
+            0 => EfbBuffer::Color,
+            1 => EfbBuffer::Depth,
+            _ => return None,
+        };
+
+        Some(Self {
+            buffer,
+            x: ((offset >> 2) & 0x3FF) as u16,
+            y: ((offset >> 12) & 0x3FF) as u16,
+        })
+    }
+
+    fn index(self) -> usize {
+        self.y as usize * EFB_WIDTH as usize + self.x as usize
+    }
+}
+
+/// A copy of the EFB contents, as last read back from the renderer.
+#[derive(Debug, Default)]
+pub struct Shadow {
+    color: Option<Texels>,
+    depth: Option<Texels>,
+}
+
+impl Shadow {
+    /// Marks the shadow as outdated, so that it's read back from the renderer on the next peek.
+    /// Must be called whenever the renderer modifies the EFB.
+    pub fn invalidate(&mut self) {
+        self.color = None;
+        self.depth = None;
+    }
+
+    fn buffer_mut(&mut self, buffer: EfbBuffer) -> &mut Option<Texels> {
+        match buffer {
+            EfbBuffer::Color => &mut self.color,
+            EfbBuffer::Depth => &mut self.depth,
+        }
+    }
+}
+
+/// Expands a channel of `bits` bits to 8 bits.
+fn expand(value: u32, bits: u32) -> u32 {
+    (value << (8 - bits)) | (value >> (2 * bits - 8))
+}
+
+/// Converts a texel of the color buffer (RGBA8, red in the lowest byte) to the ARGB8 value a peek
+/// returns in the given format.
+pub fn color_peek_value(texel: u32, format: BufferFormat) -> u32 {
+    let [r, g, b, a] = texel.to_le_bytes().map(u32::from);
+    let (r, g, b, a) = match format {
+        BufferFormat::RGBA6Z24 => (
+            expand(r >> 2, 6),
+            expand(g >> 2, 6),
+            expand(b >> 2, 6),
+            expand(a >> 2, 6),
+        ),
+        BufferFormat::RGB565Z16 => (
+            expand(r >> 3, 5),
+            expand(g >> 2, 6),
+            expand(b >> 3, 5),
+            0xFF,
+        ),
+        _ => (r, g, b, 0xFF),
+    };
+
+    (a << 24) | (r << 16) | (g << 8) | b
+}
+
+/// Converts a texel of the depth buffer (a F32) to the 24-bit value a peek returns in the given
+/// format.
+pub fn depth_peek_value(texel: u32, format: BufferFormat) -> u32 {
+    let z24 = (f32::from_bits(texel).clamp(0.0, 1.0) * MAX_Z24 as f32).round() as u32;
+    match format {
+        BufferFormat::RGB565Z16 => {
+            let z16 = z24 >> 8;
+            (z16 << 8) | (z16 >> 8)
+        }
+        _ => z24,
+    }
+}
+
+/// Returns the shadow of the given buffer, reading it back from the renderer if outdated.
+fn shadow(sys: &mut System, buffer: EfbBuffer) -> Option<&Texels> {
+    let shadow = sys.gpu.efb.buffer_mut(buffer);
+    if shadow.is_none() {
+        let (sender, receiver) = render::oneshot::channel();
+        sys.modules.render.exec(render::Action::ReadEfb {
+            buffer,
+            response: sender,
+        });
+
+        let Ok(texels) = receiver.recv() else {
+            tracing::error!("render module did not answer EFB read request");
+            return None;
+        };
+
+        *shadow = Some(texels);
+    }
+
+    shadow.as_ref()
+}
+
+/// Reads the pixel at the given offset into the EFB mapping.
+pub fn peek(sys: &mut System, offset: u32) -> u32 {
+    let Some(access) = Access::decode(offset) else {
+        tracing::warn!(pc = ?sys.cpu.pc, "reading from EFB offset 0x{offset:06X} (unknown buffer)");
+        return 0;
+    };
+
+    let format = sys.gpu.pix.control.format();
+    let Some(&texel) = shadow(sys, access.buffer).and_then(|t| t.get(access.index())) else {
+        return 0;
+    };
+
+    match access.buffer {
+        EfbBuffer::Color => self::color_peek_value(texel, format),
+        EfbBuffer::Depth => self::depth_peek_value(texel, format),
+    }
+}
+
+/// Writes the pixel at the given offset into the EFB mapping.
+pub fn poke(sys: &mut System, offset: u32, value: u32) {
+    let Some(access) = Access::decode(offset) else {
+        tracing::warn!(pc = ?sys.cpu.pc, "writing 0x{value:08X} to EFB offset 0x{offset:06X} (unknown buffer)");
+        return;
+    };
+
+    let (texel, action) = match access.buffer {
+        EfbBuffer::Color => {
+            let [a, r, g, b] = value.to_be_bytes();
+            let color = Rgba::new(
+                r as f32 / 255.0,
+                g as f32 / 255.0,
+                b as f32 / 255.0,
+                a as f32 / 255.0,
+            );
+
+            (
+                u32::from_le_bytes([r, g, b, a]),
+                render::Action::PokeColor {
+                    x: access.x,
+                    y: access.y,
+                    color,
+                },
+            )
+        }
+        EfbBuffer::Depth => {
+            let depth = (value & MAX_Z24) as f32 / MAX_Z24 as f32;
+            (
+                depth.to_bits(),
+                render::Action::PokeDepth {
+                    x: access.x,
+                    y: access.y,
+                    depth,
+                },
+            )
+        }
+    };
+
+    // keep the shadow up to date instead of reading it back again
+    if let Some(shadow) = sys.gpu.efb.buffer_mut(access.buffer)
+        && let Some(current) = shadow.get_mut(access.index())
+    {
+        *current = texel;
+    }
+
+    sys.modules.render.exec(action);
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Access, EfbBuffer, color_peek_value, depth_peek_value};
+    use crate::system::gx::pix::BufferFormat;
+
+    #[test]
+    fn decode() {
+        // GXPeekARGB(12, 34)
+        let access = Access::decode((34 << 12) | (12 << 2)).unwrap();
+        assert_eq!(access.buffer, EfbBuffer::Color);
+        assert_eq!((access.x, access.y), (12, 34));
+
+        // GXPeekZ(639, 527)
+        let access = Access::decode(0x0040_0000 | (527 << 12) | (639 << 2)).unwrap();
+        assert_eq!(access.buffer, EfbBuffer::Depth);
+        assert_eq!((access.x, access.y), (639, 527));
+
+        assert!(Access::decode(0x0080_0000).is_none());
+    }
+
+    #[test]
+    fn color_formats() {
+        let texel = u32::from_le_bytes([0x12, 0x34, 0x56, 0x78]);
+        assert_eq!(color_peek_value(texel, BufferFormat::RGB8Z24), 0xFF12_3456);
+        assert_eq!(color_peek_value(texel, BufferFormat::RGBA6Z24), 0x7910_3455);
+        assert_eq!(
+            color_peek_value(texel, BufferFormat::RGB565Z16),
+            0xFF10_3452
+        );
+
+        // full intensity is kept by every format
+        let white = u32::MAX;
+        for format in [
+            BufferFormat::RGB8Z24,
+            BufferFormat::RGBA6Z24,
+            BufferFormat::RGB565Z16,
+        ] {
+            assert_eq!(color_peek_value(white, format), 0xFFFF_FFFF);
+        }
+    }
+
+    #[test]
+    fn depth_formats() {
+        assert_eq!(depth_peek_value(0.0f32.to_bits(), BufferFormat::RGB8Z24), 0);
+        assert_eq!(
+            depth_peek_value(1.0f32.to_bits(), BufferFormat::RGB8Z24),
+            0x00FF_FFFF
+        );
+        assert_eq!(
+            depth_peek_value(1.0f32.to_bits(), BufferFormat::RGB565Z16),
+            0x00FF_FFFF
+        );
+        assert_eq!(
+            depth_peek_value(0.5f32.to_bits(), BufferFormat::RGB565Z16),
+            0x0080_0080
+        );
+    }
+}
@@ -0,0 +1,257 @@
+//! GX performance counters.
+//!
+//! Games profile their rendering by selecting a metric for each of the two GP metric counters
+//! through the performance registers and reading the counters back from the command processor,
+//! e.g. with `GXReadGP0Metric` and `GXReadGP1Metric`. The hardware offers dozens of metrics, of
+//! which only the following are emulated:
+//!
+//! | Counter | Selected by                                  | Metric                       |
+//! |---------|----------------------------------------------|------------------------------|
+//! | GP0     | [`SetupPerf`](super::Reg::SetupPerf)         | [`Metric::Triangles`]        |
+//! | GP0     | XF [`Perf0`](super::xform::Reg::Perf0)       | [`Metric::ClippedTriangles`] |
+//! | GP0     | [`RasterPerf`](super::Reg::RasterPerf)       | [`Metric::Pixels`]           |
+//! | GP1     | [`TexPerfMode`](super::Reg::TexPerfMode)     | [`Metric::TextureMisses`]    |
+//! | GP1     | [`PixelPerfMode`](super::Reg::PixelPerfMode) | [`Metric::Pixels`]           |
+//!
+//! Writing zero to a register deselects its metric. The counts are estimated from what is sent to
+//! the renderer rather than measured: pixels are the screen area of the triangles in front of the
+//! camera, ignoring culling, scissoring and depth testing, and a texture miss is counted whenever
+//! a texture has to be reloaded because it's new or its contents changed.
+use glam::{Vec2, Vec4};
+
+use crate::system::System;
+use crate::system::gx::xform::Viewport;
+use crate::system::gx::{EFB_HEIGHT, EFB_WIDTH, Topology, VertexStream};
+
+/// A metric a counter can be selected to count.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    /// Triangles submitted for drawing.
+    Triangles,
+    /// Triangles crossing or outside of the sides of the view frustum.
+    ClippedTriangles,
+    /// Pixels rasterized.
+    Pixels,
+    /// Textures missing from the texture cache.
+    TextureMisses,
+}
+
+/// A GP metric counter.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Counter {
+    pub metric: Option<Metric>,
+    pub value: u32,
+}
+
+impl Counter {
+    /// Handles a write of `value` to the register selecting `metric`: a non-zero value selects it,
+    /// while zero deselects it if it's the current one.
+    pub fn select(&mut self, metric: Metric, value: u32) {
+        if value != 0 {
+            self.metric = Some(metric);
+        } else if self.metric == Some(metric) {
+            self.metric = None;
+        }
+    }
+
+    fn count(&mut self, metric: Metric, amount: u32) {
+        if self.metric == Some(metric) {
+            self.value = self.value.wrapping_add(amount);
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Interface {
+    pub gp0: Counter,
+    pub gp1: Counter,
+}
+
+impl Interface {
+    /// Whether any of the counters is counting the given metric.
+    pub fn is_counting(&self, metric: Metric) -> bool {
+        self.gp0.metric == Some(metric) || self.gp1.metric == Some(metric)
+    }
+
+    /// Whether any of the counters has a metric selected.
+    pub fn is_active(&self) -> bool {
+        self.gp0.metric.is_some() || self.gp1.metric.is_some()
+    }
+
+    /// Adds `amount` to the counters counting the given metric.
+    pub fn count(&mut self, metric: Metric, amount: u32) {
+        self.gp0.count(metric, amount);
+        self.gp1.count(metric, amount);
+    }
+
+    /// Resets both counters to zero.
+    pub fn clear(&mut self) {
+        self.gp0.value = 0;
+        self.gp1.value = 0;
+    }
+}
+
+/// Returns the vertex indices of the triangles making up a primitive of the given topology with
+/// `count` vertices. Lines and points have no triangles.
+fn triangles(topology: Topology, count: usize) -> Vec<[usize; 3]> {
+    match topology {
+        Topology::QuadList => (0..count / 4)
+            .flat_map(|quad| {
+                let i = 4 * quad;
+                [[i, i + 1, i + 2], [i, i + 2, i + 3]]
+            })
+            .collect(),
+        Topology::TriangleList => (0..count / 3)
+            .map(|triangle| {
+                let i = 3 * triangle;
+                [i, i + 1, i + 2]
+            })
+            .collect(),
+        Topology::TriangleStrip => (2..count).map(|i| [i - 2, i - 1, i]).collect(),
+        Topology::TriangleFan => (2..count).map(|i| [0, i - 1, i]).collect(),
+        Topology::LineList | Topology::LineStrip | Topology::PointList => Vec::new(),
+    }
+}
+
+/// Whether a clip space position is inside of the sides of the view frustum.
+fn is_inside(clip: Vec4) -> bool {
+    clip.w > 0.0 && clip.x.abs() <= clip.w && clip.y.abs() <= clip.w
+}
+
+/// Converts a clip space position in front of the camera to a position on the EFB.
+fn to_screen(clip: Vec4, viewport: &Viewport) -> Vec2 {
+    let ndc = clip.truncate() / clip.w;
+    let x = viewport.center_x + ndc.x * viewport.width / 2.0;
+    let y = viewport.center_y - ndc.y * viewport.height / 2.0;
+    Vec2::new(x, y).clamp(Vec2::ZERO, Vec2::new(EFB_WIDTH as f32, EFB_HEIGHT as f32))
+}
+
+/// Estimates how many of the given triangles are clipped and how many pixels they cover.
+fn estimate(triangles: &[[usize; 3]], clip: &[Vec4], viewport: &Viewport) -> (u32, u32) {
+    let mut clipped = 0;
+    let mut pixels = 0.0;
+    for &[a, b, c] in triangles {
+        let vertices = [clip[a], clip[b], clip[c]];
+        if !vertices.iter().all(|&v| is_inside(v)) {
+            clipped += 1;
+        }
+
+        if vertices.iter().any(|v| v.w <= 0.0) {
+            continue;
+        }
+
+        let [a, b, c] = vertices.map(|v| self::to_screen(v, viewport));
+        pixels += (b - a).perp_dot(c - a).abs() / 2.0;
+    }
+
+    (clipped, pixels.round() as u32)
+}
+
+/// Updates the counters with the primitives of a draw.
+pub fn count_draw(sys: &mut System, topology: Topology, stream: &VertexStream) {
+    let vertices = stream.vertices();
+    let triangles = self::triangles(topology, vertices.len());
+    sys.gpu
+        .perf
+        .count(Metric::Triangles, triangles.len() as u32);
+
+    let perf = &sys.gpu.perf;
+    if !perf.is_counting(Metric::ClippedTriangles) && !perf.is_counting(Metric::Pixels) {
+        return;
+    }
+
+    let projection = sys.gpu.xform.projection_matrix();
+    let clip = vertices
+        .iter()
+        .map(|vertex| {
+            let view = sys.gpu.xform.matrix(vertex.pos_norm_matrix.index());
+            projection * view * vertex.position.extend(1.0)
+        })
+        .collect::<Vec<_>>();
+
+    let (clipped, pixels) = self::estimate(&triangles, &clip, &sys.gpu.xform.internal.viewport);
+    sys.gpu.perf.count(Metric::ClippedTriangles, clipped);
+    sys.gpu.perf.count(Metric::Pixels, pixels);
+}
+
+#[cfg(test)]
+mod test {
+    use glam::Vec4;
+
+    use super::{Counter, Interface, Metric, Topology, estimate, triangles};
+    use crate::system::gx::xform::Viewport;
+
+    fn viewport() -> Viewport {
+        Viewport {
+            width: 640.0,
+            height: 480.0,
+            center_x: 320.0,
+            center_y: 240.0,
+            far: 1.0,
+            far_minus_near: 1.0,
+        }
+    }
+
+    #[test]
+    fn triangle_counts() {
+        assert_eq!(triangles(Topology::QuadList, 8).len(), 4);
+        assert_eq!(triangles(Topology::TriangleList, 7).len(), 2);
+        assert_eq!(triangles(Topology::TriangleStrip, 5).len(), 3);
+        assert_eq!(triangles(Topology::TriangleFan, 2).len(), 0);
+        assert_eq!(triangles(Topology::LineStrip, 6).len(), 0);
+
+        assert_eq!(
+            triangles(Topology::TriangleFan, 4),
+            vec![[0, 1, 2], [0, 2, 3]]
+        );
+    }
+
+    #[test]
+    fn selection() {
+        let mut counter = Counter::default();
+        counter.select(Metric::Triangles, 1);
+        counter.count(Metric::Triangles, 3);
+        counter.count(Metric::Pixels, 100);
+        assert_eq!(counter.value, 3);
+
+        // deselecting another metric keeps the current one
+        counter.select(Metric::Pixels, 0);
+        assert_eq!(counter.metric, Some(Metric::Triangles));
+
+        counter.select(Metric::Triangles, 0);
+        counter.count(Metric::Triangles, 3);
+        assert_eq!(counter.metric, None);
+        assert_eq!(counter.value, 3);
+
+        let mut perf = Interface::default();
+        perf.gp0.select(Metric::Pixels, 1);
+        perf.gp1.select(Metric::Pixels, 1);
+        perf.count(Metric::Pixels, 10);
+        assert_eq!((perf.gp0.value, perf.gp1.value), (10, 10));
+
+        perf.clear();
+        assert_eq!((perf.gp0.value, perf.gp1.value), (0, 0));
+    }
+
+    #[test]
+    fn clipping_and_area() {
+        // a quad covering the top right quarter of the screen
+        let inside = [
+            Vec4::new(0.0, 0.0, 0.0, 1.0),
+            Vec4::new(1.0, 0.0, 0.0, 1.0),
+            Vec4::new(1.0, 1.0, 0.0, 1.0),
+            Vec4::new(0.0, 1.0, 0.0, 1.0),
+        ];
+
+        let quad = triangles(Topology::QuadList, 4);
+        assert_eq!(estimate(&quad, &inside, &viewport()), (0, 320 * 240));
+
+        // the same quad, extending past the right side of the screen
+        let crossing = inside.map(|v| v + Vec4::new(0.5, 0.0, 0.0, 0.0));
+        assert_eq!(estimate(&quad, &crossing, &viewport()), (2, 160 * 240));
+
+        // behind the camera
+        let behind = inside.map(|v| v * Vec4::new(1.0, 1.0, 1.0, -1.0));
+        assert_eq!(estimate(&quad, &behind, &viewport()), (2, 0));
+    }
+}
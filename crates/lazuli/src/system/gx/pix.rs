@@ -386,6 +386,61 @@ impl Scissor {
     }
 }
 
+/// The bounding box of pixels drawn by the PE, in EFB coordinates.
+///
+/// Titles reset it by writing `0x3FF` to the left/top bound and `0` to the right/bottom bound,
+/// draw, and then read the bounds back through the PE MMIO registers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoundingBox {
+    pub left: u16,
+    pub right: u16,
+    pub top: u16,
+    pub bottom: u16,
+    /// Whether the bounding box has been written to, i.e. whether the title is using it. Tracking
+    /// is skipped otherwise.
+    pub active: bool,
+}
+
+impl Default for BoundingBox {
+    fn default() -> Self {
+        Self {
+            left: 0x3FF,
+            right: 0,
+            top: 0x3FF,
+            bottom: 0,
+            active: false,
+        }
+    }
+}
+
+impl BoundingBox {
+    /// Writes the horizontal bounds register (`0x55`).
+    pub fn write_x(&mut self, value: u32) {
+        self.left = value.bits(0, 10) as u16;
+        self.right = value.bits(10, 20) as u16;
+        self.active = true;
+    }
+
+    /// Writes the vertical bounds register (`0x56`).
+    pub fn write_y(&mut self, value: u32) {
+        self.top = value.bits(0, 10) as u16;
+        self.bottom = value.bits(10, 20) as u16;
+        self.active = true;
+    }
+
+    /// Extends the bounding box so that it includes the given EFB coordinates. Coordinates are
+    /// clamped to the EFB.
+    pub fn include(&mut self, x: f32, y: f32) {
+        let x = x.clamp(0.0, (super::EFB_WIDTH - 1) as f32) as u16;
+        let y = y.clamp(0.0, (super::EFB_HEIGHT - 1) as f32) as u16;
+
+        self.left = self.left.min(x);
+        self.right = self.right.max(x);
+        self.top = self.top.min(y);
+        self.bottom = self.bottom.max(y);
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct FramebufferCopy {
     pub src: CopySrc,
@@ -405,6 +460,7 @@ pub struct Interface {
     pub blend_mode: BlendMode,
     pub scissor: Scissor,
     pub copy: FramebufferCopy,
+    pub bbox: BoundingBox,
     pub token: u32,
 }
 
@@ -418,3 +474,36 @@ impl Interface {
             .set_finish(self.interrupt.finish() & !status.bit(3));
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::BoundingBox;
+
+    #[test]
+    fn bbox_reset() {
+        let mut bbox = BoundingBox::default();
+        bbox.include(10.0, 20.0);
+
+        // GX_ClearBoundingBox
+        bbox.write_x(0x0000_03FF);
+        bbox.write_y(0x0000_03FF);
+
+        assert!(bbox.active);
+        assert_eq!((bbox.left, bbox.right), (0x3FF, 0));
+        assert_eq!((bbox.top, bbox.bottom), (0x3FF, 0));
+    }
+
+    #[test]
+    fn bbox_tracking() {
+        let mut bbox = BoundingBox::default();
+        bbox.write_x(0x0000_03FF);
+        bbox.write_y(0x0000_03FF);
+
+        bbox.include(100.5, 50.0);
+        bbox.include(20.0, 300.0);
+        bbox.include(-15.0, 1000.0);
+
+        assert_eq!((bbox.left, bbox.right), (0, 100));
+        assert_eq!((bbox.top, bbox.bottom), (50, 527));
+    }
+}
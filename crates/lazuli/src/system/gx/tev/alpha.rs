@@ -16,6 +16,17 @@ pub enum InputSrc {
     Zero      = 0x7,
 }
 
+impl InputSrc {
+    /// Whether this input reads one of the TEV registers, whose values might be outside of the
+    /// `0..=255` range.
+    pub fn is_register(self) -> bool {
+        matches!(
+            self,
+            Self::R3Alpha | Self::R0Alpha | Self::R1Alpha | Self::R2Alpha
+        )
+    }
+}
+
 impl std::fmt::Display for InputSrc {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str(match self {
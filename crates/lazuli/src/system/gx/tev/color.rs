@@ -23,6 +23,24 @@ pub enum InputSrc {
     Zero      = 0xF,
 }
 
+impl InputSrc {
+    /// Whether this input reads one of the TEV registers, whose values might be outside of the
+    /// `0..=255` range.
+    pub fn is_register(self) -> bool {
+        matches!(
+            self,
+            Self::R3Color
+                | Self::R3Alpha
+                | Self::R0Color
+                | Self::R0Alpha
+                | Self::R1Color
+                | Self::R1Alpha
+                | Self::R2Color
+                | Self::R2Alpha
+        )
+    }
+}
+
 impl std::fmt::Display for InputSrc {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str(match self {
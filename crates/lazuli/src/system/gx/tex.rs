@@ -9,8 +9,8 @@ use gxtex::PaletteIndex;
 
 use crate::modules::render;
 use crate::system::System;
-use crate::system::gx::DEPTH_24_BIT_MAX;
 use crate::system::gx::pix::{ColorCopyFormat, DepthCopyFormat};
+use crate::system::gx::{DEPTH_24_BIT_MAX, perf};
 
 #[derive(Debug, Clone)]
 enum LodData {
@@ -187,18 +187,52 @@ impl Encoding {
         }) as u32
     }
 
+    /// Size, in bytes, of the memory written by an EFB copy of the given dimensions whose rows of
+    /// tiles are `stride` cache lines apart.
+    pub fn copy_length_for(stride: u32, width: u32, height: u32, format: Format) -> u32 {
+        use gxtex::{I4, I8, IA4, IA8, Rgb5A3, Rgb565, Rgba8};
+
+        fn extent<F: gxtex::Format>(stride: u32, width: u32, height: u32) -> usize {
+            let width_in_tiles = (width as usize).div_ceil(F::TILE_WIDTH);
+            let height_in_tiles = (height as usize).div_ceil(F::TILE_HEIGHT);
+            if width_in_tiles == 0 || height_in_tiles == 0 {
+                return 0;
+            }
+
+            let stride_in_tiles = stride as usize / (F::BYTES_PER_TILE / 32);
+            ((height_in_tiles - 1) * stride_in_tiles + width_in_tiles) * F::BYTES_PER_TILE
+        }
+
+        let extent = match format {
+            Format::I4 => extent::<I4>(stride, width, height),
+            Format::I8 => extent::<I8>(stride, width, height),
+            Format::IA4 => extent::<IA4>(stride, width, height),
+            Format::IA8 => extent::<IA8>(stride, width, height),
+            Format::RGB565 => extent::<Rgb565>(stride, width, height),
+            Format::RGB5A3 => extent::<Rgb5A3>(stride, width, height),
+            Format::RGBA8 => extent::<Rgba8>(stride, width, height),
+            _ => {
+                tracing::warn!("EFB copies to {format:?} textures are not supported");
+                return 0;
+            }
+        };
+
+        // the encoder always wants room for a tightly packed texture
+        (extent as u32).max(Self::length_for(width, height, format))
+    }
+
     // Size, in bytes, of the texture.
     pub fn length(&self) -> u32 {
         Self::length_for(self.width(), self.height(), self.format())
     }
 
-    // Size, in bytes, of the texture, considering it as a mipmap.
-    pub fn length_mipmap(&self) -> u32 {
+    // Size, in bytes, of the first `levels` levels of the texture, considering it as a mipmap.
+    pub fn length_mipmap(&self, levels: u32) -> u32 {
         let mut current_width = self.width();
         let mut current_height = self.height();
 
         let mut size = 0;
-        for _ in 0..self.lod_count() {
+        for _ in 0..levels {
             size += Self::length_for(current_width, current_height, self.format());
             current_width = (current_width / 2).max(1);
             current_height = (current_height / 2).max(1);
@@ -284,6 +318,12 @@ impl LodLimits {
     pub fn max(&self) -> f32 {
         self.max_raw() as f32 / 16.0
     }
+
+    /// How many levels can be sampled with these limits. Mipmaps only store this many levels, so
+    /// reading more could go past their end.
+    pub fn level_count(&self) -> u32 {
+        self.max().ceil() as u32 + 1
+    }
 }
 
 #[derive(Debug, Clone, Copy, Default)]
@@ -549,16 +589,16 @@ pub fn update_texture(sys: &mut System, index: usize) {
     let clut_fmt = map.clut.format();
 
     let (len, lods) = if map.sampler.min_filter().uses_lods() {
-        (
-            map.encoding.length_mipmap() as usize,
-            map.encoding.lod_count() as usize,
-        )
+        let levels = map.encoding.lod_count().min(map.lods.limits.level_count());
+
+        (map.encoding.length_mipmap(levels) as usize, levels as usize)
     } else {
         (map.encoding.length() as usize, 1)
     };
 
     let data = &sys.mem.ram()[base.value() as usize..][..len];
     if sys.gpu.tex.update_tex_hash(base, data) {
+        sys.gpu.perf.count(perf::Metric::TextureMisses, 1);
         let data = self::decode_mipmap(data, width, height, format, lods);
         sys.modules.render.exec(render::Action::LoadTexture {
             id: texture_id,
@@ -592,6 +632,27 @@ pub fn update_texture(sys: &mut System, index: usize) {
     });
 }
 
+/// Releases the textures evicted by the render module, so that they're loaded again the next time
+/// they're used.
+pub fn release_evicted(sys: &mut System) {
+    let evicted = sys.modules.render.take_evicted_textures();
+    if evicted.is_empty() {
+        return;
+    }
+
+    for id in &evicted {
+        let addr = Address(id.0);
+        sys.gpu.tex.tex_cache.remove(&addr);
+        for map in &mut sys.gpu.tex.maps {
+            map.dirty |= map.address == addr;
+        }
+    }
+
+    sys.modules
+        .render
+        .exec(render::Action::ReleaseTextures(evicted));
+}
+
 pub fn update_clut(sys: &mut System) {
     let load = sys.gpu.tex.clut_load;
     let clut_addr = render::ClutId(load.tmem_offset().value());
@@ -612,3 +673,132 @@ pub fn update_clut(sys: &mut System) {
         });
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::sync::mpsc::{self, Sender};
+    use std::sync::{Arc, Mutex};
+
+    use bitos::integer::u10;
+
+    use super::{Encoding, Format, LodLimits, MinFilter, TextureData};
+    use crate::modules::render::{Action, RenderModule, TextureId};
+    use crate::system::{Config, Modules, System};
+
+    /// Forwards actions to a channel and reports the given textures as evicted.
+    struct Recorder(Sender<Action>, Arc<Mutex<Vec<TextureId>>>);
+
+    impl RenderModule for Recorder {
+        fn exec(&mut self, action: Action) {
+            self.0.send(action).unwrap();
+        }
+
+        fn take_evicted_textures(&mut self) -> Vec<TextureId> {
+            std::mem::take(&mut *self.1.lock().unwrap())
+        }
+    }
+
+    #[test]
+    fn mipmap_levels_follow_lod_limits() {
+        const BASE: u32 = 0x1000;
+
+        let (sender, receiver) = mpsc::channel();
+        let mut modules = Modules::nop();
+        modules.render = Box::new(Recorder(sender, Default::default()));
+        let mut sys = System::new(modules, Config::default());
+        receiver.try_iter().for_each(drop);
+
+        // 16x16 I8, which would have 5 levels. each level is filled with its index
+        let encoding = Encoding::default()
+            .with_width_minus_one(u10::new(15))
+            .with_height_minus_one(u10::new(15))
+            .with_format(Format::I8);
+
+        let mut offset = BASE;
+        for level in 0..encoding.lod_count() {
+            let size = 16 >> level;
+            let len = Encoding::length_for(size, size, Format::I8);
+            sys.mem.ram_mut()[offset as usize..][..len as usize].fill(level as u8);
+            offset += len;
+        }
+
+        let map = &mut sys.gpu.tex.maps[0];
+        map.address = crate::Address(BASE);
+        map.encoding = encoding;
+        map.sampler.set_min_filter(MinFilter::LinearMipLinear);
+
+        for (min, max, levels) in [(0, 0, 1), (0, 32, 3), (16, 40, 4), (0, 160, 5)] {
+            let limits = LodLimits::default().with_min_raw(min).with_max_raw(max);
+            sys.gpu.tex.maps[0].lods.limits = limits;
+            super::update_texture(&mut sys, 0);
+
+            let mut actions = receiver.try_iter();
+            let Some(Action::LoadTexture { texture, .. }) = actions.next() else {
+                panic!("texture was not loaded");
+            };
+
+            let TextureData::Direct(lods) = texture.data else {
+                panic!("I8 textures are direct");
+            };
+
+            assert_eq!(lods.len(), levels, "limits {min}..{max}");
+            for (level, data) in lods.iter().enumerate() {
+                assert_eq!(data[0].r, level as u8);
+            }
+
+            let Some(Action::SetTextureSlot { sampler, .. }) = actions.next() else {
+                panic!("texture slot was not set");
+            };
+
+            assert_eq!(sampler.lods, limits);
+        }
+    }
+
+    #[test]
+    fn evicted_textures_are_reloaded() {
+        const BASE: u32 = 0x1000;
+
+        let (sender, receiver) = mpsc::channel();
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let mut modules = Modules::nop();
+        modules.render = Box::new(Recorder(sender, evicted.clone()));
+        let mut sys = System::new(modules, Config::default());
+
+        let map = &mut sys.gpu.tex.maps[0];
+        map.address = crate::Address(BASE);
+        map.encoding = Encoding::default()
+            .with_width_minus_one(u10::new(3))
+            .with_height_minus_one(u10::new(3))
+            .with_format(Format::I8);
+
+        let loads = |receiver: &mpsc::Receiver<Action>| {
+            receiver
+                .try_iter()
+                .filter(|action| matches!(action, Action::LoadTexture { .. }))
+                .count()
+        };
+
+        super::update_texture(&mut sys, 0);
+        super::update_texture(&mut sys, 0);
+        assert_eq!(loads(&receiver), 1);
+
+        // nothing was evicted, so nothing is released
+        super::release_evicted(&mut sys);
+        assert!(!sys.gpu.tex.maps[0].dirty);
+        assert_eq!(receiver.try_iter().count(), 0);
+
+        evicted.lock().unwrap().push(TextureId(BASE));
+        super::release_evicted(&mut sys);
+        assert!(sys.gpu.tex.maps[0].dirty);
+
+        let Ok(Action::ReleaseTextures(released)) = receiver.try_recv() else {
+            panic!("textures were not released");
+        };
+
+        assert_eq!(released, [TextureId(BASE)]);
+
+        // unchanged data must be loaded again
+        super::update_texture(&mut sys, 0);
+        assert_eq!(loads(&receiver), 1);
+    }
+}
@@ -0,0 +1,153 @@
+//! Tracing of internal GX register writes.
+//!
+//! While enabled, every write that goes through [`set_register`](super::set_register) is logged to
+//! a text file along with the render actions it caused. Writes are grouped by frame, with frames
+//! delimited by XFB presents.
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crate::System;
+use crate::modules::render::{Action, NopRenderModule, RenderModule, TextureId};
+use crate::system::gx::Reg;
+
+/// Environment variable which, if set, enables the trace at startup. Its value is the path of the
+/// trace file.
+pub const PATH_VAR: &str = "LAZULI_GX_TRACE";
+
+#[derive(Default)]
+struct Recorded {
+    /// Actions executed since the last call to [`begin`].
+    actions: Vec<&'static str>,
+    /// Number of XFB presents so far.
+    frame: u64,
+}
+
+/// Render module wrapper which records the actions it executes.
+struct Recorder {
+    inner: Arc<Mutex<Box<dyn RenderModule>>>,
+    recorded: Arc<Mutex<Recorded>>,
+}
+
+impl RenderModule for Recorder {
+    fn exec(&mut self, action: Action) {
+        {
+            let mut recorded = self.recorded.lock().unwrap();
+            if let Action::PresentXfb(_) = action {
+                recorded.frame += 1;
+            }
+
+            recorded.actions.push((&action).into());
+        }
+
+        self.inner.lock().unwrap().exec(action);
+    }
+
+    fn take_evicted_textures(&mut self) -> Vec<TextureId> {
+        self.inner.lock().unwrap().take_evicted_textures()
+    }
+}
+
+/// State of an active GX register trace.
+pub struct RegisterTrace {
+    writer: BufWriter<File>,
+    /// The render module which was installed before the trace started.
+    inner: Arc<Mutex<Box<dyn RenderModule>>>,
+    recorded: Arc<Mutex<Recorded>>,
+    /// The frame of the last logged write.
+    frame: Option<u64>,
+}
+
+/// Starts tracing GX register writes into the file at `path`, replacing any trace in progress.
+pub fn start(sys: &mut System, path: impl AsRef<Path>) -> std::io::Result<()> {
+    let writer = BufWriter::new(File::create(path.as_ref())?);
+    stop(sys);
+
+    let render = std::mem::replace(&mut sys.modules.render, Box::new(NopRenderModule));
+    let inner = Arc::new(Mutex::new(render));
+    let recorded = Arc::new(Mutex::new(Recorded::default()));
+
+    sys.modules.render = Box::new(Recorder {
+        inner: inner.clone(),
+        recorded: recorded.clone(),
+    });
+
+    sys.gpu.trace = Some(RegisterTrace {
+        writer,
+        inner,
+        recorded,
+        frame: None,
+    });
+
+    tracing::info!("tracing GX register writes to {}", path.as_ref().display());
+    Ok(())
+}
+
+/// Stops the GX register trace, if one is in progress, and restores the original render module.
+pub fn stop(sys: &mut System) {
+    let Some(mut trace) = sys.gpu.trace.take() else {
+        return;
+    };
+
+    if let Err(e) = trace.writer.flush() {
+        tracing::error!("failed to flush GX register trace: {e}");
+    }
+
+    // drop the recorder so that the trace holds the only reference to the original module
+    sys.modules.render = Box::new(NopRenderModule);
+    let Ok(inner) = Arc::try_unwrap(trace.inner) else {
+        unreachable!("render module is still shared after dropping the recorder");
+    };
+
+    sys.modules.render = inner.into_inner().unwrap();
+}
+
+/// Whether GX register writes are currently being traced.
+pub fn is_active(sys: &System) -> bool {
+    sys.gpu.trace.is_some()
+}
+
+/// Prepares the trace for a register write by discarding the actions recorded since the last one.
+pub(super) fn begin(sys: &mut System) {
+    if let Some(trace) = &sys.gpu.trace {
+        trace.recorded.lock().unwrap().actions.clear();
+    }
+}
+
+/// Logs a register write, along with the actions recorded since [`begin`].
+pub(super) fn record(sys: &mut System, reg: Reg, value: u32, mask: u32) {
+    let cycle = sys.scheduler.elapsed();
+    let Some(trace) = &mut sys.gpu.trace else {
+        return;
+    };
+
+    let (frame, actions) = {
+        let mut recorded = trace.recorded.lock().unwrap();
+        (recorded.frame, std::mem::take(&mut recorded.actions))
+    };
+
+    let result = (|| {
+        if trace.frame != Some(frame) {
+            trace.frame = Some(frame);
+            writeln!(trace.writer, "=== frame {frame} ===")?;
+        }
+
+        let name = format!("{reg:?}");
+        write!(
+            trace.writer,
+            "[{cycle:>12}] {name:<20} = 0x{value:06X} (mask 0x{mask:06X})",
+        )?;
+
+        if !actions.is_empty() {
+            write!(trace.writer, " -> {}", actions.join(", "))?;
+        }
+
+        writeln!(trace.writer)
+    })();
+
+    if let Err(e) = result {
+        tracing::error!("failed to write GX register trace, stopping it: {e}");
+        stop(sys);
+    }
+}
@@ -9,8 +9,8 @@ use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout, TryFromBytes};
 use crate::Primitive;
 use crate::modules::render;
 use crate::system::System;
-use crate::system::gx::DEPTH_24_BIT_MAX;
 use crate::system::gx::cmd::ArrayDescriptor;
+use crate::system::gx::{DEPTH_24_BIT_MAX, perf};
 
 /// A transform unit register.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, FromRepr)]
@@ -482,6 +482,12 @@ pub fn set_register(sys: &mut System, reg: Reg, value: u32) {
         Reg::ProjectionParam5 => xf.projection_mtx.params[5] = f32::from_bits(value),
         Reg::ProjectionOrthographic => xf.projection_mtx.orthographic = value != 0,
 
+        Reg::Perf0 => sys
+            .gpu
+            .perf
+            .gp0
+            .select(perf::Metric::ClippedTriangles, value),
+
         Reg::TexGenCount => xf.active_texgens = value as u8,
         Reg::TexGen0 => xf.texgen[0].base = BaseTexGen::from_bits(value),
         Reg::TexGen1 => xf.texgen[1].base = BaseTexGen::from_bits(value),
@@ -0,0 +1,1078 @@
+//! High level emulation (HLE) of well-known SDK functions.
+//!
+//! A [`Patch`] replaces the first instruction of a function with an HLE trap: an instruction with
+//! the primary opcode [`HLE_OPCODE`], which the Gekko doesn't use, and the index of the patch in
+//! its lower bits. CPU cores call [`call`] whenever they execute a trap, which runs the patch
+//! instead of the function and returns from it.
+//!
+//! Functions are found by name through the debug module or, if it has no symbol for them, by
+//! scanning RAM for their [signature](Patch::signature). Patches are installed after a sideloaded
+//! executable is loaded, once the executable of a booted disc starts running (see
+//! [`install_on_start`]), or whenever [`install`] is called.
+
+use std::iter::Peekable;
+use std::ops::Range;
+use std::str::Chars;
+use std::sync::Arc;
+
+use disks::dol;
+use gekko::{Address, Exception, FREQUENCY, HLE_OPCODE};
+
+use crate::system::System;
+use crate::system::scheduler::SchedulerEventKind;
+
+/// Maximum length of strings read from the guest, in case they're not terminated.
+const MAX_STRING_LEN: usize = 4096;
+
+/// Maximum width and precision of a conversion, so that guests can't make a format string expand
+/// into a huge one.
+const MAX_WIDTH: i32 = 4096;
+
+/// Size of the chunks in which memory is copied and filled by native `memcpy` and `memset`.
+const CHUNK_LEN: usize = 4096;
+
+/// Bit of SRR1 which indicates that a program exception was caused by an illegal instruction.
+const SRR1_ILLEGAL_INSTRUCTION: u32 = 1 << 19;
+
+/// Interval at which [`poll_game`] checks whether the game executable started running.
+pub const POLL_INTERVAL: u64 = FREQUENCY / 60;
+
+/// Signature of `OSReport` as compiled in the SDK: the prologue of a variadic function, which
+/// spills the argument registers, followed by the `va_start` of a single fixed argument.
+const OS_REPORT_SIGNATURE: [SignatureWord; 21] = [
+    SignatureWord::exact(0x7C08_02A6),               // mflr r0
+    SignatureWord::exact(0x9001_0004),               // stw r0, 4(r1)
+    SignatureWord::masked(0x9421_0000, 0xFFFF_0000), // stwu r1, -frame(r1)
+    SignatureWord::exact(0x4086_0024),               // bne cr1, +0x24
+    SignatureWord::masked(0xD821_0000, 0xFFFF_0000), // stfd f1, x(r1)
+    SignatureWord::masked(0xD841_0000, 0xFFFF_0000), // stfd f2, x(r1)
+    SignatureWord::masked(0xD861_0000, 0xFFFF_0000), // stfd f3, x(r1)
+    SignatureWord::masked(0xD881_0000, 0xFFFF_0000), // stfd f4, x(r1)
+    SignatureWord::masked(0xD8A1_0000, 0xFFFF_0000), // stfd f5, x(r1)
+    SignatureWord::masked(0xD8C1_0000, 0xFFFF_0000), // stfd f6, x(r1)
+    SignatureWord::masked(0xD8E1_0000, 0xFFFF_0000), // stfd f7, x(r1)
+    SignatureWord::masked(0xD901_0000, 0xFFFF_0000), // stfd f8, x(r1)
+    SignatureWord::masked(0x9061_0000, 0xFFFF_0000), // stw r3, x(r1)
+    SignatureWord::masked(0x9081_0000, 0xFFFF_0000), // stw r4, x(r1)
+    SignatureWord::masked(0x90A1_0000, 0xFFFF_0000), // stw r5, x(r1)
+    SignatureWord::masked(0x90C1_0000, 0xFFFF_0000), // stw r6, x(r1)
+    SignatureWord::masked(0x90E1_0000, 0xFFFF_0000), // stw r7, x(r1)
+    SignatureWord::masked(0x9101_0000, 0xFFFF_0000), // stw r8, x(r1)
+    SignatureWord::masked(0x9121_0000, 0xFFFF_0000), // stw r9, x(r1)
+    SignatureWord::masked(0x9141_0000, 0xFFFF_0000), // stw r10, x(r1)
+    SignatureWord::exact(0x3C00_0100),               // lis r0, 0x100
+];
+
+/// Signature of the byte by byte `memcpy` of the SDK runtime.
+const MEMCPY_SIGNATURE: [SignatureWord; 11] = [
+    SignatureWord::exact(0x7C04_1840),               // cmplw r4, r3
+    SignatureWord::masked(0x4180_0000, 0xFFFF_0003), // blt backwards
+    SignatureWord::exact(0x3884_FFFF),               // subi r4, r4, 1
+    SignatureWord::exact(0x38C3_FFFF),               // subi r6, r3, 1
+    SignatureWord::exact(0x38A5_0001),               // addi r5, r5, 1
+    SignatureWord::masked(0x4800_0000, 0xFC00_0003), // b check
+    SignatureWord::exact(0x8C04_0001),               // loop: lbzu r0, 1(r4)
+    SignatureWord::exact(0x9C06_0001),               // stbu r0, 1(r6)
+    SignatureWord::exact(0x34A5_FFFF),               // check: subic. r5, r5, 1
+    SignatureWord::masked(0x4082_0000, 0xFFFF_0003), // bne loop
+    SignatureWord::exact(0x4E80_0020),               // blr
+];
+
+/// Signature of the `memset` of the SDK runtime, which calls `__fill_mem` and returns the
+/// destination.
+const MEMSET_SIGNATURE: [SignatureWord; 12] = [
+    SignatureWord::exact(0x7C08_02A6),               // mflr r0
+    SignatureWord::exact(0x9001_0004),               // stw r0, 4(r1)
+    SignatureWord::exact(0x9421_FFE8),               // stwu r1, -0x18(r1)
+    SignatureWord::exact(0x93E1_0014),               // stw r31, 0x14(r1)
+    SignatureWord::exact(0x3BE3_0000),               // addi r31, r3, 0
+    SignatureWord::masked(0x4800_0001, 0xFC00_0003), // bl __fill_mem
+    SignatureWord::exact(0x387F_0000),               // addi r3, r31, 0
+    SignatureWord::exact(0x8001_001C),               // lwz r0, 0x1C(r1)
+    SignatureWord::exact(0x83E1_0014),               // lwz r31, 0x14(r1)
+    SignatureWord::exact(0x3821_0018),               // addi r1, r1, 0x18
+    SignatureWord::exact(0x7C08_03A6),               // mtlr r0
+    SignatureWord::exact(0x4E80_0020),               // blr
+];
+
+/// Which of the built-in patches are installed.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Config {
+    /// Log the messages passed to `OSReport`.
+    pub os_report: bool,
+    /// Execute `memcpy` natively.
+    pub memcpy: bool,
+    /// Execute `memset` natively.
+    pub memset: bool,
+}
+
+/// A function executed in place of a patched one. It receives the arguments of the patched function
+/// as per the EABI (i.e. in r3-r10, f1-f8 and the stack) and must set its return value, but not
+/// return from it: that's done by [`call`].
+pub type Handler = Arc<dyn Fn(&mut System) + Send + Sync>;
+
+/// A word of a function signature. Only the bits set in the mask are compared, so that e.g. branch
+/// offsets can be ignored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SignatureWord {
+    pub value: u32,
+    pub mask: u32,
+}
+
+impl SignatureWord {
+    /// A word which must match exactly.
+    pub const fn exact(value: u32) -> Self {
+        Self {
+            value,
+            mask: u32::MAX,
+        }
+    }
+
+    /// A word of which only the bits set in `mask` must match.
+    pub const fn masked(value: u32, mask: u32) -> Self {
+        Self { value, mask }
+    }
+
+    fn matches(self, word: u32) -> bool {
+        (word ^ self.value) & self.mask == 0
+    }
+}
+
+/// A patch for a function.
+#[derive(Clone)]
+pub struct Patch {
+    /// Name of the patched function.
+    pub name: String,
+    /// The first words of the patched function, used to find it when there are no symbols. If
+    /// empty, the function can only be found through symbols.
+    pub signature: Vec<SignatureWord>,
+    /// The function executed in place of the patched one.
+    pub handler: Handler,
+}
+
+impl Patch {
+    /// Creates a patch for the function with the given name.
+    pub fn new(
+        name: impl Into<String>,
+        handler: impl Fn(&mut System) + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            signature: Vec::new(),
+            handler: Arc::new(handler),
+        }
+    }
+
+    /// Sets the signature used to find the function when there are no symbols.
+    pub fn with_signature(mut self, signature: impl Into<Vec<SignatureWord>>) -> Self {
+        self.signature = signature.into();
+        self
+    }
+}
+
+/// Where a patch is installed.
+#[derive(Debug, Clone, Copy)]
+struct Installed {
+    addr: Address,
+    /// The instruction replaced by the trap.
+    original: u32,
+}
+
+/// The registered patches.
+#[derive(Default)]
+pub struct Patches {
+    patches: Vec<(Patch, Option<Installed>)>,
+    /// Text sections of the game executable, as offsets into RAM. If known, signatures are only
+    /// scanned for in them.
+    game: Vec<Range<usize>>,
+    /// Whether the patches are installed once the game executable starts running.
+    waiting: bool,
+    /// Addresses modified since the last call to [`Patches::take_patched`].
+    patched: Vec<Address>,
+}
+
+impl Patches {
+    /// Creates a set with the built-in patches enabled in the given configuration.
+    pub fn new(config: &Config) -> Self {
+        let mut patches = Self::default();
+        if config.os_report {
+            patches.register(
+                Patch::new("OSReport", self::os_report).with_signature(OS_REPORT_SIGNATURE),
+            );
+        }
+
+        if config.memcpy {
+            patches.register(Patch::new("memcpy", self::memcpy).with_signature(MEMCPY_SIGNATURE));
+        }
+
+        if config.memset {
+            patches.register(Patch::new("memset", self::memset).with_signature(MEMSET_SIGNATURE));
+        }
+
+        patches
+    }
+
+    /// Registers a patch. It's installed on the next call to [`install`].
+    pub fn register(&mut self, patch: Patch) {
+        self.patches.push((patch, None));
+    }
+
+    /// Returns the name and address of the installed patches.
+    pub fn installed(&self) -> impl Iterator<Item = (&str, Address)> {
+        self.patches
+            .iter()
+            .filter_map(|(patch, installed)| Some((patch.name.as_str(), installed.as_ref()?.addr)))
+    }
+
+    /// Whether there are no registered patches.
+    pub fn is_empty(&self) -> bool {
+        self.patches.is_empty()
+    }
+
+    /// Forgets the game executable set by [`install_on_start`], so that signatures are scanned for
+    /// in the whole RAM again.
+    pub fn forget_game(&mut self) {
+        self.game.clear();
+        self.waiting = false;
+    }
+
+    /// Takes the addresses of the instructions modified by installing or uninstalling patches, so
+    /// that any code compiled from them can be invalidated.
+    pub fn take_patched(&mut self) -> Vec<Address> {
+        std::mem::take(&mut self.patched)
+    }
+
+    /// Whether patches modified memory since the last call to [`Patches::take_patched`].
+    pub fn has_patched(&self) -> bool {
+        !self.patched.is_empty()
+    }
+}
+
+/// Returns the trap of the patch with the given index.
+fn trap(index: usize) -> u32 {
+    (HLE_OPCODE << 26) | index as u32
+}
+
+/// Finds the address of the function patched by `patch`.
+fn resolve(sys: &System, patch: &Patch) -> Option<Address> {
+    if let Some(addr) = sys.modules.debug.find_address(&patch.name) {
+        return Some(addr);
+    }
+
+    if patch.signature.is_empty() {
+        return None;
+    }
+
+    let ram = sys.mem.ram();
+    let word = |index: usize| u32::from_be_bytes(ram[4 * index..][..4].try_into().unwrap());
+
+    let whole = [0..ram.len()];
+    let ranges = if sys.hle.game.is_empty() {
+        &whole[..]
+    } else {
+        &sys.hle.game[..]
+    };
+
+    let len = patch.signature.len();
+    ranges
+        .iter()
+        .find_map(|range| {
+            let end = range.end.min(ram.len()) / 4;
+            (range.start.div_ceil(4)..=end.checked_sub(len)?).find(|&start| {
+                patch
+                    .signature
+                    .iter()
+                    .enumerate()
+                    .all(|(i, expected)| expected.matches(word(start + i)))
+            })
+        })
+        .map(|start| Address(0x8000_0000 + 4 * start as u32))
+}
+
+/// Installs the registered patches which are not installed yet and whose function can be found.
+/// Returns how many were installed.
+///
+/// The patched instructions are recorded in [`Patches::take_patched`], so that code already
+/// compiled for them can be invalidated.
+pub fn install(sys: &mut System) -> usize {
+    let mut count = 0;
+    for index in 0..sys.hle.patches.len() {
+        let trap = self::trap(index);
+        let (patch, installed) = &sys.hle.patches[index];
+        if let Some(installed) = installed
+            && sys.read_pure::<u32>(installed.addr) == Some(trap)
+        {
+            continue;
+        }
+
+        let Some(addr) = self::resolve(sys, patch) else {
+            tracing::debug!("function of HLE patch {} not found", patch.name);
+            continue;
+        };
+
+        let Some(original) = sys.read_pure::<u32>(addr) else {
+            tracing::warn!("function of HLE patch {} at {addr} is unmapped", patch.name);
+            continue;
+        };
+
+        if !sys.write(addr, trap) {
+            let patch = &sys.hle.patches[index].0;
+            tracing::warn!("failed to install HLE patch {} at {addr}", patch.name);
+            continue;
+        }
+
+        let (patch, installed) = &mut sys.hle.patches[index];
+        *installed = Some(Installed { addr, original });
+        tracing::info!("installed HLE patch {} at {addr}", patch.name);
+        sys.hle.patched.push(addr);
+
+        count += 1;
+    }
+
+    count
+}
+
+/// Restores the functions modified by the installed patches.
+///
+/// Like with [`install`], the restored instructions are recorded in [`Patches::take_patched`].
+pub fn uninstall(sys: &mut System) {
+    for index in 0..sys.hle.patches.len() {
+        let Some(installed) = sys.hle.patches[index].1.take() else {
+            continue;
+        };
+
+        if sys.read_pure::<u32>(installed.addr) == Some(self::trap(index)) {
+            sys.write(installed.addr, installed.original);
+            sys.hle.patched.push(installed.addr);
+        }
+    }
+}
+
+/// Installs the patches once the CPU runs code in the text sections of the game executable with
+/// the given header, i.e. after the apploader loaded it and handed off control. Until the
+/// executable is forgotten, signatures are only scanned for in its text sections.
+pub fn install_on_start(sys: &mut System, header: &dol::Header) {
+    sys.hle.game = header
+        .text_targets
+        .iter()
+        .zip(header.text_sizes)
+        .filter(|&(_, size)| size != 0)
+        .map(|(&target, size)| {
+            let start = (target & 0x01FF_FFFF) as usize;
+            start..start + size as usize
+        })
+        .collect();
+
+    sys.hle.waiting = true;
+    sys.scheduler
+        .schedule(POLL_INTERVAL, SchedulerEventKind::HlePatches);
+}
+
+/// Installs the patches if the game executable set by [`install_on_start`] started running, or
+/// checks again later otherwise.
+pub fn poll_game(sys: &mut System) {
+    if !sys.hle.waiting {
+        return;
+    }
+
+    let pc = (sys.cpu.pc.value() & 0x01FF_FFFF) as usize;
+    if sys.hle.game.iter().any(|range| range.contains(&pc)) {
+        sys.hle.waiting = false;
+        self::install(sys);
+    } else {
+        sys.scheduler
+            .schedule(POLL_INTERVAL, SchedulerEventKind::HlePatches);
+    }
+}
+
+/// Executes the HLE trap `code` at `addr`, running its patch and returning from the patched
+/// function. Raises a program exception if the trap does not belong to an installed patch.
+pub fn call(sys: &mut System, addr: Address, code: u32) {
+    let index = (code & 0x03FF_FFFF) as usize;
+    let Some((patch, Some(_))) = sys.hle.patches.get(index) else {
+        tracing::error!("unknown HLE trap 0x{code:08X} at {addr}, raising a program exception");
+        sys.cpu.pc = addr;
+        sys.cpu.raise_exception(Exception::Program);
+        sys.cpu.supervisor.exception.srr[1] |= SRR1_ILLEGAL_INSTRUCTION;
+        return;
+    };
+
+    let handler = patch.handler.clone();
+    handler(sys);
+
+    sys.cpu.pc = Address(sys.cpu.user.lr);
+}
+
+/// Reads a NUL terminated string at the given address. Invalid UTF-8 is replaced and at most
+/// [`MAX_STRING_LEN`] bytes are read.
+pub fn read_string(sys: &System, addr: Address) -> String {
+    let mut bytes = Vec::new();
+    while bytes.len() < MAX_STRING_LEN {
+        match sys.read_pure::<u8>(addr + bytes.len() as u32) {
+            Some(0) | None => break,
+            Some(byte) => bytes.push(byte),
+        }
+    }
+
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Reads the variadic arguments of a function as per the EABI: the first ones are passed in r3-r10
+/// and f1-f8, and the rest in the parameter area of the caller's stack frame.
+#[derive(Debug, Clone)]
+pub struct VarArgs {
+    gpr: usize,
+    fpr: usize,
+    stack: Address,
+}
+
+impl VarArgs {
+    /// Creates a reader for the variadic arguments of a function which has `fixed` integer (or
+    /// pointer) arguments before them.
+    pub fn new(sys: &System, fixed: usize) -> Self {
+        Self {
+            gpr: 3 + fixed,
+            fpr: 1,
+            stack: Address(sys.cpu.user.gpr[1]) + 8u32,
+        }
+    }
+
+    /// Reads the next integer (or pointer) argument.
+    pub fn next_u32(&mut self, sys: &System) -> u32 {
+        if self.gpr <= 10 {
+            self.gpr += 1;
+            return sys.cpu.user.gpr[self.gpr - 1];
+        }
+
+        let value = sys.read_pure::<u32>(self.stack).unwrap_or(0);
+        self.stack += 4u32;
+        value
+    }
+
+    /// Reads the next floating point argument.
+    pub fn next_f64(&mut self, sys: &System) -> f64 {
+        if self.fpr <= 8 {
+            self.fpr += 1;
+            return sys.cpu.user.fpr[self.fpr - 1][0];
+        }
+
+        self.stack = self.stack.align_up(8);
+        let value = sys.read_pure::<u64>(self.stack).unwrap_or(0);
+        self.stack += 8u32;
+        f64::from_bits(value)
+    }
+}
+
+/// A conversion specification of a format string, without the conversion itself.
+#[derive(Debug, Clone, Copy, Default)]
+struct Spec {
+    left: bool,
+    plus: bool,
+    space: bool,
+    alternate: bool,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+impl Spec {
+    /// Pads a converted value (made of a prefix, e.g. its sign, and its digits) to the width.
+    fn pad(self, out: &mut String, prefix: &str, body: &str, numeric: bool) {
+        let len = prefix.chars().count() + body.chars().count();
+        let padding = self.width.saturating_sub(len);
+        if self.left {
+            out.push_str(prefix);
+            out.push_str(body);
+            out.extend(std::iter::repeat_n(' ', padding));
+        } else if self.zero && numeric {
+            out.push_str(prefix);
+            out.extend(std::iter::repeat_n('0', padding));
+            out.push_str(body);
+        } else {
+            out.extend(std::iter::repeat_n(' ', padding));
+            out.push_str(prefix);
+            out.push_str(body);
+        }
+    }
+
+    /// Returns the sign prefix of a number.
+    fn sign(self, negative: bool) -> &'static str {
+        if negative {
+            "-"
+        } else if self.plus {
+            "+"
+        } else if self.space {
+            " "
+        } else {
+            ""
+        }
+    }
+
+    /// Applies the precision (i.e. the minimum number of digits) to the digits of an integer.
+    fn digits(mut self, digits: String) -> (Self, String) {
+        let Some(precision) = self.precision else {
+            return (self, digits);
+        };
+
+        // zero padding is ignored when a precision is given
+        self.zero = false;
+        if precision == 0 && digits == "0" {
+            return (self, String::new());
+        }
+
+        let zeros = precision.saturating_sub(digits.len());
+        (self, "0".repeat(zeros) + &digits)
+    }
+}
+
+/// Parses a width or a precision, which is either a number or `*` to read it from the arguments.
+/// Values read from the arguments might be negative. The magnitude is at most [`MAX_WIDTH`].
+fn number(sys: &System, chars: &mut Peekable<Chars<'_>>, args: &mut VarArgs) -> i32 {
+    if chars.next_if_eq(&'*').is_some() {
+        return (args.next_u32(sys) as i32).clamp(-MAX_WIDTH, MAX_WIDTH);
+    }
+
+    let mut value = 0i32;
+    while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+        value = value
+            .saturating_mul(10)
+            .saturating_add(digit as i32 - '0' as i32);
+    }
+
+    value.min(MAX_WIDTH)
+}
+
+/// Formats a `printf` format string. Supports flags, width, precision and the `d`, `i`, `u`, `x`,
+/// `X`, `o`, `c`, `s`, `p`, `f`, `F` and `%` conversions. Length modifiers are skipped, and unknown
+/// conversions are written as is.
+pub fn format(sys: &System, fmt: &str, args: &mut VarArgs) -> String {
+    let mut out = String::new();
+    let mut chars = fmt.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+
+        let mut spec = Spec::default();
+        while let Some(&flag) = chars.peek() {
+            match flag {
+                '-' => spec.left = true,
+                '+' => spec.plus = true,
+                ' ' => spec.space = true,
+                '#' => spec.alternate = true,
+                '0' => spec.zero = true,
+                _ => break,
+            }
+
+            chars.next();
+        }
+
+        // a negative width means left-justify, and a negative precision is as if it was omitted
+        let width = self::number(sys, &mut chars, args);
+        spec.left |= width < 0;
+        spec.width = width.unsigned_abs() as usize;
+        if chars.next_if_eq(&'.').is_some() {
+            spec.precision = usize::try_from(self::number(sys, &mut chars, args)).ok();
+        }
+
+        while chars
+            .next_if(|&c| matches!(c, 'h' | 'l' | 'L' | 'q' | 'j' | 'z' | 't'))
+            .is_some()
+        {}
+
+        let Some(conversion) = chars.next() else {
+            out.push('%');
+            break;
+        };
+
+        match conversion {
+            'd' | 'i' => {
+                let value = args.next_u32(sys) as i32;
+                let (spec, digits) = spec.digits(value.unsigned_abs().to_string());
+                spec.pad(&mut out, spec.sign(value < 0), &digits, true);
+            }
+            'u' => {
+                let (spec, digits) = spec.digits(args.next_u32(sys).to_string());
+                spec.pad(&mut out, "", &digits, true);
+            }
+            'x' | 'X' | 'o' => {
+                let value = args.next_u32(sys);
+                let (digits, prefix) = match conversion {
+                    'x' => (format!("{value:x}"), "0x"),
+                    'X' => (format!("{value:X}"), "0X"),
+                    _ => (format!("{value:o}"), "0"),
+                };
+
+                let prefix = if spec.alternate && value != 0 {
+                    prefix
+                } else {
+                    ""
+                };
+
+                let (spec, digits) = spec.digits(digits);
+                spec.pad(&mut out, prefix, &digits, true);
+            }
+            'p' => {
+                let value = args.next_u32(sys);
+                spec.pad(&mut out, "0x", &format!("{value:08x}"), true);
+            }
+            'c' => {
+                let value = args.next_u32(sys) as u8;
+                spec.pad(&mut out, "", &char::from(value).to_string(), false);
+            }
+            's' => {
+                let value = self::read_string(sys, Address(args.next_u32(sys)));
+                let value = match spec.precision {
+                    Some(precision) => value.chars().take(precision).collect(),
+                    None => value,
+                };
+
+                spec.pad(&mut out, "", &value, false);
+            }
+            'f' | 'F' => {
+                let value = args.next_f64(sys);
+                let precision = spec.precision.unwrap_or(6);
+                let digits = format!("{:.*}", precision, value.abs());
+                let digits = if spec.alternate && precision == 0 {
+                    digits + "."
+                } else {
+                    digits
+                };
+
+                spec.pad(
+                    &mut out,
+                    spec.sign(value.is_sign_negative()),
+                    &digits,
+                    value.is_finite(),
+                );
+            }
+            '%' => out.push('%'),
+            other => {
+                out.push('%');
+                out.push(other);
+            }
+        }
+    }
+
+    out
+}
+
+/// `void OSReport(const char* msg, ...)`
+fn os_report(sys: &mut System) {
+    let fmt = self::read_string(sys, Address(sys.cpu.user.gpr[3]));
+    let mut args = VarArgs::new(sys, 1);
+    let message = self::format(sys, &fmt, &mut args);
+
+    tracing::info!("OSReport: {}", message.trim_end());
+}
+
+/// `void* memcpy(void* dst, const void* src, size_t n)`
+fn memcpy(sys: &mut System) {
+    let dst = sys.cpu.user.gpr[3];
+    let src = sys.cpu.user.gpr[4];
+    let len = sys.cpu.user.gpr[5] as usize;
+
+    let mut buffer = [0; CHUNK_LEN];
+    let mut done = 0;
+    while done < len {
+        let current = (len - done).min(CHUNK_LEN);
+        let chunk = &mut buffer[..current];
+        let offset = done as u32;
+
+        if let Err(fault) = sys.read_block(Address(src.wrapping_add(offset)), chunk) {
+            tracing::warn!("memcpy from 0x{src:08X} faulted: {fault}");
+            break;
+        }
+
+        if let Err(fault) = sys.write_block(Address(dst.wrapping_add(offset)), chunk) {
+            tracing::warn!("memcpy to 0x{dst:08X} faulted: {fault}");
+            break;
+        }
+
+        done += current;
+    }
+
+    // dst is returned, and it's already in r3
+}
+
+/// `void* memset(void* dst, int value, size_t n)`
+fn memset(sys: &mut System) {
+    let dst = sys.cpu.user.gpr[3];
+    let value = sys.cpu.user.gpr[4] as u8;
+    let len = sys.cpu.user.gpr[5] as usize;
+
+    let chunk = [value; CHUNK_LEN];
+    let mut done = 0;
+    while done < len {
+        let current = (len - done).min(CHUNK_LEN);
+        if let Err(fault) =
+            sys.write_block(Address(dst.wrapping_add(done as u32)), &chunk[..current])
+        {
+            tracing::warn!("memset to 0x{dst:08X} faulted: {fault}");
+            break;
+        }
+
+        done += current;
+    }
+
+    // dst is returned, and it's already in r3
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Cursor, Read, Seek, SeekFrom};
+    use std::sync::{Arc, Mutex};
+
+    use disks::binrw::BinWrite;
+    use disks::dol;
+    use gekko::{Address, HLE_OPCODE};
+
+    use super::{Config, POLL_INTERVAL, Patch, SignatureWord, VarArgs};
+    use crate::modules::debug::{DebugModule, Location};
+    use crate::modules::disk::DiskModule;
+    use crate::system::{self, Modules, System};
+
+    const FMT: u32 = 0x8000_1000;
+    const STRING: u32 = 0x8000_1100;
+    const STACK: u32 = 0x8000_2000;
+
+    /// A debug module which only knows the address of some symbols.
+    struct Symbols(Vec<(&'static str, Address)>);
+
+    impl DebugModule for Symbols {
+        fn find_symbol(&self, _: Address) -> Option<String> {
+            None
+        }
+
+        fn find_location(&self, _: Address) -> Option<Location<'_>> {
+            None
+        }
+
+        fn find_address(&self, name: &str) -> Option<Address> {
+            self.0.iter().find(|(n, _)| *n == name).map(|(_, a)| *a)
+        }
+    }
+
+    /// A disc image, without an apploader.
+    struct Disc(Cursor<Vec<u8>>);
+
+    impl Read for Disc {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+
+    impl Seek for Disc {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.0.seek(pos)
+        }
+    }
+
+    impl DiskModule for Disc {
+        fn has_disk(&self) -> bool {
+            true
+        }
+    }
+
+    /// Builds a system with the default BATs and the given symbols.
+    fn system(symbols: Vec<(&'static str, Address)>, hle: Config) -> System {
+        let mut modules = Modules::nop();
+        modules.debug = Box::new(Symbols(symbols));
+
+        let mut sys = System::new(
+            modules,
+            system::Config {
+                hle,
+                ..Default::default()
+            },
+        );
+
+        sys.cpu
+            .supervisor
+            .config
+            .msr
+            .set_data_addr_translation(true);
+        sys.cpu.supervisor.memory.setup_default_bats();
+        sys.mem.build_bat_lut(&sys.cpu.supervisor.memory);
+
+        sys
+    }
+
+    fn write_string(sys: &mut System, addr: u32, s: &str) {
+        let mut bytes = s.as_bytes().to_vec();
+        bytes.push(0);
+        sys.write_block(Address(addr), &bytes).unwrap();
+    }
+
+    fn format(sys: &mut System, fmt: &str) -> String {
+        write_string(sys, FMT, fmt);
+        sys.cpu.user.gpr[3] = FMT;
+
+        let fmt = super::read_string(sys, Address(FMT));
+        let mut args = VarArgs::new(sys, 1);
+        super::format(sys, &fmt, &mut args)
+    }
+
+    #[test]
+    fn printf() {
+        let mut sys = system(Vec::new(), Config::default());
+        write_string(&mut sys, STRING, "world");
+
+        sys.cpu.user.gpr[4] = -42i32 as u32;
+        sys.cpu.user.gpr[5] = 0xBEEF;
+        sys.cpu.user.gpr[6] = STRING;
+        sys.cpu.user.fpr[1][0] = 1.23456;
+        assert_eq!(
+            format(&mut sys, "%d %x %s %.2f 100%%"),
+            "-42 beef world 1.23 100%"
+        );
+
+        sys.cpu.user.gpr[4] = 7;
+        sys.cpu.user.gpr[5] = 0xBEEF;
+        sys.cpu.user.gpr[6] = STRING;
+        sys.cpu.user.gpr[7] = 5;
+        sys.cpu.user.gpr[8] = 5;
+        assert_eq!(
+            format(&mut sys, "[%03d] [%#X] [%-7s] [%*d]"),
+            "[007] [0XBEEF] [world  ] [    5]"
+        );
+    }
+
+    #[test]
+    fn printf_huge_width() {
+        let mut sys = system(Vec::new(), Config::default());
+
+        // widths and precisions are clamped
+        sys.cpu.user.gpr[4] = 0x7FFF_FFFF;
+        sys.cpu.user.gpr[5] = 1;
+        assert_eq!(format(&mut sys, "%*d").len(), 4096);
+        assert_eq!(format(&mut sys, "%999999999999d").len(), 4096);
+
+        sys.cpu.user.gpr[4] = 0x7FFF_FFFF;
+        sys.cpu.user.gpr[5] = 1;
+        assert_eq!(format(&mut sys, "%.*d").len(), 4096);
+
+        // a negative width left-justifies
+        sys.cpu.user.gpr[4] = -3i32 as u32;
+        sys.cpu.user.gpr[5] = 5;
+        assert_eq!(format(&mut sys, "[%*d]"), "[5  ]");
+
+        sys.cpu.user.gpr[4] = i32::MIN as u32;
+        sys.cpu.user.gpr[5] = 5;
+        assert_eq!(format(&mut sys, "%*d").len(), 4096);
+
+        // a negative precision is ignored
+        sys.cpu.user.gpr[4] = -3i32 as u32;
+        sys.cpu.user.gpr[5] = 5;
+        assert_eq!(format(&mut sys, "[%.*d]"), "[5]");
+    }
+
+    #[test]
+    fn varargs_overflow_to_stack() {
+        let mut sys = system(Vec::new(), Config::default());
+        sys.cpu.user.gpr[1] = STACK;
+        for (i, reg) in (4..=10).enumerate() {
+            sys.cpu.user.gpr[reg] = i as u32;
+        }
+
+        // the 8th integer argument and the 9th float one are in the parameter area
+        assert!(sys.write::<u32>(Address(STACK + 8), 7));
+        assert!(sys.write::<u64>(Address(STACK + 16), 2.5f64.to_bits()));
+        for i in 1..=8 {
+            sys.cpu.user.fpr[i][0] = i as f64;
+        }
+
+        let mut args = VarArgs::new(&sys, 1);
+        let ints = (0..8).map(|_| args.next_u32(&sys)).collect::<Vec<_>>();
+        let floats = (0..9).map(|_| args.next_f64(&sys)).collect::<Vec<_>>();
+        assert_eq!(ints, [0, 1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(floats, [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 2.5]);
+    }
+
+    #[test]
+    fn memcpy_and_memset() {
+        const MEMCPY: Address = Address(0x8000_3000);
+        const MEMSET: Address = Address(0x8000_3100);
+        const LR: u32 = 0x8000_4000;
+
+        let config = Config {
+            os_report: false,
+            memcpy: true,
+            memset: true,
+        };
+        let mut sys = system(vec![("memcpy", MEMCPY), ("memset", MEMSET)], config);
+        assert_eq!(super::install(&mut sys), 2);
+        assert_eq!(
+            sys.read::<u32>(MEMCPY).map(|ins| ins >> 26),
+            Some(HLE_OPCODE)
+        );
+
+        let data = (0..10_000u32).map(|i| i as u8).collect::<Vec<_>>();
+        sys.write_block(Address(0x8010_0000), &data).unwrap();
+
+        sys.cpu.user.gpr[3] = 0x8020_0000;
+        sys.cpu.user.gpr[4] = 0x8010_0000;
+        sys.cpu.user.gpr[5] = data.len() as u32;
+        sys.cpu.user.lr = LR;
+        let trap = sys.read(MEMCPY).unwrap();
+        super::call(&mut sys, MEMCPY, trap);
+
+        let mut copied = vec![0; data.len()];
+        sys.read_block(Address(0x8020_0000), &mut copied).unwrap();
+        assert_eq!(copied, data);
+        assert_eq!(sys.cpu.user.gpr[3], 0x8020_0000);
+        assert_eq!(sys.cpu.pc, Address(LR));
+
+        sys.cpu.user.gpr[3] = 0x8020_0010;
+        sys.cpu.user.gpr[4] = 0xAB;
+        sys.cpu.user.gpr[5] = 5000;
+        let trap = sys.read(MEMSET).unwrap();
+        super::call(&mut sys, MEMSET, trap);
+
+        sys.read_block(Address(0x8020_0000), &mut copied).unwrap();
+        assert_eq!(copied[..0x10], data[..0x10]);
+        assert!(copied[0x10..][..5000].iter().all(|&b| b == 0xAB));
+        assert_eq!(copied[0x10 + 5000..], data[0x10 + 5000..]);
+
+        // uninstalling restores the original instructions
+        super::uninstall(&mut sys);
+        assert_eq!(sys.read::<u32>(MEMCPY), Some(0));
+        assert_eq!(sys.hle.installed().count(), 0);
+    }
+
+    #[test]
+    fn signature_scanning() {
+        const FUNCTION: u32 = 0x8000_5000;
+
+        let mut sys = system(Vec::new(), Config::default());
+        let called = Arc::new(Mutex::new(0));
+        let counter = called.clone();
+
+        // stwu r1, -16(r1); bl <anything>; li r3, 0
+        for (i, word) in [0x9421_FFF0u32, 0x4800_1235, 0x3860_0000]
+            .into_iter()
+            .enumerate()
+        {
+            assert!(sys.write(Address(FUNCTION + 4 * i as u32), word));
+        }
+
+        sys.hle.register(
+            Patch::new("Function", move |_| *counter.lock().unwrap() += 1).with_signature([
+                SignatureWord::exact(0x9421_FFF0),
+                SignatureWord {
+                    value: 0x4800_0001,
+                    mask: 0xFC00_0003,
+                },
+                SignatureWord::exact(0x3860_0000),
+            ]),
+        );
+
+        assert_eq!(super::install(&mut sys), 1);
+        assert_eq!(
+            sys.hle.installed().collect::<Vec<_>>(),
+            [("Function", Address(FUNCTION))]
+        );
+
+        // installing again does nothing
+        assert_eq!(super::install(&mut sys), 0);
+
+        super::call(&mut sys, Address(FUNCTION), super::trap(0));
+        assert_eq!(*called.lock().unwrap(), 1);
+    }
+
+    #[test]
+    fn unknown_trap() {
+        let mut sys = system(Vec::new(), Config::default());
+        super::call(&mut sys, Address(0x8000_5000), super::trap(3));
+
+        assert_eq!(
+            sys.cpu.pc.value() & 0xFFFF,
+            gekko::Exception::Program as u32
+        );
+        assert_eq!(sys.cpu.supervisor.exception.srr[0], 0x8000_5000);
+    }
+
+    #[test]
+    fn disc_boot() {
+        const GAME: u32 = 0x8000_3100;
+        const MEMCPY: u32 = GAME + 0x40;
+        const IPL: u32 = 0x8130_0000;
+
+        // the memcpy of the SDK runtime
+        const CODE: [u32; 11] = [
+            0x7C04_1840,
+            0x4180_0034,
+            0x3884_FFFF,
+            0x38C3_FFFF,
+            0x38A5_0001,
+            0x4800_0010,
+            0x8C04_0001,
+            0x9C06_0001,
+            0x34A5_FFFF,
+            0x4082_FFF4,
+            0x4E80_0020,
+        ];
+
+        // the header of the disc points to an executable with a single text section
+        let mut image = vec![0; 0x2000];
+        image[0x420..][..4].copy_from_slice(&0x1000u32.to_be_bytes());
+        let header = dol::Header {
+            text_targets: [GAME, 0, 0, 0, 0, 0, 0],
+            text_sizes: [0x100, 0, 0, 0, 0, 0, 0],
+            entry: GAME,
+            ..Default::default()
+        };
+        header
+            .write(&mut Cursor::new(&mut image[0x1000..]))
+            .unwrap();
+
+        let mut modules = Modules::nop();
+        modules.disk = Box::new(Disc(Cursor::new(image)));
+        let mut sys = System::new(
+            modules,
+            system::Config {
+                ipl_lle: true,
+                hle: Config {
+                    memcpy: true,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+
+        // the IPL has its own copy of memcpy, and loads the game's while it runs
+        let write_code = |sys: &mut System, addr: u32| {
+            for (i, word) in CODE.into_iter().enumerate() {
+                let offset = (addr & 0x01FF_FFFF) as usize + 4 * i;
+                sys.mem.ram_mut()[offset..][..4].copy_from_slice(&word.to_be_bytes());
+            }
+        };
+
+        write_code(&mut sys, IPL);
+        write_code(&mut sys, MEMCPY);
+        sys.cpu.pc = Address(IPL);
+        sys.scheduler.advance(POLL_INTERVAL);
+        sys.process_events();
+        assert_eq!(sys.hle.installed().count(), 0);
+
+        // once the apploader hands off control to the game, the patches are installed in it
+        sys.cpu.pc = Address(GAME);
+        sys.scheduler.advance(POLL_INTERVAL);
+        sys.process_events();
+        assert_eq!(
+            sys.hle.installed().collect::<Vec<_>>(),
+            [("memcpy", Address(MEMCPY))]
+        );
+        assert_eq!(sys.hle.take_patched(), [Address(MEMCPY)]);
+
+        let ram = sys.mem.ram();
+        let word = |addr: u32| {
+            let offset = (addr & 0x01FF_FFFF) as usize;
+            u32::from_be_bytes(ram[offset..][..4].try_into().unwrap())
+        };
+
+        assert_eq!(word(MEMCPY) >> 26, HLE_OPCODE);
+        assert_eq!(word(IPL), CODE[0]);
+    }
+}
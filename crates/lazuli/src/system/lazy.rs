@@ -1,11 +1,14 @@
 use gekko::Exception;
 
 use crate::system::System;
+use crate::system::scheduler::{SchedulerEventKind, SchedulerToken};
 
 #[derive(Debug, Default)]
 pub struct Lazy {
     pub last_updated_tb: u64,
     pub last_updated_dec: u64,
+    /// The pending decrementer overflow event.
+    pub decrementer_event: Option<SchedulerToken>,
 }
 
 impl System {
@@ -41,14 +44,25 @@ impl System {
         self.cpu.supervisor.misc.dec = new;
     }
 
+    /// Schedules the decrementer overflow, replacing the pending one (if any).
+    pub fn schedule_decrementer_overflow(&mut self, after: u64) {
+        if let Some(token) = self.lazy.decrementer_event.take() {
+            self.scheduler.cancel(token);
+        }
+
+        let token = self
+            .scheduler
+            .schedule(after, SchedulerEventKind::DecrementerOverflow);
+        self.lazy.decrementer_event = Some(token);
+    }
+
     pub fn decrementer_overflow(&mut self) {
         self.update_decrementer();
         if self.cpu.supervisor.config.msr.interrupts() {
             self.cpu.raise_exception(Exception::Decrementer);
-            self.scheduler
-                .schedule(u32::MAX as u64, System::decrementer_overflow);
+            self.schedule_decrementer_overflow(u32::MAX as u64);
         } else {
-            self.scheduler.schedule(32, System::decrementer_overflow);
+            self.schedule_decrementer_overflow(32);
         }
     }
 }
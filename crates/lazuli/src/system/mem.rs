@@ -1,5 +1,6 @@
 //! Memory of the system.
 use std::alloc::Layout;
+use std::ops::RangeInclusive;
 use std::ptr::NonNull;
 
 use bitos::BitUtils;
@@ -74,6 +75,13 @@ pub struct Regions<'mem> {
     pub ipl: &'mem [u8],
 }
 
+/// Which set of BATs a LUT is built from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatKind {
+    Instruction,
+    Data,
+}
+
 pub struct Memory {
     ram: NonNull<u8>,
     l2c: NonNull<u8>,
@@ -81,13 +89,35 @@ pub struct Memory {
 
     data_fastmem_lut_physical: Box<FastmemLut>,
     data_fastmem_lut_logical: Box<FastmemLut>,
+    data_fastmem_lut_empty: Box<FastmemLut>,
     data_translation_lut: Box<TranslationLut>,
     inst_translation_lut: Box<TranslationLut>,
+
+    /// The DBATs the data LUTs currently reflect.
+    dbats: [Bat; 4],
+    /// The IBATs the instruction LUT currently reflects.
+    ibats: [Bat; 4],
+    /// Whether the locked cache is mapped into the fastmem LUTs, i.e. whether HID2.LCE is set.
+    locked_cache: bool,
 }
 
+/// Range of logical pages covered by a BAT, if it is enabled.
+fn bat_pages(bat: &Bat) -> Option<RangeInclusive<u32>> {
+    bat.supervisor_mode()
+        .then(|| (bat.logical_start().value() >> 17)..=(bat.logical_end().value() >> 17))
+}
+
+/// Difference between the physical and logical page of a BAT, which is constant for every page
+/// it maps.
+fn bat_page_offset(bat: &Bat) -> u32 {
+    (bat.physical_start().value() >> 17).wrapping_sub(bat.logical_start().value() >> 17)
+}
+
+/// Maps the given (logical, physical) pages in a fastmem LUT. The locked cache is left unmapped if
+/// `l2c` is `None`, so that accesses to it go through the slow path.
 fn update_fastmem_lut(
     ram: *mut u8,
-    l2c: *mut u8,
+    l2c: Option<*mut u8>,
     ipl: *mut u8,
     lut: &mut FastmemLut,
     iter: impl IntoIterator<Item = (u32, u32)>,
@@ -96,42 +126,26 @@ fn update_fastmem_lut(
         let physical = Address(physical_base << 17);
         let region = Region::of(physical);
 
-        let ptr = if let Some((region, offset)) = region {
+        let ptr = region.and_then(|(region, offset)| {
             let base = match region {
                 Region::Ram => ram,
-                Region::L2c => l2c,
+                Region::L2c => l2c?,
                 Region::Ipl => ipl,
             };
 
-            unsafe { base.add(offset as usize) }
-        } else {
-            std::ptr::null_mut()
-        };
+            NonNull::new(unsafe { base.add(offset as usize) })
+        });
 
-        lut[logical_base as usize] = NonNull::new(ptr);
+        lut[logical_base as usize] = ptr;
     }
 }
 
-fn update_fastmem_lut_with_bat(
+fn update_fastmem_lut_physical(
     ram: *mut u8,
-    l2c: *mut u8,
+    l2c: Option<*mut u8>,
     ipl: *mut u8,
     lut: &mut FastmemLut,
-    bat: &Bat,
 ) {
-    let physical_start_base = bat.physical_start().value() >> 17;
-    let physical_end_base = bat.physical_end().value() >> 17;
-    let logical_start_base = bat.logical_start().value() >> 17;
-    let logical_end_base = bat.logical_end().value() >> 17;
-
-    let logical_range = logical_start_base..=logical_end_base;
-    let physical_range = physical_start_base..=physical_end_base;
-    let iter = logical_range.zip(physical_range);
-
-    update_fastmem_lut(ram, l2c, ipl, lut, iter);
-}
-
-fn update_fastmem_lut_physical(ram: *mut u8, l2c: *mut u8, ipl: *mut u8, lut: &mut FastmemLut) {
     let iter = |a, b| ((a >> 17)..=(b >> 17)).map(|x| (x, x));
     let ram_iter = iter(RAM_START, RAM_END);
     let l2c_iter = iter(L2C_START, L2C_END);
@@ -141,21 +155,6 @@ fn update_fastmem_lut_physical(ram: *mut u8, l2c: *mut u8, ipl: *mut u8, lut: &m
     update_fastmem_lut(ram, l2c, ipl, lut, ipl_iter);
 }
 
-fn update_translation_lut_with(translation: &mut TranslationLut, bat: &Bat) {
-    let physical_start_base = (bat.physical_start().value() >> 17) as u16;
-    let physical_end_base = (bat.physical_end().value() >> 17) as u16;
-    let logical_start_base = bat.logical_start().value() >> 17;
-    let logical_end_base = bat.logical_end().value() >> 17;
-
-    let logical_range = logical_start_base..=logical_end_base;
-    let physical_range = physical_start_base..=physical_end_base;
-    let iter = logical_range.zip(physical_range);
-
-    for (logical_base, physical_base) in iter {
-        translation[logical_base as usize] = PageTranslation::new(Some(physical_base));
-    }
-}
-
 impl Memory {
     pub fn new(ipl_data: &Ipl) -> Self {
         let alloc = |len| {
@@ -170,10 +169,11 @@ impl Memory {
             std::ptr::copy_nonoverlapping(ipl_data.as_ptr(), ipl.as_ptr(), IPL_LEN);
         }
 
+        // HID2.LCE is cleared on reset, so the locked cache starts unmapped
         let mut data_fastmem_lut_physical = util::boxed_array(None);
         update_fastmem_lut_physical(
             ram.as_ptr(),
-            l2c.as_ptr(),
+            None,
             ipl.as_ptr(),
             &mut data_fastmem_lut_physical,
         );
@@ -185,8 +185,13 @@ impl Memory {
 
             data_fastmem_lut_physical,
             data_fastmem_lut_logical: util::boxed_array(None),
+            data_fastmem_lut_empty: util::boxed_array(None),
             data_translation_lut: util::boxed_array(PageTranslation::NO_MAPPING),
             inst_translation_lut: util::boxed_array(PageTranslation::NO_MAPPING),
+
+            dbats: Default::default(),
+            ibats: Default::default(),
+            locked_cache: false,
         }
     }
 
@@ -210,6 +215,46 @@ impl Memory {
         unsafe { std::slice::from_raw_parts_mut(self.l2c.as_ptr(), L2C_LEN) }
     }
 
+    /// The locked cache base pointer if it should be mapped into the fastmem LUTs.
+    #[inline(always)]
+    fn fastmem_l2c(&self) -> Option<*mut u8> {
+        self.locked_cache.then_some(self.l2c.as_ptr())
+    }
+
+    /// Whether the locked cache is mapped into the fastmem LUTs.
+    #[inline(always)]
+    pub fn locked_cache(&self) -> bool {
+        self.locked_cache
+    }
+
+    /// Maps or unmaps the locked cache in the physical and logical fastmem LUTs. Should be called
+    /// whenever HID2.LCE changes, so that fastmem accesses (including the ones from JITed code)
+    /// only reach the locked cache while it is enabled.
+    pub fn set_locked_cache(&mut self, enabled: bool) {
+        if self.locked_cache == enabled {
+            return;
+        }
+
+        self.locked_cache = enabled;
+
+        let l2c_page = L2C_START >> 17;
+        update_fastmem_lut(
+            self.ram.as_ptr(),
+            self.fastmem_l2c(),
+            self.ipl.as_ptr(),
+            &mut self.data_fastmem_lut_physical,
+            [(l2c_page, l2c_page)],
+        );
+
+        // logical pages which the DBATs map to the locked cache
+        let dbats = self.dbats.clone();
+        for page in dbats.iter().filter_map(bat_pages).flatten() {
+            if self.data_translation_lut[page as usize].base() == Some(l2c_page as u16) {
+                self.update_page(BatKind::Data, page);
+            }
+        }
+    }
+
     #[inline(always)]
     pub fn ipl(&self) -> &[u8] {
         unsafe { std::slice::from_raw_parts(self.ipl.as_ptr(), IPL_LEN) }
@@ -224,54 +269,121 @@ impl Memory {
         Regions { ram, l2c, ipl }
     }
 
+    /// Recomputes the LUT entries of a single logical page from the current BATs. If more than one
+    /// BAT covers the page, the one with the highest index wins.
+    fn update_page(&mut self, kind: BatKind, page: u32) {
+        let bats = match kind {
+            BatKind::Instruction => &self.ibats,
+            BatKind::Data => &self.dbats,
+        };
+
+        let physical = bats
+            .iter()
+            .rev()
+            .find(|bat| bat_pages(bat).is_some_and(|pages| pages.contains(&page)))
+            .map(|bat| page.wrapping_add(bat_page_offset(bat)) & 0x7FFF);
+
+        let translation = PageTranslation::new(physical.map(|p| p as u16));
+        match kind {
+            BatKind::Instruction => self.inst_translation_lut[page as usize] = translation,
+            BatKind::Data => {
+                self.data_translation_lut[page as usize] = translation;
+                match physical {
+                    Some(physical) => update_fastmem_lut(
+                        self.ram.as_ptr(),
+                        self.fastmem_l2c(),
+                        self.ipl.as_ptr(),
+                        &mut self.data_fastmem_lut_logical,
+                        [(page, physical)],
+                    ),
+                    None => self.data_fastmem_lut_logical[page as usize] = None,
+                }
+            }
+        }
+    }
+
+    /// Updates the LUTs of the given kind after a BAT changed from `old` to `new`. Only the pages
+    /// in the symmetric difference of both ranges are recomputed, plus the pages in their
+    /// intersection if the mapping itself changed.
+    ///
+    /// The stored BATs of the given kind must already contain `new`.
+    fn build_bat_lut_incremental(&mut self, old: &Bat, new: &Bat, kind: BatKind) {
+        let old_pages = bat_pages(old);
+        let new_pages = bat_pages(new);
+        let same_mapping = bat_page_offset(old) == bat_page_offset(new);
+
+        let in_new = |page: &u32| new_pages.as_ref().is_some_and(|r| r.contains(page));
+        let in_old = |page: &u32| old_pages.as_ref().is_some_and(|r| r.contains(page));
+
+        for page in old_pages.clone().into_iter().flatten() {
+            if same_mapping && in_new(&page) {
+                continue;
+            }
+
+            self.update_page(kind, page);
+        }
+
+        for page in new_pages.clone().into_iter().flatten() {
+            if in_old(&page) {
+                continue;
+            }
+
+            self.update_page(kind, page);
+        }
+    }
+
+    /// Updates the data LUTs to reflect the given DBATs. Only the DBATs which changed since the
+    /// last call are applied.
     pub fn build_data_bat_lut(&mut self, dbats: &[Bat; 4]) {
         let _span = tracing::info_span!("building dbat lut").entered();
 
-        self.data_fastmem_lut_logical.fill(None);
-        self.data_translation_lut.fill(PageTranslation::NO_MAPPING);
         for (i, bat) in dbats.iter().enumerate() {
-            if !bat.supervisor_mode() {
-                tracing::warn!("dbat{i} is disabled in supervisor mode");
+            if self.dbats[i] == *bat {
                 continue;
             }
 
-            tracing::info!(
-                "dbat{i}: logical({}..={}) -> physical({}..={})",
-                bat.logical_start(),
-                bat.logical_end(),
-                bat.physical_start(),
-                bat.physical_end()
-            );
-            update_translation_lut_with(&mut self.data_translation_lut, bat);
-            update_fastmem_lut_with_bat(
-                self.ram.as_ptr(),
-                self.l2c.as_ptr(),
-                self.ipl.as_ptr(),
-                &mut self.data_fastmem_lut_logical,
-                bat,
-            );
+            if bat.supervisor_mode() {
+                tracing::info!(
+                    "dbat{i}: logical({}..={}) -> physical({}..={})",
+                    bat.logical_start(),
+                    bat.logical_end(),
+                    bat.physical_start(),
+                    bat.physical_end()
+                );
+            } else {
+                tracing::warn!("dbat{i} is disabled in supervisor mode");
+            }
+
+            let old = std::mem::replace(&mut self.dbats[i], bat.clone());
+            self.build_bat_lut_incremental(&old, bat, BatKind::Data);
         }
     }
 
+    /// Updates the instruction LUT to reflect the given IBATs. Only the IBATs which changed since
+    /// the last call are applied.
     pub fn build_inst_bat_lut(&mut self, ibats: &[Bat; 4]) {
         let _span = tracing::info_span!("building ibat lut").entered();
 
-        self.inst_translation_lut.fill(PageTranslation::NO_MAPPING);
         for (i, bat) in ibats.iter().enumerate() {
-            if !bat.supervisor_mode() {
-                tracing::warn!("ibat{i} is disabled in supervisor mode");
+            if self.ibats[i] == *bat {
                 continue;
             }
 
-            tracing::info!(
-                "ibat{i} ({:16X}): logical({}..={}) -> physical({}..={})",
-                bat.to_bits(),
-                bat.logical_start(),
-                bat.logical_end(),
-                bat.physical_start(),
-                bat.physical_end()
-            );
-            update_translation_lut_with(&mut self.inst_translation_lut, bat);
+            if bat.supervisor_mode() {
+                tracing::info!(
+                    "ibat{i} ({:16X}): logical({}..={}) -> physical({}..={})",
+                    bat.to_bits(),
+                    bat.logical_start(),
+                    bat.logical_end(),
+                    bat.physical_start(),
+                    bat.physical_end()
+                );
+            } else {
+                tracing::warn!("ibat{i} is disabled in supervisor mode");
+            }
+
+            let old = std::mem::replace(&mut self.ibats[i], bat.clone());
+            self.build_bat_lut_incremental(&old, bat, BatKind::Instruction);
         }
     }
 
@@ -316,6 +428,12 @@ impl Memory {
     pub fn data_fastmem_lut_physical(&self) -> &FastmemLut {
         &self.data_fastmem_lut_physical
     }
+
+    /// Returns a fastmem LUT with no pages, which forces every access through the slow path.
+    #[inline(always)]
+    pub fn data_fastmem_lut_empty(&self) -> &FastmemLut {
+        &self.data_fastmem_lut_empty
+    }
 }
 
 unsafe impl Send for Memory {}
@@ -331,3 +449,93 @@ impl Drop for Memory {
         dealloc(self.ipl, IPL_LEN);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use gekko::{Address, Bat};
+
+    use super::{IPL_LEN, L2C_START, Memory, PAGES_COUNT};
+    use crate::system::ipl::Ipl;
+
+    /// Builds an enabled BAT from its logical page, physical page and block length mask.
+    fn bat(logical: u32, physical: u32, mask: u32) -> Bat {
+        let lower = physical << 17;
+        let upper = (logical << 17) | (mask << 2) | 0b10;
+        Bat::from_bits(((upper as u64) << 32) | lower as u64)
+    }
+
+    /// Checks the data LUTs against a translation computed from scratch.
+    fn check(memory: &Memory, dbats: &[Bat; 4]) {
+        for page in 0..PAGES_COUNT as u32 {
+            let addr = Address(page << 17);
+            let expected = dbats
+                .iter()
+                .rev()
+                .find(|bat| bat.supervisor_mode() && bat.contains(addr))
+                .map(|bat| bat.translate(addr));
+
+            assert_eq!(
+                memory.translate_data_addr(addr),
+                expected,
+                "page {page:04X}"
+            );
+            assert_eq!(
+                memory.data_fastmem_lut_logical()[page as usize].is_some(),
+                expected.is_some_and(|addr| super::Region::of(addr).is_some()),
+                "page {page:04X}"
+            );
+        }
+    }
+
+    #[test]
+    fn incremental_bat_lut() {
+        let mut memory = Memory::new(&Ipl::new(vec![0; IPL_LEN]));
+        let mut dbats: [Bat; 4] = Default::default();
+
+        // a small xorshift so the sequence of writes is reproducible
+        let mut state = 0x1234_5678u32;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state
+        };
+
+        for _ in 0..64 {
+            let index = next() as usize % 4;
+            let mask = (1 << (next() % 8)) - 1;
+
+            // keep the regions close together so that BATs overlap often
+            let logical = 0x4000 | (next() & 0x3F);
+            let physical = next() & 0xFF & !mask;
+            dbats[index] = if next() % 4 == 0 {
+                Bat::default()
+            } else {
+                bat(logical, physical, mask)
+            };
+
+            memory.build_data_bat_lut(&dbats);
+            check(&memory, &dbats);
+        }
+    }
+
+    #[test]
+    fn locked_cache_mapping() {
+        let mut memory = Memory::new(&Ipl::new(vec![0; IPL_LEN]));
+        let page = (L2C_START >> 17) as usize;
+        let mut dbats: [Bat; 4] = Default::default();
+        dbats[3] = bat(page as u32, page as u32, 0);
+        memory.build_data_bat_lut(&dbats);
+
+        assert!(memory.data_fastmem_lut_physical()[page].is_none());
+        assert!(memory.data_fastmem_lut_logical()[page].is_none());
+
+        memory.set_locked_cache(true);
+        assert!(memory.data_fastmem_lut_physical()[page].is_some());
+        assert!(memory.data_fastmem_lut_logical()[page].is_some());
+
+        memory.set_locked_cache(false);
+        assert!(memory.data_fastmem_lut_physical()[page].is_none());
+        assert!(memory.data_fastmem_lut_logical()[page].is_none());
+    }
+}
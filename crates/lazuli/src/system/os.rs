@@ -5,9 +5,21 @@ use bitos::{BitUtils, TryBits, bitos};
 use gekko::Address;
 
 use crate::system::System;
+use crate::system::eabi::{self, CallStack};
+use crate::system::mem::RAM_LEN;
+
+/// Address of the pointers to the head and tail of the active thread queue.
+const ACTIVE_QUEUE: Address = Address(0x8000_00DC);
+/// Address of the pointer to the current thread.
+const CURRENT_THREAD: Address = Address(0x8000_00E4);
+/// Length of an `OSThread` structure.
+const THREAD_LEN: u32 = 0x318;
+/// Maximum number of threads walked in the active thread queue.
+const MAX_THREADS: usize = 256;
 
 #[derive(Debug, Clone)]
 pub struct Context {
+    pub gpr: [u32; 32],
     pub sp: Address,
     pub srr0: Address,
 }
@@ -62,7 +74,12 @@ pub struct Thread {
 }
 
 pub fn thread(sys: &System, addr: Address) -> Option<Thread> {
-    let sp = Address(sys.read_pure::<u32>(addr + 0x4)?);
+    let mut gpr = [0; 32];
+    for (i, value) in gpr.iter_mut().enumerate() {
+        *value = sys.read_pure::<u32>(addr + 4 * i as u32)?;
+    }
+
+    let sp = Address(gpr[1]);
     let srr0 = Address(sys.read_pure::<u32>(addr + 0x198)?);
     let state = State::try_from_bits(u4::new(sys.read_pure::<u16>(addr + 0x2C8)? as u8))
         .unwrap_or_default();
@@ -80,7 +97,7 @@ pub fn thread(sys: &System, addr: Address) -> Option<Thread> {
     let error = sys.read_pure::<i32>(addr + 0x30C)?;
 
     let data = ThreadData {
-        context: Context { sp, srr0 },
+        context: Context { gpr, sp, srr0 },
         state,
         detached,
         suspended,
@@ -99,41 +116,184 @@ pub fn thread(sys: &System, addr: Address) -> Option<Thread> {
     Some(Thread { addr, data })
 }
 
-#[derive(Debug, Clone)]
-pub struct Threads {
-    pub default: Thread,
-    pub current: Option<Thread>,
-    pub active: Vec<Thread>,
+/// Whether the given address can hold a thread, i.e. it's aligned and the whole structure is in
+/// one of the RAM segments.
+fn is_thread_addr(addr: Address) -> bool {
+    let segment = addr.value() & 0xF000_0000;
+    let offset = addr.value() & 0x0FFF_FFFF;
+
+    matches!(segment, 0x8000_0000 | 0xC000_0000)
+        && addr.value().is_multiple_of(4)
+        && offset + THREAD_LEN <= RAM_LEN as u32
 }
 
-pub fn active_threads(sys: &System) -> Option<Vec<Thread>> {
-    let mut threads = vec![];
-    let mut current = Address(sys.read_pure::<u32>(Address(0x8000_00DC))?);
-    while !current.is_null() {
-        let Some(thread) = thread(sys, current) else {
+/// An OS thread along with its call stack.
+#[derive(Debug)]
+pub struct OsThread {
+    pub thread: Thread,
+    /// Whether this is the thread currently running.
+    pub current: bool,
+    /// The call stack of the thread. It's unwound from the live registers for the current thread
+    /// and from the saved context for every other thread.
+    pub call_stack: CallStack,
+}
+
+/// Parses the threads in the active thread queue.
+///
+/// The queue lives in emulated memory and might not be set up yet or be corrupted, so the walk
+/// stops at pointers outside of RAM, at threads that were already visited and after
+/// [`MAX_THREADS`] threads.
+pub fn threads(sys: &System) -> Vec<OsThread> {
+    let mut threads: Vec<OsThread> = Vec::new();
+    let Some(head) = sys.read_pure::<u32>(ACTIVE_QUEUE) else {
+        return threads;
+    };
+
+    let current = sys.read_pure::<u32>(CURRENT_THREAD).map(Address);
+    let mut addr = Address(head);
+    while !addr.is_null() && threads.len() < MAX_THREADS {
+        if !is_thread_addr(addr) || threads.iter().any(|t| t.thread.addr == addr) {
+            break;
+        }
+
+        let Some(thread) = self::thread(sys, addr) else {
             break;
         };
 
-        current = thread.data.ptr_active_next;
-        threads.push(thread);
+        let current = current == Some(addr);
+        let call_stack = if current {
+            eabi::current_call_stack(sys)
+        } else {
+            let context = &thread.data.context;
+            eabi::call_stack(sys, context.sp, context.srr0)
+        };
+
+        addr = thread.data.ptr_active_next;
+        threads.push(OsThread {
+            thread,
+            current,
+            call_stack,
+        });
     }
 
-    Some(threads)
+    threads
 }
 
-pub fn system_threads(sys: &System) -> Option<Threads> {
-    let default = thread(sys, Address(sys.read_pure::<u32>(Address(0x8000_00DC))?))?;
+#[cfg(test)]
+mod test {
+    use gekko::Address;
+
+    use super::State;
+    use crate::Primitive;
+    use crate::system::System;
 
-    let ptr_current = Address(sys.read_pure::<u32>(Address(0x8000_00E4))?);
-    let current = (!ptr_current.is_null())
-        .then(|| thread(sys, ptr_current))
-        .flatten();
+    const THREAD_A: u32 = 0x8000_3000;
+    const THREAD_B: u32 = 0x8000_4000;
 
-    let active = active_threads(sys)?;
+    /// Builds a system with the default BATs, so that low memory is reachable at 0x8000_0000.
+    fn system() -> System {
+        let mut sys = crate::test::system();
 
-    Some(Threads {
-        default,
-        current,
-        active,
-    })
+        sys.cpu
+            .supervisor
+            .config
+            .msr
+            .set_data_addr_translation(true);
+        sys.cpu.supervisor.memory.setup_default_bats();
+        sys.mem.build_bat_lut(&sys.cpu.supervisor.memory);
+
+        sys
+    }
+
+    fn write<P: Primitive>(sys: &mut System, addr: u32, value: P) {
+        assert!(sys.write(Address(addr), value));
+    }
+
+    /// Writes two threads to RAM: A, which is running, and B, which is waiting with two frames in
+    /// its stack.
+    fn two_threads() -> System {
+        let mut sys = system();
+        write::<u32>(&mut sys, 0x8000_00DC, THREAD_A);
+        write::<u32>(&mut sys, 0x8000_00E0, THREAD_B);
+        write::<u32>(&mut sys, 0x8000_00E4, THREAD_A);
+
+        write::<u16>(&mut sys, THREAD_A + 0x2C8, 2);
+        write::<i32>(&mut sys, THREAD_A + 0x2D0, 16);
+        write::<i32>(&mut sys, THREAD_A + 0x2D4, 16);
+        write::<u32>(&mut sys, THREAD_A + 0x2FC, THREAD_B);
+
+        write::<u32>(&mut sys, THREAD_B + 0x4, 0x8000_6000);
+        write::<u32>(&mut sys, THREAD_B + 0x34, 0xDEAD_BEEF);
+        write::<u32>(&mut sys, THREAD_B + 0x198, 0x8000_1234);
+        write::<u16>(&mut sys, THREAD_B + 0x2C8, 4);
+        write::<u32>(&mut sys, THREAD_B + 0x2CC, 1);
+        write::<i32>(&mut sys, THREAD_B + 0x2D0, 10);
+        write::<i32>(&mut sys, THREAD_B + 0x2D4, 20);
+        write::<u32>(&mut sys, THREAD_B + 0x300, THREAD_A);
+
+        // back chain of B
+        write::<u32>(&mut sys, 0x8000_6000, 0x8000_6100);
+        write::<u32>(&mut sys, 0x8000_6004, 0x8000_2008);
+        write::<u32>(&mut sys, 0x8000_6100, 0);
+        write::<u32>(&mut sys, 0x8000_6104, 0x8000_3008);
+
+        // live registers of A
+        sys.cpu.user.gpr[1] = 0x8000_6100;
+        sys.cpu.pc = Address(0x8000_2004);
+
+        sys
+    }
+
+    #[test]
+    fn parse_threads() {
+        let sys = two_threads();
+        let threads = super::threads(&sys);
+        assert_eq!(threads.len(), 2);
+
+        let a = &threads[0];
+        assert_eq!(a.thread.addr, Address(THREAD_A));
+        assert!(a.current);
+        assert!(matches!(a.thread.data.state, State::Running));
+        assert_eq!(a.thread.data.priority, 16);
+
+        // unwound from the live registers
+        let frames = &a.call_stack.0;
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].stack, Address(0x8000_6100));
+        assert_eq!(frames[0].address, Address(0x8000_3004));
+
+        let b = &threads[1];
+        assert_eq!(b.thread.addr, Address(THREAD_B));
+        assert!(!b.current);
+        assert!(matches!(b.thread.data.state, State::Waiting));
+        assert!(b.thread.data.suspended);
+        assert_eq!(b.thread.data.priority, 10);
+        assert_eq!(b.thread.data.base_priority, 20);
+        assert_eq!(b.thread.data.context.gpr[13], 0xDEAD_BEEF);
+        assert_eq!(b.thread.data.context.srr0, Address(0x8000_1234));
+
+        // unwound from the saved context
+        let frames = &b.call_stack.0;
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].stack, Address(0x8000_6000));
+        assert_eq!(frames[0].address, Address(0x8000_2004));
+        assert_eq!(frames[1].stack, Address(0x8000_6100));
+        assert_eq!(frames[1].address, Address(0x8000_3004));
+    }
+
+    #[test]
+    fn corrupted_queue() {
+        // cycle back to the head
+        let mut sys = two_threads();
+        write::<u32>(&mut sys, THREAD_B + 0x2FC, THREAD_A);
+        assert_eq!(super::threads(&sys).len(), 2);
+
+        // pointer outside of RAM
+        write::<u32>(&mut sys, THREAD_B + 0x2FC, 0x9000_0000);
+        assert_eq!(super::threads(&sys).len(), 2);
+
+        // misaligned head
+        write::<u32>(&mut sys, 0x8000_00DC, THREAD_A + 1);
+        assert!(super::threads(&sys).is_empty());
+    }
 }
@@ -3,7 +3,6 @@ use bitos::bitos;
 use bitos::integer::u26;
 use gekko::{Address, Exception};
 
-use crate::Primitive;
 use crate::system::{System, gx};
 
 #[bitos(14)]
@@ -99,32 +98,28 @@ impl FifoCurrent {
     }
 }
 
+#[derive(Default)]
 pub struct Interface {
     // interrupts
     pub mask: InterruptMask,
 
+    // reset
+    /// Value of the reset register. Survives soft resets, which is how the OS tells a restart
+    /// apart from a cold boot.
+    pub reset_code: u32,
+    /// Whether the reset switch has been pressed and the interrupt it raises is pending. Set by
+    /// soft resets and cleared once the interrupt is acknowledged.
+    pub reset_switch: bool,
+
     // fifo
     pub fifo_start: Address,
     pub fifo_end: Address,
     pub fifo_current: FifoCurrent,
-
-    fifo_queue: [u8; 36],
-    fifo_queue_index: usize,
 }
 
-impl Default for Interface {
-    fn default() -> Self {
-        Self {
-            mask: Default::default(),
-            fifo_start: Default::default(),
-            fifo_end: Default::default(),
-            fifo_current: Default::default(),
-
-            fifo_queue: [0; 36],
-            fifo_queue_index: 0,
-        }
-    }
-}
+/// Bit of the interrupt cause register with the state of the reset switch, which is set while it
+/// is released. Presses are instantaneous, so it always reads as released.
+pub const RESET_SWITCH_RELEASED: u32 = 1 << 16;
 
 /// Returns which interrupt sources are active (i.e. triggered but maybe masked).
 pub fn get_active_interrupts(sys: &System) -> InterruptSources {
@@ -150,11 +145,14 @@ pub fn get_active_interrupts(sys: &System) -> InterruptSources {
     sources.set_dsp_interface(sys.dsp.control.any_interrupt());
 
     // DI
-    sources.set_dvd_interface(sys.disk.status.any_interrupt());
+    sources.set_dvd_interface(sys.disk.any_interrupt());
 
     // SI
     sources.set_serial_interface(sys.serial.any_interrupt());
 
+    // reset switch
+    sources.set_reset(sys.processor.reset_switch);
+
     sources
 }
 
@@ -179,22 +177,9 @@ pub fn check_interrupts(sys: &mut System) {
     }
 }
 
-/// Pushes a value into the PI FIFO. Values are queued up until 32 bytes are available, then
-/// written all at once.
-pub fn fifo_push<P: Primitive>(sys: &mut System, value: P) {
-    value.write_be_bytes(
-        &mut sys.processor.fifo_queue[sys.processor.fifo_queue_index..][..size_of::<P>()],
-    );
-    sys.processor.fifo_queue_index += size_of::<P>();
-
-    if sys.processor.fifo_queue_index < 32 {
-        return;
-    }
-
-    let mut data = [0; 32];
-    data.copy_from_slice(&sys.processor.fifo_queue[..32]);
-
-    for byte in data {
+/// Writes a line burst by the write gather pipe into the PI FIFO.
+pub fn fifo_write_line(sys: &mut System, data: &[u8; 32]) {
+    for &byte in data {
         let current = sys.processor.fifo_current.address();
         sys.write_phys_slow(current, byte);
         sys.processor.fifo_current.set_address(current + 1);
@@ -207,11 +192,6 @@ pub fn fifo_push<P: Primitive>(sys: &mut System, value: P) {
         }
     }
 
-    sys.processor
-        .fifo_queue
-        .copy_within(32..sys.processor.fifo_queue_index, 0);
-    sys.processor.fifo_queue_index -= 32;
-
     if sys.gpu.cmd.control.linked_mode() {
         gx::cmd::sync_to_pi(sys);
         gx::cmd::consume(sys);
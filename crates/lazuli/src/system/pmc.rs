@@ -0,0 +1,35 @@
+//! Performance monitor counters (PMC1-PMC4).
+//!
+//! Events are reported in batches by the CPU core, and counted by whichever counters select them
+//! in MMCR0 and MMCR1.
+use gekko::{Exception, PmcEvent};
+
+use crate::system::System;
+use crate::system::scheduler::{INTERRUPT_PRIORITY, SchedulerEventKind};
+
+/// Counts `count` occurrences of `event`, signaling a performance monitor interrupt if a counter
+/// became negative.
+pub fn increment(sys: &mut System, event: PmcEvent, count: u32) {
+    let msr = &sys.cpu.supervisor.config.msr;
+    if sys.cpu.supervisor.performance.increment(event, count, msr) {
+        tracing::debug!("performance counter became negative, signaling interrupt");
+        sys.scheduler
+            .schedule_now(INTERRUPT_PRIORITY, SchedulerEventKind::PerformanceMonitor);
+    }
+}
+
+/// Takes a signaled performance monitor interrupt, waiting for external interrupts to be
+/// enabled.
+pub fn interrupt(sys: &mut System) {
+    if !sys.cpu.supervisor.config.msr.interrupts() {
+        sys.scheduler
+            .schedule(32, SchedulerEventKind::PerformanceMonitor);
+        return;
+    }
+
+    // taking the interrupt disables further ones until the handler enables them again
+    let performance = &mut sys.cpu.supervisor.performance;
+    performance.control[0] = performance.mmcr0().with_interrupts(false).to_bits();
+
+    sys.cpu.raise_exception(Exception::PerformanceMonitor);
+}
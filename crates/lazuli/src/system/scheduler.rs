@@ -1,8 +1,8 @@
-use std::collections::VecDeque;
+use std::collections::BinaryHeap;
 
 use gekko::Cycles;
 
-use crate::system::System;
+use crate::system::{System, action_replay, ai, di, dspi, gecko, gx, hle, pi, pmc, si, vi};
 
 pub struct HandlerCtx {
     pub cycles_late: Cycles,
@@ -17,37 +17,177 @@ pub enum Handler {
     Full(FullHandler),
 }
 
-impl PartialEq for Handler {
+impl Handler {
     #[inline(always)]
-    fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (Self::Basic(f), Self::Basic(g)) => std::ptr::fn_addr_eq(*f, *g),
-            (Self::Full(f), Self::Full(g)) => std::ptr::fn_addr_eq(*f, *g),
-            _ => false,
+    pub fn call(&self, sys: &mut System, ctx: HandlerCtx) {
+        match self {
+            Self::Basic(f) => f(sys),
+            Self::Full(f) => f(sys, ctx),
         }
     }
 }
 
-impl Eq for Handler {}
+/// Every kind of event which can be scheduled. Unlike handlers, these can be serialized (e.g. for
+/// savestates), and each maps to a static handler at runtime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SchedulerEventKind {
+    /// Checks for pending interrupts.
+    CheckInterrupts,
+    /// The decrementer overflowed.
+    DecrementerOverflow,
+    /// A performance monitor interrupt was signaled.
+    PerformanceMonitor,
+    /// Processes commands in the command processor FIFO.
+    ProcessCommands,
+    /// Display interrupt `N` (0..4) triggered.
+    DisplayInterrupt(u8),
+    /// Polls the controllers.
+    PollControllers,
+    /// The top field was scanned out.
+    TopField,
+    /// The bottom field was scanned out.
+    BottomField,
+    /// A DSP DMA completed.
+    DspDmaComplete,
+    /// Performs an ARAM DMA.
+    AramDma,
+    /// Performs an SI transfer.
+    SiTransfer,
+    /// A DI transfer completed.
+    DiTransferComplete,
+    /// A DI seek completed.
+    DiSeekComplete,
+    /// A disc swap finished.
+    DiscSwap,
+    /// Pushes a frame of streamed audio.
+    StreamingFrame,
+    /// Pushes a block of DMA audio.
+    DmaBlock,
+    /// The AI sample counter reached the interrupt sample.
+    SampleInterrupt,
+    /// Applies Gecko codes.
+    GeckoCodes,
+    /// Applies Action Replay codes.
+    ActionReplayCodes,
+    /// Installs HLE patches if the game executable started running.
+    HlePatches,
+    /// Calls an arbitrary function, scheduled through [`Scheduler::schedule_in`] or
+    /// [`Scheduler::schedule_at`].
+    ///
+    /// Function pointers are only meaningful within the process that created them, so these are
+    /// left out by [`Scheduler::serialize`].
+    Callback(BasicHandler),
+}
 
-impl Handler {
-    #[inline(always)]
-    pub fn call(&self, sys: &mut System, ctx: HandlerCtx) {
+impl SchedulerEventKind {
+    /// The handler of this kind of event.
+    pub fn handler(self) -> Handler {
         match self {
-            Self::Basic(f) => f(sys),
-            Self::Full(f) => f(sys, ctx),
+            Self::CheckInterrupts => Handler::Basic(pi::check_interrupts),
+            Self::DecrementerOverflow => Handler::Basic(System::decrementer_overflow),
+            Self::PerformanceMonitor => Handler::Basic(pmc::interrupt),
+            Self::ProcessCommands => Handler::Basic(gx::cmd::process),
+            Self::DisplayInterrupt(0) => Handler::Basic(vi::display_interrupt::<0>),
+            Self::DisplayInterrupt(1) => Handler::Basic(vi::display_interrupt::<1>),
+            Self::DisplayInterrupt(2) => Handler::Basic(vi::display_interrupt::<2>),
+            Self::DisplayInterrupt(3) => Handler::Basic(vi::display_interrupt::<3>),
+            Self::DisplayInterrupt(n) => panic!("no display interrupt {n}"),
+            Self::PollControllers => Handler::Basic(vi::poll),
+            Self::TopField => Handler::Basic(vi::top_field),
+            Self::BottomField => Handler::Basic(vi::bottom_field),
+            Self::DspDmaComplete => Handler::Basic(dspi::complete_dsp_dma),
+            Self::AramDma => Handler::Basic(dspi::aram_dma),
+            Self::SiTransfer => Handler::Basic(si::do_transfer),
+            Self::DiTransferComplete => Handler::Basic(di::complete_transfer),
+            Self::DiSeekComplete => Handler::Basic(di::complete_seek),
+            Self::DiscSwap => Handler::Basic(di::finish_swap),
+            Self::StreamingFrame => Handler::Full(ai::push_streaming_frame),
+            Self::DmaBlock => Handler::Full(ai::push_data_dma_block),
+            Self::SampleInterrupt => Handler::Basic(ai::sample_interrupt),
+            Self::GeckoCodes => Handler::Basic(gecko::GeckoEngine::apply),
+            Self::ActionReplayCodes => Handler::Basic(action_replay::ActionReplayEngine::apply),
+            Self::HlePatches => Handler::Basic(hle::poll_game),
+            Self::Callback(f) => Handler::Basic(f),
+        }
+    }
+
+    /// Priority events of this kind are restored with.
+    pub fn priority(self) -> u8 {
+        match self {
+            Self::CheckInterrupts | Self::PerformanceMonitor => INTERRUPT_PRIORITY,
+            _ => DEFAULT_PRIORITY,
+        }
+    }
+
+    /// Interval events of this kind repeat at when restored, if they repeat.
+    pub fn repeat_interval(self) -> Option<u64> {
+        match self {
+            Self::ProcessCommands => Some(gx::cmd::PROCESS_INTERVAL),
+            Self::GeckoCodes => Some(gecko::APPLY_INTERVAL),
+            Self::ActionReplayCodes => Some(action_replay::APPLY_INTERVAL),
+            _ => None,
         }
     }
 }
 
+/// Priority of events which have no ordering requirements relative to others.
+pub const DEFAULT_PRIORITY: u8 = 128;
+/// Priority of interrupt checks, which must run before any other event scheduled for the same
+/// cycle.
+pub const INTERRUPT_PRIORITY: u8 = 192;
+
+/// Identifies a scheduled event, so that it can be cancelled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SchedulerToken(u64);
+
 pub struct ScheduledEvent {
     pub cycle: u64,
-    pub handler: Handler,
+    /// Events scheduled for the same cycle fire in order of decreasing priority.
+    pub priority: u8,
+    pub kind: SchedulerEventKind,
+    /// Insertion order, which keeps events with the same cycle and priority in FIFO order.
+    sequence: u64,
+    /// Token of the event. Kept by repeating events when they are rescheduled.
+    token: SchedulerToken,
+    /// Interval of repeating events.
+    repeat: Option<u64>,
+}
+
+impl ScheduledEvent {
+    #[inline(always)]
+    fn key(&self) -> (u64, u8, u64) {
+        (self.cycle, u8::MAX - self.priority, self.sequence)
+    }
+}
+
+impl PartialEq for ScheduledEvent {
+    #[inline(always)]
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for ScheduledEvent {}
+
+impl PartialOrd for ScheduledEvent {
+    #[inline(always)]
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ScheduledEvent {
+    /// Reversed, so that the earliest event is at the top of the heap.
+    #[inline(always)]
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        other.key().cmp(&self.key())
+    }
 }
 
 pub struct Scheduler {
     elapsed: u64,
-    scheduled: VecDeque<ScheduledEvent>,
+    sequence: u64,
+    scheduled: BinaryHeap<ScheduledEvent>,
 }
 
 impl std::fmt::Debug for Scheduler {
@@ -63,53 +203,120 @@ impl Default for Scheduler {
     fn default() -> Self {
         Self {
             elapsed: 0,
-            scheduled: VecDeque::with_capacity(16),
+            sequence: 0,
+            scheduled: BinaryHeap::with_capacity(16),
         }
     }
 }
 
 impl Scheduler {
     #[inline(always)]
-    pub fn schedule(&mut self, after: u64, handler: BasicHandler) {
-        let cycle = self.elapsed + after;
-        let index = self.scheduled.partition_point(|e| e.cycle <= cycle);
-        self.scheduled.insert(
-            index,
-            ScheduledEvent {
-                cycle,
-                handler: Handler::Basic(handler),
-            },
-        );
+    fn push(
+        &mut self,
+        cycle: u64,
+        priority: u8,
+        kind: SchedulerEventKind,
+        token: Option<SchedulerToken>,
+        repeat: Option<u64>,
+    ) -> SchedulerToken {
+        let sequence = self.sequence;
+        self.sequence += 1;
+
+        let token = token.unwrap_or(SchedulerToken(sequence));
+        self.scheduled.push(ScheduledEvent {
+            cycle,
+            priority,
+            kind,
+            sequence,
+            token,
+            repeat,
+        });
+
+        token
     }
 
     #[inline(always)]
-    pub fn schedule_now(&mut self, handler: BasicHandler) {
-        self.schedule(0, handler)
+    fn insert(&mut self, after: u64, priority: u8, kind: SchedulerEventKind) -> SchedulerToken {
+        self.push(self.elapsed + after, priority, kind, None, None)
     }
 
     #[inline(always)]
-    pub fn schedule_full(&mut self, after: u64, handler: FullHandler) {
-        let cycle = self.elapsed + after;
-        let index = self.scheduled.partition_point(|e| e.cycle <= cycle);
-        self.scheduled.insert(
-            index,
-            ScheduledEvent {
-                cycle,
-                handler: Handler::Full(handler),
-            },
-        );
+    pub fn schedule(&mut self, after: u64, kind: SchedulerEventKind) -> SchedulerToken {
+        self.insert(after, DEFAULT_PRIORITY, kind)
     }
 
     #[inline(always)]
-    pub fn cancel(&mut self, handler: BasicHandler) {
-        let handler = Handler::Basic(handler);
-        self.scheduled.retain(|e| e.handler != handler);
+    pub fn schedule_with_priority(
+        &mut self,
+        after: u64,
+        priority: u8,
+        kind: SchedulerEventKind,
+    ) -> SchedulerToken {
+        self.insert(after, priority, kind)
     }
 
     #[inline(always)]
-    pub fn cancel_full(&mut self, handler: FullHandler) {
-        let handler = Handler::Full(handler);
-        self.scheduled.retain(|e| e.handler != handler);
+    pub fn schedule_now(&mut self, priority: u8, kind: SchedulerEventKind) -> SchedulerToken {
+        self.insert(0, priority, kind)
+    }
+
+    /// Schedules `callback` to be called `cycles` from now. The returned token can be used to
+    /// cancel it.
+    pub fn schedule_in(&mut self, cycles: Cycles, callback: BasicHandler) -> SchedulerToken {
+        self.schedule(cycles.0, SchedulerEventKind::Callback(callback))
+    }
+
+    /// Schedules `callback` to be called once [`Scheduler::elapsed`] reaches `cycle`. If it has
+    /// already been reached, the callback is called as soon as events are next processed.
+    pub fn schedule_at(&mut self, cycle: Cycles, callback: BasicHandler) -> SchedulerToken {
+        self.push(
+            cycle.0,
+            DEFAULT_PRIORITY,
+            SchedulerEventKind::Callback(callback),
+            None,
+            None,
+        )
+    }
+
+    /// Schedules an event which fires every `interval` cycles, starting `interval` cycles from
+    /// now, until cancelled. It is rescheduled relative to the cycle it was due, so handlers that
+    /// run late do not cause drift.
+    pub fn schedule_repeating(
+        &mut self,
+        interval: u64,
+        kind: SchedulerEventKind,
+    ) -> SchedulerToken {
+        assert!(
+            interval > 0,
+            "repeating events must have a non-zero interval"
+        );
+
+        self.push(
+            self.elapsed + interval,
+            DEFAULT_PRIORITY,
+            kind,
+            None,
+            Some(interval),
+        )
+    }
+
+    /// Cancels the event with the given token. Returns whether it was still pending, i.e. `false`
+    /// if it already fired or was cancelled before.
+    pub fn cancel(&mut self, token: SchedulerToken) -> bool {
+        let len = self.scheduled.len();
+        self.scheduled.retain(|e| e.token != token);
+        self.scheduled.len() != len
+    }
+
+    /// Stops a repeating event. Does nothing if it has already been cancelled.
+    pub fn cancel_repeating(&mut self, token: SchedulerToken) {
+        self.cancel(token);
+    }
+
+    /// Cancels every pending event of the given kind.
+    #[inline(always)]
+    pub fn cancel_kind(&mut self, kind: SchedulerEventKind) {
+        self.scheduled.retain(|e| e.kind != kind);
     }
 
     #[inline(always)]
@@ -130,25 +337,91 @@ impl Scheduler {
     #[inline(always)]
     pub fn until_next(&self) -> Option<u64> {
         self.scheduled
-            .front()
+            .peek()
             .map(|e| e.cycle.saturating_sub(self.elapsed))
     }
 
+    /// Whether any event is due, i.e. whether [`Scheduler::pop`] would return an event.
     #[inline(always)]
-    pub fn pop(&mut self) -> Option<ScheduledEvent> {
-        self.scheduled.pop_front_if(|e| e.cycle <= self.elapsed)
+    pub fn is_due(&self) -> bool {
+        self.scheduled
+            .peek()
+            .is_some_and(|e| e.cycle <= self.elapsed)
     }
 
+    /// Pops the next event which is due, if any. Repeating events are rescheduled before being
+    /// returned, so their handlers are free to cancel them.
     #[inline(always)]
-    pub fn contains(&self, handler: BasicHandler) -> bool {
-        let handler = Handler::Basic(handler);
-        self.scheduled.iter().any(|e| e.handler == handler)
+    pub fn pop(&mut self) -> Option<ScheduledEvent> {
+        if self.scheduled.peek()?.cycle > self.elapsed {
+            return None;
+        }
+
+        let event = self.scheduled.pop()?;
+        if let Some(interval) = event.repeat {
+            self.push(
+                event.cycle + interval,
+                event.priority,
+                event.kind,
+                Some(event.token),
+                event.repeat,
+            );
+        }
+
+        Some(event)
     }
 
+    /// Whether an event of the given kind is pending.
     #[inline(always)]
-    pub fn contains_full(&self, handler: FullHandler) -> bool {
-        let handler = Handler::Full(handler);
-        self.scheduled.iter().any(|e| e.handler == handler)
+    pub fn contains(&self, kind: SchedulerEventKind) -> bool {
+        self.scheduled.iter().any(|e| e.kind == kind)
+    }
+
+    /// Returns the token of the next pending event of the given kind, if any.
+    pub fn find(&self, kind: SchedulerEventKind) -> Option<SchedulerToken> {
+        self.scheduled
+            .iter()
+            .filter(|e| e.kind == kind)
+            .min_by_key(|e| e.key())
+            .map(|e| e.token)
+    }
+
+    /// Returns the pending events in the order they will fire, each with the number of cycles
+    /// until it is due.
+    ///
+    /// Callbacks can't be persisted, so they're skipped with a warning.
+    pub fn serialize(&self) -> Vec<(u64, SchedulerEventKind)> {
+        let mut events = self.scheduled.iter().collect::<Vec<_>>();
+        events.sort_by_key(|e| e.key());
+        events
+            .into_iter()
+            .filter(|e| {
+                let callback = matches!(e.kind, SchedulerEventKind::Callback(_));
+                if callback {
+                    tracing::warn!("skipping callback scheduled for cycle {}", e.cycle);
+                }
+
+                !callback
+            })
+            .map(|e| (e.cycle.saturating_sub(self.elapsed), e.kind))
+            .collect()
+    }
+
+    /// Replaces the pending events with the given ones, as returned by [`Scheduler::serialize`].
+    /// Priorities and repetition are restored according to the kind of each event.
+    ///
+    /// Events get new tokens, so tokens of the previous events must not be used anymore.
+    pub fn restore(&mut self, events: Vec<(u64, SchedulerEventKind)>) {
+        self.scheduled.clear();
+        for (after, kind) in events {
+            self.push(
+                self.elapsed + after,
+                kind.priority(),
+                kind,
+                None,
+                kind.repeat_interval(),
+            );
+        }
     }
 
     /// How many CPU cycles have elapsed.
@@ -163,3 +436,153 @@ impl Scheduler {
         self.elapsed / 12
     }
 }
+
+#[cfg(test)]
+mod test {
+    use gekko::Cycles;
+
+    use super::{DEFAULT_PRIORITY, INTERRUPT_PRIORITY, Scheduler, SchedulerEventKind};
+
+    // events are never fired here, so any kinds do
+    const A: SchedulerEventKind = SchedulerEventKind::TopField;
+    const B: SchedulerEventKind = SchedulerEventKind::BottomField;
+    const C: SchedulerEventKind = SchedulerEventKind::PollControllers;
+
+    fn drain(scheduler: &mut Scheduler) -> Vec<SchedulerEventKind> {
+        std::iter::from_fn(|| scheduler.pop())
+            .map(|e| e.kind)
+            .collect()
+    }
+
+    #[test]
+    fn ordering() {
+        let mut scheduler = Scheduler::default();
+        scheduler.schedule(10, A);
+        scheduler.schedule(5, B);
+        scheduler.schedule(10, C);
+        scheduler.schedule_with_priority(10, INTERRUPT_PRIORITY, B);
+
+        assert_eq!(scheduler.until_next(), Some(5));
+        scheduler.advance(5);
+        assert_eq!(drain(&mut scheduler), [B]);
+
+        // same cycle: higher priority first, then insertion order
+        scheduler.advance(5);
+        assert_eq!(drain(&mut scheduler), [B, A, C]);
+        assert!(scheduler.is_empty());
+    }
+
+    #[test]
+    fn schedule_now_priority() {
+        let mut scheduler = Scheduler::default();
+        scheduler.schedule_now(DEFAULT_PRIORITY, A);
+        scheduler.schedule_now(DEFAULT_PRIORITY - 1, B);
+        scheduler.schedule_now(INTERRUPT_PRIORITY, C);
+        scheduler.cancel_kind(A);
+
+        assert_eq!(drain(&mut scheduler), [C, B]);
+    }
+
+    #[test]
+    fn repeating() {
+        let mut scheduler = Scheduler::default();
+        let token = scheduler.schedule_repeating(10, A);
+        scheduler.schedule(15, B);
+
+        scheduler.advance(10);
+        assert_eq!(drain(&mut scheduler), [A]);
+
+        // late by 7 cycles: fires once and stays aligned to its interval
+        scheduler.advance(17);
+        assert_eq!(drain(&mut scheduler), [B, A]);
+        assert_eq!(scheduler.until_next(), Some(3));
+
+        scheduler.cancel_repeating(token);
+        assert!(scheduler.is_empty());
+
+        // cancelling twice is fine
+        scheduler.cancel_repeating(token);
+    }
+
+    #[test]
+    fn cancel_token() {
+        let mut scheduler = Scheduler::default();
+        let first = scheduler.schedule(10, A);
+        let second = scheduler.schedule(10, A);
+        let third = scheduler.schedule(5, B);
+
+        // only the event with the token is cancelled, even if others share its kind
+        assert!(scheduler.cancel(first));
+        assert!(!scheduler.cancel(first));
+
+        scheduler.advance(10);
+        assert_eq!(drain(&mut scheduler), [B, A]);
+
+        // fired events can't be cancelled
+        assert!(!scheduler.cancel(second));
+        assert!(!scheduler.cancel(third));
+    }
+
+    #[test]
+    fn serialize_roundtrip() {
+        let mut scheduler = Scheduler::default();
+        scheduler.advance(100);
+        scheduler.schedule(10, A);
+        scheduler.schedule(5, B);
+        scheduler.schedule(10, C);
+        scheduler.schedule_now(INTERRUPT_PRIORITY, SchedulerEventKind::CheckInterrupts);
+        scheduler.schedule_repeating(
+            crate::system::gx::cmd::PROCESS_INTERVAL,
+            SchedulerEventKind::ProcessCommands,
+        );
+
+        let events = scheduler.serialize();
+        assert_eq!(
+            events,
+            [
+                (0, SchedulerEventKind::CheckInterrupts),
+                (5, B),
+                (10, A),
+                (10, C),
+                (
+                    crate::system::gx::cmd::PROCESS_INTERVAL,
+                    SchedulerEventKind::ProcessCommands
+                ),
+            ]
+        );
+
+        let mut restored = Scheduler::default();
+        restored.advance(100);
+        restored.restore(events.clone());
+        assert_eq!(restored.serialize(), events);
+
+        // priorities and repetition come back with the events
+        restored.advance(10);
+        assert_eq!(
+            drain(&mut restored),
+            [SchedulerEventKind::CheckInterrupts, B, A, C]
+        );
+
+        restored.advance(crate::system::gx::cmd::PROCESS_INTERVAL);
+        assert_eq!(drain(&mut restored), [SchedulerEventKind::ProcessCommands]);
+        assert!(restored.contains(SchedulerEventKind::ProcessCommands));
+    }
+
+    #[test]
+    fn serialize_skips_callbacks() {
+        fn callback(_: &mut crate::system::System) {}
+
+        let mut scheduler = Scheduler::default();
+        scheduler.schedule(10, A);
+        scheduler.schedule_in(Cycles(5), callback);
+        scheduler.schedule(20, B);
+
+        let events = scheduler.serialize();
+        assert_eq!(events, [(10, A), (20, B)]);
+
+        let mut restored = Scheduler::default();
+        restored.restore(events);
+        restored.advance(20);
+        assert_eq!(drain(&mut restored), [A, B]);
+    }
+}
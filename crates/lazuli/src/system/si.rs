@@ -1,10 +1,16 @@
 //! Serial interface (SI).
+use std::ops::Range;
+
 use bitos::integer::{u2, u7, u10};
 use bitos::{BitUtils, bitos};
+use gekko::Cycles;
 use strum::FromRepr;
 use zerocopy::IntoBytes;
 
-use crate::system::{System, pi};
+use crate::Primitive;
+use crate::system::bus::{self, Device, Mmio};
+use crate::system::scheduler::SchedulerEventKind;
+use crate::system::{System, pi, vi};
 
 #[bitos(1)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
@@ -235,7 +241,12 @@ pub fn poll_controller(sys: &mut System, channel: usize) {
         return;
     }
 
-    let controller = sys.modules.input.controller(channel).unwrap_or_default();
+    let time = Cycles(sys.scheduler.elapsed());
+    let controller = sys
+        .modules
+        .input
+        .controller(channel, time)
+        .unwrap_or_default();
     let data = StandardController::from_bits(0)
         .with_analog_y(controller.analog_y)
         .with_analog_x(controller.analog_x)
@@ -293,7 +304,8 @@ fn process_cmd(sys: &mut System, channel: usize) {
     }
 }
 
-fn do_transfer(sys: &mut System) {
+/// Performs the transfer started in the communication control register.
+pub fn do_transfer(sys: &mut System) {
     // dbg!(sys.serial.comm_control);
     tracing::debug!("transfer");
 
@@ -342,7 +354,7 @@ pub fn write_comm_control(sys: &mut System, value: CommControl) {
     );
 
     if value.transfer_start() {
-        sys.scheduler.schedule(200, do_transfer);
+        sys.scheduler.schedule(200, SchedulerEventKind::SiTransfer);
     }
 }
 
@@ -359,3 +371,100 @@ pub fn write_status(sys: &mut System, value: Status) {
         }
     }
 }
+
+impl Device for Interface {
+    const NAME: &'static str = "SI";
+    const RANGE: Range<u16> = 0x6400..0x6500;
+
+    fn read<P: Primitive>(sys: &mut System, offset: u16) -> P {
+        let Some((reg, offset, range)) = bus::resolve::<P>(offset) else {
+            tracing::error!(pc = ?sys.cpu.pc, "reading from unknown SI register ({offset:04X})");
+            return P::default();
+        };
+
+        let serial = &sys.serial;
+        let bytes = match reg {
+            Mmio::SerialOutputBuf0 => serial.channel_output[0].data.as_bytes(),
+            Mmio::SerialInput0High => serial.channel_input[0].high.as_bytes(),
+            Mmio::SerialInput0Low => serial.channel_input[0].low.as_bytes(),
+            Mmio::SerialOutputBuf1 => serial.channel_output[1].data.as_bytes(),
+            Mmio::SerialInput1High => serial.channel_input[1].high.as_bytes(),
+            Mmio::SerialInput1Low => serial.channel_input[1].low.as_bytes(),
+            Mmio::SerialOutputBuf2 => serial.channel_output[2].data.as_bytes(),
+            Mmio::SerialInput2High => serial.channel_input[2].high.as_bytes(),
+            Mmio::SerialInput2Low => serial.channel_input[2].low.as_bytes(),
+            Mmio::SerialOutputBuf3 => serial.channel_output[3].data.as_bytes(),
+            Mmio::SerialInput3High => serial.channel_input[3].high.as_bytes(),
+            Mmio::SerialInput3Low => serial.channel_input[3].low.as_bytes(),
+            Mmio::SerialPoll => serial.poll.as_bytes(),
+            Mmio::SerialCommControl => serial.comm_control.as_bytes(),
+            Mmio::SerialStatus => serial.status.as_bytes(),
+            Mmio::SerialBuffer => {
+                return P::read_be_bytes(&serial.buffer[offset..offset + size_of::<P>()]);
+            }
+            _ => {
+                tracing::warn!(pc = ?sys.cpu.pc, "unimplemented read from known mmio register ({reg:?})");
+                return P::default();
+            }
+        };
+
+        let value = P::read_ne_bytes(&bytes[range.clone()]);
+        tracing::debug!(pc = ?sys.cpu.pc, "reading from {reg:?}[{range:?}]: {value:08X}");
+
+        value
+    }
+
+    fn write<P: Primitive>(sys: &mut System, offset: u16, value: P) {
+        let Some((reg, offset, range)) = bus::resolve::<P>(offset) else {
+            tracing::error!("writing 0x{value:08X} to unknown SI register ({offset:04X})");
+            return;
+        };
+
+        tracing::debug!(pc = ?sys.cpu.pc, "writing 0x{value:08X} to {reg:?}[{range:?}]");
+
+        // write to native endian bytes
+        macro_rules! ne {
+            ($bytes:expr) => {
+                value.write_ne_bytes(&mut $bytes[range.clone()])
+            };
+        }
+
+        match reg {
+            Mmio::SerialOutputBuf0 => {
+                ne!(sys.serial.channel_output[0].data.as_mut_bytes());
+                sys.serial.channel_output[0].dirty = true;
+            }
+            Mmio::SerialOutputBuf1 => {
+                ne!(sys.serial.channel_output[1].data.as_mut_bytes());
+                sys.serial.channel_output[1].dirty = true;
+            }
+            Mmio::SerialOutputBuf2 => {
+                ne!(sys.serial.channel_output[2].data.as_mut_bytes());
+                sys.serial.channel_output[2].dirty = true;
+            }
+            Mmio::SerialOutputBuf3 => {
+                ne!(sys.serial.channel_output[3].data.as_mut_bytes());
+                sys.serial.channel_output[3].dirty = true;
+            }
+            Mmio::SerialPoll => {
+                ne!(sys.serial.poll.as_mut_bytes());
+                tracing::debug!("SI poll: {:?}", sys.serial.poll);
+                vi::schedule_poll(sys);
+            }
+            Mmio::SerialCommControl => {
+                let mut written = sys.serial.comm_control;
+                ne!(written.as_mut_bytes());
+                self::write_comm_control(sys, written);
+            }
+            Mmio::SerialStatus => {
+                let mut written = sys.serial.status;
+                ne!(written.as_mut_bytes());
+                self::write_status(sys, written);
+            }
+            Mmio::SerialBuffer => {
+                value.write_be_bytes(&mut sys.serial.buffer[offset..offset + size_of::<P>()])
+            }
+            _ => tracing::warn!("unimplemented write to known mmio register ({reg:?})"),
+        }
+    }
+}
@@ -4,6 +4,7 @@ use bitos::integer::{u4, u7, u9, u10, u24};
 use gekko::{Address, FREQUENCY};
 
 use crate::modules::render;
+use crate::system::scheduler::SchedulerEventKind;
 use crate::system::{System, pi, si};
 
 #[bitos(16)]
@@ -59,11 +60,48 @@ pub struct DisplayConfig {
     /// The current field mode.
     #[bits(2)]
     pub field_mode: FieldMode,
+    /// Whether 3D display mode is enabled.
+    #[bits(3)]
+    pub stereoscopic: bool,
+    /// Latch mode of display latch 0.
+    #[bits(4..6)]
+    pub latch_0: DisplayLatchMode,
+    /// Latch mode of display latch 1.
+    #[bits(6..8)]
+    pub latch_1: DisplayLatchMode,
     /// Current video format.
     #[bits(8..10)]
     pub video_format: VideoFormat,
 }
 
+/// A field of a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Field {
+    /// The odd field, which is the first one to be scanned out.
+    #[default]
+    Top,
+    /// The even field.
+    Bottom,
+}
+
+/// Which field's lines come first when the fields are interleaved into a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FieldOrder {
+    #[default]
+    TopFirst,
+    BottomFirst,
+}
+
+impl FieldOrder {
+    /// Row of the frame in which the first line of the given field goes.
+    pub fn parity(self, field: Field) -> u32 {
+        match (self, field) {
+            (Self::TopFirst, Field::Top) | (Self::BottomFirst, Field::Bottom) => 0,
+            _ => 1,
+        }
+    }
+}
+
 #[bitos(64)]
 #[derive(Debug, Clone, Copy, Default)]
 pub struct HorizontalTiming {
@@ -184,7 +222,16 @@ pub enum VideoMode {
     Progressive,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Position of the electron beam, as seen through the vertical and horizontal count registers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BeamPosition {
+    /// The line the beam is at, counting from one.
+    pub vertical: u16,
+    /// The sample the beam is at in its line, counting from one.
+    pub horizontal: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub struct Dimensions {
     pub width: u16,
     pub height: u16,
@@ -196,6 +243,47 @@ impl Dimensions {
     }
 }
 
+/// Timing parameters of the video output, in CPU cycles and halflines.
+///
+/// These are cached from the timing registers whenever they're written so that the beam position
+/// can be rebased when a game changes them mid-frame.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Timing {
+    pub cycles_per_sample: u32,
+    pub cycles_per_halfline: u32,
+    pub halflines_per_top_field: u32,
+    pub halflines_per_frame: u32,
+}
+
+impl Timing {
+    /// How many CPU cycles long a frame is.
+    pub fn cycles_per_frame(&self) -> u64 {
+        self.cycles_per_halfline as u64 * self.halflines_per_frame as u64
+    }
+
+    /// Whether this timing describes an empty frame, in which case the beam never moves.
+    pub fn is_degenerate(&self) -> bool {
+        self.cycles_per_frame() == 0
+    }
+}
+
+/// Video configuration latched at vsync, which is used to present the field that follows.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Latched {
+    pub frame_dimensions: Dimensions,
+    pub xfb_stride: u16,
+    /// The field being scanned out.
+    pub field: Field,
+    /// Whether the fields have to be interleaved, i.e. the video is interlaced and each field
+    /// comes from its own image instead of a full-frame XFB.
+    pub weave_fields: bool,
+    pub field_order: FieldOrder,
+    /// Address of the XFB of the field being scanned out.
+    pub field_base: Address,
+    /// Stride of the lines of a field in the XFB, in pixels.
+    pub field_stride: u16,
+}
+
 #[derive(Debug, Default)]
 pub struct Interface {
     pub display_config: DisplayConfig,
@@ -207,12 +295,16 @@ pub struct Interface {
     pub bottom_vertical_timing: FieldVerticalTiming,
     pub bottom_base_left: FieldBase,
     pub bottom_base_right: u32,
-    pub vertical_count: u16,
-    pub horizontal_count: u16,
     pub interrupts: [DisplayInterrupt; 4],
     pub xfb_width: ExternalFramebufferWidth,
     pub horizontal_scaling: HorizontalScaling,
     pub clock: ClockMode,
+    /// Cycle at which some frame started. The beam position is derived from it.
+    pub frame_start: u64,
+    pub timing: Timing,
+    pub latched: Latched,
+    /// How many times each display interrupt has been triggered.
+    pub interrupt_counts: [u64; 4],
 }
 
 impl Interface {
@@ -328,6 +420,36 @@ impl Interface {
         self.xfb_width.stride() / 2
     }
 
+    /// Stride of the lines of a single field in an external framebuffer, in pixels.
+    pub fn field_stride(&self) -> u16 {
+        // a field skips every other row of the frame
+        self.xfb_stride() * 2
+    }
+
+    /// Whether both fields are stored in a single full-frame XFB, i.e. the first line of one field
+    /// sits right after the first line of the other.
+    pub fn full_frame_xfb(&self) -> bool {
+        let distance = self
+            .top_xfb_address()
+            .value()
+            .abs_diff(self.bottom_xfb_address().value());
+
+        // YCbYCr format has 2 bytes per pixel
+        distance == self.xfb_stride() as u32 * 2
+    }
+
+    /// Which field's lines come first in a frame. The field with the shortest pre-blanking
+    /// interval starts its active video earlier, so its lines are the upper ones.
+    pub fn field_order(&self) -> FieldOrder {
+        let top = self.top_vertical_timing.pre_blanking().value();
+        let bottom = self.bottom_vertical_timing.pre_blanking().value();
+        if bottom < top {
+            FieldOrder::BottomFirst
+        } else {
+            FieldOrder::TopFirst
+        }
+    }
+
     /// Dimensions of the entire frame, which may consist of either one or two extenral
     /// framebuffers.
     pub fn frame_dimensions(&self) -> Dimensions {
@@ -375,89 +497,239 @@ impl Interface {
         }
     }
 
+    /// Computes the timing parameters from the current register values.
+    pub fn compute_timing(&self) -> Timing {
+        Timing {
+            cycles_per_sample: self.cycles_per_sample(),
+            cycles_per_halfline: self.cycles_per_halfline(),
+            halflines_per_top_field: self.halflines_per_top_field(),
+            halflines_per_frame: self.halflines_per_frame(),
+        }
+    }
+
+    /// Offset, in CPU cycles, of the given cycle from the start of the frame it's in.
+    pub fn frame_offset(&self, cycle: u64) -> u64 {
+        cycle
+            .saturating_sub(self.frame_start)
+            .checked_rem(self.timing.cycles_per_frame())
+            .unwrap_or(0)
+    }
+
+    /// The halfline the beam is at on the given cycle, counting from zero at the start of the
+    /// frame.
+    pub fn halfline_at(&self, cycle: u64) -> u32 {
+        self.frame_offset(cycle)
+            .checked_div(self.timing.cycles_per_halfline as u64)
+            .unwrap_or(0) as u32
+    }
+
+    /// The line the beam is at on the given cycle, counting from one. This is the value of the
+    /// vertical count register.
+    pub fn vertical_count_at(&self, cycle: u64) -> u16 {
+        (self.halfline_at(cycle) / 2 + 1) as u16
+    }
+
+    /// The sample the beam is at on the given cycle, counting from one. This is the value of the
+    /// horizontal count register.
+    pub fn horizontal_count_at(&self, cycle: u64) -> u16 {
+        let cycles_per_line = 2 * self.timing.cycles_per_halfline as u64;
+        let in_line = self
+            .frame_offset(cycle)
+            .checked_rem(cycles_per_line)
+            .unwrap_or(0);
+
+        let sample = in_line
+            .checked_div(self.timing.cycles_per_sample as u64)
+            .unwrap_or(0);
+
+        (sample + 1) as u16
+    }
+
+    /// Position of the beam on the given cycle.
+    pub fn beam_position_at(&self, cycle: u64) -> BeamPosition {
+        BeamPosition {
+            vertical: self.vertical_count_at(cycle),
+            horizontal: self.horizontal_count_at(cycle),
+        }
+    }
+
+    /// Offset from the start of the frame, in CPU cycles, at which the given display interrupt
+    /// triggers. Returns [`None`] if the position is outside of the frame.
+    pub fn interrupt_offset(&self, interrupt: DisplayInterrupt) -> Option<u64> {
+        let line = interrupt.vertical_count().value().saturating_sub(1) as u64;
+        let sample = interrupt.horizontal_count().value().saturating_sub(1) as u64;
+        let offset = line * 2 * self.timing.cycles_per_halfline as u64
+            + sample * self.timing.cycles_per_sample as u64;
+
+        (offset < self.timing.cycles_per_frame()).then_some(offset)
+    }
+
     pub fn write_interrupt<const N: usize>(&mut self, new: DisplayInterrupt) {
         const { assert!(N < 4) };
         self.interrupts[N] = new.with_status(self.interrupts[N].status() && new.status());
     }
 }
 
-pub fn update_display_interrupts(sys: &mut System) {
-    let video_width = sys.video.video_width();
-    let mut raised = false;
-    for interrupt in sys.video.interrupts.iter_mut() {
-        interrupt.set_status(false);
-        if !interrupt.enable() {
-            continue;
-        }
+/// How many cycles from now until the beam reaches the given offset from the start of a frame.
+///
+/// If the beam is exactly at the offset, this is a whole frame.
+fn cycles_until(sys: &System, offset: u64) -> u64 {
+    let current = sys.video.frame_offset(sys.scheduler.elapsed());
+    if offset > current {
+        offset - current
+    } else {
+        sys.video.timing.cycles_per_frame() - current + offset
+    }
+}
 
-        if interrupt.horizontal_count().value() > video_width {
-            continue;
-        }
+/// Current position of the beam.
+pub fn beam_position(sys: &System) -> BeamPosition {
+    sys.video.beam_position_at(sys.scheduler.elapsed())
+}
 
-        sys.video.horizontal_count = sys
-            .video
-            .horizontal_count
-            .max(interrupt.horizontal_count().value());
+/// Display interrupt `N` triggered.
+pub fn display_interrupt<const N: usize>(sys: &mut System) {
+    sys.video.interrupts[N].set_status(true);
+    sys.video.interrupt_counts[N] += 1;
+    pi::check_interrupts(sys);
 
-        if interrupt.vertical_count().value() == sys.video.vertical_count {
-            raised = true;
-            interrupt.set_status(true);
-        }
+    self::schedule_interrupt::<N>(sys);
+}
+
+/// Schedules the next trigger of display interrupt `N`. Must be called whenever the interrupt
+/// register or the video timing changes.
+pub fn schedule_interrupt<const N: usize>(sys: &mut System) {
+    sys.scheduler
+        .cancel_kind(SchedulerEventKind::DisplayInterrupt(N as u8));
+    if !sys.video.display_config.enable() || sys.video.timing.is_degenerate() {
+        return;
     }
 
-    if raised {
-        pi::check_interrupts(sys);
+    let interrupt = sys.video.interrupts[N];
+    if !interrupt.enable() {
+        return;
+    }
+
+    if let Some(offset) = sys.video.interrupt_offset(interrupt) {
+        let after = self::cycles_until(sys, offset);
+        sys.scheduler
+            .schedule(after, SchedulerEventKind::DisplayInterrupt(N as u8));
     }
 }
 
-pub fn vertical_count(sys: &mut System) {
-    self::update_display_interrupts(sys);
+/// Polls the controllers and schedules the next poll.
+pub fn poll(sys: &mut System) {
+    si::poll_controller(sys, 0);
+    si::poll_controller(sys, 1);
+    si::poll_controller(sys, 2);
+    si::poll_controller(sys, 3);
 
-    let start_of_top_field = sys.video.vertical_count == 1;
-    let start_of_bottom_field = sys.video.display_config.field_mode() == FieldMode::Double
-        && sys.video.vertical_count as u32 == sys.video.lines_per_frame() / 2 + 1;
+    self::schedule_poll(sys);
+}
 
-    if start_of_top_field || start_of_bottom_field {
-        self::present(sys);
+/// Schedules the next controller poll, which happens every `x_lines` lines of a frame. Must be
+/// called whenever the SI poll register or the video timing changes.
+pub fn schedule_poll(sys: &mut System) {
+    sys.scheduler
+        .cancel_kind(SchedulerEventKind::PollControllers);
+    if !sys.video.display_config.enable() || sys.video.timing.is_degenerate() {
+        return;
     }
 
-    sys.video.vertical_count += 1;
-    sys.video.horizontal_count = 1;
-
-    if sys.video.vertical_count as u32 > sys.video.lines_per_frame() {
-        sys.video.vertical_count = 1;
+    let x_lines = sys.serial.poll.x_lines().value();
+    if x_lines == 0 {
+        return;
     }
 
-    if sys
-        .video
-        .vertical_count
-        .is_multiple_of(sys.serial.poll.x_lines().value())
-    {
-        si::poll_controller(sys, 0);
-        si::poll_controller(sys, 1);
-        si::poll_controller(sys, 2);
-        si::poll_controller(sys, 3);
+    let lines_per_frame = sys.video.timing.halflines_per_frame / 2;
+    let current = sys.video.vertical_count_at(sys.scheduler.elapsed());
+    let mut next = (current / x_lines + 1) * x_lines;
+    if next as u32 > lines_per_frame {
+        next = x_lines;
     }
 
-    let cycles_per_frame = (FREQUENCY as f64 / sys.video.frame_rate()) as u32;
-    let cycles_per_line = cycles_per_frame
-        .checked_div(sys.video.lines_per_frame())
-        .unwrap_or(cycles_per_frame);
+    let offset = (next as u64 - 1) * 2 * sys.video.timing.cycles_per_halfline as u64;
+    let after = self::cycles_until(sys, offset);
+    sys.scheduler
+        .schedule(after, SchedulerEventKind::PollControllers);
+}
+
+/// Start of the top field.
+pub fn top_field(sys: &mut System) {
+    self::vsync(sys, Field::Top);
+
+    let after = self::cycles_until(sys, 0);
+    sys.scheduler.schedule(after, SchedulerEventKind::TopField);
+}
 
+/// Start of the bottom field.
+pub fn bottom_field(sys: &mut System) {
+    self::vsync(sys, Field::Bottom);
+
+    let offset = sys.video.timing.halflines_per_top_field as u64
+        * sys.video.timing.cycles_per_halfline as u64;
+    let after = self::cycles_until(sys, offset);
     sys.scheduler
-        .schedule(cycles_per_line as u64, self::vertical_count);
+        .schedule(after, SchedulerEventKind::BottomField);
+}
+
+/// Vertical sync, which happens at the start of every field.
+///
+/// The video configuration is latched here and the XFB copies made during the last field are
+/// presented.
+pub fn vsync(sys: &mut System, field: Field) {
+    let video = &sys.video;
+    let field_base = match field {
+        Field::Top => video.top_xfb_address(),
+        Field::Bottom => video.bottom_xfb_address(),
+    };
+
+    sys.video.latched = Latched {
+        frame_dimensions: video.frame_dimensions(),
+        xfb_stride: video.xfb_stride(),
+        field,
+        weave_fields: video.video_mode() == VideoMode::Interlaced && !video.full_frame_xfb(),
+        field_order: video.field_order(),
+        field_base,
+        field_stride: video.field_stride(),
+    };
+
+    self::present(sys);
 }
 
+/// Updates the video timing after a change to the display configuration or timing registers.
+///
+/// The beam keeps its current halfline, so the frame start is rebased according to the new
+/// timing and every timing event is rescheduled.
 pub fn update(sys: &mut System) {
-    if sys.video.vertical_count as u32 > sys.video.lines_per_frame() {
-        sys.video.horizontal_count = 1;
-        sys.video.vertical_count = 1;
+    let now = sys.scheduler.elapsed();
+    let halfline = sys.video.halfline_at(now) as u64;
+
+    sys.video.timing = sys.video.compute_timing();
+    sys.video.frame_start =
+        now.saturating_sub(halfline * sys.video.timing.cycles_per_halfline as u64);
+
+    sys.scheduler.cancel_kind(SchedulerEventKind::TopField);
+    sys.scheduler.cancel_kind(SchedulerEventKind::BottomField);
+
+    if sys.video.display_config.enable() && !sys.video.timing.is_degenerate() {
+        let after = self::cycles_until(sys, 0);
+        sys.scheduler.schedule(after, SchedulerEventKind::TopField);
+
+        if sys.video.display_config.field_mode() == FieldMode::Double {
+            let offset = sys.video.timing.halflines_per_top_field as u64
+                * sys.video.timing.cycles_per_halfline as u64;
+            let after = self::cycles_until(sys, offset);
+            sys.scheduler
+                .schedule(after, SchedulerEventKind::BottomField);
+        }
     }
 
-    sys.scheduler.cancel(self::vertical_count);
-    if sys.video.display_config.enable() {
-        sys.scheduler.schedule_now(self::vertical_count);
-    }
+    self::schedule_interrupt::<0>(sys);
+    self::schedule_interrupt::<1>(sys);
+    self::schedule_interrupt::<2>(sys);
+    self::schedule_interrupt::<3>(sys);
+    self::schedule_poll(sys);
 }
 
 pub fn present(sys: &mut System) {
@@ -465,9 +737,22 @@ pub fn present(sys: &mut System) {
         return;
     }
 
-    let frame_dimensions = sys.video.frame_dimensions();
-    let stride_in_pixels = sys.video.xfb_stride() as u32;
-    let base_copy = sys.gpu.xfb_copies.iter().min_by_key(|x| x.addr).unwrap();
+    let latched = sys.video.latched;
+    let frame_dimensions = latched.frame_dimensions;
+
+    // when the fields are stored separately, each one holds every other row of the frame and is
+    // placed relative to its own XFB
+    let (base, stride_in_pixels, line_step, parity) = if latched.weave_fields {
+        (
+            latched.field_base,
+            latched.field_stride as u32,
+            2,
+            latched.field_order.parity(latched.field),
+        )
+    } else {
+        let base_copy = sys.gpu.xfb_copies.iter().min_by_key(|x| x.addr).unwrap();
+        (base_copy.addr, latched.xfb_stride as u32, 1, 0)
+    };
 
     if frame_dimensions.is_degenerate() {
         // TODO: black out VI
@@ -479,9 +764,13 @@ pub fn present(sys: &mut System) {
 
     let mut parts = Vec::with_capacity(sys.gpu.xfb_copies.len());
     for (id, copy) in sys.gpu.xfb_copies.iter().enumerate() {
-        let delta_pixels = (copy.addr.value() - base_copy.addr.value()) / 2;
+        let Some(delta) = copy.addr.value().checked_sub(base.value()) else {
+            continue;
+        };
+
+        let delta_pixels = delta / 2;
         let offset_x = delta_pixels % stride_in_pixels;
-        let offset_y = delta_pixels / stride_in_pixels;
+        let offset_y = (delta_pixels / stride_in_pixels) * line_step + parity;
 
         if offset_x >= frame_dimensions.width as u32 || offset_y >= frame_dimensions.height as u32 {
             continue;
@@ -491,9 +780,237 @@ pub fn present(sys: &mut System) {
             id: id as u32,
             offset_x,
             offset_y,
+            line_step,
         });
     }
 
     sys.modules.render.exec(render::Action::PresentXfb(parts));
     sys.gpu.xfb_copies.clear();
+    sys.timing.end_frame();
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use bitos::integer::{u4, u7, u9, u10, u24};
+    use gekko::Address;
+
+    use super::{DisplayInterrupt, Field, FieldMode, FieldOrder};
+    use crate::modules::render::{Action, CopyArgs, RenderModule, XfbPart};
+    use crate::system::gx::XfbCopy;
+    use crate::system::{System, vi};
+
+    /// Builds a system with NTSC interlaced video timing.
+    fn system() -> System {
+        let mut sys = crate::test::system();
+
+        let video = &mut sys.video;
+        video.horizontal_timing.set_halfline_width(u9::new(429));
+        video.vertical_timing.set_equalization_pulse(u4::new(6));
+        video.vertical_timing.set_active_video_lines(u10::new(240));
+        video.top_vertical_timing.set_pre_blanking(u10::new(24));
+        video.top_vertical_timing.set_post_blanking(u10::new(3));
+        video.bottom_vertical_timing.set_pre_blanking(u10::new(25));
+        video.bottom_vertical_timing.set_post_blanking(u10::new(2));
+        video.display_config.set_field_mode(FieldMode::Double);
+        video.display_config.set_enable(true);
+
+        vi::update(&mut sys);
+        sys
+    }
+
+    /// Runs the system in small steps until display interrupt 0 is asserted, returning the cycle
+    /// at which that was observed.
+    fn run_until_interrupt(sys: &mut System) -> u64 {
+        let limit = sys.scheduler.elapsed() + 2 * sys.video.timing.cycles_per_frame();
+        while !sys.video.interrupts[0].status() {
+            assert!(sys.scheduler.elapsed() < limit, "interrupt never fired");
+            sys.scheduler.advance(100);
+            sys.process_events();
+        }
+
+        sys.scheduler.elapsed()
+    }
+
+    fn program_interrupt(sys: &mut System, halfline: u16) {
+        let interrupt = DisplayInterrupt::default()
+            .with_vertical_count(u10::new(halfline / 2 + 1))
+            .with_horizontal_count(u9::new(1))
+            .with_enable(true);
+
+        sys.video.write_interrupt::<0>(interrupt);
+        vi::schedule_interrupt::<0>(sys);
+    }
+
+    #[test]
+    fn beam_position() {
+        let mut sys = system();
+        let timing = sys.video.timing;
+        assert_eq!(timing.halflines_per_frame, 1050);
+
+        let start = sys.video.frame_start;
+        let cycle = start + 101 * timing.cycles_per_halfline as u64 + 10;
+        assert_eq!(sys.video.halfline_at(cycle), 101);
+        assert_eq!(sys.video.vertical_count_at(cycle), 51);
+
+        let cycle = start + timing.cycles_per_frame() + 3 * timing.cycles_per_sample as u64;
+        assert_eq!(sys.video.vertical_count_at(cycle), 1);
+        assert_eq!(sys.video.horizontal_count_at(cycle), 4);
+
+        sys.scheduler.advance(cycle - sys.scheduler.elapsed());
+        let beam = vi::beam_position(&sys);
+        assert_eq!((beam.vertical, beam.horizontal), (1, 4));
+    }
+
+    #[test]
+    fn display_interrupt_timing() {
+        for halfline in [2, 100, 524, 526, 1048] {
+            let mut sys = system();
+            program_interrupt(&mut sys, halfline);
+
+            let cycles_per_halfline = sys.video.timing.cycles_per_halfline as u64;
+            let expected = sys.video.frame_start + halfline as u64 * cycles_per_halfline;
+            let fired = run_until_interrupt(&mut sys);
+
+            assert!(fired >= expected);
+            assert!(fired - expected <= cycles_per_halfline);
+        }
+    }
+
+    #[test]
+    fn display_interrupt_rescheduled() {
+        let mut sys = system();
+        program_interrupt(&mut sys, 800);
+
+        // move the interrupt earlier while the frame is in progress
+        let cycles_per_halfline = sys.video.timing.cycles_per_halfline as u64;
+        sys.scheduler.advance(200 * cycles_per_halfline);
+        sys.process_events();
+        program_interrupt(&mut sys, 400);
+
+        let expected = sys.video.frame_start + 400 * cycles_per_halfline;
+        let fired = run_until_interrupt(&mut sys);
+
+        assert!(fired >= expected);
+        assert!(fired - expected <= cycles_per_halfline);
+        assert_eq!(sys.video.interrupt_counts, [1, 0, 0, 0]);
+    }
+
+    #[test]
+    fn field_layout() {
+        let mut sys = system();
+        let video = &mut sys.video;
+        video.xfb_width.set_stride_div_16(80);
+        video
+            .top_base_left
+            .set_xfb_address_base(u24::new(0x0010_0000));
+
+        // both fields in a single full-frame XFB
+        video
+            .bottom_base_left
+            .set_xfb_address_base(u24::new(0x0010_0000 + 640 * 2));
+        assert!(video.full_frame_xfb());
+        assert_eq!(video.field_stride(), 1280);
+
+        // each field in its own XFB
+        video
+            .bottom_base_left
+            .set_xfb_address_base(u24::new(0x0020_0000));
+        assert!(!video.full_frame_xfb());
+
+        assert_eq!(video.field_order(), FieldOrder::TopFirst);
+        assert_eq!(FieldOrder::TopFirst.parity(Field::Bottom), 1);
+
+        video.top_vertical_timing.set_pre_blanking(u10::new(26));
+        assert_eq!(video.field_order(), FieldOrder::BottomFirst);
+        assert_eq!(FieldOrder::BottomFirst.parity(Field::Bottom), 0);
+    }
+
+    /// Keeps the parts of the last XFB presented.
+    #[derive(Default, Clone)]
+    struct Presented(Arc<Mutex<Vec<XfbPart>>>);
+
+    impl RenderModule for Presented {
+        fn exec(&mut self, action: Action) {
+            if let Action::PresentXfb(parts) = action {
+                *self.0.lock().unwrap() = parts;
+            }
+        }
+    }
+
+    /// Presents XFB copies at the given addresses on vertical sync of the given field, returning
+    /// the ID, offset and line step of each part.
+    fn present(sys: &mut System, field: Field, copies: &[Address]) -> Vec<(u32, u32, u32, u32)> {
+        let presented = Presented::default();
+        sys.modules.render = Box::new(presented.clone());
+
+        for &addr in copies {
+            sys.gpu.xfb_copies.push(XfbCopy {
+                addr,
+                args: CopyArgs {
+                    src: Default::default(),
+                    dims: Default::default(),
+                    half: false,
+                    clear: false,
+                },
+            });
+        }
+
+        vi::vsync(sys, field);
+        assert_eq!(sys.video.latched.field, field);
+
+        let parts = presented.0.lock().unwrap();
+        parts
+            .iter()
+            .map(|p| (p.id, p.offset_x, p.offset_y, p.line_step))
+            .collect()
+    }
+
+    #[test]
+    fn field_selection() {
+        const ROW: u32 = 640 * 2;
+
+        let mut sys = system();
+        let video = &mut sys.video;
+        video.xfb_width.set_width_div_16(u7::new(40));
+
+        // separate fields, each with its lines one after the other
+        video.xfb_width.set_stride_div_16(40);
+        video
+            .top_base_left
+            .set_xfb_address_base(u24::new(0x0010_0000));
+        video
+            .bottom_base_left
+            .set_xfb_address_base(u24::new(0x0020_0000));
+
+        let top = video.top_xfb_address();
+        let bottom = video.bottom_xfb_address();
+
+        // only copies in the XFB of the field being scanned out are placed, on every other row
+        let parts = present(&mut sys, Field::Bottom, &[top, bottom + 10 * ROW]);
+        assert!(sys.video.latched.weave_fields);
+        assert_eq!(sys.video.latched.field_base, bottom);
+        assert_eq!(parts, [(1, 0, 21, 2)]);
+
+        let parts = present(&mut sys, Field::Top, &[top + 5 * ROW, bottom]);
+        assert_eq!(sys.video.latched.field_base, top);
+        assert_eq!(parts, [(0, 0, 10, 2)]);
+
+        // the bottom field goes on the even rows when it starts first
+        sys.video.top_vertical_timing.set_pre_blanking(u10::new(26));
+        let parts = present(&mut sys, Field::Bottom, &[bottom]);
+        assert_eq!(parts, [(0, 0, 0, 2)]);
+
+        // a full-frame XFB is presented as a whole, regardless of the field
+        let video = &mut sys.video;
+        video.xfb_width.set_stride_div_16(80);
+        video
+            .bottom_base_left
+            .set_xfb_address_base(u24::new(0x0010_0000 + ROW));
+
+        let parts = present(&mut sys, Field::Bottom, &[top + 2 * ROW, top]);
+        assert!(!sys.video.latched.weave_fields);
+        assert_eq!(parts, [(0, 0, 2, 1), (1, 0, 0, 1)]);
+    }
 }
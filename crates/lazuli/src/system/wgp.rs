@@ -0,0 +1,226 @@
+//! Write gather pipe (WGP).
+//!
+//! Stores to the gather address are accumulated into 32-byte lines, which are then burst to the
+//! target pointed at by WPAR. Usually that's the PI FIFO, which feeds the command processor.
+use gekko::Address;
+
+use crate::Primitive;
+use crate::system::{System, pi};
+
+/// Physical address of the PI FIFO register, the usual target of the pipe.
+pub const FIFO_ADDRESS: Address = Address(0x0C00_8000);
+
+/// Size of a line burst by the pipe.
+pub const LINE_LEN: usize = 32;
+
+/// State of the write gather pipe.
+pub struct GatherPipe {
+    /// Physical address lines are burst to.
+    target: Address,
+    buffer: [u8; LINE_LEN + 8],
+    len: usize,
+}
+
+impl Default for GatherPipe {
+    fn default() -> Self {
+        Self {
+            target: FIFO_ADDRESS,
+            buffer: [0; LINE_LEN + 8],
+            len: 0,
+        }
+    }
+}
+
+impl GatherPipe {
+    /// Physical address lines are burst to.
+    pub fn target(&self) -> Address {
+        self.target
+    }
+
+    /// Whether the target is the PI FIFO.
+    pub fn targets_fifo(&self) -> bool {
+        (FIFO_ADDRESS..FIFO_ADDRESS + LINE_LEN as u32).contains(&self.target)
+    }
+
+    /// Bytes currently gathered and not yet burst.
+    pub fn pending(&self) -> &[u8] {
+        &self.buffer[..self.len]
+    }
+}
+
+/// Bursts a line to the current target.
+fn burst(sys: &mut System, line: [u8; LINE_LEN]) {
+    if sys.gather_pipe.targets_fifo() {
+        pi::fifo_write_line(sys, &line);
+        return;
+    }
+
+    let target = sys.gather_pipe.target;
+    tracing::trace!("bursting gather pipe line to {target}");
+    for (offset, byte) in line.into_iter().enumerate() {
+        sys.write_phys_slow(target + offset as u32, byte);
+    }
+}
+
+/// Updates the buffer not empty bit of WPAR.
+fn update_status(sys: &mut System) {
+    let not_empty = sys.gather_pipe.len != 0;
+    sys.cpu
+        .supervisor
+        .config
+        .wpar
+        .set_buffer_not_empty(not_empty);
+}
+
+/// Pushes a value into the pipe, bursting every complete line to the target.
+pub fn push<P: Primitive>(sys: &mut System, value: P) {
+    let pipe = &mut sys.gather_pipe;
+    value.write_be_bytes(&mut pipe.buffer[pipe.len..][..size_of::<P>()]);
+    pipe.len += size_of::<P>();
+
+    while sys.gather_pipe.len >= LINE_LEN {
+        let pipe = &mut sys.gather_pipe;
+        let mut line = [0; LINE_LEN];
+        line.copy_from_slice(&pipe.buffer[..LINE_LEN]);
+        pipe.buffer.copy_within(LINE_LEN..pipe.len, 0);
+        pipe.len -= LINE_LEN;
+
+        self::burst(sys, line);
+    }
+
+    self::update_status(sys);
+}
+
+/// Pads a partially filled line with zeroes (which are NOPs to the command processor) and bursts
+/// it to the target.
+pub fn pad_and_flush(sys: &mut System) {
+    let pipe = &mut sys.gather_pipe;
+    if pipe.len != 0 {
+        tracing::debug!(
+            "flushing {} bytes in the gather pipe with padding",
+            pipe.len
+        );
+
+        let mut line = [0; LINE_LEN];
+        line[..pipe.len].copy_from_slice(&pipe.buffer[..pipe.len]);
+        pipe.len = 0;
+
+        self::burst(sys, line);
+    }
+
+    self::update_status(sys);
+}
+
+/// Must be called after WPAR is written to. Pending data is flushed to the previous target before
+/// switching to the new one.
+pub fn wpar_changed(sys: &mut System) {
+    let target = sys.cpu.supervisor.config.wpar.address();
+    self::pad_and_flush(sys);
+
+    tracing::debug!("gather pipe target changed to {target}");
+    sys.gather_pipe.target = target;
+}
+
+#[cfg(test)]
+mod test {
+    use bitos::integer::u27;
+    use gekko::Address;
+
+    use super::LINE_LEN;
+    use crate::system::{System, wgp};
+    use crate::test::system;
+
+    fn set_target(sys: &mut System, target: u32) {
+        sys.cpu
+            .supervisor
+            .config
+            .wpar
+            .set_address_base(u27::new(target >> 5));
+        wgp::wpar_changed(sys);
+    }
+
+    /// Pushes a sequence of stores of mixed sizes, returning the expected byte stream.
+    fn push_mixed(sys: &mut System) -> Vec<u8> {
+        let mut expected = Vec::new();
+        for i in 0..4u8 {
+            wgp::push(sys, 0x10 + i);
+            expected.push(0x10 + i);
+
+            let half = u16::from_be_bytes([0x20 + i, 0x30 + i]);
+            wgp::push(sys, half);
+            expected.extend_from_slice(&half.to_be_bytes());
+
+            let word = u32::from_be_bytes([0x40 + i, 0x50 + i, 0x60 + i, 0x70 + i]);
+            wgp::push(sys, word);
+            expected.extend_from_slice(&word.to_be_bytes());
+
+            let double = 0x8081_8283_8485_8687u64 + i as u64;
+            wgp::push(sys, double);
+            expected.extend_from_slice(&double.to_be_bytes());
+        }
+
+        expected
+    }
+
+    #[test]
+    fn burst_to_memory() {
+        let mut sys = system();
+        set_target(&mut sys, 0x1000);
+
+        // 60 bytes: one complete line and 28 pending bytes
+        let expected = push_mixed(&mut sys);
+        assert_eq!(expected.len(), 60);
+
+        assert_eq!(&sys.mem.ram()[0x1000..][..LINE_LEN], &expected[..LINE_LEN]);
+        assert_eq!(sys.gather_pipe.pending(), &expected[LINE_LEN..]);
+        assert!(sys.cpu.supervisor.config.wpar.buffer_not_empty());
+
+        // the partial line is padded with zeroes
+        wgp::pad_and_flush(&mut sys);
+        let mut padded = [0; LINE_LEN];
+        padded[..28].copy_from_slice(&expected[LINE_LEN..]);
+
+        assert_eq!(&sys.mem.ram()[0x1000..][..LINE_LEN], &padded);
+        assert!(sys.gather_pipe.pending().is_empty());
+        assert!(!sys.cpu.supervisor.config.wpar.buffer_not_empty());
+    }
+
+    #[test]
+    fn burst_to_fifo() {
+        let mut sys = system();
+        sys.processor.fifo_start = Address(0x2000);
+        sys.processor.fifo_end = Address(0x2FFF);
+        sys.processor.fifo_current.set_address(Address(0x2000));
+        assert!(sys.gather_pipe.targets_fifo());
+
+        let expected = push_mixed(&mut sys);
+        assert_eq!(&sys.mem.ram()[0x2000..][..LINE_LEN], &expected[..LINE_LEN]);
+        assert_eq!(sys.processor.fifo_current.address(), Address(0x2020));
+
+        // changing the target flushes the pending bytes to the previous one
+        set_target(&mut sys, 0x1000);
+        assert_eq!(&sys.mem.ram()[0x2020..][..28], &expected[LINE_LEN..]);
+        assert_eq!(&sys.mem.ram()[0x2020 + 28..][..4], &[0; 4]);
+        assert_eq!(sys.processor.fifo_current.address(), Address(0x2040));
+        assert!(!sys.gather_pipe.targets_fifo());
+    }
+
+    #[test]
+    fn zeroed_line_through_bus() {
+        let mut sys = system();
+        set_target(&mut sys, 0x1000);
+        sys.mem.ram_mut()[0x1000..][..LINE_LEN].fill(0xFF);
+
+        // a dcbz on the gather address is lowered to word stores of zero across the line
+        for i in 0..LINE_LEN as u32 / 4 {
+            sys.write_phys_slow(wgp::FIFO_ADDRESS + 4 * i, 0u32);
+            assert_eq!(
+                sys.cpu.supervisor.config.wpar.buffer_not_empty(),
+                i != LINE_LEN as u32 / 4 - 1
+            );
+        }
+
+        assert_eq!(&sys.mem.ram()[0x1000..][..LINE_LEN], &[0; LINE_LEN]);
+        assert!(sys.gather_pipe.pending().is_empty());
+    }
+}
@@ -0,0 +1,157 @@
+//! Per-frame host timing instrumentation.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// How many frames are kept in a [`History`].
+pub const HISTORY_LEN: usize = 240;
+
+/// A ring of measurements of the last [`HISTORY_LEN`] frames, oldest first.
+#[derive(Debug, Clone)]
+pub struct History<T> {
+    frames: VecDeque<T>,
+}
+
+impl<T> Default for History<T> {
+    fn default() -> Self {
+        Self {
+            frames: VecDeque::with_capacity(HISTORY_LEN),
+        }
+    }
+}
+
+impl<T> History<T> {
+    /// Pushes the measurements of a frame, dropping the oldest one if full.
+    pub fn push(&mut self, frame: T) {
+        if self.frames.len() == HISTORY_LEN {
+            self.frames.pop_front();
+        }
+
+        self.frames.push_back(frame);
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &T> + ExactSizeIterator {
+        self.frames.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl DoubleEndedIterator<Item = &mut T> + ExactSizeIterator {
+        self.frames.iter_mut()
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    /// Returns the average of the worst `percent`% values of `f` over the frames (e.g. the 1%-low
+    /// frame time for `percent = 1.0`). Returns zero if there are no frames.
+    pub fn low(&self, percent: f64, f: impl Fn(&T) -> Duration) -> Duration {
+        let mut values: Vec<_> = self.frames.iter().map(f).collect();
+        if values.is_empty() {
+            return Duration::ZERO;
+        }
+
+        values.sort_unstable_by(|a, b| b.cmp(a));
+        let count =
+            ((values.len() as f64 * percent / 100.0).ceil() as usize).clamp(1, values.len());
+        values[..count].iter().sum::<Duration>() / count as u32
+    }
+}
+
+/// Host time spent by the emulator during a single frame.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EmulationTimes {
+    /// Time spent executing the system (i.e. inside [`Lazuli::exec`](crate::Lazuli::exec)),
+    /// including GX command processing.
+    pub exec: Duration,
+    /// Time spent processing GX commands.
+    pub gx: Duration,
+}
+
+/// Tracks the host time spent by the emulator in each frame.
+#[derive(Debug, Default)]
+pub struct Tracker {
+    current: EmulationTimes,
+    exec_start: Option<Instant>,
+    history: History<EmulationTimes>,
+}
+
+impl Tracker {
+    /// Marks the start of a [`Lazuli::exec`](crate::Lazuli::exec) call.
+    pub fn begin_exec(&mut self) {
+        self.exec_start = Some(Instant::now());
+    }
+
+    /// Marks the end of a [`Lazuli::exec`](crate::Lazuli::exec) call.
+    pub fn end_exec(&mut self) {
+        if let Some(start) = self.exec_start.take() {
+            self.current.exec += start.elapsed();
+        }
+    }
+
+    /// Accounts time spent processing GX commands.
+    pub fn add_gx(&mut self, time: Duration) {
+        self.current.gx += time;
+    }
+
+    /// Marks the end of a frame. Frames usually end in the middle of an `exec` call, in which case
+    /// the time spent in it so far is attributed to the ending frame.
+    pub fn end_frame(&mut self) {
+        if let Some(start) = &mut self.exec_start {
+            let now = Instant::now();
+            self.current.exec += now - *start;
+            *start = now;
+        }
+
+        self.history.push(std::mem::take(&mut self.current));
+    }
+
+    /// Measurements of the last frames.
+    pub fn history(&self) -> &History<EmulationTimes> {
+        &self.history
+    }
+
+    /// Forgets every measurement.
+    pub fn clear(&mut self) {
+        self.current = EmulationTimes::default();
+        self.history.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{HISTORY_LEN, History};
+
+    #[test]
+    fn history_is_bounded() {
+        let mut history = History::default();
+        for i in 0..HISTORY_LEN + 10 {
+            history.push(i);
+        }
+
+        assert_eq!(history.len(), HISTORY_LEN);
+        assert_eq!(history.iter().next(), Some(&10));
+        assert_eq!(history.iter().last(), Some(&(HISTORY_LEN + 9)));
+    }
+
+    #[test]
+    fn one_percent_low() {
+        let mut history = History::default();
+        for i in 0..200 {
+            history.push(Duration::from_millis(if i % 100 == 0 { 50 } else { 16 }));
+        }
+
+        // worst 1% of 200 frames is the 2 slow ones
+        assert_eq!(history.low(1.0, |d| *d), Duration::from_millis(50));
+        assert_eq!(history.low(100.0, |d| *d), Duration::from_micros(16_340));
+    }
+}
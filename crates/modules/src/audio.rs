@@ -1,14 +1,19 @@
+mod dump;
+mod resample;
+
 use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use cpal::{Device, Stream, SupportedStreamConfigRange};
 use lazuli::modules::audio::AudioModule;
 use lazuli::system::ai::{Frame, SampleRate};
-use resampler::ResamplerFir;
-use zerocopy::{FromBytes, Immutable, IntoBytes};
 
-#[derive(Debug, Clone, Copy, Default, FromBytes, IntoBytes, Immutable)]
+pub use self::dump::{AudioDump, PcmDump};
+pub use self::resample::ResamplerQuality;
+
+#[derive(Debug, Clone, Copy, Default)]
 struct FrameF32 {
     left: f32,
     right: f32,
@@ -24,113 +29,100 @@ impl From<Frame> for FrameF32 {
 }
 
 struct State {
-    sample_rate: SampleRate,
-    resampler: ResamplerFir,
-    resampled: Vec<f32>,
+    resampler: resample::Resampler,
     frames: VecDeque<FrameF32>,
     last: FrameF32,
-    writer: Option<hound::WavWriter<std::io::BufWriter<std::fs::File>>>,
+    dump: Option<AudioDump>,
+    pcm_dump: Option<PcmDump>,
+    /// Frames requested by the last callback of the output device.
+    callback_frames: usize,
 }
 
-impl Drop for State {
-    fn drop(&mut self) {
-        self.writer.take().unwrap().finalize().unwrap();
+impl State {
+    fn new(quality: ResamplerQuality, output_rate: u32) -> Self {
+        let input_rate = SampleRate::KHz48.value() as u32;
+        Self {
+            resampler: resample::Resampler::new(quality, input_rate, output_rate),
+            frames: VecDeque::with_capacity(8192),
+            last: FrameF32::default(),
+            dump: None,
+            pcm_dump: None,
+            callback_frames: 0,
+        }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: SampleRate) {
+        let rate = sample_rate.value() as u32;
+        if self.pcm_dump.is_some() && rate != self.resampler.input_rate() {
+            tracing::info!("PCM dump sample rate changed to {rate} Hz");
+        }
+
+        self.resampler.set_input_rate(rate);
+    }
+
+    fn push(&mut self, frame: Frame) {
+        if let Some(dump) = &mut self.pcm_dump {
+            dump.push(frame);
+        }
+
+        self.frames.push_back(frame.into());
     }
 }
 
 fn fill_buffer(state: &Arc<Mutex<State>>, out: &mut [f32]) {
     let mut state = state.lock().unwrap();
     let state = &mut *state;
+    state.callback_frames = out.len() / 2;
 
-    match state.sample_rate {
-        SampleRate::KHz48 => {
-            let mut last = state.last;
-            for out in out.chunks_exact_mut(2) {
-                let frame = if let Some(frame) = state.frames.pop_front() {
-                    state
-                        .writer
-                        .as_mut()
-                        .unwrap()
-                        .write_sample(frame.left)
-                        .unwrap();
-                    state
-                        .writer
-                        .as_mut()
-                        .unwrap()
-                        .write_sample(frame.right)
-                        .unwrap();
-
-                    frame
-                } else {
-                    last
-                };
-
-                out[0] = frame.left;
-                out[1] = frame.right;
-                last = frame;
+    let mut last = state.last;
+    for out in out.chunks_exact_mut(2) {
+        let frame = if let Some(frame) = state.resampler.next(&mut state.frames) {
+            if let Some(dump) = &mut state.dump {
+                dump.push(frame);
             }
 
-            state.last = last;
-        }
-        SampleRate::KHz32 => {
-            let slices = state.frames.as_slices();
-            let frames = match (slices.0.is_empty(), slices.1.is_empty()) {
-                (true, true) => slices.0,
-                (false, true) => slices.0,
-                (true, false) => slices.1,
-                (false, false) => state.frames.make_contiguous(),
-            };
-
-            let samples: &[f32] = zerocopy::transmute_ref!(frames);
-            let samples_needed = (2 * out.len()) / 3;
-
-            let (consumed, produced) = state
-                .resampler
-                .resample(
-                    &samples[..samples_needed.min(samples.len())],
-                    &mut state.resampled,
-                )
-                .unwrap();
-
-            state.frames.drain(..consumed / 2);
-
-            let mut produced = state
-                .resampled
-                .chunks_exact(2)
-                .map(|s| FrameF32 {
-                    left: s[0],
-                    right: s[1],
-                })
-                .take(produced / 2);
-
-            let mut last = state.last;
-            for out in out.chunks_exact_mut(2) {
-                let frame = if let Some(frame) = produced.next() {
-                    state
-                        .writer
-                        .as_mut()
-                        .unwrap()
-                        .write_sample(frame.left)
-                        .unwrap();
-                    state
-                        .writer
-                        .as_mut()
-                        .unwrap()
-                        .write_sample(frame.right)
-                        .unwrap();
-
-                    frame
-                } else {
-                    last
-                };
-
-                out[0] = frame.left;
-                out[1] = frame.right;
-                last = frame;
-            }
+            frame
+        } else {
+            last
+        };
 
-            state.last = last;
-        }
+        out[0] = frame.left;
+        out[1] = frame.right;
+        last = frame;
+    }
+
+    state.last = last;
+}
+
+/// Configuration of a [`CpalModule`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CpalConfig {
+    /// Quality of the resampling to the rate of the output device.
+    pub quality: ResamplerQuality,
+    /// Size of the buffer of the output device, in frames. Smaller buffers have less latency, but
+    /// are more prone to glitches. If `None`, the platform default is used.
+    pub buffer_size_frames: Option<u32>,
+}
+
+/// Estimated latency of the audio output.
+#[derive(Debug, Clone, Copy)]
+pub struct Latency {
+    /// Frames requested by the output device on each callback, i.e. the size of its buffer.
+    pub buffer_frames: usize,
+    /// Frames waiting to be resampled.
+    pub queued_frames: usize,
+    /// Sample rate of the queued frames.
+    pub input_rate: u32,
+    /// Sample rate of the output device.
+    pub output_rate: u32,
+}
+
+impl Latency {
+    /// Estimated time between a frame being played and it being heard, in milliseconds.
+    pub fn millis(&self) -> f64 {
+        let buffered = self.buffer_frames as f64 / self.output_rate as f64;
+        let queued = self.queued_frames as f64 / self.input_rate as f64;
+        1000.0 * (buffered + queued)
     }
 }
 
@@ -139,13 +131,11 @@ pub struct CpalModule {
     _stream: Stream,
 }
 
+/// Preferred sample rate of the output device.
 const SAMPLE_RATE: u32 = 48_000;
 
 fn is_supported_config(c: &SupportedStreamConfigRange) -> bool {
-    c.sample_format() == cpal::SampleFormat::F32
-        && c.channels() == 2
-        && c.min_sample_rate() <= SAMPLE_RATE
-        && c.max_sample_rate() >= SAMPLE_RATE
+    c.sample_format() == cpal::SampleFormat::F32 && c.channels() == 2
 }
 
 fn is_supported_device(device: &Device) -> bool {
@@ -158,10 +148,24 @@ fn is_supported_device(device: &Device) -> bool {
 }
 
 fn get_supported_config(device: &Device) -> Option<cpal::StreamConfig> {
-    let mut device_supported_configs = device.supported_output_configs().ok()?;
-    device_supported_configs
-        .find(is_supported_config)
-        .map(|c| c.with_sample_rate(SAMPLE_RATE))
+    let device_supported_configs: Vec<_> = device
+        .supported_output_configs()
+        .ok()?
+        .filter(is_supported_config)
+        .collect();
+
+    // prefer the native rate of the DSP, falling back to the closest rate the device supports
+    let preferred = device_supported_configs
+        .iter()
+        .find_map(|c| c.try_with_sample_rate(SAMPLE_RATE));
+
+    preferred
+        .or_else(|| {
+            device_supported_configs.into_iter().next().map(|c| {
+                let rate = SAMPLE_RATE.clamp(c.min_sample_rate(), c.max_sample_rate());
+                c.with_sample_rate(rate)
+            })
+        })
         .map(Into::into)
 }
 
@@ -195,10 +199,37 @@ fn get_device_and_config(host: &cpal::Host) -> Option<(cpal::Device, cpal::Strea
     None
 }
 
+fn build_stream(
+    device: &Device,
+    config: &cpal::StreamConfig,
+    state: &Arc<Mutex<State>>,
+) -> Result<Stream, cpal::BuildStreamError> {
+    let state = state.clone();
+    device.build_output_stream(
+        config,
+        move |out: &mut [f32], _: &cpal::OutputCallbackInfo| {
+            fill_buffer(&state, out);
+        },
+        move |e| tracing::error!("audio error: {}", e),
+        None,
+    )
+}
+
 impl CpalModule {
-    pub fn new() -> Self {
+    /// Creates a module playing through the default output device (or the first supported one).
+    pub fn new(config: CpalConfig) -> Self {
+        let CpalConfig {
+            quality,
+            buffer_size_frames,
+        } = config;
+
         let host = cpal::default_host();
-        let (device, config) = get_device_and_config(&host).expect("no supported output device");
+        let (device, mut config) =
+            get_device_and_config(&host).expect("no supported output device");
+
+        if let Some(frames) = buffer_size_frames {
+            config.buffer_size = cpal::BufferSize::Fixed(frames);
+        }
 
         match device.description() {
             Ok(description) => {
@@ -213,46 +244,23 @@ impl CpalModule {
             }
         }
 
-        let resampler = ResamplerFir::new(
-            2,
-            resampler::SampleRate::Hz32000,
-            resampler::SampleRate::Hz48000,
-            resampler::Latency::Sample64,
-            resampler::Attenuation::Db90,
+        tracing::info!(
+            "output sample rate: {} Hz, buffer size: {:?}, resampler quality: {quality:?}",
+            config.sample_rate,
+            config.buffer_size,
         );
 
-        let spec = hound::WavSpec {
-            channels: 2,
-            sample_rate: 48000,
-            bits_per_sample: 32,
-            sample_format: hound::SampleFormat::Float,
-        };
-        let writer = hound::WavWriter::create("audio.wav", spec).unwrap();
-
-        let state = State {
-            sample_rate: SampleRate::KHz48,
-            resampled: vec![0.0; resampler.buffer_size_output()],
-            resampler,
-            frames: VecDeque::with_capacity(8192),
-            last: FrameF32::default(),
-            writer: Some(writer),
+        let state = Arc::new(Mutex::new(State::new(quality, config.sample_rate)));
+        let stream = match build_stream(&device, &config, &state) {
+            Ok(stream) => stream,
+            Err(e) if buffer_size_frames.is_some() => {
+                tracing::warn!("failed to use the requested buffer size ({e}), using the default");
+                config.buffer_size = cpal::BufferSize::Default;
+                build_stream(&device, &config, &state).unwrap()
+            }
+            Err(e) => panic!("failed to build output stream: {e}"),
         };
 
-        let state = Arc::new(Mutex::new(state));
-        let stream = device
-            .build_output_stream(
-                &config,
-                {
-                    let state = state.clone();
-                    move |out: &mut [f32], _: &cpal::OutputCallbackInfo| {
-                        fill_buffer(&state, out);
-                    }
-                },
-                move |e| tracing::error!("audio error: {}", e),
-                None,
-            )
-            .unwrap();
-
         stream.play().unwrap();
 
         Self {
@@ -262,12 +270,169 @@ impl CpalModule {
     }
 }
 
+impl CpalModule {
+    /// Starts dumping the output to a WAV file at the given path, replacing the current dump (if
+    /// any).
+    pub fn start_dump(&mut self, path: impl AsRef<Path>) -> Result<(), hound::Error> {
+        let output_rate = self.state.lock().unwrap().resampler.output_rate();
+        let dump = AudioDump::create(path, output_rate)?;
+        self.state.lock().unwrap().dump = Some(dump);
+        Ok(())
+    }
+
+    /// Stops dumping the output, finishing the current dump.
+    pub fn stop_dump(&mut self) {
+        let dump = self.state.lock().unwrap().dump.take();
+        drop(dump);
+    }
+
+    /// Starts dumping the raw input to a PCM file at the given path. See [`PcmDump`].
+    pub fn start_pcm_dump(&mut self, path: PathBuf) -> std::io::Result<()> {
+        self.handle().start_pcm_dump(path)
+    }
+
+    /// Stops dumping the raw input, flushing the current PCM dump.
+    pub fn stop_pcm_dump(&mut self) {
+        self.handle().stop_pcm_dump();
+    }
+
+    /// Returns a handle to this module which can be used to control it after the module has been
+    /// handed over to the system.
+    pub fn handle(&self) -> CpalHandle {
+        CpalHandle {
+            state: self.state.clone(),
+        }
+    }
+}
+
+/// A handle to a [`CpalModule`], used to control it from other threads.
+#[derive(Clone)]
+pub struct CpalHandle {
+    state: Arc<Mutex<State>>,
+}
+
+impl CpalHandle {
+    /// Starts dumping the raw input to a PCM file at the given path, replacing the current PCM
+    /// dump (if any).
+    pub fn start_pcm_dump(&self, path: PathBuf) -> std::io::Result<()> {
+        let dump = PcmDump::create(path)?;
+        let mut state = self.state.lock().unwrap();
+        tracing::info!("PCM dump sample rate: {} Hz", state.resampler.input_rate());
+        state.pcm_dump = Some(dump);
+
+        Ok(())
+    }
+
+    /// Stops dumping the raw input, flushing the current PCM dump.
+    pub fn stop_pcm_dump(&self) {
+        let dump = self.state.lock().unwrap().pcm_dump.take();
+        if dump.is_some() {
+            tracing::info!("stopped PCM dump");
+        }
+    }
+
+    /// Whether a PCM dump is in progress.
+    pub fn is_pcm_dumping(&self) -> bool {
+        self.state.lock().unwrap().pcm_dump.is_some()
+    }
+
+    /// Returns the estimated latency of the output.
+    pub fn latency(&self) -> Latency {
+        let state = self.state.lock().unwrap();
+        Latency {
+            buffer_frames: state.callback_frames,
+            queued_frames: state.frames.len(),
+            input_rate: state.resampler.input_rate(),
+            output_rate: state.resampler.output_rate(),
+        }
+    }
+}
+
 impl AudioModule for CpalModule {
     fn set_sample_rate(&mut self, sample_rate: SampleRate) {
-        self.state.lock().unwrap().sample_rate = sample_rate;
+        self.state.lock().unwrap().set_sample_rate(sample_rate);
+    }
+
+    fn play(&mut self, sample: Frame) {
+        self.state.lock().unwrap().push(sample);
+    }
+}
+
+/// An audio module without an output device. Frames are still consumed and resampled like they
+/// would be for playback, so they can be dumped (e.g. for comparing the output of different runs).
+pub struct NullAudioModule {
+    state: Arc<Mutex<State>>,
+    scratch: Vec<f32>,
+}
+
+impl NullAudioModule {
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State::new(
+                ResamplerQuality::default(),
+                SAMPLE_RATE,
+            ))),
+            scratch: Vec::new(),
+        }
+    }
+
+    /// Creates a module which dumps the output to a WAV file at the given path.
+    pub fn with_dump(path: impl AsRef<Path>) -> Result<Self, hound::Error> {
+        let module = Self::new();
+        module.state.lock().unwrap().dump = Some(AudioDump::create(path, SAMPLE_RATE)?);
+        Ok(module)
+    }
+
+    /// Consumes every queued frame.
+    fn consume(&mut self) {
+        let (input_rate, output_rate, queued) = {
+            let state = self.state.lock().unwrap();
+            (
+                state.resampler.input_rate() as usize,
+                state.resampler.output_rate() as usize,
+                state.frames.len(),
+            )
+        };
+
+        if queued == 0 {
+            return;
+        }
+
+        // output frames needed to consume every queued frame
+        let len = (queued * output_rate).div_ceil(input_rate);
+
+        self.scratch.resize(2 * len, 0.0);
+        fill_buffer(&self.state, &mut self.scratch);
+    }
+}
+
+impl Default for NullAudioModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AudioModule for NullAudioModule {
+    fn set_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.consume();
+        self.state.lock().unwrap().set_sample_rate(sample_rate);
     }
 
     fn play(&mut self, sample: Frame) {
-        self.state.lock().unwrap().frames.push_back(sample.into());
+        let queued = {
+            let mut state = self.state.lock().unwrap();
+            state.push(sample);
+            state.frames.len()
+        };
+
+        if queued >= 512 {
+            self.consume();
+        }
+    }
+}
+
+impl Drop for NullAudioModule {
+    fn drop(&mut self) {
+        self.consume();
     }
 }
@@ -0,0 +1,178 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::mpsc::{SyncSender, TrySendError};
+use std::thread::JoinHandle;
+
+use lazuli::system::ai::Frame;
+
+use super::FrameF32;
+
+/// Number of samples sent to the writer thread at once.
+const CHUNK_LEN: usize = 4096;
+
+/// Number of chunks which can be queued for the writer thread before samples start getting
+/// dropped.
+const QUEUE_LEN: usize = 64;
+
+fn to_i16(sample: f32) -> i16 {
+    (sample * 32_768.0).clamp(i16::MIN as f32, i16::MAX as f32) as i16
+}
+
+/// Dumps the output sample stream to a 16-bit stereo WAV file.
+///
+/// The dump is recorded at the rate of the output, after resampling, so changes of the source rate
+/// mid-recording don't affect it.
+///
+/// Samples are written by a separate thread, so that disk stalls never block the audio callback.
+/// If the writer falls too far behind, samples are dropped instead.
+pub struct AudioDump {
+    sender: Option<SyncSender<Vec<i16>>>,
+    thread: Option<JoinHandle<()>>,
+    pending: Vec<i16>,
+    dropped: u64,
+}
+
+impl AudioDump {
+    /// Creates a new dump at the given path, recorded at the given sample rate.
+    pub fn create(path: impl AsRef<Path>, sample_rate: u32) -> Result<Self, hound::Error> {
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+
+        let path = path.as_ref();
+        let mut writer = hound::WavWriter::create(path, spec)?;
+        tracing::info!("dumping audio to {}", path.display());
+
+        let (sender, receiver) = std::sync::mpsc::sync_channel::<Vec<i16>>(QUEUE_LEN);
+        let thread = std::thread::Builder::new()
+            .name("audio dump".into())
+            .spawn(move || {
+                for chunk in receiver {
+                    for sample in chunk {
+                        if let Err(e) = writer.write_sample(sample) {
+                            tracing::error!("failed to write audio dump: {e}");
+                            return;
+                        }
+                    }
+                }
+
+                if let Err(e) = writer.finalize() {
+                    tracing::error!("failed to finalize audio dump: {e}");
+                }
+            })
+            .expect("failed to spawn audio dump thread");
+
+        Ok(Self {
+            sender: Some(sender),
+            thread: Some(thread),
+            pending: Vec::with_capacity(CHUNK_LEN),
+            dropped: 0,
+        })
+    }
+
+    fn send_pending(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+
+        let chunk = std::mem::replace(&mut self.pending, Vec::with_capacity(CHUNK_LEN));
+        let Some(sender) = &self.sender else {
+            return;
+        };
+
+        match sender.try_send(chunk) {
+            Ok(()) => (),
+            Err(TrySendError::Full(chunk)) => {
+                self.dropped += chunk.len() as u64 / 2;
+                tracing::warn!(
+                    "audio dump writer is falling behind, dropped {} frames so far",
+                    self.dropped
+                );
+            }
+            Err(TrySendError::Disconnected(_)) => self.sender = None,
+        }
+    }
+
+    /// Pushes a frame of output into the dump.
+    pub(super) fn push(&mut self, frame: FrameF32) {
+        self.pending.push(self::to_i16(frame.left));
+        self.pending.push(self::to_i16(frame.right));
+
+        if self.pending.len() >= CHUNK_LEN {
+            self.send_pending();
+        }
+    }
+
+    /// Number of frames dropped so far because the writer thread fell behind.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
+
+impl Drop for AudioDump {
+    fn drop(&mut self) {
+        // the audio thread is not waiting on us anymore, so block until the rest is queued
+        if let Some(sender) = self.sender.take()
+            && !self.pending.is_empty()
+        {
+            _ = sender.send(std::mem::take(&mut self.pending));
+        }
+
+        if let Some(thread) = self.thread.take() {
+            _ = thread.join();
+        }
+    }
+}
+
+/// Dumps the input sample stream, before resampling, to a raw PCM file.
+///
+/// Samples are written exactly as they were received from the DSP: little-endian signed 16-bit,
+/// interleaved stereo (left first), with no header. The stream is recorded at the rate of the DSP,
+/// which is usually 32 kHz but can be switched to 48 kHz by games mid-recording. Use
+/// `tools/pcm2wav.py` to convert a dump to a WAV file.
+pub struct PcmDump {
+    writer: Option<BufWriter<File>>,
+}
+
+impl PcmDump {
+    /// Creates a new dump at the given path.
+    pub fn create(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let path = path.as_ref();
+        let file = File::create(path)?;
+        tracing::info!("dumping raw PCM audio to {}", path.display());
+
+        Ok(Self {
+            writer: Some(BufWriter::new(file)),
+        })
+    }
+
+    /// Pushes a frame of input into the dump.
+    pub(super) fn push(&mut self, frame: Frame) {
+        let Some(writer) = &mut self.writer else {
+            return;
+        };
+
+        let mut bytes = [0; 4];
+        bytes[..2].copy_from_slice(&frame.left.to_le_bytes());
+        bytes[2..].copy_from_slice(&frame.right.to_le_bytes());
+
+        if let Err(e) = writer.write_all(&bytes) {
+            tracing::error!("failed to write PCM dump: {e}");
+            self.writer = None;
+        }
+    }
+}
+
+impl Drop for PcmDump {
+    fn drop(&mut self) {
+        if let Some(writer) = &mut self.writer
+            && let Err(e) = writer.flush()
+        {
+            tracing::error!("failed to flush PCM dump: {e}");
+        }
+    }
+}
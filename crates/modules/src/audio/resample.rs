@@ -0,0 +1,254 @@
+use std::collections::VecDeque;
+
+use rubato::{
+    Resampler as _, SincFixedOut, SincInterpolationParameters, SincInterpolationType,
+    WindowFunction,
+};
+
+use super::FrameF32;
+
+/// Number of output frames produced by each run of the sinc resampler.
+const SINC_CHUNK_LEN: usize = 256;
+
+/// Quality of the conversion from the rate of the DSP output to the rate of the output device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResamplerQuality {
+    /// Repeat or drop frames. Cheapest, but aliases heavily.
+    Nearest,
+    /// Linearly interpolate between frames.
+    Linear,
+    /// Band-limited sinc interpolation, with cubic interpolation between 4 points of the
+    /// oversampled filter.
+    #[default]
+    Sinc4,
+}
+
+/// State of the sinc resampler. Frames are processed in chunks, so they are buffered on both
+/// sides.
+struct Sinc {
+    resampler: SincFixedOut<f32>,
+    input: [Vec<f32>; 2],
+    output: [Vec<f32>; 2],
+    position: usize,
+    len: usize,
+}
+
+impl Sinc {
+    fn new(ratio: f64) -> Self {
+        let params = SincInterpolationParameters {
+            sinc_len: 128,
+            f_cutoff: 0.95,
+            oversampling_factor: 128,
+            interpolation: SincInterpolationType::Cubic,
+            window: WindowFunction::BlackmanHarris2,
+        };
+
+        // the source can switch between 48 kHz and 32 kHz, so the ratio must be allowed to change
+        // by 1.5x in either direction
+        let resampler = SincFixedOut::new(ratio, 2.0, params, SINC_CHUNK_LEN, 2)
+            .expect("sinc resampler parameters should be valid");
+
+        let input_len = resampler.input_frames_max();
+        Self {
+            resampler,
+            input: [Vec::with_capacity(input_len), Vec::with_capacity(input_len)],
+            output: [vec![0.0; SINC_CHUNK_LEN], vec![0.0; SINC_CHUNK_LEN]],
+            position: 0,
+            len: 0,
+        }
+    }
+
+    fn next(&mut self, input: &mut VecDeque<FrameF32>) -> Option<FrameF32> {
+        if self.position == self.len {
+            let needed = self.resampler.input_frames_next();
+            if input.len() < needed {
+                return None;
+            }
+
+            let [left, right] = &mut self.input;
+            left.clear();
+            right.clear();
+            for frame in input.drain(..needed) {
+                left.push(frame.left);
+                right.push(frame.right);
+            }
+
+            let (_, produced) = self
+                .resampler
+                .process_into_buffer(&self.input, &mut self.output, None)
+                .expect("buffers should have the length requested by the resampler");
+
+            self.position = 0;
+            self.len = produced;
+        }
+
+        let frame = FrameF32 {
+            left: self.output[0][self.position],
+            right: self.output[1][self.position],
+        };
+
+        self.position += 1;
+        Some(frame)
+    }
+}
+
+/// Converts frames from the rate of the DSP output to the rate of the output device.
+pub struct Resampler {
+    quality: ResamplerQuality,
+    input_rate: u32,
+    output_rate: u32,
+
+    // nearest and linear: position between the previous and current input frames
+    position: f64,
+    previous: FrameF32,
+    current: FrameF32,
+
+    sinc: Option<Sinc>,
+}
+
+impl Resampler {
+    pub fn new(quality: ResamplerQuality, input_rate: u32, output_rate: u32) -> Self {
+        let sinc = (quality == ResamplerQuality::Sinc4)
+            .then(|| Sinc::new(output_rate as f64 / input_rate as f64));
+
+        Self {
+            quality,
+            input_rate,
+            output_rate,
+            position: 0.0,
+            previous: FrameF32::default(),
+            current: FrameF32::default(),
+            sinc,
+        }
+    }
+
+    pub fn input_rate(&self) -> u32 {
+        self.input_rate
+    }
+
+    pub fn output_rate(&self) -> u32 {
+        self.output_rate
+    }
+
+    /// Changes the rate of the input frames.
+    pub fn set_input_rate(&mut self, input_rate: u32) {
+        self.input_rate = input_rate;
+        if let Some(sinc) = &mut self.sinc {
+            sinc.resampler
+                .set_resample_ratio(self.output_rate as f64 / input_rate as f64, false)
+                .expect("ratio should be within the range the resampler was created for");
+        }
+    }
+
+    /// Produces the next output frame, consuming input frames as needed. Returns [`None`] if there
+    /// are not enough input frames.
+    pub fn next(&mut self, input: &mut VecDeque<FrameF32>) -> Option<FrameF32> {
+        if self.input_rate == self.output_rate {
+            return input.pop_front();
+        }
+
+        if let Some(sinc) = &mut self.sinc {
+            return sinc.next(input);
+        }
+
+        while self.position >= 1.0 {
+            let frame = input.pop_front()?;
+            self.previous = self.current;
+            self.current = frame;
+            self.position -= 1.0;
+        }
+
+        let t = self.position as f32;
+        let frame = match self.quality {
+            ResamplerQuality::Nearest if t < 0.5 => self.previous,
+            ResamplerQuality::Nearest => self.current,
+            _ => FrameF32 {
+                left: self.previous.left + (self.current.left - self.previous.left) * t,
+                right: self.previous.right + (self.current.right - self.previous.right) * t,
+            },
+        };
+
+        self.position += self.input_rate as f64 / self.output_rate as f64;
+        Some(frame)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::VecDeque;
+
+    use super::{FrameF32, Resampler, ResamplerQuality};
+
+    fn frames(values: impl IntoIterator<Item = f32>) -> VecDeque<FrameF32> {
+        values
+            .into_iter()
+            .map(|v| FrameF32 { left: v, right: -v })
+            .collect()
+    }
+
+    fn run(resampler: &mut Resampler, input: &mut VecDeque<FrameF32>) -> Vec<f32> {
+        std::iter::from_fn(|| resampler.next(input))
+            .map(|frame| {
+                assert!((frame.left + frame.right).abs() < 1e-6);
+                frame.left
+            })
+            .collect()
+    }
+
+    #[test]
+    fn same_rate_passthrough() {
+        for quality in [
+            ResamplerQuality::Nearest,
+            ResamplerQuality::Linear,
+            ResamplerQuality::Sinc4,
+        ] {
+            let mut resampler = Resampler::new(quality, 48_000, 48_000);
+            let mut input = frames((0..16).map(|i| i as f32));
+            let output = run(&mut resampler, &mut input);
+            assert_eq!(output, (0..16).map(|i| i as f32).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn linear_upsample() {
+        let mut resampler = Resampler::new(ResamplerQuality::Linear, 32_000, 48_000);
+        let mut input = frames((1..=64).map(|i| i as f32));
+        let output = run(&mut resampler, &mut input);
+
+        // 3 output frames for every 2 input frames, each 2/3 of an input frame apart
+        assert!(input.is_empty());
+        // interpolation starts from silence, so the first input frame comes out one frame late
+        assert_eq!(output.len(), 98);
+        for pair in output[2..].windows(2) {
+            assert!((pair[1] - pair[0] - 2.0 / 3.0).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn nearest_downsample() {
+        let mut resampler = Resampler::new(ResamplerQuality::Nearest, 48_000, 32_000);
+        let mut input = frames((1..=64).map(|i| i as f32));
+        let output = run(&mut resampler, &mut input);
+
+        // 2 output frames for every 3 input frames, picking whichever input frame is closest
+        assert_eq!(output.len(), 44);
+        assert_eq!(output[..8], [0.0, 1.0, 2.0, 4.0, 5.0, 7.0, 8.0, 10.0]);
+    }
+
+    #[test]
+    fn sinc_preserves_dc() {
+        let mut resampler = Resampler::new(ResamplerQuality::Sinc4, 32_000, 44_100);
+        let mut input = frames(std::iter::repeat_n(0.5, 4096));
+        let output = run(&mut resampler, &mut input);
+
+        assert!(output.len() > 4096);
+        assert!(output[512..].iter().all(|v| (v - 0.5).abs() < 1e-2));
+
+        // switching rates mid-stream keeps working
+        resampler.set_input_rate(48_000);
+        let mut input = frames(std::iter::repeat_n(0.5, 4096));
+        let output = run(&mut resampler, &mut input);
+        assert!(output.len() > 3072);
+        assert!(output[512..].iter().all(|v| (v - 0.5).abs() < 1e-2));
+    }
+}
@@ -4,6 +4,7 @@ use std::path::Path;
 use addr2line::gimli;
 use lazuli::Address;
 use lazuli::modules::debug::{DebugModule, Location};
+use lazuli::system::executable::Symbol;
 use mapfile_parser::MapFile;
 
 fn demangle(s: &str) -> String {
@@ -71,3 +72,35 @@ impl DebugModule for MapFileModule {
             })
     }
 }
+
+/// A debug module backed by the symbol table of an executable.
+pub struct SymbolTableModule(Vec<Symbol>);
+
+impl SymbolTableModule {
+    /// Creates a new module from the given symbols, which must be sorted by address.
+    pub fn new(symbols: &[Symbol]) -> Self {
+        Self(symbols.to_vec())
+    }
+
+    fn find(&self, addr: Address) -> Option<&Symbol> {
+        let index = self.0.partition_point(|s| s.addr <= addr).checked_sub(1)?;
+        let symbol = &self.0[index];
+
+        // symbols without a size extend up to the next one
+        (symbol.size == 0 || addr.value() - symbol.addr.value() < symbol.size).then_some(symbol)
+    }
+}
+
+impl DebugModule for SymbolTableModule {
+    fn find_symbol(&self, addr: Address) -> Option<String> {
+        self.find(addr).map(|s| demangle(&s.name))
+    }
+
+    fn find_location(&self, _: Address) -> Option<Location<'_>> {
+        None
+    }
+
+    fn find_address(&self, name: &str) -> Option<Address> {
+        self.0.iter().find(|s| s.name == name).map(|s| s.addr)
+    }
+}
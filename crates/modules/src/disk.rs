@@ -1,17 +1,238 @@
+use std::collections::VecDeque;
 use std::io::{Read, Seek, SeekFrom};
+use std::sync::mpsc::{Receiver, Sender, SyncSender};
+use std::sync::{Arc, Mutex, mpsc};
 
 use lazuli::disks::cso::{Cso, CsoReader};
 use lazuli::disks::rvz::{Rvz, RvzReader};
+use lazuli::disks::wia::{Wia, WiaReader};
 use lazuli::modules::disk::DiskModule;
 
-/// An implementation of [`DiskModule`] for raw .iso data from a reader.
-#[derive(Debug)]
-pub struct IsoModule<R>(pub Option<R>);
+/// Default amount of bytes read ahead by [`IsoModule`] after each seek.
+pub const DEFAULT_PREFETCH_LEN: usize = 64 * 1024;
 
-impl<R> Read for IsoModule<R>
+/// How many prefetched chunks are kept around.
+const PREFETCH_CACHE_LEN: usize = 16;
+
+/// Prefetched chunks of disk data, oldest first, along with their offsets.
+type ChunkCache = Arc<Mutex<VecDeque<(u64, Vec<u8>)>>>;
+
+/// Finds the cached data at the given offset. Returns the data from the offset up to the end of
+/// the chunk containing it, and the offset at which that chunk ends.
+fn find_cached(cache: &VecDeque<(u64, Vec<u8>)>, offset: u64) -> Option<(&[u8], u64)> {
+    cache.iter().find_map(|(start, data)| {
+        let end = start + data.len() as u64;
+        (*start <= offset && offset < end).then(|| (&data[(offset - start) as usize..], end))
+    })
+}
+
+enum Request {
+    Prefetch(u64),
+    Read {
+        offset: u64,
+        len: usize,
+        response: SyncSender<std::io::Result<Vec<u8>>>,
+    },
+}
+
+fn read_chunk<R>(reader: &mut R, offset: u64, len: usize) -> std::io::Result<Vec<u8>>
 where
-    R: Read,
+    R: Read + Seek,
 {
+    reader.seek(SeekFrom::Start(offset))?;
+
+    let mut data = Vec::with_capacity(len);
+    reader.take(len as u64).read_to_end(&mut data)?;
+
+    Ok(data)
+}
+
+#[expect(
+    clippy::needless_pass_by_value,
+    reason = "the worker owns the receiver"
+)]
+fn prefetch_worker<R: Read + Seek>(
+    mut reader: R,
+    cache: ChunkCache,
+    chunk_len: usize,
+    requests: Receiver<Request>,
+) {
+    while let Ok(request) = requests.recv() {
+        match request {
+            Request::Prefetch(offset) => {
+                if find_cached(&cache.lock().unwrap(), offset).is_some() {
+                    continue;
+                }
+
+                let data = match read_chunk(&mut reader, offset, chunk_len) {
+                    Ok(data) if !data.is_empty() => data,
+                    Ok(_) => continue,
+                    Err(e) => {
+                        tracing::warn!("failed to prefetch disk data at 0x{offset:08X}: {e}");
+                        continue;
+                    }
+                };
+
+                let mut cache = cache.lock().unwrap();
+                if cache.len() == PREFETCH_CACHE_LEN {
+                    cache.pop_front();
+                }
+
+                cache.push_back((offset, data));
+            }
+            Request::Read {
+                offset,
+                len,
+                response,
+            } => {
+                // requests are handled in order, so any prefetch requested before this read (e.g.
+                // by the seek leading to it) has already completed and might contain the data
+                let cached = find_cached(&cache.lock().unwrap(), offset)
+                    .map(|(data, _)| data[..data.len().min(len)].to_vec());
+
+                let result = match cached {
+                    Some(data) => Ok(data),
+                    None => read_chunk(&mut reader, offset, len),
+                };
+
+                _ = response.send(result);
+            }
+        }
+    }
+}
+
+/// A reader which reads ahead of the current position in a separate thread.
+///
+/// After each seek (and whenever reads reach the end of prefetched data), the next chunk of data
+/// is requested from the worker thread. Reads which hit the prefetched data are served without
+/// blocking on I/O, while misses are sent to the worker, which serves them from a prefetch still in
+/// flight if possible or reads them synchronously otherwise.
+struct Prefetcher {
+    requests: Sender<Request>,
+    cache: ChunkCache,
+    position: u64,
+    len: u64,
+    last_prefetch: Option<u64>,
+}
+
+impl Prefetcher {
+    fn new<R>(mut reader: R, chunk_len: usize) -> std::io::Result<Self>
+    where
+        R: Read + Seek + Send + 'static,
+    {
+        let len = reader.seek(SeekFrom::End(0))?;
+        let cache = ChunkCache::default();
+        let (sender, receiver) = mpsc::channel();
+
+        let worker_cache = cache.clone();
+        std::thread::Builder::new()
+            .name("lazuli disk prefetcher".into())
+            .spawn(move || prefetch_worker(reader, worker_cache, chunk_len, receiver))?;
+
+        let mut prefetcher = Self {
+            requests: sender,
+            cache,
+            position: 0,
+            len,
+            last_prefetch: None,
+        };
+
+        prefetcher.prefetch(0);
+        Ok(prefetcher)
+    }
+
+    fn prefetch(&mut self, offset: u64) {
+        if offset >= self.len || self.last_prefetch == Some(offset) {
+            return;
+        }
+
+        self.last_prefetch = Some(offset);
+        _ = self.requests.send(Request::Prefetch(offset));
+    }
+}
+
+impl Read for Prefetcher {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let cached = {
+            let cache = self.cache.lock().unwrap();
+            find_cached(&cache, self.position).map(|(data, end)| {
+                let len = data.len().min(buf.len());
+                buf[..len].copy_from_slice(&data[..len]);
+                (len, end)
+            })
+        };
+
+        if let Some((len, end)) = cached {
+            // keep ahead of the reads
+            self.position += len as u64;
+            self.prefetch(end);
+            return Ok(len);
+        }
+
+        let (sender, receiver) = mpsc::sync_channel(1);
+        self.requests
+            .send(Request::Read {
+                offset: self.position,
+                len: buf.len(),
+                response: sender,
+            })
+            .map_err(|_| std::io::Error::other("disk prefetcher is dead"))?;
+
+        let data = receiver
+            .recv()
+            .map_err(|_| std::io::Error::other("disk prefetcher is dead"))??;
+
+        buf[..data.len()].copy_from_slice(&data);
+        self.position += data.len() as u64;
+        self.prefetch(self.position);
+
+        Ok(data.len())
+    }
+}
+
+impl Seek for Prefetcher {
+    fn seek(&mut self, from: SeekFrom) -> std::io::Result<u64> {
+        match from {
+            SeekFrom::Start(x) => self.position = x,
+            SeekFrom::End(x) => self.position = self.len.saturating_add_signed(x),
+            SeekFrom::Current(x) => self.position = self.position.saturating_add_signed(x),
+        }
+
+        self.prefetch(self.position);
+        Ok(self.position)
+    }
+}
+
+/// An implementation of [`DiskModule`] for raw .iso/.gcm data from a reader.
+///
+/// Data is read ahead of the current position in a separate thread, so that sequential reads
+/// (e.g. streamed audio) do not block emulation on I/O.
+pub struct IsoModule(Option<Prefetcher>);
+
+impl IsoModule {
+    /// Creates a new [`IsoModule`] which reads ahead [`DEFAULT_PREFETCH_LEN`] bytes.
+    pub fn new<R>(reader: R) -> std::io::Result<Self>
+    where
+        R: Read + Seek + Send + 'static,
+    {
+        Self::with_prefetch_len(reader, DEFAULT_PREFETCH_LEN)
+    }
+
+    /// Creates a new [`IsoModule`] which reads ahead `prefetch_len` bytes.
+    pub fn with_prefetch_len<R>(reader: R, prefetch_len: usize) -> std::io::Result<Self>
+    where
+        R: Read + Seek + Send + 'static,
+    {
+        Prefetcher::new(reader, prefetch_len).map(|p| Self(Some(p)))
+    }
+
+    /// Creates a new [`IsoModule`] without a disk inserted.
+    pub fn empty() -> Self {
+        Self(None)
+    }
+}
+
+impl Read for IsoModule {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
         if let Some(r) = &mut self.0 {
             r.read(buf)
@@ -21,10 +242,7 @@ where
     }
 }
 
-impl<R> Seek for IsoModule<R>
-where
-    R: Seek,
-{
+impl Seek for IsoModule {
     fn seek(&mut self, from: SeekFrom) -> std::io::Result<u64> {
         if let Some(r) = &mut self.0 {
             r.seek(from)
@@ -34,10 +252,7 @@ where
     }
 }
 
-impl<R> DiskModule for IsoModule<R>
-where
-    R: Read + Seek + Send,
-{
+impl DiskModule for IsoModule {
     fn has_disk(&self) -> bool {
         self.0.is_some()
     }
@@ -79,6 +294,42 @@ where
     }
 }
 
+/// An implementation of [`DiskModule`] for .wia disks.
+pub struct WiaModule<R>(WiaReader<R>);
+
+impl<R> WiaModule<R> {
+    pub fn new(wia: Wia<R>) -> Self {
+        Self(WiaReader::new(wia))
+    }
+}
+
+impl<R> Read for WiaModule<R>
+where
+    R: Read + Seek,
+{
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl<R> Seek for WiaModule<R>
+where
+    R: Read + Seek,
+{
+    fn seek(&mut self, from: SeekFrom) -> std::io::Result<u64> {
+        self.0.seek(from)
+    }
+}
+
+impl<R> DiskModule for WiaModule<R>
+where
+    R: Read + Seek + Send,
+{
+    fn has_disk(&self) -> bool {
+        true
+    }
+}
+
 /// An implementation of [`DiskModule`] for .cso/.ciso disks.
 pub struct CsoModule<R>(CsoReader<R>);
 
@@ -114,3 +365,107 @@ where
         true
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::io::{Cursor, Read, Seek, SeekFrom};
+    use std::sync::{Arc, Mutex};
+
+    use super::Prefetcher;
+
+    const CHUNK_LEN: usize = 0x1000;
+
+    /// A reader which records the offsets of the chunks read from it.
+    struct Recorder {
+        inner: Cursor<Vec<u8>>,
+        reads: Arc<Mutex<Vec<u64>>>,
+    }
+
+    impl Read for Recorder {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.inner.read(buf)
+        }
+    }
+
+    impl Seek for Recorder {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            if let SeekFrom::Start(offset) = pos {
+                self.reads.lock().unwrap().push(offset);
+            }
+
+            self.inner.seek(pos)
+        }
+    }
+
+    fn data() -> Vec<u8> {
+        (0..0x10 * CHUNK_LEN)
+            .map(|i| (i * 7 + i / 251) as u8)
+            .collect()
+    }
+
+    fn prefetcher(chunk_len: usize) -> (Prefetcher, Arc<Mutex<Vec<u64>>>) {
+        let reads = Arc::default();
+        let reader = Recorder {
+            inner: Cursor::new(data()),
+            reads: Arc::clone(&reads),
+        };
+
+        (Prefetcher::new(reader, chunk_len).unwrap(), reads)
+    }
+
+    /// How many times the chunk at the given offset was read from the underlying reader.
+    fn reads_at(reads: &Mutex<Vec<u64>>, offset: u64) -> usize {
+        reads
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|&&o| o == offset)
+            .count()
+    }
+
+    #[test]
+    fn read_after_seek() {
+        let (mut prefetcher, reads) = prefetcher(CHUNK_LEN);
+        let offset = 5 * CHUNK_LEN as u64 + 0x10;
+
+        prefetcher.seek(SeekFrom::Start(offset)).unwrap();
+        let mut buf = [0; 0x20];
+        prefetcher.read_exact(&mut buf).unwrap();
+
+        let start = offset as usize;
+        assert_eq!(buf, data()[start..start + buf.len()]);
+
+        // the read waits for the prefetch caused by the seek instead of reading again
+        assert_eq!(reads_at(&reads, offset), 1);
+    }
+
+    #[test]
+    fn read_hit() {
+        let (mut prefetcher, reads) = prefetcher(CHUNK_LEN);
+
+        let mut first = [0; 0x20];
+        prefetcher.read_exact(&mut first).unwrap();
+        let mut second = [0; 0x20];
+        prefetcher.read_exact(&mut second).unwrap();
+
+        assert_eq!(first, data()[..0x20]);
+        assert_eq!(second, data()[0x20..0x40]);
+        assert_eq!(reads_at(&reads, 0), 1);
+        assert_eq!(reads_at(&reads, 0x20), 0);
+    }
+
+    #[test]
+    fn read_miss() {
+        // nothing is ever prefetched, so every read goes to the underlying reader
+        let (mut prefetcher, reads) = prefetcher(0);
+        let offset = 3 * CHUNK_LEN as u64;
+
+        prefetcher.seek(SeekFrom::Start(offset)).unwrap();
+        let mut buf = [0; 0x20];
+        prefetcher.read_exact(&mut buf).unwrap();
+
+        let start = offset as usize;
+        assert_eq!(buf, data()[start..start + buf.len()]);
+        assert_eq!(reads_at(&reads, offset), 2);
+    }
+}
@@ -1,12 +1,32 @@
+mod analog;
+mod recording;
+mod turbo;
+
+use std::io;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 
 use gilrs::{Axis, Button, GamepadId, Gilrs};
+use lazuli::Cycles;
 use lazuli::modules::input::{ControllerState, InputModule};
 
+pub use self::analog::{AnalogConfig, AnalogSettings, ResponseCurve};
+pub use self::recording::{Playback, Recorder};
+use self::turbo::MacroStatus;
+pub use self::turbo::{MacroConfig, TurboConfig};
+
 struct GilrsInner {
     gilrs: Gilrs,
     active_gamepad: Option<GamepadId>,
     fallback_state: ControllerState,
+    recorder: Option<Recorder>,
+    playback: Option<Playback>,
+    analog: AnalogSettings,
+    turbo: Vec<TurboConfig>,
+    macro_config: MacroConfig,
+    macro_status: MacroStatus,
+    /// Time of the last poll.
+    last_poll: Cycles,
 }
 
 impl Default for GilrsInner {
@@ -24,6 +44,13 @@ impl GilrsInner {
             gilrs,
             active_gamepad,
             fallback_state: Default::default(),
+            recorder: None,
+            playback: None,
+            analog: AnalogSettings::default(),
+            turbo: Vec::new(),
+            macro_config: MacroConfig::default(),
+            macro_status: MacroStatus::Idle,
+            last_poll: Cycles(0),
         }
     }
 
@@ -45,15 +72,18 @@ impl GilrsInner {
             return self.fallback_state;
         };
 
-        let axis = |axis| (255.0 * ((gamepad.value(axis) + 1.0) / 2.0)) as u8;
+        let axis = |axis, config: AnalogConfig| {
+            let value = config.apply(gamepad.value(axis));
+            (255.0 * ((value + 1.0) / 2.0)) as u8
+        };
         let trigger =
             |button| (255.0 * gamepad.button_data(button).map_or(0.0, |v| v.value())) as u8;
 
         ControllerState {
-            analog_x: axis(Axis::LeftStickX),
-            analog_y: axis(Axis::LeftStickY),
-            analog_sub_x: axis(Axis::RightStickX),
-            analog_sub_y: axis(Axis::RightStickY),
+            analog_x: axis(Axis::LeftStickX, self.analog.stick_x),
+            analog_y: axis(Axis::LeftStickY, self.analog.stick_y),
+            analog_sub_x: axis(Axis::RightStickX, self.analog.c_stick_x),
+            analog_sub_y: axis(Axis::RightStickY, self.analog.c_stick_y),
             analog_trigger_left: trigger(Button::LeftTrigger2),
             analog_trigger_right: trigger(Button::RightTrigger2),
             trigger_z: gamepad.is_pressed(Button::LeftTrigger)
@@ -71,6 +101,29 @@ impl GilrsInner {
             button_start: gamepad.is_pressed(Button::Start),
         }
     }
+
+    /// Applies the macro and turbo to the state of the actual gamepad.
+    fn apply_overrides(&mut self, mut state: ControllerState, time: Cycles) -> ControllerState {
+        self.macro_status.record(state, time);
+
+        if matches!(self.macro_status, MacroStatus::Pending) {
+            self.macro_status = MacroStatus::Playing { start: time };
+        }
+
+        if let MacroStatus::Playing { start } = self.macro_status {
+            let elapsed = Cycles(time.0.saturating_sub(start.0)).to_duration();
+            match self.macro_config.state_at(elapsed) {
+                Some(macro_state) => state = macro_state,
+                None => self.macro_status = MacroStatus::Idle,
+            }
+        }
+
+        for turbo in &self.turbo {
+            turbo.apply(&mut state, time);
+        }
+
+        state
+    }
 }
 
 /// This type is internally reference-counted.
@@ -92,17 +145,121 @@ impl GilrsModule {
         let mut inner = self.0.lock().unwrap();
         f(&mut inner.fallback_state);
     }
+
+    /// Starts recording every controller poll to the given path. Replaces any ongoing recording.
+    pub fn start_recording(&self, path: PathBuf) -> io::Result<()> {
+        let recorder = Recorder::create(path)?;
+        self.0.lock().unwrap().recorder = Some(recorder);
+        Ok(())
+    }
+
+    /// Stops the ongoing recording, if any.
+    pub fn stop_recording(&self) {
+        self.0.lock().unwrap().recorder = None;
+    }
+
+    /// Starts replaying the recording at the given path. While replaying, the actual gamepad is
+    /// ignored.
+    pub fn start_playback(&self, path: PathBuf) -> io::Result<()> {
+        let playback = Playback::open(path)?;
+        self.0.lock().unwrap().playback = Some(playback);
+        Ok(())
+    }
+
+    /// Stops the ongoing playback, if any, and goes back to polling the actual gamepad.
+    pub fn stop_playback(&self) {
+        self.0.lock().unwrap().playback = None;
+    }
+
+    /// The configuration of the analog axes of the gamepad.
+    pub fn analog(&self) -> AnalogSettings {
+        self.0.lock().unwrap().analog
+    }
+
+    /// Sets the configuration of the analog axes of the gamepad.
+    pub fn set_analog(&self, analog: AnalogSettings) {
+        self.0.lock().unwrap().analog = analog;
+    }
+
+    /// The turbo buttons.
+    pub fn turbo(&self) -> Vec<TurboConfig> {
+        self.0.lock().unwrap().turbo.clone()
+    }
+
+    /// Sets the turbo buttons.
+    pub fn set_turbo(&self, turbo: Vec<TurboConfig>) {
+        self.0.lock().unwrap().turbo = turbo;
+    }
+
+    /// The current macro.
+    pub fn macro_config(&self) -> MacroConfig {
+        self.0.lock().unwrap().macro_config.clone()
+    }
+
+    /// Sets the current macro.
+    pub fn set_macro(&self, config: MacroConfig) {
+        self.0.lock().unwrap().macro_config = config;
+    }
+
+    /// Plays the current macro from the start. While playing, it replaces the state of the actual
+    /// gamepad, except for turbo.
+    pub fn play_macro(&self) {
+        let mut inner = self.0.lock().unwrap();
+        if !inner.macro_config.steps.is_empty() {
+            inner.macro_status = MacroStatus::Pending;
+        }
+    }
+
+    /// Starts recording the state of the actual gamepad as a new macro.
+    pub fn start_macro_recording(&self) {
+        self.0.lock().unwrap().macro_status = MacroStatus::Recording { steps: Vec::new() };
+    }
+
+    /// Stops recording a macro, replacing the current one with it.
+    pub fn stop_macro_recording(&self) {
+        let mut inner = self.0.lock().unwrap();
+        let time = inner.last_poll;
+        if let Some(config) = inner.macro_status.finish_recording(time) {
+            inner.macro_config = config;
+        }
+    }
+
+    /// Whether a macro is being recorded.
+    pub fn is_recording_macro(&self) -> bool {
+        matches!(
+            self.0.lock().unwrap().macro_status,
+            MacroStatus::Recording { .. }
+        )
+    }
+
+    /// Whether a macro is being played.
+    pub fn is_playing_macro(&self) -> bool {
+        matches!(
+            self.0.lock().unwrap().macro_status,
+            MacroStatus::Pending | MacroStatus::Playing { .. }
+        )
+    }
 }
 
 impl InputModule for GilrsModule {
-    fn controller(&mut self, index: usize) -> Option<ControllerState> {
+    fn controller(&mut self, index: usize, time: Cycles) -> Option<ControllerState> {
         let mut inner = self.0.lock().unwrap();
         inner.process_events();
 
-        if index != 0 {
-            return None;
+        let state = if let Some(playback) = &mut inner.playback {
+            playback.poll(time, index)
+        } else if index == 0 {
+            inner.last_poll = time;
+            let state = inner.get_state();
+            Some(inner.apply_overrides(state, time))
+        } else {
+            None
+        };
+
+        if let Some(recorder) = &mut inner.recorder {
+            recorder.push(time, index, state);
         }
 
-        Some(inner.get_state())
+        state
     }
 }
@@ -0,0 +1,100 @@
+/// How the position of an analog axis is mapped to its output, after the dead zone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResponseCurve {
+    #[default]
+    Linear,
+    Quadratic,
+    Cubic,
+}
+
+impl ResponseCurve {
+    pub const ALL: [Self; 3] = [Self::Linear, Self::Quadratic, Self::Cubic];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Linear => "Linear",
+            Self::Quadratic => "Quadratic",
+            Self::Cubic => "Cubic",
+        }
+    }
+
+    fn apply(self, value: f32) -> f32 {
+        match self {
+            Self::Linear => value,
+            Self::Quadratic => value * value,
+            Self::Cubic => value * value * value,
+        }
+    }
+}
+
+/// Configuration of a single analog axis.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct AnalogConfig {
+    /// Fraction of the range around the center which is ignored, from 0 to 1.
+    pub dead_zone: f32,
+    pub response_curve: ResponseCurve,
+}
+
+impl AnalogConfig {
+    /// Applies the dead zone and response curve to the position of an axis, from -1 to 1.
+    ///
+    /// Positions inside the dead zone become 0, and the rest of the range (`[dead_zone, 1]`) is
+    /// rescaled to `[0, 1]` before going through the curve, so that the output is continuous.
+    pub fn apply(&self, value: f32) -> f32 {
+        let dead_zone = self.dead_zone.clamp(0.0, 0.99);
+        let magnitude = value.abs();
+        if magnitude <= dead_zone {
+            return 0.0;
+        }
+
+        let rescaled = ((magnitude - dead_zone) / (1.0 - dead_zone)).min(1.0);
+        self.response_curve.apply(rescaled).copysign(value)
+    }
+}
+
+/// Configuration of every analog axis of a controller.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct AnalogSettings {
+    pub stick_x: AnalogConfig,
+    pub stick_y: AnalogConfig,
+    pub c_stick_x: AnalogConfig,
+    pub c_stick_y: AnalogConfig,
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AnalogConfig, ResponseCurve};
+
+    #[test]
+    fn dead_zone_rescales() {
+        let config = AnalogConfig {
+            dead_zone: 0.2,
+            response_curve: ResponseCurve::Linear,
+        };
+
+        assert_eq!(config.apply(0.1), 0.0);
+        assert_eq!(config.apply(-0.2), 0.0);
+        assert!((config.apply(0.6) - 0.5).abs() < 1e-6);
+        assert!((config.apply(-0.6) + 0.5).abs() < 1e-6);
+        assert_eq!(config.apply(1.0), 1.0);
+        assert_eq!(config.apply(-1.0), -1.0);
+    }
+
+    #[test]
+    fn curves_keep_sign() {
+        let config = AnalogConfig {
+            dead_zone: 0.0,
+            response_curve: ResponseCurve::Quadratic,
+        };
+
+        assert_eq!(config.apply(0.5), 0.25);
+        assert_eq!(config.apply(-0.5), -0.25);
+
+        let config = AnalogConfig {
+            response_curve: ResponseCurve::Cubic,
+            ..config
+        };
+
+        assert_eq!(config.apply(-0.5), -0.125);
+    }
+}
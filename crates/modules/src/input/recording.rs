@@ -0,0 +1,316 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use lazuli::Cycles;
+use lazuli::modules::input::ControllerState;
+
+/// Magic at the start of every input recording.
+const MAGIC: &[u8; 4] = b"LZIR";
+/// Version of the recording format.
+const VERSION: u32 = 1;
+/// Size of an encoded [`Event`].
+const EVENT_SIZE: usize = 18;
+/// Number of controller ports.
+const PORTS: usize = 4;
+
+/// A single controller poll.
+#[derive(Debug, Clone, Copy)]
+struct Event {
+    /// Cycles elapsed since the system started when the poll happened.
+    time: Cycles,
+    /// Controller port that was polled.
+    index: u8,
+    /// Result of the poll. `None` if the controller was disconnected.
+    state: Option<ControllerState>,
+}
+
+impl Event {
+    fn encode(&self) -> [u8; EVENT_SIZE] {
+        let mut out = [0; EVENT_SIZE];
+        out[0..8].copy_from_slice(&self.time.0.to_le_bytes());
+        out[8] = self.index;
+
+        let Some(state) = self.state else {
+            return out;
+        };
+
+        let buttons = [
+            state.trigger_z,
+            state.trigger_left,
+            state.trigger_right,
+            state.pad_left,
+            state.pad_right,
+            state.pad_down,
+            state.pad_up,
+            state.button_a,
+            state.button_b,
+            state.button_x,
+            state.button_y,
+            state.button_start,
+        ]
+        .into_iter()
+        .enumerate()
+        .fold(0u16, |acc, (i, pressed)| acc | ((pressed as u16) << i));
+
+        out[9] = 1;
+        out[10] = state.analog_x;
+        out[11] = state.analog_y;
+        out[12] = state.analog_sub_x;
+        out[13] = state.analog_sub_y;
+        out[14] = state.analog_trigger_left;
+        out[15] = state.analog_trigger_right;
+        out[16..18].copy_from_slice(&buttons.to_le_bytes());
+
+        out
+    }
+
+    fn decode(bytes: &[u8; EVENT_SIZE]) -> io::Result<Self> {
+        let time = Cycles(u64::from_le_bytes(bytes[0..8].try_into().unwrap()));
+        let index = bytes[8];
+        if index as usize >= PORTS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("input recording has an event for invalid port {index}"),
+            ));
+        }
+
+        if bytes[9] == 0 {
+            return Ok(Self {
+                time,
+                index,
+                state: None,
+            });
+        }
+
+        let buttons = u16::from_le_bytes([bytes[16], bytes[17]]);
+        let pressed = |i: u32| buttons & (1 << i) != 0;
+
+        let state = ControllerState {
+            analog_x: bytes[10],
+            analog_y: bytes[11],
+            analog_sub_x: bytes[12],
+            analog_sub_y: bytes[13],
+            analog_trigger_left: bytes[14],
+            analog_trigger_right: bytes[15],
+            trigger_z: pressed(0),
+            trigger_left: pressed(1),
+            trigger_right: pressed(2),
+            pad_left: pressed(3),
+            pad_right: pressed(4),
+            pad_down: pressed(5),
+            pad_up: pressed(6),
+            button_a: pressed(7),
+            button_b: pressed(8),
+            button_x: pressed(9),
+            button_y: pressed(10),
+            button_start: pressed(11),
+        };
+
+        Ok(Self {
+            time,
+            index,
+            state: Some(state),
+        })
+    }
+}
+
+/// Records every controller poll to a file.
+///
+/// The file starts with the `LZIR` magic and a little-endian `u32` version, followed by one
+/// fixed-size event per poll: the elapsed cycles (`u64`), the port (`u8`), whether the controller
+/// was connected (`u8`), the six analog values and the buttons as a little-endian `u16` bitset.
+pub struct Recorder {
+    writer: BufWriter<File>,
+}
+
+impl Recorder {
+    /// Creates a new recording at the given path.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        tracing::info!("recording input to {}", path.display());
+
+        Ok(Self { writer })
+    }
+
+    /// Records the result of a poll.
+    pub fn push(&mut self, time: Cycles, index: usize, state: Option<ControllerState>) {
+        let event = Event {
+            time,
+            index: index as u8,
+            state,
+        };
+
+        if let Err(e) = self.writer.write_all(&event.encode()) {
+            tracing::error!("failed to write input recording: {e}");
+        }
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        if let Err(e) = self.writer.flush() {
+            tracing::error!("failed to flush input recording: {e}");
+        }
+    }
+}
+
+/// Replays a recording made by [`Recorder`].
+///
+/// A poll returns the most recent recorded state of the port at or before the time of the poll.
+/// Since emulation is deterministic, polls happen at the same cycles they did while recording.
+/// Once the recording runs out, the last state of each port is held.
+pub struct Playback {
+    events: Vec<Event>,
+    cursor: usize,
+    current: [Option<ControllerState>; PORTS],
+}
+
+impl Playback {
+    /// Opens the recording at the given path.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let playback = Self::parse(&std::fs::read(path)?)?;
+        tracing::info!(
+            "replaying {} input events from {}",
+            playback.events.len(),
+            path.display()
+        );
+
+        Ok(playback)
+    }
+
+    fn parse(data: &[u8]) -> io::Result<Self> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_owned());
+
+        let Some((header, body)) = data.split_at_checked(8) else {
+            return Err(invalid("input recording is too short"));
+        };
+
+        if &header[0..4] != MAGIC {
+            return Err(invalid("file is not an input recording"));
+        }
+
+        if u32::from_le_bytes(header[4..8].try_into().unwrap()) != VERSION {
+            return Err(invalid("input recording has an unsupported version"));
+        }
+
+        let (chunks, rest) = body.as_chunks::<EVENT_SIZE>();
+        if !rest.is_empty() {
+            tracing::warn!("input recording ends with a truncated event, ignoring it");
+        }
+
+        let events = chunks
+            .iter()
+            .map(Event::decode)
+            .collect::<io::Result<_>>()?;
+
+        Ok(Self {
+            events,
+            cursor: 0,
+            current: [None; PORTS],
+        })
+    }
+
+    /// Returns the recorded state of the given port at the given time.
+    pub fn poll(&mut self, time: Cycles, index: usize) -> Option<ControllerState> {
+        while let Some(event) = self.events.get(self.cursor)
+            && event.time <= time
+        {
+            self.current[event.index as usize] = event.state;
+            self.cursor += 1;
+
+            if self.cursor == self.events.len() {
+                tracing::info!("input playback finished");
+            }
+        }
+
+        self.current.get(index).copied().flatten()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lazuli::Cycles;
+    use lazuli::modules::input::ControllerState;
+
+    use super::{Event, MAGIC, Playback, VERSION};
+
+    fn recording(events: &[Event]) -> Vec<u8> {
+        let mut data = MAGIC.to_vec();
+        data.extend(VERSION.to_le_bytes());
+        for event in events {
+            data.extend(event.encode());
+        }
+
+        data
+    }
+
+    #[test]
+    fn roundtrip() {
+        let state = ControllerState {
+            analog_x: 12,
+            analog_sub_y: 200,
+            analog_trigger_right: 255,
+            trigger_z: true,
+            pad_down: true,
+            button_start: true,
+            ..Default::default()
+        };
+
+        let event = Event {
+            time: Cycles(0x1234_5678_9ABC),
+            index: 2,
+            state: Some(state),
+        };
+
+        let decoded = Event::decode(&event.encode()).unwrap();
+        assert_eq!(decoded.time, event.time);
+        assert_eq!(decoded.index, 2);
+        assert_eq!(decoded.state, event.state);
+    }
+
+    #[test]
+    fn playback_holds_latest_state() {
+        let pressed = ControllerState {
+            button_a: true,
+            ..Default::default()
+        };
+
+        let data = recording(&[
+            Event {
+                time: Cycles(100),
+                index: 0,
+                state: Some(ControllerState::default()),
+            },
+            Event {
+                time: Cycles(200),
+                index: 0,
+                state: Some(pressed),
+            },
+            Event {
+                time: Cycles(200),
+                index: 1,
+                state: None,
+            },
+        ]);
+
+        let mut playback = Playback::parse(&data).unwrap();
+        assert!(playback.poll(Cycles(50), 0).is_none());
+        assert!(!playback.poll(Cycles(100), 0).unwrap().button_a);
+        assert!(!playback.poll(Cycles(199), 0).unwrap().button_a);
+        assert!(playback.poll(Cycles(200), 0).unwrap().button_a);
+        assert!(playback.poll(Cycles(1000), 0).unwrap().button_a);
+        assert!(playback.poll(Cycles(1000), 1).is_none());
+    }
+
+    #[test]
+    fn rejects_bad_magic() {
+        let mut data = recording(&[]);
+        data[0] = b'X';
+        assert!(Playback::parse(&data).is_err());
+    }
+}
@@ -0,0 +1,185 @@
+use std::time::Duration;
+
+use lazuli::Cycles;
+use lazuli::modules::input::{ControllerButton, ControllerState};
+
+/// Automatically presses and releases a button while it is held.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TurboConfig {
+    pub button: ControllerButton,
+    /// How many times per second the button is pressed.
+    pub frequency_hz: f32,
+}
+
+impl TurboConfig {
+    /// Applies turbo to the given state at the given emulated time. The button is pressed during
+    /// the first half of each period and released during the second.
+    pub fn apply(&self, state: &mut ControllerState, time: Cycles) {
+        if !state.pressed(self.button) || self.frequency_hz <= 0.0 {
+            return;
+        }
+
+        let phase = (time.to_duration().as_secs_f64() * self.frequency_hz as f64).fract();
+        if phase >= 0.5 {
+            state.set(self.button, false);
+        }
+    }
+}
+
+/// A sequence of controller states, each held for some time.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MacroConfig {
+    pub steps: Vec<(ControllerState, Duration)>,
+}
+
+impl MacroConfig {
+    /// Total duration of the macro.
+    pub fn duration(&self) -> Duration {
+        self.steps.iter().map(|(_, duration)| *duration).sum()
+    }
+
+    /// Returns the state of the macro `elapsed` time after it started, or `None` if it has
+    /// already finished.
+    pub fn state_at(&self, elapsed: Duration) -> Option<ControllerState> {
+        let mut end = Duration::ZERO;
+        for (state, duration) in &self.steps {
+            end += *duration;
+            if elapsed < end {
+                return Some(*state);
+            }
+        }
+
+        None
+    }
+}
+
+/// What is being done with the macro.
+#[derive(Debug, Clone, Default)]
+pub enum MacroStatus {
+    #[default]
+    Idle,
+    /// Playback has been requested and starts at the next poll.
+    Pending,
+    Playing {
+        start: Cycles,
+    },
+    /// Every poll is being recorded as a step of a new macro.
+    Recording {
+        steps: Vec<(ControllerState, Cycles)>,
+    },
+}
+
+impl MacroStatus {
+    /// Records a poll if recording.
+    pub fn record(&mut self, state: ControllerState, time: Cycles) {
+        let Self::Recording { steps } = self else {
+            return;
+        };
+
+        // only changes of state start a new step
+        if steps.last().is_none_or(|(last, _)| *last != state) {
+            steps.push((state, time));
+        }
+    }
+
+    /// Finishes a recording at the given time, returning the recorded macro.
+    pub fn finish_recording(&mut self, time: Cycles) -> Option<MacroConfig> {
+        let Self::Recording { steps } = std::mem::take(self) else {
+            return None;
+        };
+
+        let ends = steps.iter().skip(1).map(|(_, start)| *start).chain([time]);
+        let steps = steps
+            .iter()
+            .zip(ends)
+            .map(|((state, start), end)| {
+                (*state, Cycles(end.0.saturating_sub(start.0)).to_duration())
+            })
+            .collect();
+
+        Some(MacroConfig { steps })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use lazuli::Cycles;
+    use lazuli::modules::input::{ControllerButton, ControllerState};
+
+    use super::{MacroConfig, MacroStatus, TurboConfig};
+
+    fn pressed(button: ControllerButton) -> ControllerState {
+        let mut state = ControllerState::default();
+        state.set(button, true);
+        state
+    }
+
+    #[test]
+    fn turbo_alternates() {
+        let turbo = TurboConfig {
+            button: ControllerButton::A,
+            frequency_hz: 10.0,
+        };
+
+        let at = |millis| {
+            let mut state = pressed(ControllerButton::A);
+            turbo.apply(
+                &mut state,
+                Cycles::from_duration(Duration::from_millis(millis)),
+            );
+            state.button_a
+        };
+
+        assert!(at(0));
+        assert!(at(40));
+        assert!(!at(60));
+        assert!(!at(90));
+        assert!(at(110));
+
+        // released buttons stay released
+        let mut state = ControllerState::default();
+        turbo.apply(&mut state, Cycles(0));
+        assert!(!state.button_a);
+    }
+
+    #[test]
+    fn macro_steps() {
+        let config = MacroConfig {
+            steps: vec![
+                (pressed(ControllerButton::A), Duration::from_millis(100)),
+                (pressed(ControllerButton::B), Duration::from_millis(50)),
+            ],
+        };
+
+        assert_eq!(config.duration(), Duration::from_millis(150));
+        assert!(config.state_at(Duration::ZERO).unwrap().button_a);
+        assert!(
+            config
+                .state_at(Duration::from_millis(120))
+                .unwrap()
+                .button_b
+        );
+        assert!(config.state_at(Duration::from_millis(150)).is_none());
+    }
+
+    #[test]
+    fn macro_recording() {
+        let mut status = MacroStatus::Recording { steps: vec![] };
+        let a = pressed(ControllerButton::A);
+
+        status.record(a, Cycles(0));
+        status.record(a, Cycles(100));
+        status.record(ControllerState::default(), Cycles(Cycles::PER_SECOND.0));
+
+        let config = status
+            .finish_recording(Cycles(2 * Cycles::PER_SECOND.0))
+            .unwrap();
+
+        assert_eq!(config.steps.len(), 2);
+        assert_eq!(config.steps[0], (a, Duration::from_secs(1)));
+        assert_eq!(config.steps[1].1, Duration::from_secs(1));
+        assert!(matches!(status, MacroStatus::Idle));
+    }
+}
@@ -2,4 +2,5 @@ pub mod audio;
 pub mod debug;
 pub mod disk;
 pub mod input;
+pub mod plugin;
 pub mod vertex;
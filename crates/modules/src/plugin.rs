@@ -0,0 +1,377 @@
+//! Modules implemented by external plugins, loaded from shared libraries.
+//!
+//! See the [`plugin`] crate for the ABI plugins have to implement.
+use std::ffi::c_void;
+use std::mem::offset_of;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::path::Path;
+
+use easyerr::{Error, ResultExt};
+use lazuli::Cycles;
+use lazuli::modules::audio::AudioModule;
+use lazuli::modules::input::{ControllerState, InputModule};
+use lazuli::system::ai::{Frame, SampleRate};
+use libloading::Library;
+
+#[derive(Debug, Error)]
+pub enum LoadError {
+    #[error("failed to load library")]
+    Library { source: libloading::Error },
+    #[error("library is not a plugin or does not implement this kind of module")]
+    Symbol { source: libloading::Error },
+    #[error("plugin has incompatible ABI version {version}")]
+    Version { version: u32 },
+    #[error("plugin panicked while creating the module")]
+    Panicked,
+    #[error("plugin returned an invalid module")]
+    Invalid,
+}
+
+/// Opens the library at `path` and checks that it implements a compatible ABI version.
+fn open(path: &Path) -> Result<Library, LoadError> {
+    // SAFETY: loading a library runs its initializers, which is inherent to plugins
+    let library = unsafe { Library::new(path) }.context(LoadCtx::Library)?;
+
+    // SAFETY: the symbol has the type defined by the ABI
+    let version = *unsafe { library.get::<plugin::AbiVersionFn>(plugin::ABI_VERSION_SYMBOL) }
+        .context(LoadCtx::Symbol)?;
+
+    // SAFETY: the function takes no arguments
+    let version = catch_unwind(|| unsafe { version() }).map_err(|_| LoadError::Panicked)?;
+    check_version(version)?;
+
+    Ok(library)
+}
+
+fn check_version(version: u32) -> Result<(), LoadError> {
+    if version == plugin::ABI_VERSION {
+        Ok(())
+    } else {
+        Err(LoadError::Version { version })
+    }
+}
+
+/// Reads a function from the vtable of a plugin, or returns `None` if the vtable is too small to
+/// contain it. Functions are only ever appended to vtables, so a plugin provides every function
+/// which fits in the size of its vtable.
+///
+/// Must be used in an unsafe block where `$vtable` points to a vtable which is `$size` bytes long.
+macro_rules! read_fn {
+    ($ty:ty, $vtable:expr, $size:expr, $field:ident) => {{
+        let vtable: *const $ty = $vtable;
+        let end = offset_of!($ty, $field) + size_of::<unsafe extern "C-unwind" fn()>();
+        (end <= $size).then(|| (&raw const (*vtable).$field).read())
+    }};
+}
+
+/// Reads the size of a vtable, which all of them start with.
+///
+/// # Safety
+/// `vtable` must be null or point to a vtable.
+unsafe fn vtable_size<T>(vtable: *const T) -> Result<usize, LoadError> {
+    if vtable.is_null() {
+        return Err(LoadError::Invalid);
+    }
+
+    // SAFETY: vtables start with their size
+    let size = unsafe { vtable.cast::<usize>().read() };
+    if size < size_of::<usize>() {
+        return Err(LoadError::Invalid);
+    }
+
+    Ok(size)
+}
+
+/// Copies the audio vtable of a plugin, checking that it provides every function the host uses.
+///
+/// # Safety
+/// `vtable` must be null or point to an audio vtable.
+unsafe fn read_audio_vtable(
+    vtable: *const plugin::AudioVTable,
+) -> Result<plugin::AudioVTable, LoadError> {
+    // SAFETY: guaranteed by the caller
+    let size = unsafe { vtable_size(vtable) }?;
+
+    // SAFETY: the vtable is `size` bytes long
+    unsafe {
+        Ok(plugin::AudioVTable {
+            size,
+            set_sample_rate: read_fn!(plugin::AudioVTable, vtable, size, set_sample_rate)
+                .ok_or(LoadError::Invalid)?,
+            play: read_fn!(plugin::AudioVTable, vtable, size, play).ok_or(LoadError::Invalid)?,
+            destroy: read_fn!(plugin::AudioVTable, vtable, size, destroy)
+                .ok_or(LoadError::Invalid)?,
+        })
+    }
+}
+
+/// Copies the input vtable of a plugin, checking that it provides every function the host uses.
+///
+/// # Safety
+/// `vtable` must be null or point to an input vtable.
+unsafe fn read_input_vtable(
+    vtable: *const plugin::InputVTable,
+) -> Result<plugin::InputVTable, LoadError> {
+    // SAFETY: guaranteed by the caller
+    let size = unsafe { vtable_size(vtable) }?;
+
+    // SAFETY: the vtable is `size` bytes long
+    unsafe {
+        Ok(plugin::InputVTable {
+            size,
+            controller: read_fn!(plugin::InputVTable, vtable, size, controller)
+                .ok_or(LoadError::Invalid)?,
+            destroy: read_fn!(plugin::InputVTable, vtable, size, destroy)
+                .ok_or(LoadError::Invalid)?,
+        })
+    }
+}
+
+/// Calls a function of a plugin, catching any panic it causes. Returns `None` if it panicked or
+/// if the plugin had already panicked before.
+fn call<T>(name: &str, poisoned: &mut bool, f: impl FnOnce() -> T) -> Option<T> {
+    if *poisoned {
+        return None;
+    }
+
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(value) => Some(value),
+        Err(_) => {
+            tracing::error!("plugin panicked in {name}, it won't be called anymore");
+            *poisoned = true;
+            None
+        }
+    }
+}
+
+/// An [`AudioModule`] implemented by a plugin.
+pub struct PluginAudioModule {
+    state: *mut c_void,
+    /// Functions of the module. These point into the code of the plugin, so they must not be
+    /// called or leave this struct once the library is dropped.
+    vtable: plugin::AudioVTable,
+    poisoned: bool,
+    // must be dropped last, as it owns the code of the plugin. `Drop` destroys the module before
+    // any field is dropped
+    _library: Library,
+}
+
+// SAFETY: plugins are required to allow their modules to be used from any thread
+unsafe impl Send for PluginAudioModule {}
+
+impl PluginAudioModule {
+    /// Loads the plugin at `path` and creates its audio module.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, LoadError> {
+        let library = open(path.as_ref())?;
+
+        // SAFETY: the symbol has the type defined by the ABI
+        let entry = *unsafe { library.get::<plugin::AudioEntryFn>(plugin::AUDIO_ENTRY_SYMBOL) }
+            .context(LoadCtx::Symbol)?;
+
+        // SAFETY: the function takes no arguments
+        let module = catch_unwind(|| unsafe { entry() }).map_err(|_| LoadError::Panicked)?;
+
+        // SAFETY: the vtable, if not null, is a valid vtable provided by the plugin
+        let vtable = unsafe { read_audio_vtable(module.vtable) }?;
+
+        Ok(Self {
+            state: module.state,
+            vtable,
+            poisoned: false,
+            _library: library,
+        })
+    }
+
+    /// Whether the plugin panicked, in which case it isn't called anymore.
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned
+    }
+}
+
+impl AudioModule for PluginAudioModule {
+    fn set_sample_rate(&mut self, sample_rate: SampleRate) {
+        let (state, f) = (self.state, self.vtable.set_sample_rate);
+        // SAFETY: the state belongs to this vtable and hasn't been destroyed
+        call("set_sample_rate", &mut self.poisoned, || unsafe {
+            f(state, sample_rate.value() as u32)
+        });
+    }
+
+    fn play(&mut self, frame: Frame) {
+        let (state, f) = (self.state, self.vtable.play);
+        let frame = plugin::Frame {
+            left: frame.left,
+            right: frame.right,
+        };
+
+        // SAFETY: the state belongs to this vtable and hasn't been destroyed
+        call("play", &mut self.poisoned, || unsafe { f(state, frame) });
+    }
+}
+
+impl Drop for PluginAudioModule {
+    fn drop(&mut self) {
+        let (state, f) = (self.state, self.vtable.destroy);
+        // SAFETY: the state belongs to this vtable and is never used again
+        call("destroy", &mut self.poisoned, || unsafe { f(state) });
+    }
+}
+
+fn controller_from_plugin(state: plugin::ControllerState) -> ControllerState {
+    let pressed = |button| state.buttons & button != 0;
+    ControllerState {
+        analog_x: state.analog_x,
+        analog_y: state.analog_y,
+        analog_sub_x: state.analog_sub_x,
+        analog_sub_y: state.analog_sub_y,
+        analog_trigger_left: state.analog_trigger_left,
+        analog_trigger_right: state.analog_trigger_right,
+        trigger_z: pressed(plugin::BUTTON_Z),
+        trigger_left: pressed(plugin::BUTTON_L),
+        trigger_right: pressed(plugin::BUTTON_R),
+        pad_left: pressed(plugin::BUTTON_PAD_LEFT),
+        pad_right: pressed(plugin::BUTTON_PAD_RIGHT),
+        pad_down: pressed(plugin::BUTTON_PAD_DOWN),
+        pad_up: pressed(plugin::BUTTON_PAD_UP),
+        button_a: pressed(plugin::BUTTON_A),
+        button_b: pressed(plugin::BUTTON_B),
+        button_x: pressed(plugin::BUTTON_X),
+        button_y: pressed(plugin::BUTTON_Y),
+        button_start: pressed(plugin::BUTTON_START),
+    }
+}
+
+/// An [`InputModule`] implemented by a plugin.
+pub struct PluginInputModule {
+    state: *mut c_void,
+    /// Functions of the module. These point into the code of the plugin, so they must not be
+    /// called or leave this struct once the library is dropped.
+    vtable: plugin::InputVTable,
+    poisoned: bool,
+    // must be dropped last, as it owns the code of the plugin. `Drop` destroys the module before
+    // any field is dropped
+    _library: Library,
+}
+
+// SAFETY: plugins are required to allow their modules to be used from any thread
+unsafe impl Send for PluginInputModule {}
+
+impl PluginInputModule {
+    /// Loads the plugin at `path` and creates its input module.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, LoadError> {
+        let library = open(path.as_ref())?;
+
+        // SAFETY: the symbol has the type defined by the ABI
+        let entry = *unsafe { library.get::<plugin::InputEntryFn>(plugin::INPUT_ENTRY_SYMBOL) }
+            .context(LoadCtx::Symbol)?;
+
+        // SAFETY: the function takes no arguments
+        let module = catch_unwind(|| unsafe { entry() }).map_err(|_| LoadError::Panicked)?;
+
+        // SAFETY: the vtable, if not null, is a valid vtable provided by the plugin
+        let vtable = unsafe { read_input_vtable(module.vtable) }?;
+
+        Ok(Self {
+            state: module.state,
+            vtable,
+            poisoned: false,
+            _library: library,
+        })
+    }
+
+    /// Whether the plugin panicked, in which case it isn't called anymore.
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned
+    }
+}
+
+impl InputModule for PluginInputModule {
+    fn controller(&mut self, index: usize, time: Cycles) -> Option<ControllerState> {
+        let (state, f) = (self.state, self.vtable.controller);
+        let mut out = plugin::ControllerState::default();
+
+        // SAFETY: the state belongs to this vtable and hasn't been destroyed, and `out` is valid
+        // for writes
+        let connected = call("controller", &mut self.poisoned, || unsafe {
+            f(state, index as u32, time.0, &raw mut out)
+        })?;
+
+        connected.then(|| controller_from_plugin(out))
+    }
+}
+
+impl Drop for PluginInputModule {
+    fn drop(&mut self) {
+        let (state, f) = (self.state, self.vtable.destroy);
+        // SAFETY: the state belongs to this vtable and is never used again
+        call("destroy", &mut self.poisoned, || unsafe { f(state) });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn version_mismatch() {
+        assert!(check_version(plugin::ABI_VERSION).is_ok());
+        assert!(matches!(
+            check_version(plugin::ABI_VERSION + 1),
+            Err(LoadError::Version { .. })
+        ));
+    }
+
+    #[test]
+    fn missing_library() {
+        assert!(matches!(
+            PluginAudioModule::load("this library does not exist"),
+            Err(LoadError::Library { .. })
+        ));
+    }
+
+    unsafe extern "C-unwind" fn set_sample_rate(_: *mut c_void, _: u32) {}
+    unsafe extern "C-unwind" fn play(_: *mut c_void, _: plugin::Frame) {}
+    unsafe extern "C-unwind" fn destroy(_: *mut c_void) {}
+
+    fn audio_vtable(size: usize) -> Result<plugin::AudioVTable, LoadError> {
+        let vtable = plugin::AudioVTable {
+            size,
+            set_sample_rate,
+            play,
+            destroy,
+        };
+
+        // SAFETY: the vtable is valid and at least `size` bytes are read from it
+        unsafe { read_audio_vtable(&raw const vtable) }
+    }
+
+    #[test]
+    fn vtable_size() {
+        let full = size_of::<plugin::AudioVTable>();
+        assert!(audio_vtable(full).is_ok());
+
+        // newer plugins might provide functions the host doesn't know about
+        assert_eq!(audio_vtable(full + 16).unwrap().size, full + 16);
+
+        // but every function the host uses must be provided
+        let without_destroy = offset_of!(plugin::AudioVTable, destroy);
+        assert!(matches!(
+            audio_vtable(without_destroy),
+            Err(LoadError::Invalid)
+        ));
+        assert!(matches!(audio_vtable(0), Err(LoadError::Invalid)));
+
+        // SAFETY: null vtables are rejected
+        let null = unsafe { read_audio_vtable(std::ptr::null()) };
+        assert!(matches!(null, Err(LoadError::Invalid)));
+    }
+
+    #[test]
+    fn panics_poison() {
+        let mut poisoned = false;
+        assert_eq!(call("test", &mut poisoned, || 1), Some(1));
+        assert_eq!(call("test", &mut poisoned, || panic!("oops")), None::<()>);
+        assert!(poisoned);
+        assert_eq!(call("test", &mut poisoned, || 1), None);
+    }
+}
@@ -17,9 +17,10 @@ fn read_attribute_from_array<D: AttributeDescriptor>(
     array: ArrayDescriptor,
     index: u16,
 ) -> D::Value {
+    // indices might be garbage, so clamp the address to leave room for the largest attribute
     let base = array.address.value() as usize;
     let offset = array.stride as usize * index as usize;
-    let address = base + offset;
+    let address = (base + offset).min(ram.len() - 16);
     let mut array = &ram[address..];
     let mut reader = array.reader();
     descriptor.read(&mut reader).unwrap()
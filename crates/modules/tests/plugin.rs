@@ -0,0 +1,48 @@
+//! Loads the example sine plugin through the plugin loader.
+use std::path::PathBuf;
+use std::process::Command;
+
+use lazuli::modules::audio::AudioModule;
+use lazuli::system::ai::{Frame, SampleRate};
+use modules::plugin::{LoadError, PluginAudioModule, PluginInputModule};
+
+/// Builds the sine plugin into a separate target directory and returns the path to the library.
+fn build_sine_plugin() -> PathBuf {
+    let target_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("plugins");
+    let status = Command::new(env!("CARGO"))
+        .args(["build", "--package", "sine-plugin", "--target-dir"])
+        .arg(&target_dir)
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .status()
+        .expect("failed to run cargo");
+    assert!(status.success(), "failed to build the sine plugin");
+
+    let name = format!(
+        "{}sine_plugin{}",
+        std::env::consts::DLL_PREFIX,
+        std::env::consts::DLL_SUFFIX
+    );
+
+    target_dir.join("debug").join(name)
+}
+
+// runs a nested cargo build, so it's only run explicitly (e.g. by CI)
+#[test]
+#[ignore = "builds the sine plugin with a nested cargo invocation"]
+fn sine_plugin() {
+    let path = build_sine_plugin();
+
+    let mut audio = PluginAudioModule::load(&path).unwrap();
+    audio.set_sample_rate(SampleRate::KHz32);
+    for i in 0..1024 {
+        audio.play(Frame { left: i, right: -i });
+    }
+    assert!(!audio.is_poisoned());
+    drop(audio);
+
+    // the plugin does not implement an input module
+    assert!(matches!(
+        PluginInputModule::load(&path),
+        Err(LoadError::Symbol { .. })
+    ));
+}
@@ -0,0 +1,111 @@
+//! Stable C ABI for modules implemented by external plugins.
+//!
+//! A plugin is a shared library which exports a function named [`ABI_VERSION_SYMBOL`] of type
+//! [`AbiVersionFn`] and the entry points of the kinds of modules it implements
+//! ([`AUDIO_ENTRY_SYMBOL`], [`INPUT_ENTRY_SYMBOL`]). Each entry point creates a module, which is
+//! an opaque state pointer along with a vtable of functions that receive it.
+//!
+//! Versioning works at two levels:
+//! - [`ABI_VERSION`] is bumped whenever an existing type or function changes. Hosts refuse
+//!   plugins with a different version.
+//! - New functions are only ever appended to the end of vtables. Since every vtable starts with
+//!   its size as known by the plugin, hosts can tell whether a function is provided by checking
+//!   that it fits in it.
+//!
+//! Functions use the `C-unwind` ABI, so that panics in plugins written in Rust can be caught by
+//! the host instead of aborting the process.
+use std::ffi::c_void;
+
+/// Version of the ABI described by this crate.
+pub const ABI_VERSION: u32 = 1;
+
+/// Name of the function that returns the ABI version of a plugin.
+pub const ABI_VERSION_SYMBOL: &[u8] = b"lazuli_plugin_abi_version\0";
+/// Name of the function that creates an audio module.
+pub const AUDIO_ENTRY_SYMBOL: &[u8] = b"lazuli_audio_plugin\0";
+/// Name of the function that creates an input module.
+pub const INPUT_ENTRY_SYMBOL: &[u8] = b"lazuli_input_plugin\0";
+
+pub type AbiVersionFn = unsafe extern "C-unwind" fn() -> u32;
+pub type AudioEntryFn = unsafe extern "C-unwind" fn() -> AudioPlugin;
+pub type InputEntryFn = unsafe extern "C-unwind" fn() -> InputPlugin;
+
+/// A stereo audio frame.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Frame {
+    pub left: i16,
+    pub right: i16,
+}
+
+/// Functions of an audio module.
+#[repr(C)]
+pub struct AudioVTable {
+    /// Size of this vtable, in bytes.
+    pub size: usize,
+    /// Sets the sample rate of the frames that will be played, in Hz.
+    pub set_sample_rate: unsafe extern "C-unwind" fn(state: *mut c_void, sample_rate: u32),
+    /// Plays a frame.
+    pub play: unsafe extern "C-unwind" fn(state: *mut c_void, frame: Frame),
+    /// Destroys the module. The state is not used again afterwards.
+    pub destroy: unsafe extern "C-unwind" fn(state: *mut c_void),
+}
+
+/// An audio module created by a plugin.
+#[repr(C)]
+pub struct AudioPlugin {
+    pub state: *mut c_void,
+    pub vtable: *const AudioVTable,
+}
+
+/// State of a controller.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ControllerState {
+    pub analog_x: u8,
+    pub analog_y: u8,
+    pub analog_sub_x: u8,
+    pub analog_sub_y: u8,
+    pub analog_trigger_left: u8,
+    pub analog_trigger_right: u8,
+    /// Pressed buttons, as a combination of the `BUTTON_*` constants.
+    pub buttons: u16,
+}
+
+pub const BUTTON_Z: u16 = 1 << 0;
+pub const BUTTON_L: u16 = 1 << 1;
+pub const BUTTON_R: u16 = 1 << 2;
+pub const BUTTON_PAD_LEFT: u16 = 1 << 3;
+pub const BUTTON_PAD_RIGHT: u16 = 1 << 4;
+pub const BUTTON_PAD_DOWN: u16 = 1 << 5;
+pub const BUTTON_PAD_UP: u16 = 1 << 6;
+pub const BUTTON_A: u16 = 1 << 7;
+pub const BUTTON_B: u16 = 1 << 8;
+pub const BUTTON_X: u16 = 1 << 9;
+pub const BUTTON_Y: u16 = 1 << 10;
+pub const BUTTON_START: u16 = 1 << 11;
+
+/// Functions of an input module.
+#[repr(C)]
+pub struct InputVTable {
+    /// Size of this vtable, in bytes.
+    pub size: usize,
+    /// Polls the controller at port `index`. `time` is the number of CPU cycles elapsed since the
+    /// system started. Returns whether the controller is connected, in which case its state has
+    /// been written to `out`.
+    pub controller: unsafe extern "C-unwind" fn(
+        state: *mut c_void,
+        index: u32,
+        time: u64,
+        out: *mut ControllerState,
+    ) -> bool,
+    /// Destroys the module. The state is not used again afterwards.
+    pub destroy: unsafe extern "C-unwind" fn(state: *mut c_void),
+}
+
+/// An input module created by a plugin.
+#[repr(C)]
+pub struct InputPlugin {
+    pub state: *mut c_void,
+    pub vtable: *const InputVTable,
+}
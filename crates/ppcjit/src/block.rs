@@ -49,6 +49,8 @@ pub enum Pattern {
     IdleVolatileRead,
     /// Function which the status of the CPU->DSP mailbox and returns it.
     GetMailboxStatusFunc,
+    /// Loop copying words with `lwzu`, `stwu` and `bdnz`, which can be run on the host.
+    MemcpyLoop,
 }
 
 /// Meta information regarding a block.
@@ -15,7 +15,7 @@ use cranelift::frontend;
 use cranelift::prelude::InstBuilder;
 use easyerr::Error;
 use gekko::disasm::{Ins, Opcode};
-use gekko::{Reg, SPR};
+use gekko::{PerformanceMonitor, PmcEvent, Reg, SPR};
 use rustc_hash::FxHashMap;
 
 use crate::block::Info;
@@ -23,6 +23,7 @@ use crate::builder::util::IntoIrValue;
 use crate::hooks::{HookKind, Hooks};
 use crate::{
     Codegen, INTERNAL_RAISE_EXCEPTION, NAMESPACE_INTERNALS, NAMESPACE_USER_HOOKS, Sequence,
+    UnimplementedPolicy,
 };
 
 const MEMFLAGS: ir::MemFlags = ir::MemFlags::trusted();
@@ -49,7 +50,13 @@ fn is_cacheable(reg: Reg) -> bool {
             | SPR::DMAU
             | SPR::SRR0
             | SPR::SRR1
-            | SPR::DAR => false,
+            | SPR::DAR
+            | SPR::MMCR0
+            | SPR::MMCR1
+            | SPR::PMC1
+            | SPR::PMC2
+            | SPR::PMC3
+            | SPR::PMC4 => false,
             spr if spr.is_bat() => false,
             spr if spr.is_gqr() => false,
             _ => true,
@@ -80,7 +87,6 @@ pub enum Action {
 
 #[derive(Clone, Copy)]
 pub(crate) struct InstructionInfo {
-    cycles: u8,
     auto_pc: bool,
     action: Action,
 }
@@ -102,6 +108,9 @@ struct Signatures {
     write_quant_hook: ir::SigRef,
     invalidate_icache_hook: ir::SigRef,
     generic_hook: ir::SigRef,
+    unimplemented_instruction_hook: ir::SigRef,
+    hle_hook: ir::SigRef,
+    pmc_increment_hook: ir::SigRef,
 
     raise_exception: ir::SigRef,
 }
@@ -124,6 +133,7 @@ struct HookFuncs {
     // generic
     clear_icache: ir::FuncRef,
     dcache_dma: ir::FuncRef,
+    hid2_changed: ir::FuncRef,
     msr_changed: ir::FuncRef,
     ibat_changed: ir::FuncRef,
     dbat_changed: ir::FuncRef,
@@ -131,6 +141,11 @@ struct HookFuncs {
     tb_changed: ir::FuncRef,
     dec_read: ir::FuncRef,
     dec_changed: ir::FuncRef,
+    wpar_read: ir::FuncRef,
+    wpar_changed: ir::FuncRef,
+    unimplemented_instruction: ir::FuncRef,
+    hle: ir::FuncRef,
+    pmc_increment: ir::FuncRef,
 
     // special
     raise_exception: ir::FuncRef,
@@ -166,6 +181,8 @@ pub struct BlockBuilder<'ctx> {
 
     executed_cycles: u32,
     executed_instructions: u32,
+    /// Cycles taken by the instruction being emitted.
+    current_cycles: u32,
 
     link_index: u32,
     last_updated_cycles: u32,
@@ -248,6 +265,11 @@ impl<'ctx> BlockBuilder<'ctx> {
             invalidate_icache_hook: builder
                 .import_signature(Hooks::invalidate_icache_sig(ptr_type, default)),
             generic_hook: builder.import_signature(Hooks::generic_hook_sig(ptr_type, default)),
+            unimplemented_instruction_hook: builder
+                .import_signature(Hooks::unimplemented_instruction_sig(ptr_type, default)),
+            hle_hook: builder.import_signature(Hooks::hle_sig(ptr_type, default)),
+            pmc_increment_hook: builder
+                .import_signature(Hooks::pmc_increment_sig(ptr_type, default)),
 
             raise_exception: builder
                 .import_signature(exception::raise_exception_sig(ptr_type, default)),
@@ -301,6 +323,7 @@ impl<'ctx> BlockBuilder<'ctx> {
             inv_icache: hook(sigs.invalidate_icache_hook, HookKind::InvICache),
             clear_icache: hook(sigs.generic_hook, HookKind::ClearICache),
             dcache_dma: hook(sigs.generic_hook, HookKind::DCacheDma),
+            hid2_changed: hook(sigs.generic_hook, HookKind::Hid2Changed),
             msr_changed: hook(sigs.generic_hook, HookKind::MsrChanged),
             ibat_changed: hook(sigs.generic_hook, HookKind::IBatChanged),
             dbat_changed: hook(sigs.generic_hook, HookKind::DBatChanged),
@@ -308,6 +331,14 @@ impl<'ctx> BlockBuilder<'ctx> {
             tb_changed: hook(sigs.generic_hook, HookKind::TbChanged),
             dec_read: hook(sigs.generic_hook, HookKind::DecRead),
             dec_changed: hook(sigs.generic_hook, HookKind::DecChanged),
+            wpar_read: hook(sigs.generic_hook, HookKind::WparRead),
+            wpar_changed: hook(sigs.generic_hook, HookKind::WparChanged),
+            unimplemented_instruction: hook(
+                sigs.unimplemented_instruction_hook,
+                HookKind::UnimplementedInstruction,
+            ),
+            hle: hook(sigs.hle_hook, HookKind::Hle),
+            pmc_increment: hook(sigs.pmc_increment_hook, HookKind::PmcIncrement),
             raise_exception,
         };
 
@@ -334,6 +365,7 @@ impl<'ctx> BlockBuilder<'ctx> {
 
             link_index: 0,
             executed_cycles: 0,
+            current_cycles: 0,
             executed_instructions: 0,
 
             last_updated_cycles: 0,
@@ -477,6 +509,53 @@ impl<'ctx> BlockBuilder<'ctx> {
 
         self.last_updated_cycles = self.executed_cycles;
         self.last_updated_instructions = self.executed_instructions;
+
+        if instruction_delta > 0 {
+            self.count_pmc_event(PmcEvent::InstructionComplete, instruction_delta as u32);
+        }
+    }
+
+    /// Reports `count` occurrences of `event` to the performance monitor, if enabled in the
+    /// codegen settings. The hook is only called if MMCR0 or MMCR1 select any event.
+    fn count_pmc_event(&mut self, event: PmcEvent, count: u32) {
+        if !self.codegen.settings.performance_monitor {
+            return;
+        }
+
+        let mmcr0 = self.get(SPR::MMCR0);
+        let mmcr1 = self.get(SPR::MMCR1);
+        let mmcr0 = self
+            .bd
+            .ins()
+            .band_imm(mmcr0, PerformanceMonitor::SELECT_MASK[0] as i64);
+        let mmcr1 = self
+            .bd
+            .ins()
+            .band_imm(mmcr1, PerformanceMonitor::SELECT_MASK[1] as i64);
+        let selected = self.bd.ins().bor(mmcr0, mmcr1);
+
+        let count_bb = self.bd.create_block();
+        let continue_bb = self.bd.create_block();
+        self.bd.set_cold_block(count_bb);
+
+        self.bd
+            .ins()
+            .brif(selected, count_bb, &[], continue_bb, &[]);
+        self.bd.seal_block(count_bb);
+
+        // => count
+        self.switch_to_bb(count_bb);
+        let event = self.bd.ins().iconst(ir::types::I8, event as i64);
+        let count = self.ir_value(count as i32);
+        self.bd.ins().call(
+            self.hooks.pmc_increment,
+            &[self.consts.ctx_ptr, event, count],
+        );
+        self.bd.ins().jump(continue_bb, &[]);
+        self.bd.seal_block(continue_bb);
+
+        // => continue
+        self.switch_to_bb(continue_bb);
     }
 
     /// Calls a generic context hook.
@@ -503,21 +582,23 @@ impl<'ctx> BlockBuilder<'ctx> {
             .set_srcloc(ir::SourceLoc::new(self.executed_instructions));
     }
 
-    /// Calls [`prologue`] as if an instruction with `info` had been executed.
-    fn prologue_with(&mut self, info: InstructionInfo) {
+    /// Calls [`prologue`] as if the current instruction had been executed.
+    fn prologue_current(&mut self) {
         self.executed_instructions += 1;
-        self.executed_cycles += info.cycles as u32;
+        self.executed_cycles += self.current_cycles;
 
         self.prologue();
 
         self.executed_instructions -= 1;
-        self.executed_cycles -= info.cycles as u32;
+        self.executed_cycles -= self.current_cycles;
     }
 
     /// Emits the given instruction into the block.
     fn emit(&mut self, ins: Ins) -> Result<Action, BuilderError> {
         self.bd
             .set_srcloc(ir::SourceLoc::new(self.executed_instructions));
+        self.current_cycles = self.codegen.settings.cycles.cycles(ins);
+
         let info: InstructionInfo = match ins.op {
             Opcode::Add => self.add(ins),
             Opcode::Addc => self.addc(ins),
@@ -549,6 +630,7 @@ impl<'ctx> BlockBuilder<'ctx> {
             Opcode::Cror => self.cror(ins),
             Opcode::Crorc => self.crorc(ins),
             Opcode::Crxor => self.crxor(ins),
+            // the data cache isn't emulated, so flushing, invalidating or touching it is a no-op
             Opcode::Dcbf => self.nop(Action::Continue),
             Opcode::Dcbi => self.nop(Action::Continue),
             Opcode::Dcbst => self.nop(Action::Continue),
@@ -558,6 +640,10 @@ impl<'ctx> BlockBuilder<'ctx> {
             Opcode::DcbzL => self.stub(ins),
             Opcode::Divw => self.divw(ins),
             Opcode::Divwu => self.divwu(ins),
+            // memory accesses are emitted in program order: fastmem stores go straight to guest
+            // memory and cranelift never moves stores or hook calls across each other, so there's
+            // nothing to enforce
+            Opcode::Eieio => self.nop(Action::Continue),
             Opcode::Eqv => self.eqv(ins),
             Opcode::Extsb => self.extsb(ins),
             Opcode::Extsh => self.extsh(ins),
@@ -588,6 +674,8 @@ impl<'ctx> BlockBuilder<'ctx> {
             Opcode::Fsel => self.fsel(ins),
             Opcode::Fsub => self.fsub(ins),
             Opcode::Fsubs => self.fsubs(ins),
+            // stale blocks are dropped by the icbi hook, and since both exit the block, the next
+            // instruction is always fetched again
             Opcode::Icbi => self.icbi(ins),
             Opcode::Isync => self.isync(ins),
             Opcode::Lbz => self.lbz(ins),
@@ -620,7 +708,7 @@ impl<'ctx> BlockBuilder<'ctx> {
             Opcode::Lwzux => self.lwzux(ins),
             Opcode::Lwzx => self.lwzx(ins),
             Opcode::Mcrf => self.mcrf(ins),
-            Opcode::Mcrxr => self.mcrx(ins),
+            Opcode::Mcrxr => self.mcrxr(ins),
             Opcode::Mfcr => self.mfcr(ins),
             Opcode::Mffs => self.mffs(ins),
             Opcode::Mfmsr => self.mfmsr(ins),
@@ -719,30 +807,31 @@ impl<'ctx> BlockBuilder<'ctx> {
             Opcode::Subfic => self.subfic(ins),
             Opcode::Subfme => self.subfme(ins),
             Opcode::Subfze => self.subfze(ins),
+            // like eieio, but also exits the block so that anything made visible by prior stores
+            // (e.g. a DMA kick) is observed by the scheduler before the guest continues
             Opcode::Sync => self.nop(Action::FlushAndPrologue),
+            // translation only goes through the BATs, so there are no cached page translations
+            // to invalidate
             Opcode::Tlbie => self.nop(Action::Continue),
             Opcode::Tlbsync => self.nop(Action::Continue),
             Opcode::Xor => self.xor(ins),
             Opcode::Xori => self.xori(ins),
             Opcode::Xoris => self.xoris(ins),
-            Opcode::Illegal => {
-                if self.codegen.settings.ignore_unimplemented {
-                    self.stub(ins)
-                } else {
-                    return Err(BuilderError::Illegal(ins));
-                }
-            }
-            _ => {
-                if self.codegen.settings.ignore_unimplemented {
-                    self.stub(ins)
-                } else {
-                    todo!("unimplemented instruction {ins:?}")
-                }
-            }
+            Opcode::Illegal if ins.code >> 26 == gekko::HLE_OPCODE => self.hle(ins),
+            Opcode::Illegal => match self.codegen.settings.unimplemented {
+                UnimplementedPolicy::Panic => return Err(BuilderError::Illegal(ins)),
+                UnimplementedPolicy::Ignore => self.stub(ins),
+                UnimplementedPolicy::Hook => self.unimplemented(ins),
+            },
+            _ => match self.codegen.settings.unimplemented {
+                UnimplementedPolicy::Panic => todo!("unimplemented instruction {ins:?}"),
+                UnimplementedPolicy::Ignore => self.stub(ins),
+                UnimplementedPolicy::Hook => self.unimplemented(ins),
+            },
         };
 
         self.executed_instructions += 1;
-        self.executed_cycles += info.cycles as u32;
+        self.executed_cycles += self.current_cycles;
 
         if info.auto_pc {
             let old_pc = self.get(Reg::PC);
@@ -7,13 +7,11 @@ use super::{Action, BlockBuilder};
 use crate::builder::InstructionInfo;
 
 const INT_INFO: InstructionInfo = InstructionInfo {
-    cycles: 2,
     auto_pc: true,
     action: Action::Continue,
 };
 
 const FLOAT_INFO: InstructionInfo = InstructionInfo {
-    cycles: 2,
     auto_pc: true,
     action: Action::Continue,
 };
@@ -404,13 +402,11 @@ impl BlockBuilder<'_> {
 }
 
 const MUL_INFO: InstructionInfo = InstructionInfo {
-    cycles: 3,
     auto_pc: true,
     action: Action::Continue,
 };
 
 const DIV_INFO: InstructionInfo = InstructionInfo {
-    cycles: 19,
     auto_pc: true,
     action: Action::Continue,
 };
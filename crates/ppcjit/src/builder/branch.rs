@@ -3,7 +3,7 @@ use bitos::integer::u5;
 use cranelift::codegen::ir;
 use cranelift::prelude::{Imm64, InstBuilder};
 use gekko::disasm::Ins;
-use gekko::{Reg, SPR};
+use gekko::{CondReg, PmcEvent, Reg, SPR};
 
 use super::BlockBuilder;
 use crate::NAMESPACE_LINK_DATA;
@@ -11,13 +11,11 @@ use crate::builder::util::IntoIrValue;
 use crate::builder::{Action, InstructionInfo, MEMFLAGS};
 
 const UNCONDITIONAL_BRANCH_INFO: InstructionInfo = InstructionInfo {
-    cycles: 2,
     auto_pc: false,
     action: Action::Finish,
 };
 
 const CONDITIONAL_BRANCH_INFO: InstructionInfo = InstructionInfo {
-    cycles: 2,
     auto_pc: true,
     action: Action::Continue,
 };
@@ -183,7 +181,9 @@ impl BlockBuilder<'_> {
         }
 
         self.executed_instructions += 1;
-        self.executed_cycles += 2;
+        self.executed_cycles += self.current_cycles;
+
+        self.count_pmc_event(PmcEvent::BranchTaken, 1);
 
         if block_link {
             self.jump_with_block_link(destination);
@@ -194,7 +194,7 @@ impl BlockBuilder<'_> {
         }
 
         self.executed_instructions -= 1;
-        self.executed_cycles -= 2;
+        self.executed_cycles -= self.current_cycles;
     }
 
     pub fn b(&mut self, ins: Ins) -> InstructionInfo {
@@ -218,7 +218,7 @@ impl BlockBuilder<'_> {
             return UNCONDITIONAL_BRANCH_INFO;
         }
 
-        let cond_bit = 31 - ins.field_bi();
+        let cond_bit = CondReg::crb_index(ins.field_bi());
         let current_pc = self.get(Reg::PC);
 
         let mut branch = self.ir_value(true);
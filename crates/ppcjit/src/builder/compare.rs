@@ -7,7 +7,6 @@ use super::BlockBuilder;
 use crate::builder::{Action, InstructionInfo};
 
 const CMP_INFO: InstructionInfo = InstructionInfo {
-    cycles: 2,
     auto_pc: true,
     action: Action::Continue,
 };
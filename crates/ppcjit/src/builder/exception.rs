@@ -1,24 +1,37 @@
 use cranelift::codegen::ir;
 use cranelift::codegen::ir::InstBuilder;
 use cranelift::codegen::isa::CallConv;
-use gekko::disasm::Ins;
+use gekko::disasm::{Ins, ParsedIns};
 use gekko::{Exception, Reg, SPR};
 
 use super::BlockBuilder;
-use crate::builder::{Action, InstructionInfo};
+use crate::builder::{Action, InstructionInfo, MEMFLAGS};
 
 const RFI_INFO: InstructionInfo = InstructionInfo {
-    cycles: 2,
     auto_pc: false,
     action: Action::FlushAndPrologue,
 };
 
 const EXCEPTION_INFO: InstructionInfo = InstructionInfo {
-    cycles: 2,
     auto_pc: false,
     action: Action::Prologue,
 };
 
+// the hook might have changed anything, so the block ends right after
+const UNIMPLEMENTED_INFO: InstructionInfo = InstructionInfo {
+    auto_pc: true,
+    action: Action::FlushAndPrologue,
+};
+
+// the hook sets PC to wherever the patched function returns to
+const HLE_INFO: InstructionInfo = InstructionInfo {
+    auto_pc: false,
+    action: Action::Prologue,
+};
+
+/// Bit of SRR1 which indicates that a program exception was caused by an illegal instruction.
+const SRR1_ILLEGAL_INSTRUCTION: u32 = 1 << 19;
+
 pub fn raise_exception_sig(ptr_type: ir::Type, call_conv: CallConv) -> ir::Signature {
     ir::Signature {
         params: vec![
@@ -77,6 +90,70 @@ impl BlockBuilder<'_> {
         self.current_bb = continue_block;
     }
 
+    /// Calls the unimplemented instruction hook, raising a program exception if it asks to.
+    pub fn unimplemented(&mut self, ins: Ins) -> InstructionInfo {
+        let mut parsed = ParsedIns::new();
+        ins.parse_basic(&mut parsed);
+        tracing::warn!("emitting hook for unimplemented instruction ({parsed})");
+
+        // the hook must observe the current state, PC included
+        self.flush();
+
+        let pc = self.get(Reg::PC);
+        let code = self.ir_value(ins.code);
+        let call = self.bd.ins().call(
+            self.hooks.unimplemented_instruction,
+            &[self.consts.ctx_ptr, pc, code],
+        );
+        let raise = self.bd.inst_results(call)[0];
+
+        let exit_block = self.bd.create_block();
+        let continue_block = self.bd.create_block();
+
+        self.bd.set_cold_block(exit_block);
+
+        self.bd
+            .ins()
+            .brif(raise, exit_block, &[], continue_block, &[]);
+
+        self.bd.seal_block(exit_block);
+        self.bd.seal_block(continue_block);
+
+        self.switch_to_bb(exit_block);
+        self.raise_exception(Exception::Program);
+
+        // the exception was raised outside of the JIT, so SRR1 is not cached
+        let srr1_offset = SPR::SRR1.offset() as i32;
+        let srr1 = self
+            .bd
+            .ins()
+            .load(ir::types::I32, MEMFLAGS, self.consts.regs_ptr, srr1_offset);
+        let srr1 = self.bd.ins().bor_imm(srr1, SRR1_ILLEGAL_INSTRUCTION as i64);
+        self.bd
+            .ins()
+            .store(MEMFLAGS, srr1, self.consts.regs_ptr, srr1_offset);
+        self.prologue();
+
+        self.switch_to_bb(continue_block);
+        self.current_bb = continue_block;
+
+        UNIMPLEMENTED_INFO
+    }
+
+    /// Calls the HLE hook for an HLE trap.
+    pub fn hle(&mut self, ins: Ins) -> InstructionInfo {
+        // the hook must observe the current state, PC included
+        self.flush();
+
+        let pc = self.get(Reg::PC);
+        let code = self.ir_value(ins.code);
+        self.bd
+            .ins()
+            .call(self.hooks.hle, &[self.consts.ctx_ptr, pc, code]);
+
+        HLE_INFO
+    }
+
     pub fn sc(&mut self, _: Ins) -> InstructionInfo {
         if self.codegen.settings.nop_syscalls {
             return self.nop(Action::FlushAndPrologue);
@@ -105,6 +182,8 @@ impl BlockBuilder<'_> {
         self.set(Reg::PC, new_pc);
         self.set(Reg::MSR, new_msr);
 
+        // the hook must observe the restored MSR and PC
+        self.flush();
         self.call_generic_hook(self.hooks.msr_changed);
 
         RFI_INFO
@@ -8,7 +8,6 @@ use super::BlockBuilder;
 use crate::builder::{Action, InstructionInfo};
 
 const LOGIC_INFO: InstructionInfo = InstructionInfo {
-    cycles: 1,
     auto_pc: true,
     action: Action::Continue,
 };
@@ -89,7 +89,7 @@ impl BlockBuilder<'_> {
         self.switch_to_bb(exit_block);
         self.set(SPR::DAR, addr);
         self.raise_exception(Exception::DSI);
-        self.prologue_with(LOAD_INFO);
+        self.prologue_current();
 
         self.switch_to_bb(continue_block);
         self.bd
@@ -118,7 +118,7 @@ impl BlockBuilder<'_> {
         self.switch_to_bb(exit_block);
         self.set(SPR::DAR, addr);
         self.raise_exception(Exception::DSI);
-        self.prologue_with(STORE_INFO);
+        self.prologue_current();
 
         self.bd.seal_block(continue_block);
         self.switch_to_bb(continue_block);
@@ -244,7 +244,7 @@ impl BlockBuilder<'_> {
         self.switch_to_bb(exit_block);
         self.set(SPR::DAR, addr);
         self.raise_exception(Exception::DSI);
-        self.prologue_with(LOAD_INFO);
+        self.prologue_current();
 
         self.switch_to_bb(continue_block);
         (
@@ -277,7 +277,7 @@ impl BlockBuilder<'_> {
         self.switch_to_bb(exit_block);
         self.set(SPR::DAR, addr);
         self.raise_exception(Exception::DSI);
-        self.prologue_with(STORE_INFO);
+        self.prologue_current();
 
         self.switch_to_bb(continue_block);
         self.bd.ins().uextend(ir::types::I32, size)
@@ -285,7 +285,6 @@ impl BlockBuilder<'_> {
 }
 
 const LOAD_INFO: InstructionInfo = InstructionInfo {
-    cycles: 2,
     auto_pc: true,
     action: Action::Continue,
 };
@@ -570,10 +569,7 @@ impl BlockBuilder<'_> {
             addr = self.bd.ins().iadd_imm(addr, 4);
         }
 
-        InstructionInfo {
-            cycles: 10, // random, chosen by fair dice roll
-            ..LOAD_INFO
-        }
+        LOAD_INFO
     }
 
     pub fn lswi(&mut self, ins: Ins) -> InstructionInfo {
@@ -610,10 +606,7 @@ impl BlockBuilder<'_> {
             addr = self.bd.ins().iadd_imm(addr, 1);
         }
 
-        InstructionInfo {
-            cycles: 10, // random, chosen by fair dice roll
-            ..LOAD_INFO
-        }
+        LOAD_INFO
     }
 
     pub fn lfd(&mut self, ins: Ins) -> InstructionInfo {
@@ -798,7 +791,6 @@ impl BlockBuilder<'_> {
 }
 
 const STORE_INFO: InstructionInfo = InstructionInfo {
-    cycles: 2,
     auto_pc: true,
     action: Action::Continue,
 };
@@ -930,10 +922,7 @@ impl BlockBuilder<'_> {
             addr = self.bd.ins().iadd_imm(addr, 4);
         }
 
-        InstructionInfo {
-            cycles: 10, // random, chosen by fair dice roll
-            ..STORE_INFO
-        }
+        STORE_INFO
     }
 
     pub fn stswi(&mut self, ins: Ins) -> InstructionInfo {
@@ -962,10 +951,7 @@ impl BlockBuilder<'_> {
             addr = self.bd.ins().iadd_imm(addr, 1);
         }
 
-        InstructionInfo {
-            cycles: 10, // random, chosen by fair dice roll
-            ..LOAD_INFO
-        }
+        LOAD_INFO
     }
 
     pub fn stfd(&mut self, ins: Ins) -> InstructionInfo {
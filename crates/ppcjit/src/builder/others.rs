@@ -2,59 +2,63 @@ use bitos::BitUtils;
 use cranelift::codegen::ir;
 use cranelift::prelude::InstBuilder;
 use gekko::disasm::Ins;
-use gekko::{InsExt, Reg, SPR};
+use gekko::{CondReg, InsExt, Reg, SPR};
 
 use super::BlockBuilder;
 use crate::builder::{Action, InstructionInfo};
 
 const SPR_INFO: InstructionInfo = InstructionInfo {
-    cycles: 1,
     auto_pc: true,
     action: Action::Continue,
 };
 
 const MSR_INFO: InstructionInfo = InstructionInfo {
-    cycles: 1,
     auto_pc: true,
     action: Action::Continue,
 };
 
 const CR_INFO: InstructionInfo = InstructionInfo {
-    cycles: 1,
     auto_pc: true,
     action: Action::Continue,
 };
 
 const SR_INFO: InstructionInfo = InstructionInfo {
-    cycles: 2,
     auto_pc: true,
     action: Action::Continue,
 };
 
 const TB_INFO: InstructionInfo = InstructionInfo {
-    cycles: 1,
     auto_pc: true,
     action: Action::Continue,
 };
 
 const DCACHE_INFO: InstructionInfo = InstructionInfo {
-    cycles: 2,
     auto_pc: true,
     action: Action::Continue,
 };
 
 const INV_ICACHE_INFO: InstructionInfo = InstructionInfo {
-    cycles: 2,
     auto_pc: true,
     action: Action::FlushAndPrologue,
 };
 
 const SYNC_ICACHE_INFO: InstructionInfo = InstructionInfo {
-    cycles: 2,
     auto_pc: true,
     action: Action::FlushAndPrologue,
 };
 
+/// Generates the CR mask of the fields selected by a `mtcrf` CRM, where the MSB selects CR0.
+fn generate_cr_mask(crm: u8) -> u32 {
+    let mut mask = 0;
+    for n in 0..8 {
+        if crm.bit(7 - n) {
+            mask |= CondReg::field_mask(n);
+        }
+    }
+
+    mask
+}
+
 fn generate_mask(control: u8) -> u32 {
     let mut mask = 0;
     for i in 0..8 {
@@ -68,11 +72,20 @@ fn generate_mask(control: u8) -> u32 {
 
 impl BlockBuilder<'_> {
     pub fn mfspr(&mut self, ins: Ins) -> InstructionInfo {
-        let spr = ins.spr();
+        let Some(spr) = SPR::from_repr(ins.field_spr()) else {
+            tracing::warn!(
+                "emitting mfspr from unknown SPR {}, reads 0",
+                ins.field_spr()
+            );
+            let zero = self.ir_value(0i32);
+            self.set(ins.gpr_d(), zero);
+            return SPR_INFO;
+        };
+
         match spr {
             SPR::DEC => self.call_generic_hook(self.hooks.dec_read),
             SPR::TBL | SPR::TBU => self.call_generic_hook(self.hooks.tb_read),
-            SPR::WPAR => tracing::warn!("read from WPAR"),
+            SPR::WPAR => self.call_generic_hook(self.hooks.wpar_read),
             _ => (),
         }
 
@@ -83,15 +96,23 @@ impl BlockBuilder<'_> {
     }
 
     pub fn mtspr(&mut self, ins: Ins) -> InstructionInfo {
+        let Some(spr) = SPR::from_repr(ins.field_spr()) else {
+            tracing::warn!(
+                "emitting mtspr to unknown SPR {} as a no-op",
+                ins.field_spr()
+            );
+            return SPR_INFO;
+        };
+
         let value = self.get(ins.gpr_s());
-        let spr = ins.spr();
         self.set(spr, value);
 
         match spr {
             SPR::DEC => self.call_generic_hook(self.hooks.dec_changed),
             SPR::TBL | SPR::TBU => self.call_generic_hook(self.hooks.tb_changed),
             SPR::DMAL | SPR::DMAU => self.call_generic_hook(self.hooks.dcache_dma),
-            SPR::WPAR => tracing::warn!("write to WPAR"),
+            SPR::HID2 => self.call_generic_hook(self.hooks.hid2_changed),
+            SPR::WPAR => self.call_generic_hook(self.hooks.wpar_changed),
             spr if spr.is_data_bat() => self.dbat_changed = true,
             spr if spr.is_instr_bat() => self.ibat_changed = true,
             _ => (),
@@ -143,7 +164,7 @@ impl BlockBuilder<'_> {
 
     pub fn mtcrf(&mut self, ins: Ins) -> InstructionInfo {
         let rs = self.get(ins.gpr_s());
-        let mask = self.ir_value(generate_mask(ins.field_crm()));
+        let mask = self.ir_value(generate_cr_mask(ins.field_crm()));
 
         let cr = self.get(Reg::CR);
         let value = self.bd.ins().bitselect(mask, rs, cr);
@@ -193,9 +214,9 @@ impl BlockBuilder<'_> {
     }
 
     pub fn crxor(&mut self, ins: Ins) -> InstructionInfo {
-        let bit_a = 31 - ins.field_crba();
-        let bit_b = 31 - ins.field_crbb();
-        let bit_dest = 31 - ins.field_crbd();
+        let bit_a = CondReg::crb_index(ins.field_crba());
+        let bit_b = CondReg::crb_index(ins.field_crbb());
+        let bit_dest = CondReg::crb_index(ins.field_crbd());
 
         let cr = self.get(Reg::CR);
         let bit_a = self.get_bit(cr, bit_a);
@@ -209,9 +230,9 @@ impl BlockBuilder<'_> {
     }
 
     pub fn creqv(&mut self, ins: Ins) -> InstructionInfo {
-        let bit_a = 31 - ins.field_crba();
-        let bit_b = 31 - ins.field_crbb();
-        let bit_dest = 31 - ins.field_crbd();
+        let bit_a = CondReg::crb_index(ins.field_crba());
+        let bit_b = CondReg::crb_index(ins.field_crbb());
+        let bit_dest = CondReg::crb_index(ins.field_crbd());
 
         let cr = self.get(Reg::CR);
         let bit_a = self.get_bit(cr, bit_a);
@@ -226,9 +247,9 @@ impl BlockBuilder<'_> {
     }
 
     pub fn cror(&mut self, ins: Ins) -> InstructionInfo {
-        let bit_a = 31 - ins.field_crba();
-        let bit_b = 31 - ins.field_crbb();
-        let bit_dest = 31 - ins.field_crbd();
+        let bit_a = CondReg::crb_index(ins.field_crba());
+        let bit_b = CondReg::crb_index(ins.field_crbb());
+        let bit_dest = CondReg::crb_index(ins.field_crbd());
 
         let cr = self.get(Reg::CR);
         let bit_a = self.get_bit(cr, bit_a);
@@ -242,9 +263,9 @@ impl BlockBuilder<'_> {
     }
 
     pub fn crorc(&mut self, ins: Ins) -> InstructionInfo {
-        let bit_a = 31 - ins.field_crba();
-        let bit_b = 31 - ins.field_crbb();
-        let bit_dest = 31 - ins.field_crbd();
+        let bit_a = CondReg::crb_index(ins.field_crba());
+        let bit_b = CondReg::crb_index(ins.field_crbb());
+        let bit_dest = CondReg::crb_index(ins.field_crbd());
 
         let cr = self.get(Reg::CR);
         let bit_a = self.get_bit(cr, bit_a);
@@ -259,9 +280,9 @@ impl BlockBuilder<'_> {
     }
 
     pub fn crnor(&mut self, ins: Ins) -> InstructionInfo {
-        let bit_a = 31 - ins.field_crba();
-        let bit_b = 31 - ins.field_crbb();
-        let bit_dest = 31 - ins.field_crbd();
+        let bit_a = CondReg::crb_index(ins.field_crba());
+        let bit_b = CondReg::crb_index(ins.field_crbb());
+        let bit_dest = CondReg::crb_index(ins.field_crbd());
 
         let cr = self.get(Reg::CR);
         let bit_a = self.get_bit(cr, bit_a);
@@ -276,9 +297,9 @@ impl BlockBuilder<'_> {
     }
 
     pub fn crand(&mut self, ins: Ins) -> InstructionInfo {
-        let bit_a = 31 - ins.field_crba();
-        let bit_b = 31 - ins.field_crbb();
-        let bit_dest = 31 - ins.field_crbd();
+        let bit_a = CondReg::crb_index(ins.field_crba());
+        let bit_b = CondReg::crb_index(ins.field_crbb());
+        let bit_dest = CondReg::crb_index(ins.field_crbd());
 
         let cr = self.get(Reg::CR);
         let bit_a = self.get_bit(cr, bit_a);
@@ -292,9 +313,9 @@ impl BlockBuilder<'_> {
     }
 
     pub fn crandc(&mut self, ins: Ins) -> InstructionInfo {
-        let bit_a = 31 - ins.field_crba();
-        let bit_b = 31 - ins.field_crbb();
-        let bit_dest = 31 - ins.field_crbd();
+        let bit_a = CondReg::crb_index(ins.field_crba());
+        let bit_b = CondReg::crb_index(ins.field_crbb());
+        let bit_dest = CondReg::crb_index(ins.field_crbd());
 
         let cr = self.get(Reg::CR);
         let bit_a = self.get_bit(cr, bit_a);
@@ -309,9 +330,9 @@ impl BlockBuilder<'_> {
     }
 
     pub fn crnand(&mut self, ins: Ins) -> InstructionInfo {
-        let bit_a = 31 - ins.field_crba();
-        let bit_b = 31 - ins.field_crbb();
-        let bit_dest = 31 - ins.field_crbd();
+        let bit_a = CondReg::crb_index(ins.field_crba());
+        let bit_b = CondReg::crb_index(ins.field_crbb());
+        let bit_dest = CondReg::crb_index(ins.field_crbd());
 
         let cr = self.get(Reg::CR);
         let bit_a = self.get_bit(cr, bit_a);
@@ -326,17 +347,17 @@ impl BlockBuilder<'_> {
     }
 
     pub fn mcrf(&mut self, ins: Ins) -> InstructionInfo {
-        let src_field = 7 - ins.field_crfs();
-        let dst_field = 7 - ins.field_crfd();
+        let src_shift = CondReg::field_shift(ins.field_crfs());
+        let dst_shift = CondReg::field_shift(ins.field_crfd());
 
         // get src
         let cr = self.get(Reg::CR);
-        let src = self.bd.ins().ushr_imm(cr, 4 * src_field as u64 as i64);
+        let src = self.bd.ins().ushr_imm(cr, src_shift as i64);
         let src = self.bd.ins().band_imm(src, 0b1111u64 as i64);
 
         // place src in dst
-        let new = self.bd.ins().ishl_imm(src, 4 * dst_field as u64 as i64);
-        let dst_mask = self.ir_value(0b1111 << (4 * dst_field));
+        let new = self.bd.ins().ishl_imm(src, dst_shift as i64);
+        let dst_mask = self.ir_value(CondReg::field_mask(ins.field_crfd()));
         let value = self.bd.ins().bitselect(dst_mask, new, cr);
 
         self.set(Reg::CR, value);
@@ -344,18 +365,18 @@ impl BlockBuilder<'_> {
         CR_INFO
     }
 
-    pub fn mcrx(&mut self, ins: Ins) -> InstructionInfo {
-        let dst_field = 7 - ins.field_crfd();
+    pub fn mcrxr(&mut self, ins: Ins) -> InstructionInfo {
+        let dst_shift = CondReg::field_shift(ins.field_crfd());
 
-        // get src
+        // get src: SO, OV, CA and bit 28, which are then cleared
         let xer = self.get(SPR::XER);
-        let src = self.bd.ins().band_imm(xer, 0b1111u64 as i64);
-        let new_xer = self.bd.ins().band_imm(xer, !0b1111u64 as i64);
+        let src = self.bd.ins().ushr_imm(xer, 28);
+        let new_xer = self.bd.ins().band_imm(xer, 0x0FFF_FFFF);
 
         // place src in dst
         let cr = self.get(Reg::CR);
-        let new = self.bd.ins().ishl_imm(src, 4 * dst_field as u64 as i64);
-        let dst_mask = self.ir_value(0b1111 << (4 * dst_field));
+        let new = self.bd.ins().ishl_imm(src, dst_shift as i64);
+        let dst_mask = self.ir_value(CondReg::field_mask(ins.field_crfd()));
         let value = self.bd.ins().bitselect(dst_mask, new, cr);
 
         self.set(SPR::XER, new_xer);
@@ -1,7 +1,7 @@
 use cranelift::codegen::ir;
 use cranelift::prelude::{FloatCC, FunctionBuilder, InstBuilder, IntCC};
 use gekko::disasm::{Ins, ParsedIns};
-use gekko::{Reg, SPR};
+use gekko::{CondReg, Reg, SPR};
 use zerocopy::IntoBytes;
 
 use super::{Action, BlockBuilder};
@@ -77,7 +77,6 @@ impl BlockBuilder<'_> {
     pub fn nop(&mut self, action: Action) -> InstructionInfo {
         self.bd.ins().nop();
         InstructionInfo {
-            cycles: 2,
             auto_pc: true,
             action,
         }
@@ -93,7 +92,6 @@ impl BlockBuilder<'_> {
 
         self.bd.ins().nop();
         InstructionInfo {
-            cycles: 2,
             auto_pc: true,
             action: Action::FlushAndPrologue,
         }
@@ -138,16 +136,43 @@ impl BlockBuilder<'_> {
         self.bd.ins().bor(value, rhs)
     }
 
-    /// Rounds each lane in a F64X2 to single point precision (according to the codegen settings).
+    /// Rounds each lane in a F64X2 to single point precision and flushes denormals to zero
+    /// (according to the codegen settings).
     pub fn round_to_single(&mut self, value: ir::Value) -> ir::Value {
-        if self.codegen.settings.round_to_single {
+        let value = if self.codegen.settings.round_to_single {
             let single = self.bd.ins().fvdemote(value);
             self.bd.ins().fvpromote_low(single)
         } else {
             value
+        };
+
+        if self.codegen.settings.flush_denormals {
+            self.flush_denormals(value)
+        } else {
+            value
         }
     }
 
+    /// Replaces each lane in a F64X2 whose magnitude is below [`f32::MIN_POSITIVE`] with a zero
+    /// of the same sign.
+    pub fn flush_denormals(&mut self, value: ir::Value) -> ir::Value {
+        let min = self.ir_value(f32::MIN_POSITIVE as f64);
+        let min = self.bd.ins().splat(ir::types::F64X2, min);
+        let sign = self.ir_value(-0.0f64);
+        let sign = self.bd.ins().splat(ir::types::F64X2, sign);
+
+        let abs = self.bd.ins().fabs(value);
+        let is_denormal = self.bd.ins().fcmp(FloatCC::LessThan, abs, min);
+        let is_denormal = self.bd.ins().bitcast(
+            ir::types::F64X2,
+            ir::MemFlags::new().with_endianness(ir::Endianness::Little),
+            is_denormal,
+        );
+
+        let zero = self.bd.ins().band(value, sign);
+        self.bd.ins().bitselect(is_denormal, zero, value)
+    }
+
     /// Given a F64X2, copies lane 0 to lane 1.
     pub fn copy_ps0_to_ps1(&mut self, value: ir::Value) -> ir::Value {
         let bytes = self.bd.ins().bitcast(
@@ -251,6 +276,8 @@ impl BlockBuilder<'_> {
     }
 
     /// Updates OV and SO in XER. `overflowed` must be a boolean (I8).
+    ///
+    /// SO is sticky: it is only ever set here, and cleared by `mtxer` or `mcrxr`.
     pub fn update_xer_ov(&mut self, overflowed: impl IntoIrValue) {
         let xer = self.get(SPR::XER);
         let overflowed = self.ir_value(overflowed);
@@ -291,7 +318,7 @@ impl BlockBuilder<'_> {
         let eq = self.bd.ins().uextend(ir::types::I32, eq);
         let ov = self.bd.ins().uextend(ir::types::I32, ov);
 
-        let base = (4 * (7 - index)) as u64 as i64;
+        let base = CondReg::field_shift(index) as i64;
         let lt = self.bd.ins().ishl_imm(lt, base + 3);
         let gt = self.bd.ins().ishl_imm(gt, base + 2);
         let eq = self.bd.ins().ishl_imm(eq, base + 1);
@@ -301,7 +328,7 @@ impl BlockBuilder<'_> {
         let value = self.bd.ins().bor(value, eq);
         let value = self.bd.ins().bor(value, ov);
 
-        let mask = self.ir_value(0b1111u32 << base);
+        let mask = self.ir_value(CondReg::field_mask(index));
         let updated = self.bd.ins().band_not(cr, mask);
         let updated = self.bd.ins().bor(updated, value);
 
@@ -371,7 +398,7 @@ impl BlockBuilder<'_> {
         let cr = self.get(Reg::CR);
 
         let bits = self.bd.ins().ushr_imm(fpscr, 4);
-        let mask = self.ir_value(0b1111 << 24);
+        let mask = self.ir_value(CondReg::field_mask(1));
         let updated = self.bd.ins().bitselect(mask, bits, cr);
 
         self.set(Reg::CR, updated);
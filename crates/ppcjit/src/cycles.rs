@@ -0,0 +1,249 @@
+use gekko::disasm::{Ins, Opcode};
+
+/// Approximate cost, in CPU cycles, of each class of instruction.
+///
+/// The defaults are the latencies published for the Gekko (750CXe/750CL) assuming cache hits. No
+/// pipelining is modeled: the cost of a block is just the sum of the costs of its instructions.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CycleTable {
+    /// Simple integer arithmetic, logical, rotate and shift instructions.
+    pub integer: u8,
+    /// Integer comparisons and condition register operations.
+    pub compare: u8,
+    /// `mulli`.
+    pub multiply_immediate: u8,
+    /// `mullw`, `mulhw` and `mulhwu`.
+    pub multiply: u8,
+    /// `divw` and `divwu`.
+    pub divide: u8,
+    /// Floating point and paired single operations, except for divisions.
+    pub float: u8,
+    /// `fdivs` and `ps_div`.
+    pub float_divide_single: u8,
+    /// `fdiv`.
+    pub float_divide_double: u8,
+    /// Loads, including floating point and quantized ones.
+    pub load: u8,
+    /// Stores, including floating point and quantized ones.
+    pub store: u8,
+    /// Additional cost for each word transferred by `lmw`, `stmw`, `lswi` and `stswi`.
+    pub multiple_word: u8,
+    /// Branches.
+    pub branch: u8,
+    /// Moves to and from special registers (SPRs, MSR, SRs, CR and FPSCR).
+    pub system: u8,
+    /// Cache control and synchronization instructions.
+    pub cache: u8,
+    /// `sc` and `rfi`.
+    pub exception: u8,
+}
+
+impl Default for CycleTable {
+    fn default() -> Self {
+        Self {
+            integer: 1,
+            compare: 1,
+            multiply_immediate: 3,
+            multiply: 5,
+            divide: 19,
+            float: 3,
+            float_divide_single: 17,
+            float_divide_double: 31,
+            load: 2,
+            store: 2,
+            multiple_word: 1,
+            branch: 1,
+            system: 2,
+            cache: 2,
+            exception: 2,
+        }
+    }
+}
+
+impl CycleTable {
+    /// Returns how many cycles the given instruction takes.
+    pub fn cycles(&self, ins: Ins) -> u32 {
+        // words transferred by multiple and string instructions
+        let words = match ins.op {
+            Opcode::Lmw => 32 - ins.field_rd() as u32,
+            Opcode::Stmw => 32 - ins.field_rs() as u32,
+            Opcode::Lswi | Opcode::Stswi if ins.field_nb() == 0 => 8,
+            Opcode::Lswi | Opcode::Stswi => (ins.field_nb() as u32).div_ceil(4),
+            _ => 0,
+        };
+
+        let cycles = match ins.op {
+            Opcode::Mulli => self.multiply_immediate,
+            Opcode::Mullw | Opcode::Mulhw | Opcode::Mulhwu => self.multiply,
+            Opcode::Divw | Opcode::Divwu => self.divide,
+
+            Opcode::Cmp
+            | Opcode::Cmpi
+            | Opcode::Cmpl
+            | Opcode::Cmpli
+            | Opcode::Crand
+            | Opcode::Crandc
+            | Opcode::Creqv
+            | Opcode::Crnand
+            | Opcode::Crnor
+            | Opcode::Cror
+            | Opcode::Crorc
+            | Opcode::Crxor
+            | Opcode::Mcrf
+            | Opcode::Mcrxr => self.compare,
+
+            Opcode::Fdivs | Opcode::PsDiv => self.float_divide_single,
+            Opcode::Fdiv => self.float_divide_double,
+            Opcode::Fabs
+            | Opcode::Fadd
+            | Opcode::Fadds
+            | Opcode::Fcmpo
+            | Opcode::Fcmpu
+            | Opcode::Fctiw
+            | Opcode::Fctiwz
+            | Opcode::Fmadd
+            | Opcode::Fmadds
+            | Opcode::Fmr
+            | Opcode::Fmsub
+            | Opcode::Fmsubs
+            | Opcode::Fmul
+            | Opcode::Fmuls
+            | Opcode::Fneg
+            | Opcode::Fnmadd
+            | Opcode::Fnmadds
+            | Opcode::Fnmsub
+            | Opcode::Fnmsubs
+            | Opcode::Fres
+            | Opcode::Frsp
+            | Opcode::Frsqrte
+            | Opcode::Fsel
+            | Opcode::Fsub
+            | Opcode::Fsubs
+            | Opcode::PsAdd
+            | Opcode::PsCmpo0
+            | Opcode::PsCmpo1
+            | Opcode::PsCmpu0
+            | Opcode::PsCmpu1
+            | Opcode::PsMadd
+            | Opcode::PsMadds0
+            | Opcode::PsMadds1
+            | Opcode::PsMerge00
+            | Opcode::PsMerge01
+            | Opcode::PsMerge10
+            | Opcode::PsMerge11
+            | Opcode::PsMr
+            | Opcode::PsMsub
+            | Opcode::PsMul
+            | Opcode::PsMuls0
+            | Opcode::PsMuls1
+            | Opcode::PsNeg
+            | Opcode::PsNmadd
+            | Opcode::PsNmsub
+            | Opcode::PsRes
+            | Opcode::PsRsqrte
+            | Opcode::PsSel
+            | Opcode::PsSub
+            | Opcode::PsSum0
+            | Opcode::PsSum1 => self.float,
+
+            Opcode::Lbz
+            | Opcode::Lbzu
+            | Opcode::Lbzux
+            | Opcode::Lbzx
+            | Opcode::Lfd
+            | Opcode::Lfdu
+            | Opcode::Lfdux
+            | Opcode::Lfdx
+            | Opcode::Lfs
+            | Opcode::Lfsu
+            | Opcode::Lfsux
+            | Opcode::Lfsx
+            | Opcode::Lha
+            | Opcode::Lhau
+            | Opcode::Lhaux
+            | Opcode::Lhax
+            | Opcode::Lhbrx
+            | Opcode::Lhz
+            | Opcode::Lhzu
+            | Opcode::Lhzux
+            | Opcode::Lhzx
+            | Opcode::Lmw
+            | Opcode::Lswi
+            | Opcode::Lwarx
+            | Opcode::Lwbrx
+            | Opcode::Lwz
+            | Opcode::Lwzu
+            | Opcode::Lwzux
+            | Opcode::Lwzx
+            | Opcode::PsqL
+            | Opcode::PsqLu
+            | Opcode::PsqLx => self.load,
+
+            Opcode::Stb
+            | Opcode::Stbu
+            | Opcode::Stbux
+            | Opcode::Stbx
+            | Opcode::Stfd
+            | Opcode::Stfdu
+            | Opcode::Stfdux
+            | Opcode::Stfdx
+            | Opcode::Stfiwx
+            | Opcode::Stfs
+            | Opcode::Stfsu
+            | Opcode::Stfsux
+            | Opcode::Stfsx
+            | Opcode::Sth
+            | Opcode::Sthbrx
+            | Opcode::Sthu
+            | Opcode::Sthux
+            | Opcode::Sthx
+            | Opcode::Stmw
+            | Opcode::Stswi
+            | Opcode::Stw
+            | Opcode::Stwbrx
+            | Opcode::Stwcx_
+            | Opcode::Stwu
+            | Opcode::Stwux
+            | Opcode::Stwx
+            | Opcode::PsqSt
+            | Opcode::PsqStu
+            | Opcode::PsqStx => self.store,
+
+            Opcode::B | Opcode::Bc | Opcode::Bcctr | Opcode::Bclr => self.branch,
+
+            Opcode::Mfcr
+            | Opcode::Mffs
+            | Opcode::Mfmsr
+            | Opcode::Mfspr
+            | Opcode::Mfsr
+            | Opcode::Mftb
+            | Opcode::Mtcrf
+            | Opcode::Mtfsb0
+            | Opcode::Mtfsb1
+            | Opcode::Mtfsf
+            | Opcode::Mtmsr
+            | Opcode::Mtspr
+            | Opcode::Mtsr => self.system,
+
+            Opcode::Dcbf
+            | Opcode::Dcbi
+            | Opcode::Dcbst
+            | Opcode::Dcbt
+            | Opcode::Dcbtst
+            | Opcode::Dcbz
+            | Opcode::DcbzL
+            | Opcode::Eieio
+            | Opcode::Icbi
+            | Opcode::Isync
+            | Opcode::Sync
+            | Opcode::Tlbie
+            | Opcode::Tlbsync => self.cache,
+
+            Opcode::Sc | Opcode::Rfi => self.exception,
+
+            _ => self.integer,
+        };
+
+        cycles as u32 + words * self.multiple_word as u32
+    }
+}
@@ -1,6 +1,6 @@
 use cranelift::codegen::ir;
 use cranelift::codegen::isa::CallConv;
-use gekko::{Address, Cpu, QuantReg};
+use gekko::{Address, Cpu, PmcEvent, QuantReg};
 use strum::FromRepr;
 
 use crate::FastmemLut;
@@ -23,6 +23,12 @@ pub type InvalidateICache = extern "C-unwind" fn(*mut Context, Address);
 
 pub type GenericHook = extern "C-unwind" fn(*mut Context);
 
+pub type UnimplementedInstructionHook = extern "C-unwind" fn(*mut Context, Address, u32) -> bool;
+
+pub type HleHook = extern "C-unwind" fn(*mut Context, Address, u32);
+
+pub type PmcIncrementHook = extern "C-unwind" fn(*mut Context, PmcEvent, u32);
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, FromRepr)]
 #[repr(u32)]
 pub enum HookKind {
@@ -50,6 +56,12 @@ pub enum HookKind {
     TbChanged,
     DecRead,
     DecChanged,
+    WparRead,
+    WparChanged,
+    UnimplementedInstruction,
+    Hle,
+    PmcIncrement,
+    Hid2Changed,
 }
 
 /// External functions that JITed code calls.
@@ -81,6 +93,8 @@ pub struct Hooks {
     pub invalidate_icache: InvalidateICache,
     pub clear_icache: GenericHook,
     pub dcache_dma: GenericHook,
+    /// Called after HID2 is written, since it controls whether the locked cache is enabled.
+    pub hid2_changed: GenericHook,
 
     // msr
     pub msr_changed: GenericHook,
@@ -96,6 +110,29 @@ pub struct Hooks {
     // decrementer
     pub dec_read: GenericHook,
     pub dec_changed: GenericHook,
+
+    // write gather pipe
+    pub wpar_read: GenericHook,
+    pub wpar_changed: GenericHook,
+
+    // unimplemented instructions
+    /// Called when an unimplemented or illegal instruction is executed, if the
+    /// [`UnimplementedPolicy::Hook`](crate::UnimplementedPolicy::Hook) policy is used. Receives the
+    /// address and the raw instruction, and returns whether a program exception should be raised.
+    /// Otherwise, the instruction is skipped.
+    pub unimplemented_instruction: UnimplementedInstructionHook,
+
+    // hle
+    /// Called when an HLE trap (an instruction with the primary opcode [`gekko::HLE_OPCODE`]) is
+    /// executed. Receives the address and the raw instruction, and must set PC to where execution
+    /// continues.
+    pub hle: HleHook,
+
+    // performance monitor
+    /// Called with the number of occurrences of a [`PmcEvent`] since the last call, if
+    /// [`CodegenSettings::performance_monitor`](crate::CodegenSettings::performance_monitor) is
+    /// enabled and MMCR0 or MMCR1 select any event.
+    pub pmc_increment: PmcIncrementHook,
 }
 
 impl Hooks {
@@ -136,6 +173,12 @@ impl Hooks {
             tb_changed: stub!(),
             dec_read: stub!(),
             dec_changed: stub!(),
+            wpar_read: stub!(),
+            wpar_changed: stub!(),
+            unimplemented_instruction: stub!(),
+            hle: stub!(),
+            pmc_increment: stub!(),
+            hid2_changed: stub!(),
         }
     }
 
@@ -261,6 +304,48 @@ impl Hooks {
         }
     }
 
+    /// Returns the function signature for the `unimplemented_instruction` hook.
+    pub(crate) fn unimplemented_instruction_sig(
+        ptr_type: ir::Type,
+        call_conv: CallConv,
+    ) -> ir::Signature {
+        ir::Signature {
+            params: vec![
+                ir::AbiParam::new(ptr_type),       // ctx
+                ir::AbiParam::new(ir::types::I32), // address
+                ir::AbiParam::new(ir::types::I32), // instruction
+            ],
+            returns: vec![ir::AbiParam::new(ir::types::I8)], // raise exception?
+            call_conv,
+        }
+    }
+
+    /// Returns the function signature for the `hle` hook.
+    pub(crate) fn hle_sig(ptr_type: ir::Type, call_conv: CallConv) -> ir::Signature {
+        ir::Signature {
+            params: vec![
+                ir::AbiParam::new(ptr_type),       // ctx
+                ir::AbiParam::new(ir::types::I32), // address
+                ir::AbiParam::new(ir::types::I32), // instruction
+            ],
+            returns: vec![],
+            call_conv,
+        }
+    }
+
+    /// Returns the function signature for the `pmc_increment` hook.
+    pub(crate) fn pmc_increment_sig(ptr_type: ir::Type, call_conv: CallConv) -> ir::Signature {
+        ir::Signature {
+            params: vec![
+                ir::AbiParam::new(ptr_type),       // ctx
+                ir::AbiParam::new(ir::types::I8),  // event
+                ir::AbiParam::new(ir::types::I32), // count
+            ],
+            returns: vec![],
+            call_conv,
+        }
+    }
+
     /// Returns the function signature for a generic hook.
     pub(crate) fn generic_hook_sig(ptr_type: ir::Type, call_conv: CallConv) -> ir::Signature {
         ir::Signature {
@@ -3,6 +3,7 @@
 
 mod builder;
 mod cache;
+mod cycles;
 mod module;
 mod sequence;
 mod unwind;
@@ -39,19 +40,41 @@ use crate::unwind::UnwindHandle;
 #[rustfmt::skip]
 pub use crate::{
     block::Block,
+    cycles::CycleTable,
     sequence::Sequence,
 };
 
+/// What to do with unimplemented and illegal instructions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Hash)]
+pub enum UnimplementedPolicy {
+    /// Fail to compile the block (illegal instructions) or panic (unimplemented instructions).
+    #[default]
+    Panic,
+    /// Treat them as no-ops.
+    Ignore,
+    /// Call the [`unimplemented_instruction`](Hooks::unimplemented_instruction) hook when they
+    /// are executed, which decides whether to raise a program exception.
+    Hook,
+}
+
 #[derive(Debug, Clone, PartialEq, Default, Hash)]
 pub struct CodegenSettings {
     /// Whether to treat `sc` instructions as no-ops.
     pub nop_syscalls: bool,
     /// Whether to ignore the FPU enabled bit in MSR.
     pub force_fpu: bool,
-    /// Whether to ignore unimplemented instructions instead of panicking.
-    pub ignore_unimplemented: bool,
+    /// What to do with unimplemented and illegal instructions.
+    pub unimplemented: UnimplementedPolicy,
     /// Whether to perform round to single operations.
     pub round_to_single: bool,
+    /// Whether to flush single precision results which are too small to be a normal `f32` to
+    /// zero, as the Gekko does.
+    pub flush_denormals: bool,
+    /// Cost of each instruction, used to compute the cycles executed by blocks.
+    pub cycles: CycleTable,
+    /// Whether to report completed instructions and taken branches to the performance monitor
+    /// counters.
+    pub performance_monitor: bool,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -200,6 +223,14 @@ impl Codegen {
                     HookKind::TbChanged => self.hooks.tb_changed as usize,
                     HookKind::DecRead => self.hooks.dec_read as usize,
                     HookKind::DecChanged => self.hooks.dec_changed as usize,
+                    HookKind::WparRead => self.hooks.wpar_read as usize,
+                    HookKind::WparChanged => self.hooks.wpar_changed as usize,
+                    HookKind::UnimplementedInstruction => {
+                        self.hooks.unimplemented_instruction as usize
+                    }
+                    HookKind::Hle => self.hooks.hle as usize,
+                    HookKind::PmcIncrement => self.hooks.pmc_increment as usize,
+                    HookKind::Hid2Changed => self.hooks.hid2_changed as usize,
                 };
 
                 jitclif::write_relocation(code, reloc, addr);
@@ -449,6 +480,34 @@ impl Jit {
         Ok((artifact, meta))
     }
 
+    /// Compiles the given instructions (up until a terminal instruction or the end of the iterator)
+    /// without producing a block, returning meta information which always includes the CLIR and
+    /// disassembly, even in release builds. The cache is bypassed.
+    ///
+    /// This is meant for inspecting the code generated for a block on demand.
+    pub fn inspect(&mut self, instructions: impl Iterator<Item = Ins>) -> Result<Meta, BuildError> {
+        let translated = self.translate(instructions)?;
+        let sequence = translated.sequence;
+        let pattern = sequence.detect_pattern();
+
+        let clir = translated.func.display().to_string();
+        let artifact = self
+            .codegen
+            .compile(translated.func, true)
+            .with_context(|_| BuildCtx::Codegen {
+                sequence: sequence.clone(),
+                clir: Some(clir.clone()),
+            })?;
+
+        Ok(Meta {
+            seq: sequence,
+            clir: Some(clir),
+            disasm: artifact.disasm,
+            cycles: translated.cycles,
+            pattern,
+        })
+    }
+
     /// Builds a block with the given instructions (up until a terminal instruction or the end of
     /// the iterator).
     pub fn build(&mut self, instructions: impl Iterator<Item = Ins>) -> Result<Block, BuildError> {
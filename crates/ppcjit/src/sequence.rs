@@ -31,6 +31,32 @@ impl Sequence {
         is_load && is_cmp_imm && is_branch_cond && load_dst_is_cmp_src && is_rel_jmp_to_start
     }
 
+    fn is_memcpy_loop(&self) -> bool {
+        if self.len() != 3 {
+            return false;
+        }
+
+        let (load, store, branch) = (self[0], self[1], self[2]);
+
+        let is_load = matches!(load.op, Opcode::Lwzu);
+        let is_store = matches!(store.op, Opcode::Stwu);
+        let stores_loaded = store.field_rs() == load.field_rd();
+
+        // update forms are invalid with rA = 0 or rA = rD, and the pointers must be distinct
+        let src = load.field_ra();
+        let dst = store.field_ra();
+        let valid_pointers =
+            src != 0 && dst != 0 && src != dst && src != load.field_rd() && dst != load.field_rd();
+
+        // bdnz, ignoring the prediction hint
+        let is_bdnz = matches!(branch.op, Opcode::Bc)
+            && branch.field_bo() & 0b10110 == 0b10000
+            && !branch.field_lk();
+        let is_rel_jmp_to_start = !branch.field_aa() && branch.field_bd() == -8;
+
+        is_load && is_store && stores_loaded && valid_pointers && is_bdnz && is_rel_jmp_to_start
+    }
+
     // fn is_call_check_loop(&self) -> bool {
     //     if self.len() < 3 {
     //         return false;
@@ -126,6 +152,10 @@ impl Sequence {
             return Pattern::IdleVolatileRead;
         }
 
+        if self.is_memcpy_loop() {
+            return Pattern::MemcpyLoop;
+        }
+
         Pattern::None
     }
 }
@@ -1,8 +1,15 @@
+use std::collections::HashMap;
+use std::ptr::NonNull;
+
 use cranelift::codegen::isa;
+use gekko::{Address, CondReg, Cpu, Exception, MachineState, PmcEvent};
 
-use crate::block::Meta;
-use crate::hooks::Hooks;
-use crate::{Artifact, CodegenSettings, Jit, Sequence, Settings};
+use crate::block::{Meta, Pattern};
+use crate::hooks::{Context, Hooks};
+use crate::{
+    Artifact, Block, CodegenSettings, CycleTable, FASTMEM_LUT_COUNT, FastmemLut, Jit, Sequence,
+    Settings, UnimplementedPolicy,
+};
 
 macro_rules! ppc {
     ($($mnemonic:ident $($arg:expr)*);* $(;)?) => {
@@ -63,8 +70,11 @@ fn compile_sequence(isa: isa::Builder, sequence: Sequence) -> (Artifact, Meta) {
             codegen: CodegenSettings {
                 nop_syscalls: false,
                 force_fpu: false,
-                ignore_unimplemented: false,
+                unimplemented: UnimplementedPolicy::Panic,
                 round_to_single: false,
+                flush_denormals: false,
+                cycles: CycleTable::default(),
+                performance_monitor: false,
             },
             cache_path: None,
         },
@@ -98,6 +108,37 @@ fn test_sequence(name: &str, sequence: Sequence) {
     inner(name, sequence.clone(), jitclif::isa::aarch64(), "aarch64");
 }
 
+#[test]
+fn inspect() {
+    let mut jit = Jit::with_isa(
+        jitclif::isa::x86_64_v3(),
+        Settings {
+            codegen: CodegenSettings {
+                nop_syscalls: false,
+                force_fpu: false,
+                unimplemented: UnimplementedPolicy::Panic,
+                round_to_single: false,
+                flush_denormals: false,
+                cycles: CycleTable::default(),
+                performance_monitor: false,
+            },
+            cache_path: None,
+        },
+        unsafe { Hooks::stub() },
+    );
+
+    let sequence = ppc! {
+        addi gpr(3) gpr(3) i(1);
+        addi gpr(4) gpr(4) i(2);
+    };
+
+    // code is always available when inspecting, regardless of the build profile
+    let meta = jit.inspect(sequence.0.clone().into_iter()).unwrap();
+    assert_eq!(meta.seq.len(), sequence.len());
+    assert!(meta.clir.is_some_and(|clir| !clir.is_empty()));
+    assert!(meta.disasm.is_some_and(|disasm| !disasm.is_empty()));
+}
+
 #[test]
 fn fcmpu() {
     test_sequence(
@@ -121,6 +162,31 @@ fn ps_add_acc() {
     );
 }
 
+#[test]
+fn multiply_costs_more_than_add() {
+    let (_, add) = compile_sequence(
+        jitclif::isa::x86_64_v1(),
+        ppc! {
+            add gpr(3) gpr(4) gpr(5);
+            add gpr(3) gpr(3) gpr(5);
+            add gpr(3) gpr(3) gpr(5);
+            add gpr(3) gpr(3) gpr(5);
+        },
+    );
+
+    let (_, mullw) = compile_sequence(
+        jitclif::isa::x86_64_v1(),
+        ppc! {
+            mullw gpr(3) gpr(4) gpr(5);
+            mullw gpr(3) gpr(3) gpr(5);
+            mullw gpr(3) gpr(3) gpr(5);
+            mullw gpr(3) gpr(3) gpr(5);
+        },
+    );
+
+    assert!(mullw.cycles > add.cycles);
+}
+
 #[test]
 fn gu_vec_scale() {
     // ps_guVecScale:
@@ -210,3 +276,912 @@ fn gu_mtx_identity() {
         },
     );
 }
+
+#[test]
+fn memcpy_loop_pattern() {
+    fn detect(mut sequence: Sequence, bd: i16) -> Pattern {
+        // bdnz
+        let code = 0x4200_0000 | (bd as u16 as u32 & 0xFFFC);
+        sequence.0.push(gekko::disasm::Ins::new(
+            code,
+            gekko::disasm::Extensions::gekko_broadway(),
+        ));
+
+        let (_, meta) = compile_sequence(jitclif::isa::x86_64_v1(), sequence);
+        meta.pattern
+    }
+
+    let copy = ppc! {
+        lwzu gpr(0) off(4) gpr(4);
+        stwu gpr(0) off(4) gpr(3);
+    };
+    assert_eq!(detect(copy.clone(), -8), Pattern::MemcpyLoop);
+    assert_eq!(detect(copy, -12), Pattern::None);
+
+    // stores something other than the loaded word
+    let other = ppc! {
+        lwzu gpr(0) off(4) gpr(4);
+        stwu gpr(5) off(4) gpr(3);
+    };
+    assert_eq!(detect(other, -8), Pattern::None);
+
+    // same pointer for both
+    let aliased = ppc! {
+        lwzu gpr(0) off(4) gpr(4);
+        stwu gpr(0) off(4) gpr(4);
+    };
+    assert_eq!(detect(aliased, -8), Pattern::None);
+}
+
+struct RfiContext {
+    cpu: Cpu,
+    fastmem: Box<FastmemLut>,
+    /// MSR values observed by the `msr_changed` hook.
+    msr_changes: Vec<u32>,
+}
+
+extern "C-unwind" fn rfi_get_registers(ctx: *mut Context) -> *mut Cpu {
+    let ctx = unsafe { &mut *ctx.cast::<RfiContext>() };
+    &raw mut ctx.cpu
+}
+
+extern "C-unwind" fn rfi_get_fastmem(ctx: *mut Context) -> *mut FastmemLut {
+    let ctx = unsafe { &mut *ctx.cast::<RfiContext>() };
+    &raw mut *ctx.fastmem
+}
+
+extern "C-unwind" fn rfi_msr_changed(ctx: *mut Context) {
+    let ctx = unsafe { &mut *ctx.cast::<RfiContext>() };
+    let msr = ctx.cpu.supervisor.config.msr.to_bits();
+    ctx.msr_changes.push(msr);
+}
+
+#[test]
+fn rfi_restores_msr() {
+    let mut jit = Jit::new(
+        Settings {
+            codegen: CodegenSettings {
+                nop_syscalls: false,
+                force_fpu: false,
+                unimplemented: UnimplementedPolicy::Panic,
+                round_to_single: false,
+                flush_denormals: false,
+                cycles: CycleTable::default(),
+                performance_monitor: false,
+            },
+            cache_path: None,
+        },
+        Hooks {
+            get_registers: rfi_get_registers,
+            get_fastmem: rfi_get_fastmem,
+            msr_changed: rfi_msr_changed,
+            ..unsafe { Hooks::stub() }
+        },
+    );
+
+    let mut ctx = RfiContext {
+        cpu: Cpu::default(),
+        fastmem: Box::new([None; FASTMEM_LUT_COUNT]),
+        msr_changes: Vec::new(),
+    };
+
+    // running with translation and interrupts enabled, take a syscall
+    let msr = MachineState::default()
+        .with_data_addr_translation(true)
+        .with_instr_addr_translation(true)
+        .with_float_available(true)
+        .with_interrupts(true)
+        .with_recoverable_exception(true);
+    ctx.cpu.pc = Address(0x8000_1234);
+    ctx.cpu.supervisor.config.msr = msr;
+    ctx.cpu.raise_exception(Exception::Syscall);
+
+    assert_eq!(ctx.cpu.supervisor.exception.srr[0], 0x8000_1238);
+    assert!(!ctx.cpu.supervisor.config.msr.data_addr_translation());
+    assert!(!ctx.cpu.supervisor.config.msr.interrupts());
+
+    // the handler returns somewhere else with interrupts disabled, and leaves some bits set in
+    // MSR that rfi must not keep
+    ctx.cpu.supervisor.exception.srr[0] = 0x8000_4003;
+    let srr1 = ctx.cpu.supervisor.exception.srr[1];
+    let srr1 = MachineState::from_bits(srr1)
+        .with_interrupts(false)
+        .with_exception_little_endian(true)
+        .to_bits();
+    ctx.cpu.supervisor.exception.srr[1] = srr1;
+
+    let handler_msr = ctx
+        .cpu
+        .supervisor
+        .config
+        .msr
+        .clone()
+        .with_reduced_power(true)
+        .to_bits();
+    ctx.cpu.supervisor.config.msr = MachineState::from_bits(handler_msr);
+
+    let block = jit.build(ppc! { rfi }.0.into_iter()).unwrap();
+    let info = unsafe { jit.call((&raw mut ctx).cast(), block.as_ptr()) };
+    assert_eq!(info.instructions, 1);
+
+    let mask = Exception::SRR1_TO_MSR_MASK;
+    let expected = ((handler_msr & !mask) | (srr1 & mask)) & !(1 << 18);
+    let restored = ctx.cpu.supervisor.config.msr.clone();
+    assert_eq!(restored.to_bits(), expected);
+    assert!(restored.data_addr_translation());
+    assert!(restored.instr_addr_translation());
+    assert!(restored.float_available());
+    assert!(!restored.interrupts());
+    assert!(!restored.reduced_power());
+    assert!(!restored.exception_little_endian());
+
+    assert_eq!(ctx.cpu.pc, Address(0x8000_4000));
+    assert_eq!(ctx.cpu.supervisor.exception.srr[1], srr1);
+    assert_eq!(ctx.msr_changes, [expected]);
+}
+
+#[test]
+fn mtcrf_sparse_mask() {
+    let mut jit = Jit::new(
+        Settings {
+            codegen: CodegenSettings {
+                nop_syscalls: false,
+                force_fpu: false,
+                unimplemented: UnimplementedPolicy::Panic,
+                round_to_single: false,
+                flush_denormals: false,
+                cycles: CycleTable::default(),
+                performance_monitor: false,
+            },
+            cache_path: None,
+        },
+        Hooks {
+            get_registers: rfi_get_registers,
+            get_fastmem: rfi_get_fastmem,
+            ..unsafe { Hooks::stub() }
+        },
+    );
+
+    let mut ctx = RfiContext {
+        cpu: Cpu::default(),
+        fastmem: Box::new([None; FASTMEM_LUT_COUNT]),
+        msr_changes: Vec::new(),
+    };
+
+    ctx.cpu.user.gpr[3] = 0x1234_5678;
+    ctx.cpu.user.cr = CondReg::from_bits(0xAAAA_AAAA);
+
+    // CRM's MSB selects CR0, so this moves CR0, CR5 and CR7 only
+    let block = jit
+        .build(ppc! { mtcrf u(0b1000_0101) gpr(3) }.0.into_iter())
+        .unwrap();
+    let info = unsafe { jit.call((&raw mut ctx).cast(), block.as_ptr()) };
+    assert_eq!(info.instructions, 1);
+
+    let cr = &ctx.cpu.user.cr;
+    assert_eq!(cr.to_bits(), 0x1AAA_A6A8);
+    assert_eq!(cr.field(0).to_bits().value(), 0x1);
+    assert_eq!(cr.field(1).to_bits().value(), 0xA);
+    assert_eq!(cr.field(5).to_bits().value(), 0x6);
+    assert_eq!(cr.field(7).to_bits().value(), 0x8);
+    assert!(cr.crb(3));
+    assert!(!cr.crb(0));
+}
+
+/// Runs a single instruction with `r4 = ra`, `r5 = rb` and the given XER, returning `r3`, XER
+/// and CR.
+fn exec_xer(code: u32, ra: u32, rb: u32, xer: u32) -> (u32, u32, u32) {
+    let mut jit = Jit::new(
+        Settings {
+            codegen: CodegenSettings::default(),
+            cache_path: None,
+        },
+        Hooks {
+            get_registers: rfi_get_registers,
+            get_fastmem: rfi_get_fastmem,
+            ..unsafe { Hooks::stub() }
+        },
+    );
+
+    let mut ctx = RfiContext {
+        cpu: Cpu::default(),
+        fastmem: Box::new([None; FASTMEM_LUT_COUNT]),
+        msr_changes: Vec::new(),
+    };
+
+    ctx.cpu.user.gpr[4] = ra;
+    ctx.cpu.user.gpr[5] = rb;
+    ctx.cpu.user.xer = gekko::XerReg::from_bits(xer);
+
+    let ins = gekko::disasm::Ins::new(code, gekko::disasm::Extensions::gekko_broadway());
+    let block = jit.build(std::iter::once(ins)).unwrap();
+    let info = unsafe { jit.call((&raw mut ctx).cast(), block.as_ptr()) };
+    assert_eq!(info.instructions, 1);
+
+    (
+        ctx.cpu.user.gpr[3],
+        ctx.cpu.user.xer.to_bits(),
+        ctx.cpu.user.cr.to_bits(),
+    )
+}
+
+/// Encodes a XO-form instruction `rD = 3, rA = 4, rB = 5`.
+fn xo_form(xo: u32, oe: bool, rc: bool) -> u32 {
+    (31 << 26) | (3 << 21) | (4 << 16) | (5 << 11) | ((oe as u32) << 10) | (xo << 1) | rc as u32
+}
+
+const SO: u32 = 1 << 31;
+const OV: u32 = 1 << 30;
+const CA: u32 = 1 << 29;
+
+#[test]
+fn extended_add() {
+    const ADDC: u32 = 10;
+    const ADDE: u32 = 138;
+    const ADDZE: u32 = 202;
+    const ADDME: u32 = 234;
+
+    // carry out of 0xFFFF_FFFF + 0 only happens with the incoming carry
+    assert_eq!(exec_xer(xo_form(ADDE, false, false), !0, 0, CA), (0, CA, 0));
+    assert_eq!(exec_xer(xo_form(ADDE, false, false), !0, 0, 0), (!0, 0, 0));
+    assert_eq!(
+        exec_xer(xo_form(ADDE, false, false), !0, !0, CA),
+        (!0, CA, 0)
+    );
+    assert_eq!(exec_xer(xo_form(ADDC, false, false), !0, 1, 0), (0, CA, 0));
+    assert_eq!(exec_xer(xo_form(ADDC, false, false), !0, 0, CA), (!0, 0, 0));
+
+    assert_eq!(
+        exec_xer(xo_form(ADDZE, false, false), !0, 0, CA),
+        (0, CA, 0)
+    );
+    assert_eq!(exec_xer(xo_form(ADDZE, false, false), !0, 0, 0), (!0, 0, 0));
+
+    // ra + 0xFFFF_FFFF + CA carries unless both are zero
+    assert_eq!(exec_xer(xo_form(ADDME, false, false), 0, 0, 0), (!0, 0, 0));
+    assert_eq!(exec_xer(xo_form(ADDME, false, false), 0, 0, CA), (0, CA, 0));
+    assert_eq!(exec_xer(xo_form(ADDME, false, false), 1, 0, 0), (0, CA, 0));
+
+    // overflow comes from the operand signs, including the incoming carry
+    assert_eq!(
+        exec_xer(xo_form(ADDZE, true, false), 0x7FFF_FFFF, 0, CA),
+        (0x8000_0000, SO | OV, 0)
+    );
+    assert_eq!(
+        exec_xer(xo_form(ADDME, true, false), 0x8000_0000, 0, 0),
+        (0x7FFF_FFFF, SO | OV | CA, 0)
+    );
+    assert_eq!(
+        exec_xer(xo_form(ADDE, true, false), 0x7FFF_FFFF, 0x8000_0000, CA),
+        (0, CA, 0)
+    );
+
+    // addeo.: LT and SO in CR0
+    assert_eq!(
+        exec_xer(xo_form(ADDE, true, true), 0x7FFF_FFFF, 0, CA),
+        (0x8000_0000, SO | OV, 0x9000_0000)
+    );
+
+    // SO is sticky, OV is not
+    assert_eq!(
+        exec_xer(xo_form(ADDE, true, true), 1, 1, SO | OV),
+        (2, SO, 0x5000_0000)
+    );
+}
+
+#[test]
+fn extended_sub() {
+    const SUBFC: u32 = 8;
+    const SUBFE: u32 = 136;
+    const SUBFZE: u32 = 200;
+    const SUBFME: u32 = 232;
+
+    // CA is NOT borrow
+    assert_eq!(exec_xer(xo_form(SUBFC, false, false), 0, 0, 0), (0, CA, 0));
+    assert_eq!(exec_xer(xo_form(SUBFC, false, false), 1, 0, CA), (!0, 0, 0));
+    assert_eq!(exec_xer(xo_form(SUBFC, false, false), 1, 1, 0), (0, CA, 0));
+
+    // rb + !ra + CA
+    assert_eq!(exec_xer(xo_form(SUBFE, false, false), 0, 0, 0), (!0, 0, 0));
+    assert_eq!(exec_xer(xo_form(SUBFE, false, false), 0, 0, CA), (0, CA, 0));
+    assert_eq!(
+        exec_xer(xo_form(SUBFE, false, false), 0, !0, 0),
+        (!0 - 1, CA, 0)
+    );
+
+    assert_eq!(
+        exec_xer(xo_form(SUBFZE, false, false), 0, 0, CA),
+        (0, CA, 0)
+    );
+    assert_eq!(exec_xer(xo_form(SUBFZE, false, false), 0, 0, 0), (!0, 0, 0));
+
+    assert_eq!(
+        exec_xer(xo_form(SUBFME, false, false), 0, 0, 0),
+        (!0 - 1, CA, 0)
+    );
+    assert_eq!(
+        exec_xer(xo_form(SUBFME, false, false), !0, 0, 0),
+        (!0, 0, 0)
+    );
+    assert_eq!(
+        exec_xer(xo_form(SUBFME, false, false), !0, 0, CA),
+        (0, CA, 0)
+    );
+
+    // overflow
+    assert_eq!(
+        exec_xer(xo_form(SUBFZE, true, false), 0x8000_0000, 0, CA),
+        (0x8000_0000, SO | OV, 0)
+    );
+    assert_eq!(
+        exec_xer(xo_form(SUBFE, true, false), 1, 0x8000_0000, CA),
+        (0x7FFF_FFFF, SO | OV | CA, 0)
+    );
+    assert_eq!(
+        exec_xer(xo_form(SUBFE, true, true), 0x8000_0000, 0, CA),
+        (0x8000_0000, SO | OV, 0x9000_0000)
+    );
+    assert_eq!(
+        exec_xer(xo_form(SUBFC, true, false), 1, 2, SO),
+        (1, SO | CA, 0)
+    );
+}
+
+#[test]
+fn subfic_carry() {
+    let subfic = |simm: i16| (8 << 26) | (3 << 21) | (4 << 16) | simm as u16 as u32;
+
+    assert_eq!(exec_xer(subfic(0), 0, 0, 0), (0, CA, 0));
+    assert_eq!(exec_xer(subfic(0), 1, 0, CA), (!0, 0, 0));
+    assert_eq!(exec_xer(subfic(-1), !0, 0, 0), (0, CA, 0));
+    assert_eq!(exec_xer(subfic(5), 3, 0, 0), (2, CA, 0));
+}
+
+#[test]
+fn mcrxr() {
+    // mcrxr cr2
+    let code = (31 << 26) | (2 << 23) | (512 << 1);
+
+    let (_, xer, cr) = exec_xer(code, 0, 0, SO | CA | 0x42);
+    assert_eq!(xer, 0x42);
+    assert_eq!(CondReg::from_bits(cr).field(2).to_bits().value(), 0b1010);
+    assert_eq!(cr & !CondReg::field_mask(2), 0);
+}
+
+#[test]
+fn unknown_spr() {
+    let mut jit = Jit::new(
+        Settings {
+            codegen: CodegenSettings::default(),
+            cache_path: None,
+        },
+        Hooks {
+            get_registers: rfi_get_registers,
+            get_fastmem: rfi_get_fastmem,
+            ..unsafe { Hooks::stub() }
+        },
+    );
+
+    let mut ctx = RfiContext {
+        cpu: Cpu::default(),
+        fastmem: Box::new([None; FASTMEM_LUT_COUNT]),
+        msr_changes: Vec::new(),
+    };
+
+    ctx.cpu.user.gpr[3] = 0x1234_5678;
+    ctx.cpu.user.gpr[4] = 0xDEAD_BEEF;
+    let before = ctx.cpu.clone();
+
+    // writes are dropped and reads return 0
+    let block = jit
+        .build(
+            ppc! {
+                mtspr u(0) gpr(3);
+                mtspr u(1023) gpr(3);
+                mfspr gpr(4) u(0);
+            }
+            .0
+            .into_iter(),
+        )
+        .unwrap();
+    let info = unsafe { jit.call((&raw mut ctx).cast(), block.as_ptr()) };
+    assert_eq!(info.instructions, 3);
+
+    assert_eq!(ctx.cpu.user.gpr[4], 0);
+    ctx.cpu.user.gpr[4] = before.user.gpr[4];
+    ctx.cpu.pc = before.pc;
+    assert_eq!(ctx.cpu, before);
+}
+
+#[test]
+fn flush_denormals() {
+    let mut jit = Jit::new(
+        Settings {
+            codegen: CodegenSettings {
+                force_fpu: true,
+                round_to_single: true,
+                flush_denormals: true,
+                ..Default::default()
+            },
+            cache_path: None,
+        },
+        Hooks {
+            get_registers: rfi_get_registers,
+            get_fastmem: rfi_get_fastmem,
+            ..unsafe { Hooks::stub() }
+        },
+    );
+
+    let mut ctx = RfiContext {
+        cpu: Cpu::default(),
+        fastmem: Box::new([None; FASTMEM_LUT_COUNT]),
+        msr_changes: Vec::new(),
+    };
+
+    let tiny = f32::MIN_POSITIVE as f64;
+    ctx.cpu.user.fpr[1].0 = [tiny, -tiny];
+    ctx.cpu.user.fpr[2].0 = [0.5, 0.5];
+    ctx.cpu.user.fpr[3].0 = [tiny * 4.0, 2.0];
+
+    let block = jit
+        .build(
+            ppc! {
+                ps_mul fpr(4) fpr(1) fpr(2);
+                ps_mul fpr(5) fpr(3) fpr(2);
+            }
+            .0
+            .into_iter(),
+        )
+        .unwrap();
+    unsafe { jit.call((&raw mut ctx).cast(), block.as_ptr()) };
+
+    // denormal results become zeros of the same sign
+    let [ps0, ps1] = ctx.cpu.user.fpr[4].0;
+    assert_eq!(ps0.to_bits(), 0.0f64.to_bits());
+    assert_eq!(ps1.to_bits(), (-0.0f64).to_bits());
+
+    // normal results are untouched
+    assert_eq!(ctx.cpu.user.fpr[5].0, [tiny * 2.0, 1.0]);
+}
+
+struct DmaContext {
+    cpu: Cpu,
+    fastmem: Box<FastmemLut>,
+    ram: Box<[u8]>,
+    /// First word of RAM as observed by each DMA kick.
+    transfers: Vec<u32>,
+}
+
+extern "C-unwind" fn dma_get_registers(ctx: *mut Context) -> *mut Cpu {
+    let ctx = unsafe { &mut *ctx.cast::<DmaContext>() };
+    &raw mut ctx.cpu
+}
+
+extern "C-unwind" fn dma_get_fastmem(ctx: *mut Context) -> *mut FastmemLut {
+    let ctx = unsafe { &mut *ctx.cast::<DmaContext>() };
+    &raw mut *ctx.fastmem
+}
+
+extern "C-unwind" fn dma_kick(ctx: *mut Context) {
+    let ctx = unsafe { &mut *ctx.cast::<DmaContext>() };
+    let word = u32::from_be_bytes(ctx.ram[..4].try_into().unwrap());
+    ctx.transfers.push(word);
+}
+
+#[test]
+fn dma_observes_prior_stores() {
+    const RAM_BASE: u32 = 0x8000_0000;
+    const PAGE_LEN: usize = 1 << 17;
+
+    let mut jit = Jit::new(
+        Settings {
+            codegen: CodegenSettings {
+                nop_syscalls: false,
+                force_fpu: false,
+                unimplemented: UnimplementedPolicy::Panic,
+                round_to_single: false,
+                flush_denormals: false,
+                cycles: CycleTable::default(),
+                performance_monitor: false,
+            },
+            cache_path: None,
+        },
+        Hooks {
+            get_registers: dma_get_registers,
+            get_fastmem: dma_get_fastmem,
+            dcache_dma: dma_kick,
+            ..unsafe { Hooks::stub() }
+        },
+    );
+
+    let mut ctx = DmaContext {
+        cpu: Cpu::default(),
+        fastmem: Box::new([None; FASTMEM_LUT_COUNT]),
+        ram: vec![0; PAGE_LEN].into_boxed_slice(),
+        transfers: Vec::new(),
+    };
+    ctx.fastmem[(RAM_BASE >> 17) as usize] = NonNull::new(ctx.ram.as_mut_ptr());
+
+    // stores and DMA kicks (mtspr DMAL) alternate, with and without a barrier in between
+    let block = jit
+        .build(
+            ppc! {
+                stw gpr(3) off(0) gpr(4);
+                mtspr u(923) gpr(6);
+                stw gpr(5) off(0) gpr(4);
+                eieio;
+                mtspr u(923) gpr(6);
+            }
+            .0
+            .into_iter(),
+        )
+        .unwrap();
+
+    for i in 0..1000u32 {
+        ctx.cpu.user.gpr[3] = 2 * i;
+        ctx.cpu.user.gpr[4] = RAM_BASE;
+        ctx.cpu.user.gpr[5] = 2 * i + 1;
+        ctx.cpu.user.gpr[6] = 0xE000_0002;
+        ctx.transfers.clear();
+
+        let info = unsafe { jit.call((&raw mut ctx).cast(), block.as_ptr()) };
+        assert_eq!(info.instructions, 5);
+        assert_eq!(ctx.transfers, [2 * i, 2 * i + 1]);
+    }
+}
+
+struct UnimplementedContext {
+    cpu: Cpu,
+    fastmem: Box<FastmemLut>,
+    /// Address and instruction of each call to the hook.
+    calls: Vec<(Address, u32)>,
+    raise: bool,
+}
+
+extern "C-unwind" fn unimplemented_get_registers(ctx: *mut Context) -> *mut Cpu {
+    let ctx = unsafe { &mut *ctx.cast::<UnimplementedContext>() };
+    &raw mut ctx.cpu
+}
+
+extern "C-unwind" fn unimplemented_get_fastmem(ctx: *mut Context) -> *mut FastmemLut {
+    let ctx = unsafe { &mut *ctx.cast::<UnimplementedContext>() };
+    &raw mut *ctx.fastmem
+}
+
+extern "C-unwind" fn unimplemented_instruction(ctx: *mut Context, addr: Address, ins: u32) -> bool {
+    let ctx = unsafe { &mut *ctx.cast::<UnimplementedContext>() };
+    ctx.calls.push((addr, ins));
+    ctx.raise
+}
+
+#[test]
+fn unimplemented_hook() {
+    const ILLEGAL: u32 = 0x0000_0000;
+
+    let mut jit = Jit::new(
+        Settings {
+            codegen: CodegenSettings {
+                nop_syscalls: false,
+                force_fpu: false,
+                unimplemented: UnimplementedPolicy::Hook,
+                round_to_single: false,
+                flush_denormals: false,
+                cycles: CycleTable::default(),
+                performance_monitor: false,
+            },
+            cache_path: None,
+        },
+        Hooks {
+            get_registers: unimplemented_get_registers,
+            get_fastmem: unimplemented_get_fastmem,
+            unimplemented_instruction,
+            ..unsafe { Hooks::stub() }
+        },
+    );
+
+    let mut sequence = ppc! {
+        addi gpr(3) gpr(0) i(1);
+    };
+    sequence.0.push(gekko::disasm::Ins::new(
+        ILLEGAL,
+        gekko::disasm::Extensions::gekko_broadway(),
+    ));
+    let block = jit.build(sequence.0.into_iter()).unwrap();
+
+    let mut ctx = UnimplementedContext {
+        cpu: Cpu::default(),
+        fastmem: Box::new([None; FASTMEM_LUT_COUNT]),
+        calls: Vec::new(),
+        raise: false,
+    };
+
+    // skipped: execution continues after it
+    ctx.cpu.pc = Address(0x8000_0100);
+    unsafe { jit.call((&raw mut ctx).cast(), block.as_ptr()) };
+    assert_eq!(ctx.calls, [(Address(0x8000_0104), ILLEGAL)]);
+    assert_eq!(ctx.cpu.user.gpr[3], 1);
+    assert_eq!(ctx.cpu.pc, Address(0x8000_0108));
+
+    // raised: a program exception for an illegal instruction
+    ctx.cpu = Cpu::default();
+    ctx.cpu.pc = Address(0x8000_0100);
+    ctx.calls.clear();
+    ctx.raise = true;
+    unsafe { jit.call((&raw mut ctx).cast(), block.as_ptr()) };
+    assert_eq!(ctx.calls, [(Address(0x8000_0104), ILLEGAL)]);
+    assert_eq!(ctx.cpu.user.gpr[3], 1);
+    assert_eq!(ctx.cpu.pc, Address(0xFFF0_0000 | Exception::Program as u32));
+    assert_eq!(ctx.cpu.supervisor.exception.srr[0], 0x8000_0104);
+    assert_ne!(ctx.cpu.supervisor.exception.srr[1] & (1 << 19), 0);
+}
+
+extern "C-unwind" fn hle(ctx: *mut Context, addr: Address, ins: u32) {
+    let ctx = unsafe { &mut *ctx.cast::<UnimplementedContext>() };
+    ctx.calls.push((addr, ins));
+    ctx.cpu.pc = Address(ctx.cpu.user.lr);
+}
+
+#[test]
+fn hle_hook() {
+    const TRAP: u32 = (gekko::HLE_OPCODE << 26) | 42;
+
+    // traps are not illegal instructions, regardless of the policy
+    let mut jit = Jit::new(
+        Settings {
+            codegen: CodegenSettings::default(),
+            cache_path: None,
+        },
+        Hooks {
+            get_registers: unimplemented_get_registers,
+            get_fastmem: unimplemented_get_fastmem,
+            hle,
+            ..unsafe { Hooks::stub() }
+        },
+    );
+
+    let mut sequence = ppc! {
+        addi gpr(3) gpr(0) i(1);
+    };
+    sequence.0.push(gekko::disasm::Ins::new(
+        TRAP,
+        gekko::disasm::Extensions::gekko_broadway(),
+    ));
+    let after = ppc! {
+        addi gpr(3) gpr(0) i(2);
+    };
+    sequence.0.extend(after.0);
+    let block = jit.build(sequence.0.into_iter()).unwrap();
+
+    let mut ctx = UnimplementedContext {
+        cpu: Cpu::default(),
+        fastmem: Box::new([None; FASTMEM_LUT_COUNT]),
+        calls: Vec::new(),
+        raise: false,
+    };
+
+    // the block ends at the trap and continues wherever the hook says
+    ctx.cpu.pc = Address(0x8000_0100);
+    ctx.cpu.user.lr = 0x8000_0200;
+    unsafe { jit.call((&raw mut ctx).cast(), block.as_ptr()) };
+    assert_eq!(ctx.calls, [(Address(0x8000_0104), TRAP)]);
+    assert_eq!(ctx.cpu.user.gpr[3], 1);
+    assert_eq!(ctx.cpu.pc, Address(0x8000_0200));
+}
+
+struct SmcContext {
+    cpu: Cpu,
+    fastmem: Box<FastmemLut>,
+    ram: Box<[u8]>,
+    /// Address and word at that address (as seen by the hook) of each icbi.
+    invalidated: Vec<(Address, u32)>,
+    isyncs: usize,
+}
+
+extern "C-unwind" fn smc_get_registers(ctx: *mut Context) -> *mut Cpu {
+    let ctx = unsafe { &mut *ctx.cast::<SmcContext>() };
+    &raw mut ctx.cpu
+}
+
+extern "C-unwind" fn smc_get_fastmem(ctx: *mut Context) -> *mut FastmemLut {
+    let ctx = unsafe { &mut *ctx.cast::<SmcContext>() };
+    &raw mut *ctx.fastmem
+}
+
+extern "C-unwind" fn smc_invalidate_icache(ctx: *mut Context, addr: Address) {
+    let ctx = unsafe { &mut *ctx.cast::<SmcContext>() };
+    let offset = (addr.value() - 0x8000_0000) as usize;
+    let word = u32::from_be_bytes(ctx.ram[offset..][..4].try_into().unwrap());
+    ctx.invalidated.push((addr, word));
+}
+
+extern "C-unwind" fn smc_clear_icache(ctx: *mut Context) {
+    let ctx = unsafe { &mut *ctx.cast::<SmcContext>() };
+    ctx.isyncs += 1;
+}
+
+#[test]
+fn self_modifying_code() {
+    const RAM_BASE: u32 = 0x8000_0000;
+    const PAGE_LEN: usize = 1 << 17;
+    const TARGET: u32 = RAM_BASE + 0x100;
+
+    let mut jit = Jit::new(
+        Settings {
+            codegen: CodegenSettings {
+                nop_syscalls: false,
+                force_fpu: false,
+                unimplemented: UnimplementedPolicy::Panic,
+                round_to_single: false,
+                flush_denormals: false,
+                cycles: CycleTable::default(),
+                performance_monitor: false,
+            },
+            cache_path: None,
+        },
+        Hooks {
+            get_registers: smc_get_registers,
+            get_fastmem: smc_get_fastmem,
+            invalidate_icache: smc_invalidate_icache,
+            clear_icache: smc_clear_icache,
+            ..unsafe { Hooks::stub() }
+        },
+    );
+
+    let mut ctx = SmcContext {
+        cpu: Cpu::default(),
+        fastmem: Box::new([None; FASTMEM_LUT_COUNT]),
+        ram: vec![0; PAGE_LEN].into_boxed_slice(),
+        invalidated: Vec::new(),
+        isyncs: 0,
+    };
+    ctx.fastmem[(RAM_BASE >> 17) as usize] = NonNull::new(ctx.ram.as_mut_ptr());
+
+    let write = |ram: &mut [u8], addr: u32, sequence: Sequence| {
+        let offset = (addr - RAM_BASE) as usize;
+        for (i, ins) in sequence.0.iter().enumerate() {
+            ram[offset + 4 * i..][..4].copy_from_slice(&ins.code.to_be_bytes());
+        }
+    };
+
+    // overwrites the instruction at TARGET with the one in r5
+    write(
+        &mut ctx.ram,
+        RAM_BASE,
+        ppc! {
+            stw gpr(5) off(0) gpr(4);
+            icbi gpr(0) gpr(4);
+            isync;
+        },
+    );
+    write(
+        &mut ctx.ram,
+        TARGET,
+        ppc! {
+            addi gpr(3) gpr(0) i(1);
+            isync;
+        },
+    );
+
+    // a minimal dispatcher, which compiles blocks straight from RAM and drops the ones in
+    // invalidated cache lines
+    let mut blocks: HashMap<Address, Block> = HashMap::new();
+    let mut run = |jit: &mut Jit, ctx: &mut SmcContext, pc: u32| {
+        for (addr, _) in ctx.invalidated.drain(..) {
+            let line = addr.align_down_pow2(32);
+            blocks.retain(|start, _| start.align_down_pow2(32) != line);
+        }
+
+        ctx.cpu.pc = Address(pc);
+        let block = blocks.entry(Address(pc)).or_insert_with(|| {
+            let ram = &ctx.ram[(pc - RAM_BASE) as usize..];
+            let instructions = ram.chunks_exact(4).map(|word| {
+                gekko::disasm::Ins::new(
+                    u32::from_be_bytes(word.try_into().unwrap()),
+                    gekko::disasm::Extensions::gekko_broadway(),
+                )
+            });
+
+            jit.build(instructions).unwrap()
+        });
+
+        unsafe { jit.call((&raw mut *ctx).cast(), block.as_ptr()) }
+    };
+
+    run(&mut jit, &mut ctx, TARGET);
+    assert_eq!(ctx.cpu.user.gpr[3], 1);
+
+    let new = ppc! { addi gpr(3) gpr(0) i(2) }.0[0].code;
+    ctx.cpu.user.gpr[4] = TARGET;
+    ctx.cpu.user.gpr[5] = new;
+
+    // icbi exits the block, after the store is visible
+    let info = run(&mut jit, &mut ctx, RAM_BASE);
+    assert_eq!(info.instructions, 2);
+    assert_eq!(ctx.cpu.pc, Address(RAM_BASE + 8));
+    assert_eq!(ctx.invalidated, [(Address(TARGET), new)]);
+
+    let isyncs = ctx.isyncs;
+    run(&mut jit, &mut ctx, RAM_BASE + 8);
+    assert_eq!(ctx.cpu.pc, Address(RAM_BASE + 12));
+    assert_eq!(ctx.isyncs, isyncs + 1);
+
+    // the new instruction is executed
+    run(&mut jit, &mut ctx, TARGET);
+    assert_eq!(ctx.cpu.user.gpr[3], 2);
+}
+
+struct PmcContext {
+    cpu: Cpu,
+    fastmem: Box<FastmemLut>,
+    /// Events reported to the hook, in order.
+    events: Vec<(PmcEvent, u32)>,
+}
+
+extern "C-unwind" fn pmc_get_registers(ctx: *mut Context) -> *mut Cpu {
+    let ctx = unsafe { &mut *ctx.cast::<PmcContext>() };
+    &raw mut ctx.cpu
+}
+
+extern "C-unwind" fn pmc_get_fastmem(ctx: *mut Context) -> *mut FastmemLut {
+    let ctx = unsafe { &mut *ctx.cast::<PmcContext>() };
+    &raw mut *ctx.fastmem
+}
+
+extern "C-unwind" fn pmc_increment(ctx: *mut Context, event: PmcEvent, count: u32) {
+    let ctx = unsafe { &mut *ctx.cast::<PmcContext>() };
+    ctx.events.push((event, count));
+}
+
+#[test]
+fn performance_monitor_events() {
+    const BLR: u32 = 0x4E80_0020;
+
+    let mut jit = Jit::new(
+        Settings {
+            codegen: CodegenSettings {
+                performance_monitor: true,
+                ..Default::default()
+            },
+            cache_path: None,
+        },
+        Hooks {
+            get_registers: pmc_get_registers,
+            get_fastmem: pmc_get_fastmem,
+            pmc_increment,
+            ..unsafe { Hooks::stub() }
+        },
+    );
+
+    let mut sequence = ppc! {
+        addi gpr(3) gpr(0) i(1);
+        addi gpr(4) gpr(0) i(2);
+    };
+    sequence.0.push(gekko::disasm::Ins::new(
+        BLR,
+        gekko::disasm::Extensions::gekko_broadway(),
+    ));
+    let block = jit.build(sequence.0.into_iter()).unwrap();
+
+    let mut ctx = PmcContext {
+        cpu: Cpu::default(),
+        fastmem: Box::new([None; FASTMEM_LUT_COUNT]),
+        events: Vec::new(),
+    };
+
+    // no event selected: the hook is never called
+    ctx.cpu.user.lr = 0x8000_0200;
+    unsafe { jit.call((&raw mut ctx).cast(), block.as_ptr()) };
+    assert_eq!(ctx.cpu.pc, Address(0x8000_0200));
+    assert!(ctx.events.is_empty());
+
+    // PMC1 counts completed instructions
+    ctx.cpu.supervisor.performance.control[0] = 2 << 6;
+    unsafe { jit.call((&raw mut ctx).cast(), block.as_ptr()) };
+    assert_eq!(
+        ctx.events,
+        [
+            (PmcEvent::BranchTaken, 1),
+            (PmcEvent::InstructionComplete, 3)
+        ]
+    );
+}
@@ -6,10 +6,13 @@ mod clear;
 mod render;
 
 use std::sync::Arc;
-use std::sync::atomic::Ordering;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
 
+use easyerr::{Error, ResultExt};
 use flume::{Receiver, Sender};
-use lazuli::modules::render::{Action, RenderModule};
+use lazuli::modules::render::{Action, RenderModule, TextureId};
+use lazuli::timing::History;
 
 use crate::blit::XfbBlitter;
 use crate::render::Renderer as RendererInner;
@@ -17,19 +20,141 @@ use crate::render::Renderer as RendererInner;
 #[expect(clippy::needless_pass_by_value, reason = "makes it clearer")]
 fn worker(mut renderer: RendererInner, receiver: Receiver<Action>) {
     while let Ok(action) = receiver.recv() {
+        // execute everything that is already queued as a single batch
+        renderer.begin_batch();
         renderer.exec(action);
+        for action in receiver.try_iter() {
+            renderer.exec(action);
+        }
+        renderer.end_batch();
     }
 }
 
+pub use crate::render::{
+    AspectRatio, CacheStats, CameraOverride, DEFAULT_GROUP_CACHE_CAPACITY, DEFAULT_TEXTURE_BUDGET,
+    DebugFlags, Deinterlace, RenderTimes, TextureFilter,
+};
+
 pub struct Stats {
     pub counters: wgpu::InternalCounters,
     pub alloc: Option<wgpu::AllocatorReport>,
+    /// Texture memory currently in use, in bytes.
+    pub texture_memory: u64,
+    /// Peak texture memory usage, in bytes.
+    pub texture_memory_peak: u64,
+    /// Texture memory budget, in bytes.
+    pub texture_budget: u64,
+    /// Capacity of the texture bind group cache, in entries.
+    pub group_cache_capacity: u32,
+    /// Counters of the texture caches, as of the end of the last frame.
+    pub cache: CacheStats,
+    /// Times of the last frames.
+    pub frame_times: History<RenderTimes>,
+    /// Average time the GPU spent rendering a frame, over the last [`GPU_TIME_WINDOW`] measured
+    /// frames. `None` if timestamp queries are not supported or no frame has been measured yet.
+    pub gpu_frame_time: Option<Duration>,
+}
+
+/// Number of measured frames the average GPU frame time is taken over.
+pub const GPU_TIME_WINDOW: usize = 60;
+
+/// Returns the average GPU time of the last [`GPU_TIME_WINDOW`] measured frames.
+fn average_gpu_time(frames: &History<RenderTimes>) -> Option<Duration> {
+    let (count, total) = frames
+        .iter()
+        .rev()
+        .filter_map(|times| times.gpu)
+        .take(GPU_TIME_WINDOW)
+        .fold((0u32, Duration::ZERO), |(count, total), gpu| {
+            (count + 1, total + gpu)
+        });
+
+    (count > 0).then(|| total / count)
+}
+
+/// Builds the descriptor of the device used by the renderer, requesting every feature and limit
+/// it needs from the given adapter.
+///
+/// If `mappable_vram` is set, primary buffers are requested to be mappable even on dedicated
+/// GPUs.
+pub fn device_descriptor(
+    adapter: &wgpu::Adapter,
+    mappable_vram: bool,
+) -> wgpu::DeviceDescriptor<'static> {
+    let info = adapter.get_info();
+
+    let mut required_features = wgpu::Features::empty();
+    required_features |= wgpu::Features::DUAL_SOURCE_BLENDING;
+    required_features |= wgpu::Features::FLOAT32_FILTERABLE;
+    required_features |= wgpu::Features::PUSH_CONSTANTS;
+    required_features |= wgpu::Features::CLEAR_TEXTURE;
+
+    // optional, only used to measure GPU times
+    if adapter.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+        required_features |= wgpu::Features::TIMESTAMP_QUERY;
+    }
+
+    // optional, only used by the wireframe debug flag
+    if adapter
+        .features()
+        .contains(wgpu::Features::POLYGON_MODE_LINE)
+    {
+        required_features |= wgpu::Features::POLYGON_MODE_LINE;
+    }
+
+    if mappable_vram
+        || matches!(
+            info.device_type,
+            wgpu::DeviceType::IntegratedGpu | wgpu::DeviceType::Cpu
+        )
+    {
+        required_features |= wgpu::Features::MAPPABLE_PRIMARY_BUFFERS;
+    }
+
+    let mut required_limits = wgpu::Limits::defaults();
+    required_limits.max_texture_dimension_2d = 8192;
+    required_limits.max_push_constant_size = 64 + 32;
+
+    wgpu::DeviceDescriptor {
+        label: Some("lazuli wgpu device"),
+        required_features,
+        required_limits,
+        ..Default::default()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum HeadlessError {
+    #[error("no suitable adapter found")]
+    Adapter { source: wgpu::RequestAdapterError },
+    #[error(transparent)]
+    Device { source: wgpu::RequestDeviceError },
+}
+
+/// An RGBA8 image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    /// Pixels in row-major order, 4 bytes each.
+    pub data: Vec<u8>,
+}
+
+impl Image {
+    /// Returns the pixel at the given coordinates.
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let index = 4 * (y * self.width + x) as usize;
+        self.data[index..][..4].try_into().unwrap()
+    }
 }
 
 struct Inner {
     device: wgpu::Device,
+    queue: wgpu::Queue,
     shared: Arc<render::Shared>,
     blitter: XfbBlitter,
+    /// How many actions have been sent to the rendering thread.
+    sent: AtomicU64,
 }
 
 /// A WGPU based renderer implementation.
@@ -44,7 +169,7 @@ pub struct Renderer {
 impl Renderer {
     pub fn new(device: wgpu::Device, queue: wgpu::Queue, format: wgpu::TextureFormat) -> Self {
         let blitter = XfbBlitter::new(&device, format);
-        let (renderer, shared) = RendererInner::new(device.clone(), queue);
+        let (renderer, shared) = RendererInner::new(device.clone(), queue.clone());
 
         const CAPACITY: usize = 1024 * 1024 / size_of::<Action>();
         let (sender, receiver) = flume::bounded(CAPACITY);
@@ -57,13 +182,132 @@ impl Renderer {
         Self {
             inner: Arc::new(Inner {
                 device,
+                queue,
                 shared,
                 blitter,
+                sent: AtomicU64::new(0),
             }),
             sender,
         }
     }
 
+    /// Requests the device used by headless renderers, which isn't tied to any surface. Parts of
+    /// the renderer can also be tested on it directly.
+    ///
+    /// If no GPU is available, falls back to a software adapter (e.g. lavapipe).
+    pub fn headless_device() -> Result<(wgpu::Device, wgpu::Queue), HeadlessError> {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::all(),
+            ..Default::default()
+        });
+
+        let request_adapter = |force_fallback_adapter| {
+            pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                force_fallback_adapter,
+                compatible_surface: None,
+            }))
+        };
+
+        let adapter = request_adapter(false)
+            .or_else(|_| request_adapter(true))
+            .context(HeadlessCtx::Adapter)?;
+
+        let descriptor = self::device_descriptor(&adapter, false);
+        pollster::block_on(adapter.request_device(&descriptor)).context(HeadlessCtx::Device)
+    }
+
+    /// Creates a renderer with its own device, which isn't tied to any surface. This is meant for
+    /// tests and embedding, where the output is read back with [`Renderer::read_output`].
+    ///
+    /// If no GPU is available, falls back to a software adapter (e.g. lavapipe).
+    pub fn new_headless() -> Result<Self, HeadlessError> {
+        let (device, queue) = Self::headless_device()?;
+        Ok(Self::new(
+            device,
+            queue,
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+        ))
+    }
+
+    /// Blocks until every action sent so far has been executed by the rendering thread.
+    pub fn wait_idle(&self) {
+        let sent = self.inner.sent.load(Ordering::Acquire);
+        while self.inner.shared.executed.load(Ordering::Acquire) < sent {
+            std::thread::yield_now();
+        }
+    }
+
+    /// Reads back the output, i.e. the last presented XFB, once every action sent so far has been
+    /// executed.
+    pub fn read_output(&self) -> Image {
+        self.wait_idle();
+
+        let output = self.inner.shared.output.lock().unwrap().clone();
+        let texture = output.texture();
+        let size = texture.size();
+        let row_size = size.width * 4;
+        let row_stride = row_size.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+
+        let buffer = self.inner.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("output read buffer"),
+            size: (row_stride * size.height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .inner
+            .device
+            .create_command_encoder(&Default::default());
+
+        encoder.copy_texture_to_buffer(
+            wgpu::TexelCopyTextureInfo {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::default(),
+            },
+            wgpu::TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(row_stride),
+                    rows_per_image: None,
+                },
+            },
+            size,
+        );
+
+        let (sender, receiver) = flume::bounded(1);
+        encoder.map_buffer_on_submit(&buffer, wgpu::MapMode::Read, .., move |r| {
+            sender.send(r).unwrap()
+        });
+
+        let submission = self.inner.queue.submit([encoder.finish()]);
+        self.inner
+            .device
+            .poll(wgpu::wgt::PollType::Wait {
+                submission_index: Some(submission),
+                timeout: None,
+            })
+            .unwrap();
+
+        receiver.recv().unwrap().unwrap();
+
+        let mapped = buffer.get_mapped_range(..);
+        let mut data = Vec::with_capacity((row_size * size.height) as usize);
+        for row in mapped.chunks_exact(row_stride as usize) {
+            data.extend_from_slice(&row[..row_size as usize]);
+        }
+
+        Image {
+            width: size.width,
+            height: size.height,
+            data,
+        }
+    }
+
     pub fn render(&self, pass: &mut wgpu::RenderPass<'_>) {
         let output = self.inner.shared.output.lock().unwrap();
         let size = output.texture().size();
@@ -88,12 +332,346 @@ impl Renderer {
     pub fn stats(&self) -> Box<Stats> {
         let counters = self.inner.device.get_internal_counters();
         let alloc = self.inner.device.generate_allocator_report();
-        Box::new(Stats { counters, alloc })
+        let shared = &self.inner.shared;
+        let frame_times = shared.frame_times.lock().unwrap().clone();
+
+        Box::new(Stats {
+            counters,
+            alloc,
+            texture_memory: shared.texture_memory.load(Ordering::Relaxed),
+            texture_memory_peak: shared.texture_memory_peak.load(Ordering::Relaxed),
+            texture_budget: shared.texture_budget.load(Ordering::Relaxed),
+            group_cache_capacity: shared.group_cache_capacity.load(Ordering::Relaxed),
+            cache: *shared.cache_stats.lock().unwrap(),
+            gpu_frame_time: average_gpu_time(&frame_times),
+            frame_times,
+        })
+    }
+
+    /// Sets the texture memory budget, in bytes. Once over it, textures which haven't been used
+    /// recently are evicted.
+    pub fn set_texture_budget(&self, budget: u64) {
+        self.inner
+            .shared
+            .texture_budget
+            .store(budget, Ordering::Relaxed);
+    }
+
+    /// Sets the capacity of the texture bind group cache, in entries. Takes effect on the next
+    /// render pass.
+    pub fn set_group_cache_capacity(&self, capacity: u32) {
+        self.inner
+            .shared
+            .group_cache_capacity
+            .store(capacity.max(1), Ordering::Relaxed);
+    }
+
+    /// Sets how the fields of interlaced video are combined into a frame.
+    pub fn set_deinterlace(&self, mode: Deinterlace) {
+        self.inner
+            .shared
+            .deinterlace
+            .store(mode as u8, Ordering::Relaxed);
+    }
+
+    /// Sets the texture filtering override. Takes effect on the next frame.
+    pub fn set_texture_filter(&self, filter: TextureFilter) {
+        self.inner
+            .shared
+            .texture_filter
+            .store(filter as u8, Ordering::Relaxed);
+    }
+
+    /// Sets the aspect ratio mode. Takes effect on the next frame.
+    pub fn set_aspect_ratio(&self, aspect_ratio: AspectRatio) {
+        self.inner
+            .shared
+            .aspect_ratio
+            .store(aspect_ratio as u8, Ordering::Relaxed);
+    }
+
+    /// Returns the aspect ratio mode.
+    pub fn aspect_ratio(&self) -> AspectRatio {
+        match self.inner.shared.aspect_ratio.load(Ordering::Relaxed) {
+            x if x == AspectRatio::Stretch as u8 => AspectRatio::Stretch,
+            x if x == AspectRatio::Hack as u8 => AspectRatio::Hack,
+            _ => AspectRatio::Native,
+        }
+    }
+
+    /// Sets the camera override, or removes it if `None`. Takes effect on the next frame.
+    pub fn set_camera_override(&self, camera: Option<CameraOverride>) {
+        *self.inner.shared.camera.lock().unwrap() = camera;
+    }
+
+    /// Whether the device supports [`DebugFlags::wireframe`], i.e. whether it has the
+    /// [`wgpu::Features::POLYGON_MODE_LINE`] feature.
+    pub fn supports_wireframe(&self) -> bool {
+        self.inner
+            .device
+            .features()
+            .contains(wgpu::Features::POLYGON_MODE_LINE)
+    }
+
+    /// Sets the debug flags. Takes effect on the next frame.
+    ///
+    /// If wireframe rendering isn't supported by the device, it is ignored.
+    pub fn set_debug_flags(&self, mut flags: DebugFlags) {
+        if flags.wireframe && !self.supports_wireframe() {
+            tracing::warn!("wireframe rendering is not supported by the device");
+            flags.wireframe = false;
+        }
+
+        *self.inner.shared.debug_flags.lock().unwrap() = flags;
     }
 }
 
 impl RenderModule for Renderer {
     fn exec(&mut self, action: Action) {
+        self.inner.sent.fetch_add(1, Ordering::Release);
         self.sender.send(action).expect("rendering thread is alive");
     }
+
+    fn take_evicted_textures(&mut self) -> Vec<TextureId> {
+        std::mem::take(&mut *self.inner.shared.evicted_textures.lock().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::hash::Hasher;
+    use std::time::Duration;
+
+    use bitos::integer::{u10, u11};
+    use glam::Mat4;
+    use lazuli::modules::render::{Action, CopyArgs, EfbBuffer, RenderModule, XfbPart, oneshot};
+    use lazuli::system::gx::color::Rgba;
+    use lazuli::system::gx::pix::{
+        CompareMode, CopyDims, CopySrc, DepthMode, Scissor, ScissorCorner, ScissorOffset,
+    };
+    use lazuli::system::gx::xform::ProjectionMtx;
+    use lazuli::system::gx::{MatrixId, Topology, Vertex, VertexStream};
+    use lazuli::system::vi::Dimensions;
+    use lazuli::timing::History;
+    use rustc_hash::FxHasher;
+
+    use super::{GPU_TIME_WINDOW, Image, RenderTimes, Renderer, average_gpu_time};
+
+    /// Scissor covering the whole EFB.
+    pub(crate) fn scissor() -> Scissor {
+        let corner = |x, y| {
+            ScissorCorner::default()
+                .with_x_plus_342(u11::new(342 + x))
+                .with_y_plus_342(u11::new(342 + y))
+        };
+
+        Scissor {
+            top_left: corner(0, 0),
+            bottom_right: corner(639, 527),
+            offset: ScissorOffset::default()
+                .with_x_plus_342_div_2(u10::new(171))
+                .with_y_plus_342_div_2(u10::new(171)),
+        }
+    }
+
+    /// Copy of the whole EFB.
+    fn copy(clear: bool) -> CopyArgs {
+        CopyArgs {
+            src: CopySrc::default(),
+            dims: CopyDims::default()
+                .with_width_minus_one(u10::new(639))
+                .with_height_minus_one(u10::new(527)),
+            half: false,
+            clear,
+        }
+    }
+
+    /// A triangle in the middle of the screen.
+    pub(crate) fn triangle() -> VertexStream {
+        let vertex = |x, y| Vertex {
+            position: glam::Vec3::new(x, y, 0.0),
+            chan0: Rgba::new(0.0, 1.0, 0.0, 1.0),
+            chan1: Rgba::new(0.0, 1.0, 0.0, 1.0),
+            ..Default::default()
+        };
+
+        let matrix = MatrixId::default();
+        VertexStream::new(
+            [vertex(-0.5, -0.5), vertex(0.5, -0.5), vertex(0.0, 0.5)],
+            &[(matrix, Mat4::IDENTITY), (matrix.normal(), Mat4::IDENTITY)],
+        )
+    }
+
+    /// Renders a frame with a triangle over a red background, or `None` if there's no adapter to
+    /// render with.
+    fn render() -> Option<Image> {
+        let mut renderer = match Renderer::new_headless() {
+            Ok(renderer) => renderer,
+            Err(e) => {
+                eprintln!("skipping headless rendering: {e}");
+                return None;
+            }
+        };
+
+        let actions = [
+            Action::SetXfbDimensions(Dimensions {
+                width: 640,
+                height: 528,
+            }),
+            Action::SetScissor(scissor()),
+            Action::SetClearColor(Rgba::new(1.0, 0.0, 0.0, 1.0)),
+            Action::CopyXfb {
+                args: copy(true),
+                id: 0,
+            },
+            Action::SetProjectionMatrix(ProjectionMtx {
+                params: [1.0, 0.0, 1.0, 0.0, 0.0, -0.5],
+                orthographic: true,
+            }),
+            Action::Draw(Topology::TriangleList, triangle()),
+            Action::CopyXfb {
+                args: copy(false),
+                id: 1,
+            },
+            Action::PresentXfb(vec![XfbPart {
+                id: 1,
+                offset_x: 0,
+                offset_y: 0,
+                line_step: 1,
+            }]),
+        ];
+
+        for action in actions {
+            renderer.exec(action);
+        }
+
+        Some(renderer.read_output())
+    }
+
+    fn hash(image: &Image) -> u64 {
+        let mut hasher = FxHasher::default();
+        hasher.write(&image.data);
+        hasher.finish()
+    }
+
+    #[test]
+    fn headless_triangle() {
+        let Some(image) = render() else {
+            return;
+        };
+
+        assert_eq!((image.width, image.height), (640, 528));
+
+        // the triangle covers the center but not the corners
+        let corner = image.pixel(0, 0);
+        let center = image.pixel(320, 264);
+        assert_ne!(corner, center);
+        assert_eq!(corner, image.pixel(639, 527));
+
+        // rendering is deterministic, so frames can be compared by their hashes
+        let again = render().unwrap();
+        assert_eq!(hash(&image), hash(&again));
+    }
+
+    #[test]
+    fn efb_peek_and_poke() {
+        let mut renderer = match Renderer::new_headless() {
+            Ok(renderer) => renderer,
+            Err(e) => {
+                eprintln!("skipping headless rendering: {e}");
+                return;
+            }
+        };
+
+        let actions = [
+            Action::SetScissor(scissor()),
+            Action::SetClearColor(Rgba::new(1.0, 0.0, 0.0, 1.0)),
+            Action::SetDepthMode(
+                DepthMode::default()
+                    .with_enable(true)
+                    .with_compare(CompareMode::Always)
+                    .with_update(true),
+            ),
+            Action::CopyXfb {
+                args: copy(true),
+                id: 0,
+            },
+            Action::SetProjectionMatrix(ProjectionMtx {
+                params: [1.0, 0.0, 1.0, 0.0, 0.0, -0.5],
+                orthographic: true,
+            }),
+            Action::Draw(Topology::TriangleList, triangle()),
+        ];
+
+        for action in actions {
+            renderer.exec(action);
+        }
+
+        let mut read = |buffer| {
+            let (sender, receiver) = oneshot::channel();
+            renderer.exec(Action::ReadEfb {
+                buffer,
+                response: sender,
+            });
+
+            receiver.recv().unwrap()
+        };
+
+        let inside = 264 * 640 + 320;
+        let outside = 0;
+        let rgb = |texel: u32| <[u8; 3]>::try_from(&texel.to_le_bytes()[..3]).unwrap();
+
+        let color = read(EfbBuffer::Color);
+        assert_eq!(color.len(), 640 * 528);
+        assert_eq!(rgb(color[inside]), [0, 255, 0]);
+        assert_eq!(rgb(color[outside]), [255, 0, 0]);
+
+        let depth = read(EfbBuffer::Depth);
+        assert_eq!(f32::from_bits(depth[outside]), 1.0);
+        assert!(f32::from_bits(depth[inside]) < 1.0);
+
+        renderer.exec(Action::PokeColor {
+            x: 0,
+            y: 0,
+            color: Rgba::new(0.0, 0.0, 1.0, 1.0),
+        });
+        renderer.exec(Action::PokeDepth {
+            x: 0,
+            y: 0,
+            depth: 0.25,
+        });
+
+        // only the poked pixel changes
+        let color = read(EfbBuffer::Color);
+        assert_eq!(rgb(color[outside]), [0, 0, 255]);
+        assert_eq!(rgb(color[outside + 1]), [255, 0, 0]);
+        assert_eq!(rgb(color[inside]), [0, 255, 0]);
+
+        let depth = read(EfbBuffer::Depth);
+        assert_eq!(f32::from_bits(depth[outside]), 0.25);
+        assert_eq!(f32::from_bits(depth[outside + 1]), 1.0);
+    }
+
+    #[test]
+    fn gpu_time_average() {
+        let mut frames = History::default();
+        assert_eq!(average_gpu_time(&frames), None);
+
+        let frame = |gpu: Option<u64>| RenderTimes {
+            gpu: gpu.map(Duration::from_millis),
+            ..Default::default()
+        };
+
+        // unmeasured frames are skipped
+        frames.push(frame(Some(4)));
+        frames.push(frame(None));
+        frames.push(frame(Some(2)));
+        assert_eq!(average_gpu_time(&frames), Some(Duration::from_millis(3)));
+
+        // only the most recent frames are taken into account
+        for _ in 0..GPU_TIME_WINDOW {
+            frames.push(frame(Some(10)));
+        }
+        assert_eq!(average_gpu_time(&frames), Some(Duration::from_millis(10)));
+    }
 }
@@ -1,18 +1,24 @@
 mod data;
+mod expand;
 mod framebuffer;
 mod pipeline;
 mod texture;
+mod timing;
 
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicU32, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
-use glam::{Mat4, Vec2};
-use lazuli::modules::render::{Action, Sampler, Scaling, Viewport};
+use glam::{Mat4, Vec2, Vec4};
+use lazuli::modules::render::{Action, Sampler, Scaling, TextureId, Viewport};
 use lazuli::system::gx::color::Rgba;
 use lazuli::system::gx::pix::{ConstantAlpha, Scissor};
 use lazuli::system::gx::tev::Fog;
-use lazuli::system::gx::xform::{Channel, Light};
-use lazuli::system::gx::{EFB_HEIGHT, EFB_WIDTH, MatrixId, Topology, Vertex, VertexStream};
+use lazuli::system::gx::xform::{Channel, Light, ProjectionMtx};
+use lazuli::system::gx::{
+    CullingMode, EFB_HEIGHT, EFB_WIDTH, LinePointSize, MatrixId, Topology, Vertex, VertexStream,
+};
+use lazuli::timing::History;
 use rustc_hash::FxBuildHasher;
 use schnellru::{ByLength, LruMap};
 use seq_macro::seq;
@@ -21,11 +27,85 @@ use zerocopy::IntoBytes;
 use crate::alloc::Allocator;
 use crate::blit::{ColorBlitter, Converter, DepthBlitter};
 use crate::clear::Cleaner;
+pub use crate::render::framebuffer::Deinterlace;
+pub use crate::render::pipeline::DebugFlags;
 use crate::render::texture::TextureRef;
+pub use crate::render::texture::{CacheStats, DEFAULT_TEXTURE_BUDGET, TextureFilter};
+pub use crate::render::timing::RenderTimes;
+use crate::render::timing::{FrameTimer, GpuTimer};
+
+/// Default capacity of the texture bind group cache, in entries.
+pub const DEFAULT_GROUP_CACHE_CAPACITY: u32 = 512;
+
+/// A user controlled camera which overrides the one of the game, for inspecting scenes.
+///
+/// Games don't provide a separate view matrix (it's baked into the position matrices), so the
+/// override is applied in view space: geometry is moved by `view` and then projected. Orthographic
+/// projections, which are usually used for 2D elements, are left untouched.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraOverride {
+    /// Transform applied to positions in view space, before projecting them.
+    pub view: Mat4,
+    /// Vertical field of view, in radians, replacing the one of perspective projections. If
+    /// `None`, the field of view of the game is kept.
+    pub fov_y: Option<f32>,
+}
+
+/// Aspect ratio of the output.
+///
+/// The hack widens perspective projections so that games render a 16:9 view instead of a
+/// stretched 4:3 one. 2D elements which are drawn with a perspective projection, like some HUDs,
+/// will be distorted by it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AspectRatio {
+    /// Present the output as 4:3, as the console does.
+    #[default]
+    Native,
+    /// Present the output as 16:9, for games which render in anamorphic widescreen.
+    Stretch,
+    /// Present the output as 16:9 and widen the projections of the game to match.
+    Hack,
+}
+
+impl AspectRatio {
+    /// Width divided by the height of the presented output.
+    pub fn value(self) -> f32 {
+        match self {
+            Self::Native => 4.0 / 3.0,
+            Self::Stretch | Self::Hack => 16.0 / 9.0,
+        }
+    }
+}
 
 pub struct Shared {
     pub output: Mutex<wgpu::TextureView>,
     pub rendered_anything: AtomicBool,
+    /// Texture memory budget, in bytes.
+    pub texture_budget: AtomicU64,
+    /// Texture memory currently in use, in bytes.
+    pub texture_memory: AtomicU64,
+    /// Peak texture memory usage, in bytes.
+    pub texture_memory_peak: AtomicU64,
+    /// Capacity of the texture bind group cache, in entries.
+    pub group_cache_capacity: AtomicU32,
+    /// Counters of the texture caches, as of the end of the last frame.
+    pub cache_stats: Mutex<CacheStats>,
+    /// Textures evicted since the emulator last took them.
+    pub evicted_textures: Mutex<Vec<TextureId>>,
+    /// Deinterlacing mode, as the discriminant of a [`Deinterlace`].
+    pub deinterlace: AtomicU8,
+    /// Texture filtering override, as the discriminant of a [`TextureFilter`].
+    pub texture_filter: AtomicU8,
+    /// Aspect ratio mode, as the discriminant of an [`AspectRatio`].
+    pub aspect_ratio: AtomicU8,
+    /// Camera override to use starting from the next frame.
+    pub camera: Mutex<Option<CameraOverride>>,
+    /// Debug flags to use starting from the next frame.
+    pub debug_flags: Mutex<DebugFlags>,
+    /// How many actions have been executed.
+    pub executed: AtomicU64,
+    /// Times of the last frames.
+    pub frame_times: Mutex<History<RenderTimes>>,
 }
 
 struct Allocators {
@@ -75,19 +155,28 @@ pub struct Renderer {
     color_blitter: ColorBlitter,
     depth_blitter: DepthBlitter,
     data_read_buffer: wgpu::Buffer,
+    gpu_timer: Option<GpuTimer>,
+    frame_timer: FrameTimer,
 
     // caches
     pipeline_cache: pipeline::Cache,
     texture_cache: texture::Cache,
     textures_group_cache: GroupCache<TexturesGroupEntries>,
+    group_cache_capacity: u32,
+    group_hits: u64,
+    group_misses: u64,
 
     // state
     viewport: Viewport,
     scissor: Scissor,
+    line_point_size: LinePointSize,
     clear_color: Rgba,
     clear_depth: f32,
     current_config: data::Config,
     current_config_dirty: bool,
+    projection: ProjectionMtx,
+    widescreen_hack: bool,
+    camera: Option<CameraOverride>,
 
     indices: Vec<u32>,
     vertices: Vec<data::Vertex>,
@@ -117,6 +206,19 @@ impl Renderer {
         let shared = Arc::new(Shared {
             output: Mutex::new(external_fb.framebuffer().clone()),
             rendered_anything: AtomicBool::new(false),
+            texture_budget: AtomicU64::new(texture::DEFAULT_TEXTURE_BUDGET),
+            texture_memory: AtomicU64::new(0),
+            texture_memory_peak: AtomicU64::new(0),
+            group_cache_capacity: AtomicU32::new(DEFAULT_GROUP_CACHE_CAPACITY),
+            cache_stats: Mutex::new(CacheStats::default()),
+            evicted_textures: Mutex::new(Vec::new()),
+            deinterlace: AtomicU8::new(Deinterlace::default() as u8),
+            texture_filter: AtomicU8::new(TextureFilter::default() as u8),
+            aspect_ratio: AtomicU8::new(AspectRatio::default() as u8),
+            camera: Mutex::new(None),
+            debug_flags: Mutex::new(DebugFlags::default()),
+            executed: AtomicU64::new(0),
+            frame_times: Mutex::new(History::default()),
         });
 
         let cleaner = Cleaner::new(&device);
@@ -131,6 +233,11 @@ impl Renderer {
             mapped_at_creation: false,
         });
 
+        let mut gpu_timer = GpuTimer::new(&device, &queue);
+        if gpu_timer.is_none() {
+            tracing::info!("timestamp queries not supported, GPU times won't be measured");
+        }
+
         let transfer_encoder = device.create_command_encoder(&Default::default());
         let mut render_encoder = device.create_command_encoder(&Default::default());
         let pass = render_encoder
@@ -153,7 +260,7 @@ impl Renderer {
                     }),
                     stencil_ops: None,
                 }),
-                timestamp_writes: None,
+                timestamp_writes: gpu_timer.as_mut().and_then(GpuTimer::pass_writes),
                 occlusion_query_set: None,
             })
             .forget_lifetime();
@@ -177,17 +284,29 @@ impl Renderer {
             color_blitter,
             depth_blitter,
             data_read_buffer,
+            gpu_timer,
+            frame_timer: FrameTimer::default(),
 
             pipeline_cache,
             texture_cache,
-            textures_group_cache: LruMap::with_hasher(ByLength::new(512), FxBuildHasher),
+            textures_group_cache: LruMap::with_hasher(
+                ByLength::new(DEFAULT_GROUP_CACHE_CAPACITY),
+                FxBuildHasher,
+            ),
+            group_cache_capacity: DEFAULT_GROUP_CACHE_CAPACITY,
+            group_hits: 0,
+            group_misses: 0,
 
             viewport: Default::default(),
             scissor: Default::default(),
+            line_point_size: Default::default(),
             clear_color: Default::default(),
             clear_depth: 1.0,
             current_config: Default::default(),
             current_config_dirty: true,
+            projection: Default::default(),
+            widescreen_hack: false,
+            camera: None,
 
             vertices: Vec::new(),
             indices: Vec::new(),
@@ -208,13 +327,14 @@ impl Renderer {
             Action::SetViewport(viewport) => self.set_viewport(viewport),
             Action::SetScissor(scissor) => self.set_scissor(scissor),
             Action::SetCullingMode(mode) => self.set_culling_mode(mode),
+            Action::SetLinePointSize(size) => self.line_point_size = size,
             Action::SetClearColor(color) => self.set_clear_color(color),
             Action::SetClearDepth(depth) => self.set_clear_depth(depth),
             Action::SetBlendMode(mode) => self.set_blend_mode(mode),
             Action::SetDepthMode(mode) => self.set_depth_mode(mode),
             Action::SetAlphaTest(test) => self.set_alpha_test(test),
             Action::SetConstantAlpha(mode) => self.set_constant_alpha_mode(mode),
-            Action::SetProjectionMatrix(mtx) => self.set_projection_mtx(mtx.value()),
+            Action::SetProjectionMatrix(mtx) => self.set_projection_mtx(mtx),
             Action::SetTexEnvConfig(config) => self.set_texenv_config(config),
             Action::SetTexGenConfig(config) => self.set_texgen_config(config),
             Action::SetTexEnvRegisters(regs) => self.set_texenv_registers(regs),
@@ -227,14 +347,15 @@ impl Renderer {
                 sampler,
                 scaling,
             } => self.set_texture_slot(slot, texture_id, clut_ref, sampler, scaling),
+            Action::ReleaseTextures(ids) => self.release_textures(ids),
             Action::Draw(topology, vertices) => match topology {
                 Topology::QuadList => self.draw_quad_list(&vertices),
                 Topology::TriangleList => self.draw_triangle_list(&vertices),
                 Topology::TriangleStrip => self.draw_triangle_strip(&vertices),
                 Topology::TriangleFan => self.draw_triangle_fan(&vertices),
-                Topology::LineList => tracing::warn!("ignored line list primitive"),
-                Topology::LineStrip => tracing::warn!("ignored line strip primitive"),
-                Topology::PointList => tracing::warn!("ignored point list primitive"),
+                Topology::LineList => self.draw_line_list(&vertices),
+                Topology::LineStrip => self.draw_line_strip(&vertices),
+                Topology::PointList => self.draw_point_list(&vertices),
             },
             Action::SetAmbient(idx, color) => self.set_ambient(idx, color.into()),
             Action::SetMaterial(idx, color) => self.set_material(idx, color.into()),
@@ -256,9 +377,13 @@ impl Renderer {
             } => self.copy_depth(args, format, response, id),
             Action::CopyXfb { args, id } => self.copy_xfb(args, id),
             Action::PresentXfb(parts) => self.present_xfb(parts),
+            Action::ReadEfb { buffer, response } => self.read_efb(buffer, response),
+            Action::PokeColor { x, y, color } => self.poke_efb(x, y, Some(color), None),
+            Action::PokeDepth { x, y, depth } => self.poke_efb(x, y, None, Some(depth)),
         }
 
         self.actions += 1;
+        self.shared.executed.store(self.actions, Ordering::Release);
     }
 
     fn debug(&mut self, s: impl AsRef<str>) {
@@ -299,6 +424,28 @@ impl Renderer {
         idx as u32
     }
 
+    /// Inserts a vertex of an expanded line or point, placed at the given clip space position.
+    ///
+    /// The position is brought back to view space through `unproject` and uses the `identity`
+    /// position matrix, so that lighting and fog still see a position in view space.
+    fn insert_expanded_vertex(
+        &mut self,
+        vertex: &Vertex,
+        matrices: &[(MatrixId, u32)],
+        clip: Vec4,
+        unproject: Mat4,
+        identity: u32,
+    ) -> u32 {
+        let idx = self.insert_vertex(vertex, matrices);
+        let view = unproject * clip;
+
+        let data = &mut self.vertices[idx as usize];
+        data.position = view.truncate() / view.w;
+        data.position_mtx_idx = identity;
+
+        idx
+    }
+
     fn insert_matrix(&mut self, matrix: Mat4) -> u32 {
         let idx = self.matrices.len();
         self.matrices.push(matrix);
@@ -322,12 +469,19 @@ impl Renderer {
 
         let scissor_max_width = EFB_WIDTH as u32 - scissor_effective_x;
         let scissor_max_height = EFB_HEIGHT as u32 - scissor_effective_y;
-        self.current_pass.set_scissor_rect(
-            scissor_effective_x,
-            scissor_effective_y,
-            scissor_width.min(scissor_max_width),
-            scissor_height.min(scissor_max_height),
-        );
+
+        // with a free camera, geometry outside of the original frustum should still be visible
+        if self.camera.is_some() {
+            self.current_pass
+                .set_scissor_rect(0, 0, EFB_WIDTH as u32, EFB_HEIGHT as u32);
+        } else {
+            self.current_pass.set_scissor_rect(
+                scissor_effective_x,
+                scissor_effective_y,
+                scissor_width.min(scissor_max_width),
+                scissor_height.min(scissor_max_height),
+            );
+        }
 
         self.current_pass.set_viewport(
             self.viewport.top_left_x - scissor_offset_x as f32,
@@ -403,8 +557,39 @@ impl Renderer {
         self.current_config_dirty = true;
     }
 
-    fn set_projection_mtx(&mut self, mtx: Mat4) {
-        self.current_config.projection_mtx = mtx;
+    fn set_projection_mtx(&mut self, mtx: ProjectionMtx) {
+        self.projection = mtx;
+        self.update_projection();
+    }
+
+    /// Updates the projection and view matrices of the current config, applying the widescreen
+    /// hack and the camera override (if any).
+    fn update_projection(&mut self) {
+        let mut projection = self.projection.value();
+        let mut view = Mat4::IDENTITY;
+
+        if self.widescreen_hack && !self.projection.orthographic {
+            // show more of the scene horizontally instead of stretching it
+            projection.x_axis.x *= AspectRatio::Native.value() / AspectRatio::Hack.value();
+        }
+
+        if let Some(camera) = self.camera
+            && !self.projection.orthographic
+        {
+            // keep the aspect ratio of the game while replacing its field of view
+            if let Some(fov_y) = camera.fov_y
+                && projection.y_axis.y.abs() > f32::EPSILON
+            {
+                let scale = 1.0 / (fov_y / 2.0).tan();
+                projection.x_axis.x *= scale / projection.y_axis.y;
+                projection.y_axis.y = scale;
+            }
+
+            view = camera.view;
+        }
+
+        self.current_config.projection_mtx = projection;
+        self.current_config.view_mtx = view;
         self.current_config_dirty = true;
     }
 
@@ -529,6 +714,124 @@ impl Renderer {
         }
     }
 
+    fn draw_line_list(&mut self, stream: &VertexStream) {
+        let vertices = stream.vertices();
+
+        if vertices.is_empty() {
+            return;
+        }
+
+        if vertices.len() < 2 {
+            tracing::warn!("malformed line list draw call");
+            return;
+        }
+
+        self.draw_lines(stream, vertices.chunks_exact(2));
+    }
+
+    fn draw_line_strip(&mut self, stream: &VertexStream) {
+        let vertices = stream.vertices();
+
+        if vertices.is_empty() {
+            return;
+        }
+
+        if vertices.len() < 2 {
+            tracing::warn!("malformed line strip draw call");
+            return;
+        }
+
+        self.draw_lines(stream, vertices.windows(2));
+    }
+
+    /// Returns the matrix which takes view space positions to clip space, and its inverse, or
+    /// `None` if it can't be inverted.
+    fn clip_transform(&self) -> Option<(Mat4, Mat4)> {
+        let to_clip = self.current_config.projection_mtx * self.current_config.view_mtx;
+        (to_clip.determinant() != 0.0).then(|| (to_clip, to_clip.inverse()))
+    }
+
+    /// Draws lines as quads expanded on the CPU. Each item of `lines` is a pair of vertices.
+    fn draw_lines<'a>(
+        &mut self,
+        stream: &'a VertexStream,
+        lines: impl Iterator<Item = &'a [Vertex]>,
+    ) {
+        let Some((to_clip, unproject)) = self.clip_transform() else {
+            tracing::warn!("ignored lines drawn with a singular projection");
+            return;
+        };
+
+        let width = self.line_point_size.line_width_px();
+        let viewport = Vec2::new(self.viewport.width, self.viewport.height);
+
+        // culling does not apply to lines and points
+        let culling = self.pipeline_config.culling;
+        self.set_culling_mode(CullingMode::None);
+
+        self.flush_config();
+        let identity = self.insert_matrix(Mat4::IDENTITY);
+        let matrices = self.create_matrix_indices(stream.matrices());
+        for line in lines {
+            let [a, b] = [&line[0], &line[1]];
+            let clip_a = to_clip * view_position(a, stream.matrices());
+            let clip_b = to_clip * view_position(b, stream.matrices());
+            let Some(quad) = expand::line(clip_a, clip_b, width, viewport) else {
+                continue;
+            };
+
+            let corners = [a, b, b, a];
+            let [v0, v1, v2, v3] = std::array::from_fn(|i| {
+                self.insert_expanded_vertex(corners[i], &matrices, quad[i], unproject, identity)
+            });
+
+            self.indices.extend_from_slice(&[v0, v1, v2]);
+            self.indices.extend_from_slice(&[v0, v2, v3]);
+        }
+
+        self.set_culling_mode(culling);
+    }
+
+    /// Draws points as quads expanded on the CPU.
+    fn draw_point_list(&mut self, stream: &VertexStream) {
+        let vertices = stream.vertices();
+
+        if vertices.is_empty() {
+            return;
+        }
+
+        let Some((to_clip, unproject)) = self.clip_transform() else {
+            tracing::warn!("ignored points drawn with a singular projection");
+            return;
+        };
+
+        let size = self.line_point_size.point_size_px();
+        let viewport = Vec2::new(self.viewport.width, self.viewport.height);
+
+        // culling does not apply to lines and points
+        let culling = self.pipeline_config.culling;
+        self.set_culling_mode(CullingMode::None);
+
+        self.flush_config();
+        let identity = self.insert_matrix(Mat4::IDENTITY);
+        let matrices = self.create_matrix_indices(stream.matrices());
+        for vertex in vertices {
+            let clip = to_clip * view_position(vertex, stream.matrices());
+            let Some(quad) = expand::point(clip, size, viewport) else {
+                continue;
+            };
+
+            let [v0, v1, v2, v3] = quad.map(|corner| {
+                self.insert_expanded_vertex(vertex, &matrices, corner, unproject, identity)
+            });
+
+            self.indices.extend_from_slice(&[v0, v1, v2]);
+            self.indices.extend_from_slice(&[v0, v2, v3]);
+        }
+
+        self.set_culling_mode(culling);
+    }
+
     fn reset(&mut self) {
         self.indices.clear();
         self.vertices.clear();
@@ -571,8 +874,11 @@ impl Renderer {
     }
 
     fn get_textures_group(&mut self, entries: TexturesGroupEntries) -> wgpu::BindGroup {
-        self.textures_group_cache
+        let mut created = false;
+        let group = self
+            .textures_group_cache
             .get_or_insert(entries.clone(), || {
+                created = true;
                 let textures_group_entries: [wgpu::BindGroupEntry; 16] =
                     std::array::from_fn(|binding| {
                         let tex = binding / 2;
@@ -595,7 +901,15 @@ impl Renderer {
                 })
             })
             .unwrap()
-            .clone()
+            .clone();
+
+        if created {
+            self.group_misses += 1;
+        } else {
+            self.group_hits += 1;
+        }
+
+        group
     }
 
     /// Flushes all pending draws as a single draw call.
@@ -707,7 +1021,7 @@ impl Renderer {
                     }),
                     stencil_ops: None,
                 }),
-                timestamp_writes: None,
+                timestamp_writes: self.gpu_timer.as_mut().and_then(GpuTimer::pass_writes),
                 occlusion_query_set: None,
             })
             .forget_lifetime();
@@ -723,13 +1037,126 @@ impl Renderer {
         let transfer_cmds = prev_transfer_encoder.finish();
         let render_cmds = prev_render_encoder.finish();
 
+        let start = Instant::now();
         self.queue.submit([transfer_cmds, render_cmds]);
         self.device.poll(wgpu::PollType::Poll).unwrap();
+        self.frame_timer.add_pass(start.elapsed());
 
         self.allocators.index.free();
         self.allocators.storage.free();
         self.textures_group_cache.clear();
 
+        let capacity = self.shared.group_cache_capacity.load(Ordering::Relaxed);
+        if self.group_cache_capacity != capacity {
+            self.group_cache_capacity = capacity;
+            self.textures_group_cache = LruMap::with_hasher(ByLength::new(capacity), FxBuildHasher);
+        }
+
         self.shared.rendered_anything.store(true, Ordering::Relaxed);
     }
+
+    /// Marks the start of a batch of actions, for timing purposes.
+    pub fn begin_batch(&mut self) {
+        self.frame_timer.begin_batch();
+    }
+
+    /// Marks the end of a batch of actions, for timing purposes.
+    pub fn end_batch(&mut self) {
+        self.frame_timer.end_batch();
+    }
+
+    /// Records the times of the frame that just ended.
+    fn record_frame_times(&mut self) {
+        let times = self.frame_timer.end_frame();
+        let results = self
+            .gpu_timer
+            .as_mut()
+            .map(GpuTimer::collect)
+            .unwrap_or_default();
+
+        let mut history = self.shared.frame_times.lock().unwrap();
+        history.push(times);
+        for (frame, gpu) in results {
+            if let Some(entry) = history.iter_mut().find(|t| t.frame == frame) {
+                entry.gpu = Some(gpu);
+            }
+        }
+        drop(history);
+
+        if let Some(timer) = &mut self.gpu_timer {
+            timer.resolve(&self.device, &self.queue, times.frame);
+        }
+    }
+
+    /// Marks the end of a frame, submitting pending work and evicting unused textures if over
+    /// budget.
+    fn end_frame(&mut self) {
+        // passes started from now on belong to the next frame, including the one started by the
+        // submission below
+        if let Some(timer) = &mut self.gpu_timer {
+            timer.next_frame();
+        }
+
+        self.submit();
+        self.record_frame_times();
+
+        // bind groups are cleared on submit, so evicted textures won't be kept alive by them
+        debug_assert!(self.textures_group_cache.is_empty());
+
+        let budget = self.shared.texture_budget.load(Ordering::Relaxed);
+        self.texture_cache.set_budget(budget);
+        self.texture_cache.end_frame();
+        self.shared
+            .evicted_textures
+            .lock()
+            .unwrap()
+            .extend(self.texture_cache.take_evicted());
+
+        let filter = match self.shared.texture_filter.load(Ordering::Relaxed) {
+            x if x == TextureFilter::Bilinear as u8 => TextureFilter::Bilinear,
+            x if x == TextureFilter::Anisotropic as u8 => TextureFilter::Anisotropic,
+            _ => TextureFilter::Native,
+        };
+        self.texture_cache.set_filter(filter);
+
+        let usage = self.texture_cache.usage();
+        self.shared
+            .texture_memory
+            .store(usage.current, Ordering::Relaxed);
+        self.shared
+            .texture_memory_peak
+            .store(usage.peak, Ordering::Relaxed);
+
+        *self.shared.cache_stats.lock().unwrap() = CacheStats {
+            group_hits: self.group_hits,
+            group_misses: self.group_misses,
+            ..self.texture_cache.stats()
+        };
+
+        let aspect_ratio = self.shared.aspect_ratio.load(Ordering::Relaxed);
+        let widescreen_hack = aspect_ratio == AspectRatio::Hack as u8;
+        let camera = *self.shared.camera.lock().unwrap();
+        if self.camera != camera || self.widescreen_hack != widescreen_hack {
+            self.camera = camera;
+            self.widescreen_hack = widescreen_hack;
+            self.update_projection();
+        }
+
+        let debug_flags = *self.shared.debug_flags.lock().unwrap();
+        if self.pipeline_config.debug != debug_flags {
+            self.flush(format_args!("changed debug flags to {debug_flags:?}"));
+            self.pipeline_config.debug = debug_flags;
+        }
+    }
+}
+
+/// Returns the view space position of a vertex, i.e. its position transformed by its position
+/// matrix.
+fn view_position(vertex: &Vertex, matrices: &[(MatrixId, Mat4)]) -> Vec4 {
+    let matrix = matrices
+        .iter()
+        .find_map(|(id, m)| (*id == vertex.pos_norm_matrix).then_some(*m))
+        .unwrap();
+
+    matrix * vertex.position.extend(1.0)
 }
@@ -120,6 +120,7 @@ pub struct Config {
     pub regs: [Rgba; 4],
     pub consts: [Rgba; 4],
     pub projection_mtx: Mat4,
+    pub view_mtx: Mat4,
     pub post_transform_mtx: [Mat4; 8],
     pub constant_alpha: u32,
     pub alpha_refs: [u32; 2],
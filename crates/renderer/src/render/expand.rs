@@ -0,0 +1,96 @@
+//! Expansion of lines and points into quads, since wgpu lines and points are always one pixel
+//! wide.
+
+use glam::{Vec2, Vec4};
+
+/// Returns the normalized device coordinates of a clip space position.
+fn ndc(clip: Vec4) -> Vec2 {
+    Vec2::new(clip.x, clip.y) / clip.w
+}
+
+/// Returns the clip space offset of half of `pixels` at the given `w`.
+fn half_offset(pixels: Vec2, viewport: Vec2, w: f32) -> Vec4 {
+    // a pixel is 2 / viewport wide in NDC, so half of it is 1 / viewport
+    (pixels / viewport * w).extend(0.0).extend(0.0)
+}
+
+/// Expands the line between the clip space positions `a` and `b` into a quad `width` pixels wide,
+/// returning its corners as `[a, b, b, a]` in clip space.
+///
+/// Like on the GPU, lines are widened along the minor axis of their direction on screen, so their
+/// ends are either horizontal or vertical.
+pub fn line(a: Vec4, b: Vec4, width: f32, viewport: Vec2) -> Option<[Vec4; 4]> {
+    if a.w == 0.0 || b.w == 0.0 {
+        return None;
+    }
+
+    let delta = (ndc(b) - ndc(a)) * viewport;
+    let size = if delta.x.abs() >= delta.y.abs() {
+        Vec2::new(0.0, width)
+    } else {
+        Vec2::new(width, 0.0)
+    };
+
+    let offset_a = half_offset(size, viewport, a.w);
+    let offset_b = half_offset(size, viewport, b.w);
+
+    Some([a - offset_a, b - offset_b, b + offset_b, a + offset_a])
+}
+
+/// Expands the point at the clip space position `p` into a square quad `size` pixels wide,
+/// returning its corners in clip space.
+pub fn point(p: Vec4, size: f32, viewport: Vec2) -> Option<[Vec4; 4]> {
+    if p.w == 0.0 {
+        return None;
+    }
+
+    let x = half_offset(Vec2::new(size, 0.0), viewport, p.w);
+    let y = half_offset(Vec2::new(0.0, size), viewport, p.w);
+
+    Some([p - x - y, p + x - y, p + x + y, p - x + y])
+}
+
+#[cfg(test)]
+mod test {
+    use glam::{Vec2, Vec4};
+
+    use super::{line, ndc, point};
+
+    const VIEWPORT: Vec2 = Vec2::new(640.0, 480.0);
+
+    /// Converts a clip space position to pixels.
+    fn pixels(clip: Vec4) -> Vec2 {
+        ndc(clip) * VIEWPORT / 2.0
+    }
+
+    #[test]
+    fn line_width() {
+        let a = Vec4::new(-0.5, 0.0, 0.5, 1.0);
+        let b = Vec4::new(0.5, 0.1, 0.5, 2.0);
+
+        for width in [1.0, 4.0, 42.5] {
+            let [a0, b0, b1, a1] = line(a, b, width, VIEWPORT).unwrap();
+
+            // horizontal line, widened vertically
+            assert!((pixels(a1) - pixels(a0)).abs_diff_eq(Vec2::new(0.0, width), 1e-3));
+            assert!((pixels(b1) - pixels(b0)).abs_diff_eq(Vec2::new(0.0, width), 1e-3));
+        }
+
+        // vertical line, widened horizontally
+        let c = Vec4::new(-0.5, 0.5, 0.5, 1.0);
+        let [a0, _, _, a1] = line(a, c, 3.0, VIEWPORT).unwrap();
+        assert!((pixels(a1) - pixels(a0)).abs_diff_eq(Vec2::new(3.0, 0.0), 1e-3));
+    }
+
+    #[test]
+    fn point_size() {
+        let p = Vec4::new(0.2, -0.3, 0.5, 4.0);
+
+        for size in [1.0, 6.0] {
+            let [min, _, max, _] = point(p, size, VIEWPORT).unwrap();
+            assert!((pixels(max) - pixels(min)).abs_diff_eq(Vec2::splat(size), 1e-3));
+        }
+
+        assert!(point(Vec4::ZERO, 1.0, VIEWPORT).is_none());
+    }
+}
@@ -1,9 +1,11 @@
 //! Framebuffers (EFB and XFB).
 
 use std::collections::hash_map::Entry;
+use std::sync::atomic::Ordering;
 
 use lazuli::modules::render::oneshot::{self, Sender};
-use lazuli::modules::render::{CopyArgs, Texels, TextureId, XfbPart};
+use lazuli::modules::render::{CopyArgs, EfbBuffer, Texels, TextureId, XfbPart};
+use lazuli::system::gx::color::Rgba;
 use lazuli::system::gx::pix::{ColorCopyFormat, DepthCopyFormat};
 use lazuli::system::gx::{EFB_HEIGHT, EFB_WIDTH, pix};
 use lazuli::system::vi::Dimensions;
@@ -92,6 +94,17 @@ impl Embedded {
     }
 }
 
+/// How the fields of an interlaced frame are combined when they are stored in separate XFBs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Deinterlace {
+    /// Interleave the lines of the current field with the lines of the previous one. Sharp, but
+    /// moving objects show combing.
+    #[default]
+    Weave,
+    /// Line double the current field. No combing, but halves the vertical resolution.
+    Bob,
+}
+
 pub struct External {
     framebuffer: wgpu::TextureView,
     texture_pool: FxHashMap<wgpu::Extent3d, wgpu::TextureView>,
@@ -106,7 +119,9 @@ impl External {
                 dimension: wgpu::TextureDimension::D2,
                 size,
                 format: wgpu::TextureFormat::Rgba8UnormSrgb,
-                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_SRC
+                    | wgpu::TextureUsages::COPY_DST,
                 view_formats: &[],
                 mip_level_count: 1,
                 sample_count: 1,
@@ -176,18 +191,31 @@ impl External {
 
     /// Builds the XFB texture from a list of parts describing where to put each copy. Copies must
     /// have been previously added with `insert_copy` and are consumed by this method.
-    pub fn build(&mut self, encoder: &mut wgpu::CommandEncoder, parts: Vec<XfbPart>) {
+    ///
+    /// Parts which are a single field of an interlaced frame are either weaved with the other
+    /// field already in the framebuffer or line doubled, depending on `deinterlace`.
+    pub fn build(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        parts: Vec<XfbPart>,
+        deinterlace: Deinterlace,
+    ) {
         let framebuffer = self.framebuffer.texture();
-        encoder.clear_texture(
-            framebuffer,
-            &wgpu::ImageSubresourceRange {
-                aspect: wgpu::TextureAspect::default(),
-                base_mip_level: 0,
-                mip_level_count: None,
-                base_array_layer: 0,
-                array_layer_count: None,
-            },
-        );
+
+        // weaving keeps the lines of the previous field around
+        let fields = parts.iter().any(|p| p.line_step > 1);
+        if !fields || deinterlace == Deinterlace::Bob {
+            encoder.clear_texture(
+                framebuffer,
+                &wgpu::ImageSubresourceRange {
+                    aspect: wgpu::TextureAspect::default(),
+                    base_mip_level: 0,
+                    mip_level_count: None,
+                    base_array_layer: 0,
+                    array_layer_count: None,
+                },
+            );
+        }
 
         for part in parts {
             let saved = self.copies.get(&part.id).unwrap();
@@ -197,6 +225,19 @@ impl External {
             // HACK: this isnt the right way to deal with this... Animal Crossing needs it,
             // investigate further (XFB dimensions seem incorrect?)
             let width = saved_size.width.min(framebuffer_size.width - part.offset_x);
+
+            if part.line_step > 1 {
+                Self::copy_field(
+                    encoder,
+                    saved.texture(),
+                    framebuffer,
+                    part,
+                    width,
+                    deinterlace,
+                );
+                continue;
+            }
+
             let height = saved_size
                 .height
                 .min(framebuffer_size.height - part.offset_y);
@@ -232,6 +273,60 @@ impl External {
                 (size, tex)
             }));
     }
+
+    /// Copies a single field into the framebuffer, one line at a time.
+    fn copy_field(
+        encoder: &mut wgpu::CommandEncoder,
+        field: &wgpu::Texture,
+        framebuffer: &wgpu::Texture,
+        part: XfbPart,
+        width: u32,
+        deinterlace: Deinterlace,
+    ) {
+        let framebuffer_height = framebuffer.size().height;
+        let mut copy_line = |src: u32, dst: u32| {
+            if dst >= framebuffer_height {
+                return;
+            }
+
+            encoder.copy_texture_to_texture(
+                wgpu::TexelCopyTextureInfo {
+                    texture: field,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d { x: 0, y: src, z: 0 },
+                    aspect: wgpu::TextureAspect::default(),
+                },
+                wgpu::TexelCopyTextureInfo {
+                    texture: framebuffer,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d {
+                        x: part.offset_x,
+                        y: dst,
+                        z: 0,
+                    },
+                    aspect: wgpu::TextureAspect::default(),
+                },
+                wgpu::Extent3d {
+                    width,
+                    height: 1,
+                    depth_or_array_layers: 1,
+                },
+            );
+        };
+
+        for line in 0..field.size().height {
+            let y = part.offset_y + line * part.line_step;
+            match deinterlace {
+                Deinterlace::Weave => copy_line(line, y),
+                Deinterlace::Bob => {
+                    // fill both rows of the line pair, regardless of which field this is
+                    let top = y & !1;
+                    copy_line(line, top);
+                    copy_line(line, top + 1);
+                }
+            }
+        }
+    }
 }
 
 impl Renderer {
@@ -621,6 +716,7 @@ impl Renderer {
         assert!(!half);
 
         self.debug("XFB copy requested");
+        self.frame_timer.xfb_copied();
         self.submit();
 
         let x = src.x().value() as u32;
@@ -658,10 +754,212 @@ impl Renderer {
         }
     }
 
+    pub fn read_efb(&mut self, buffer: EfbBuffer, response: Sender<Texels>) {
+        self.debug(format!("EFB {buffer:?} readback requested"));
+        self.submit();
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+
+        let (width, height) = (EFB_WIDTH as u16, EFB_HEIGHT as u16);
+        let texture = match buffer {
+            EfbBuffer::Color => self.copy_color_to_tex(0, 0, width, height, false, &mut encoder),
+            EfbBuffer::Depth => self.copy_depth_to_tex(0, 0, width, height, false, &mut encoder),
+        };
+
+        let data = self.get_texture_data(&texture, encoder);
+        response.send(data).unwrap();
+    }
+
+    /// Writes a single pixel of the EFB by clearing it to the given color and/or depth.
+    pub fn poke_efb(&mut self, x: u16, y: u16, color: Option<Rgba>, depth: Option<f32>) {
+        // pending draws must land before the poke
+        self.flush(format_args!("EFB poke at ({x}, {y})"));
+
+        self.current_pass.set_scissor_rect(x as u32, y as u32, 1, 1);
+        self.current_pass
+            .set_viewport(0.0, 0.0, 640.0, 528.0, 0.0, 1.0);
+        self.cleaner
+            .clear_target(color, depth, &mut self.current_pass);
+    }
+
     pub fn present_xfb(&mut self, parts: Vec<XfbPart>) {
+        let deinterlace = match self.shared.deinterlace.load(Ordering::Relaxed) {
+            x if x == Deinterlace::Bob as u8 => Deinterlace::Bob,
+            _ => Deinterlace::Weave,
+        };
+
         self.external_fb
-            .build(&mut self.current_transfer_encoder, parts);
+            .build(&mut self.current_transfer_encoder, parts, deinterlace);
 
-        self.submit();
+        self.end_frame();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lazuli::modules::render::XfbPart;
+    use zerocopy::{FromBytes, IntoBytes};
+
+    use super::{Deinterlace, External};
+
+    /// Width of the test textures, chosen so that rows are aligned for copies to buffers.
+    const WIDTH: u32 = 64;
+
+    fn texture(device: &wgpu::Device, height: u32) -> wgpu::Texture {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width: WIDTH,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        })
+    }
+
+    /// Creates a field with a solid color per line.
+    fn field(device: &wgpu::Device, queue: &wgpu::Queue, lines: &[u32]) -> wgpu::Texture {
+        let texture = self::texture(device, lines.len() as u32);
+        let texels = lines
+            .iter()
+            .flat_map(|&color| std::iter::repeat_n(color, WIDTH as usize))
+            .collect::<Vec<_>>();
+
+        queue.write_texture(
+            texture.as_image_copy(),
+            texels.as_bytes(),
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(WIDTH * 4),
+                rows_per_image: None,
+            },
+            texture.size(),
+        );
+
+        texture
+    }
+
+    /// Reads back the color of each row of the texture, checking that rows are solid.
+    fn rows(device: &wgpu::Device, queue: &wgpu::Queue, texture: &wgpu::Texture) -> Vec<u32> {
+        let size = texture.size();
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (WIDTH * 4 * size.height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&Default::default());
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(WIDTH * 4),
+                    rows_per_image: None,
+                },
+            },
+            size,
+        );
+
+        let (sender, receiver) = flume::bounded(1);
+        encoder.map_buffer_on_submit(&buffer, wgpu::MapMode::Read, .., move |r| {
+            sender.send(r).unwrap()
+        });
+
+        let submission = queue.submit([encoder.finish()]);
+        device
+            .poll(wgpu::wgt::PollType::Wait {
+                submission_index: Some(submission),
+                timeout: None,
+            })
+            .unwrap();
+
+        receiver.recv().unwrap().unwrap();
+
+        let mapped = buffer.get_mapped_range(..);
+        let texels = <[u32]>::ref_from_bytes(&mapped).unwrap();
+        texels
+            .chunks_exact(WIDTH as usize)
+            .map(|row| {
+                assert!(row.iter().all(|&x| x == row[0]));
+                row[0]
+            })
+            .collect()
+    }
+
+    /// Copies the given fields, in order, into an empty four line framebuffer and returns its
+    /// rows.
+    fn deinterlace(fields: &[(&[u32], u32)], deinterlace: Deinterlace) -> Option<Vec<u32>> {
+        let (device, queue) = match crate::Renderer::headless_device() {
+            Ok(device) => device,
+            Err(e) => {
+                eprintln!("skipping headless rendering: {e}");
+                return None;
+            }
+        };
+
+        let framebuffer = self::texture(&device, 4);
+        let mut encoder = device.create_command_encoder(&Default::default());
+        for &(lines, parity) in fields {
+            let field = self::field(&device, &queue, lines);
+            let part = XfbPart {
+                id: 0,
+                offset_x: 0,
+                offset_y: parity,
+                line_step: 2,
+            };
+
+            External::copy_field(&mut encoder, &field, &framebuffer, part, WIDTH, deinterlace);
+        }
+
+        queue.submit([encoder.finish()]);
+        Some(self::rows(&device, &queue, &framebuffer))
+    }
+
+    const TOP: [u32; 2] = [0xFF00_0001, 0xFF00_0002];
+    const BOTTOM: [u32; 2] = [0xFF00_0003, 0xFF00_0004];
+
+    #[test]
+    fn weave_interleaves_fields() {
+        let Some(rows) = self::deinterlace(&[(&TOP, 0), (&BOTTOM, 1)], Deinterlace::Weave) else {
+            return;
+        };
+
+        assert_eq!(rows, [TOP[0], BOTTOM[0], TOP[1], BOTTOM[1]]);
+    }
+
+    #[test]
+    fn weave_keeps_other_field() {
+        // a single field only touches its own rows
+        let Some(rows) = self::deinterlace(&[(&BOTTOM, 1)], Deinterlace::Weave) else {
+            return;
+        };
+
+        assert_eq!(rows, [0, BOTTOM[0], 0, BOTTOM[1]]);
+    }
+
+    #[test]
+    fn bob_doubles_lines() {
+        let Some(rows) = self::deinterlace(&[(&TOP, 0), (&BOTTOM, 1)], Deinterlace::Bob) else {
+            return;
+        };
+
+        // the last field copied fills every row
+        assert_eq!(rows, [BOTTOM[0], BOTTOM[0], BOTTOM[1], BOTTOM[1]]);
+
+        let Some(rows) = self::deinterlace(&[(&TOP, 0)], Deinterlace::Bob) else {
+            return;
+        };
+
+        assert_eq!(rows, [TOP[0], TOP[0], TOP[1], TOP[1]]);
     }
 }
@@ -140,6 +140,7 @@ mod cache {
             };
 
             let cull_mode = match config.culling {
+                _ if config.debug.no_culling => None,
                 CullingMode::None => None,
                 CullingMode::Back => Some(wgpu::Face::Back),
                 CullingMode::Front => Some(wgpu::Face::Front),
@@ -149,6 +150,12 @@ mod cache {
                 }
             };
 
+            let polygon_mode = if config.debug.wireframe {
+                wgpu::PolygonMode::Line
+            } else {
+                wgpu::PolygonMode::Fill
+            };
+
             let label = format!("Pipeline {}", id);
             device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
                 label: Some(&label),
@@ -159,7 +166,7 @@ mod cache {
                     front_face: wgpu::FrontFace::Cw,
                     cull_mode,
                     unclipped_depth: false,
-                    polygon_mode: wgpu::PolygonMode::Fill,
+                    polygon_mode,
                     conservative: false,
                 },
                 vertex: wgpu::VertexState {
@@ -44,10 +44,23 @@ impl Default for DepthConfig {
     }
 }
 
+/// Debugging overrides of the pipeline state, controlled by the user rather than the game.
+///
+/// These are kept apart from the state set by the game, so toggling them never changes what the
+/// game configured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct DebugFlags {
+    /// Draws triangle edges only. Requires [`wgpu::Features::POLYGON_MODE_LINE`].
+    pub wireframe: bool,
+    /// Ignores the culling mode, drawing both front and back faces.
+    pub no_culling: bool,
+}
+
 #[derive(Clone, PartialEq, Eq, Hash, Default)]
 pub struct Config {
     pub has_alpha: bool,
     pub culling: CullingMode,
+    pub debug: DebugFlags,
     pub blend: BlendConfig,
     pub depth: DepthConfig,
     pub shader: shader::Config,
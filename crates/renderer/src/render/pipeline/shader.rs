@@ -190,7 +190,7 @@ fn vertex_stage(texgen: &TexGenConfig) -> wesl::syntax::GlobalDeclaration {
 
             let vertex_local_pos = vec4f(vertex.position, 1.0);
             let vertex_world_pos = render::matrices[vertex.position_mtx_idx] * vertex_local_pos;
-            var vertex_view_pos = config.projection_mtx * vertex_world_pos;
+            var vertex_view_pos = config.projection_mtx * (config.view_mtx * vertex_world_pos);
 
             let vertex_local_norm = vec4f(vertex.normal, 0.0);
             let vertex_world_norm = normalize((render::matrices[vertex.normal_mtx_idx] * vertex_local_norm).xyz);
@@ -298,8 +298,8 @@ fn fragment_stage(texenv: &TexEnvConfig) -> wesl::syntax::GlobalDeclaration {
 
             @#compute_stages {}
 
-            let color = regs[last_color_output].rgb;
-            let alpha = regs[last_alpha_output].a;
+            let color = common::tev_u8_vec3(regs[last_color_output].rgb);
+            let alpha = common::tev_u8(regs[last_alpha_output].a);
 
             let alpha_ref0 = f32(config.alpha_refs[0]) / 255.0;
             let alpha_ref1 = f32(config.alpha_refs[1]) / 255.0;
@@ -309,10 +309,10 @@ fn fragment_stage(texenv: &TexEnvConfig) -> wesl::syntax::GlobalDeclaration {
             }
 
             var out: render::FragmentOutput;
-            out.blend = vec4f(regs[last_color_output].rgb, regs[last_alpha_output].a);
+            out.blend = vec4f(color, alpha);
 
             @if(constant_alpha)
-            out.color = vec4f(regs[last_color_output].rgb, f32(config.constant_alpha) / 255.0);
+            out.color = vec4f(color, f32(config.constant_alpha) / 255.0);
 
             @if(!constant_alpha)
             out.color = out.blend;
@@ -346,8 +346,27 @@ fn main_module(config: &Config) -> wesl::syntax::TranslationUnit {
 }
 
 pub fn compile(config: &Config) -> String {
+    let needs_frag_depth = match config.texenv.depth_tex.mode.op() {
+        tev::depth::Op::Disabled => false,
+        tev::depth::Op::Add | tev::depth::Op::Replace => true,
+        _ => panic!("reserved depth tex mode"),
+    };
+
+    self::compile_module(
+        main_module(config),
+        &[
+            ("sample_shading", !config.texenv.alpha_test.is_noop()),
+            ("frag_depth", needs_frag_depth),
+            ("constant_alpha", config.texenv.constant_alpha),
+        ],
+    )
+}
+
+/// Compiles the given main module, which can import the `common` and `render` packages. Features
+/// which aren't given are disabled.
+fn compile_module(main: wesl::syntax::TranslationUnit, features: &[(&str, bool)]) -> String {
     let mut resolver = VirtualResolver::new();
-    resolver.add_translation_unit("package::main".parse().unwrap(), main_module(config));
+    resolver.add_translation_unit("package::main".parse().unwrap(), main);
     resolver.add_module(
         "package::common".parse().unwrap(),
         Cow::Borrowed(include_str!("../../../shaders/common.wesl")),
@@ -378,15 +397,13 @@ pub fn compile(config: &Config) -> String {
         ..Default::default()
     });
 
-    let needs_frag_depth = match config.texenv.depth_tex.mode.op() {
-        tev::depth::Op::Disabled | tev::depth::Op::Add => false,
-        tev::depth::Op::Replace => true,
-        _ => panic!("reserved depth tex mode"),
-    };
+    for feature in ["sample_shading", "frag_depth", "constant_alpha"] {
+        wesl.set_feature(feature, false);
+    }
 
-    wesl.set_feature("sample_shading", !config.texenv.alpha_test.is_noop());
-    wesl.set_feature("frag_depth", needs_frag_depth);
-    wesl.set_feature("constant_alpha", config.texenv.constant_alpha);
+    for &(feature, enabled) in features {
+        wesl.set_feature(feature, enabled);
+    }
 
     let compiled = match wesl.compile(&"package::main".parse().unwrap()) {
         Ok(ok) => ok,
@@ -397,3 +414,435 @@ pub fn compile(config: &Config) -> String {
 
     compiled.syntax.to_string()
 }
+
+#[cfg(test)]
+mod test {
+    //! Checks the lighting equations in `lighting.wesl` against expected values, by evaluating
+    //! them on the GPU with a compute pass.
+    //!
+    //! Also checks that the depth texture path writes the fragment depth, and renders overflowing
+    //! TEV stages to check their output.
+
+    use glam::Vec3;
+    use lazuli::modules::render::{
+        Action, EfbBuffer, TexEnvConfig, TexEnvRegisters, TexEnvStage, oneshot,
+    };
+    use lazuli::system::gx::color::{Rgba, Rgba16};
+    use lazuli::system::gx::xform::{DiffuseAttenuation, ProjectionMtx};
+    use lazuli::system::gx::{Topology, tev};
+    use zerocopy::IntoBytes;
+
+    use super::Config;
+    use crate::Renderer;
+    use crate::render::data::{self, Channel, Light};
+
+    /// Evaluates the light function of color channel 0 on the GPU for each of the given vertex
+    /// positions and normals, with a white material and no ambient. Returns `None` if there's no
+    /// adapter to compute with.
+    fn lit(channel: Channel, light: Light, vertices: &[(Vec3, Vec3)]) -> Option<Vec<f32>> {
+        use wesl::syntax::*;
+
+        let (device, queue) = match crate::Renderer::headless_device() {
+            Ok(device) => device,
+            Err(e) => {
+                eprintln!("skipping headless rendering: {e}");
+                return None;
+            }
+        };
+
+        let main = wesl_quote::quote_module! {
+            import package::render;
+            import package::render::lighting;
+
+            @group(0) @binding(0) var<storage> config: render::Config;
+            @group(0) @binding(1) var<storage> samples: array<vec4f>;
+            @group(0) @binding(2) var<storage, read_write> results: array<f32>;
+
+            @compute @workgroup_size(1)
+            fn main(@builtin(global_invocation_id) id: vec3u) {
+                let position = samples[2u * id.x].xyz;
+                let normal = samples[2u * id.x + 1u].xyz;
+                results[id.x] = lighting::color_channel(position, normal, vec3f(1f), 0u, config).r;
+            }
+        };
+
+        let source = super::compile_module(main, &[]);
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("lighting test"),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("lighting test pipeline"),
+            layout: None,
+            module: &module,
+            entry_point: None,
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        let mut config = data::Config::default();
+        config.material[0] = Rgba::new(1.0, 1.0, 1.0, 1.0);
+        config.lights[0] = light;
+        config.color_channels[0] = channel;
+
+        let samples = vertices
+            .iter()
+            .flat_map(|(position, normal)| [position.extend(0.0), normal.extend(0.0)])
+            .map(|v| v.to_array())
+            .collect::<Vec<_>>();
+
+        let buffer = |label, size, usage| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size,
+                usage,
+                mapped_at_creation: false,
+            })
+        };
+
+        let input = wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST;
+        let config_buffer = buffer("config", config.as_bytes().len() as u64, input);
+        let samples_buffer = buffer("samples", samples.as_bytes().len() as u64, input);
+        queue.write_buffer(&config_buffer, 0, config.as_bytes());
+        queue.write_buffer(&samples_buffer, 0, samples.as_bytes());
+
+        let results_size = (vertices.len() * size_of::<f32>()) as u64;
+        let results_buffer = buffer(
+            "results",
+            results_size,
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        );
+        let read_buffer = buffer(
+            "results read",
+            results_size,
+            wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        );
+
+        let group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: config_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: samples_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: results_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut encoder = device.create_command_encoder(&Default::default());
+        let mut pass = encoder.begin_compute_pass(&Default::default());
+        pass.set_pipeline(&pipeline);
+        pass.set_bind_group(0, &group, &[]);
+        pass.dispatch_workgroups(vertices.len() as u32, 1, 1);
+        std::mem::drop(pass);
+
+        encoder.copy_buffer_to_buffer(&results_buffer, 0, &read_buffer, 0, results_size);
+
+        let (sender, receiver) = flume::bounded(1);
+        encoder.map_buffer_on_submit(&read_buffer, wgpu::MapMode::Read, .., move |r| {
+            sender.send(r).unwrap()
+        });
+
+        let submission = queue.submit([encoder.finish()]);
+        device
+            .poll(wgpu::wgt::PollType::Wait {
+                submission_index: Some(submission),
+                timeout: None,
+            })
+            .unwrap();
+
+        receiver.recv().unwrap().unwrap();
+
+        let mapped = read_buffer.get_mapped_range(..);
+        let results = mapped
+            .chunks_exact(size_of::<f32>())
+            .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+            .collect();
+
+        Some(results)
+    }
+
+    /// A point light 10 units above the origin, pointing down.
+    fn diffuse_light() -> Light {
+        Light {
+            color: Rgba::new(1.0, 1.0, 1.0, 1.0),
+            cos_atten: Vec3::new(1.0, 0.0, 0.0),
+            dist_atten: Vec3::new(1.0, 0.1, 0.0),
+            position: Vec3::new(0.0, 0.0, 10.0),
+            direction: Vec3::new(0.0, 0.0, -1.0),
+            ..Default::default()
+        }
+    }
+
+    /// A channel lit by light 0.
+    fn channel(diffuse_atten: DiffuseAttenuation, specular: bool) -> Channel {
+        let mut light_mask = [false; 8];
+        light_mask[0] = true;
+
+        Channel::default()
+            .with_lighting_enabled(true)
+            .with_diffuse_atten(diffuse_atten)
+            .with_position_atten(true)
+            .with_specular(specular)
+            .with_light_mask(light_mask)
+    }
+
+    fn diffuse_channel() -> Channel {
+        self::channel(DiffuseAttenuation::ComputeClamped, false)
+    }
+
+    /// A specular light set up like `GX_InitSpecularDir` and `GX_InitLightShininess(4.0)` would.
+    fn specular_light() -> Light {
+        Light {
+            color: Rgba::new(1.0, 1.0, 1.0, 1.0),
+            cos_atten: Vec3::new(0.0, 0.0, 1.0),
+            dist_atten: Vec3::new(2.0, 0.0, -1.0),
+            position: Vec3::new(0.0, 0.0, 1048576.0),
+            direction: Vec3::new(0.0, 0.6, 0.8),
+            ..Default::default()
+        }
+    }
+
+    fn specular_channel() -> Channel {
+        self::channel(DiffuseAttenuation::One, true)
+    }
+
+    fn assert_close(values: &[f32], expected: &[f32]) {
+        assert_eq!(values.len(), expected.len());
+        for (value, expected) in values.iter().zip(expected) {
+            assert!(
+                (value - expected).abs() < 1e-4,
+                "expected {expected}, got {value}"
+            );
+        }
+    }
+
+    #[test]
+    fn diffuse_point_light() {
+        let normal = Vec3::Z;
+
+        // off axis: both the diffuse factor and the distance change
+        let pos = Vec3::new(10.0, 0.0, 0.0);
+        let dist = 200f32.sqrt();
+        let off_axis = (10.0 / dist) / (1.0 + 0.1 * dist);
+
+        let vertices = [
+            // facing the light: distance attenuation of 1 / (1 + 0.1 * 10)
+            (Vec3::ZERO, normal),
+            // away from the light: clamped to zero
+            (Vec3::ZERO, -normal),
+            (pos, normal),
+        ];
+
+        let Some(values) = lit(diffuse_channel(), diffuse_light(), &vertices) else {
+            return;
+        };
+
+        assert_close(&values, &[0.5, 0.0, off_axis]);
+    }
+
+    #[test]
+    fn specular_light_uses_half_angle() {
+        let normal = Vec3::Z;
+
+        // n.h = 0.8, so the attenuation is 0.8^2 / (2 - 0.8^2)
+        let expected = 0.64 / 1.36;
+
+        let vertices = [
+            (Vec3::ZERO, normal),
+            // the vertex position does not matter for specular lights
+            (Vec3::new(300.0, -50.0, 20.0), normal),
+            // facing away from the light
+            (Vec3::ZERO, -normal),
+        ];
+
+        let Some(values) = lit(specular_channel(), specular_light(), &vertices) else {
+            return;
+        };
+
+        assert_close(&values, &[expected, expected, 0.0]);
+    }
+
+    #[test]
+    fn specular_light_with_diffuse_attenuation() {
+        let channel = self::channel(DiffuseAttenuation::ComputeClamped, true);
+
+        // distance coefficients are normalized to (2, 0, -1) / sqrt(5)
+        let norm = 5f32.sqrt();
+        let expected = 0.64 / (2.0 / norm - 0.64 / norm);
+
+        let Some(values) = lit(channel, specular_light(), &[(Vec3::ZERO, Vec3::Z)]) else {
+            return;
+        };
+
+        assert_close(&values, &[expected.min(1.0)]);
+    }
+
+    fn depth_tex_config(op: tev::depth::Op) -> Config {
+        let mut config = Config::default();
+        config.texenv.stages.push(TexEnvStage::default());
+        config.texenv.depth_tex = tev::depth::Texture {
+            mode: tev::depth::Mode::default()
+                .with_format(tev::depth::Format::U16)
+                .with_op(op),
+            bias: 16,
+        };
+
+        config
+    }
+
+    #[test]
+    fn depth_texture() {
+        let disabled = super::compile(&depth_tex_config(tev::depth::Op::Disabled));
+        assert!(!disabled.contains("@builtin(frag_depth)"));
+        assert!(!disabled.contains("depth_tex_value"));
+
+        for op in [tev::depth::Op::Add, tev::depth::Op::Replace] {
+            let shader = super::compile(&depth_tex_config(op));
+            assert!(shader.contains("@builtin(frag_depth)"), "{op:?}");
+            assert!(shader.contains("depth_tex_value"), "{op:?}");
+        }
+    }
+
+    /// A stage which computes `scale * (±R1 + R2)` into R0, for both color and alpha. R1 is used as
+    /// the A input, so only its low 8 bits are taken into account.
+    fn overflow_stage(clamp: bool, negate: bool, scale: tev::Scale) -> TexEnvStage {
+        use tev::alpha::InputSrc as Alpha;
+        use tev::color::InputSrc as Color;
+
+        let mut stage = TexEnvStage::default();
+        stage.ops.color = tev::color::Stage::default()
+            .with_input_a(Color::R1Color)
+            .with_input_b(Color::Zero)
+            .with_input_c(Color::Zero)
+            .with_input_d(Color::R2Color)
+            .with_bias(tev::Bias::Zero)
+            .with_negate(negate)
+            .with_clamp(clamp)
+            .with_scale(scale)
+            .with_output(tev::OutputDst::R0);
+        stage.ops.alpha = tev::alpha::Stage::default()
+            .with_input_a(Alpha::R1Alpha)
+            .with_input_b(Alpha::Zero)
+            .with_input_c(Alpha::Zero)
+            .with_input_d(Alpha::R2Alpha)
+            .with_bias(tev::Bias::Zero)
+            .with_negate(negate)
+            .with_clamp(clamp)
+            .with_scale(scale)
+            .with_output(tev::OutputDst::R0);
+
+        stage
+    }
+
+    /// A stage which outputs the alpha of R0 as its color, so that it can be read back.
+    fn alpha_as_color_stage() -> TexEnvStage {
+        use tev::color::InputSrc as Color;
+
+        let mut stage = TexEnvStage::default();
+        stage.ops.color = tev::color::Stage::default()
+            .with_input_a(Color::Zero)
+            .with_input_b(Color::Zero)
+            .with_input_c(Color::Zero)
+            .with_input_d(Color::R0Alpha)
+            .with_bias(tev::Bias::Zero)
+            .with_scale(tev::Scale::One)
+            .with_output(tev::OutputDst::R3);
+
+        stage
+    }
+
+    /// Renders a triangle with the given TEV stages and values of R1 and R2, returning the color
+    /// at its center, or `None` if there's no adapter to render with.
+    fn render_stages(stages: Vec<TexEnvStage>, r1: i16, r2: i16) -> Option<[u8; 3]> {
+        let mut renderer = match Renderer::new_headless() {
+            Ok(renderer) => renderer,
+            Err(e) => {
+                eprintln!("skipping headless rendering: {e}");
+                return None;
+            }
+        };
+
+        let gray = |value: i16| Rgba16 {
+            r: value,
+            g: value,
+            b: value,
+            a: value,
+        };
+
+        let actions = [
+            Action::SetScissor(crate::test::scissor()),
+            Action::SetProjectionMatrix(ProjectionMtx {
+                params: [1.0, 0.0, 1.0, 0.0, 0.0, -0.5],
+                orthographic: true,
+            }),
+            Action::SetTexEnvConfig(TexEnvConfig {
+                stages,
+                ..Default::default()
+            }),
+            Action::SetTexEnvRegisters(TexEnvRegisters {
+                regs: [gray(0), gray(r1), gray(r2), gray(0)],
+                constants: Default::default(),
+            }),
+            Action::Draw(Topology::TriangleList, crate::test::triangle()),
+        ];
+
+        for action in actions {
+            renderer.exec(action);
+        }
+
+        let (sender, receiver) = oneshot::channel();
+        renderer.exec(Action::ReadEfb {
+            buffer: EfbBuffer::Color,
+            response: sender,
+        });
+
+        let color = receiver.recv().unwrap();
+        let center = color[264 * 640 + 320].to_le_bytes();
+        Some([center[0], center[1], center[2]])
+    }
+
+    #[test]
+    fn overflowing_stage() {
+        use tev::Scale;
+
+        // (R1, R2, clamp, negate, scale, output)
+        let cases = [
+            // 200 + 100 doesn't fit in 8 bits: clamping saturates it...
+            (200, 100, true, false, Scale::One, 255),
+            // ...otherwise the register keeps it, but the output only has the low 8 bits
+            (200, 100, false, false, Scale::One, 300 & 0xFF),
+            // results outside of the register range saturate to -1024..=1023
+            (200, 100, false, false, Scale::Four, 1023 & 0xFF),
+            (255, -100, false, true, Scale::Four, -1024 & 0xFF),
+            // negative results
+            (200, 100, true, true, Scale::One, 0),
+            (200, 100, false, true, Scale::One, -100 & 0xFF),
+            // R1 is the A input, so only 300 & 0xFF = 44 is added to R2
+            (300, 100, true, false, Scale::One, 144),
+        ];
+
+        for (r1, r2, clamp, negate, scale, expected) in cases {
+            let stage = overflow_stage(clamp, negate, scale);
+            let expected = [expected as u8; 3];
+            let case = (r1, r2, clamp, negate, scale);
+
+            let Some(color) = render_stages(vec![stage.clone()], r1, r2) else {
+                return;
+            };
+            assert_eq!(color, expected, "color of {case:?}");
+
+            let alpha = render_stages(vec![stage, alpha_as_color_stage()], r1, r2).unwrap();
+            assert_eq!(alpha, expected, "alpha of {case:?}");
+        }
+    }
+}
@@ -112,10 +112,8 @@ fn comparison_target(
 pub fn compute_depth_texture(config: &TexEnvConfig) -> wesl::syntax::Statement {
     use wesl::syntax::*;
 
-    if matches!(
-        config.depth_tex.mode.op(),
-        tev::depth::Op::Disabled | tev::depth::Op::Add
-    ) {
+    let op = config.depth_tex.mode.op();
+    if op == tev::depth::Op::Disabled {
         return Statement::Void;
     }
 
@@ -140,11 +138,20 @@ pub fn compute_depth_texture(config: &TexEnvConfig) -> wesl::syntax::Statement {
         _ => panic!("reserved format"),
     };
 
+    // when adding, the texture value is added to the 24-bit depth of the fragment
+    let depth = match op {
+        tev::depth::Op::Add => quote_expression! {
+            (frag_depth * 16777215.0 + f32(depth_tex_value)) / 16777215.0
+        },
+        tev::depth::Op::Replace => quote_expression!(f32(depth_tex_value) / #depth_max),
+        _ => panic!("reserved depth tex op"),
+    };
+
     quote_statement! {
         {
             let depth_tex_sample = common::vec4f_to_vec4u(#sampled);
             let depth_tex_value = pack4xU8(vec4u(depth_tex_sample.x, #depth_mid, #depth_hi, 0)) + #bias;
-            out.depth = clamp(f32(depth_tex_value) / #depth_max, 0.0, 1.0);
+            out.depth = clamp(#depth, 0.0, 1.0);
             frag_depth = out.depth;
         }
     }
@@ -28,17 +28,40 @@ fn input(stage: &TexEnvStage, src: tev::alpha::InputSrc) -> wesl::syntax::Expres
     }
 }
 
+/// Same as [`input`], but keeping only the low 8 bits of registers, like the A, B and C inputs do.
+fn input_u8(stage: &TexEnvStage, src: tev::alpha::InputSrc) -> wesl::syntax::Expression {
+    use wesl::syntax::*;
+
+    let input = input(stage, src);
+    if src.is_register() {
+        quote_expression! { common::tev_u8(#input) }
+    } else {
+        input
+    }
+}
+
+/// Clamps the output of a stage to `0..=255` or saturates it to the signed 11-bit range of the
+/// registers, depending on the clamp setting of the stage.
+fn clamp_output(stage: &TexEnvStage, value: wesl::syntax::Expression) -> wesl::syntax::Expression {
+    use wesl::syntax::*;
+
+    if stage.ops.alpha.clamp() {
+        quote_expression! { clamp(#value, 0f, 1f) }
+    } else {
+        quote_expression! { common::tev_s11(#value) }
+    }
+}
+
 fn comparative_stage(stage: &TexEnvStage) -> wesl::syntax::Statement {
     use wesl::syntax::*;
 
-    let input_a = input(stage, stage.ops.alpha.input_a());
-    let input_b = input(stage, stage.ops.alpha.input_b());
-    let input_c = input(stage, stage.ops.alpha.input_c());
+    let input_a = input_u8(stage, stage.ops.alpha.input_a());
+    let input_b = input_u8(stage, stage.ops.alpha.input_b());
+    let input_c = input_u8(stage, stage.ops.alpha.input_c());
     let input_d = input(stage, stage.ops.alpha.input_d());
 
     let target = stage.ops.alpha.compare_target();
     let op = stage.ops.alpha.compare_op();
-    let clamp = stage.ops.alpha.clamp();
     let output = stage.ops.alpha.output().index();
 
     let compare_target_a = comparison_target(
@@ -58,11 +81,7 @@ fn comparative_stage(stage: &TexEnvStage) -> wesl::syntax::Statement {
         tev::ComparisonOp::Equal => quote_expression! { #compare_target_a == #compare_target_b },
     };
 
-    let clamped = if clamp {
-        quote_expression! { alpha_compare }
-    } else {
-        quote_expression! { clamp(alpha_compare, 0f, 1f) }
-    };
+    let clamped = clamp_output(stage, quote_expression!(alpha_compare));
 
     wesl_quote::quote_statement! {
         {
@@ -86,22 +105,17 @@ fn comparative_stage(stage: &TexEnvStage) -> wesl::syntax::Statement {
 fn regular_stage(stage: &TexEnvStage) -> wesl::syntax::Statement {
     use wesl::syntax::*;
 
-    let input_a = input(stage, stage.ops.alpha.input_a());
-    let input_b = input(stage, stage.ops.alpha.input_b());
-    let input_c = input(stage, stage.ops.alpha.input_c());
+    let input_a = input_u8(stage, stage.ops.alpha.input_a());
+    let input_b = input_u8(stage, stage.ops.alpha.input_b());
+    let input_c = input_u8(stage, stage.ops.alpha.input_c());
     let input_d = input(stage, stage.ops.alpha.input_d());
 
     let sign = if stage.ops.alpha.negate() { -1.0 } else { 1.0 };
     let bias = stage.ops.alpha.bias().value();
     let scale = stage.ops.alpha.scale().value();
-    let clamp = stage.ops.alpha.clamp();
     let output = stage.ops.alpha.output().index();
 
-    let clamped = if clamp {
-        quote_expression! { alpha_add_mul }
-    } else {
-        quote_expression! { clamp(alpha_add_mul, 0f, 1f) }
-    };
+    let clamped = clamp_output(stage, quote_expression!(alpha_add_mul));
 
     wesl_quote::quote_statement! {
         {
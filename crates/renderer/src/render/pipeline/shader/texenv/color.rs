@@ -41,17 +41,40 @@ fn input(stage: &TexEnvStage, src: tev::color::InputSrc) -> wesl::syntax::Expres
     }
 }
 
+/// Same as [`input`], but keeping only the low 8 bits of registers, like the A, B and C inputs do.
+fn input_u8(stage: &TexEnvStage, src: tev::color::InputSrc) -> wesl::syntax::Expression {
+    use wesl::syntax::*;
+
+    let input = input(stage, src);
+    if src.is_register() {
+        quote_expression! { common::tev_u8_vec3(#input) }
+    } else {
+        input
+    }
+}
+
+/// Clamps the output of a stage to `0..=255` or saturates it to the signed 11-bit range of the
+/// registers, depending on the clamp setting of the stage.
+fn clamp_output(stage: &TexEnvStage, value: wesl::syntax::Expression) -> wesl::syntax::Expression {
+    use wesl::syntax::*;
+
+    if stage.ops.color.clamp() {
+        quote_expression! { clamp(#value, vec3f(0f), vec3f(1f)) }
+    } else {
+        quote_expression! { common::tev_s11_vec3(#value) }
+    }
+}
+
 fn comparative_stage(stage: &TexEnvStage) -> wesl::syntax::Statement {
     use wesl::syntax::*;
 
-    let input_a = input(stage, stage.ops.color.input_a());
-    let input_b = input(stage, stage.ops.color.input_b());
-    let input_c = input(stage, stage.ops.color.input_c());
+    let input_a = input_u8(stage, stage.ops.color.input_a());
+    let input_b = input_u8(stage, stage.ops.color.input_b());
+    let input_c = input_u8(stage, stage.ops.color.input_c());
     let input_d = input(stage, stage.ops.color.input_d());
 
     let target = stage.ops.color.comparison_target();
     let op = stage.ops.color.comparison_op();
-    let clamp = stage.ops.color.clamp();
     let output = stage.ops.color.output().index();
 
     let compare_target_a = comparison_target(
@@ -71,11 +94,7 @@ fn comparative_stage(stage: &TexEnvStage) -> wesl::syntax::Statement {
         tev::ComparisonOp::Equal => quote_expression! { #compare_target_a == #compare_target_b },
     };
 
-    let clamped = if clamp {
-        quote_expression! { color_compare }
-    } else {
-        quote_expression! { clamp(color_compare, vec3f(0f), vec3f(1f)) }
-    };
+    let clamped = clamp_output(stage, quote_expression!(color_compare));
 
     wesl_quote::quote_statement! {
         {
@@ -99,22 +118,17 @@ fn comparative_stage(stage: &TexEnvStage) -> wesl::syntax::Statement {
 fn regular_stage(stage: &TexEnvStage) -> wesl::syntax::Statement {
     use wesl::syntax::*;
 
-    let input_a = input(stage, stage.ops.color.input_a());
-    let input_b = input(stage, stage.ops.color.input_b());
-    let input_c = input(stage, stage.ops.color.input_c());
+    let input_a = input_u8(stage, stage.ops.color.input_a());
+    let input_b = input_u8(stage, stage.ops.color.input_b());
+    let input_c = input_u8(stage, stage.ops.color.input_c());
     let input_d = input(stage, stage.ops.color.input_d());
 
     let sign = if stage.ops.color.negate() { -1.0 } else { 1.0 };
     let bias = stage.ops.color.bias().value();
     let scale = stage.ops.color.scale().value();
-    let clamp = stage.ops.color.clamp();
     let output = stage.ops.color.output().index();
 
-    let clamped = if clamp {
-        quote_expression! { color_add_mul }
-    } else {
-        quote_expression! { clamp(color_add_mul, vec3f(0f), vec3f(1f)) }
-    };
+    let clamped = clamp_output(stage, quote_expression!(color_add_mul));
 
     wesl_quote::quote_statement! {
         {
@@ -2,10 +2,29 @@ use std::collections::hash_map::Entry;
 
 use lazuli::modules::render::{ClutData, ClutId, ClutRef, Sampler, Scaling, Texture, TextureId};
 use lazuli::system::gx::color::Rgba8;
-use lazuli::system::gx::tex::{ClutFormat, TextureData, WrapMode};
+use lazuli::system::gx::tex::{ClutFormat, Format, TextureData, WrapMode};
 use rustc_hash::FxHashMap;
 
 use crate::render::{Renderer, TexSlotConfig};
+
+/// Default texture memory budget, in bytes.
+pub const DEFAULT_TEXTURE_BUDGET: u64 = 512 * 1024 * 1024;
+
+/// How many frames a texture must go unreferenced before it can be evicted.
+const EVICTION_IDLE_FRAMES: u64 = 8;
+
+/// Texture filtering used regardless of what the game asks for, as an enhancement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TextureFilter {
+    /// Filter textures as the game asks.
+    #[default]
+    Native,
+    /// Always filter textures linearly, including mipmaps.
+    Bilinear,
+    /// Always filter textures linearly and anisotropically.
+    Anisotropic,
+}
+
 /// Configuration of a processed texture.
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
 pub struct TextureRef {
@@ -19,20 +38,169 @@ enum Processed {
     Indirect(FxHashMap<ClutRef, wgpu::TextureView>),
 }
 
+impl Processed {
+    fn count(&self) -> u64 {
+        match self {
+            Self::Direct(view) => view.is_some() as u64,
+            Self::Indirect(views) => views.len() as u64,
+        }
+    }
+
+    fn clear(&mut self) {
+        match self {
+            Self::Direct(view) => *view = None,
+            Self::Indirect(views) => views.clear(),
+        }
+    }
+}
+
 /// A texture family.
 struct Family {
     raw: Option<Texture>,
     processed: Processed,
+    /// Frame in which this family was last referenced.
+    last_used: u64,
+    /// Whether the processed textures of this family were evicted and it hasn't been referenced
+    /// since, in which case the raw data can be released.
+    evicted: bool,
+}
+
+impl Family {
+    /// Size in bytes of a single processed texture of this family, if it can be evicted.
+    fn texture_size(&self) -> Option<u64> {
+        self.raw
+            .as_ref()
+            .map(|raw| texture_size(raw.width, raw.height, raw.data.lod_count()))
+    }
+
+    /// Total size in bytes of the processed textures of this family, if it can be evicted.
+    fn size(&self) -> Option<u64> {
+        self.texture_size()
+            .map(|size| size * self.processed.count())
+    }
+}
+
+/// Decodes the first `count` entries of a CLUT in the given format. Entries past the end of
+/// `entries` are decoded as transparent black.
+pub fn decode_clut(entries: &[u16], format: ClutFormat, count: usize) -> Vec<Rgba8> {
+    let convert = match format {
+        ClutFormat::IA8 => Rgba8::from_ia8,
+        ClutFormat::RGB565 => Rgba8::from_rgb565,
+        ClutFormat::RGB5A3 => Rgba8::from_rgb5a3,
+        _ => panic!("reserved clut format"),
+    };
+
+    let mut palette: Vec<_> = entries.iter().take(count).copied().map(convert).collect();
+    palette.resize(count, Rgba8::default());
+    palette
+}
+
+/// Number of CLUT entries a color indexed texture format can address.
+fn clut_len(format: Format) -> usize {
+    match format {
+        Format::CI4 => 1 << 4,
+        Format::CI8 => 1 << 8,
+        Format::CI14X2 => 1 << 14,
+        _ => 0,
+    }
+}
+
+/// Size in bytes of a RGBA8 texture with the given dimensions and LOD count.
+fn texture_size(width: u32, height: u32, lods: u32) -> u64 {
+    let mut size = 0;
+    let mut width = width as u64;
+    let mut height = height as u64;
+    for _ in 0..lods {
+        size += width * height * 4;
+        width = (width / 2).max(1);
+        height = (height / 2).max(1);
+    }
+
+    size
+}
+
+/// An eviction candidate: a texture family with processed textures that can be re-created.
+#[derive(Clone, Copy)]
+struct Candidate {
+    id: TextureId,
+    size: u64,
+    last_used: u64,
+}
+
+/// Selects which candidates to evict so that memory usage goes back under `budget`, least
+/// recently used first. Candidates referenced in the last [`EVICTION_IDLE_FRAMES`] frames are
+/// never evicted.
+fn select_evictions(
+    mut candidates: Vec<Candidate>,
+    mut bytes: u64,
+    budget: u64,
+    frame: u64,
+) -> Vec<TextureId> {
+    candidates.retain(|c| frame.saturating_sub(c.last_used) > EVICTION_IDLE_FRAMES);
+    candidates.sort_unstable_by_key(|c| c.last_used);
+
+    let mut evicted = Vec::new();
+    for candidate in candidates {
+        if bytes <= budget {
+            break;
+        }
+
+        bytes -= candidate.size;
+        evicted.push(candidate.id);
+    }
+
+    evicted
+}
+
+/// Memory usage of the texture cache.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Usage {
+    /// Bytes currently used by evictable textures.
+    pub current: u64,
+    /// Highest value `current` has ever reached.
+    pub peak: u64,
+}
+
+/// Counters of the texture caches, since the renderer was created.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CacheStats {
+    /// Lookups of processed textures which were already created.
+    pub hits: u64,
+    /// Lookups of processed textures which had to be created.
+    pub misses: u64,
+    /// Processed textures evicted for being over budget.
+    pub evictions: u64,
+    /// Raw texture loads which replaced a texture already in the cache, discarding all of its
+    /// processed textures.
+    pub replacements: u64,
+    /// Lookups of texture bind groups which were already created.
+    pub group_hits: u64,
+    /// Lookups of texture bind groups which had to be created.
+    pub group_misses: u64,
 }
 
 const TMEM_HIGH_LEN: usize = 512 * 1024 / 2;
 
 type TmemHigh = Box<[u16; TMEM_HIGH_LEN]>;
 
+/// Cache of textures uploaded by the guest.
+///
+/// Memory used by processed textures is accounted for and, once over budget, textures which have
+/// not been referenced recently are evicted. Evicted textures are reported through
+/// [`Cache::take_evicted`] so that their raw data can be released once the emulator knows it has to
+/// load them again. Until then, they're transparently re-created if referenced.
 pub struct Cache {
     tmem: TmemHigh,
     families: FxHashMap<TextureId, Family>,
+    /// Samplers created for the settings of the game, with `filter` applied.
     samplers: FxHashMap<Sampler, wgpu::Sampler>,
+    filter: TextureFilter,
+    budget: u64,
+    frame: u64,
+    usage: Usage,
+    stats: CacheStats,
+    /// Families evicted since the last call to [`Cache::take_evicted`].
+    evicted: Vec<TextureId>,
 }
 
 impl Default for Cache {
@@ -41,12 +209,108 @@ impl Default for Cache {
             tmem: util::boxed_array(0),
             families: Default::default(),
             samplers: Default::default(),
+            filter: TextureFilter::default(),
+            budget: DEFAULT_TEXTURE_BUDGET,
+            frame: 0,
+            usage: Usage::default(),
+            stats: CacheStats::default(),
+            evicted: Vec::new(),
         }
     }
 }
 
 impl Cache {
-    fn create_sampler(device: &wgpu::Device, sampler: Sampler) -> wgpu::Sampler {
+    pub fn usage(&self) -> Usage {
+        self.usage
+    }
+
+    /// Counters of this cache. Bind group counters are not tracked here and are always zero.
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    pub fn set_budget(&mut self, budget: u64) {
+        self.budget = budget;
+    }
+
+    /// Sets the filtering override, discarding samplers created with the previous one.
+    ///
+    /// This must only be called when no bind groups reference cached samplers.
+    pub fn set_filter(&mut self, filter: TextureFilter) {
+        if self.filter != filter {
+            self.filter = filter;
+            self.samplers.clear();
+        }
+    }
+
+    fn add_usage(&mut self, bytes: u64) {
+        self.usage.current += bytes;
+        self.usage.peak = self.usage.peak.max(self.usage.current);
+    }
+
+    fn remove_family(&mut self, old: Option<Family>) {
+        if let Some(size) = old.and_then(|f| f.size()) {
+            self.usage.current -= size;
+        }
+    }
+
+    /// Marks the end of a frame, evicting textures if over budget.
+    ///
+    /// This must only be called when no bind groups reference cached textures, otherwise evicted
+    /// textures would be kept alive by them.
+    pub fn end_frame(&mut self) {
+        self.frame += 1;
+        if self.usage.current <= self.budget {
+            return;
+        }
+
+        let candidates = self
+            .families
+            .iter()
+            .filter_map(|(id, family)| {
+                family
+                    .size()
+                    .filter(|size| *size > 0)
+                    .map(|size| Candidate {
+                        id: *id,
+                        size,
+                        last_used: family.last_used,
+                    })
+            })
+            .collect();
+
+        let evicted = select_evictions(candidates, self.usage.current, self.budget, self.frame);
+        for id in evicted {
+            let family = self.families.get_mut(&id).unwrap();
+            self.usage.current -= family.size().unwrap();
+            self.stats.evictions += family.processed.count();
+            family.processed.clear();
+            family.evicted = true;
+            self.evicted.push(id);
+        }
+    }
+
+    /// Takes the IDs of the families evicted since the last call.
+    pub fn take_evicted(&mut self) -> Vec<TextureId> {
+        std::mem::take(&mut self.evicted)
+    }
+
+    /// Releases the raw data of the given families, unless they were loaded or referenced again
+    /// after being evicted.
+    pub fn release(&mut self, ids: &[TextureId]) {
+        for id in ids {
+            if self.families.get(id).is_some_and(|family| family.evicted) {
+                let old = self.families.remove(id);
+                self.remove_family(old);
+            }
+        }
+    }
+
+    fn create_sampler(
+        device: &wgpu::Device,
+        sampler: Sampler,
+        filter: TextureFilter,
+    ) -> wgpu::Sampler {
         let address_mode = |wrap| match wrap {
             WrapMode::Clamp => wgpu::AddressMode::ClampToEdge,
             WrapMode::Repeat => wgpu::AddressMode::Repeat,
@@ -54,31 +318,37 @@ impl Cache {
             _ => panic!("reserved wrap mode"),
         };
 
-        let mag_filter = if sampler.mode.mag_linear() {
+        let forced = filter != TextureFilter::Native;
+        let mag_filter = if forced || sampler.mode.mag_linear() {
             wgpu::FilterMode::Linear
         } else {
             wgpu::FilterMode::Nearest
         };
 
-        let min_filter = if sampler.mode.min_filter().is_linear() {
+        let min_filter = if forced || sampler.mode.min_filter().is_linear() {
             wgpu::FilterMode::Linear
         } else {
             wgpu::FilterMode::Nearest
         };
 
-        let anisotropy_clamp = if sampler.mode.mag_linear() && sampler.mode.min_filter().is_linear()
-        {
-            16
-        } else {
-            1
+        // anisotropic filtering requires every filter to be linear
+        let anisotropy_clamp = match filter {
+            TextureFilter::Native
+                if sampler.mode.mag_linear() && sampler.mode.min_filter().is_linear() =>
+            {
+                16
+            }
+            TextureFilter::Anisotropic => 16,
+            _ => 1,
         };
 
         let label = format!(
-            "Sampler {:?}x{:?} (Mag {:?}, Min {:?})",
+            "Sampler {:?}x{:?} (Mag {:?}, Min {:?}, Anisotropy {})",
             sampler.mode.wrap_u(),
             sampler.mode.wrap_v(),
             mag_filter,
             min_filter,
+            anisotropy_clamp,
         );
         device.create_sampler(&wgpu::SamplerDescriptor {
             label: Some(&label),
@@ -89,30 +359,16 @@ impl Cache {
             mipmap_filter: min_filter,
             anisotropy_clamp,
             lod_min_clamp: sampler.lods.min(),
-            lod_max_clamp: sampler.lods.max(),
+            // invalid limits would fail validation
+            lod_max_clamp: sampler.lods.max().max(sampler.lods.min()),
             ..Default::default()
         })
     }
 
-    fn create_texture_data_indirect(
-        indirect: &[u16],
-        palette: &[u16],
-        format: ClutFormat,
-    ) -> Vec<Rgba8> {
-        let convert = match format {
-            ClutFormat::IA8 => Rgba8::from_ia8,
-            ClutFormat::RGB565 => Rgba8::from_rgb565,
-            ClutFormat::RGB5A3 => Rgba8::from_rgb5a3,
-            _ => panic!("reserved clut format"),
-        };
-
+    fn create_texture_data_indirect(indirect: &[u16], palette: &[Rgba8]) -> Vec<Rgba8> {
         indirect
             .iter()
-            .copied()
-            .map(|index| {
-                let color = palette[index as usize];
-                convert(color)
-            })
+            .map(|&index| palette.get(index as usize).copied().unwrap_or_default())
             .collect()
     }
 
@@ -132,11 +388,11 @@ impl Cache {
                 .collect::<Vec<_>>(),
             TextureData::Indirect(data) => {
                 let clut_base = clut.id.to_tmem_addr();
-                let clut_data = &tmem[clut_base..];
+                let palette = decode_clut(&tmem[clut_base..], clut.fmt, clut_len(raw.format));
 
                 owned_data = data
                     .iter()
-                    .map(|lod| Self::create_texture_data_indirect(lod, clut_data, clut.fmt))
+                    .map(|lod| Self::create_texture_data_indirect(lod, &palette))
                     .collect::<Vec<_>>();
 
                 owned_data
@@ -215,10 +471,19 @@ impl Cache {
             Family {
                 raw: Some(raw),
                 processed,
+                last_used: self.frame,
+                evicted: false,
             },
         );
 
-        old.is_some()
+        let existed = old.is_some();
+        if existed {
+            self.stats.replacements += 1;
+        }
+
+        self.remove_family(old);
+
+        existed
     }
 
     pub fn update_clut(&mut self, addr: ClutId, clut: ClutData) {
@@ -237,6 +502,25 @@ impl Cache {
         queue: &wgpu::Queue,
         tex: TextureRef,
     ) -> &wgpu::TextureView {
+        let family = self.families.get_mut(&tex.id).unwrap();
+        family.last_used = self.frame;
+        family.evicted = false;
+
+        let created = match &family.processed {
+            Processed::Direct(processed) => processed.is_none(),
+            Processed::Indirect(processed) => !processed.contains_key(&tex.clut),
+        };
+
+        if created {
+            self.stats.misses += 1;
+        } else {
+            self.stats.hits += 1;
+        }
+
+        if created && let Some(size) = family.texture_size() {
+            self.add_usage(size);
+        }
+
         let family = self.families.get_mut(&tex.id).unwrap();
         match &mut family.processed {
             Processed::Direct(processed) => processed.get_or_insert_with(|| {
@@ -271,20 +555,26 @@ impl Cache {
         match self.samplers.entry(sampler) {
             Entry::Occupied(o) => o.into_mut(),
             Entry::Vacant(v) => {
-                let s = Self::create_sampler(device, sampler);
+                let s = Self::create_sampler(device, sampler, self.filter);
                 v.insert(s)
             }
         }
     }
 
+    /// Inserts a texture which has no raw data (i.e. an EFB copy). These are not accounted for,
+    /// as they cannot be re-created once evicted.
     pub fn insert_direct(&mut self, id: TextureId, tex: wgpu::TextureView) {
-        self.families.insert(
+        let old = self.families.insert(
             id,
             Family {
                 raw: None,
                 processed: Processed::Direct(Some(tex)),
+                last_used: self.frame,
+                evicted: false,
             },
         );
+
+        self.remove_family(old);
     }
 }
 
@@ -293,6 +583,12 @@ impl Renderer {
         self.texture_cache.update_raw(id, texture);
     }
 
+    pub fn release_textures(&mut self, ids: Vec<TextureId>) {
+        // pending draws might still reference them
+        self.flush(format_args!("textures released"));
+        self.texture_cache.release(&ids);
+    }
+
     pub fn load_clut(&mut self, id: ClutId, clut: ClutData) {
         self.texture_cache.update_clut(id, clut);
     }
@@ -319,3 +615,213 @@ impl Renderer {
         self.tex_slots[slot] = config;
     }
 }
+
+#[cfg(test)]
+mod test {
+    use lazuli::modules::render::{Texture, TextureId};
+    use lazuli::system::gx::color::Rgba8;
+    use lazuli::system::gx::tex::{ClutFormat, Format, TextureData};
+    use rustc_hash::FxHashMap;
+
+    use super::{
+        Cache, Candidate, EVICTION_IDLE_FRAMES, TextureRef, decode_clut, select_evictions,
+        texture_size,
+    };
+
+    const fn rgba(r: u8, g: u8, b: u8, a: u8) -> Rgba8 {
+        Rgba8 { r, g, b, a }
+    }
+
+    #[test]
+    fn size_with_lods() {
+        assert_eq!(texture_size(64, 32, 1), 64 * 32 * 4);
+        assert_eq!(texture_size(4, 4, 4), (4 * 4 + 2 * 2 + 1 + 1) * 4);
+    }
+
+    #[test]
+    fn clut_ia8() {
+        // alpha in the high byte, intensity in the low byte
+        let palette = decode_clut(&[0xFF00, 0x80FF, 0x0040], ClutFormat::IA8, 3);
+        assert_eq!(
+            palette,
+            [
+                rgba(0x00, 0x00, 0x00, 0xFF),
+                rgba(0xFF, 0xFF, 0xFF, 0x80),
+                rgba(0x40, 0x40, 0x40, 0x00),
+            ]
+        );
+    }
+
+    #[test]
+    fn clut_rgb565() {
+        let palette = decode_clut(&[0xF800, 0x07E0, 0x001F, 0xFFFF], ClutFormat::RGB565, 4);
+        assert_eq!(
+            palette,
+            [
+                rgba(0xFF, 0x00, 0x00, 0xFF),
+                rgba(0x00, 0xFF, 0x00, 0xFF),
+                rgba(0x00, 0x00, 0xFF, 0xFF),
+                rgba(0xFF, 0xFF, 0xFF, 0xFF),
+            ]
+        );
+    }
+
+    #[test]
+    fn clut_rgb5a3() {
+        // opaque RGB555 when the top bit is set, RGB4A3 otherwise
+        let palette = decode_clut(&[0xFC00, 0x83E0, 0x0F00, 0x700F], ClutFormat::RGB5A3, 4);
+        assert_eq!(
+            palette,
+            [
+                rgba(0xFF, 0x00, 0x00, 0xFF),
+                rgba(0x00, 0xFF, 0x00, 0xFF),
+                rgba(0xFF, 0x00, 0x00, 0x00),
+                rgba(0x00, 0x00, 0xFF, 0xE0),
+            ]
+        );
+    }
+
+    #[test]
+    fn clut_shorter_than_count() {
+        let palette = decode_clut(&[0xFFFF], ClutFormat::RGB565, 16);
+        assert_eq!(palette.len(), 16);
+        assert_eq!(palette[0], rgba(0xFF, 0xFF, 0xFF, 0xFF));
+        assert_eq!(palette[15], Rgba8::default());
+    }
+
+    #[test]
+    fn replacements_are_counted() {
+        let texture = || Texture {
+            width: 1,
+            height: 1,
+            format: Format::RGBA8,
+            data: TextureData::Direct(vec![vec![Rgba8::default()]]),
+        };
+
+        let mut cache = Cache::default();
+        assert!(!cache.update_raw(TextureId(0), texture()));
+        assert!(!cache.update_raw(TextureId(1), texture()));
+        assert!(cache.update_raw(TextureId(0), texture()));
+
+        let stats = cache.stats();
+        assert_eq!(stats.replacements, 1);
+        assert_eq!(stats.evictions, 0);
+    }
+
+    #[test]
+    fn evicted_raw_data_is_released() {
+        let (device, queue) = match crate::Renderer::headless_device() {
+            Ok(device) => device,
+            Err(e) => {
+                eprintln!("skipping headless rendering: {e}");
+                return;
+            }
+        };
+
+        let texture = || Texture {
+            width: 4,
+            height: 4,
+            format: Format::RGBA8,
+            data: TextureData::Direct(vec![vec![Rgba8::default(); 16]]),
+        };
+
+        let get = |cache: &mut Cache, id| {
+            let tex = TextureRef {
+                id: TextureId(id),
+                ..Default::default()
+            };
+
+            cache.get_texture(&device, &queue, tex);
+        };
+
+        let mut cache = Cache::default();
+        cache.set_budget(0);
+        for id in 0..3 {
+            cache.update_raw(TextureId(id), texture());
+            get(&mut cache, id);
+        }
+
+        // keep referencing the last texture
+        for _ in 0..=EVICTION_IDLE_FRAMES {
+            get(&mut cache, 2);
+            cache.end_frame();
+        }
+
+        let mut evicted = cache.take_evicted();
+        evicted.sort_unstable_by_key(|id| id.0);
+        assert_eq!(evicted, [TextureId(0), TextureId(1)]);
+        assert!(cache.take_evicted().is_empty());
+        assert_eq!(cache.stats().evictions, 2);
+
+        // referenced again before being released, so the raw data is still needed
+        get(&mut cache, 1);
+        cache.release(&evicted);
+
+        assert!(!cache.families.contains_key(&TextureId(0)));
+        assert!(cache.families.contains_key(&TextureId(1)));
+        assert_eq!(cache.usage().current, 2 * texture_size(4, 4, 1));
+    }
+
+    #[test]
+    fn recently_used_is_kept() {
+        let candidates = vec![Candidate {
+            id: TextureId(0),
+            size: 1024,
+            last_used: 10,
+        }];
+
+        let evicted = select_evictions(candidates, 1024, 0, 10 + EVICTION_IDLE_FRAMES);
+        assert!(evicted.is_empty());
+    }
+
+    #[test]
+    fn soak() {
+        const BUDGET: u64 = 64 * 1024 * 1024;
+        const PER_FRAME: u32 = 16;
+
+        // (size, last used) of resident textures
+        let mut resident = FxHashMap::<TextureId, (u64, u64)>::default();
+        let mut bytes = 0;
+        let mut peak = 0;
+        let mut next_id = 0;
+
+        for frame in 0..1000 {
+            // upload a bunch of unique textures, with a few different sizes
+            for i in 0..PER_FRAME {
+                let dim = 32 << (i % 5);
+                let size = texture_size(dim, dim, 1);
+
+                resident.insert(TextureId(next_id), (size, frame));
+                next_id += 1;
+                bytes += size;
+            }
+
+            // keep referencing the first texture
+            resident.get_mut(&TextureId(0)).unwrap().1 = frame;
+            peak = peak.max(bytes);
+
+            let candidates = resident
+                .iter()
+                .map(|(id, (size, last_used))| Candidate {
+                    id: *id,
+                    size: *size,
+                    last_used: *last_used,
+                })
+                .collect();
+
+            for id in select_evictions(candidates, bytes, BUDGET, frame + 1) {
+                let (size, _) = resident.remove(&id).unwrap();
+                bytes -= size;
+            }
+        }
+
+        let per_frame: u64 = (0..PER_FRAME)
+            .map(|i| texture_size(32 << (i % 5), 32 << (i % 5), 1))
+            .sum();
+
+        assert!(next_id > 10_000);
+        assert!(bytes <= BUDGET);
+        assert!(peak <= BUDGET + (EVICTION_IDLE_FRAMES + 1) * per_frame);
+        assert!(resident.contains_key(&TextureId(0)));
+    }
+}
@@ -0,0 +1,264 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::time::{Duration, Instant};
+
+/// Maximum number of passes per frame whose GPU time is measured. Passes past this limit are not
+/// measured, and the GPU time of the frame is reported as unknown.
+const MAX_PASSES: u32 = 256;
+/// Number of frames whose timestamps can be in flight at once.
+const FRAMES_IN_FLIGHT: usize = 3;
+
+const READBACK_IDLE: u8 = 0;
+const READBACK_PENDING: u8 = 1;
+const READBACK_READY: u8 = 2;
+
+/// Time spent by the renderer on a single frame.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RenderTimes {
+    /// Index of the frame.
+    pub frame: u64,
+    /// Time the rendering thread spent executing actions.
+    pub cpu: Duration,
+    /// Time spent submitting command buffers. Part of `cpu`.
+    pub submit: Duration,
+    /// Number of render passes.
+    pub passes: u32,
+    /// Time the GPU spent executing the render passes. `None` if timestamp queries are not
+    /// supported or the measurement isn't available (yet).
+    pub gpu: Option<Duration>,
+    /// Time from the first XFB copy of the frame until it was presented.
+    pub xfb_to_present: Duration,
+    /// Time since the previous frame was presented.
+    pub interval: Duration,
+}
+
+/// Measures the CPU side of frames.
+#[derive(Default)]
+pub struct FrameTimer {
+    current: RenderTimes,
+    batch_start: Option<Instant>,
+    first_xfb_copy: Option<Instant>,
+    last_present: Option<Instant>,
+}
+
+impl FrameTimer {
+    pub fn frame(&self) -> u64 {
+        self.current.frame
+    }
+
+    /// Marks the start of a batch of actions.
+    pub fn begin_batch(&mut self) {
+        self.batch_start = Some(Instant::now());
+    }
+
+    /// Marks the end of a batch of actions.
+    pub fn end_batch(&mut self) {
+        if let Some(start) = self.batch_start.take() {
+            self.current.cpu += start.elapsed();
+        }
+    }
+
+    /// Accounts a submission of a render pass which took the given time.
+    pub fn add_pass(&mut self, submit: Duration) {
+        self.current.passes += 1;
+        self.current.submit += submit;
+    }
+
+    pub fn xfb_copied(&mut self) {
+        self.first_xfb_copy.get_or_insert_with(Instant::now);
+    }
+
+    /// Finishes the current frame and returns its times.
+    pub fn end_frame(&mut self) -> RenderTimes {
+        let now = Instant::now();
+
+        // the rest of the current batch is attributed to the next frame
+        if let Some(start) = &mut self.batch_start {
+            self.current.cpu += now - *start;
+            *start = now;
+        }
+
+        if let Some(copy) = self.first_xfb_copy.take() {
+            self.current.xfb_to_present = now - copy;
+        }
+
+        if let Some(last) = self.last_present.replace(now) {
+            self.current.interval = now - last;
+        }
+
+        let frame = self.current.frame;
+        std::mem::replace(
+            &mut self.current,
+            RenderTimes {
+                frame: frame + 1,
+                ..Default::default()
+            },
+        )
+    }
+}
+
+/// Timestamp queries of a single frame.
+struct FrameQueries {
+    query_set: wgpu::QuerySet,
+    resolve: wgpu::Buffer,
+    readback: wgpu::Buffer,
+    state: Arc<AtomicU8>,
+    /// Number of passes whose timestamps were written.
+    passes: u32,
+    /// Whether some passes of the frame weren't measured.
+    overflowed: bool,
+    /// Frame the readback buffer contains the timestamps of.
+    frame: u64,
+    /// Number of passes the readback buffer contains the timestamps of.
+    readback_passes: u32,
+}
+
+/// Measures the GPU time of render passes through timestamp queries.
+pub struct GpuTimer {
+    frames: Vec<FrameQueries>,
+    current: usize,
+    /// Nanoseconds per timestamp tick.
+    period: f64,
+}
+
+impl GpuTimer {
+    /// Creates a new timer, or `None` if timestamp queries are not supported by the device.
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+
+        let size = 2 * MAX_PASSES as u64 * size_of::<u64>() as u64;
+        let frames = (0..FRAMES_IN_FLIGHT)
+            .map(|_| FrameQueries {
+                query_set: device.create_query_set(&wgpu::QuerySetDescriptor {
+                    label: Some("pass timestamps"),
+                    ty: wgpu::QueryType::Timestamp,
+                    count: 2 * MAX_PASSES,
+                }),
+                resolve: device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("pass timestamps resolve buffer"),
+                    size,
+                    usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                    mapped_at_creation: false,
+                }),
+                readback: device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("pass timestamps readback buffer"),
+                    size,
+                    usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                    mapped_at_creation: false,
+                }),
+                state: Arc::new(AtomicU8::new(READBACK_IDLE)),
+                passes: 0,
+                overflowed: false,
+                frame: 0,
+                readback_passes: 0,
+            })
+            .collect();
+
+        Some(Self {
+            frames,
+            current: 0,
+            period: queue.get_timestamp_period() as f64,
+        })
+    }
+
+    /// Returns the timestamp writes for the next render pass of the current frame.
+    pub fn pass_writes(&mut self) -> Option<wgpu::RenderPassTimestampWrites<'_>> {
+        let frame = &mut self.frames[self.current];
+        if frame.passes == MAX_PASSES {
+            frame.overflowed = true;
+            return None;
+        }
+
+        let index = frame.passes;
+        frame.passes += 1;
+
+        Some(wgpu::RenderPassTimestampWrites {
+            query_set: &frame.query_set,
+            beginning_of_pass_write_index: Some(2 * index),
+            end_of_pass_write_index: Some(2 * index + 1),
+        })
+    }
+
+    /// Moves on to the next frame. Passes started after this call are attributed to it.
+    pub fn next_frame(&mut self) {
+        self.current = (self.current + 1) % FRAMES_IN_FLIGHT;
+
+        let frame = &mut self.frames[self.current];
+        frame.passes = 0;
+        frame.overflowed = false;
+    }
+
+    /// Resolves the timestamps of the previous frame, which must have had all of its passes
+    /// submitted already, and schedules them to be read back.
+    pub fn resolve(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, frame_index: u64) {
+        let previous = (self.current + FRAMES_IN_FLIGHT - 1) % FRAMES_IN_FLIGHT;
+        let frame = &mut self.frames[previous];
+        if frame.passes == 0 || frame.overflowed {
+            return;
+        }
+
+        // the readback of an older frame is still in flight, so this one can't be measured
+        if frame.state.load(Ordering::Acquire) != READBACK_IDLE {
+            return;
+        }
+
+        let count = 2 * frame.passes;
+        let size = count as u64 * size_of::<u64>() as u64;
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("pass timestamps resolve"),
+        });
+        encoder.resolve_query_set(&frame.query_set, 0..count, &frame.resolve, 0);
+        encoder.copy_buffer_to_buffer(&frame.resolve, 0, &frame.readback, 0, size);
+
+        let state = frame.state.clone();
+        state.store(READBACK_PENDING, Ordering::Release);
+        encoder.map_buffer_on_submit(&frame.readback, wgpu::MapMode::Read, ..size, move |r| {
+            let value = if r.is_ok() {
+                READBACK_READY
+            } else {
+                READBACK_IDLE
+            };
+
+            state.store(value, Ordering::Release);
+        });
+
+        frame.frame = frame_index;
+        frame.readback_passes = frame.passes;
+        queue.submit([encoder.finish()]);
+    }
+
+    /// Collects the GPU times of the frames whose timestamps have been read back, as `(frame,
+    /// time)` pairs.
+    pub fn collect(&mut self) -> Vec<(u64, Duration)> {
+        let mut results = Vec::new();
+        for frame in &mut self.frames {
+            if frame.state.load(Ordering::Acquire) != READBACK_READY {
+                continue;
+            }
+
+            let size = 2 * frame.readback_passes as u64 * size_of::<u64>() as u64;
+            let ticks: u64 = {
+                let mapped = frame.readback.get_mapped_range(..size);
+                mapped
+                    .chunks_exact(2 * size_of::<u64>())
+                    .map(|pair| {
+                        let begin = u64::from_ne_bytes(pair[..8].try_into().unwrap());
+                        let end = u64::from_ne_bytes(pair[8..].try_into().unwrap());
+                        end.saturating_sub(begin)
+                    })
+                    .sum()
+            };
+
+            frame.readback.unmap();
+            frame.state.store(READBACK_IDLE, Ordering::Release);
+
+            let nanos = ticks as f64 * self.period;
+            results.push((frame.frame, Duration::from_nanos(nanos as u64)));
+        }
+
+        results
+    }
+}
@@ -0,0 +1,71 @@
+//! An example audio plugin which replaces the audio of the game with a sine wave.
+//!
+//! The wave is synthesized in step with the frames the emulator plays, so it keeps the pacing of
+//! the game. Since plugins only receive audio, the tone is not sent anywhere: this plugin exists
+//! to show how to implement the ABI.
+use std::f32::consts::TAU;
+use std::ffi::c_void;
+
+use plugin::{ABI_VERSION, AudioPlugin, AudioVTable, Frame};
+
+/// Frequency of the tone, in Hz.
+const FREQUENCY: f32 = 440.0;
+/// Amplitude of the tone, relative to full scale.
+const AMPLITUDE: f32 = 0.25;
+
+struct Sine {
+    sample_rate: u32,
+    phase: f32,
+}
+
+impl Sine {
+    fn next(&mut self) -> Frame {
+        let sample = (self.phase.sin() * AMPLITUDE * i16::MAX as f32) as i16;
+        self.phase = (self.phase + TAU * FREQUENCY / self.sample_rate as f32) % TAU;
+
+        Frame {
+            left: sample,
+            right: sample,
+        }
+    }
+}
+
+unsafe extern "C-unwind" fn set_sample_rate(state: *mut c_void, sample_rate: u32) {
+    let sine = unsafe { &mut *state.cast::<Sine>() };
+    assert!(sample_rate > 0, "sample rate must not be zero");
+    sine.sample_rate = sample_rate;
+}
+
+unsafe extern "C-unwind" fn play(state: *mut c_void, _: Frame) {
+    let sine = unsafe { &mut *state.cast::<Sine>() };
+    sine.next();
+}
+
+unsafe extern "C-unwind" fn destroy(state: *mut c_void) {
+    drop(unsafe { Box::from_raw(state.cast::<Sine>()) });
+}
+
+static VTABLE: AudioVTable = AudioVTable {
+    size: size_of::<AudioVTable>(),
+    set_sample_rate,
+    play,
+    destroy,
+};
+
+#[unsafe(no_mangle)]
+pub extern "C-unwind" fn lazuli_plugin_abi_version() -> u32 {
+    ABI_VERSION
+}
+
+#[unsafe(no_mangle)]
+pub extern "C-unwind" fn lazuli_audio_plugin() -> AudioPlugin {
+    let sine = Box::new(Sine {
+        sample_rate: 48_000,
+        phase: 0.0,
+    });
+
+    AudioPlugin {
+        state: Box::into_raw(sine).cast(),
+        vtable: &raw const VTABLE,
+    }
+}
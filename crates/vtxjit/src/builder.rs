@@ -6,6 +6,7 @@ use cranelift::prelude::InstBuilder;
 use lazuli::system::gx::Vertex;
 use lazuli::system::gx::cmd::ArrayDescriptor;
 use lazuli::system::gx::cmd::attributes::{self, AttributeMode};
+use lazuli::system::mem::RAM_LEN;
 use rustc_hash::FxHashMap;
 use seq_macro::seq;
 use util::offset_of;
@@ -20,6 +21,11 @@ const MEMFLAGS_READONLY: ir::MemFlags = ir::MemFlags::new()
     .with_can_move()
     .with_readonly();
 
+/// Largest offset into RAM array elements are read from. Indices might be garbage (e.g. for
+/// uninitialized vertices), so offsets are clamped to it, leaving room for the widest load (16
+/// bytes) instead of reading past the end of RAM.
+const ARRAY_LIMIT: i64 = (RAM_LEN - 16) as i64;
+
 struct Array {
     base: ir::Value,
     stride: ir::Value,
@@ -162,6 +168,7 @@ impl<'ctx> ParserBuilder<'ctx> {
     fn parse_indexed<A: AttributeExt>(&mut self, index_ty: ir::Type) {
         let descriptor = A::get_descriptor(&self.config.vat);
         let array = &self.vars.arrays[&A::ARRAY_OFFSET];
+        let (base, stride) = (array.base, array.stride);
 
         // load index
         let index = self
@@ -175,18 +182,49 @@ impl<'ctx> ParserBuilder<'ctx> {
             self.bd.ins().bswap(index)
         };
 
-        let index = self.bd.ins().uextend(ir::types::I32, index);
+        let index = self.bd.ins().uextend(self.consts.ptr_type, index);
 
-        // compute address
-        let offset = self.bd.ins().imul(index, array.stride);
-        let addr = self.bd.ins().iadd(array.base, offset);
+        // compute address, in 64 bits so that it can't wrap around, and clamp it
+        let base = self.bd.ins().uextend(self.consts.ptr_type, base);
+        let stride = self.bd.ins().uextend(self.consts.ptr_type, stride);
+        let offset = self.bd.ins().imul(index, stride);
+        let addr = self.bd.ins().iadd(base, offset);
+        let limit = self.bd.ins().iconst(self.consts.ptr_type, ARRAY_LIMIT);
+        let addr = self.bd.ins().umin(addr, limit);
 
         // compute ptr
-        let addr = self.bd.ins().uextend(self.consts.ptr_type, addr);
         let ptr = self.bd.ins().iadd(self.consts.ram_ptr, addr);
 
         // parse
-        A::parse(&descriptor, self, ptr);
+        if A::SKIPPABLE {
+            let skip_bb = self.bd.create_block();
+            let parse_bb = self.bd.create_block();
+            let continue_bb = self.bd.create_block();
+            self.bd.set_cold_block(skip_bb);
+
+            let all_ones = (1i64 << index_ty.bits()) - 1;
+            let skip = self
+                .bd
+                .ins()
+                .icmp_imm(ir::condcodes::IntCC::Equal, index, all_ones);
+            self.bd.ins().brif(skip, skip_bb, &[], parse_bb, &[]);
+            self.bd.seal_block(skip_bb);
+            self.bd.seal_block(parse_bb);
+
+            self.switch_to_bb(parse_bb);
+            A::parse(&descriptor, self, ptr);
+            self.bd.ins().jump(continue_bb, &[]);
+
+            self.switch_to_bb(skip_bb);
+            A::skip(self);
+            self.bd.ins().jump(continue_bb, &[]);
+
+            self.bd.seal_block(continue_bb);
+            self.switch_to_bb(continue_bb);
+        } else {
+            A::parse(&descriptor, self, ptr);
+        }
+
         self.vars.data_ptr = self
             .bd
             .ins()
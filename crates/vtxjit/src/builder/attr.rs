@@ -517,9 +517,13 @@ fn read_color(format: ColorFormat, parser: &mut ParserBuilder, ptr: ir::Value) -
 
 pub trait AttributeExt: Attribute {
     const ARRAY_OFFSET: usize;
+    /// Whether an index with every bit set skips the vertex instead of being read from the array.
+    const SKIPPABLE: bool = false;
 
     fn set_default(_parser: &mut ParserBuilder) {}
     fn parse(desc: &Self::Descriptor, parser: &mut ParserBuilder, ptr: ir::Value) -> u32;
+    /// Emits the code for a skipped vertex. Only used if [`Self::SKIPPABLE`] is set.
+    fn skip(_parser: &mut ParserBuilder) {}
 }
 
 impl AttributeExt for attributes::PosMatrixIndex {
@@ -578,6 +582,22 @@ impl<const N: usize> AttributeExt for attributes::TexMatrixIndex<N> {
 
 impl AttributeExt for attributes::Position {
     const ARRAY_OFFSET: usize = offset_of!(Arrays, position);
+    const SKIPPABLE: bool = true;
+
+    /// Skipped vertices get a NaN position, which makes the GPU discard any primitive using them.
+    fn skip(parser: &mut ParserBuilder) {
+        let nan = parser.bd.ins().f32const(f32::NAN);
+        for offset in [
+            offset_of!(Vertex, position.x),
+            offset_of!(Vertex, position.y),
+            offset_of!(Vertex, position.z),
+        ] {
+            parser
+                .bd
+                .ins()
+                .store(MEMFLAGS, nan, parser.vars.vertex_ptr, offset as i32);
+        }
+    }
 
     fn parse(desc: &Self::Descriptor, parser: &mut ParserBuilder, ptr: ir::Value) -> u32 {
         let (ty, signed) = match desc.format() {
@@ -19,6 +19,7 @@ use lazuli::system::gx::cmd::attributes::VertexAttributeTable;
 use lazuli::system::gx::cmd::{VertexAttributeStream, VertexDescriptor};
 use lazuli::system::gx::xform::DefaultMatrices;
 use lazuli::system::gx::{MatrixId, MatrixSet, Vertex};
+use lazuli::system::mem::RAM_LEN;
 use parser::VertexParser;
 use rustc_hash::FxHashMap;
 
@@ -195,6 +196,9 @@ impl VertexModule for JitVertexModule {
         vertices: &mut [MaybeUninit<Vertex>],
         matrix_set: &mut MatrixSet,
     ) {
+        // array offsets are clamped assuming the whole RAM is there
+        assert_eq!(ctx.ram.len(), RAM_LEN);
+
         let config = Config {
             vcd: *vcd,
             vat: *vat,
@@ -1,13 +1,21 @@
+use std::mem::MaybeUninit;
+
 use cranelift::codegen::{self, isa};
 use cranelift::frontend::FunctionBuilderContext;
-use lazuli::system::gx::cmd::VertexDescriptor;
+use lazuli::Address;
+use lazuli::modules::vertex::{Ctx, VertexModule};
 use lazuli::system::gx::cmd::attributes::{
     AttributeMode, ColorDescriptor, ColorFormat, ColorKind, CoordsFormat, PositionDescriptor,
     PositionKind, VertexAttributeTable, VertexAttributeTableA,
 };
+use lazuli::system::gx::cmd::{ArrayDescriptor, Arrays, VertexAttributeStream, VertexDescriptor};
+use lazuli::system::gx::glam::Vec3;
+use lazuli::system::gx::xform::DefaultMatrices;
+use lazuli::system::gx::{MatrixSet, Vertex};
+use lazuli::system::mem::RAM_LEN;
 
-use crate::Codegen;
 use crate::parser::Config;
+use crate::{Codegen, JitVertexModule};
 
 fn test_config(name: &str, config: Config) {
     fn inner(name: &str, config: Config, isa: isa::Builder, isa_name: &str) {
@@ -51,3 +59,95 @@ fn basic() {
     let config = Config { vcd, vat };
     test_config("pos(vec3_i16)_chan0(rgba_rgb565)", config);
 }
+
+#[test]
+fn out_of_range_indices() {
+    const STRIDE: u32 = 0x1000;
+
+    let pos = PositionDescriptor::default()
+        .with_kind(PositionKind::Vec3)
+        .with_format(CoordsFormat::F32);
+
+    let chan0 = ColorDescriptor::default()
+        .with_kind(ColorKind::Rgba)
+        .with_format(ColorFormat::Rgba8888);
+
+    let vcd = VertexDescriptor::default()
+        .with_position(AttributeMode::Index16)
+        .with_chan0(AttributeMode::Index8);
+
+    let vat = VertexAttributeTable {
+        a: VertexAttributeTableA::default()
+            .with_position(pos)
+            .with_chan0(chan0),
+        ..Default::default()
+    };
+
+    let write_vec3 = |ram: &mut [u8], offset: usize, values: [f32; 3]| {
+        for (i, value) in values.into_iter().enumerate() {
+            ram[offset + 4 * i..][..4].copy_from_slice(&value.to_be_bytes());
+        }
+    };
+
+    let mut ram = vec![0; RAM_LEN];
+    write_vec3(&mut ram, STRIDE as usize, [4.0, 5.0, 6.0]);
+    write_vec3(&mut ram, RAM_LEN - 16, [1.0, 2.0, 3.0]);
+
+    let mut arrays = Arrays::default();
+    arrays.position = ArrayDescriptor {
+        address: Address(0),
+        stride: STRIDE,
+    };
+    arrays.chan0 = ArrayDescriptor {
+        address: Address(0xFFFF_FFF0),
+        stride: 0xFF,
+    };
+
+    // (position, chan0) indices: in range, past the end of RAM and skipped
+    #[rustfmt::skip]
+    let data = vec![
+        0x00, 0x01, 0x00,
+        0xFF, 0xFE, 0xFF,
+        0xFF, 0xFF, 0x12,
+    ];
+    let stream = VertexAttributeStream::new(0, 3, data);
+
+    let default_matrices = DefaultMatrices::default();
+    let ctx = Ctx {
+        ram: &ram,
+        arrays: &arrays,
+        default_matrices: &default_matrices,
+    };
+
+    let mut module = JitVertexModule::new();
+    let mut parse = || {
+        let mut vertices = (0..3).map(|_| MaybeUninit::uninit()).collect::<Vec<_>>();
+        module.parse(
+            ctx,
+            &vcd,
+            &vat,
+            &stream,
+            &mut vertices,
+            &mut MatrixSet::default(),
+        );
+
+        vertices
+            .into_iter()
+            .map(|v| unsafe { v.assume_init() })
+            .collect::<Vec<Vertex>>()
+    };
+
+    let vertices = parse();
+    assert_eq!(vertices[0].position, Vec3::new(4.0, 5.0, 6.0));
+    assert_eq!(vertices[1].position, Vec3::new(1.0, 2.0, 3.0));
+    assert!(vertices[2].position.is_nan());
+
+    // every chan0 index is clamped to the same element
+    assert_eq!(vertices[0].chan0, vertices[1].chan0);
+    assert_eq!(vertices[1].chan0, vertices[2].chan0);
+
+    // and parsing again gives the same result
+    let again = parse();
+    assert_eq!(vertices[..2], again[..2]);
+    assert_eq!(vertices[2].chan0, again[2].chan0);
+}
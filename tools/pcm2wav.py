@@ -0,0 +1,45 @@
+#!/usr/bin/env python3
+"""Converts a raw PCM audio dump produced by lazuli into a WAV file.
+
+PCM dumps are started and stopped with Ctrl+Shift+R and contain the audio exactly as it was
+produced by the DSP, before resampling: little-endian signed 16-bit samples, interleaved stereo,
+with no header. Since the dump carries no metadata, the sample rate has to be given explicitly. It
+is logged when the dump starts (and whenever it changes), and is usually 32000 Hz.
+
+Usage:
+    python3 tools/pcm2wav.py lazuli-1234.pcm lazuli-1234.wav --rate 32000
+"""
+
+import argparse
+import wave
+
+
+def main():
+    parser = argparse.ArgumentParser(description="Convert a lazuli PCM dump to WAV.")
+    parser.add_argument("input", help="path to the .pcm dump")
+    parser.add_argument("output", help="path to the .wav file to write")
+    parser.add_argument(
+        "--rate",
+        type=int,
+        default=32000,
+        help="sample rate of the dump, in Hz (default: 32000)",
+    )
+    args = parser.parse_args()
+
+    with open(args.input, "rb") as f:
+        data = f.read()
+
+    # drop a trailing partial frame, if any
+    data = data[: len(data) - len(data) % 4]
+
+    with wave.open(args.output, "wb") as wav:
+        wav.setnchannels(2)
+        wav.setsampwidth(2)
+        wav.setframerate(args.rate)
+        wav.writeframes(data)
+
+    print(f"wrote {len(data) // 4} frames to {args.output}")
+
+
+if __name__ == "__main__":
+    main()